@@ -0,0 +1,97 @@
+//! The benchmarks of wren_c's `test/benchmark` directory, timed with
+//! criterion so that work on dispatch, calls and the garbage collector can
+//! be compared against earlier runs.
+//!
+//! Run with `cargo bench`, or `cargo bench -- fib` for just one. Adding
+//! `--features superinstructions,threaded-dispatch` times the VM with them.
+//!
+//! The `pooling` group times a workload making many small objects with and
+//! without the heap's pool of freed objects' buffers.
+
+use std::cell::RefCell;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use wren_rs::heap::POOL_SIZE;
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// Each benchmark's name, source, and what it prints, which is checked
+// before it is timed so that a VM that got faster by going wrong doesn't
+// go unnoticed.
+const BENCHMARKS: &[(&str, &str, &str)] = &[
+    (
+	"binary_trees",
+	include_str!("wren/binary_trees.wren"),
+	"stretch tree of depth 13 check: -1
+8192 trees of depth 4 check: -8192
+2048 trees of depth 6 check: -2048
+512 trees of depth 8 check: -512
+128 trees of depth 10 check: -128
+32 trees of depth 12 check: -32
+long lived tree of depth 12 check: -1
+",
+    ),
+    ("delta_blue", include_str!("wren/delta_blue.wren"), "14065400\n"),
+    ("fib", include_str!("wren/fib.wren"), "317811\n317811\n317811\n317811\n317811\n"),
+    ("fibers", include_str!("wren/fibers.wren"), "4999950000\n"),
+    ("map_numeric", include_str!("wren/map_numeric.wren"), "2000001000000\n"),
+    ("method_call", include_str!("wren/method_call.wren"), "true\nfalse\n"),
+    ("string_equals", include_str!("wren/string_equals.wren"), "3000000\n"),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+// Runs `source` in a new VM, as wren_c's benchmark runner runs each script
+// in a new process, and returns what it printed.
+fn run(source: &str, configuration: WrenConfiguration) -> String {
+    let mut vm = WrenVM::with_configuration(WrenConfiguration {
+	write_fn: Some(write),
+	..configuration
+    });
+    vm.interpret("main", source).expect("the benchmark runs");
+    OUTPUT.with(|output| output.take())
+}
+
+fn wren_c(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wren_c");
+    // Each run takes a good part of a second.
+    group.sample_size(10);
+    for &(name, source, expected) in BENCHMARKS {
+	let configuration = WrenConfiguration::default;
+	assert_eq!(run(source, configuration()), expected, "{} printed the wrong output", name);
+	group.bench_function(name, |b| b.iter(|| run(source, configuration())));
+    }
+    group.finish();
+}
+
+fn pooling(c: &mut Criterion) {
+    let source = include_str!("wren/small_objects.wren");
+    let mut group = c.benchmark_group("pooling");
+    group.sample_size(10);
+    // With a nursery, objects are freed soon after they become garbage, so
+    // the pool is refilled more often.
+    let nursery_size = Some(256 * 1024);
+    for (name, pool_size, nursery_size) in [
+	("unpooled", 0, None),
+	("pooled", POOL_SIZE, None),
+	("unpooled_nursery", 0, nursery_size),
+	("pooled_nursery", POOL_SIZE, nursery_size),
+    ] {
+	let configuration = || WrenConfiguration {
+	    pool_size,
+	    nursery_size,
+	    ..WrenConfiguration::default()
+	};
+	assert_eq!(run(source, configuration()), "[399998, 400000]\n60000439998\n");
+	group.bench_function(name, |b| b.iter(|| run(source, configuration())));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, wren_c, pooling);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+// Builds the vendored wren_c, with its optional `meta` and `random` modules,
+// into a static library for the harness to call.
+
+use std::env;
+use std::fs;
+
+fn main() {
+    let mut build = cc::Build::new();
+    for directory in ["wren_c/src/vm", "wren_c/src/optional"] {
+	println!("cargo:rerun-if-changed={}", directory);
+	let entries = fs::read_dir(directory).expect("the vendored sources");
+	for entry in entries {
+	    let path = entry.expect("a vendored source").path();
+	    if path.extension().is_some_and(|extension| extension == "c") {
+		build.file(path);
+	    }
+	}
+    }
+    build
+	.include("wren_c/src/include")
+	.include("wren_c/src/vm")
+	.include("wren_c/src/optional")
+	.std("c99")
+	// The sources are upstream's, warnings and all.
+	.warnings(false)
+	.compile("wren");
+    if env::var("CARGO_CFG_TARGET_FAMILY").is_ok_and(|family| family == "unix") {
+	println!("cargo:rustc-link-lib=m");
+    }
+}
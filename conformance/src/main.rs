@@ -0,0 +1,168 @@
+// Runs `.wren` files through both wren-rs and wren_c, the reference
+// implementation, vendored in `wren_c/` and called through its C API, and
+// reports each file where what they print, or the errors they report,
+// differ:
+//
+//     cargo run -p wren-rs-conformance -- path/to/wren/test benches/wren
+//
+// wren-rs runs each file at every opt level, which should make no
+// difference to what it does. Both VMs import modules from `.wren` files
+// beside the file that is run, by the name given in the `import`, and have
+// only wren_c's optional modules, `meta` and `random`. Files containing
+// `// nontest`, such as modules other tests import, aren't run on their own.
+// The scripts in `cases/` are places they have differed, which `cargo test`
+// checks they still agree on.
+//
+// Where wren-rs knowingly differs, and so scripts doing these are left out of
+// `cases/`:
+//
+// - A fiber transferring to itself, `Fiber.current.transfer(value)`, returns
+//   `value`. wren_c returns the fiber instead if it hasn't yet called a
+//   method written in Wren, as it reads a stale instruction pointer.
+
+mod wren_c;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use wren_rs::{ModuleLoader, WrenConfiguration, WrenError, WrenVM};
+
+const NONTEST: &str = "// nontest";
+
+// The opt levels wren-rs runs each file at.
+const OPT_LEVELS: [u8; 2] = [0, 1];
+
+thread_local! {
+    // What the VM running the current file has printed and reported, since
+    // its callbacks can't capture anything.
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_: &mut WrenVM, error: &WrenError) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(&format!("{}\n", error)));
+}
+
+// The source of the module `name`, from beside the file being run.
+fn load(root: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(root.join(format!("{}.wren", name))).ok()
+}
+
+// Loads modules for wren-rs as `load` does for both VMs.
+struct Beside(PathBuf);
+
+impl ModuleLoader for Beside {
+    fn load_module(&mut self, name: &str) -> Option<String> {
+	load(&self.0, name)
+    }
+}
+
+// Runs `source` in a new wren-rs VM, and returns what it printed and the
+// errors it reported, as `wren_c::run` does.
+fn run(source: &str, root: &Path, opt_level: u8) -> String {
+    let config = WrenConfiguration {
+	module_loader: Some(Box::new(Beside(root.to_path_buf()))),
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let _ = vm.interpret("main", source);
+    OUTPUT.with(|output| output.take())
+}
+
+// The first line where `actual` differs from `expected`, counting from 1,
+// with what each has there.
+fn first_difference<'a>(expected: &'a str, actual: &'a str) -> (usize, &'a str, &'a str) {
+    let mut expected_lines = expected.split('\n');
+    let mut actual_lines = actual.split('\n');
+    let mut line = 1;
+    loop {
+	match (expected_lines.next(), actual_lines.next()) {
+	    (Some(expected), Some(actual)) if expected == actual => line += 1,
+	    (expected, actual) => {
+		return (line, expected.unwrap_or("<end>"), actual.unwrap_or("<end>"));
+	    }
+	}
+    }
+}
+
+// The `.wren` files at or below `path`, in a stable order.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+	let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+	    Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+	    Err(error) => {
+		eprintln!("Could not read directory \"{}\": {}", path.display(), error);
+		return;
+	    }
+	};
+	entries.sort();
+	for entry in entries {
+	    collect_files(&entry, files);
+	}
+    } else if path.extension().is_some_and(|extension| extension == "wren") {
+	files.push(path.to_path_buf());
+    }
+}
+
+fn main() {
+    let paths: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+	eprintln!("Usage: wren_conformance <file or directory>...");
+	process::exit(64);
+    }
+    let mut files = Vec::new();
+    for path in &paths {
+	collect_files(path, &mut files);
+    }
+
+    let (mut agreed, mut differed) = (0, 0);
+    for file in &files {
+	let source = match fs::read_to_string(file) {
+	    Ok(source) => source,
+	    Err(error) => {
+		eprintln!("Could not read file \"{}\": {}", file.display(), error);
+		differed += 1;
+		continue;
+	    }
+	};
+	if source.contains(NONTEST) {
+	    continue;
+	}
+	let root = file.parent().unwrap_or_else(|| Path::new(""));
+	let expected = wren_c::run(&source, &|name| load(root, name));
+	let mut differences = Vec::new();
+	for opt_level in OPT_LEVELS {
+	    let actual = run(&source, root, opt_level);
+	    if actual != expected {
+		let (line, expected, actual) = first_difference(&expected, &actual);
+		differences.push(format!(
+		    "At opt_level {}, output line {}: wren_c gave '{}' and wren-rs '{}'.",
+		    opt_level, line, expected, actual
+		));
+	    }
+	}
+	if differences.is_empty() {
+	    agreed += 1;
+	} else {
+	    differed += 1;
+	    println!("DIFFER: {}", file.display());
+	    for difference in differences {
+		println!("      {}", difference);
+	    }
+	}
+    }
+
+    println!("{} agreed, {} differed.", agreed, differed);
+    if differed > 0 {
+	process::exit(1);
+    }
+}
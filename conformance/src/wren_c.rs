@@ -0,0 +1,124 @@
+// The parts of wren_c's C API the harness uses, and running a script in a
+// new wren_c VM.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+#[repr(C)]
+struct WrenVM {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct WrenLoadModuleResult {
+    source: *const c_char,
+    on_complete: Option<unsafe extern "C" fn(*mut WrenVM, *const c_char, WrenLoadModuleResult)>,
+    user_data: *mut c_void,
+}
+
+// The values of `WrenErrorType`.
+const WREN_ERROR_COMPILE: c_int = 0;
+const WREN_ERROR_RUNTIME: c_int = 1;
+
+// The callbacks the harness doesn't set are left as wrenInitConfiguration
+// makes them, and only declared as pointers.
+#[repr(C)]
+struct WrenConfiguration {
+    reallocate_fn: *const c_void,
+    resolve_module_fn: *const c_void,
+    load_module_fn: Option<unsafe extern "C" fn(*mut WrenVM, *const c_char) -> WrenLoadModuleResult>,
+    bind_foreign_method_fn: *const c_void,
+    bind_foreign_class_fn: *const c_void,
+    write_fn: Option<unsafe extern "C" fn(*mut WrenVM, *const c_char)>,
+    error_fn: Option<unsafe extern "C" fn(*mut WrenVM, c_int, *const c_char, c_int, *const c_char)>,
+    initial_heap_size: usize,
+    min_heap_size: usize,
+    heap_growth_percent: c_int,
+    user_data: *mut c_void,
+}
+
+extern "C" {
+    fn wrenInitConfiguration(configuration: *mut WrenConfiguration);
+    fn wrenNewVM(configuration: *mut WrenConfiguration) -> *mut WrenVM;
+    fn wrenFreeVM(vm: *mut WrenVM);
+    fn wrenInterpret(vm: *mut WrenVM, module: *const c_char, source: *const c_char) -> c_int;
+    fn wrenGetUserData(vm: *mut WrenVM) -> *mut c_void;
+}
+
+// What a VM's callbacks share through its user data.
+struct Run<'a> {
+    output: String,
+    load: &'a dyn Fn(&str) -> Option<String>,
+}
+
+unsafe fn run_of<'a>(vm: *mut WrenVM) -> &'a mut Run<'a> {
+    &mut *(wrenGetUserData(vm) as *mut Run)
+}
+
+unsafe fn text(text: *const c_char) -> String {
+    CStr::from_ptr(text).to_string_lossy().into_owned()
+}
+
+unsafe extern "C" fn write(vm: *mut WrenVM, output: *const c_char) {
+    run_of(vm).output.push_str(&text(output));
+}
+
+// Writes each error the way `WrenError` displays it.
+unsafe extern "C" fn report(
+    vm: *mut WrenVM,
+    kind: c_int,
+    module: *const c_char,
+    line: c_int,
+    message: *const c_char,
+) {
+    let message = text(message);
+    let error = match kind {
+	WREN_ERROR_COMPILE => format!("[{} line {}] {}\n", text(module), line, message),
+	WREN_ERROR_RUNTIME => format!("{}\n", message),
+	_ => format!("[{} line {}] in {}\n", text(module), line, message),
+    };
+    run_of(vm).output.push_str(&error);
+}
+
+unsafe extern "C" fn load_module(vm: *mut WrenVM, name: *const c_char) -> WrenLoadModuleResult {
+    let source = (run_of(vm).load)(&text(name)).and_then(|source| CString::new(source).ok());
+    WrenLoadModuleResult {
+	source: source.map_or(ptr::null(), |source| source.into_raw() as *const c_char),
+	on_complete: Some(free_module),
+	user_data: ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn free_module(_vm: *mut WrenVM, _name: *const c_char, result: WrenLoadModuleResult) {
+    if !result.source.is_null() {
+	drop(CString::from_raw(result.source as *mut c_char));
+    }
+}
+
+// Runs `source` as the module `main` of a new wren_c VM, which imports the
+// modules `load` gives the source of, and returns what it printed and the
+// errors it reported, in the order it did.
+pub fn run(source: &str, load: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut run = Run {
+	output: String::new(),
+	load,
+    };
+    // A script with a NUL in it is cut short there, as it would be if a C
+    // host read it.
+    let source = source.split('\0').next().unwrap_or_default();
+    let source = CString::new(source).expect("no NUL");
+    unsafe {
+	let mut config = std::mem::MaybeUninit::<WrenConfiguration>::uninit();
+	wrenInitConfiguration(config.as_mut_ptr());
+	let mut config = config.assume_init();
+	config.load_module_fn = Some(load_module);
+	config.write_fn = Some(write);
+	config.error_fn = Some(report);
+	config.user_data = &mut run as *mut Run as *mut c_void;
+	let vm = wrenNewVM(&mut config);
+	wrenInterpret(vm, b"main\0".as_ptr() as *const c_char, source.as_ptr());
+	wrenFreeVM(vm);
+    }
+    run.output
+}
@@ -0,0 +1,13 @@
+// Runs the scripts in `cases/`, each somewhere wren-rs has differed from
+// wren_c, through the conformance tool, which fails if any still differ.
+
+use std::process::Command;
+
+#[test]
+fn cases_agree() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wren_conformance"))
+	.arg(concat!(env!("CARGO_MANIFEST_DIR"), "/cases"))
+	.output()
+	.expect("the conformance tool runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use wren_rs::{WrenError, WrenVM};
+
+// `bind` makes a foreign method of a closure. Its parameters are read from
+// the call's arguments and its result becomes the call's, so most methods
+// never touch the slots.
+const SOURCE: &str = r#"
+class Math {
+  foreign static add(a, b)
+  foreign static mean(numbers)
+  foreign static parse(text)
+  foreign static repeat(text, count)
+}
+
+class Counter {
+  construct new() {}
+  foreign increment()
+}
+
+System.print(Math.add(1, 2))
+System.print(Math.mean([1, 2, 3, 4]))
+System.print(Math.repeat("ab", 3))
+System.print(Fiber.new { Math.parse("twelve") }.try())
+System.print(Fiber.new { Math.repeat("ab", 1.5) }.try())
+
+var counter = Counter.new()
+counter.increment()
+System.print(counter.increment())
+"#;
+
+fn main() -> Result<(), WrenError> {
+    let mut vm = WrenVM::new();
+    vm.bind("main", "Math", "static add(_,_)", |a: f64, b: f64| a + b)?;
+    vm.bind("main", "Math", "static mean(_)", |numbers: Vec<f64>| {
+	numbers.iter().sum::<f64>() / numbers.len() as f64
+    })?;
+    // An `Err` aborts the fiber with its message.
+    vm.bind("main", "Math", "static parse(_)", |text: String| text.parse::<f64>())?;
+    vm.bind("main", "Math", "static repeat(_,_)", |text: String, count: usize| {
+	text.repeat(count)
+    })?;
+
+    // A signature no method could have, or a closure taking a different
+    // number of arguments, is an error rather than a binding.
+    let result = vm.bind("main", "Math", "static add(_,", |a: f64| a);
+    assert!(matches!(result, Err(WrenError::Api { .. })));
+    let result = vm.bind("main", "Math", "static add(_,_)", |a: f64| a);
+    assert!(matches!(result, Err(WrenError::Api { .. })));
+
+    // Binding a method again replaces the closure, which is dropped.
+    let first = Arc::new(());
+    let captured = Arc::clone(&first);
+    vm.bind("main", "Counter", "increment()", move || Arc::strong_count(&captured))?;
+    assert_eq!(Arc::strong_count(&first), 2);
+
+    // Closures can capture state from the host.
+    let count = AtomicUsize::new(0);
+    vm.bind("main", "Counter", "increment()", move || {
+	count.fetch_add(1, Ordering::Relaxed) + 1
+    })?;
+    assert_eq!(Arc::strong_count(&first), 1);
+
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+    Ok(())
+}
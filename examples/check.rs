@@ -0,0 +1,29 @@
+//! Validates scripts without running them, as a CI step might.
+//!
+//! Run with `cargo run --example check`.
+
+use wren_rs::{check, parse, Severity};
+
+const SCRIPTS: &[(&str, &str)] = &[
+    ("valid", "var greeting = \"hi\"\nSystem.print(greeting)\n"),
+    ("syntax", "System.print(\"unterminated)\n"),
+    ("undefined", "System.print(greeting)\n"),
+    ("self-reference", "{\n  var count = count + 1\n}\n"),
+    // Parsing goes on after a syntax error, from the next line.
+    ("several", "var a = (1 +\nSystem.print(a b)\nclass {\n}\nvar total = 1 +\n"),
+];
+
+fn main() {
+    for (name, source) in SCRIPTS {
+	let statements = parse(source).map_or(0, |module| module.statements.len());
+	match check(source) {
+	    Ok(()) => println!("{}: ok, {} statements", name, statements),
+	    Err(diagnostics) => {
+		for diagnostic in diagnostics {
+		    assert_eq!(diagnostic.severity, Severity::Error);
+		    println!("{}: {}", name, diagnostic);
+		}
+	    }
+	}
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::{BTreeMap, HashMap};
+
+use wren_rs::WrenVM;
+
+// The host hands whole lists and maps to a script and reads them back,
+// converting every element at once instead of one slot call each.
+const SOURCE: &str = r#"
+class Stats {
+  static summarize(readings, names) {
+    var totals = {}
+    for (name in names.keys) {
+      var sum = readings[names[name]].reduce(0) {|a, b| a + b }
+      totals[name] = sum
+    }
+    return totals
+  }
+}
+var inventory = {"apples": 3, "pears": 0, "plums": 12}
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.interpret("main", SOURCE).expect("the script runs");
+
+    // A map of the script's, read into a Rust map.
+    vm.ensure_slots(1);
+    vm.get_variable("main", "inventory", 0).expect("inventory is defined");
+    let inventory: BTreeMap<String, u32> = vm.get_slot_map_as(0).expect("counts by name");
+    println!("{:?}", inventory);
+
+    // A list of lists and a map, built from Rust collections, as the
+    // arguments of a call.
+    let readings = vec![vec![1.5, 2.5], vec![10.0, 20.0, 30.0]];
+    let names = [("small", 0), ("large", 1)];
+    let summarize = vm.make_call_handle("summarize(_,_)").expect("a valid signature");
+    vm.ensure_slots(3);
+    vm.get_variable("main", "Stats", 0).expect("Stats is defined");
+    vm.set_slot_list_from(1, &readings).expect("readings are numbers");
+    vm.set_slot_map_from(2, names.iter().copied()).expect("names are strings");
+    vm.call(&summarize).expect("summarize runs");
+    let totals: HashMap<String, f64> = vm.get_slot_map_as(0).expect("totals by name");
+    assert_eq!(totals["small"], 4.0);
+    assert_eq!(totals["large"], 60.0);
+    let mut totals: Vec<(String, f64)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| a.0.cmp(&b.0));
+    println!("{:?}", totals);
+    vm.release_handle(summarize);
+
+    // The same list, read back as it was stored.
+    vm.ensure_slots(1);
+    vm.set_slot_list_from_iter(0, (1..=5).map(|n| n * n)).expect("squares");
+    let squares: Vec<i32> = vm.get_slot_list_as(0).expect("integers");
+    assert_eq!(squares, [1, 4, 9, 16, 25]);
+    println!("{:?}", squares);
+
+    // Reading the wrong type, or a key a map can't have, is an error.
+    let error = vm.get_slot_list_as::<String>(0).expect_err("numbers aren't strings");
+    println!("{}", error);
+    let error = vm.set_slot_map_from(0, vec![(vec![1.0], 1.0)]).expect_err("lists aren't keys");
+    println!("{}", error);
+}
@@ -0,0 +1,74 @@
+//! Checks that `?:`, `&&` and `||` only evaluate the operands they need,
+//! in order, and that only `false` and `null` count as false, whether or
+//! not the compiler folds constants. Then shows the jumps they compile to.
+//!
+//! Run with `cargo run --example conditionals`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// `f` records each operand it evaluates before returning its value.
+const SOURCE: &str = r#"
+var log = []
+var f = Fn.new {|name, value|
+  log.add(name)
+  return value
+}
+System.print(f.call("a", false) ? f.call("b", 1) : f.call("c", 2))
+System.print(f.call("d", 0) ? f.call("e", 1) : f.call("f", 2))
+System.print(f.call("g", null) || f.call("h", "default"))
+System.print(f.call("i", "") || f.call("j", "default"))
+System.print(f.call("k", 1) && f.call("l", null) && f.call("m", 3))
+System.print([true ? 1 : false ? 2 : 3, false ? 1 : null ? 2 : 3])
+System.print([null || false || 0, 1 && null || "x", false && 1, "" && []])
+var setting = null
+setting = setting || "fallback"
+System.print(setting)
+System.print(log.join())
+"#;
+
+const EXPECTED: &str = "2
+1
+default
+
+null
+[1, 3]
+[0, x, false, []]
+fallback
+acdeghikl
+";
+
+const EXAMPLE: &str = "var a = 1
+var b = a ? a : 2
+var c = a && b || 3
+";
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn config(opt_level: u8) -> WrenConfiguration {
+    WrenConfiguration {
+	write_fn: Some(write),
+	opt_level,
+	..WrenConfiguration::default()
+    }
+}
+
+fn main() {
+    for opt_level in [0, 1] {
+	let mut vm = WrenVM::with_configuration(config(opt_level));
+	vm.interpret("main", SOURCE).expect("the script runs");
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(output, EXPECTED, "at opt_level {}", opt_level);
+    }
+    print!("{}", EXPECTED);
+
+    let mut vm = WrenVM::with_configuration(config(1));
+    print!("{}", vm.disassemble("main", EXAMPLE).expect("compiles"));
+}
@@ -0,0 +1,127 @@
+//! Checks how constructors work: each `construct` defines a static method
+//! on the metaclass that creates the instance, and an initializer it runs
+//! on it, which a subclass's initializer can call with `super`.
+//!
+//! Run with `cargo run --example constructors`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+const SOURCE: &str = r#"
+class Point {
+  construct new(x, y) {
+    _x = x
+    _y = y
+  }
+  // A class may have any number of named constructors.
+  construct origin() {
+    _x = 0
+    _y = 0
+  }
+  construct polar(r, theta) {
+    _x = r * theta.cos
+    _y = r * theta.sin
+  }
+  x { _x }
+  y { _y }
+  toString { "(%(_x), %(_y))" }
+}
+class Pixel is Point {
+  construct new(x, y, color) {
+    // Runs Point's `new(_,_)` initializer on this instance.
+    super(x, y)
+    _color = color
+  }
+  construct origin() {
+    super()
+    _color = "black"
+  }
+  color { _color }
+  // A bare `return` in a constructor still returns the instance.
+  construct blank() {
+    _color = "none"
+    return
+  }
+}
+System.print([Point.new(1, 2), Point.origin(), Point.polar(2, 0)])
+var pixel = Pixel.new(3, 4, "red")
+System.print([pixel, pixel.color, pixel is Pixel, pixel is Point])
+System.print([Pixel.origin(), Pixel.origin().color, Pixel.blank().x])
+// Constructors belong to the metaclass, so neither instances nor
+// subclasses have them.
+System.print(Fiber.new { pixel.new(1, 2) }.try())
+System.print(Fiber.new { Pixel.polar(1, 0) }.try())
+System.print(Fiber.new { Object.new() }.try())
+"#;
+
+const EXPECTED: &str = "[(1, 2), (0, 0), (2, 0)]
+[(3, 4), red, true, true]
+[(0, 0), black, null]
+Pixel does not implement 'new(_,_)'.
+Pixel metaclass does not implement 'polar(_,_)'.
+Object metaclass does not implement 'new()'.
+";
+
+// Constructors written wrongly, and the error each is.
+const ERRORS: &[(&str, &str)] = &[
+    ("class A {\n  construct new {}\n}", "A parameter list is required for a constructor."),
+    ("class A {\n  static construct new() {}\n}", "A constructor cannot be static."),
+    (
+	"class A {\n  construct new() {\n    return 1\n  }\n}",
+	"A constructor cannot return a value.",
+    ),
+    (
+	"class A {\n  construct new() {\n    super\n  }\n}",
+	"A superclass constructor must have an argument list.",
+    ),
+    // The superclass has no initializer of the same name.
+    (
+	"class A {}\nclass B is A {\n  construct new() {\n    super()\n  }\n}\nB.new()",
+	"A does not implement 'init new()'.",
+    ),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    let message = match error {
+	WrenError::Compile { error, .. } => error.message.clone(),
+	WrenError::Runtime { message, .. } => message.clone(),
+	_ => return,
+    };
+    OUTPUT.with(|output| output.borrow_mut().push_str(&message));
+}
+
+fn config(opt_level: u8) -> WrenConfiguration {
+    WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    }
+}
+
+fn main() {
+    for opt_level in [0, 1] {
+	let mut vm = WrenVM::with_configuration(config(opt_level));
+	vm.interpret("main", SOURCE).expect("the script runs");
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(output, EXPECTED, "at opt_level {}", opt_level);
+    }
+    print!("{}", EXPECTED);
+
+    for (source, expected) in ERRORS {
+	let mut vm = WrenVM::with_configuration(config(1));
+	assert!(vm.interpret("main", source).is_err());
+	let message = OUTPUT.with(|output| output.take());
+	assert_eq!(&message, expected, "for {}", source);
+	println!("{}", message);
+    }
+}
@@ -0,0 +1,70 @@
+//! Checks the parts of the core library written in Wren, which every VM
+//! compiles from `core.wren` as it starts: the `Sequence` methods, which
+//! classes of scripts inherit too, and the helpers of `List`, `Map`,
+//! `String` and `System` layered on their primitives.
+//!
+//! Run with `cargo run --example core_library`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+const SOURCE: &str = r#"
+// A sequence only has to iterate to get the rest of Sequence's methods.
+class Countdown is Sequence {
+  construct new(from) { _from = from }
+  iterate(n) {
+    if (n == null) return _from
+    return n > 1 ? n - 1 : false
+  }
+  iteratorValue(n) { n }
+}
+var countdown = Countdown.new(5)
+System.print(countdown.toList)
+System.print([countdown.count, countdown.count {|n| n > 2 }, countdown.isEmpty])
+System.print([countdown.all {|n| n > 0 }, countdown.any {|n| n > 4 }, countdown.contains(3)])
+System.print(countdown.where {|n| n % 2 == 1 }.map {|n| n * 10 }.join(", "))
+System.print([countdown.skip(1).take(2).toList, countdown.reduce {|a, b| a + b }])
+System.print([3, 1, 2].sort {|a, b| a > b })
+var list = [1]
+list.addAll([2, 3])
+System.print([list, list + [4], list * 2])
+var map = {"a": 1}
+System.print([map, map.keys.toList, map.values.toList])
+System.print(["a,b".split(","), " trim ".trim(), "ab" * 2, "héllo".bytes.count])
+System.printAll([1, "two", null])
+System.print([true.toString, null.toString])
+"#;
+
+const EXPECTED: &str = "[5, 4, 3, 2, 1]
+[5, 3, false]
+[true, true, true]
+50, 30, 10
+[[4, 3], 15]
+[3, 2, 1]
+[[1, 2, 3], [1, 2, 3, 4], [1, 2, 3, 1, 2, 3]]
+[{a: 1}, [a], [1]]
+[[a, b], trim, abab, 6]
+1twonull
+[true, null]
+";
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    vm.interpret("main", SOURCE).expect("the script runs");
+    let output = OUTPUT.with(|output| output.take());
+    assert_eq!(output, EXPECTED);
+    print!("{}", output);
+}
@@ -0,0 +1,66 @@
+use wren_rs::{DebugAction, DebugHook, PauseReason, WrenVM};
+
+const SOURCE: &str = r#"
+var square = Fn.new {|n|
+  var result = n * n
+  return result
+}
+
+var total = 0
+for (i in 1..3) {
+  total = total + square.call(i)
+}
+System.print(total)
+"#;
+
+// A scripted debugger: it stops at the breakpoint, steps into the call on
+// that line and then out of it, and prints the calls as they happen.
+struct Tracer {
+    steps: Vec<DebugAction>,
+    depth: usize,
+}
+
+impl DebugHook for Tracer {
+    fn on_line(&mut self, vm: &mut WrenVM, reason: PauseReason) -> DebugAction {
+	let frames = vm.debug_frames();
+	let locals: Vec<String> = vm
+	    .debug_locals(0)
+	    .into_iter()
+	    .map(|local| format!("{} = {}", local.name, local.value))
+	    .collect();
+	println!(
+	    "{:?} at line {} in {}: {}",
+	    reason,
+	    frames[0].line,
+	    frames[0].function,
+	    locals.join(", ")
+	);
+	if self.steps.is_empty() {
+	    // Done stepping, so stop breaking as well.
+	    vm.clear_breakpoints();
+	    return DebugAction::Continue;
+	}
+	self.steps.remove(0)
+    }
+
+    fn on_call(&mut self, vm: &mut WrenVM) {
+	self.depth += 1;
+	println!("{:indent$}call {}", "", vm.debug_frames()[0].function, indent = self.depth * 2);
+    }
+
+    fn on_return(&mut self, _vm: &mut WrenVM) {
+	self.depth -= 1;
+    }
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.set_breakpoint("main", 9);
+    vm.set_debug_hook(Some(Box::new(Tracer {
+	steps: vec![DebugAction::StepIn, DebugAction::StepOver, DebugAction::StepOut],
+	depth: 0,
+    })));
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+}
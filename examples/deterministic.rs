@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// A replay of a simulation is only worth having if it plays out the same as
+// the original did, on any machine. In a deterministic VM, the clock moves
+// only as sleeping fibers wake, an unseeded Random gives the same numbers
+// every time, and the math methods round the same everywhere.
+const SOURCE: &str = r#"
+import "random" for Random
+import "scheduler" for Scheduler
+import "timer" for Timer
+
+var random = Random.new()
+var positions = {}
+for (name in ["ada", "grace", "alan"]) {
+  var delay = random.int(50)
+  Scheduler.add {
+    Timer.sleep(delay)
+    var angle = random.float() * Num.pi
+    positions[name] = [angle.sin, angle.cos, 2.pow(angle), angle.log]
+    System.print("%(name) moved at %(System.clock)")
+  }
+}
+Timer.sleep(100)
+System.print("done at %(System.clock)")
+for (name in positions.keys) System.print("%(name): %(positions[name])")
+"#;
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(&error.to_string()));
+}
+
+fn vm(opt_level: u8) -> WrenVM {
+    WrenVM::with_configuration(WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	deterministic: true,
+	..WrenConfiguration::default()
+    })
+}
+
+fn run(opt_level: u8) -> String {
+    let mut vm = vm(opt_level);
+    vm.interpret("main", SOURCE).expect("the script starts");
+    vm.run_event_loop().expect("the fibers finish");
+    OUTPUT.with(|output| output.take())
+}
+
+fn main() {
+    // Sleeping takes no time, and every run prints the same.
+    let started = Instant::now();
+    let first = run(0);
+    for opt_level in [0, 1] {
+	assert_eq!(run(opt_level), first, "at opt_level {}", opt_level);
+    }
+    assert!(started.elapsed().as_millis() < 100);
+    assert!(first.ends_with("]\n"), "{}", first);
+    print!("{}", first);
+
+    // Nor can scripts find out what machine they are on.
+    let result = vm(0).interpret("main", "import \"os\" for Platform");
+    assert!(result.is_err());
+    let output = OUTPUT.with(|output| output.take());
+    assert!(output.contains("Could not load module 'os'."), "{}", output);
+    println!("{}", output.lines().next().unwrap_or_default());
+}
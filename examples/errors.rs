@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+thread_local! {
+    // The messages of the compile errors reported, since `report` can't
+    // capture anything.
+    static COMPILE_ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// Errors go to the configured `error_fn` instead of stderr, so the host can
+// present them however it likes. They are also returned, so the host can
+// decide what to do next.
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    match error {
+	WrenError::Compile { module, error } => {
+	    COMPILE_ERRORS.with(|errors| errors.borrow_mut().push(error.message.clone()));
+	    println!(
+		"compile error in {} at {}:{}: {}",
+		module, error.span.line, error.span.column, error.message
+	    );
+	}
+	WrenError::Runtime {
+	    message,
+	    stack_trace,
+	    hint,
+	} => {
+	    println!("runtime error: {}", message);
+	    for frame in stack_trace {
+		println!("  in {} on line {} of {}", frame.function, frame.line, frame.module);
+	    }
+	    if let Some(hint) = hint {
+		println!("  {}", hint);
+	    }
+	}
+	WrenError::StackOverflow
+	| WrenError::Timeout
+	| WrenError::Api { .. }
+	| WrenError::Bytecode { .. }
+	| WrenError::Snapshot { .. }
+	| WrenError::Warning { .. } => println!("error: {}", error),
+    }
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	error_fn: Some(report),
+	max_call_depth: 1000,
+	max_stack_size: 10_000,
+	max_nesting: 64,
+	max_heap_size: Some(4 * 1024 * 1024),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let result = vm.interpret("main", "var x = (1 +");
+    assert!(matches!(result, Err(WrenError::Compile { .. })));
+
+    // Parsing goes on past each syntax error, as wren_c does, so they are
+    // all reported, and the first is returned.
+    COMPILE_ERRORS.with(|errors| errors.borrow_mut().clear());
+    let result = vm.interpret("main", "System.print(1 +)\nvar y = [1, 2\nSystem.print(2)");
+    match result {
+	Err(WrenError::Compile { error, .. }) => assert_eq!(error.span.line, 1),
+	other => panic!("expected a compile error, got {:?}", other),
+    }
+    let reported = COMPILE_ERRORS.with(|errors| errors.take());
+    assert_eq!(
+	reported,
+	["Expected expression.", "Expect ')' after arguments.", "Expect end of file."]
+    );
+    let source = r#"
+class Parser {
+  static parse(text) { Fiber.new { text.missing }.call() }
+}
+Parser.parse("input")
+"#;
+    let result = vm.interpret("main", source);
+    assert!(matches!(result, Err(WrenError::Runtime { .. })));
+
+    // Calling a method that's missing names the receiver's class and the
+    // signature called, and hints at a method that's close, if any is.
+    let result = vm.interpret("main", "var items = [1, 2]\nSystem.print(items.cuont)");
+    let (message, hint) = match result {
+	Err(WrenError::Runtime { message, hint, .. }) => (message, hint),
+	other => panic!("expected a runtime error, got {:?}", other),
+    };
+    assert_eq!(message, "List does not implement 'cuont'.");
+    assert_eq!(hint.as_deref(), Some("Did you mean 'count'?"));
+
+    // Private methods and operators are never suggested, nor is anything
+    // for a name too short to have a likely typo.
+    for (source, expected) in [
+	("\"text\".byteAt(0)", "String does not implement 'byteAt(_)'."),
+	("1.e", "Num does not implement 'e'."),
+	("[].x", "List does not implement 'x'."),
+	(
+	    "class A {\n  construct new() {}\n  +(other) { this }\n}\nA.new() < 1",
+	    "A does not implement '<(_)'.",
+	),
+    ] {
+	match vm.interpret("main", source) {
+	    Err(WrenError::Runtime { message, hint, .. }) => {
+		assert_eq!(message, expected);
+		assert_eq!(hint, None);
+	    }
+	    other => panic!("expected a runtime error, got {:?}", other),
+	}
+    }
+
+    // Unbounded recursion stops at the configured depth, even in a fiber
+    // run with `try`.
+    let source = r#"
+var recurse
+recurse = Fn.new {|n| recurse.call(n + 1) }
+Fiber.new { recurse.call(0) }.try()
+"#;
+    assert_eq!(vm.interpret("main", source), Err(WrenError::StackOverflow));
+
+    // So does recursion that fills the configured stack size first, and
+    // code nested too deeply to compile is an error rather than a crash.
+    let source = r#"
+var deep
+deep = Fn.new {|a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p|
+  deep.call(a + 1, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p)
+}
+deep.call(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15)
+"#;
+    assert_eq!(vm.interpret("main", source), Err(WrenError::StackOverflow));
+    let source = format!("var x = {}1{}", "(".repeat(100), ")".repeat(100));
+    assert!(matches!(vm.interpret("main", &source), Err(WrenError::Compile { .. })));
+
+    // Allocating past the configured heap size is a runtime error the script
+    // can catch, raised once collecting garbage doesn't free enough.
+    let source = r#"
+var error = Fiber.new {
+  var list = []
+  while (true) list.add(list.count)
+}.try()
+System.print("caught: %(error)")
+"#;
+    assert_eq!(vm.interpret("main", source), Ok(()));
+
+    // Without a limit on the heap, asking for more memory than the host has
+    // is the same error, rather than aborting the host.
+    let config = WrenConfiguration {
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut unlimited = WrenVM::with_configuration(config);
+    let source = r#"
+for (allocate in [Fn.new { List.filled(1e12, 0) }, Fn.new { "a" * 1e11 }]) {
+  var error = Fiber.new { allocate.call() }.try()
+  if (error != "Out of memory.") Fiber.abort("Expected no memory, got %(error).")
+}
+"#;
+    assert_eq!(unlimited.interpret("main", source), Ok(()));
+
+    // Misusing the slot API is an error too, rather than a panic.
+    vm.ensure_slots(1);
+    vm.get_variable("main", "recurse", 0).expect("recurse is defined");
+    if let Err(error) = vm.get_slot_double(0) {
+	println!("api error: {}", error);
+    }
+}
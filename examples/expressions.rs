@@ -0,0 +1,31 @@
+use wren_rs::{WrenType, WrenVM};
+
+// The host evaluates expressions and reads their values from slot 0, as a
+// configuration file whose settings are Wren expressions might.
+const SOURCE: &str = r#"
+var Width = 640
+var scale = Fn.new {|n| n * 2 }
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    if vm.interpret("config", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    for expression in ["Width / 2", "scale.call(Width)", "\"%(Width)px\"", "[1, 2].count > 1"] {
+	if vm.interpret_expression("config", expression).is_err() {
+	    std::process::exit(1);
+	}
+	let value = match vm.get_slot_type(0) {
+	    WrenType::Num => vm.get_slot_double(0).unwrap_or(0.0).to_string(),
+	    WrenType::String => vm.get_slot_string(0).unwrap_or("").to_string(),
+	    WrenType::Bool => vm.get_slot_bool(0).unwrap_or(false).to_string(),
+	    _ => "something else".to_string(),
+	};
+	println!("{} = {}", expression, value);
+    }
+
+    // Statements aren't expressions, and are reported as compile errors.
+    println!("{}", vm.interpret_expression("config", "var x = 1").is_err());
+}
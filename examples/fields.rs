@@ -0,0 +1,136 @@
+//! Checks instance fields, including those of inherited classes, and static
+//! fields, which every instance of a class shares, along with the errors
+//! for fields used where they can't be.
+//!
+//! Run with `cargo run --example fields`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+const SOURCE: &str = r#"
+class Shape {
+  construct new(name) { _name = name }
+  name { _name }
+}
+class Rect is Shape {
+  construct new(width, height) {
+    super("rect")
+    _width = width
+    _height = height
+  }
+  area { _width * _height }
+  // Fields of the superclass go through its methods.
+  describe { "%(name) %(_width)x%(_height)" }
+}
+class Square is Rect {
+  construct new(side) {
+    super(side, side)
+    _side = side
+    Square.created_ = Square.created + 1
+  }
+  side { _side }
+  static created { __created || 0 }
+  static created_=(value) { __created = value }
+  // Block arguments close over `this` and the static fields.
+  scaled(factors) { factors.map {|f| _side * f * (__created || 0) }.toList }
+}
+var square = Square.new(3)
+var other = Square.new(4)
+System.print([square.name, square.area, square.describe, square.side])
+System.print([other.describe, Square.created, square.scaled([1, 2])])
+
+// Each time a class definition runs, its static fields start afresh.
+var make = Fn.new {|value|
+  class Box {
+    static value { __value }
+    static value=(value) { __value = value }
+  }
+  Box.value = value
+  return Box
+}
+var first = make.call("first")
+var second = make.call("second")
+System.print([first.value, second.value])
+"#;
+
+const EXPECTED: &str = "[rect, 9, rect 3x3, 3]
+[rect 4x4, 2, [6, 12]]
+[first, second]
+";
+
+// Fields used where they can't be, and the error each is.
+const ERRORS: &[(&str, &str)] = &[
+    ("System.print(_name)", "Cannot reference a field outside of a class definition."),
+    ("__count = 1", "Cannot use a static field outside of a class definition."),
+    ("class A {\n  static get { _x }\n}", "Cannot use an instance field in a static method."),
+    ("foreign class F {\n  get { _x }\n}", "Cannot define fields in a foreign class."),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    let message = match error {
+	WrenError::Compile { error, .. } => error.message.clone(),
+	WrenError::Runtime { message, .. } => message.clone(),
+	_ => return,
+    };
+    OUTPUT.with(|output| output.borrow_mut().push_str(&message));
+}
+
+// A class with `count` fields of its own, as a subclass of `superclass`.
+fn class_with_fields(name: &str, superclass: &str, count: usize) -> String {
+    let mut source = format!("class {} is {} {{\n  construct new() {{\n", name, superclass);
+    for field in 0..count {
+	source.push_str(&format!("    _{}{} = {}\n", name, field, field));
+    }
+    source.push_str("  }\n}\n");
+    source
+}
+
+fn config(opt_level: u8) -> WrenConfiguration {
+    WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    }
+}
+
+fn main() {
+    for opt_level in [0, 1] {
+	let mut vm = WrenVM::with_configuration(config(opt_level));
+	vm.interpret("main", SOURCE).expect("the script runs");
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(output, EXPECTED, "at opt_level {}", opt_level);
+    }
+    print!("{}", EXPECTED);
+
+    // A class may have 255 fields, counting those it inherits.
+    let inherited = class_with_fields("Base", "Object", 200);
+    let limits = [
+	(class_with_fields("Wide", "Object", 255), ""),
+	(class_with_fields("Wider", "Object", 256), "A class can only have 255 fields."),
+	(
+	    inherited + &class_with_fields("Derived", "Base", 56),
+	    "Class 'Derived' may not have more than 255 fields, including inherited ones.",
+	),
+    ];
+    let mut vm = WrenVM::with_configuration(config(1));
+    let errors = ERRORS.iter().map(|&(source, message)| (source.to_string(), message));
+    for (source, expected) in errors.chain(limits) {
+	let result = vm.interpret("main", &source);
+	assert_eq!(result.is_err(), !expected.is_empty());
+	let message = OUTPUT.with(|output| output.take());
+	assert_eq!(message, expected, "for {}", source);
+	if !message.is_empty() {
+	    println!("{}", message);
+	}
+    }
+}
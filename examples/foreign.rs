@@ -0,0 +1,57 @@
+use wren_rs::WrenVM;
+
+// Foreign methods are declared in Wren and implemented in Rust.
+const SOURCE: &str = r#"
+class Math {
+  foreign static hypot(x, y)
+  foreign static checkedSqrt(n)
+}
+
+class Greeter {
+  construct new() {}
+  foreign greet(name)
+}
+
+System.print(Math.hypot(3, 4))
+System.print(Fiber.new { Math.hypot("3", 4) }.try())
+System.print(Greeter.new().greet("Wren"))
+var fiber = Fiber.new { Math.checkedSqrt(-1) }
+System.print(fiber.try())
+"#;
+
+// Arguments can be anything a script passes, so a slot holding the wrong
+// type becomes a runtime error in the calling fiber.
+fn hypot(vm: &mut WrenVM) {
+    match (vm.get_slot_double(1), vm.get_slot_double(2)) {
+	(Ok(x), Ok(y)) => vm.set_slot_double(0, x.hypot(y)),
+	(Err(error), _) | (_, Err(error)) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn checked_sqrt(vm: &mut WrenVM) {
+    match vm.get_slot_double(1) {
+	Ok(n) if n >= 0.0 => vm.set_slot_double(0, n.sqrt()),
+	_ => {
+	    vm.set_slot_string(0, "Cannot take the square root of a negative number.");
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn greet(vm: &mut WrenVM) {
+    let greeting = format!("Hello, {}!", vm.get_slot_string(1).unwrap_or("stranger"));
+    vm.set_slot_string(0, greeting);
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.bind_foreign_method("main", "Math", true, "hypot(_,_)", hypot);
+    vm.bind_foreign_method("main", "Math", true, "checkedSqrt(_)", checked_sqrt);
+    vm.bind_foreign_method("main", "Greeter", false, "greet(_)", greet);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+}
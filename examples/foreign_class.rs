@@ -0,0 +1,62 @@
+use std::any::Any;
+
+use wren_rs::{ForeignClassMethods, WrenVM};
+
+// A foreign class keeps its state in Rust, here a running total.
+const SOURCE: &str = r#"
+foreign class Accumulator {
+  construct new(start) {}
+
+  foreign add(n)
+  foreign total
+}
+
+var sum = Accumulator.new(10)
+for (i in 1..4) sum.add(i)
+System.print(sum.total)
+System.print(sum is Accumulator)
+sum = null
+"#;
+
+struct Accumulator {
+    total: f64,
+}
+
+fn allocate(vm: &mut WrenVM) {
+    let start = vm.get_slot_double(1).unwrap_or(0.0);
+    vm.set_slot_new_foreign(0, 0, Accumulator { total: start })
+	.expect("the Accumulator class");
+}
+
+fn finalize(data: &mut dyn Any) {
+    if let Some(accumulator) = data.downcast_mut::<Accumulator>() {
+	println!("finalized with {}", accumulator.total);
+    }
+}
+
+fn add(vm: &mut WrenVM) {
+    let n = vm.get_slot_double(1).unwrap_or(0.0);
+    let accumulator = vm.get_slot_foreign_mut::<Accumulator>(0).expect("an Accumulator");
+    accumulator.total += n;
+    vm.set_slot_null(0);
+}
+
+fn total(vm: &mut WrenVM) {
+    let total = vm.get_slot_foreign::<Accumulator>(0).expect("an Accumulator").total;
+    vm.set_slot_double(0, total);
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    let methods = ForeignClassMethods {
+	allocate,
+	finalize: Some(finalize),
+    };
+    vm.bind_foreign_class("main", "Accumulator", methods);
+    vm.bind_foreign_method("main", "Accumulator", false, "add(_)", add);
+    vm.bind_foreign_method("main", "Accumulator", false, "total", total);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+    vm.collect_garbage();
+}
@@ -0,0 +1,77 @@
+use wren_rs::{wren_class, ForeignClass, WrenVM};
+
+// `#[wren_class]` writes the allocator, finalizer, method glue and the
+// `foreign class` declaration for a Rust type.
+struct Vector {
+    x: f64,
+    y: f64,
+}
+
+#[wren_class(name = "Vec2")]
+impl Vector {
+    #[wren_method(construct)]
+    fn new(x: f64, y: f64) -> Vector {
+	Vector { x, y }
+    }
+
+    #[wren_method(finalize)]
+    fn finalize(&mut self) {
+	println!("finalized ({}, {})", self.x, self.y);
+    }
+
+    #[wren_method(getter)]
+    fn x(&self) -> f64 {
+	self.x
+    }
+
+    #[wren_method(setter)]
+    fn set_x(&mut self, x: f64) {
+	self.x = x;
+    }
+
+    #[wren_method(getter)]
+    fn length(&self) -> f64 {
+	self.x.hypot(self.y)
+    }
+
+    #[wren_method(name = "scale")]
+    fn scale_by(&mut self, factor: f64) {
+	self.x *= factor;
+	self.y *= factor;
+    }
+
+    #[wren_method(getter, name = "toString")]
+    fn describe(&self) -> String {
+	format!("({}, {})", self.x, self.y)
+    }
+
+    #[wren_method]
+    fn parse(text: String) -> Result<Vec<f64>, String> {
+	text.split(',')
+	    .map(|part| part.trim())
+	    .map(|part| part.parse().map_err(|_| format!("'{}' is not a number.", part)))
+	    .collect()
+    }
+}
+
+const SOURCE: &str = r#"
+var v = Vec2.new(3, 4)
+System.print(v.length)
+v.scale(2)
+v.x = 0
+System.print(v)
+System.print(Vec2.parse("1, 2.5"))
+System.print(Fiber.new { Vec2.parse("1, two") }.try())
+v = null
+"#;
+
+fn main() {
+    print!("{}", Vector::SOURCE);
+    let mut vm = WrenVM::new();
+    Vector::bind(&mut vm, "main").expect("a valid binding");
+    let source = format!("{}{}", Vector::SOURCE, SOURCE);
+    if vm.interpret("main", &source).is_err() {
+	std::process::exit(1);
+    }
+    vm.collect_garbage();
+}
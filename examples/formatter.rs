@@ -0,0 +1,28 @@
+use wren_rs::formatter::{format_with, FormatOptions};
+
+const SOURCE: &str = r#"
+// A messy class.
+class Point{
+construct new(x,y){
+_x=x
+_y=y
+}
+  +(other) {Point.new(_x+other.x,(_y+other.y))}   // adds
+  x {_x}
+      y {_y}
+}
+var sum=Point.new(1,2)+Point.new(3,4)
+System.print([sum.x,
+sum.y])
+"#;
+
+fn main() {
+    let options = FormatOptions { indent_width: 4 };
+    match format_with(SOURCE, &options) {
+	Ok(formatted) => print!("{}", formatted),
+	Err(error) => {
+	    eprintln!("{}", error);
+	    std::process::exit(1);
+	}
+    }
+}
@@ -0,0 +1,49 @@
+use wren_rs::WrenVM;
+
+// Functions are objects: they can be created with `Fn.new`, passed as block
+// arguments, stored in collections and called later.
+const SOURCE: &str = r#"
+var add = Fn.new {|a, b| a + b }
+System.print(add.arity)
+System.print(add.call(1, 2))
+
+class Counter {
+  construct new() { _count = 0 }
+
+  // A block after the arguments is passed as the last one.
+  times(n, f) {
+    for (i in 1..n) {
+      _count = _count + 1
+      f.call(i)
+    }
+  }
+
+  count { _count }
+}
+
+var counter = Counter.new()
+counter.times(3) {|i| System.print("tick %(i)") }
+System.print(counter.count)
+
+var operations = {
+  "double": Fn.new {|n| n * 2 },
+  "square": Fn.new {|n| n * n }
+}
+for (name in ["double", "square"]) {
+  System.print("%(name): %(operations[name].call(7))")
+}
+
+var callbacks = []
+for (i in 1..3) callbacks.add(Fn.new { i * 10 })
+System.print(callbacks.map {|f| f.call() }.toList)
+
+var fiber = Fiber.new { add.call(1) }
+System.print(fiber.try())
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+}
@@ -0,0 +1,45 @@
+use wren_rs::{GcEvent, WrenConfiguration, WrenVM};
+
+// A host keeps an eye on the garbage collector in production, logging each
+// collection as it happens.
+const SOURCE: &str = r#"
+var kept = []
+for (i in 0...100000) {
+  var temporary = "item %(i)"
+  if (i % 100 == 0) kept.add([temporary])
+}
+System.print(kept.count)
+"#;
+
+fn log_gc(_vm: &mut WrenVM, event: &GcEvent) {
+    println!(
+	"{} collection: {} -> {} bytes, {} objects freed, {} left ({} strings), {:.3}ms",
+	if event.minor { "minor" } else { "full" },
+	event.bytes_before,
+	event.bytes_after,
+	event.objects_freed,
+	event.objects.total(),
+	event.objects.strings,
+	event.pause * 1000.0
+    );
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	initial_heap_size: 1024 * 1024,
+	min_heap_size: 1024 * 1024,
+	nursery_size: Some(512 * 1024),
+	gc_fn: Some(log_gc),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    // The last collection can also be looked up afterwards.
+    vm.collect_garbage();
+    if let Some(event) = vm.last_gc() {
+	println!("{} objects left, {} lists", event.objects.total(), event.objects.lists);
+    }
+}
@@ -0,0 +1,47 @@
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// Most objects a typical script makes, like the strings and lists here, are
+// garbage almost at once, while a few live on in a long-lived structure.
+// A nursery collects the former without tracing the latter each time.
+const SOURCE: &str = r#"
+var index = {}
+for (i in 0...20000) index["key%(i)"] = [i, i * 2]
+
+var total = 0
+for (round in 0...50) {
+  for (i in 0...2000) {
+    var parts = ["key", (i * 7 % 20000).toString]
+    total = total + index[parts.join("")][1]
+  }
+  index["round%(round)"] = [round]
+}
+System.print(total)
+"#;
+
+fn run(name: &str, config: WrenConfiguration) {
+    let mut vm = WrenVM::with_configuration(config);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+    let stats = vm.gc_stats();
+    println!(
+	"{}: {} full and {} minor collections, {} objects traced, {} freed, {} tenured",
+	name,
+	stats.collections,
+	stats.minor_collections,
+	stats.objects_traced,
+	stats.objects_freed,
+	stats.objects_tenured
+    );
+}
+
+fn main() {
+    let config = || WrenConfiguration {
+	initial_heap_size: 1024 * 1024,
+	min_heap_size: 1024 * 1024,
+	..WrenConfiguration::default()
+    };
+
+    run("mark-sweep", config());
+    run("generational", WrenConfiguration { nursery_size: Some(256 * 1024), ..config() });
+}
@@ -0,0 +1,47 @@
+use wren_rs::{WrenError, WrenVM};
+
+// The host keeps handles to a Wren object and its method, then calls the
+// method every frame without compiling anything more.
+const SOURCE: &str = r#"
+class Game {
+  construct new() {
+    _time = 0
+  }
+
+  update(dt) {
+    _time = _time + dt
+    return "t = %(_time)"
+  }
+}
+
+var game = Game.new()
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    vm.ensure_slots(1);
+    vm.get_variable("main", "game", 0).expect("a game");
+    let game = vm.get_slot_handle(0);
+    let update = vm.make_call_handle("update(_)").expect("a valid signature");
+
+    // A signature no method could have is an error, not a handle.
+    let result = vm.make_call_handle("update(_");
+    assert!(matches!(result, Err(WrenError::Api { .. })));
+
+    for _ in 0..3 {
+	vm.ensure_slots(2);
+	vm.set_slot_handle(0, &game);
+	vm.set_slot_double(1, 0.5);
+	if vm.call(&update).is_err() {
+	    std::process::exit(1);
+	}
+	println!("{}", vm.get_slot_string(0).expect("a status string"));
+    }
+
+    vm.release_handle(game);
+    vm.release_handle(update);
+}
@@ -0,0 +1,26 @@
+//! Prints each token of a script with how a highlighter would color it.
+//!
+//! Run with `cargo run --example highlight`.
+
+use wren_rs::tokenize_for_highlighting;
+
+const SOURCE: &str = r#"// A counter.
+#!doc = "Counts things"
+class Counter {
+  construct new() { _count = 0 }
+  static zero { Counter.new() }
+  count { _count }
+  increment() { _count = _count + 1 }
+}
+
+var counter = Counter.new()
+counter.increment()
+System.print("Count: %(counter.count) of %(1..10)") /* done */
+"#;
+
+fn main() {
+    for (span, class) in tokenize_for_highlighting(SOURCE) {
+	let text = &SOURCE[span.start..span.end];
+	println!("{:>3}:{:<3} {:<14} {}", span.line, span.column, format!("{:?}", class), text);
+    }
+}
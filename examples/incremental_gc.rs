@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// A game runs a script once a frame and can't stall for a whole collection,
+// so the collector does a little work at a time instead.
+const SOURCE: &str = r#"
+class World {
+  construct new() {
+    _entities = []
+    for (i in 0...2000) _entities.add({"id": i, "trail": []})
+  }
+
+  // Each frame every entity leaves a little garbage behind.
+  update(frame) {
+    for (entity in _entities) {
+      entity["trail"].add([frame, entity["id"]])
+      if (entity["trail"].count > 4) entity["trail"].removeAt(0)
+    }
+  }
+
+  checksum {
+    var sum = 0
+    for (entity in _entities) {
+      for (point in entity["trail"]) sum = sum + point[0] + point[1]
+    }
+    return sum
+  }
+}
+
+var world = World.new()
+"#;
+
+fn run(config: WrenConfiguration, budget: Option<usize>) {
+    let mut vm = WrenVM::with_configuration(config);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    let mut longest = Duration::ZERO;
+    for frame in 0..200 {
+	let start = Instant::now();
+	let source = format!("world.update({})", frame);
+	if vm.interpret("main", &source).is_err() {
+	    std::process::exit(1);
+	}
+	// Collecting between frames keeps up with the garbage they make.
+	if let Some(budget) = budget {
+	    vm.gc_step(budget);
+	}
+	longest = longest.max(start.elapsed());
+    }
+    println!("longest frame {:?}, {} bytes", longest, vm.bytes_allocated());
+    if vm.interpret("main", "System.print(world.checksum)").is_err() {
+	std::process::exit(1);
+    }
+}
+
+fn main() {
+    let config = || WrenConfiguration {
+	min_heap_size: 64 * 1024,
+	initial_heap_size: 64 * 1024,
+	..WrenConfiguration::default()
+    };
+
+    // Collecting all at once.
+    run(config(), None);
+
+    // Tracing at most 100 objects whenever the script allocates, once the
+    // heap has grown enough to need collecting.
+    run(WrenConfiguration { gc_step_size: Some(100), ..config() }, None);
+
+    // The host deciding when to collect, a slice between each frame.
+    run(config(), Some(5_000));
+}
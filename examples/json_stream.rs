@@ -0,0 +1,92 @@
+use std::io::{self, Read};
+
+use wren_rs::{JsonParser, WrenType, WrenVM};
+
+// The host feeds a JSON document to a script an event at a time as it reads
+// it, so the document is never in memory all at once. Here the document is
+// a long array of orders, made up as it is read.
+const SOURCE: &str = r#"
+class Totals {
+  construct new() {
+    _count = 0
+    _sum = 0
+    _key = null
+  }
+
+  // Called with each event of the document and its value.
+  handle(event, value) {
+    if (event == "key") {
+      _key = value
+    } else if (event == "value" && _key == "amount") {
+      _count = _count + 1
+      _sum = _sum + value
+    }
+  }
+
+  toString { "%(_count) orders, %(_sum) in total" }
+}
+
+var totals = Totals.new()
+var handler = Fn.new {|event, value| totals.handle(event, value) }
+"#;
+
+// Reads a document of `count` orders without ever holding more than one.
+struct Orders {
+    count: usize,
+    next: usize,
+    pending: Vec<u8>,
+}
+
+impl Read for Orders {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+	if self.pending.is_empty() && self.next <= self.count {
+	    let order = match self.next {
+		0 => "[".to_string(),
+		n if n == self.count => format!("{{\"id\": {}, \"amount\": {}}}]", n, n % 10),
+		n => format!("{{\"id\": {}, \"amount\": {}}},\n", n, n % 10),
+	    };
+	    self.pending = order.into_bytes();
+	    self.next += 1;
+	}
+	let length = self.pending.len().min(buffer.len());
+	buffer[..length].copy_from_slice(&self.pending[..length]);
+	self.pending.drain(..length);
+	Ok(length)
+    }
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    vm.ensure_slots(1);
+    vm.get_variable("main", "handler", 0).expect("a handler");
+    let handler = vm.get_slot_handle(0);
+    let orders = Orders {
+	count: 100_000,
+	next: 0,
+	pending: Vec::new(),
+    };
+    if let Err(error) = vm.stream_json(&handler, JsonParser::from_reader(orders)) {
+	eprintln!("{}", error);
+	std::process::exit(1);
+    }
+    vm.release_handle(handler);
+    if vm.interpret("main", "System.print(totals)").is_err() {
+	std::process::exit(1);
+    }
+
+    // A small document can be read into a slot whole, as `Json.parse` does,
+    // and errors say where in the document they are.
+    vm.ensure_slots(1);
+    let document = "{\"name\": \"wren\", \"tags\": [\"small\", \"fast\"]}";
+    vm.set_slot_json(0, JsonParser::from_bytes(document.bytes()))
+	.expect("a valid document");
+    println!("{}", matches!(vm.get_slot_type(0), WrenType::Map));
+    let error = vm
+	.set_slot_json(0, JsonParser::from_reader("[1,\n 2,,]".as_bytes()))
+	.expect_err("an invalid document");
+    println!("{}", error);
+}
@@ -0,0 +1,47 @@
+use wren_rs::{LintKind, WrenConfiguration, WrenError, WrenVM};
+
+const SOURCE: &str = r#"
+class Greeter {
+  construct new(name) { _name = name }
+  hello(greeting) { "%(greeting), %(_name)" }
+  static greet(name) {
+    var greeting = "Hello"
+    for (name in [name]) System.print(name)
+    return "Hi, %(name)!"
+    System.print("unreachable")
+  }
+}
+System.prnt(Greeter.greet("Wren", "again"))
+System.print(Greeter.new("Wren").hello())
+System.print(List.new().count)
+"#;
+
+// Reading a local in its own initializer is an error instead, reported
+// where the local is defined.
+const SELF_REFERENCE: &str = r#"
+{
+  var total = total + 1
+}
+"#;
+
+// Warnings arrive at the `error_fn` along with errors.
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    if let WrenError::Warning { module, warning } = error {
+	println!("{} line {}: {}", module, warning.span.line, warning.message);
+    }
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let lints = vm.lint("main", SOURCE).expect("the source parses");
+    let unknown = lints.iter().filter(|lint| lint.kind == LintKind::UnknownMethod).count();
+    println!("{} warnings, {} of them unknown methods", lints.len(), unknown);
+
+    if let Err(WrenError::Compile { error, .. }) = vm.interpret("main", SELF_REFERENCE) {
+	println!("line {}, '{}': {}", error.span.line, error.token, error.message);
+    }
+}
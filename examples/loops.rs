@@ -0,0 +1,97 @@
+//! Checks `break` and `continue` in `while` and `for` loops, nested and
+//! with closures capturing the locals of the iterations they leave.
+//!
+//! Run with `cargo run --example loops`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+const SOURCE: &str = r#"
+var out = []
+for (i in 0...6) {
+  if (i % 2 == 0) continue
+  out.add(i)
+}
+System.print(out)
+var i = 0
+var evens = []
+while (i < 10) {
+  i = i + 1
+  var half = i / 2
+  if (i % 2 == 1) continue
+  evens.add(half)
+}
+System.print(evens)
+var pairs = []
+for (a in 1..3) {
+  for (b in 1..3) {
+    if (b == a) continue
+    if (b > 2) break
+    var sum = a + b
+    pairs.add([a, b, sum])
+  }
+}
+System.print(pairs)
+var fns = []
+for (n in 0...5) {
+  var doubled = n * 2
+  fns.add(Fn.new { [n, doubled] })
+  if (n < 3) continue
+  fns.add(Fn.new { "late %(n)" })
+}
+System.print(fns.map {|f| f.call() }.toList)
+var count = 0
+while (true) {
+  count = count + 1
+  if (count < 5) continue
+  break
+}
+System.print(count)
+var fiber = Fiber.new {
+  for (x in [1, 2, 3]) {
+    var y = x * 10
+    if (x == 2) continue
+    Fiber.yield(y)
+  }
+}
+System.print([fiber.call(), fiber.call()])
+"#;
+
+const EXPECTED: &str = "[1, 3, 5]
+[1, 2, 3, 4, 5]
+[[1, 2, 3], [2, 1, 3], [3, 1, 4], [3, 2, 5]]
+[[0, 0], [1, 2], [2, 4], [3, 6], late 3, [4, 8], late 4]
+5
+[10, 30]
+";
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn main() {
+    // With branches that can't run left in and taken out.
+    for opt_level in [0, 1] {
+	let config = WrenConfiguration {
+	    write_fn: Some(write),
+	    opt_level,
+	    ..WrenConfiguration::default()
+	};
+	let mut vm = WrenVM::with_configuration(config);
+	vm.interpret("main", SOURCE).expect("the script runs");
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(output, EXPECTED, "at opt_level {}", opt_level);
+    }
+    print!("{}", EXPECTED);
+
+    // Neither can be used outside a loop, including from a function
+    // inside one.
+    let mut vm = WrenVM::new();
+    let source = "while (true) {\n  Fn.new {\n    continue\n  }\n  break\n}";
+    assert!(vm.interpret("main", source).is_err());
+}
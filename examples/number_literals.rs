@@ -0,0 +1,66 @@
+//! Checks number literals read as they do in wren_c: decimal, with a
+//! fraction or an exponent, and hexadecimal, along with the errors for
+//! malformed ones.
+//!
+//! Run with `cargo run --example number_literals`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// Each literal, and what printing it writes or the error it is.
+const CASES: &[(&str, &str)] = &[
+    ("0", "0"),
+    ("1234", "1234"),
+    ("1.5", "1.5"),
+    ("0x1F", "31"),
+    ("0xff", "255"),
+    ("0xABCDEF", "11259375"),
+    ("0x7FFFFFFFFFFFFFFF", "9.2233720368548e+18"),
+    // Just `0x` is zero.
+    ("0x", "0"),
+    ("1e3", "1000"),
+    ("1E3", "1000"),
+    ("1e-3", "0.001"),
+    ("2.5e+10", "25000000000"),
+    ("1.5E-2", "0.015"),
+    ("12e00", "12"),
+    ("1e308", "1e+308"),
+    // A method call on a number, rather than a fraction or an exponent.
+    ("0x1F.toString", "31"),
+    ("2.5e3.floor", "2500"),
+    ("1e", "Error: Unterminated scientific notation."),
+    ("1e+", "Error: Unterminated scientific notation."),
+    ("1E-", "Error: Unterminated scientific notation."),
+    ("1e400", "Error: Number literal was too large (8)."),
+    ("0x8000000000000000", "Error: Number literal was too large (8)."),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    if let WrenError::Compile { error, .. } = error {
+	OUTPUT.with(|output| *output.borrow_mut() = format!("Error: {}", error.message));
+    }
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    for (literal, expected) in CASES {
+	let _ = vm.interpret("main", &format!("System.write({})", literal));
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(&output, expected, "for {}", literal);
+	println!("{:<20} {}", literal, output);
+    }
+}
@@ -0,0 +1,50 @@
+use wren_rs::WrenVM;
+
+// Operators are ordinary method calls, so a class can define its own.
+const SOURCE: &str = r#"
+class Vec2 {
+  construct new(x, y) {
+    _x = x
+    _y = y
+  }
+
+  x { _x }
+  y { _y }
+
+  +(other) { Vec2.new(_x + other.x, _y + other.y) }
+  -(other) { Vec2.new(_x - other.x, _y - other.y) }
+  *(scale) { Vec2.new(_x * scale, _y * scale) }
+  - { Vec2.new(-_x, -_y) }
+  ==(other) { (other is Vec2) && _x == other.x && _y == other.y }
+  !=(other) { !(this == other) }
+
+  [index] { index == 0 ? _x : _y }
+  [index]=(value) {
+    if (index == 0) {
+      _x = value
+    } else {
+      _y = value
+    }
+  }
+
+  toString { "(%(_x), %(_y))" }
+}
+
+var a = Vec2.new(1, 2)
+var b = Vec2.new(3, 4)
+System.print((a + b).toString)
+System.print((b - a).toString)
+System.print((a * 3).toString)
+System.print((-a).toString)
+System.print(a + b == Vec2.new(4, 6))
+System.print(a != b)
+a[1] = 10
+System.print(a[1])
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+}
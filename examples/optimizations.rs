@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// Runs scripts compiled as written and optimized, checking they print and
+// report exactly the same things, then shows what the optimizations do to
+// some code.
+const SCRIPTS: &[&str] = &[
+    // Expressions made only of literals.
+    r#"
+    System.print(1 + 2 * 3 - 4 / 8)
+    System.print([-5 % 3, 2 < 3, 3 <= 2, 1 == 1, 1 != 1.0, 0 / 0 == 0 / 0])
+    System.print([~0, 1 << 31, 5 & 3, 5 | 3, 5 ^ 3, -1 >> 28])
+    System.print(["a" + "b" + "c", "x" == "x", "x" == 1, null == null, !null, !true])
+    System.print([true && 1, null && 1, false || "y", 2 || 3, false ? 1 : 2, 1 ? 3 : 4])
+    "#,
+    // Branches that can't run, including ones that break out of a loop.
+    r#"
+    var a = 1
+    if (false) System.print("never") else System.print("else")
+    if (true) System.print("then") else System.print("never")
+    if (1 > 2) {
+      var hidden = Fn.new { a }
+      System.print(hidden.call())
+    }
+    while (false) {
+      System.print("never")
+      break
+    }
+    var count = 0
+    while (true) {
+      count = count + 1
+      if (count == 3) break
+    }
+    for (i in 0...3) {
+      if (false) break
+      if (!true) {
+        for (j in 0...i) break
+      }
+      System.print([i, count])
+    }
+    System.print([true && a, false && a.missing, null || a, 1 || a.missing])
+    System.print(true ? a : a.missing)
+    "#,
+    // Operators the compiler leaves to run, which fail the same way.
+    r#"
+    System.print(Fiber.new { 1 + "a" }.try())
+    System.print(Fiber.new { "a" + 1 }.try())
+    System.print(Fiber.new { -"a" }.try())
+    System.print(1..3)
+    "#,
+    // Code that can't run still has to compile.
+    r#"
+    if (false) System.print(Undefined)
+    "#,
+    r#"
+    while (false) System.print(this)
+    "#,
+];
+
+const EXAMPLE: &str = r#"
+var seconds = 60 * 60 * 24
+if (false) System.print("debugging")
+while (true) {
+  seconds = seconds / 2
+  if (seconds < 1) break
+}
+"#;
+
+thread_local! {
+    // Everything a script printed or reported.
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(&format!("{}\n", error)));
+}
+
+fn config(opt_level: u8) -> WrenConfiguration {
+    WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    }
+}
+
+// What running `source` prints and reports.
+fn run(source: &str, opt_level: u8) -> String {
+    let mut vm = WrenVM::with_configuration(config(opt_level));
+    let _ = vm.interpret("main", source);
+    OUTPUT.with(|output| output.take())
+}
+
+fn main() {
+    for script in SCRIPTS {
+	let optimized = run(script, 1);
+	let plain = run(script, 0);
+	assert_eq!(optimized, plain, "output differs for {}", script);
+	print!("{}", optimized);
+    }
+
+    for opt_level in [0, 1] {
+	let mut vm = WrenVM::with_configuration(config(opt_level));
+	let code = vm.disassemble("main", EXAMPLE).expect("compiles");
+	println!("opt_level {}:\n{}", opt_level, code);
+    }
+}
@@ -1,3 +1,5 @@
+// Kept as it was first written, rather than as clippy would write it.
+#![allow(clippy::is_digit_ascii_radix)]
 
 use std::iter::Peekable;
 
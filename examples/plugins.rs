@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+
+use wren_rs::{ForeignClassMethods, WrenConfiguration, WrenError, WrenVM};
+
+// Foreign methods and classes can be bound at any time, as plugins arrive.
+// Modules compiled afterwards pick the bindings up, and binding a method
+// of a class that is already defined replaces it there and in the
+// subclasses that inherit it, but not those that override it.
+const GREETER: &str = r#"
+class Greeter {
+  construct new() {}
+  foreign greet(name)
+  foreign static language
+}
+
+class LoudGreeter is Greeter {
+  construct new() {}
+}
+
+class QuietGreeter is Greeter {
+  construct new() {}
+  greet(name) { "hi, %(name)" }
+}
+
+var Loud = LoudGreeter.new()
+System.print("%(Greeter.language): %(Loud.greet("ada"))")
+"#;
+
+const LATER: &str = r#"
+System.print("%(Greeter.language): %(Loud.greet("grace"))")
+System.print(QuietGreeter.new().greet("grace"))
+"#;
+
+const PLUGIN: &str = r#"
+foreign class Stopwatch {
+  construct new() {}
+  foreign laps
+  foreign lap()
+}
+
+var watch = Stopwatch.new()
+watch.lap()
+watch.lap()
+System.print("%(watch.laps) laps")
+"#;
+
+const EXPECTED: &str = "\
+English: hello, ada
+French: bonjour, grace
+hi, grace
+2 laps
+";
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(&error.to_string()));
+}
+
+fn greet_in_english(vm: &mut WrenVM) {
+    let name = vm.get_slot_string(1).unwrap_or("nobody").to_string();
+    vm.set_slot_string(0, format!("hello, {}", name));
+}
+
+fn english(vm: &mut WrenVM) {
+    vm.set_slot_string(0, "English");
+}
+
+fn new_stopwatch(vm: &mut WrenVM) {
+    vm.set_slot_new_foreign(0, 0, 0u32).expect("a foreign class");
+}
+
+fn main() {
+    for opt_level in [0, 1] {
+	let mut vm = WrenVM::with_configuration(WrenConfiguration {
+	    write_fn: Some(write),
+	    error_fn: Some(report),
+	    opt_level,
+	    ..WrenConfiguration::default()
+	});
+	vm.bind_foreign_method("main", "Greeter", false, "greet(_)", greet_in_english);
+	vm.bind_foreign_method("main", "Greeter", true, "language", english);
+	vm.interpret("main", GREETER).expect("the script runs");
+
+	// A plugin replaces the greeting, which the subclass inherited.
+	vm.bind("main", "Greeter", "greet(_)", |name: String| format!("bonjour, {}", name))
+	    .expect("a valid binding");
+	vm.bind("main", "Greeter", "static language", || "French").expect("a valid binding");
+	vm.interpret("main", LATER).expect("the script runs");
+
+	// Another brings a foreign class for a module compiled afterwards.
+	vm.bind_foreign_class(
+	    "stopwatch",
+	    "Stopwatch",
+	    ForeignClassMethods {
+		allocate: new_stopwatch,
+		finalize: None,
+	    },
+	);
+	vm.bind("stopwatch", "Stopwatch", "laps", |laps: &mut u32| *laps).expect("a valid binding");
+	vm.bind("stopwatch", "Stopwatch", "lap()", |laps: &mut u32| *laps += 1)
+	    .expect("a valid binding");
+	vm.interpret("stopwatch", PLUGIN).expect("the script runs");
+
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(output, EXPECTED, "at opt_level {}", opt_level);
+    }
+    print!("{}", EXPECTED);
+}
@@ -0,0 +1,50 @@
+use wren_rs::{WrenError, WrenVM};
+
+// A script compiled ahead of time, as a build step might, and shipped as
+// bytes that are run without being parsed or compiled again.
+const SOURCE: &str = r#"
+class Shape {
+  construct square(side) { _area = side * side }
+  area { _area }
+}
+
+var shapes = (1..3).map {|side| Shape.square(side) }
+System.print(shapes.map {|shape| shape.area }.toList)
+"#;
+
+fn main() {
+    let bytes = match WrenVM::new().compile_to_bytes("shapes", SOURCE) {
+	Ok(bytes) => bytes,
+	Err(_) => std::process::exit(1),
+    };
+    println!("compiled to {} bytes", bytes.len());
+
+    // The loading VM has seen other methods and variables first, so the
+    // code is fixed up to use its own tables.
+    let mut vm = WrenVM::new();
+    if vm.interpret("shapes", "var unrelated = Fn.new { 1.neverCalled(2) }").is_err() {
+	std::process::exit(1);
+    }
+    if vm.load_compiled(&bytes).is_err() {
+	std::process::exit(1);
+    }
+
+    // Corrupt or incompatible bytes are refused before anything runs.
+    let mut corrupt = bytes.clone();
+    corrupt.truncate(bytes.len() / 2);
+    match vm.load_compiled(&corrupt) {
+	Err(WrenError::Bytecode { message }) => println!("rejected: {}", message),
+	_ => std::process::exit(1),
+    }
+
+    // So are bytes with a bit flipped in any byte, which the checksum catches
+    // before the code is looked at.
+    for index in 0..bytes.len() {
+	let mut corrupt = bytes.clone();
+	corrupt[index] ^= 0x10;
+	if !matches!(WrenVM::new().load_compiled(&corrupt), Err(WrenError::Bytecode { .. })) {
+	    std::process::exit(1);
+	}
+    }
+    println!("rejected a flipped bit in each of the {} bytes", bytes.len());
+}
@@ -0,0 +1,28 @@
+use wren_rs::{Profiler, WrenVM};
+
+const SOURCE: &str = r#"
+class Shapes {
+  static squares(count) { (1..count).map {|side| side * side }.toList }
+  static total(list) { list.reduce {|sum, area| sum + area } }
+}
+
+for (i in 1..20) Shapes.total(Shapes.squares(100))
+"#;
+
+fn main() {
+    let mut vm = WrenVM::new();
+    // The VM keeps one clone as its debug hook, and this one reads what it
+    // recorded.
+    let profiler = Profiler::new();
+    vm.set_debug_hook(Some(Box::new(profiler.clone())));
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+    vm.set_debug_hook(None);
+
+    let report = profiler.report();
+    for function in &report.functions {
+	println!("{:>6} calls  {}", function.calls, function.label());
+    }
+    println!("{} distinct stacks", report.stacks.len());
+}
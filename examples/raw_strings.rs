@@ -0,0 +1,56 @@
+//! Shows how raw strings, written between `"""`, are read: as written,
+//! without escapes or interpolation, and with a blank first and last line
+//! trimmed as wren_c trims them.
+//!
+//! Run with `cargo run --example raw_strings`.
+
+use std::cell::RefCell;
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// Each raw string, and the string it reads as.
+const CASES: &[(&str, &str)] = &[
+    // Escapes and interpolation are left as written.
+    (r#""""a\n%(b) "c" """"#, r#"a\n%(b) "c" "#),
+    // A first and last line of nothing but whitespace are dropped, with
+    // the newlines after and before them.
+    ("\"\"\"\n  indented\n    more\n  \"\"\"", "  indented\n    more"),
+    ("\"\"\"  \t\nfirst\nlast\n\t  \"\"\"", "first\nlast"),
+    // Whitespace on the same line as text is kept.
+    ("\"\"\" padded \"\"\"", " padded "),
+    ("\"\"\" text\nmore \"\"\"", " text\nmore "),
+    // Only a first line that's blank is dropped, and only a blank last one.
+    ("\"\"\"\n\nafter a blank line\n\n\"\"\"", "\nafter a blank line\n"),
+    // A single blank line is dropped entirely.
+    ("\"\"\"  \n  \"\"\"", ""),
+    ("\"\"\"\"\"\"", ""),
+    // Carriage returns are dropped.
+    ("\"\"\"\r\nwindows\r\nlines\r\n\"\"\"", "windows\nlines"),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    for (literal, expected) in CASES {
+	// `System.write` so the string's own trailing newlines show.
+	let source = format!("System.write({})", literal);
+	vm.interpret("main", &source).expect("the raw string compiles");
+	let output = OUTPUT.with(|output| output.take());
+	assert_eq!(&output, expected, "for {:?}", literal);
+	println!("{:?} reads as {:?}", literal, output);
+    }
+
+    let unterminated = vm.interpret("main", "System.write(\"\"\"never closed)");
+    assert!(unterminated.is_err());
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use wren_rs::WrenVM;
+
+// With the `serde` feature, Rust data goes into a slot, and comes back out,
+// in one call. Structs become maps keyed by field name.
+const SOURCE: &str = r#"
+class Game {
+  foreign static player
+  foreign static save(player)
+}
+
+var player = Game.player
+System.print("%(player["name"]) has %(player["health"]) health")
+player["health"] = player["health"] - 10
+player["inventory"].add("Shield")
+player["state"] = {"Resting": 3}
+Game.save(player)
+"#;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum State {
+    Exploring,
+    Resting(u32),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Player {
+    name: String,
+    health: u32,
+    inventory: Vec<String>,
+    state: State,
+}
+
+fn player(vm: &mut WrenVM) {
+    let player = Player {
+	name: "Ada".to_string(),
+	health: 100,
+	inventory: vec!["Sword".to_string()],
+	state: State::Exploring,
+    };
+    vm.set_slot_serialized(0, &player).unwrap();
+}
+
+fn save(vm: &mut WrenVM) {
+    match vm.get_slot_deserialized::<Player>(1) {
+	Ok(player) => println!("Saved {:?}", player),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.bind_foreign_method("main", "Game", true, "player", player);
+    vm.bind_foreign_method("main", "Game", true, "save(_)", save);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Defines a method of every signature form a class body allows, calls
+//! each, and lists the signature strings they are stored under, which
+//! match wren_c's. Each of those parses back with `Signature::parse`,
+//! which rejects the malformed signatures a host might make a call handle
+//! for.
+//!
+//! Run with `cargo run --example signatures`.
+
+use std::cell::RefCell;
+
+use wren_rs::{Signature, SignatureKind, WrenConfiguration, WrenError, WrenVM};
+
+const SOURCE: &str = r#"
+class Grid {
+  construct new() { _cells = {} }
+  name { _name }
+  name=(value) { _name = value }
+  - { "negated" }
+  ! { "not" }
+  ~ { "complement" }
+  -(other) { "minus" }
+  <=(other) { "at most" }
+  ..(other) { "range" }
+  is(other) { "is" }
+  [x] { _cells[x] }
+  [x, y] { _cells["%(x),%(y)"] }
+  [x]=(value) { _cells[x] = value }
+  [x, y]=(value) { _cells["%(x),%(y)"] = value }
+  resize(width, height) { "resized" }
+  static origin { "origin" }
+  static origin=(value) { "moved" }
+  static [index] { index * 2 }
+  static [index]=(value) { "stored" }
+  static - { "static negated" }
+}
+var grid = Grid.new()
+grid.name = "grid"
+grid[1] = "one"
+grid[1, 2] = "two"
+System.print([grid.name, -grid, !grid, ~grid, grid - 1, grid <= 2, grid..3])
+System.print([grid is Grid, grid[1], grid[1, 2], grid.resize(3, 4)])
+System.print([Grid.origin, Grid.origin = 1, Grid[4], Grid[4] = 5, -Grid])
+"#;
+
+const EXPECTED: &str = "[grid, negated, not, complement, minus, at most, range]
+[is, one, two, resized]
+[origin, moved, 8, stored, static negated]
+";
+
+const SIGNATURES: &[&str] = &[
+    "init new()",
+    "name",
+    "name=(_)",
+    "-",
+    "!",
+    "~",
+    "-(_)",
+    "<=(_)",
+    "..(_)",
+    "is(_)",
+    "[_]",
+    "[_,_]",
+    "[_]=(_)",
+    "[_,_]=(_)",
+    "resize(_,_)",
+    "origin",
+    "origin=(_)",
+];
+
+// Malformed signatures, and the error each is.
+const ERRORS: &[(&str, &str)] = &[
+    ("[] { 1 }", "Expect variable name."),
+    ("[]=(value) { 1 }", "Expect variable name."),
+    ("[x]=(a, b) { 1 }", "Expect ')' after parameter name."),
+    ("name=() { 1 }", "Expect variable name."),
+    ("+ { 1 }", "Expect '(' after operator name."),
+];
+
+// Malformed call signatures, the error each is, and its column.
+const CALL_ERRORS: &[(&str, &str, usize)] = &[
+    ("", "Expect a method name", 1),
+    ("update(_", "Expect ',' or ')' after a parameter", 9),
+    ("update(_,)", "Expect '_' for a parameter", 10),
+    ("update(a)", "Expect '_' for a parameter", 8),
+    ("update (_)", "Unexpected ' ' after the signature", 7),
+    ("[]", "Expect '_' for a subscript's parameter", 2),
+    ("[_]=", "Expect '(' after '='", 5),
+    ("name=(_,_)", "Expect ')' after a setter's parameter", 8),
+    ("+", "Expect '(' after operator name", 2),
+    ("~(_)", "Operator '~' takes no parameters", 2),
+    ("==(_,_)", "Operator '==' takes one parameter", 3),
+    ("while(_)", "'while' is a reserved word", 1),
+    ("init new", "Expect '(' after an initializer's name", 9),
+    (
+	"call(_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_)",
+	"Methods cannot have more than 16 parameters",
+	38,
+    ),
+];
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+// The compile errors are checked as `interpret` returns them instead.
+fn report(_vm: &mut WrenVM, _error: &WrenError) {}
+
+fn main() {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    vm.interpret("main", SOURCE).expect("the script runs");
+    let output = OUTPUT.with(|output| output.take());
+    assert_eq!(output, EXPECTED);
+    print!("{}", output);
+
+    let defined: Vec<&str> = vm.method_signatures().collect();
+    for signature in SIGNATURES {
+	assert!(defined.contains(signature), "missing {}", signature);
+	println!("{}", signature);
+    }
+
+    // Each goes in a module of its own, where `Broken` isn't defined yet.
+    for (index, (method, expected)) in ERRORS.iter().enumerate() {
+	let source = format!("class Broken {{\n  {}\n}}", method);
+	let message = match vm.interpret(&format!("broken{}", index), &source) {
+	    Err(WrenError::Compile { error, .. }) => error.message,
+	    other => panic!("expected a compile error, got {:?}", other),
+	};
+	assert_eq!(&message, expected, "for {}", method);
+	println!("{:<20} {}", method, message);
+    }
+    // Every stored signature parses, and writes back as itself.
+    for signature in SIGNATURES {
+	let parsed = Signature::parse(signature).expect("a valid signature");
+	assert_eq!(&parsed.to_string(), signature);
+    }
+    let parsed = Signature::parse("[_,_]=(_)").expect("a valid signature");
+    assert_eq!((parsed.kind, parsed.arity), (SignatureKind::SubscriptSetter, 3));
+
+    for (signature, expected, column) in CALL_ERRORS {
+	let error = Signature::parse(signature).expect_err("a malformed signature");
+	assert_eq!((&*error.message, error.column), (*expected, *column), "for {}", signature);
+	println!("{:<20} {}", format!("{:?}", signature), error);
+    }
+}
+
+// Built as a test too, so that `cargo test` runs the checks above.
+#[test]
+fn signatures() {
+    main();
+}
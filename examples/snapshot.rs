@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use wren_rs::{ForeignClassMethods, WrenConfiguration, WrenError, WrenVM};
+
+// A game saved part way through and restored into a new VM carries on as
+// the original does: its objects, the closures' captured variables, and a
+// fiber paused in the middle of a loop all come back.
+const SOURCE: &str = r#"
+class Dice {
+  foreign static roll(sides)
+}
+
+class Player {
+  construct new(name) {
+    _name = name
+    _score = 0
+    _history = []
+  }
+  name { _name }
+  score { _score }
+  add(points) {
+    _score = _score + points
+    _history.add(points)
+  }
+  toString { "%(_name): %(_score) %(_history)" }
+}
+
+var makeCounter = Fn.new {
+  var count = 0
+  return Fn.new { count = count + 1 }
+}
+
+var Turns = makeCounter.call()
+var Players = [Player.new("ada"), Player.new("grace")]
+var Scores = {}
+var Game = Fiber.new {
+  while (true) {
+    for (player in Players) {
+      player.add(Dice.roll(6))
+      Scores[player.name] = player.score
+    }
+    Fiber.yield(Turns.call())
+  }
+}
+"#;
+
+const TURN: &str = r#"
+System.print("turn %(Game.call()): %(Players.join(", ")) %(Scores.count) scores")
+"#;
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    eprintln!("{}", error);
+}
+
+// A VM with the game's foreign method bound. Its dice count up from
+// `seed`, so that runs are repeatable.
+fn game_vm(opt_level: u8, seed: u32) -> WrenVM {
+    let mut vm = WrenVM::with_configuration(WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    });
+    let next = AtomicU32::new(seed);
+    vm.bind("main", "Dice", "static roll(_)", move |sides: f64| {
+	let roll = next.fetch_add(1, Ordering::Relaxed);
+	f64::from(roll % sides as u32 + 1)
+    })
+    .expect("a valid binding");
+    vm
+}
+
+fn play(vm: &mut WrenVM, turns: usize) -> String {
+    for _ in 0..turns {
+	vm.interpret("main", TURN).expect("the turn runs");
+    }
+    OUTPUT.with(|output| output.take())
+}
+
+fn main() {
+    for opt_level in [0, 1] {
+	let mut original = game_vm(opt_level, 0);
+	original.interpret("main", SOURCE).expect("the game starts");
+	play(&mut original, 2);
+	let saved = original.snapshot().expect("the game saves");
+	assert!(wren_rs::snapshot::is_snapshot(&saved));
+
+	// The restored game rolls the dice the original would have.
+	let mut restored = game_vm(opt_level, 4);
+	restored.restore(&saved).expect("the game restores");
+	let from_save = play(&mut restored, 3);
+	let continued = play(&mut original, 3);
+	assert_eq!(from_save, continued, "at opt_level {}", opt_level);
+	if opt_level == 0 {
+	    print!("{}", continued);
+	}
+
+	// Saving the same state twice gives the same bytes.
+	let again = restored.snapshot().expect("the game saves");
+	assert_eq!(again, original.snapshot().expect("the game saves"));
+    }
+
+    // A VM without the foreign method the game uses can't restore it, nor
+    // can one given something other than a whole snapshot.
+    let mut original = game_vm(0, 0);
+    original.interpret("main", SOURCE).expect("the game starts");
+    let saved = original.snapshot().expect("the game saves");
+    let unbound = WrenConfiguration {
+	opt_level: 0,
+	..WrenConfiguration::default()
+    };
+    let errors = [
+	(
+	    WrenVM::with_configuration(unbound).restore(&saved),
+	    "Could not find foreign method 'roll(_)' for class Dice",
+	),
+	(game_vm(0, 0).restore(b"\0wrb"), "Not a Wren snapshot."),
+	(
+	    game_vm(0, 0).restore(&saved[..saved.len() - 1]),
+	    "The snapshot is corrupt: its checksum doesn't match.",
+	),
+    ];
+    for (result, expected) in errors {
+	match result {
+	    Err(WrenError::Snapshot { message }) => {
+		assert!(message.starts_with(expected), "{}", message);
+		println!("rejected: {}", message);
+	    }
+	    other => panic!("expected an error, got {:?}", other),
+	}
+    }
+    // A save edited by hand is rejected wherever it was changed. The tests
+    // try every byte; a few here are enough to show it.
+    for index in [0, 4, saved.len() / 3, saved.len() / 2, saved.len() - 1] {
+	let mut edited = saved.clone();
+	edited[index] ^= 0x10;
+	assert!(matches!(game_vm(0, 0).restore(&edited), Err(WrenError::Snapshot { .. })));
+    }
+
+    // A failed restore leaves the VM as it was, without the objects it had
+    // made for the snapshot by the time it failed.
+    let mut vm = game_vm(0, 0);
+    vm.interpret("main", "var Kept = 1").expect("the script runs");
+    assert!(vm.restore(&saved[..saved.len() / 2]).is_err());
+    vm.interpret("main", "System.print(Kept)").expect("the script runs");
+    assert_eq!(OUTPUT.with(|output| output.take()), "1\n");
+    let mut vm = WrenVM::with_configuration(WrenConfiguration {
+	opt_level: 0,
+	..WrenConfiguration::default()
+    });
+    let objects = vm.object_counts().total();
+    assert!(vm.restore(&saved).is_err());
+    assert_eq!(vm.object_counts().total(), objects);
+
+    // A `Random` is saved with its state, so the restored one goes on with
+    // the same numbers.
+    let source = "import \"random\" for Random\nvar Dice = Random.new(12345)";
+    let roll = "System.print((1..5).map { Dice.int(6) }.toList)";
+    let mut original = game_vm(0, 0);
+    original.interpret("main", source).expect("the script runs");
+    original.interpret("main", roll).expect("the dice roll");
+    OUTPUT.with(|output| output.take());
+    let saved = original.snapshot().expect("a Random saves");
+    let mut restored = game_vm(0, 0);
+    restored.restore(&saved).expect("a Random restores");
+    restored.interpret("main", roll).expect("the dice roll");
+    let from_save = OUTPUT.with(|output| output.take());
+    original.interpret("main", roll).expect("the dice roll");
+    assert_eq!(from_save, OUTPUT.with(|output| output.take()));
+
+    // Other foreign objects hold the host's data, which can't be saved.
+    let mut vm = game_vm(0, 0);
+    vm.bind_foreign_class(
+	"main",
+	"Token",
+	ForeignClassMethods {
+	    allocate: |vm| vm.set_slot_new_foreign(0, 0, 7u8).expect("a foreign class"),
+	    finalize: None,
+	},
+    );
+    let source = "foreign class Token {\n  construct new() {}\n}\nvar token = Token.new()";
+    vm.interpret("main", source).expect("the script runs");
+    let error = vm.snapshot().expect_err("a foreign object is reachable");
+    assert!(error.to_string().contains("of class Token"), "{}", error);
+    println!("rejected: {}", error);
+}
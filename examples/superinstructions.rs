@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// Runs scripts with and without superinstructions, checking they print and
+// report exactly the same things, then times a loop heavy on arithmetic.
+const SCRIPTS: &[&str] = &[
+    // Numbers, through every operator, with constants and locals.
+    r#"
+    var f = Fn.new {|a, b|
+      System.print([a + b, a - b, a * b, a / b, a % b, b % a])
+      System.print([a < b, a > b, a <= b, a >= b, a == b, a != b])
+      System.print([a + 2, a - 2, a * 2, a / 2, a % 2, a < 2, a == 2])
+    }
+    f.call(7, 3)
+    f.call(-7.5, 0)
+    f.call(0, -0)
+    f.call(0 / 0, 1)
+    "#,
+    // Comparisons that branch, including as the right of `&&` and `||`,
+    // which jump straight to the branch.
+    r#"
+    var count = 0
+    for (i in 0...20) {
+      if (i < 5) count = count + 1
+      if (i > 2 && i < 8) count = count + 10
+      if (i == 3 || i >= 18) count = count + 100
+      var j = i
+      while (j > 15) j = j - 1
+      count = count + (i < 10 ? 1000 : j)
+    }
+    System.print(count)
+    "#,
+    // Operands that aren't numbers, which call the methods as usual.
+    r#"
+    class Vec {
+      construct new(x) { _x = x }
+      x { _x }
+      +(other) { Vec.new(_x + (other is Vec ? other.x : other)) }
+      <(other) { _x < other }
+      toString { "Vec(%(_x))" }
+    }
+    var v = Vec.new(1)
+    System.print(v + 2)
+    var w = v
+    System.print(w + v)
+    if (v < 2) System.print("less")
+    System.print("a" + "b")
+    var s = "c"
+    System.print(s + "d")
+    System.print(null == 1)
+    System.print(1 == null)
+    System.print(1 == "1")
+    "#,
+    // Runtime errors come from the same place either way.
+    r#"
+    var a = 1
+    System.print(Fiber.new { a + "x" }.try())
+    System.print(Fiber.new { a < null }.try())
+    System.print(Fiber.new {
+      if (a < "b") System.print("no")
+    }.try())
+    a + []
+    "#,
+];
+
+const BENCHMARK: &str = r#"
+var fib = Fn.new {|n|
+  var a = 0
+  var b = 1
+  for (i in 0...n) {
+    var t = a + b
+    a = b
+    b = t % 1000000
+  }
+  return a
+}
+var total = 0
+for (i in 0...2000) total = total + fib.call(1000)
+System.print(total)
+"#;
+
+thread_local! {
+    // Everything a script printed or reported.
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(&format!("{}\n", error)));
+}
+
+// What running `source` prints and reports.
+fn run(source: &str, superinstructions: bool) -> String {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	superinstructions,
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let _ = vm.interpret("main", source);
+    OUTPUT.with(|output| output.take())
+}
+
+fn main() {
+    for script in SCRIPTS {
+	let fused = run(script, true);
+	let plain = run(script, false);
+	assert_eq!(fused, plain, "output differs for {}", script);
+	print!("{}", fused);
+    }
+
+    for superinstructions in [false, true] {
+	let start = Instant::now();
+	let output = run(BENCHMARK, superinstructions);
+	let elapsed = start.elapsed();
+	println!("superinstructions {}: {:?}, {}", superinstructions, elapsed, output.trim());
+    }
+}
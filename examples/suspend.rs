@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use wren_rs::{WrenHandle, WrenVM};
+
+// A foreign method starts a slow operation on another thread and suspends
+// its fiber, and the host resumes the fiber with the result once it
+// arrives. Here the operation is a pretend download.
+const SOURCE: &str = r#"
+class Http {
+  foreign static get(url)
+}
+
+System.print("fetching...")
+System.print(Http.get("/index"))
+var error = Fiber.new { Http.get("/missing") }.try()
+System.print("failed: %(error)")
+System.print(Http.get("/about"))
+"#;
+
+type Request = JoinHandle<(WrenHandle, Result<String, String>)>;
+
+thread_local! {
+    // The requests in flight, kept here as foreign methods are plain
+    // functions.
+    static REQUESTS: RefCell<Vec<Request>> = const { RefCell::new(Vec::new()) };
+}
+
+fn http_get(vm: &mut WrenVM) {
+    let url = vm.get_slot_string(1).unwrap_or("").to_string();
+    let fiber = vm.suspend_fiber().expect("a foreign method");
+    let request = thread::spawn(move || {
+	thread::sleep(Duration::from_millis(50));
+	let body = match url.as_str() {
+	    "/missing" => Err(format!("{} was not found.", url)),
+	    _ => Ok(format!("<html>{}</html>", url)),
+	};
+	(fiber, body)
+    });
+    REQUESTS.with(|requests| requests.borrow_mut().push(request));
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.bind_foreign_method("main", "Http", true, "get(_)", http_get);
+    if vm.interpret("main", SOURCE).is_err() {
+	std::process::exit(1);
+    }
+
+    // Each resumed fiber runs until it finishes or makes another request,
+    // so the script is done when no request is left.
+    while let Some(request) = REQUESTS.with(|requests| requests.borrow_mut().pop()) {
+	let (fiber, body) = request.join().expect("a finished request");
+	let result = vm.resume_fiber(&fiber, body);
+	vm.release_handle(fiber);
+	if result.is_err() {
+	    std::process::exit(1);
+	}
+    }
+}
@@ -0,0 +1,105 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use wren_rs::{WrenConfiguration, WrenVM};
+
+// Benchmarks that each lean on different instructions, timed with the
+// `match`-based loop and with threaded dispatch.
+const BENCHMARKS: &[(&str, &str)] = &[
+    (
+	"calls",
+	r#"
+var fib
+fib = Fn.new {|n| n < 2 ? n : fib.call(n - 1) + fib.call(n - 2) }
+System.print(fib.call(27))
+"#,
+    ),
+    (
+	"loops",
+	r#"
+var total = 0
+for (i in 0...3000000) {
+  if (i % 3 == 0 || i % 5 == 0) total = total + i
+}
+System.print(total)
+"#,
+    ),
+    (
+	"methods",
+	r#"
+class Toggle {
+  construct new(state) { _state = state }
+  value { _state }
+  activate() {
+    _state = !_state
+    return this
+  }
+}
+var toggle = Toggle.new(true)
+var count = 0
+for (i in 0...1000000) {
+  if (toggle.activate().value) count = count + 1
+}
+System.print(count)
+"#,
+    ),
+    (
+	"closures",
+	r#"
+var counter = Fn.new {
+  var count = 0
+  return Fn.new { count = count + 1 }
+}
+var last = 0
+for (i in 0...100000) {
+  var next = counter.call()
+  for (j in 0...10) last = next.call()
+}
+System.print(last)
+"#,
+    ),
+    (
+	"strings",
+	r#"
+var length = 0
+for (i in 0...200000) {
+  var word = "w%(i % 100)"
+  length = length + word.count + word.indexOf("9")
+}
+System.print(length)
+"#,
+    ),
+];
+
+thread_local! {
+    // Everything a benchmark printed.
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+// How long running `source` took, and what it printed.
+fn run(source: &str, threaded_dispatch: bool) -> (Duration, String) {
+    let config = WrenConfiguration {
+	write_fn: Some(write),
+	threaded_dispatch,
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let start = Instant::now();
+    if vm.interpret("main", source).is_err() {
+	std::process::exit(1);
+    }
+    (start.elapsed(), OUTPUT.with(|output| output.take()))
+}
+
+fn main() {
+    for (name, source) in BENCHMARKS {
+	let (matched, expected) = run(source, false);
+	let (threaded, output) = run(source, true);
+	assert_eq!(output, expected, "output differs for {}", name);
+	println!("{}: match {:?}, threaded {:?}", name, matched, threaded);
+    }
+}
@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// VMs share no state, so each thread can run its own, and with the `send`
+// feature a VM can be moved from one thread to another between calls.
+const SOURCE: &str = r#"
+class Host {
+  foreign static seed
+  foreign static tally()
+}
+
+class Sieve {
+  static primes(limit) {
+    var composite = List.filled(limit + 1, false)
+    var primes = []
+    for (i in 2..limit) {
+      if (!composite[i]) {
+        primes.add(i)
+        var j = i * i
+        while (j <= limit) {
+          composite[j] = true
+          j = j + i
+        }
+      }
+    }
+    return primes
+  }
+}
+
+var Total = 0
+var limit = 200 + Host.seed
+for (prime in Sieve.primes(limit)) Total = Total + prime
+Host.tally()
+System.print("%(Host.seed): %(Total)")
+
+class Counter {
+  static add(n) {
+    Total = Total + n
+    return Total
+  }
+}
+"#;
+
+const THREADS: usize = 32;
+
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| output.borrow_mut().push_str(text));
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    eprintln!("{}", error);
+}
+
+// What `SOURCE` prints for `seed`, worked out on the host.
+fn expected(seed: usize) -> String {
+    let limit = 200 + seed;
+    let total: usize = (2..=limit).filter(|&n| (2..n).all(|d| n % d != 0)).sum();
+    format!("{}: {}\n", seed, total)
+}
+
+// A VM running `SOURCE` with `Host.seed` as `seed`, counting its runs in
+// `tallies`.
+fn vm_for(seed: usize, opt_level: u8, tallies: &Arc<AtomicUsize>) -> WrenVM {
+    let mut vm = WrenVM::with_configuration(WrenConfiguration {
+	write_fn: Some(write),
+	error_fn: Some(report),
+	opt_level,
+	..WrenConfiguration::default()
+    });
+    vm.bind("main", "Host", "static seed", move || seed).expect("a valid binding");
+    let tallies = Arc::clone(tallies);
+    vm.bind("main", "Host", "static tally()", move || {
+	tallies.fetch_add(1, Ordering::Relaxed);
+    })
+    .expect("a valid binding");
+    vm
+}
+
+fn assert_send<T: Send>() {}
+
+fn main() {
+    assert_send::<WrenVM>();
+    let tallies = Arc::new(AtomicUsize::new(0));
+
+    // Many VMs at once, one per thread, each collecting garbage and
+    // interning symbols of its own.
+    let workers: Vec<_> = (0..THREADS)
+	.map(|seed| {
+	    let tallies = Arc::clone(&tallies);
+	    thread::spawn(move || {
+		for opt_level in [0, 1] {
+		    let mut vm = vm_for(seed, opt_level, &tallies);
+		    vm.interpret("main", SOURCE).expect("the script runs");
+		    vm.collect_garbage();
+		    let output = OUTPUT.with(|output| output.take());
+		    assert_eq!(output, expected(seed), "at opt_level {}", opt_level);
+		}
+	    })
+	})
+	.collect();
+    for worker in workers {
+	worker.join().expect("the thread finishes");
+    }
+    assert_eq!(tallies.load(Ordering::Relaxed), THREADS * 2);
+
+    // A VM made on this thread carries on where it left off on another,
+    // with its handles, and comes back.
+    let mut vm = vm_for(THREADS, 1, &tallies);
+    vm.interpret("main", SOURCE).expect("the script runs");
+    let first = OUTPUT.with(|output| output.take());
+    assert_eq!(first, expected(THREADS));
+    let (mut vm, total) = thread::spawn(move || {
+	vm.ensure_slots(2);
+	vm.get_variable("main", "Counter", 0).expect("Counter is defined");
+	let add = vm.make_call_handle("add(_)").expect("a valid signature");
+	vm.set_slot_double(1, 1.0);
+	vm.call(&add).expect("the call succeeds");
+	let total = vm.get_slot_double(0).expect("a number");
+	vm.release_handle(add);
+	(vm, total)
+    })
+    .join()
+    .expect("the thread finishes");
+    vm.interpret("main", "System.print(Total)").expect("the script runs");
+    let moved = OUTPUT.with(|output| output.take());
+    assert_eq!(moved, format!("{}\n", total));
+    print!("{}", first);
+    println!("{} threads, {} runs", THREADS, tallies.load(Ordering::Relaxed));
+}
@@ -0,0 +1,49 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// A host running untrusted scripts stops the ones that never finish, either
+// after a number of instructions or after a time limit.
+const RUNAWAY: &str = r#"
+Fiber.new {
+  while (true) {}
+}.try()
+System.print("unreachable")
+"#;
+
+thread_local! {
+    // When the script being run must stop by.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+fn past_deadline(_vm: &mut WrenVM) -> bool {
+    DEADLINE.with(|deadline| deadline.get().is_some_and(|deadline| Instant::now() >= deadline))
+}
+
+fn main() {
+    let config = WrenConfiguration {
+	interrupt_fn: Some(past_deadline),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+
+    // Fuel counts instructions, so the same script always stops at the
+    // same point, and what is left carries over to the next call.
+    vm.set_fuel(Some(1_000));
+    let result = vm.interpret("main", "System.print(\"fueled\")");
+    println!("{:?}, {:?} left", result, vm.fuel());
+    let result = vm.interpret("main", RUNAWAY);
+    assert_eq!(result, Err(WrenError::Timeout));
+    vm.set_fuel(None);
+
+    // The interrupt function is called every so often, here to enforce a
+    // time limit.
+    DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + Duration::from_millis(100))));
+    let result = vm.interpret("main", RUNAWAY);
+    assert_eq!(result, Err(WrenError::Timeout));
+    DEADLINE.with(|deadline| deadline.set(None));
+
+    // The VM is still usable afterwards.
+    let _ = vm.interpret("main", "System.print(\"still running\")");
+}
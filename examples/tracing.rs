@@ -0,0 +1,120 @@
+//! Times the VM's `tracing` spans with a small subscriber of its own,
+//! totalling them by name and by the module, method or class each is for,
+//! which is the view an embedder's own subscriber would give of where a
+//! script spends its time.
+//!
+//! Run with `cargo run --example tracing --features tracing`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use wren_rs::WrenVM;
+
+const SOURCE: &str = r#"
+import "random" for Random
+
+class Dice {
+  foreign static roll(sides)
+}
+
+var random = Random.new(12)
+var total = 0
+for (i in 1..100) total = total + Dice.roll(6) + random.int(6)
+System.gc()
+System.print(total)
+"#;
+
+#[derive(Default)]
+struct Spans {
+    // The label of each span by its id, less one, and when it was entered.
+    open: Vec<(String, Option<Instant>)>,
+    // How many times spans with each label were entered, and for how long.
+    totals: BTreeMap<String, (usize, Duration)>,
+}
+
+#[derive(Clone, Default)]
+struct Timings(Arc<Mutex<Spans>>);
+
+// Appends the value of a span's first field to its label.
+struct Label<'a>(&'a mut String, bool);
+
+impl Visit for Label<'_> {
+    fn record_str(&mut self, _field: &Field, value: &str) {
+	if !self.1 {
+	    self.0.push(' ');
+	    self.0.push_str(value);
+	    self.1 = true;
+	}
+    }
+
+    fn record_debug(&mut self, _field: &Field, value: &dyn std::fmt::Debug) {
+	if !self.1 {
+	    self.0.push_str(&format!(" {:?}", value));
+	    self.1 = true;
+	}
+    }
+}
+
+impl Subscriber for Timings {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+	true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+	let mut label = span.metadata().name().to_string();
+	span.record(&mut Label(&mut label, false));
+	let mut spans = self.0.lock().unwrap();
+	spans.open.push((label, None));
+	Id::from_u64(spans.open.len() as u64)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event) {}
+
+    fn enter(&self, span: &Id) {
+	let mut spans = self.0.lock().unwrap();
+	spans.open[span.into_u64() as usize - 1].1 = Some(Instant::now());
+    }
+
+    fn exit(&self, span: &Id) {
+	let mut spans = self.0.lock().unwrap();
+	let (label, entered) = &spans.open[span.into_u64() as usize - 1];
+	let (label, elapsed) = (label.clone(), entered.expect("an entered span").elapsed());
+	let total = spans.totals.entry(label).or_default();
+	total.0 += 1;
+	total.1 += elapsed;
+    }
+}
+
+fn main() {
+    let timings = Timings::default();
+    tracing::subscriber::with_default(timings.clone(), || {
+	let mut vm = WrenVM::new();
+	let next = AtomicU32::new(0);
+	vm.bind("main", "Dice", "static roll(_)", move |sides: f64| {
+	    f64::from(next.fetch_add(1, Ordering::Relaxed) % sides as u32 + 1)
+	})
+	.expect("a valid binding");
+	vm.interpret("main", SOURCE).expect("the script runs");
+    });
+
+    let spans = timings.0.lock().unwrap();
+    for (label, (count, elapsed)) in &spans.totals {
+	println!("{:>5} x {:<28} {:>10.3} ms", count, label, elapsed.as_secs_f64() * 1000.0);
+    }
+    let count = |label: &str| spans.totals.get(label).map_or(0, |total| total.0);
+    assert_eq!(count("compile main"), 1);
+    assert_eq!(count("load_module random"), 1);
+    assert_eq!(count("compile random"), 1);
+    assert_eq!(count("foreign_call roll(_)"), 100);
+    assert_eq!(count("foreign_allocate Random"), 1);
+    assert!(count("collect_garbage") >= 1);
+}
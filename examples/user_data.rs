@@ -0,0 +1,57 @@
+use wren_rs::WrenVM;
+
+// Foreign methods are plain functions, so state of the host's that they
+// need is kept on the VM as user data rather than in a global.
+const SOURCE: &str = r#"
+class Game {
+  foreign static award(player, points)
+  foreign static score(player)
+}
+
+Game.award("ada", 10)
+Game.award("grace", 5)
+Game.award("ada", 7)
+System.print("ada has %(Game.score("ada"))")
+"#;
+
+#[derive(Default)]
+struct Scores {
+    entries: Vec<(String, f64)>,
+}
+
+impl Scores {
+    fn of(&self, player: &str) -> f64 {
+	let points = self.entries.iter().filter(|(name, _)| name == player);
+	points.map(|(_, points)| points).sum()
+    }
+}
+
+fn award(vm: &mut WrenVM) {
+    let player = vm.get_slot_string(1).unwrap_or("nobody").to_string();
+    let points = vm.get_slot_double(2).unwrap_or(0.0);
+    let scores = vm.user_data_mut::<Scores>().expect("the host keeps scores");
+    scores.entries.push((player, points));
+    vm.set_slot_null(0);
+}
+
+fn score(vm: &mut WrenVM) {
+    let player = vm.get_slot_string(1).unwrap_or("nobody").to_string();
+    let points = vm.user_data::<Scores>().map_or(0.0, |scores| scores.of(&player));
+    vm.set_slot_double(0, points);
+}
+
+fn main() {
+    let mut vm = WrenVM::new();
+    vm.set_user_data(Scores::default());
+    vm.bind_foreign_method("main", "Game", true, "award(_,_)", award);
+    vm.bind_foreign_method("main", "Game", true, "score(_)", score);
+    vm.interpret("main", SOURCE).expect("the script runs");
+
+    // User data of another type is not there, and taking it as one leaves
+    // it in place.
+    assert!(vm.user_data::<String>().is_none());
+    assert!(vm.take_user_data::<String>().is_none());
+    let scores = vm.take_user_data::<Scores>().expect("the scores");
+    assert!(vm.user_data::<Scores>().is_none());
+    println!("grace has {}, {} awards", scores.of("grace"), scores.entries.len());
+}
@@ -0,0 +1,108 @@
+//! Walks a script's syntax tree to list the methods it calls, then folds
+//! it to replace a flag with its value.
+//!
+//! Run with `cargo run --example visitor`.
+
+use std::collections::BTreeMap;
+
+use wren_rs::ast::{Expr, ExprKind, Ident, Module};
+use wren_rs::parser;
+use wren_rs::visit::{self, Fold, Visitor};
+
+const SOURCE: &str = r#"
+var verbose = true
+class Logger {
+  construct new(prefix) { _prefix = prefix }
+  log(message) {
+    if (verbose) System.print("%(_prefix): %(message)")
+  }
+}
+var logger = Logger.new("main")
+[1, 2, 3].each {|n| logger.log(n * 2) }
+"#;
+
+// Counts calls by method name, with the line of the first.
+#[derive(Default)]
+struct Calls<'a> {
+    calls: BTreeMap<&'a str, (usize, u32)>,
+}
+
+impl<'a> Visitor<'a> for Calls<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+	if let ExprKind::Call { name, .. } = &expr.kind {
+	    let entry = self.calls.entry(&name.name).or_insert((0, name.span.line));
+	    entry.0 += 1;
+	}
+	visit::walk_expr(self, expr);
+    }
+}
+
+// Replaces a variable with a constant.
+struct Inline {
+    name: &'static str,
+    value: bool,
+}
+
+impl Fold for Inline {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+	match expr.kind {
+	    ExprKind::Name(name) if name == self.name => Expr {
+		kind: ExprKind::Bool(self.value),
+		span: expr.span,
+	    },
+	    _ => visit::fold_expr(self, expr),
+	}
+    }
+}
+
+// Collects every name written in a declaration.
+struct Declared<'a>(Vec<&'a Ident>);
+
+impl<'a> Visitor<'a> for Declared<'a> {
+    fn visit_ident(&mut self, ident: &'a Ident) {
+	self.0.push(ident);
+    }
+}
+
+fn uses_of(module: &Module, name: &str) -> usize {
+    struct Uses<'n>(&'n str, usize);
+    impl<'a> Visitor<'a> for Uses<'_> {
+	fn visit_expr(&mut self, expr: &'a Expr) {
+	    if matches!(&expr.kind, ExprKind::Name(name) if name == self.0) {
+		self.1 += 1;
+	    }
+	    visit::walk_expr(self, expr);
+	}
+    }
+    let mut uses = Uses(name, 0);
+    uses.visit_module(module);
+    uses.1
+}
+
+fn main() {
+    let module = parser::parse(SOURCE).expect("the source parses");
+
+    let mut calls = Calls::default();
+    calls.visit_module(&module);
+    for (name, (count, line)) in &calls.calls {
+	println!("{} called {} time(s), first on line {}", name, count, line);
+    }
+
+    let mut declared = Declared(Vec::new());
+    declared.visit_module(&module);
+    let names: Vec<String> = declared
+	.0
+	.iter()
+	.map(|ident| format!("{}@{}:{}", ident.name, ident.span.line, ident.span.column))
+	.collect();
+    println!("names: {}", names.join(" "));
+
+    let before = uses_of(&module, "verbose");
+    let mut inline = Inline {
+	name: "verbose",
+	value: false,
+    };
+    let folded = inline.fold_module(module.clone());
+    println!("uses of verbose: {} before, {} after", before, uses_of(&folded, "verbose"));
+    assert_eq!(folded.statements.len(), module.statements.len());
+}
@@ -0,0 +1,37 @@
+// Runs arbitrary source in a VM limited the way a host running untrusted
+// scripts would limit it, so that every script ends, one way or another,
+// without taking the host down:
+//
+//     cargo +nightly fuzz run interpret
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+// How many instructions each script may run.
+const FUEL: u64 = 100_000;
+
+fn write(_: &mut WrenVM, _: &str) {}
+
+fn report(_: &mut WrenVM, _: &WrenError) {}
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+	Ok(source) => source,
+	Err(_) => return,
+    };
+    let config = WrenConfiguration {
+	initial_heap_size: 1024 * 1024,
+	min_heap_size: 256 * 1024,
+	max_heap_size: Some(16 * 1024 * 1024),
+	max_call_depth: 1_000,
+	max_stack_size: 1 << 16,
+	write_fn: Some(write),
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    vm.set_fuel(Some(FUEL));
+    let _ = vm.interpret("main", source);
+});
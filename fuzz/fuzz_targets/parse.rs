@@ -0,0 +1,15 @@
+// Parses and compiles arbitrary source without running it, which should
+// only ever produce diagnostics:
+//
+//     cargo +nightly fuzz run parse
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+	let _ = wren_rs::parse(source);
+	let _ = wren_rs::check(source);
+    }
+});
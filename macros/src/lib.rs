@@ -0,0 +1,285 @@
+// `#[wren_class]`, which exports a Rust type to scripts as a foreign class.
+//
+// It goes on an `impl` block of the type and generates the glue a foreign
+// class needs by hand: an allocator that calls the constructor, a finalizer,
+// a closure for each method, and the `foreign class` declaration scripts
+// define the class with. `wren_rs` re-exports it with the `macros` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Error, FnArg, ImplItem, ImplItemFn, ItemImpl, LitStr, Pat, Type};
+
+/// Exports the type an `impl` block is for as a foreign class, by
+/// implementing `wren_rs::ForeignClass` for it. The class is named after the
+/// type, unless it is given as in `#[wren_class(name = "Point")]`.
+///
+/// The methods marked `#[wren_method]` are exported, taking and returning
+/// `FromSlot` and `IntoSlot` values. One taking `&self` or `&mut self` is a
+/// method on instances and one taking neither is static. The mark takes
+/// these options:
+///
+/// - `construct`: the constructor, which returns `Self` or a `Result` with
+///   it. A foreign class has at most one.
+/// - `finalize`: called with `&mut self` when the object is collected.
+/// - `getter` and `setter`: exported as `name` and `name=(_)`. A setter
+///   named `set_name` sets `name`.
+/// - `name = "..."`: the method's name in scripts, if not its Rust name.
+#[proc_macro_attribute]
+pub fn wren_class(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+	if meta.path.is_ident("name") {
+	    name = Some(meta.value()?.parse::<LitStr>()?.value());
+	    Ok(())
+	} else {
+	    Err(meta.error("expected `name = \"...\"`"))
+	}
+    });
+    parse_macro_input!(attr with parser);
+    let mut item = parse_macro_input!(item as ItemImpl);
+    match expand(&mut item, name) {
+	Ok(tokens) => tokens.into(),
+	Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Marks a method of a `#[wren_class]` impl to export. See `wren_class` for
+/// its options.
+#[proc_macro_attribute]
+pub fn wren_method(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    // `wren_class` removes the marks it handles, so this one is misplaced.
+    let message = "#[wren_method] marks a method in a #[wren_class] impl";
+    let mut tokens = Error::new(Span::call_site(), message).to_compile_error();
+    tokens.extend(TokenStream2::from(item));
+    tokens.into()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Method,
+    Getter,
+    Setter,
+    Construct,
+    Finalize,
+}
+
+// What a `#[wren_method]` mark says about its method.
+struct Export {
+    kind: Kind,
+    name: Option<String>,
+}
+
+fn expand(item: &mut ItemImpl, name: Option<String>) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+	return Err(Error::new(path.span(), "#[wren_class] goes on an inherent impl"));
+    }
+    if !item.generics.params.is_empty() {
+	let message = "a generic type can't be a foreign class";
+	return Err(Error::new(item.generics.span(), message));
+    }
+    let class = match (name, &*item.self_ty) {
+	(Some(name), _) => name,
+	(None, Type::Path(path)) => match path.path.segments.last() {
+	    Some(segment) => segment.ident.to_string(),
+	    None => return Err(Error::new(path.span(), "expected a type name")),
+	},
+	(None, ty) => {
+	    let message = "the class needs a name, as in #[wren_class(name = \"...\")]";
+	    return Err(Error::new(ty.span(), message));
+	}
+    };
+
+    let mut declarations = Vec::new();
+    let mut bindings = Vec::new();
+    let mut allocate = None;
+    let mut finalize = None;
+    for item in &mut item.items {
+	let method = match item {
+	    ImplItem::Fn(method) => method,
+	    _ => continue,
+	};
+	let export = match take_export(method)? {
+	    Some(export) => export,
+	    None => continue,
+	};
+	let function = &method.sig.ident;
+	let span = method.sig.span();
+
+	let is_static = match method.sig.receiver() {
+	    Some(receiver) if receiver.reference.is_none() => {
+		let message = "an exported method takes &self or &mut self";
+		return Err(Error::new(receiver.span(), message));
+	    }
+	    Some(_) => false,
+	    None => true,
+	};
+	let mut params = Vec::new();
+	let mut types = Vec::new();
+	for input in &method.sig.inputs {
+	    if let FnArg::Typed(arg) = input {
+		match &*arg.pat {
+		    Pat::Ident(pat) => params.push(pat.ident.to_string()),
+		    _ => params.push(format!("arg{}", params.len())),
+		}
+		types.push(&*arg.ty);
+	    }
+	}
+	let values: Vec<_> = (0..types.len()).map(|index| format_ident!("arg{}", index)).collect();
+	let slots = (1..=types.len()).map(Literal::usize_unsuffixed);
+
+	let is_renamed = export.name.is_some();
+	let mut name = export.name.unwrap_or_else(|| function.to_string());
+	let (signature, declaration) = match export.kind {
+	    Kind::Construct => {
+		if !is_static {
+		    return Err(Error::new(span, "a constructor doesn't take self"));
+		}
+		if allocate.is_some() {
+		    return Err(Error::new(span, "a foreign class has one constructor"));
+		}
+		declarations.push(format!("construct {}({}) {{}}", name, params.join(", ")));
+		allocate = Some(quote! {
+		    |vm: &mut ::wren_rs::WrenVM| {
+			let result = (|| -> ::std::result::Result<(), ::wren_rs::WrenError> {
+			    use ::wren_rs::{bind::IntoForeign, FromSlot};
+			    #(let #values = <#types as FromSlot>::from_slot(vm, #slots)?;)*
+			    let object = Self::#function(#(#values),*);
+			    let object = IntoForeign::<Self>::into_foreign(object)?;
+			    vm.set_slot_new_foreign(0, 0, object)
+			})();
+			if let Err(error) = result {
+			    vm.set_slot_string(0, error.to_string());
+			    vm.abort_fiber(0);
+			}
+		    }
+		});
+		continue;
+	    }
+	    Kind::Finalize => {
+		if is_static || !types.is_empty() {
+		    return Err(Error::new(span, "a finalizer takes only self"));
+		}
+		if finalize.is_some() {
+		    return Err(Error::new(span, "a foreign class has one finalizer"));
+		}
+		finalize = Some(quote! {
+		    |data: &mut dyn ::std::any::Any| {
+			if let Some(object) = data.downcast_mut::<Self>() {
+			    Self::#function(object);
+			}
+		    }
+		});
+		continue;
+	    }
+	    Kind::Getter => {
+		if !types.is_empty() {
+		    return Err(Error::new(span, "a getter takes no arguments"));
+		}
+		(name.clone(), name)
+	    }
+	    Kind::Setter => {
+		if types.len() != 1 {
+		    return Err(Error::new(span, "a setter takes one argument"));
+		}
+		if !is_renamed && name.starts_with("set_") {
+		    name = name["set_".len()..].to_string();
+		}
+		(format!("{}=(_)", name), format!("{}=({})", name, params[0]))
+	    }
+	    Kind::Method => {
+		let holes = vec!["_"; types.len()].join(",");
+		(format!("{}({})", name, holes), format!("{}({})", name, params.join(", ")))
+	    }
+	};
+	let prefix = if is_static { "static " } else { "" };
+	declarations.push(format!("foreign {}{}", prefix, declaration));
+	let signature = format!("{}{}", prefix, signature);
+	let closure = if is_static {
+	    quote! { |#(#values: #types),*| Self::#function(#(#values),*) }
+	} else {
+	    quote! { |this: &mut Self, #(#values: #types),*| Self::#function(this, #(#values),*) }
+	};
+	bindings.push(quote! { vm.bind(module, #class, #signature, #closure)?; });
+    }
+
+    // Without a constructor scripts can't create instances, so this only
+    // runs if one is declared by hand.
+    let allocate = allocate.unwrap_or_else(|| {
+	let message = format!("{} has no constructor.", class);
+	quote! {
+	    |vm: &mut ::wren_rs::WrenVM| {
+		vm.set_slot_string(0, #message);
+		vm.abort_fiber(0);
+	    }
+	}
+    });
+    let finalize = match finalize {
+	Some(finalize) => quote! { Some((#finalize) as ::wren_rs::FinalizerFn) },
+	None => quote! { None },
+    };
+    let body: String = declarations.iter().map(|line| format!("  {}\n", line)).collect();
+    let source = format!("foreign class {} {{\n{}}}\n", class, body);
+    let ty = &item.self_ty;
+    Ok(quote! {
+	#item
+
+	impl ::wren_rs::ForeignClass for #ty {
+	    const NAME: &'static str = #class;
+	    const SOURCE: &'static str = #source;
+
+	    fn bind(
+		vm: &mut ::wren_rs::WrenVM,
+		module: &str,
+	    ) -> ::core::result::Result<(), ::wren_rs::WrenError> {
+		let methods = ::wren_rs::ForeignClassMethods {
+		    allocate: #allocate,
+		    finalize: #finalize,
+		};
+		vm.bind_foreign_class(module, #class, methods);
+		#(#bindings)*
+		Ok(())
+	    }
+	}
+    })
+}
+
+// Removes the `#[wren_method]` mark from `method`, returning what it says.
+fn take_export(method: &mut ImplItemFn) -> syn::Result<Option<Export>> {
+    let index = match method.attrs.iter().position(|attr| attr.path().is_ident("wren_method")) {
+	Some(index) => index,
+	None => return Ok(None),
+    };
+    let attr = method.attrs.remove(index);
+    let mut export = Export {
+	kind: Kind::Method,
+	name: None,
+    };
+    if let syn::Meta::Path(_) = attr.meta {
+	return Ok(Some(export));
+    }
+    attr.parse_nested_meta(|meta| {
+	let kind = if meta.path.is_ident("name") {
+	    export.name = Some(meta.value()?.parse::<LitStr>()?.value());
+	    return Ok(());
+	} else if meta.path.is_ident("construct") {
+	    Kind::Construct
+	} else if meta.path.is_ident("finalize") {
+	    Kind::Finalize
+	} else if meta.path.is_ident("getter") {
+	    Kind::Getter
+	} else if meta.path.is_ident("setter") {
+	    Kind::Setter
+	} else {
+	    let message = "expected construct, finalize, getter, setter or name = \"...\"";
+	    return Err(meta.error(message));
+	};
+	if export.kind != Kind::Method {
+	    return Err(meta.error("a method has one kind"));
+	}
+	export.kind = kind;
+	Ok(())
+    })?;
+    Ok(Some(export))
+}
@@ -0,0 +1,387 @@
+// The slot API, through which the host exchanges values with Wren.
+//
+// While a foreign method runs, slot 0 holds the receiver and, when the
+// method returns, its result. Slots 1 and up hold the arguments. Outside a
+// foreign method, `ensure_slots` provides slots of the host's own.
+//
+// Values in slots come from scripts, so reading one as the wrong type is an
+// `Err` the host can turn into a runtime error with `abort_fiber`. Slot
+// numbers are the host's own choice, and one that is out of bounds panics,
+// like indexing a slice.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use ::core::any::Any;
+
+use crate::core;
+use crate::error::WrenError;
+use crate::value::{Obj, ObjForeign, ObjMap, ObjRef, Value};
+use crate::vm::{MaybeSend, WrenVM};
+
+/// The kind of value in a slot, as reported by `get_slot_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrenType {
+    Bool,
+    Num,
+    Foreign,
+    List,
+    Map,
+    Null,
+    String,
+    /// Any other kind of object, such as a class or an instance.
+    Unknown,
+}
+
+impl WrenVM {
+    /// The number of slots available.
+    pub fn slot_count(&self) -> usize {
+	match self.api_stack {
+	    Some(base) => self.stack.len() - base,
+	    None => 0,
+	}
+    }
+
+    /// Makes sure there are at least `count` slots, adding null ones as
+    /// needed.
+    pub fn ensure_slots(&mut self, count: usize) {
+	let base = *self.api_stack.get_or_insert(self.stack.len());
+	if self.stack.len() < base + count {
+	    self.stack.resize(base + count, Value::Null);
+	}
+    }
+
+    pub fn get_slot_type(&self, slot: usize) -> WrenType {
+	match self.slot(slot) {
+	    Value::Null => WrenType::Null,
+	    Value::Bool(_) => WrenType::Bool,
+	    Value::Num(_) => WrenType::Num,
+	    Value::Obj(obj) => match self.heap.get(obj) {
+		Obj::Foreign(_) => WrenType::Foreign,
+		Obj::List(_) => WrenType::List,
+		Obj::Map(_) => WrenType::Map,
+		Obj::String(_) => WrenType::String,
+		_ => WrenType::Unknown,
+	    },
+	}
+    }
+
+    pub fn get_slot_bool(&self, slot: usize) -> Result<bool, WrenError> {
+	self.slot(slot).as_bool().ok_or_else(|| slot_error(slot, "a bool"))
+    }
+
+    pub fn get_slot_double(&self, slot: usize) -> Result<f64, WrenError> {
+	self.slot(slot).as_num().ok_or_else(|| slot_error(slot, "a number"))
+    }
+
+    pub fn get_slot_string(&self, slot: usize) -> Result<&str, WrenError> {
+	self.heap.as_str(self.slot(slot)).ok_or_else(|| slot_error(slot, "a string"))
+    }
+
+    /// The bytes of the string in `slot`.
+    pub fn get_slot_bytes(&self, slot: usize) -> Result<&[u8], WrenError> {
+	self.get_slot_string(slot).map(str::as_bytes)
+    }
+
+    /// The data of the foreign object in `slot`, if it is a `T`.
+    pub fn get_slot_foreign<T: Any>(&self, slot: usize) -> Option<&T> {
+	match self.heap.get(self.slot(slot).as_obj()?) {
+	    Obj::Foreign(foreign) => foreign.data.downcast_ref(),
+	    _ => None,
+	}
+    }
+
+    /// The data of the foreign object in `slot`, if it is a `T`.
+    pub fn get_slot_foreign_mut<T: Any>(&mut self, slot: usize) -> Option<&mut T> {
+	let obj = self.slot(slot).as_obj()?;
+	match self.heap.get_mut(obj) {
+	    Obj::Foreign(foreign) => foreign.data.downcast_mut(),
+	    _ => None,
+	}
+    }
+
+    pub fn set_slot_null(&mut self, slot: usize) {
+	self.set_slot(slot, Value::Null);
+    }
+
+    pub fn set_slot_bool(&mut self, slot: usize, value: bool) {
+	self.set_slot(slot, Value::Bool(value));
+    }
+
+    pub fn set_slot_double(&mut self, slot: usize, value: f64) {
+	self.set_slot(slot, Value::Num(value));
+    }
+
+    pub fn set_slot_string(&mut self, slot: usize, value: impl Into<String>) {
+	let value = self.new_string(value);
+	self.set_slot(slot, value);
+    }
+
+    /// Stores a string made from `bytes` in `slot`. Strings are UTF-8, so
+    /// invalid sequences are replaced with U+FFFD.
+    pub fn set_slot_bytes(&mut self, slot: usize, bytes: &[u8]) {
+	self.set_slot_string(slot, String::from_utf8_lossy(bytes));
+    }
+
+    /// Creates an instance of the foreign class in `class_slot` holding
+    /// `data`, and stores it in `slot`. A foreign class's allocator calls
+    /// this with both slots 0.
+    pub fn set_slot_new_foreign<T: Any + MaybeSend>(
+	&mut self,
+	slot: usize,
+	class_slot: usize,
+	data: T,
+    ) -> Result<(), WrenError> {
+	let class = match self.slot(class_slot).as_obj() {
+	    Some(obj) if matches!(self.heap.get(obj), Obj::Class(_)) => obj,
+	    _ => return Err(slot_error(class_slot, "a class")),
+	};
+	let finalize = match self.heap.class(class).foreign {
+	    Some(methods) => methods.finalize,
+	    None => return Err(slot_error(class_slot, "a foreign class")),
+	};
+	let foreign = self.heap.alloc(Obj::Foreign(ObjForeign {
+	    class,
+	    data: Box::new(data),
+	    finalize,
+	}));
+	self.set_slot(slot, Value::Obj(foreign));
+	Ok(())
+    }
+
+    pub fn set_slot_new_list(&mut self, slot: usize) {
+	let list = self.new_list(Vec::new());
+	self.set_slot(slot, list);
+    }
+
+    pub fn set_slot_new_map(&mut self, slot: usize) {
+	let map = self.heap.alloc(Obj::Map(ObjMap::default()));
+	self.set_slot(slot, Value::Obj(map));
+    }
+
+    pub fn get_list_count(&self, slot: usize) -> Result<usize, WrenError> {
+	Ok(self.heap.list(self.list_in(slot)?).elements.len())
+    }
+
+    /// Copies element `index` of the list in `list_slot` into
+    /// `element_slot`. Negative indices count from the end.
+    pub fn get_list_element(
+	&mut self,
+	list_slot: usize,
+	index: isize,
+	element_slot: usize,
+    ) -> Result<(), WrenError> {
+	let list = self.list_in(list_slot)?;
+	let index = self.list_index(list, index)?;
+	let element = self.heap.list(list).elements[index];
+	self.set_slot(element_slot, element);
+	Ok(())
+    }
+
+    /// Replaces element `index` of the list in `list_slot` with the value
+    /// in `element_slot`. Negative indices count from the end.
+    pub fn set_list_element(
+	&mut self,
+	list_slot: usize,
+	index: isize,
+	element_slot: usize,
+    ) -> Result<(), WrenError> {
+	let list = self.list_in(list_slot)?;
+	let index = self.list_index(list, index)?;
+	let element = self.slot(element_slot);
+	self.heap.list_mut(list).elements[index] = element;
+	Ok(())
+    }
+
+    /// Inserts the value in `element_slot` into the list in `list_slot`
+    /// before `index`. As with `List.insert`, -1 appends.
+    pub fn insert_in_list(
+	&mut self,
+	list_slot: usize,
+	index: isize,
+	element_slot: usize,
+    ) -> Result<(), WrenError> {
+	let list = self.list_in(list_slot)?;
+	let count = self.heap.list(list).elements.len() as isize;
+	let position = if index < 0 { index + count + 1 } else { index };
+	if position < 0 || position > count {
+	    return Err(index_error(index));
+	}
+	let element = self.slot(element_slot);
+	core::list_insert_at(self, list, position as usize, element);
+	Ok(())
+    }
+
+    pub fn get_map_count(&self, slot: usize) -> Result<usize, WrenError> {
+	Ok(self.heap.map(self.map_in(slot)?).count)
+    }
+
+    pub fn get_map_contains_key(&self, map_slot: usize, key_slot: usize) -> Result<bool, WrenError> {
+	let map = self.map_in(map_slot)?;
+	let key = self.key_in(key_slot)?;
+	Ok(core::map_find(self, map, key).is_some())
+    }
+
+    /// Copies the value for the key in `key_slot` of the map in `map_slot`
+    /// into `value_slot`, or null if the key is missing.
+    pub fn get_map_value(
+	&mut self,
+	map_slot: usize,
+	key_slot: usize,
+	value_slot: usize,
+    ) -> Result<(), WrenError> {
+	let map = self.map_in(map_slot)?;
+	let key = self.key_in(key_slot)?;
+	let value = core::map_get(self, map, key).unwrap_or(Value::Null);
+	self.set_slot(value_slot, value);
+	Ok(())
+    }
+
+    pub fn set_map_value(
+	&mut self,
+	map_slot: usize,
+	key_slot: usize,
+	value_slot: usize,
+    ) -> Result<(), WrenError> {
+	let map = self.map_in(map_slot)?;
+	let key = self.key_in(key_slot)?;
+	let value = self.slot(value_slot);
+	core::map_set(self, map, key, value);
+	Ok(())
+    }
+
+    /// Removes the key in `key_slot` from the map in `map_slot`, storing
+    /// the value it had, or null, in `removed_value_slot`.
+    pub fn remove_map_value(
+	&mut self,
+	map_slot: usize,
+	key_slot: usize,
+	removed_value_slot: usize,
+    ) -> Result<(), WrenError> {
+	let map = self.map_in(map_slot)?;
+	let key = self.key_in(key_slot)?;
+	let removed = core::map_remove(self, map, key).unwrap_or(Value::Null);
+	self.set_slot(removed_value_slot, removed);
+	Ok(())
+    }
+
+    /// Whether a module named `module` has been loaded.
+    pub fn has_module(&self, module: &str) -> bool {
+	self.modules.contains_key(module)
+    }
+
+    /// Whether `module` has a top-level variable called `name`.
+    pub fn has_variable(&self, module: &str, name: &str) -> bool {
+	self.find_variable(module, name).is_some()
+    }
+
+    /// Copies the top-level variable `name` of `module` into `slot`.
+    pub fn get_variable(&mut self, module: &str, name: &str, slot: usize) -> Result<(), WrenError> {
+	let value = match self.find_variable(module, name) {
+	    Some(value) => value,
+	    None => {
+		return Err(api_error(format!(
+		    "Module '{}' has no variable '{}'.",
+		    module, name
+		)))
+	    }
+	};
+	self.set_slot(slot, value);
+	Ok(())
+    }
+
+    /// Keeps `data` on the VM, replacing what was there, so foreign methods
+    /// can reach the host's state through the VM they are given, as with
+    /// wren_c's `userData`.
+    pub fn set_user_data<T: Any + MaybeSend>(&mut self, data: T) {
+	self.user_data = Some(Box::new(data));
+    }
+
+    /// The data kept with `set_user_data`, if it is a `T`.
+    pub fn user_data<T: Any>(&self) -> Option<&T> {
+	self.user_data.as_ref()?.downcast_ref()
+    }
+
+    /// The data kept with `set_user_data`, if it is a `T`.
+    pub fn user_data_mut<T: Any>(&mut self) -> Option<&mut T> {
+	self.user_data.as_mut()?.downcast_mut()
+    }
+
+    /// Removes the data kept with `set_user_data` and returns it, if it is
+    /// a `T`. Otherwise it is left in place.
+    pub fn take_user_data<T: Any>(&mut self) -> Option<T> {
+	match self.user_data.take()?.downcast() {
+	    Ok(data) => Some(*data),
+	    Err(data) => {
+		self.user_data = Some(data);
+		None
+	    }
+	}
+    }
+
+    /// Aborts the current fiber with the value in `slot` as its error, once
+    /// the foreign method returns.
+    pub fn abort_fiber(&mut self, slot: usize) {
+	let error = self.slot(slot);
+	let fiber = self.fiber.expect("a running fiber");
+	self.heap.fiber_mut(fiber).error = error;
+    }
+
+    pub(crate) fn slot(&self, slot: usize) -> Value {
+	assert!(slot < self.slot_count(), "slot {} is out of bounds", slot);
+	self.stack[self.api_stack.expect("slots") + slot]
+    }
+
+    pub(crate) fn set_slot(&mut self, slot: usize, value: Value) {
+	assert!(slot < self.slot_count(), "slot {} is out of bounds", slot);
+	let base = self.api_stack.expect("slots");
+	self.stack[base + slot] = value;
+    }
+
+    pub(crate) fn list_in(&self, slot: usize) -> Result<ObjRef, WrenError> {
+	match self.slot(slot).as_obj() {
+	    Some(obj) if matches!(self.heap.get(obj), Obj::List(_)) => Ok(obj),
+	    _ => Err(slot_error(slot, "a list")),
+	}
+    }
+
+    pub(crate) fn map_in(&self, slot: usize) -> Result<ObjRef, WrenError> {
+	match self.slot(slot).as_obj() {
+	    Some(obj) if matches!(self.heap.get(obj), Obj::Map(_)) => Ok(obj),
+	    _ => Err(slot_error(slot, "a map")),
+	}
+    }
+
+    pub(crate) fn key_in(&self, slot: usize) -> Result<Value, WrenError> {
+	let key = self.slot(slot);
+	if core::is_valid_key(self, key) {
+	    Ok(key)
+	} else {
+	    Err(slot_error(slot, "a value type"))
+	}
+    }
+
+    // Turns a possibly negative index into `list` into a position in it.
+    fn list_index(&self, list: ObjRef, index: isize) -> Result<usize, WrenError> {
+	let count = self.heap.list(list).elements.len() as isize;
+	let position = if index < 0 { index + count } else { index };
+	if position < 0 || position >= count {
+	    return Err(index_error(index));
+	}
+	Ok(position as usize)
+    }
+}
+
+pub(crate) fn api_error(message: impl Into<String>) -> WrenError {
+    WrenError::Api {
+	message: message.into(),
+    }
+}
+
+pub(crate) fn slot_error(slot: usize, expected: &str) -> WrenError {
+    api_error(format!("Slot {} must hold {}.", slot, expected))
+}
+
+fn index_error(index: isize) -> WrenError {
+    api_error(format!("Index {} is out of bounds.", index))
+}
@@ -0,0 +1,277 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::lexer::Span;
+
+/// A parsed source file: a sequence of top-level statements.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expr(Expr),
+    Var {
+	name: Ident,
+	initializer: Option<Expr>,
+	/// The statement's last token, where wren_c reports the name being
+	/// declared twice, as it declares it after the initializer.
+	end: Ident,
+    },
+    Class(Box<ClassDecl>),
+    Import {
+	module: String,
+	variables: Vec<ImportVariable>,
+    },
+    Block(Vec<Stmt>),
+    If {
+	condition: Expr,
+	then_branch: Box<Stmt>,
+	else_branch: Option<Box<Stmt>>,
+    },
+    While {
+	condition: Expr,
+	body: Box<Stmt>,
+    },
+    For {
+	variable: Ident,
+	sequence: Expr,
+	body: Box<Stmt>,
+    },
+    Break,
+    Continue,
+    Return(Option<Expr>),
+}
+
+/// A name together with where it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// `Name` or `Name as Alias` in an import's `for` clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportVariable {
+    pub name: Ident,
+    pub alias: Option<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDecl {
+    pub name: Ident,
+    pub superclass: Option<Expr>,
+    pub is_foreign: bool,
+    pub methods: Vec<Method>,
+    pub attributes: Vec<Attribute>,
+    /// From `class` or `foreign` to the closing brace.
+    pub span: Span,
+}
+
+/// An attribute before a class or method: `#key`, `#key = value` or one
+/// entry of a group, `#group(key = value, ...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub group: Option<Ident>,
+    pub key: Ident,
+    /// A null, bool, number or string literal. A name is kept as a string,
+    /// and a key without a value has a null one.
+    pub value: Expr,
+    /// Whether it was written `#!`, making it visible at runtime through
+    /// the class's `attributes`.
+    pub is_runtime: bool,
+    /// The key and its value.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodKind {
+    /// `name { ... }`
+    Getter,
+    /// `name=(value) { ... }`
+    Setter,
+    /// `name(a, b) { ... }`
+    Method,
+    /// `[a, b] { ... }`
+    Subscript,
+    /// `[a, b]=(value) { ... }`
+    SubscriptSetter,
+    /// A prefix operator such as `-` or `!`.
+    Unary,
+    /// An infix operator such as `+(other)`.
+    Binary,
+    /// `construct name(a, b) { ... }`
+    Constructor,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub kind: MethodKind,
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    /// The last token of the signature: its name, or the ")" or "]" that
+    /// closes its parameters.
+    pub signature_end: Span,
+    pub is_static: bool,
+    pub is_foreign: bool,
+    /// `None` for foreign methods.
+    pub body: Option<Body>,
+    pub attributes: Vec<Attribute>,
+    pub span: Span,
+}
+
+/// The code of a method or block argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    /// `{ expr }` on a single line, whose value is returned.
+    Expr(Expr),
+    Block(Vec<Stmt>),
+}
+
+/// A block argument, `{ |a, b| ... }`, passed to a method call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockArg {
+    pub params: Vec<Ident>,
+    pub body: Body,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Null,
+    Bool(bool),
+    Num(f64),
+    String(String),
+    /// An interpolated string. String literal parts and expressions
+    /// alternate, starting and ending with a literal.
+    Interpolation(Vec<Expr>),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    /// A bare identifier: a variable, or a getter on `this` in a method.
+    Name(String),
+    Field(String),
+    StaticField(String),
+    This,
+    /// `receiver.name`, `receiver.name(args)` or a bare `name(args)` on
+    /// the implicit `this`.
+    Call {
+	receiver: Option<Box<Expr>>,
+	name: Ident,
+	args: Option<Vec<Expr>>,
+	block: Option<Box<BlockArg>>,
+    },
+    /// `super`, `super(args)`, `super.name` or `super.name(args)`. Without
+    /// a name it calls the enclosing method's superclass implementation.
+    Super {
+	name: Option<Ident>,
+	args: Option<Vec<Expr>>,
+	block: Option<Box<BlockArg>>,
+    },
+    Subscript {
+	receiver: Box<Expr>,
+	args: Vec<Expr>,
+    },
+    Unary {
+	op: UnaryOp,
+	operand: Box<Expr>,
+    },
+    Binary {
+	op: BinaryOp,
+	left: Box<Expr>,
+	right: Box<Expr>,
+    },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Conditional {
+	condition: Box<Expr>,
+	then_branch: Box<Expr>,
+	else_branch: Box<Expr>,
+    },
+    /// `target = value`. The target is a name, field, getter call,
+    /// subscript or `super` getter.
+    Assign {
+	target: Box<Expr>,
+	value: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+impl UnaryOp {
+    pub fn method_name(self) -> &'static str {
+	match self {
+	    UnaryOp::Neg => "-",
+	    UnaryOp::Not => "!",
+	    UnaryOp::BitNot => "~",
+	}
+    }
+}
+
+/// Infix operators, all of which are method calls on the left operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    Eq,
+    NotEq,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    RangeInclusive,
+    RangeExclusive,
+    Is,
+}
+
+impl BinaryOp {
+    pub fn method_name(self) -> &'static str {
+	match self {
+	    BinaryOp::Add => "+",
+	    BinaryOp::Sub => "-",
+	    BinaryOp::Mul => "*",
+	    BinaryOp::Div => "/",
+	    BinaryOp::Mod => "%",
+	    BinaryOp::Lt => "<",
+	    BinaryOp::Gt => ">",
+	    BinaryOp::LtEq => "<=",
+	    BinaryOp::GtEq => ">=",
+	    BinaryOp::Eq => "==",
+	    BinaryOp::NotEq => "!=",
+	    BinaryOp::BitAnd => "&",
+	    BinaryOp::BitOr => "|",
+	    BinaryOp::BitXor => "^",
+	    BinaryOp::Shl => "<<",
+	    BinaryOp::Shr => ">>",
+	    BinaryOp::RangeInclusive => "..",
+	    BinaryOp::RangeExclusive => "...",
+	    BinaryOp::Is => "is",
+	}
+    }
+}
@@ -0,0 +1,63 @@
+// The `http` module of the CLI, an HTTP client for scripts built with the
+// `http` feature.
+//
+// Each request is an operation of the `io` module, sent from another
+// thread while the fiber is suspended.
+
+use std::io;
+
+use ureq::http::Request;
+use ureq::Agent;
+use wren_rs::{FromSlot, WrenVM};
+
+use crate::io::{perform, string_arg, Reply};
+
+pub const SOURCE: &str = include_str!("http.wren");
+
+/// Registers the foreign methods of the `http` module.
+pub fn bind(vm: &mut WrenVM) {
+    vm.bind_foreign_method("http", "Http", true, "request_(_,_,_,_)", http_request);
+}
+
+// Sends the request and replies with a list of the status, the headers as
+// names followed by values, and the body.
+fn http_request(vm: &mut WrenVM) {
+    let method = string_arg(vm, 1);
+    let url = string_arg(vm, 2);
+    // `Http.request` has checked the headers are strings.
+    let fields = Vec::<String>::from_slot(vm, 3).unwrap_or_default();
+    let body = Option::<String>::from_slot(vm, 4).ok().flatten();
+    perform::<true>(vm, move || {
+	let mut request = Request::builder().method(method.as_str()).uri(&url);
+	for field in fields.chunks(2) {
+	    request = request.header(&field[0], &field[1]);
+	}
+	let request = request
+	    .body(body.unwrap_or_default().into_bytes())
+	    .map_err(io::Error::other)?;
+	// Error statuses are for the script to handle, like any other.
+	let config = Agent::config_builder().http_status_as_error(false).build();
+	let mut response = Agent::new_with_config(config)
+	    .run(request)
+	    .map_err(|error| io::Error::other(format!("Request to '{}' failed: {}", url, error)))?;
+
+	let mut headers: Vec<(String, String)> = Vec::new();
+	for (name, value) in response.headers() {
+	    let value = String::from_utf8_lossy(value.as_bytes());
+	    match headers.iter_mut().find(|(known, _)| known == name.as_str()) {
+		Some((_, values)) => {
+		    values.push_str(", ");
+		    values.push_str(&value);
+		}
+		None => headers.push((name.to_string(), value.into_owned())),
+	    }
+	}
+	let fields = headers.into_iter().flat_map(|(name, value)| [name, value]).collect();
+	let body = response.body_mut().read_to_vec().map_err(io::Error::other)?;
+	Ok(Reply::List(vec![
+	    Reply::Num(response.status().as_u16().into()),
+	    Reply::Names(fields),
+	    Reply::Bytes(body),
+	]))
+    });
+}
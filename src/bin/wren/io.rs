@@ -0,0 +1,358 @@
+// The `io` module of the CLI: files, directories and their metadata.
+//
+// An operation's `Async` form suspends its fiber and does the work on
+// another thread, and `run_event_loop` resumes the fiber with the result
+// once it arrives, along with those sleeping in `Timer.sleep`. The blocking
+// form does the same work before returning. The module is only in the CLI,
+// as an embedder decides for itself what scripts may touch.
+
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Instant, UNIX_EPOCH};
+
+use wren_rs::{ForeignClassMethods, ForeignMethodFn, IntoSlot, WrenError, WrenHandle, WrenVM};
+
+use crate::net::Stream;
+
+pub const SOURCE: &str = include_str!("io.wren");
+
+// The foreign methods of each class, by whether they are static and their
+// signature.
+const DIRECTORY_METHODS: &[(bool, &str, ForeignMethodFn)] = &[
+    (true, "create_(_)", directory_create::<false>),
+    (true, "delete_(_)", directory_delete::<false>),
+    (true, "exists_(_)", directory_exists::<false>),
+    (true, "list_(_)", directory_list::<false>),
+    (true, "listAsync_(_)", directory_list::<true>),
+];
+
+const FILE_METHODS: &[(bool, &str, ForeignMethodFn)] = &[
+    (true, "delete_(_)", file_delete::<false>),
+    (true, "exists_(_)", file_exists::<false>),
+    (true, "read_(_)", file_read::<false>),
+    (true, "readAsync_(_)", file_read::<true>),
+    (true, "write_(_,_)", file_write::<false>),
+    (true, "writeAsync_(_,_)", file_write::<true>),
+    (false, "path", file_path),
+    (false, "isOpen", file_is_open),
+    (false, "close_()", file_close),
+    (false, "readBytes_(_,_)", file_read_bytes::<false>),
+    (false, "readBytesAsync_(_,_)", file_read_bytes::<true>),
+    (false, "writeBytes_(_,_)", file_write_bytes::<false>),
+    (false, "writeBytesAsync_(_,_)", file_write_bytes::<true>),
+];
+
+const STAT_METHODS: &[(bool, &str, ForeignMethodFn)] = &[
+    (true, "path_(_)", stat_path::<false>),
+    (true, "pathAsync_(_)", stat_path::<true>),
+];
+
+const FILE_CLASS: ForeignClassMethods = ForeignClassMethods {
+    allocate: file_allocate,
+    finalize: None,
+};
+
+/// Registers the foreign classes and methods of the `io` module.
+pub fn bind(vm: &mut WrenVM) {
+    vm.bind_foreign_class("io", "File", FILE_CLASS);
+    let classes = [
+	("Directory", DIRECTORY_METHODS),
+	("File", FILE_METHODS),
+	("Stat", STAT_METHODS),
+    ];
+    for (class, methods) in classes {
+	for &(is_static, signature, method) in methods {
+	    vm.bind_foreign_method("io", class, is_static, signature, method);
+	}
+    }
+}
+
+/// Runs the fibers the script left scheduled, sleeping or waiting on an
+/// operation, until every fiber has finished or one fails.
+pub fn run_event_loop(vm: &mut WrenVM) -> Result<(), WrenError> {
+    vm.run_scheduled()?;
+    loop {
+	let deadline = vm.wake_timers()?;
+	let done = OPERATIONS.with(|operations| {
+	    let operations = operations.borrow();
+	    if operations.pending == 0 {
+		return None;
+	    }
+	    // Waking up for the next timer is the same as an operation
+	    // that isn't done yet.
+	    Some(match deadline {
+		Some(deadline) => {
+		    let timeout = deadline.saturating_duration_since(Instant::now());
+		    operations.receiver.recv_timeout(timeout).ok()
+		}
+		None => operations.receiver.recv().ok(),
+	    })
+	});
+	match done {
+	    Some(Some((fiber, result))) => {
+		OPERATIONS.with(|operations| operations.borrow_mut().pending -= 1);
+		let result = vm.resume_fiber(&fiber, result.map_err(|error| error.to_string()));
+		vm.release_handle(fiber);
+		result?;
+		vm.run_scheduled()?;
+	    }
+	    Some(None) => {}
+	    None => match deadline {
+		Some(deadline) => thread::sleep(deadline.saturating_duration_since(Instant::now())),
+		None => return Ok(()),
+	    },
+	}
+    }
+}
+
+/// What an operation gives the script.
+pub enum Reply {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Bytes(Vec<u8>),
+    Names(Vec<String>),
+    List(Vec<Reply>),
+    /// A new `TcpStream` from the `net` module.
+    Stream(TcpStream),
+}
+
+impl IntoSlot for Reply {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	match self {
+	    Reply::Null => vm.set_slot_null(slot),
+	    Reply::Bool(value) => vm.set_slot_bool(slot, value),
+	    Reply::Num(value) => vm.set_slot_double(slot, value),
+	    Reply::Bytes(bytes) => vm.set_slot_bytes(slot, &bytes),
+	    Reply::Names(names) => return names.into_slot(vm, slot),
+	    Reply::List(replies) => return replies.into_slot(vm, slot),
+	    Reply::Stream(stream) => {
+		let class = vm.slot_count();
+		vm.ensure_slots(class + 1);
+		vm.get_variable("net", "TcpStream", class)?;
+		vm.set_slot_new_foreign(slot, class, Stream(Some(stream)))?;
+	    }
+	}
+	Ok(())
+    }
+}
+
+type Done = (WrenHandle, io::Result<Reply>);
+
+// The operations running on other threads, which send their fiber and
+// result back when they are done.
+struct Operations {
+    sender: Sender<Done>,
+    receiver: Receiver<Done>,
+    pending: usize,
+}
+
+thread_local! {
+    // Kept here as foreign methods are plain functions.
+    static OPERATIONS: RefCell<Operations> = {
+	let (sender, receiver) = mpsc::channel();
+	RefCell::new(Operations {
+	    sender,
+	    receiver,
+	    pending: 0,
+	})
+    };
+}
+
+/// Does `operation` now and stores its result in slot 0, or if `ASYNC` is
+/// set, suspends the fiber and does it on another thread.
+pub fn perform<const ASYNC: bool>(
+    vm: &mut WrenVM,
+    operation: impl FnOnce() -> io::Result<Reply> + Send + 'static,
+) {
+    if !ASYNC {
+	if let Err(error) = operation().into_slot(vm, 0) {
+	    abort(vm, error);
+	}
+	return;
+    }
+    let fiber = match vm.suspend_fiber() {
+	Ok(fiber) => fiber,
+	Err(error) => return abort(vm, error),
+    };
+    let sender = OPERATIONS.with(|operations| {
+	let mut operations = operations.borrow_mut();
+	operations.pending += 1;
+	operations.sender.clone()
+    });
+    thread::spawn(move || {
+	let _ = sender.send((fiber, operation()));
+    });
+}
+
+/// Aborts the fiber with the message of `error`.
+pub fn abort(vm: &mut WrenVM, error: impl ToString) {
+    vm.set_slot_string(0, error.to_string());
+    vm.abort_fiber(0);
+}
+
+/// The string in `slot`, which the script has checked the type of.
+pub fn string_arg(vm: &WrenVM, slot: usize) -> String {
+    vm.get_slot_string(slot).unwrap_or("").to_string()
+}
+
+// The number in `slot`, which the script has checked the type of.
+fn number_arg(vm: &WrenVM, slot: usize) -> u64 {
+    vm.get_slot_double(slot).unwrap_or(0.0) as u64
+}
+
+fn directory_create<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || fs::create_dir(path).map(|()| Reply::Null));
+}
+
+fn directory_delete<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || fs::remove_dir(path).map(|()| Reply::Null));
+}
+
+fn directory_exists<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || {
+	Ok(Reply::Bool(fs::metadata(path).is_ok_and(|metadata| metadata.is_dir())))
+    });
+}
+
+// The names of the entries of a directory, sorted.
+fn directory_list<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || {
+	let mut names = fs::read_dir(path)?
+	    .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+	    .collect::<io::Result<Vec<_>>>()?;
+	names.sort();
+	Ok(Reply::Names(names))
+    });
+}
+
+fn file_delete<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || fs::remove_file(path).map(|()| Reply::Null));
+}
+
+fn file_exists<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || {
+	Ok(Reply::Bool(fs::metadata(path).is_ok_and(|metadata| metadata.is_file())))
+    });
+}
+
+fn file_read<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || fs::read(path).map(Reply::Bytes));
+}
+
+fn file_write<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    let bytes = string_arg(vm, 2);
+    perform::<ASYNC>(vm, move || fs::write(path, bytes).map(|()| Reply::Null));
+}
+
+// An open file, or one that has been closed.
+struct File {
+    path: String,
+    file: Option<fs::File>,
+}
+
+fn file_allocate(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    let create = vm.get_slot_bool(2).unwrap_or(false);
+    let file = OpenOptions::new()
+	.read(true)
+	.write(true)
+	.create(create)
+	.truncate(create)
+	.open(&path);
+    match file {
+	Ok(file) => {
+	    let file = File {
+		path,
+		file: Some(file),
+	    };
+	    if let Err(error) = vm.set_slot_new_foreign(0, 0, file) {
+		abort(vm, error);
+	    }
+	}
+	Err(error) => abort(vm, error),
+    }
+}
+
+fn file_path(vm: &mut WrenVM) {
+    let path = vm.get_slot_foreign::<File>(0).map(|file| file.path.clone());
+    vm.set_slot_string(0, path.unwrap_or_default());
+}
+
+fn file_is_open(vm: &mut WrenVM) {
+    let is_open = vm.get_slot_foreign::<File>(0).is_some_and(|file| file.file.is_some());
+    vm.set_slot_bool(0, is_open);
+}
+
+fn file_close(vm: &mut WrenVM) {
+    if let Some(file) = vm.get_slot_foreign_mut::<File>(0) {
+	file.file = None;
+    }
+    vm.set_slot_null(0);
+}
+
+// Another handle to the open file in slot 0, for an operation to take to
+// its thread.
+fn open_file(vm: &WrenVM) -> io::Result<fs::File> {
+    match vm.get_slot_foreign::<File>(0).and_then(|file| file.file.as_ref()) {
+	Some(file) => file.try_clone(),
+	None => Err(io::Error::other("File is not open.")),
+    }
+}
+
+fn file_read_bytes<const ASYNC: bool>(vm: &mut WrenVM) {
+    let file = open_file(vm);
+    let count = number_arg(vm, 1);
+    let offset = number_arg(vm, 2);
+    perform::<ASYNC>(vm, move || {
+	let mut file = file?;
+	file.seek(SeekFrom::Start(offset))?;
+	let mut bytes = Vec::new();
+	file.take(count).read_to_end(&mut bytes)?;
+	Ok(Reply::Bytes(bytes))
+    });
+}
+
+fn file_write_bytes<const ASYNC: bool>(vm: &mut WrenVM) {
+    let file = open_file(vm);
+    let bytes = string_arg(vm, 1);
+    let offset = number_arg(vm, 2);
+    perform::<ASYNC>(vm, move || {
+	let mut file = file?;
+	file.seek(SeekFrom::Start(offset))?;
+	file.write_all(bytes.as_bytes())?;
+	Ok(Reply::Null)
+    });
+}
+
+// Replies with a list of the fields of a `Stat`.
+fn stat_path<const ASYNC: bool>(vm: &mut WrenVM) {
+    let path = string_arg(vm, 1);
+    perform::<ASYNC>(vm, move || {
+	let metadata = fs::metadata(path)?;
+	let modified = metadata
+	    .modified()
+	    .ok()
+	    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+	    .map_or(Reply::Null, |duration| Reply::Num(duration.as_secs_f64()));
+	Ok(Reply::List(vec![
+	    Reply::Num(metadata.len() as f64),
+	    Reply::Bool(metadata.is_file()),
+	    Reply::Bool(metadata.is_dir()),
+	    modified,
+	    Reply::Bool(metadata.permissions().readonly()),
+	]))
+    });
+}
@@ -0,0 +1,512 @@
+#[cfg(feature = "http")]
+mod http;
+mod io;
+mod net;
+mod os;
+mod repl;
+
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use wren_rs::bytecode;
+use wren_rs::formatter::{self, FormatOptions};
+use wren_rs::{
+    DapServer, FileModuleLoader, LspServer, ModuleLoader, Profiler, WrenConfiguration, WrenError,
+    WrenVM,
+};
+
+const USAGE: &str = "Usage: wren [run [--module-path <dir>]... <script> [<argument>...]]
+       wren debug [--port <port>] [--module-path <dir>]... <script> [<argument>...]
+       wren profile [--collapsed <output>] [--module-path <dir>]... <script> [<argument>...]
+       wren check <script>...
+       wren compile <script> [-o <output>]
+       wren dump <script>
+       wren fmt [--indent <width>] [--write] <script>...
+       wren lint <script>...
+       wren lsp";
+
+const DEFAULT_DEBUG_PORT: u16 = 4711;
+
+// What to do, from the command line.
+enum Command {
+    Repl,
+    /// Run the script, passing it the arguments after it.
+    Run {
+	script: String,
+	module_paths: Vec<String>,
+	arguments: Vec<String>,
+    },
+    /// Run the script once an editor has connected on `port` to debug it
+    /// with the Debug Adapter Protocol.
+    Debug {
+	script: String,
+	module_paths: Vec<String>,
+	arguments: Vec<String>,
+	port: u16,
+    },
+    /// Run the script and then print how long its functions took, or
+    /// write the stacks they ran in to `collapsed` for a flame graph.
+    Profile {
+	script: String,
+	module_paths: Vec<String>,
+	arguments: Vec<String>,
+	collapsed: Option<String>,
+    },
+    /// Report the errors in the scripts without running them.
+    Check { scripts: Vec<String> },
+    /// Compile the script to bytes `run` can load, by default in a file
+    /// next to it with the extension `.wrenb`.
+    Compile {
+	script: String,
+	output: Option<String>,
+    },
+    /// Print the script's bytecode instead of running it.
+    Dump { script: String },
+    /// Print the scripts formatted, or rewrite them in place if `write` is
+    /// set.
+    Format {
+	scripts: Vec<String>,
+	options: FormatOptions,
+	write: bool,
+    },
+    /// Print the likely mistakes in the scripts.
+    Lint { scripts: Vec<String> },
+    /// Serve an editor with the Language Server Protocol over stdin and
+    /// stdout.
+    Lsp,
+}
+
+fn main() {
+    match parse_args(env::args().skip(1)) {
+	Ok(Command::Repl) => repl::run(),
+	Ok(Command::Run {
+	    script,
+	    module_paths,
+	    arguments,
+	}) => run_file(&script, &module_paths, arguments),
+	Ok(Command::Debug {
+	    script,
+	    module_paths,
+	    arguments,
+	    port,
+	}) => debug_file(&script, &module_paths, arguments, port),
+	Ok(Command::Profile {
+	    script,
+	    module_paths,
+	    arguments,
+	    collapsed,
+	}) => profile_file(&script, &module_paths, arguments, collapsed),
+	Ok(Command::Check { scripts }) => check_files(&scripts),
+	Ok(Command::Compile { script, output }) => compile_file(&script, output),
+	Ok(Command::Dump { script }) => dump_file(&script),
+	Ok(Command::Format {
+	    scripts,
+	    options,
+	    write,
+	}) => format_files(&scripts, &options, write),
+	Ok(Command::Lint { scripts }) => lint_files(&scripts),
+	Ok(Command::Lsp) => serve_lsp(),
+	Err(message) => {
+	    eprintln!("{}\n{}", message, USAGE);
+	    process::exit(64);
+	}
+    }
+}
+
+// `wren` alone starts the REPL, and `wren <script>` is short for
+// `wren run <script>`. The arguments after a script to run are the
+// script's, even those that look like options.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let first = match args.next() {
+	Some(first) => first,
+	None => return Ok(Command::Repl),
+    };
+    if first == "dump" {
+	return match (args.next(), args.next()) {
+	    (Some(script), None) => Ok(Command::Dump { script }),
+	    (None, _) => Err("Expected a script to dump.".to_string()),
+	    (Some(_), Some(extra)) => Err(format!("Unexpected argument '{}'.", extra)),
+	};
+    }
+    if first == "compile" {
+	let mut script = None;
+	let mut output = None;
+	while let Some(arg) = args.next() {
+	    if arg == "-o" {
+		match args.next() {
+		    Some(path) => output = Some(path),
+		    None => return Err("Expected a file after '-o'.".to_string()),
+		}
+	    } else if arg.starts_with('-') {
+		return Err(format!("Unknown option '{}'.", arg));
+	    } else if script.is_none() {
+		script = Some(arg);
+	    } else {
+		return Err(format!("Unexpected argument '{}'.", arg));
+	    }
+	}
+	return match script {
+	    Some(script) => Ok(Command::Compile { script, output }),
+	    None => Err("Expected a script to compile.".to_string()),
+	};
+    }
+    if first == "lsp" {
+	return match args.next() {
+	    Some(arg) => Err(format!("Unexpected argument '{}'.", arg)),
+	    None => Ok(Command::Lsp),
+	};
+    }
+    if first == "check" || first == "lint" {
+	let scripts: Vec<String> = args.collect();
+	if let Some(option) = scripts.iter().find(|arg| arg.starts_with('-')) {
+	    return Err(format!("Unknown option '{}'.", option));
+	}
+	if scripts.is_empty() {
+	    return Err(format!("Expected a script to {}.", first));
+	}
+	return Ok(match first.as_str() {
+	    "check" => Command::Check { scripts },
+	    _ => Command::Lint { scripts },
+	});
+    }
+    if first == "fmt" {
+	let mut scripts = Vec::new();
+	let mut options = FormatOptions::default();
+	let mut write = false;
+	while let Some(arg) = args.next() {
+	    if arg == "--indent" {
+		match args.next().map(|width| width.parse()) {
+		    Some(Ok(width)) => options.indent_width = width,
+		    _ => return Err("Expected a width after '--indent'.".to_string()),
+		}
+	    } else if arg == "--write" {
+		write = true;
+	    } else if arg.starts_with('-') {
+		return Err(format!("Unknown option '{}'.", arg));
+	    } else {
+		scripts.push(arg);
+	    }
+	}
+	if scripts.is_empty() {
+	    return Err("Expected a script to format.".to_string());
+	}
+	return Ok(Command::Format {
+	    scripts,
+	    options,
+	    write,
+	});
+    }
+    if first != "run" && first != "debug" && first != "profile" {
+	return Ok(Command::Run {
+	    script: first,
+	    module_paths: Vec::new(),
+	    arguments: args.collect(),
+	});
+    }
+
+    let mut script = None;
+    let mut module_paths = Vec::new();
+    let mut port = DEFAULT_DEBUG_PORT;
+    let mut collapsed = None;
+    while let Some(arg) = args.next() {
+	if arg == "--module-path" {
+	    match args.next() {
+		Some(path) => module_paths.push(path),
+		None => return Err("Expected a directory after '--module-path'.".to_string()),
+	    }
+	} else if let Some(path) = arg.strip_prefix("--module-path=") {
+	    module_paths.push(path.to_string());
+	} else if arg == "--port" && first == "debug" {
+	    match args.next().map(|port| port.parse()) {
+		Some(Ok(number)) => port = number,
+		_ => return Err("Expected a port number after '--port'.".to_string()),
+	    }
+	} else if arg == "--collapsed" && first == "profile" {
+	    match args.next() {
+		Some(path) => collapsed = Some(path),
+		None => return Err("Expected a file after '--collapsed'.".to_string()),
+	    }
+	} else if arg.starts_with("--") {
+	    return Err(format!("Unknown option '{}'.", arg));
+	} else {
+	    script = Some(arg);
+	    break;
+	}
+    }
+    let arguments = args.collect();
+    match script {
+	Some(script) if first == "debug" => Ok(Command::Debug {
+	    script,
+	    module_paths,
+	    arguments,
+	    port,
+	}),
+	Some(script) if first == "profile" => Ok(Command::Profile {
+	    script,
+	    module_paths,
+	    arguments,
+	    collapsed,
+	}),
+	Some(script) => Ok(Command::Run {
+	    script,
+	    module_paths,
+	    arguments,
+	}),
+	None => Err(format!("Expected a script to {}.", first)),
+    }
+}
+
+fn read_script(path: &str) -> String {
+    match fs::read_to_string(path) {
+	Ok(source) => source,
+	Err(error) => {
+	    eprintln!("Could not read file \"{}\": {}", path, error);
+	    process::exit(66);
+	}
+    }
+}
+
+fn run_file(path: &str, module_paths: &[String], arguments: Vec<String>) {
+    let bytes = read_program(path);
+    let mut vm = program_vm(path, module_paths, arguments);
+    let result = run_program(&mut vm, path, bytes);
+    exit_on_error(path, result);
+}
+
+fn debug_file(path: &str, module_paths: &[String], arguments: Vec<String>, port: u16) {
+    let bytes = read_program(path);
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+	Ok(listener) => listener,
+	Err(error) => {
+	    eprintln!("Could not listen on port {}: {}", port, error);
+	    process::exit(69);
+	}
+    };
+    eprintln!("Waiting for a debugger to connect on port {}.", port);
+    let connection = listener
+	.accept()
+	.and_then(|(stream, _)| Ok((stream.try_clone()?, stream)));
+    let (input, output) = match connection {
+	Ok(connection) => connection,
+	Err(error) => {
+	    eprintln!("Could not accept a debugger: {}", error);
+	    process::exit(69);
+	}
+    };
+
+    let mut server = DapServer::new(input, output, script_root(path));
+    server.add_source("main", path);
+    for module_path in module_paths {
+	server.add_search_path(module_path);
+    }
+    let mut vm = program_vm(path, module_paths, arguments);
+    let result = server.run(&mut vm, |vm| run_program(vm, path, bytes));
+    exit_on_error(path, result);
+}
+
+fn profile_file(
+    path: &str,
+    module_paths: &[String],
+    arguments: Vec<String>,
+    collapsed: Option<String>,
+) {
+    let bytes = read_program(path);
+    let mut vm = program_vm(path, module_paths, arguments);
+    let profiler = Profiler::new();
+    vm.set_debug_hook(Some(Box::new(profiler.clone())));
+    let result = run_program(&mut vm, path, bytes);
+    vm.set_debug_hook(None);
+
+    // The report goes to stderr, apart from the script's output.
+    let report = profiler.report();
+    match collapsed {
+	Some(output) => {
+	    if let Err(error) = fs::write(&output, report.collapsed_stacks()) {
+		eprintln!("Could not write file \"{}\": {}", output, error);
+		process::exit(73);
+	    }
+	}
+	None => eprint!("{}", report),
+    }
+    exit_on_error(path, result);
+}
+
+fn read_program(path: &str) -> Vec<u8> {
+    match fs::read(path) {
+	Ok(bytes) => bytes,
+	Err(error) => {
+	    eprintln!("Could not read file \"{}\": {}", path, error);
+	    process::exit(66);
+	}
+    }
+}
+
+fn script_root(path: &str) -> &Path {
+    Path::new(path).parent().unwrap_or_else(|| Path::new(""))
+}
+
+// A VM whose imports are found next to the script, then in the module
+// paths.
+fn program_vm(path: &str, module_paths: &[String], arguments: Vec<String>) -> WrenVM {
+    let mut loader = FileModuleLoader::new(script_root(path));
+    for module_path in module_paths {
+	loader.add_search_path(module_path);
+    }
+    let config = WrenConfiguration {
+	module_loader: Some(Box::new(CliModuleLoader(loader))),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    #[cfg(feature = "http")]
+    http::bind(&mut vm);
+    io::bind(&mut vm);
+    net::bind(&mut vm);
+    os::bind(&mut vm, arguments);
+    vm
+}
+
+// Loads the modules that come with the CLI, and others from files.
+struct CliModuleLoader(FileModuleLoader);
+
+impl ModuleLoader for CliModuleLoader {
+    fn resolve_module(&mut self, importer: &str, name: &str) -> Option<String> {
+	self.0.resolve_module(importer, name)
+    }
+
+    fn load_module(&mut self, name: &str) -> Option<String> {
+	match name {
+	    #[cfg(feature = "http")]
+	    "http" => Some(http::SOURCE.to_string()),
+	    "io" => Some(io::SOURCE.to_string()),
+	    "net" => Some(net::SOURCE.to_string()),
+	    "os" => Some(os::SOURCE.to_string()),
+	    _ => self.0.load_module(name),
+	}
+    }
+}
+
+// Runs a script, or one compiled by `wren compile`, which is recognized
+// by the bytes it starts with, and then the fibers it left scheduled or
+// sleeping. A compiled file is checked for corruption before any of it
+// runs, so a damaged one exits with 65 as a script that doesn't compile
+// would.
+fn run_program(vm: &mut WrenVM, path: &str, bytes: Vec<u8>) -> Result<(), WrenError> {
+    if bytecode::is_compiled(&bytes) {
+	vm.load_compiled(&bytes)?;
+    } else {
+	match String::from_utf8(bytes) {
+	    Ok(source) => vm.interpret("main", &source)?,
+	    Err(_) => {
+		eprintln!("File \"{}\" is not UTF-8 text.", path);
+		process::exit(65);
+	    }
+	}
+    }
+    io::run_event_loop(vm)
+}
+
+fn exit_on_error(path: &str, result: Result<(), WrenError>) {
+    // Other errors have already been reported on stderr.
+    match result {
+	Ok(()) => {}
+	Err(WrenError::Compile { .. }) => process::exit(65),
+	Err(error @ WrenError::Bytecode { .. }) => {
+	    eprintln!("Could not load \"{}\": {}", path, error);
+	    process::exit(65);
+	}
+	Err(_) => process::exit(70),
+    }
+}
+
+fn check_files(paths: &[String]) {
+    let mut failed = false;
+    for path in paths {
+	let source = read_script(path);
+	if let Err(diagnostics) = wren_rs::check(&source) {
+	    for diagnostic in diagnostics {
+		eprintln!("{}: {}", path, diagnostic);
+	    }
+	    failed = true;
+	}
+    }
+    if failed {
+	process::exit(65);
+    }
+}
+
+fn compile_file(path: &str, output: Option<String>) {
+    let source = read_script(path);
+    let output = output.map_or_else(|| Path::new(path).with_extension("wrenb"), PathBuf::from);
+    let mut vm = WrenVM::new();
+    let bytes = match vm.compile_to_bytes("main", &source) {
+	Ok(bytes) => bytes,
+	// The error has already been reported on stderr.
+	Err(_) => process::exit(65),
+    };
+    if let Err(error) = fs::write(&output, bytes) {
+	eprintln!("Could not write file \"{}\": {}", output.display(), error);
+	process::exit(73);
+    }
+}
+
+fn dump_file(path: &str) {
+    let source = read_script(path);
+    let mut vm = WrenVM::new();
+    match vm.disassemble("main", &source) {
+	Ok(disassembly) => print!("{}", disassembly),
+	// The error has already been reported on stderr.
+	Err(_) => process::exit(65),
+    }
+}
+
+fn format_files(paths: &[String], options: &FormatOptions, write: bool) {
+    let mut failed = false;
+    for path in paths {
+	let source = read_script(path);
+	let formatted = match formatter::format_with(&source, options) {
+	    Ok(formatted) => formatted,
+	    Err(error) => {
+		eprintln!("Could not format \"{}\": {}", path, error);
+		failed = true;
+		continue;
+	    }
+	};
+	if !write {
+	    print!("{}", formatted);
+	} else if formatted != source {
+	    if let Err(error) = fs::write(path, formatted) {
+		eprintln!("Could not write file \"{}\": {}", path, error);
+		process::exit(73);
+	    }
+	}
+    }
+    if failed {
+	process::exit(65);
+    }
+}
+
+fn lint_files(paths: &[String]) {
+    // The warnings and errors are reported on stderr as they're found.
+    let mut vm = WrenVM::new();
+    let mut failed = false;
+    for path in paths {
+	let source = read_script(path);
+	if vm.lint(path, &source).is_err() {
+	    failed = true;
+	}
+    }
+    if failed {
+	process::exit(65);
+    }
+}
+
+fn serve_lsp() {
+    let server = LspServer::new(stdin(), stdout());
+    if !server.run() {
+	process::exit(1);
+    }
+}
@@ -0,0 +1,192 @@
+// The `net` module of the CLI: TCP listeners and streams.
+//
+// Everything that waits on the network is an operation of the `io` module,
+// done on another thread while the fiber is suspended, so a server can
+// serve each connection in a fiber of its own.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+
+use wren_rs::{ForeignClassMethods, ForeignMethodFn, WrenVM};
+
+use crate::io::{abort, perform, string_arg, Reply};
+
+pub const SOURCE: &str = include_str!("net.wren");
+
+// How many bytes a read waits for at most.
+const READ_SIZE: usize = 4096;
+
+// The foreign methods of each class, by whether they are static and their
+// signature.
+const LISTENER_METHODS: &[(bool, &str, ForeignMethodFn)] = &[
+    (false, "localAddress", listener_local_address),
+    (false, "isOpen", listener_is_open),
+    (false, "accept_()", listener_accept),
+    (false, "close_()", listener_close),
+];
+
+const STREAM_METHODS: &[(bool, &str, ForeignMethodFn)] = &[
+    (true, "connect_(_)", stream_connect),
+    (false, "localAddress", stream_local_address),
+    (false, "peerAddress", stream_peer_address),
+    (false, "isOpen", stream_is_open),
+    (false, "read_()", stream_read),
+    (false, "write_(_)", stream_write),
+    (false, "close_()", stream_close),
+];
+
+const LISTENER_CLASS: ForeignClassMethods = ForeignClassMethods {
+    allocate: listener_allocate,
+    finalize: None,
+};
+
+const STREAM_CLASS: ForeignClassMethods = ForeignClassMethods {
+    allocate: stream_allocate,
+    finalize: None,
+};
+
+/// Registers the foreign classes and methods of the `net` module.
+pub fn bind(vm: &mut WrenVM) {
+    vm.bind_foreign_class("net", "TcpListener", LISTENER_CLASS);
+    vm.bind_foreign_class("net", "TcpStream", STREAM_CLASS);
+    for (class, methods) in [("TcpListener", LISTENER_METHODS), ("TcpStream", STREAM_METHODS)] {
+	for &(is_static, signature, method) in methods {
+	    vm.bind_foreign_method("net", class, is_static, signature, method);
+	}
+    }
+}
+
+/// A listening socket, or one that has been closed.
+struct Listener(Option<TcpListener>);
+
+/// A connection, or one that has been closed.
+pub struct Stream(pub Option<TcpStream>);
+
+fn set_address(vm: &mut WrenVM, address: Option<SocketAddr>) {
+    match address {
+	Some(address) => vm.set_slot_string(0, address.to_string()),
+	None => vm.set_slot_null(0),
+    }
+}
+
+fn listener_allocate(vm: &mut WrenVM) {
+    let address = string_arg(vm, 1);
+    match TcpListener::bind(&address) {
+	Ok(listener) => {
+	    if let Err(error) = vm.set_slot_new_foreign(0, 0, Listener(Some(listener))) {
+		abort(vm, error);
+	    }
+	}
+	Err(error) => abort(vm, format!("Could not listen on '{}': {}", address, error)),
+    }
+}
+
+fn listener(vm: &WrenVM) -> Option<&TcpListener> {
+    vm.get_slot_foreign::<Listener>(0).and_then(|listener| listener.0.as_ref())
+}
+
+fn listener_local_address(vm: &mut WrenVM) {
+    let address = listener(vm).and_then(|listener| listener.local_addr().ok());
+    set_address(vm, address);
+}
+
+fn listener_is_open(vm: &mut WrenVM) {
+    let is_open = listener(vm).is_some();
+    vm.set_slot_bool(0, is_open);
+}
+
+fn listener_accept(vm: &mut WrenVM) {
+    let listener = match listener(vm) {
+	Some(listener) => listener.try_clone(),
+	None => Err(io::Error::other("Listener is not open.")),
+    };
+    perform::<true>(vm, move || {
+	let (stream, _) = listener?.accept()?;
+	Ok(Reply::Stream(stream))
+    });
+}
+
+fn listener_close(vm: &mut WrenVM) {
+    if let Some(listener) = vm.get_slot_foreign_mut::<Listener>(0) {
+	listener.0 = None;
+    }
+    vm.set_slot_null(0);
+}
+
+// Streams come from `connect` and `accept`, as the class has no
+// constructor.
+fn stream_allocate(vm: &mut WrenVM) {
+    abort(vm, "TcpStream has no constructor.");
+}
+
+fn stream(vm: &WrenVM) -> Option<&TcpStream> {
+    vm.get_slot_foreign::<Stream>(0).and_then(|stream| stream.0.as_ref())
+}
+
+// Another handle to the open stream in slot 0, for an operation to take
+// to its thread.
+fn open_stream(vm: &WrenVM) -> io::Result<TcpStream> {
+    match stream(vm) {
+	Some(stream) => stream.try_clone(),
+	None => Err(io::Error::other("Stream is not open.")),
+    }
+}
+
+fn stream_connect(vm: &mut WrenVM) {
+    let address = string_arg(vm, 1);
+    perform::<true>(vm, move || match TcpStream::connect(&address) {
+	Ok(stream) => Ok(Reply::Stream(stream)),
+	Err(error) => {
+	    let message = format!("Could not connect to '{}': {}", address, error);
+	    Err(io::Error::new(error.kind(), message))
+	}
+    });
+}
+
+fn stream_local_address(vm: &mut WrenVM) {
+    let address = stream(vm).and_then(|stream| stream.local_addr().ok());
+    set_address(vm, address);
+}
+
+fn stream_peer_address(vm: &mut WrenVM) {
+    let address = stream(vm).and_then(|stream| stream.peer_addr().ok());
+    set_address(vm, address);
+}
+
+fn stream_is_open(vm: &mut WrenVM) {
+    let is_open = stream(vm).is_some();
+    vm.set_slot_bool(0, is_open);
+}
+
+fn stream_read(vm: &mut WrenVM) {
+    let stream = open_stream(vm);
+    perform::<true>(vm, move || {
+	let mut bytes = vec![0; READ_SIZE];
+	match stream?.read(&mut bytes)? {
+	    0 => Ok(Reply::Null),
+	    count => {
+		bytes.truncate(count);
+		Ok(Reply::Bytes(bytes))
+	    }
+	}
+    });
+}
+
+fn stream_write(vm: &mut WrenVM) {
+    let stream = open_stream(vm);
+    let bytes = string_arg(vm, 1);
+    perform::<true>(vm, move || {
+	stream?.write_all(bytes.as_bytes())?;
+	Ok(Reply::Null)
+    });
+}
+
+// Shutting the connection down wakes the operations still waiting on it.
+fn stream_close(vm: &mut WrenVM) {
+    if let Some(stream) = vm.get_slot_foreign_mut::<Stream>(0) {
+	if let Some(stream) = stream.0.take() {
+	    let _ = stream.shutdown(Shutdown::Both);
+	}
+    }
+    vm.set_slot_null(0);
+}
@@ -0,0 +1,105 @@
+// The `os` module of the CLI: the platform, and the process running the
+// script and the processes it starts. Like `io`, it is only in the CLI, as
+// it lets a script run commands and end the process hosting it.
+
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Write};
+use std::process::{self, Command};
+
+use wren_rs::{ForeignMethodFn, FromSlot, WrenVM};
+
+pub const SOURCE: &str = include_str!("os.wren");
+
+// The foreign static methods of each class, by signature.
+const PLATFORM_METHODS: &[(&str, ForeignMethodFn)] = &[("name", platform_name)];
+
+const PROCESS_METHODS: &[(&str, ForeignMethodFn)] = &[
+    ("arguments", process_arguments),
+    ("cwd", process_cwd),
+    ("env_(_)", process_env),
+    ("exit_(_)", process_exit),
+    ("exec_(_,_)", process_exec),
+];
+
+thread_local! {
+    // What `Process.arguments` gives scripts.
+    static ARGUMENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers the foreign methods of the `os` module, with the arguments
+/// `Process.arguments` gives scripts.
+pub fn bind(vm: &mut WrenVM, arguments: Vec<String>) {
+    ARGUMENTS.with(|cell| *cell.borrow_mut() = arguments);
+    for (class, methods) in [("Platform", PLATFORM_METHODS), ("Process", PROCESS_METHODS)] {
+	for &(signature, method) in methods {
+	    vm.bind_foreign_method("os", class, true, signature, method);
+	}
+    }
+}
+
+fn platform_name(vm: &mut WrenVM) {
+    vm.set_slot_string(0, env::consts::OS);
+}
+
+fn process_arguments(vm: &mut WrenVM) {
+    vm.set_slot_new_list(0);
+    vm.ensure_slots(2);
+    let arguments = ARGUMENTS.with(|cell| cell.borrow().clone());
+    for argument in arguments {
+	vm.set_slot_string(1, argument);
+	let _ = vm.insert_in_list(0, -1, 1);
+    }
+}
+
+fn process_cwd(vm: &mut WrenVM) {
+    match env::current_dir() {
+	Ok(path) => vm.set_slot_string(0, path.to_string_lossy()),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn process_env(vm: &mut WrenVM) {
+    // `Process.env` has checked the name is a string.
+    let value = vm.get_slot_string(1).ok().and_then(|name| env::var(name).ok());
+    match value {
+	Some(value) => vm.set_slot_string(0, value),
+	None => vm.set_slot_null(0),
+    }
+}
+
+fn process_exit(vm: &mut WrenVM) {
+    let code = vm.get_slot_double(1).unwrap_or(0.0) as i32;
+    // What the script printed last may not have been written out yet.
+    let _ = io::stdout().flush();
+    process::exit(code);
+}
+
+// Runs a command and stores a list of its exit code, stdout and stderr in
+// slot 0.
+fn process_exec(vm: &mut WrenVM) {
+    let command = vm.get_slot_string(1).unwrap_or("").to_string();
+    let arguments = Vec::<String>::from_slot(vm, 2).unwrap_or_default();
+    let output = match Command::new(&command).args(&arguments).output() {
+	Ok(output) => output,
+	Err(error) => {
+	    vm.set_slot_string(0, format!("Could not run '{}': {}", command, error));
+	    return vm.abort_fiber(0);
+	}
+    };
+    vm.set_slot_new_list(0);
+    let field = vm.slot_count();
+    vm.ensure_slots(field + 1);
+    match output.status.code() {
+	Some(code) => vm.set_slot_double(field, code.into()),
+	None => vm.set_slot_null(field),
+    }
+    let _ = vm.insert_in_list(0, -1, field);
+    for bytes in [&output.stdout, &output.stderr] {
+	vm.set_slot_bytes(field, bytes);
+	let _ = vm.insert_in_list(0, -1, field);
+    }
+}
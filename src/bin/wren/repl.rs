@@ -0,0 +1,179 @@
+// The interactive prompt `wren` starts when it isn't given a script.
+//
+// Each entry is interpreted in the same module, so top-level variables
+// defined by one are visible to the next. An entry that is a single
+// expression has its value printed. Lines are read with a line editor that
+// keeps a history across sessions and completes variable and method names
+// on tab.
+
+use std::env;
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use wren_rs::lexer::{Lexer, Token};
+use wren_rs::parser;
+use wren_rs::{FileModuleLoader, WrenConfiguration, WrenVM};
+
+const MODULE: &str = "repl";
+
+pub fn run() {
+    // Imports are found in the current directory.
+    let config = WrenConfiguration {
+	module_loader: Some(Box::new(FileModuleLoader::new("."))),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    // Create the module up front, so the core classes it imports can be
+    // completed before the first entry.
+    let _ = vm.interpret(MODULE, "");
+
+    let mut editor = match Editor::<Completions, DefaultHistory>::new() {
+	Ok(editor) => editor,
+	Err(error) => {
+	    eprintln!("Could not start the line editor: {}", error);
+	    return;
+	}
+    };
+    let history = history_file();
+    if let Some(history) = &history {
+	// There is no history yet the first time.
+	let _ = editor.load_history(history);
+    }
+    editor.set_helper(Some(Completions::from_vm(&vm)));
+
+    let mut input = String::new();
+    loop {
+	let prompt = if input.is_empty() { "> " } else { "| " };
+	let line = match editor.readline(prompt) {
+	    Ok(line) => line,
+	    // Ctrl-C abandons the entry being typed.
+	    Err(ReadlineError::Interrupted) => {
+		input.clear();
+		continue;
+	    }
+	    Err(_) => break,
+	};
+	if !line.trim().is_empty() {
+	    let _ = editor.add_history_entry(line.as_str());
+	}
+	input.push_str(&line);
+	input.push('\n');
+	if is_incomplete(&input) {
+	    continue;
+	}
+	evaluate(&mut vm, &input);
+	input.clear();
+	editor.set_helper(Some(Completions::from_vm(&vm)));
+    }
+    if let Some(history) = &history {
+	let _ = editor.save_history(history);
+    }
+}
+
+// Where the history is kept between sessions: `~/.wren_history`.
+fn history_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".wren_history"))
+}
+
+// Runs an entry, printing its value if it is an expression. Errors have
+// already been reported on stderr, and leave the module's variables as the
+// entry left them.
+fn evaluate(vm: &mut WrenVM, input: &str) {
+    if input.trim().is_empty() {
+	return;
+    }
+    let _ = if parser::parse_expression(input).is_ok() {
+	vm.interpret(MODULE, &format!("System.print({})", input.trim_end()))
+    } else {
+	vm.interpret(MODULE, input)
+    };
+}
+
+// Whether `input` stops partway through, such as inside a block or string,
+// so the prompt should continue it on the next line.
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut lexer = Lexer::new(input);
+    for lexeme in lexer.by_ref() {
+	match lexeme.token {
+	    Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+	    Token::RightParen | Token::RightBracket | Token::RightBrace => depth -= 1,
+	    // A backslash at the very end starts an escape.
+	    Token::Error(message) => return message == "Unterminated string.",
+	    _ => {}
+	}
+    }
+    // The only errors the lexer goes on past are from reaching the end
+    // inside a string or comment.
+    depth > 0 || !lexer.errors().is_empty()
+}
+
+// The names tab completes: the REPL module's variables, or after a `.`,
+// the names of the methods the VM knows. It is rebuilt after each entry,
+// since the editor can't borrow the VM while it reads a line.
+struct Completions {
+    variables: Vec<String>,
+    methods: Vec<String>,
+}
+
+impl Completions {
+    fn from_vm(vm: &WrenVM) -> Completions {
+	let variables = vm.variable_names(MODULE).into_iter().map(str::to_string).collect();
+	// Reduce signatures like `insert(_,_)` and `count=(_)` to their
+	// names, skipping operators and constructor initializers.
+	let mut methods: Vec<String> = vm
+	    .method_signatures()
+	    .filter(|signature| !signature.starts_with("init "))
+	    .map(|signature| signature.split(['(', '=']).next().unwrap_or(""))
+	    .filter(|name| name.starts_with(is_name_char) && !name.ends_with('_'))
+	    .map(str::to_string)
+	    .collect();
+	methods.sort();
+	methods.dedup();
+	Completions { variables, methods }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+	let before = &line[..pos];
+	let start = before
+	    .char_indices()
+	    .rev()
+	    .take_while(|&(_, c)| is_name_char(c))
+	    .last()
+	    .map_or(pos, |(index, _)| index);
+	let word = &before[start..];
+	let names = if before[..start].ends_with('.') {
+	    &self.methods
+	} else {
+	    &self.variables
+	};
+	let candidates = names.iter().filter(|name| name.starts_with(word)).cloned().collect();
+	Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
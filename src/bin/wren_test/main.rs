@@ -0,0 +1,263 @@
+// Runs `.wren` test files written for the reference implementation's test
+// suite, checking what they print and the errors they raise against the
+// expectations in their comments:
+//
+//     System.print(1 + 2) // expect: 3
+//     var x = (          // expect error
+//     Fiber.abort("oops") // expect runtime error: oops
+//
+// `// expect error line N` expects a compile error on another line. Files
+// containing `// skip:` are skipped, and those containing `// nontest`,
+// such as modules other tests import, aren't run at all.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use wren_rs::{FileModuleLoader, WrenConfiguration, WrenError, WrenVM};
+
+const EXPECT_OUTPUT: &str = "// expect: ";
+const EXPECT_ERROR: &str = "// expect error";
+const EXPECT_ERROR_LINE: &str = "// expect error line ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const SKIP: &str = "// skip:";
+const NONTEST: &str = "// nontest";
+
+// What a test file expects to happen when it runs.
+#[derive(Default)]
+struct Expectations {
+    // Each printed line, with the line of the file that expects it.
+    output: Vec<(u32, String)>,
+    compile_error_lines: Vec<u32>,
+    runtime_error: Option<(u32, String)>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Expectations {
+	let mut expectations = Expectations::default();
+	for (index, text) in source.lines().enumerate() {
+	    let line = index as u32 + 1;
+	    if let Some(output) = after(text, EXPECT_OUTPUT) {
+		expectations.output.push((line, output.to_string()));
+	    } else if let Some(message) = after(text, EXPECT_RUNTIME_ERROR) {
+		expectations.runtime_error = Some((line, message.to_string()));
+	    } else if let Some(number) = after(text, EXPECT_ERROR_LINE) {
+		if let Ok(number) = number.trim().parse() {
+		    expectations.compile_error_lines.push(number);
+		}
+	    } else if after(text, EXPECT_ERROR).is_some() {
+		expectations.compile_error_lines.push(line);
+	    }
+	}
+	expectations
+    }
+}
+
+// The rest of `text` after `marker`, if it has one.
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|start| &text[start + marker.len()..])
+}
+
+// What a test file did when it ran.
+#[derive(Default)]
+struct Outcome {
+    output: String,
+    compile_error_lines: Vec<u32>,
+    // The message and the line it was raised on.
+    runtime_error: Option<(String, Option<u32>)>,
+    stack_overflow: bool,
+}
+
+thread_local! {
+    // Where the callbacks of the VM running the current test record what
+    // happened, since they can't capture anything.
+    static OUTCOME: RefCell<Outcome> = RefCell::new(Outcome::default());
+}
+
+fn write(_: &mut WrenVM, text: &str) {
+    OUTCOME.with(|outcome| outcome.borrow_mut().output.push_str(text));
+}
+
+fn report(_: &mut WrenVM, error: &WrenError) {
+    OUTCOME.with(|outcome| {
+	let mut outcome = outcome.borrow_mut();
+	match error {
+	    // Only errors in the test itself are expected, not in modules it
+	    // imports.
+	    WrenError::Compile { module, error } if module == "main" => {
+		outcome.compile_error_lines.push(error.span.line);
+	    }
+	    WrenError::Compile { .. } => {}
+	    WrenError::Runtime {
+		message,
+		stack_trace,
+		..
+	    } => {
+		let line = stack_trace.first().map(|frame| frame.line);
+		outcome.runtime_error = Some((message.clone(), line));
+	    }
+	    WrenError::StackOverflow => outcome.stack_overflow = true,
+	    WrenError::Timeout
+	    | WrenError::Api { .. }
+	    | WrenError::Bytecode { .. }
+	    | WrenError::Snapshot { .. }
+	    | WrenError::Warning { .. } => {}
+	}
+    });
+}
+
+enum TestResult {
+    Pass,
+    Fail(Vec<String>),
+    Skip,
+}
+
+fn run_test(path: &Path, source: &str) -> TestResult {
+    if source.contains(SKIP) {
+	return TestResult::Skip;
+    }
+    let expected = Expectations::parse(source);
+
+    OUTCOME.with(|outcome| *outcome.borrow_mut() = Outcome::default());
+    let root = path.parent().unwrap_or_else(|| Path::new(""));
+    let config = WrenConfiguration {
+	module_loader: Some(Box::new(FileModuleLoader::new(root))),
+	write_fn: Some(write),
+	error_fn: Some(report),
+	..WrenConfiguration::default()
+    };
+    let mut vm = WrenVM::with_configuration(config);
+    let _ = vm.interpret("main", source);
+    let actual = OUTCOME.with(|outcome| outcome.replace(Outcome::default()));
+
+    let mut failures = Vec::new();
+    let mut lines: Vec<&str> = actual.output.split('\n').collect();
+    if lines.last() == Some(&"") {
+	lines.pop();
+    }
+    for (index, (line, expected)) in expected.output.iter().enumerate() {
+	match lines.get(index) {
+	    Some(&actual) if actual == expected => {}
+	    Some(&actual) => failures.push(format!(
+		"Expected output '{}' on line {} and got '{}'.",
+		expected, line, actual
+	    )),
+	    None => failures.push(format!(
+		"Missing expected output '{}' on line {}.",
+		expected, line
+	    )),
+	}
+    }
+    for extra in lines.iter().skip(expected.output.len()) {
+	failures.push(format!("Got output '{}' when none was expected.", extra));
+    }
+
+    for line in &expected.compile_error_lines {
+	if !actual.compile_error_lines.contains(line) {
+	    failures.push(format!("Expected a compile error on line {}.", line));
+	}
+    }
+    for line in &actual.compile_error_lines {
+	if !expected.compile_error_lines.contains(line) {
+	    failures.push(format!("Unexpected compile error on line {}.", line));
+	}
+    }
+
+    match (&expected.runtime_error, &actual.runtime_error) {
+	(None, None) => {}
+	(Some((line, message)), None) => failures.push(format!(
+	    "Expected runtime error '{}' on line {}.",
+	    message, line
+	)),
+	(None, Some((message, _))) => {
+	    failures.push(format!("Unexpected runtime error '{}'.", message))
+	}
+	(Some((expected_line, expected)), Some((message, line))) => {
+	    if message != expected {
+		failures.push(format!(
+		    "Expected runtime error '{}' and got '{}'.",
+		    expected, message
+		));
+	    }
+	    if *line != Some(*expected_line) {
+		failures.push(format!(
+		    "Expected runtime error on line {} but was on line {}.",
+		    expected_line,
+		    line.map_or_else(|| "?".to_string(), |line| line.to_string())
+		));
+	    }
+	}
+    }
+    if actual.stack_overflow {
+	failures.push("Stack overflow.".to_string());
+    }
+
+    if failures.is_empty() {
+	TestResult::Pass
+    } else {
+	TestResult::Fail(failures)
+    }
+}
+
+// The `.wren` files at or below `path`, in a stable order.
+fn collect_tests(path: &Path, tests: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+	let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+	    Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+	    Err(error) => {
+		eprintln!("Could not read directory \"{}\": {}", path.display(), error);
+		return;
+	    }
+	};
+	entries.sort();
+	for entry in entries {
+	    collect_tests(&entry, tests);
+	}
+    } else if path.extension().is_some_and(|extension| extension == "wren") {
+	tests.push(path.to_path_buf());
+    }
+}
+
+fn main() {
+    let mut paths: Vec<PathBuf> = env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+	paths.push(PathBuf::from("test"));
+    }
+    let mut tests = Vec::new();
+    for path in &paths {
+	collect_tests(path, &mut tests);
+    }
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for test in &tests {
+	let source = match fs::read_to_string(test) {
+	    Ok(source) => source,
+	    Err(error) => {
+		eprintln!("Could not read file \"{}\": {}", test.display(), error);
+		failed += 1;
+		continue;
+	    }
+	};
+	if source.contains(NONTEST) {
+	    continue;
+	}
+	match run_test(test, &source) {
+	    TestResult::Pass => passed += 1,
+	    TestResult::Skip => skipped += 1,
+	    TestResult::Fail(failures) => {
+		failed += 1;
+		println!("FAIL: {}", test.display());
+		for failure in failures {
+		    println!("      {}", failure);
+		}
+	    }
+	}
+    }
+
+    println!("{} passed, {} failed, {} skipped.", passed, failed, skipped);
+    if failed > 0 {
+	process::exit(1);
+    }
+}
@@ -0,0 +1,456 @@
+// Foreign methods from Rust closures with typed parameters.
+//
+// `FromSlot` reads a Rust value out of a slot and `IntoSlot` stores one,
+// so a closure such as `|a: f64, b: f64| a + b` can read its arguments
+// from slots 1 and up and leave its result in slot 0 without touching the
+// slot API itself.
+
+#[cfg(not(feature = "send"))]
+use alloc::rc::Rc as Shared;
+#[cfg(feature = "send")]
+use alloc::sync::Arc as Shared;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::any::{type_name, Any};
+use core::fmt::Display;
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+
+use crate::api::{api_error, slot_error, WrenType};
+use crate::compiler::Signature;
+use crate::error::WrenError;
+#[allow(unused_imports)]
+use crate::float::Float;
+use crate::handle::WrenHandle;
+use crate::core::{list_insert_at, map_set};
+use crate::value::Value;
+use crate::vm::{ForeignClosure, MaybeSendSync, Method, WrenVM};
+
+/// A Rust value that can be read from a slot.
+pub trait FromSlot: Sized {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<Self, WrenError>;
+}
+
+/// A Rust value that can be stored in a slot.
+pub trait IntoSlot {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError>;
+}
+
+impl FromSlot for bool {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<bool, WrenError> {
+	vm.get_slot_bool(slot)
+    }
+}
+
+impl FromSlot for f64 {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<f64, WrenError> {
+	vm.get_slot_double(slot)
+    }
+}
+
+impl FromSlot for f32 {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<f32, WrenError> {
+	vm.get_slot_double(slot).map(|value| value as f32)
+    }
+}
+
+impl FromSlot for String {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<String, WrenError> {
+	vm.get_slot_string(slot).map(str::to_string)
+    }
+}
+
+/// Null is `None`.
+impl<T: FromSlot> FromSlot for Option<T> {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<Option<T>, WrenError> {
+	match vm.get_slot_type(slot) {
+	    WrenType::Null => Ok(None),
+	    _ => T::from_slot(vm, slot).map(Some),
+	}
+    }
+}
+
+/// The elements of a list, as `get_slot_list_as` reads them.
+impl<T: FromSlot> FromSlot for Vec<T> {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<Vec<T>, WrenError> {
+	vm.get_slot_list_as(slot)
+    }
+}
+
+/// A handle to the value, which the host must release.
+impl FromSlot for WrenHandle {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<WrenHandle, WrenError> {
+	Ok(vm.get_slot_handle(slot))
+    }
+}
+
+impl IntoSlot for () {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_null(slot);
+	Ok(())
+    }
+}
+
+impl IntoSlot for bool {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_bool(slot, self);
+	Ok(())
+    }
+}
+
+impl IntoSlot for f64 {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_double(slot, self);
+	Ok(())
+    }
+}
+
+impl IntoSlot for f32 {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_double(slot, self.into());
+	Ok(())
+    }
+}
+
+impl IntoSlot for String {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_string(slot, self);
+	Ok(())
+    }
+}
+
+impl IntoSlot for &str {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_string(slot, self);
+	Ok(())
+    }
+}
+
+/// `None` is null.
+impl<T: IntoSlot> IntoSlot for Option<T> {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	match self {
+	    Some(value) => value.into_slot(vm, slot),
+	    None => {
+		vm.set_slot_null(slot);
+		Ok(())
+	    }
+	}
+    }
+}
+
+/// A new list, as `set_slot_list_from_iter` stores it.
+impl<T: IntoSlot> IntoSlot for Vec<T> {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	vm.set_slot_list_from_iter(slot, self)
+    }
+}
+
+/// An `Err` is an error with the error's message, which aborts the fiber
+/// when returned from a bound closure.
+impl<T: IntoSlot, E: Display> IntoSlot for Result<T, E> {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	match self {
+	    Ok(value) => value.into_slot(vm, slot),
+	    Err(error) => Err(api_error(error.to_string())),
+	}
+    }
+}
+
+// Integers are numbers with no fractional part, in the type's range.
+macro_rules! integer_slots {
+    ($($ty:ty),*) => {
+	$(
+	    impl FromSlot for $ty {
+		fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<$ty, WrenError> {
+		    let value = vm.get_slot_double(slot)?;
+		    let (min, max) = (<$ty>::MIN as f64, <$ty>::MAX as f64);
+		    if value.fract() != 0.0 || value < min || value > max {
+			let expected = format!("an integer that fits in {}", stringify!($ty));
+			return Err(slot_error(slot, &expected));
+		    }
+		    Ok(value as $ty)
+		}
+	    }
+
+	    impl IntoSlot for $ty {
+		fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+		    vm.set_slot_double(slot, self as f64);
+		    Ok(())
+		}
+	    }
+	)*
+    };
+}
+
+integer_slots!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// A closure `WrenVM::bind` can make a foreign method of. It is implemented
+/// for closures taking up to eight `FromSlot` arguments and returning
+/// something `IntoSlot`, and for closures taking a `&mut T` before those
+/// arguments, which are methods on a foreign object holding a `T`.
+pub trait ForeignFn<Args> {
+    /// How many arguments the closure takes, not counting the receiver.
+    const ARITY: usize;
+
+    /// Calls the closure with the arguments in slots 1 and up, storing its
+    /// result in slot 0.
+    fn call(&self, vm: &mut WrenVM) -> Result<(), WrenError>;
+}
+
+/// Marks the arguments of a closure that takes the foreign object in slot
+/// 0 as its first argument.
+pub struct Receiver<T>(PhantomData<T>);
+
+macro_rules! foreign_fn {
+    ($arity:literal $(, $arg:ident $value:ident $slot:literal)*) => {
+	impl<F, R, $($arg),*> ForeignFn<($($arg,)*)> for F
+	where
+	    F: Fn($($arg),*) -> R,
+	    R: IntoSlot,
+	    $($arg: FromSlot,)*
+	{
+	    const ARITY: usize = $arity;
+
+	    fn call(&self, vm: &mut WrenVM) -> Result<(), WrenError> {
+		let result = self($(<$arg>::from_slot(vm, $slot)?),*);
+		result.into_slot(vm, 0)
+	    }
+	}
+
+	impl<F, R, T: Any, $($arg),*> ForeignFn<(Receiver<T>, $($arg,)*)> for F
+	where
+	    F: Fn(&mut T, $($arg),*) -> R,
+	    R: IntoSlot,
+	    $($arg: FromSlot,)*
+	{
+	    const ARITY: usize = $arity;
+
+	    fn call(&self, vm: &mut WrenVM) -> Result<(), WrenError> {
+		// The arguments are read first, as the receiver borrows the VM.
+		$(let $value = <$arg>::from_slot(vm, $slot)?;)*
+		let receiver = match vm.get_slot_foreign_mut::<T>(0) {
+		    Some(receiver) => receiver,
+		    None => {
+			let expected = format!("a foreign {}", type_name::<T>());
+			return Err(slot_error(0, &expected));
+		    }
+		};
+		let result = self(receiver, $($value),*);
+		result.into_slot(vm, 0)
+	    }
+	}
+    };
+}
+
+foreign_fn!(0);
+foreign_fn!(1, A a 1);
+foreign_fn!(2, A a 1, B b 2);
+foreign_fn!(3, A a 1, B b 2, C c 3);
+foreign_fn!(4, A a 1, B b 2, C c 3, D d 4);
+foreign_fn!(5, A a 1, B b 2, C c 3, D d 4, E e 5);
+foreign_fn!(6, A a 1, B b 2, C c 3, D d 4, E e 5, G g 6);
+foreign_fn!(7, A a 1, B b 2, C c 3, D d 4, E e 5, G g 6, H h 7);
+foreign_fn!(8, A a 1, B b 2, C c 3, D d 4, E e 5, G g 6, H h 7, I i 8);
+
+/// A Rust type exported to scripts as a foreign class, usually by
+/// `#[wren_class]`.
+pub trait ForeignClass: Any {
+    /// The name of the class in scripts.
+    const NAME: &'static str;
+
+    /// The `foreign class` declaration for a module to include, with the
+    /// class's constructor and foreign methods.
+    const SOURCE: &'static str;
+
+    /// Registers the class's allocator, finalizer and methods for the class
+    /// declared in `module`. Fails as `WrenVM::bind` does, if a method's
+    /// name makes a signature no method could have.
+    fn bind(vm: &mut WrenVM, module: &str) -> Result<(), WrenError>;
+}
+
+/// What a foreign class's constructor returns: the object, or a result
+/// with it, whose `Err` aborts the fiber.
+pub trait IntoForeign<T> {
+    fn into_foreign(self) -> Result<T, WrenError>;
+}
+
+impl<T> IntoForeign<T> for T {
+    fn into_foreign(self) -> Result<T, WrenError> {
+	Ok(self)
+    }
+}
+
+impl<T, E: Display> IntoForeign<T> for Result<T, E> {
+    fn into_foreign(self) -> Result<T, WrenError> {
+	self.map_err(|error| api_error(error.to_string()))
+    }
+}
+
+impl WrenVM {
+    /// Registers `method` as the implementation of the foreign method with
+    /// `signature` in `class` of `module`, like `bind_foreign_method`. A
+    /// static method's signature starts with `static `, as in
+    /// `"static add(_,_)"`.
+    ///
+    /// The closure is passed the call's arguments and its result is the
+    /// call's, so `|a: f64, b: f64| a + b` implements `add(_,_)`. A method
+    /// of a foreign class can take the object's data first, as in
+    /// `|counter: &mut Counter, n: f64| counter.add(n)`. If an
+    /// argument has the wrong type, or the closure returns an `Err`, the
+    /// fiber is aborted with the error's message.
+    ///
+    /// Binding a method again replaces the closure bound before. Fails
+    /// with an API error if the signature isn't one a method could have,
+    /// or if the closure doesn't take as many arguments as it has.
+    pub fn bind<Args, F: ForeignFn<Args> + MaybeSendSync + 'static>(
+	&mut self,
+	module: &str,
+	class: &str,
+	signature: &str,
+	method: F,
+    ) -> Result<(), WrenError> {
+	let (is_static, signature) = match signature.strip_prefix("static ") {
+	    Some(signature) => (true, signature),
+	    None => (false, signature),
+	};
+	let arity = Signature::parse(signature)
+	    .map_err(|error| api_error(format!("Invalid signature '{}': {}", signature, error)))?
+	    .arity;
+	if arity != F::ARITY {
+	    return Err(api_error(format!(
+		"'{}' takes {} arguments but the closure takes {}.",
+		signature,
+		arity,
+		F::ARITY
+	    )));
+	}
+	let closure: ForeignClosure = Shared::new(move |vm: &mut WrenVM| {
+	    if let Err(error) = method.call(vm) {
+		vm.set_slot_string(0, error.to_string());
+		vm.abort_fiber(0);
+	    }
+	});
+	let key = (module.to_string(), class.to_string(), is_static, signature.to_string());
+	let index = match self.foreign_methods.get(&key) {
+	    Some(&Method::ForeignClosure(index)) => {
+		self.foreign_closures[index] = closure;
+		index
+	    }
+	    _ => {
+		self.foreign_closures.push(closure);
+		self.foreign_closures.len() - 1
+	    }
+	};
+	self.rebind_foreign_method(&key, Method::ForeignClosure(index));
+	self.foreign_methods.insert(key, Method::ForeignClosure(index));
+	Ok(())
+    }
+
+    /// Reads every element of the list in `slot` as a `T`. The elements are
+    /// read straight from the list, each through a slot past the others
+    /// that is released afterwards, rather than fetched into a slot one
+    /// call at a time.
+    pub fn get_slot_list_as<T: FromSlot>(&mut self, slot: usize) -> Result<Vec<T>, WrenError> {
+	let list = self.list_in(slot)?;
+	let elements = self.heap.list(list).elements.clone();
+	self.with_scratch_slots(1, |vm, element| {
+	    elements
+		.into_iter()
+		.map(|value| {
+		    vm.set_slot(element, value);
+		    T::from_slot(vm, element)
+		})
+		.collect()
+	})
+    }
+
+    /// Stores a new list of `values` in `slot`.
+    pub fn set_slot_list_from<T: IntoSlot + Clone>(
+	&mut self,
+	slot: usize,
+	values: &[T],
+    ) -> Result<(), WrenError> {
+	self.set_slot_list_from_iter(slot, values.iter().cloned())
+    }
+
+    /// Stores a new list of the values `values` yields in `slot`, each
+    /// appended to the list straight from a slot past the others.
+    pub fn set_slot_list_from_iter<I>(&mut self, slot: usize, values: I) -> Result<(), WrenError>
+    where
+	I: IntoIterator,
+	I::Item: IntoSlot,
+    {
+	self.set_slot_new_list(slot);
+	let list = self.list_in(slot)?;
+	self.with_scratch_slots(1, |vm, element| {
+	    for value in values {
+		value.into_slot(vm, element)?;
+		let value = vm.slot(element);
+		let count = vm.heap.list(list).elements.len();
+		list_insert_at(vm, list, count, value);
+	    }
+	    Ok(())
+	})
+    }
+
+    /// Reads every entry of the map in `slot` as a key `K` and a value `V`,
+    /// collected into anything that can be built from the pairs, such as a
+    /// `Vec<(K, V)>` or a `HashMap<K, V>`.
+    pub fn get_slot_map_as<K, V, C>(&mut self, slot: usize) -> Result<C, WrenError>
+    where
+	K: FromSlot,
+	V: FromSlot,
+	C: FromIterator<(K, V)>,
+    {
+	let map = self.map_in(slot)?;
+	let entries: Vec<(Value, Value)> = self.heap.map(map).iter().collect();
+	self.with_scratch_slots(2, |vm, key_slot| {
+	    let value_slot = key_slot + 1;
+	    entries
+		.into_iter()
+		.map(|(key, value)| {
+		    vm.set_slot(key_slot, key);
+		    vm.set_slot(value_slot, value);
+		    Ok((K::from_slot(vm, key_slot)?, V::from_slot(vm, value_slot)?))
+		})
+		.collect()
+	})
+    }
+
+    /// Stores a new map of the key and value pairs `entries` yields in
+    /// `slot`. Later entries replace earlier ones with an equal key.
+    pub fn set_slot_map_from<K, V, I>(&mut self, slot: usize, entries: I) -> Result<(), WrenError>
+    where
+	K: IntoSlot,
+	V: IntoSlot,
+	I: IntoIterator<Item = (K, V)>,
+    {
+	self.set_slot_new_map(slot);
+	let map = self.map_in(slot)?;
+	self.with_scratch_slots(2, |vm, key_slot| {
+	    let value_slot = key_slot + 1;
+	    for (key, value) in entries {
+		key.into_slot(vm, key_slot)?;
+		let key = vm.key_in(key_slot)?;
+		value.into_slot(vm, value_slot)?;
+		let value = vm.slot(value_slot);
+		map_set(vm, map, key, value);
+	    }
+	    Ok(())
+	})
+    }
+
+    // Runs `f` with `count` more slots past the others, starting at the one
+    // it is passed, for values on their way in or out of a collection, which
+    // keeps them from being collected in the meantime. They are gone
+    // afterwards.
+    fn with_scratch_slots<R>(&mut self, count: usize, f: impl FnOnce(&mut Self, usize) -> R) -> R {
+	let first = self.slot_count();
+	self.ensure_slots(first + count);
+	let result = f(self, first);
+	let base = self.api_stack.expect("slots");
+	self.stack.truncate(base + first);
+	result
+    }
+}
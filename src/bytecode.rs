@@ -0,0 +1,774 @@
+// A compact binary format for compiled modules, so a host can ship scripts
+// precompiled and skip parsing and compiling them at startup.
+//
+// Bytecode refers to methods and module variables by their index in tables
+// that belong to the VM that compiled it. A serialized module carries the
+// names behind every index its code uses, numbered in order of first use,
+// and loading it rewrites the code to the indices of the loading VM.
+//
+// The layout, with integers as LEB128 varints unless noted:
+//
+//     magic "\0wrb", version (u16, little-endian), module name,
+//     method names, variable names, function, checksum
+//
+// where a function is its name, arity, upvalue count, code, lines (as runs
+// of equal lines), named locals (name, slot and code range) and constants,
+// each tagged as a number (its bits as a little-endian u64), string or
+// nested function. The checksum is the CRC-32 of everything before it, as
+// a little-endian u32.
+//
+// Loading checks the checksum, then that the code can't index outside the
+// stack, its constants or its upvalues on any path through it. That catches
+// corrupted bytes, but not code crafted to pass a value of the wrong type
+// to an instruction, such as a number where a class belongs.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use hashbrown::HashMap;
+
+use crate::chunk::{Chunk, Code, Constant, FnProto, LocalName};
+
+/// The first bytes of every serialized module.
+pub const MAGIC: &[u8; 4] = b"\0wrb";
+
+/// The version of the format. Bytecode of any other version is rejected,
+/// since the instruction set may have changed.
+pub const VERSION: u16 = 3;
+
+// Functions nested deeper than this are rejected rather than risk
+// overflowing the stack while reading them.
+const MAX_NESTING: usize = 256;
+
+const TAG_NUM: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FN: u8 = 2;
+
+/// A deserialized module whose code refers to methods and variables by
+/// their index in `methods` and `variables`.
+#[derive(Debug, Clone)]
+pub struct CompiledModule {
+    pub module: String,
+    pub methods: Vec<String>,
+    pub variables: Vec<String>,
+    pub function: FnProto,
+}
+
+/// Whether `bytes` start like a serialized module.
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Serializes the body of `module`, compiled with method names from
+/// `method_name` and variable names from `variable_name`.
+pub fn serialize<'a>(
+    mut function: FnProto,
+    module: &str,
+    method_name: impl Fn(usize) -> &'a str,
+    variable_name: impl Fn(usize) -> &'a str,
+) -> Vec<u8> {
+    let mut methods = Names::default();
+    let mut variables = Names::default();
+    remap(
+	&mut function,
+	&mut |symbol| Ok(methods.intern(method_name(symbol))),
+	&mut |slot| Ok(variables.intern(variable_name(slot))),
+    )
+    .expect("the compiler emits well-formed code");
+
+    let mut out = Writer::default();
+    out.bytes.extend_from_slice(MAGIC);
+    out.bytes.extend_from_slice(&VERSION.to_le_bytes());
+    out.string(module);
+    out.strings(&methods.names);
+    out.strings(&variables.names);
+    out.function(&function);
+    out.seal();
+    out.bytes
+}
+
+/// Reads a module written by `serialize`, checking that it is intact and
+/// that its code is well formed.
+pub fn deserialize(bytes: &[u8]) -> Result<CompiledModule, String> {
+    if !is_compiled(bytes) {
+	return Err("Not a compiled Wren module.".to_string());
+    }
+    let mut reader = Reader::new(bytes, "compiled module");
+    reader.position = MAGIC.len();
+    let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    if version != VERSION {
+	return Err(format!(
+	    "Compiled module has version {}, but only version {} is supported.",
+	    version, VERSION
+	));
+    }
+    reader.unseal()?;
+    let module = reader.string()?;
+    let methods = reader.strings()?;
+    let variables = reader.strings()?;
+    let mut function = reader.function(0)?;
+    if !reader.is_at_end() {
+	return Err("Unexpected bytes after the compiled module.".to_string());
+    }
+    let (method_count, variable_count) = (methods.len(), variables.len());
+    remap(
+	&mut function,
+	&mut |symbol| in_table(symbol, method_count, "method"),
+	&mut |slot| in_table(slot, variable_count, "variable"),
+    )?;
+    verify_nested(&function, variable_count, method_count)?;
+    Ok(CompiledModule {
+	module,
+	methods,
+	variables,
+	function,
+    })
+}
+
+fn in_table(index: usize, count: usize, kind: &str) -> Result<usize, String> {
+    if index < count {
+	Ok(index)
+    } else {
+	Err(format!("Reference to undefined {} {}.", kind, index))
+    }
+}
+
+/// Replaces each method symbol and module variable slot in the code of
+/// `function`, and of the functions it defines, with what `method` and
+/// `variable` map it to. Fails if any instruction is malformed.
+pub(crate) fn remap(
+    function: &mut FnProto,
+    method: &mut dyn FnMut(usize) -> Result<usize, String>,
+    variable: &mut dyn FnMut(usize) -> Result<usize, String>,
+) -> Result<(), String> {
+    remap_nested(function, method, variable, 0)
+}
+
+fn remap_nested(
+    function: &mut FnProto,
+    method: &mut dyn FnMut(usize) -> Result<usize, String>,
+    variable: &mut dyn FnMut(usize) -> Result<usize, String>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > MAX_NESTING {
+	return Err("Functions are nested too deeply.".to_string());
+    }
+    let FnProto { name, chunk, .. } = function;
+    if chunk.lines.len() != chunk.code.len() {
+	return Err(format!("Function '{}' doesn't have a line for each byte.", name));
+    }
+    let mut offset = 0;
+    let mut last = None;
+    while offset < chunk.code.len() {
+	let code = Code::from_u8(chunk.code[offset])
+	    .filter(|code| !code.is_superinstruction())
+	    .ok_or_else(|| format!("Invalid opcode {} in '{}'.", chunk.code[offset], name))?;
+	let mut next = offset + 1 + code.operand_bytes();
+	if next > chunk.code.len() {
+	    return Err(format!("Truncated instruction in '{}'.", name));
+	}
+	let operand = match code.operand_bytes() {
+	    1 => chunk.code[offset + 1] as usize,
+	    2 => chunk.read_u16(offset + 1) as usize,
+	    _ => 0,
+	};
+	match code {
+	    Code::MethodInstance | Code::MethodStatic => write_u16(chunk, offset + 1, method(operand)?)?,
+	    code if code.arity().is_some() => write_u16(chunk, offset + 1, method(operand)?)?,
+	    Code::LoadModuleVar | Code::StoreModuleVar => {
+		write_u16(chunk, offset + 1, variable(operand)?)?
+	    }
+	    Code::Constant if operand >= chunk.constants.len() => {
+		return Err(format!("Invalid constant in '{}'.", name));
+	    }
+	    Code::ImportModule | Code::ImportVariable => match chunk.constants.get(operand) {
+		Some(Constant::String(_)) => {}
+		_ => return Err(format!("Import without a name in '{}'.", name)),
+	    },
+	    Code::Closure => match chunk.constants.get(operand) {
+		Some(Constant::Fn(closure)) => next += 2 * closure.num_upvalues,
+		_ => return Err(format!("Closure without a function in '{}'.", name)),
+	    },
+	    Code::Jump | Code::JumpIf | Code::And | Code::Or if next + operand > chunk.code.len() => {
+		return Err(format!("Jump out of bounds in '{}'.", name));
+	    }
+	    Code::Loop if operand > next => {
+		return Err(format!("Loop out of bounds in '{}'.", name));
+	    }
+	    _ => {}
+	}
+	if next > chunk.code.len() {
+	    return Err(format!("Truncated instruction in '{}'.", name));
+	}
+	last = Some(code);
+	offset = next;
+    }
+    if !matches!(last, Some(Code::Return) | Some(Code::End)) {
+	return Err(format!("Function '{}' doesn't end with a return.", name));
+    }
+
+    for constant in &mut chunk.constants {
+	if let Constant::Fn(nested) = constant {
+	    remap_nested(nested, method, variable, depth + 1)?;
+	}
+    }
+    Ok(())
+}
+
+// Checks the code of `function` and of the functions it defines, which
+// refer to `variables` module variables and `methods` method symbols.
+fn verify_nested(function: &FnProto, variables: usize, methods: usize) -> Result<(), String> {
+    let constants = function.chunk.constants.iter().map(|constant| match constant {
+	Constant::Num(_) => ConstantKind::Other,
+	Constant::String(_) => ConstantKind::String,
+	Constant::Fn(nested) => ConstantKind::Fn(nested.num_upvalues),
+    });
+    verify(&Unverified {
+	name: &function.name,
+	arity: function.arity,
+	num_upvalues: function.num_upvalues,
+	code: &function.chunk.code,
+	constants: constants.collect(),
+	variables,
+	methods,
+    })?;
+    for constant in &function.chunk.constants {
+	if let Constant::Fn(nested) = constant {
+	    verify_nested(nested, variables, methods)?;
+	}
+    }
+    Ok(())
+}
+
+/// What an instruction can expect of a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConstantKind {
+    /// A function with this many upvalues.
+    Fn(usize),
+    String,
+    Other,
+}
+
+/// The code of a function to check with `verify`, and what it refers to.
+pub(crate) struct Unverified<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) arity: usize,
+    pub(crate) num_upvalues: usize,
+    pub(crate) code: &'a [u8],
+    pub(crate) constants: Vec<ConstantKind>,
+    /// How many module variables there are.
+    pub(crate) variables: usize,
+    /// How many method symbols there are.
+    pub(crate) methods: usize,
+}
+
+/// What `verify` found out about a function's code.
+pub(crate) struct Verified {
+    /// The slots in use when each instruction is reached, if it can be.
+    pub(crate) heights: Vec<Option<usize>>,
+    /// How many fields of its class's instances the code uses, past those
+    /// of the superclass.
+    pub(crate) num_fields: usize,
+}
+
+/// Checks that running `function`'s code can't reach outside its stack
+/// slots, upvalues or constants, nor the module variables and method
+/// symbols. The code may have superinstructions.
+///
+/// Each instruction is reached with the same number of slots in use on
+/// every path to it, which is what lets the VM pop without checking.
+pub(crate) fn verify(function: &Unverified) -> Result<Verified, String> {
+    let Unverified { name, code, .. } = *function;
+    let invalid = |what: &str| format!("{} in '{}'.", what, name);
+    let operand = |offset: usize| u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize;
+
+    // Where each instruction starts, checking its operands along the way.
+    let mut starts = vec![false; code.len()];
+    let mut num_fields = 0;
+    let mut offset = 0;
+    while offset < code.len() {
+	starts[offset] = true;
+	let op = Code::from_u8(code[offset]).ok_or_else(|| invalid("Invalid opcode"))?;
+	let mut next = offset + 1 + op.operand_bytes();
+	if next > code.len() {
+	    return Err(invalid("Truncated instruction"));
+	}
+	let constant = |kind: fn(ConstantKind) -> bool| {
+	    function.constants.get(operand(offset)).copied().filter(|&constant| kind(constant))
+	};
+	let valid = match op {
+	    Code::Constant | Code::ConstantCall1 => constant(|_| true).is_some(),
+	    Code::ImportModule | Code::ImportVariable => {
+		constant(|kind| kind == ConstantKind::String).is_some()
+	    }
+	    Code::Closure => match constant(|kind| matches!(kind, ConstantKind::Fn(_))) {
+		Some(ConstantKind::Fn(num_upvalues)) => {
+		    next += 2 * num_upvalues;
+		    next <= code.len()
+		}
+		_ => false,
+	    },
+	    Code::LoadModuleVar | Code::StoreModuleVar => operand(offset) < function.variables,
+	    Code::MethodInstance | Code::MethodStatic | Code::Call1JumpIf => {
+		operand(offset) < function.methods
+	    }
+	    Code::LoadLocalCall1 => operand(offset + 1) < function.methods,
+	    Code::LoadFieldThis | Code::StoreFieldThis | Code::LoadField | Code::StoreField => {
+		num_fields = num_fields.max(code[offset + 1] as usize + 1);
+		true
+	    }
+	    op if op.arity().is_some() => operand(offset) < function.methods,
+	    _ => true,
+	};
+	if !valid {
+	    return Err(invalid(&format!("Invalid operand of {}", op.name())));
+	}
+	offset = next;
+    }
+
+    let mut heights: Vec<Option<usize>> = vec![None; code.len()];
+    let mut pending = vec![(0, function.arity + 1)];
+    while let Some((offset, height)) = pending.pop() {
+	if !starts.get(offset).copied().unwrap_or(false) {
+	    return Err(invalid("Jump to the middle of an instruction"));
+	}
+	match heights[offset] {
+	    Some(reached) if reached == height => continue,
+	    Some(_) => return Err(invalid("Paths leave different slots in use")),
+	    None => heights[offset] = Some(height),
+	}
+
+	let op = Code::from_u8(code[offset]).expect("checked above");
+	let next = match op {
+	    Code::Closure => match function.constants[operand(offset)] {
+		ConstantKind::Fn(num_upvalues) => offset + 3 + 2 * num_upvalues,
+		_ => unreachable!("checked above"),
+	    },
+	    op => offset + 1 + op.operand_bytes(),
+	};
+	let (pops, pushes) = match op {
+	    Code::Constant
+	    | Code::ConstantCall1
+	    | Code::Null
+	    | Code::False
+	    | Code::True
+	    | Code::LoadLocal0
+	    | Code::LoadLocal1
+	    | Code::LoadLocal2
+	    | Code::LoadLocal3
+	    | Code::LoadLocal4
+	    | Code::LoadLocal5
+	    | Code::LoadLocal6
+	    | Code::LoadLocal7
+	    | Code::LoadLocal8
+	    | Code::LoadLocal
+	    | Code::LoadUpvalue
+	    | Code::LoadModuleVar
+	    | Code::Closure
+	    | Code::LoadFieldThis
+	    | Code::ImportModule
+	    | Code::ImportVariable
+	    | Code::EndModule => (0, 1),
+	    // Loads a local as the argument of a call on what is on top.
+	    Code::LoadLocalCall1
+	    | Code::StoreLocal
+	    | Code::StoreUpvalue
+	    | Code::StoreModuleVar
+	    | Code::StoreFieldThis
+	    | Code::LoadField
+	    | Code::And
+	    | Code::Or => (1, 1),
+	    Code::Pop | Code::CloseUpvalue | Code::JumpIf | Code::Return => (1, 0),
+	    Code::StoreField | Code::Class | Code::ForeignClass | Code::Call1JumpIf => (2, 1),
+	    Code::EndClass | Code::MethodInstance | Code::MethodStatic => (2, 0),
+	    Code::Construct | Code::ForeignConstruct | Code::Jump | Code::Loop | Code::End => (0, 0),
+	    op => match op.arity() {
+		Some(arity) => (arity + 1, 1),
+		None => unreachable!("{:?} is handled above", op),
+	    },
+	};
+	if height < pops {
+	    return Err(invalid("Stack underflow"));
+	}
+
+	// The slot each local instruction uses, which must be in use.
+	let slot = match op {
+	    Code::LoadLocal | Code::StoreLocal | Code::LoadLocalCall1 => {
+		Some(code[offset + 1] as usize)
+	    }
+	    Code::Construct
+	    | Code::ForeignConstruct
+	    | Code::LoadFieldThis
+	    | Code::StoreFieldThis => Some(0),
+	    op if (Code::LoadLocal0 as u8..=Code::LoadLocal8 as u8).contains(&(op as u8)) => {
+		Some(op as usize - Code::LoadLocal0 as usize)
+	    }
+	    _ => None,
+	};
+	if slot.is_some_and(|slot| slot >= height) {
+	    return Err(invalid("Invalid local slot"));
+	}
+	let is_upvalue = matches!(op, Code::LoadUpvalue | Code::StoreUpvalue);
+	if is_upvalue && code[offset + 1] as usize >= function.num_upvalues {
+	    return Err(invalid("Invalid upvalue"));
+	}
+	if op == Code::Closure {
+	    // Each upvalue captures a local in use or one of this function's
+	    // upvalues.
+	    for capture in code[offset + 3..next].chunks(2) {
+		let limit = if capture[0] != 0 { height } else { function.num_upvalues };
+		if capture[1] as usize >= limit {
+		    return Err(invalid("Invalid upvalue"));
+		}
+	    }
+	}
+	// Superinstructions that read the instruction after them need it to
+	// be the one they were made from, which may have been fused with the
+	// one after it in turn.
+	let second: &[Code] = match op {
+	    Code::ConstantCall1 => &[Code::Call1, Code::Call1JumpIf],
+	    Code::Call1JumpIf => &[Code::JumpIf],
+	    _ => &[],
+	};
+	let fused = code.get(next).and_then(|&second| Code::from_u8(second));
+	if !second.is_empty() && !fused.is_some_and(|fused| second.contains(&fused)) {
+	    return Err(invalid(&format!("{} without its second instruction", op.name())));
+	}
+
+	let after = height - pops + pushes;
+	let jump = match op {
+	    Code::Jump | Code::JumpIf | Code::And | Code::Or => Some(next + operand(offset)),
+	    Code::Loop => next.checked_sub(operand(offset)),
+	    _ => None,
+	};
+	match op {
+	    Code::Return => {}
+	    Code::End => return Err(invalid("Code runs past its end")),
+	    Code::Jump | Code::Loop => {
+		pending.push((jump.ok_or_else(|| invalid("Jump out of bounds"))?, after))
+	    }
+	    Code::JumpIf => pending.extend([(next, after), (jump.expect("a jump"), after)]),
+	    // The jump keeps the operand as the result.
+	    Code::And | Code::Or => pending.extend([(next, after - 1), (jump.expect("a jump"), after)]),
+	    _ => pending.push((next, after)),
+	}
+    }
+    Ok(Verified { heights, num_fields })
+}
+
+// The CRC-32 of `bytes`, as zlib computes it.
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+	crc = CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+	let mut crc = index as u32;
+	let mut bit = 0;
+	while bit < 8 {
+	    crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+	    bit += 1;
+	}
+	table[index] = crc;
+	index += 1;
+    }
+    table
+}
+
+fn write_u16(chunk: &mut Chunk, offset: usize, value: usize) -> Result<(), String> {
+    let value = u16::try_from(value).map_err(|_| "Too many methods or variables.".to_string())?;
+    chunk.code[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+// Names numbered in order of first use.
+#[derive(Default)]
+struct Names {
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl Names {
+    fn intern(&mut self, name: &str) -> usize {
+	if let Some(&index) = self.indices.get(name) {
+	    return index;
+	}
+	self.names.push(name.to_string());
+	self.indices.insert(name.to_string(), self.names.len() - 1);
+	self.names.len() - 1
+    }
+}
+
+// Writes the parts of the format, which snapshots share.
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn varint(&mut self, mut value: u64) {
+	loop {
+	    let byte = (value & 0x7f) as u8;
+	    value >>= 7;
+	    if value == 0 {
+		self.bytes.push(byte);
+		return;
+	    }
+	    self.bytes.push(byte | 0x80);
+	}
+    }
+
+    // Appends the checksum of everything written so far.
+    pub(crate) fn seal(&mut self) {
+	let checksum = checksum(&self.bytes);
+	self.bytes.extend_from_slice(&checksum.to_le_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+	self.varint(value.len() as u64);
+	self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub(crate) fn strings(&mut self, values: &[String]) {
+	self.varint(values.len() as u64);
+	for value in values {
+	    self.string(value);
+	}
+    }
+
+    fn function(&mut self, function: &FnProto) {
+	self.string(&function.name);
+	self.varint(function.arity as u64);
+	self.varint(function.num_upvalues as u64);
+	let chunk = &function.chunk;
+	self.varint(chunk.code.len() as u64);
+	self.bytes.extend_from_slice(&chunk.code);
+	self.lines(&chunk.lines);
+	self.locals(&chunk.locals);
+
+	self.varint(chunk.constants.len() as u64);
+	for constant in &chunk.constants {
+	    match constant {
+		Constant::Num(n) => {
+		    self.bytes.push(TAG_NUM);
+		    self.f64(*n);
+		}
+		Constant::String(s) => {
+		    self.bytes.push(TAG_STRING);
+		    self.string(s);
+		}
+		Constant::Fn(nested) => {
+		    self.bytes.push(TAG_FN);
+		    self.function(nested);
+		}
+	    }
+	}
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+	self.bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    // A line for each byte of code, as runs of equal lines.
+    pub(crate) fn lines(&mut self, lines: &[u32]) {
+	let mut runs: Vec<(u32, usize)> = Vec::new();
+	for &line in lines {
+	    match runs.last_mut() {
+		Some((last, count)) if *last == line => *count += 1,
+		_ => runs.push((line, 1)),
+	    }
+	}
+	self.varint(runs.len() as u64);
+	for (line, count) in runs {
+	    self.varint(line as u64);
+	    self.varint(count as u64);
+	}
+    }
+
+    pub(crate) fn locals(&mut self, locals: &[LocalName]) {
+	self.varint(locals.len() as u64);
+	for local in locals {
+	    self.string(&local.name);
+	    self.varint(local.slot as u64);
+	    self.varint(local.start as u64);
+	    self.varint(local.end as u64);
+	}
+    }
+}
+
+// Reads what `Writer` wrote, with errors naming `what` is being read.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) position: usize,
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], what: &'static str) -> Reader<'a> {
+	Reader {
+	    bytes,
+	    position: 0,
+	    what,
+	}
+    }
+
+    // Checks the checksum `Writer::seal` ended the bytes with, then leaves
+    // it out of what is left to read.
+    pub(crate) fn unseal(&mut self) -> Result<(), String> {
+	let corrupt = format!("The {} is corrupt: its checksum doesn't match.", self.what);
+	let end = match self.bytes.len().checked_sub(4) {
+	    Some(end) if end >= self.position => end,
+	    _ => return Err(corrupt),
+	};
+	let (sealed, sum) = self.bytes.split_at(end);
+	if checksum(sealed).to_le_bytes() != sum {
+	    return Err(corrupt);
+	}
+	self.bytes = sealed;
+	Ok(())
+    }
+
+    // An error about something invalid, such as "string".
+    pub(crate) fn invalid(&self, thing: &str) -> String {
+	format!("Invalid {} in {}.", thing, self.what)
+    }
+
+    pub(crate) fn is_at_end(&self) -> bool {
+	self.position == self.bytes.len()
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, String> {
+	let byte = *self
+	    .bytes
+	    .get(self.position)
+	    .ok_or_else(|| format!("Unexpected end of {}.", self.what))?;
+	self.position += 1;
+	Ok(byte)
+    }
+
+    pub(crate) fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+	if self.bytes.len() - self.position < count {
+	    return Err(format!("Unexpected end of {}.", self.what));
+	}
+	let taken = &self.bytes[self.position..self.position + count];
+	self.position += count;
+	Ok(taken)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, String> {
+	let mut value = 0u64;
+	for shift in (0..64).step_by(7) {
+	    let byte = self.byte()?;
+	    value |= u64::from(byte & 0x7f) << shift;
+	    if byte & 0x80 == 0 {
+		return Ok(value);
+	    }
+	}
+	Err(self.invalid("integer"))
+    }
+
+    pub(crate) fn number(&mut self) -> Result<usize, String> {
+	let value = self.varint()?;
+	usize::try_from(value).map_err(|_| self.invalid("integer"))
+    }
+
+    // The length of something that takes at least a byte per element, so
+    // it can't be more than the bytes left.
+    pub(crate) fn length(&mut self) -> Result<usize, String> {
+	let length = self.varint()?;
+	match usize::try_from(length) {
+	    Ok(length) if length <= self.bytes.len() - self.position => Ok(length),
+	    _ => Err(self.invalid("length")),
+	}
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+	let length = self.length()?;
+	let bytes = self.take(length)?.to_vec();
+	String::from_utf8(bytes).map_err(|_| self.invalid("string"))
+    }
+
+    pub(crate) fn strings(&mut self) -> Result<Vec<String>, String> {
+	let count = self.length()?;
+	(0..count).map(|_| self.string()).collect()
+    }
+
+    fn function(&mut self, depth: usize) -> Result<FnProto, String> {
+	if depth > MAX_NESTING {
+	    return Err("Functions are nested too deeply.".to_string());
+	}
+	let name = self.string()?;
+	let arity = self.number()?;
+	let num_upvalues = self.number()?;
+	let code_length = self.length()?;
+	let code = self.take(code_length)?.to_vec();
+	let lines = self.lines(&name, code.len())?;
+	let locals = self.locals()?;
+
+	let mut constants = Vec::new();
+	for _ in 0..self.length()? {
+	    let constant = match self.byte()? {
+		TAG_NUM => Constant::Num(self.f64()?),
+		TAG_STRING => Constant::String(self.string()?),
+		TAG_FN => Constant::Fn(Box::new(self.function(depth + 1)?)),
+		tag => return Err(format!("Invalid constant tag {} in {}.", tag, self.what)),
+	    };
+	    constants.push(constant);
+	}
+	Ok(FnProto {
+	    name,
+	    arity,
+	    num_upvalues,
+	    chunk: Chunk {
+		code,
+		constants,
+		lines,
+		locals,
+	    },
+	})
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, String> {
+	let mut bits = [0; 8];
+	bits.copy_from_slice(self.take(8)?);
+	Ok(f64::from_bits(u64::from_le_bytes(bits)))
+    }
+
+    // A line for each of the `count` bytes of the code of `function`.
+    pub(crate) fn lines(&mut self, function: &str, count: usize) -> Result<Vec<u32>, String> {
+	let mut lines = Vec::with_capacity(count);
+	for _ in 0..self.length()? {
+	    let line = self.varint()?;
+	    let line = u32::try_from(line).map_err(|_| self.invalid("line"))?;
+	    let run = self.number()?;
+	    if run > count - lines.len() {
+		return Err(format!("Function '{}' doesn't have a line for each byte.", function));
+	    }
+	    lines.resize(lines.len() + run, line);
+	}
+	Ok(lines)
+    }
+
+    pub(crate) fn locals(&mut self) -> Result<Vec<LocalName>, String> {
+	let mut locals = Vec::new();
+	for _ in 0..self.length()? {
+	    locals.push(LocalName {
+		name: self.string()?,
+		slot: self.number()?,
+		start: self.number()?,
+		end: self.number()?,
+	    });
+	}
+	Ok(locals)
+    }
+}
@@ -0,0 +1,561 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::compiler::{ModuleScope, SymbolTable};
+
+/// The bytecode instruction set, mirroring wren_c's `wren_opcodes.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Code {
+    /// Load the constant at index [arg].
+    Constant,
+    /// Push null onto the stack.
+    Null,
+    /// Push false onto the stack.
+    False,
+    /// Push true onto the stack.
+    True,
+    /// Push the value in local slot 0 (`this` or the function itself).
+    LoadLocal0,
+    /// Push the value in local slot 1.
+    LoadLocal1,
+    /// Push the value in local slot 2.
+    LoadLocal2,
+    /// Push the value in local slot 3.
+    LoadLocal3,
+    /// Push the value in local slot 4.
+    LoadLocal4,
+    /// Push the value in local slot 5.
+    LoadLocal5,
+    /// Push the value in local slot 6.
+    LoadLocal6,
+    /// Push the value in local slot 7.
+    LoadLocal7,
+    /// Push the value in local slot 8.
+    LoadLocal8,
+    /// Push the value in local slot [arg].
+    LoadLocal,
+    /// Store the top of stack in local slot [arg]. Does not pop it.
+    StoreLocal,
+    /// Push the value in upvalue [arg].
+    LoadUpvalue,
+    /// Store the top of stack in upvalue [arg]. Does not pop it.
+    StoreUpvalue,
+    /// Push the value of the module-level variable in slot [arg].
+    LoadModuleVar,
+    /// Store the top of stack in module-level variable slot [arg]. Does not pop it.
+    StoreModuleVar,
+    /// Push the value of field [arg] of the receiver in slot 0.
+    LoadFieldThis,
+    /// Store the top of stack in field [arg] of the receiver in slot 0. Does not pop it.
+    StoreFieldThis,
+    /// Pop an instance and push the value of its field [arg].
+    LoadField,
+    /// Pop an instance and store the new top of stack in its field [arg].
+    StoreField,
+    /// Pop and discard the top of stack.
+    Pop,
+    /// Invoke method [arg] with no arguments, just the receiver.
+    Call0,
+    /// Invoke method [arg] with 1 argument.
+    Call1,
+    /// Invoke method [arg] with 2 arguments.
+    Call2,
+    /// Invoke method [arg] with 3 arguments.
+    Call3,
+    /// Invoke method [arg] with 4 arguments.
+    Call4,
+    /// Invoke method [arg] with 5 arguments.
+    Call5,
+    /// Invoke method [arg] with 6 arguments.
+    Call6,
+    /// Invoke method [arg] with 7 arguments.
+    Call7,
+    /// Invoke method [arg] with 8 arguments.
+    Call8,
+    /// Invoke method [arg] with 9 arguments.
+    Call9,
+    /// Invoke method [arg] with 10 arguments.
+    Call10,
+    /// Invoke method [arg] with 11 arguments.
+    Call11,
+    /// Invoke method [arg] with 12 arguments.
+    Call12,
+    /// Invoke method [arg] with 13 arguments.
+    Call13,
+    /// Invoke method [arg] with 14 arguments.
+    Call14,
+    /// Invoke method [arg] with 15 arguments.
+    Call15,
+    /// Invoke method [arg] with 16 arguments.
+    Call16,
+    /// Invoke superclass method [arg] with 0 arguments.
+    Super0,
+    /// Invoke superclass method [arg] with 1 argument.
+    Super1,
+    /// Invoke superclass method [arg] with 2 arguments.
+    Super2,
+    /// Invoke superclass method [arg] with 3 arguments.
+    Super3,
+    /// Invoke superclass method [arg] with 4 arguments.
+    Super4,
+    /// Invoke superclass method [arg] with 5 arguments.
+    Super5,
+    /// Invoke superclass method [arg] with 6 arguments.
+    Super6,
+    /// Invoke superclass method [arg] with 7 arguments.
+    Super7,
+    /// Invoke superclass method [arg] with 8 arguments.
+    Super8,
+    /// Invoke superclass method [arg] with 9 arguments.
+    Super9,
+    /// Invoke superclass method [arg] with 10 arguments.
+    Super10,
+    /// Invoke superclass method [arg] with 11 arguments.
+    Super11,
+    /// Invoke superclass method [arg] with 12 arguments.
+    Super12,
+    /// Invoke superclass method [arg] with 13 arguments.
+    Super13,
+    /// Invoke superclass method [arg] with 14 arguments.
+    Super14,
+    /// Invoke superclass method [arg] with 15 arguments.
+    Super15,
+    /// Invoke superclass method [arg] with 16 arguments.
+    Super16,
+    /// Jump the instruction pointer [arg] forward.
+    Jump,
+    /// Jump the instruction pointer [arg] backward.
+    Loop,
+    /// Pop and if not truthy then jump the instruction pointer [arg] forward.
+    JumpIf,
+    /// If the top of the stack is false, jump [arg] forward. Otherwise, pop and continue.
+    And,
+    /// If the top of the stack is non-false, jump [arg] forward. Otherwise, pop and continue.
+    Or,
+    /// Close the upvalue for the local on the top of the stack, then pop it.
+    CloseUpvalue,
+    /// Exit from the current function and return the value on the top of the stack.
+    Return,
+    /// Create a closure for the function stored at [arg] in the constant table. Followed by a pair of bytes for each upvalue: whether it captures a local (1) or an upvalue (0), and its index.
+    Closure,
+    /// Create a new instance of the class in slot 0 and store it there.
+    Construct,
+    /// Create a new instance of the foreign class in slot 0 and store it there.
+    ForeignConstruct,
+    /// Create a class. Top of stack is the superclass, below that the name. [arg] is the number of fields.
+    Class,
+    /// Ends a class. Atm the stack contains the class and the ClassAttributes (or null).
+    EndClass,
+    /// Create a foreign class. Top of stack is the superclass, below that the name.
+    ForeignClass,
+    /// Define an instance method [arg]. Top of stack is the class, below it the method's closure.
+    MethodInstance,
+    /// Define a static method [arg]. Top of stack is the class, below it the method's closure.
+    MethodStatic,
+    /// Finish the current module body and push null.
+    EndModule,
+    /// Load the module named by the string constant [arg], running it if needed, and push its result.
+    ImportModule,
+    /// Push the variable named by the string constant [arg] from the most recently imported module.
+    ImportVariable,
+    /// Marks the end of the bytecode. Never executed.
+    End,
+
+    // Superinstructions, which the VM substitutes for common pairs of
+    // instructions as it loads a function, with the `superinstructions`
+    // feature. Compiled and serialized code never contains them.
+    /// `CONSTANT` [arg] followed by a `CALL_1`, which is left in place.
+    ConstantCall1,
+    /// `LOAD_LOCAL_n` followed by `CALL_1`: the local's slot, then the
+    /// method.
+    LoadLocalCall1,
+    /// `CALL_1` [arg] followed by a `JUMP_IF`, which is left in place.
+    Call1JumpIf,
+}
+
+pub(crate) const CODES: [Code; 80] = [
+    Code::Constant,
+    Code::Null,
+    Code::False,
+    Code::True,
+    Code::LoadLocal0,
+    Code::LoadLocal1,
+    Code::LoadLocal2,
+    Code::LoadLocal3,
+    Code::LoadLocal4,
+    Code::LoadLocal5,
+    Code::LoadLocal6,
+    Code::LoadLocal7,
+    Code::LoadLocal8,
+    Code::LoadLocal,
+    Code::StoreLocal,
+    Code::LoadUpvalue,
+    Code::StoreUpvalue,
+    Code::LoadModuleVar,
+    Code::StoreModuleVar,
+    Code::LoadFieldThis,
+    Code::StoreFieldThis,
+    Code::LoadField,
+    Code::StoreField,
+    Code::Pop,
+    Code::Call0,
+    Code::Call1,
+    Code::Call2,
+    Code::Call3,
+    Code::Call4,
+    Code::Call5,
+    Code::Call6,
+    Code::Call7,
+    Code::Call8,
+    Code::Call9,
+    Code::Call10,
+    Code::Call11,
+    Code::Call12,
+    Code::Call13,
+    Code::Call14,
+    Code::Call15,
+    Code::Call16,
+    Code::Super0,
+    Code::Super1,
+    Code::Super2,
+    Code::Super3,
+    Code::Super4,
+    Code::Super5,
+    Code::Super6,
+    Code::Super7,
+    Code::Super8,
+    Code::Super9,
+    Code::Super10,
+    Code::Super11,
+    Code::Super12,
+    Code::Super13,
+    Code::Super14,
+    Code::Super15,
+    Code::Super16,
+    Code::Jump,
+    Code::Loop,
+    Code::JumpIf,
+    Code::And,
+    Code::Or,
+    Code::CloseUpvalue,
+    Code::Return,
+    Code::Closure,
+    Code::Construct,
+    Code::ForeignConstruct,
+    Code::Class,
+    Code::EndClass,
+    Code::ForeignClass,
+    Code::MethodInstance,
+    Code::MethodStatic,
+    Code::EndModule,
+    Code::ImportModule,
+    Code::ImportVariable,
+    Code::End,
+    Code::ConstantCall1,
+    Code::LoadLocalCall1,
+    Code::Call1JumpIf,
+];
+impl Code {
+    pub fn from_u8(byte: u8) -> Option<Code> {
+	CODES.get(byte as usize).copied()
+    }
+
+    /// `CALL_n` for a call passing `arity` arguments.
+    pub fn call(arity: usize) -> Code {
+	CODES[Code::Call0 as usize + arity]
+    }
+
+    /// `SUPER_n` for a superclass call passing `arity` arguments.
+    pub fn super_call(arity: usize) -> Code {
+	CODES[Code::Super0 as usize + arity]
+    }
+
+    /// The argument count of a `CALL_n` or `SUPER_n` instruction.
+    pub fn arity(self) -> Option<usize> {
+	let code = self as usize;
+	if (Code::Call0 as usize..=Code::Call16 as usize).contains(&code) {
+	    Some(code - Code::Call0 as usize)
+	} else if (Code::Super0 as usize..=Code::Super16 as usize).contains(&code) {
+	    Some(code - Code::Super0 as usize)
+	} else {
+	    None
+	}
+    }
+
+    /// Whether this is one of the superinstructions only the VM uses.
+    pub fn is_superinstruction(self) -> bool {
+	self as u8 > Code::End as u8
+    }
+
+    /// The instruction's name as wren_c spells it, such as `LOAD_LOCAL_0`.
+    pub fn name(self) -> String {
+	let mut name = String::new();
+	let mut previous: Option<char> = None;
+	for c in format!("{:?}", self).chars() {
+	    let starts_word = match previous {
+		Some(p) => (c.is_ascii_uppercase() && !p.is_ascii_uppercase())
+		    || (c.is_ascii_digit() && !p.is_ascii_digit()),
+		None => false,
+	    };
+	    if starts_word {
+		name.push('_');
+	    }
+	    name.push(c.to_ascii_uppercase());
+	    previous = Some(c);
+	}
+	name
+    }
+
+    /// How many bytes of operands follow the instruction. `Closure` is
+    /// followed by two more bytes per upvalue on top of this.
+    pub fn operand_bytes(self) -> usize {
+	match self {
+	    Code::LoadLocal
+	    | Code::StoreLocal
+	    | Code::LoadUpvalue
+	    | Code::StoreUpvalue
+	    | Code::LoadFieldThis
+	    | Code::StoreFieldThis
+	    | Code::LoadField
+	    | Code::StoreField
+	    | Code::Class => 1,
+	    Code::Constant
+	    | Code::LoadModuleVar
+	    | Code::StoreModuleVar
+	    | Code::Jump
+	    | Code::Loop
+	    | Code::JumpIf
+	    | Code::And
+	    | Code::Or
+	    | Code::Closure
+	    | Code::MethodInstance
+	    | Code::MethodStatic
+	    | Code::ImportModule
+	    | Code::ImportVariable
+	    | Code::ConstantCall1
+	    | Code::Call1JumpIf => 2,
+	    Code::LoadLocalCall1 => 3,
+	    code if code.arity().is_some() => 2,
+	    _ => 0,
+	}
+    }
+}
+
+/// A compile-time constant. Constants are plain data; the VM turns them
+/// into heap values when it loads a compiled function.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Num(f64),
+    String(String),
+    Fn(Box<FnProto>),
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Constant) -> bool {
+	match (self, other) {
+	    // Compare bits so that 0 and -0, or two NaNs, are kept apart.
+	    (Constant::Num(a), Constant::Num(b)) => a.to_bits() == b.to_bits(),
+	    (Constant::String(a), Constant::String(b)) => a == b,
+	    _ => false,
+	}
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Constant::Num(n) => write!(f, "{}", n),
+	    Constant::String(s) => write!(f, "{:?}", s),
+	    Constant::Fn(proto) => write!(f, "<fn {}>", proto.name),
+	}
+    }
+}
+
+/// The name of a local variable, for debuggers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalName {
+    pub name: String,
+    pub slot: usize,
+    /// The range of code offsets where the variable is in scope.
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// The source line of each byte in `code`.
+    pub lines: Vec<u32>,
+    /// The named locals, in the order they were declared.
+    pub locals: Vec<LocalName>,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+	Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32) {
+	self.code.push(byte);
+	self.lines.push(line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+	u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// The bytecode as readable text, one instruction per line with its
+    /// offset, source line and operands. Methods and module variables are
+    /// shown by symbol number.
+    pub fn disassemble(&self) -> String {
+	let mut out = String::new();
+	self.write_disassembly(&mut out, None);
+	out
+    }
+
+    /// Like `disassemble`, but names methods from `methods` and module
+    /// variables from `variables`, the tables the chunk was compiled with.
+    pub fn disassemble_with(&self, methods: &SymbolTable, variables: &ModuleScope) -> String {
+	let mut out = String::new();
+	self.write_disassembly(&mut out, Some((methods, variables)));
+	out
+    }
+
+    fn write_disassembly(&self, out: &mut String, names: Option<(&SymbolTable, &ModuleScope)>) {
+	let mut offset = 0;
+	let mut last_line = None;
+	while offset < self.code.len() {
+	    let code = match Code::from_u8(self.code[offset]) {
+		Some(code) => code,
+		None => {
+		    let _ = writeln!(out, "{:04}  invalid opcode {}", offset, self.code[offset]);
+		    offset += 1;
+		    continue;
+		}
+	    };
+	    let line = self.lines[offset];
+	    let _ = write!(out, "{:04}  ", offset);
+	    if last_line == Some(line) {
+		out.push_str("   |  ");
+	    } else {
+		let _ = write!(out, "{:4}  ", line);
+		last_line = Some(line);
+	    }
+	    let operand = match code.operand_bytes() {
+		1 => self.code[offset + 1] as usize,
+		2 => self.read_u16(offset + 1) as usize,
+		_ => 0,
+	    };
+	    let next = offset + 1 + code.operand_bytes();
+	    let operands = match code {
+		Code::Constant
+		| Code::ImportModule
+		| Code::ImportVariable
+		| Code::Closure
+		| Code::ConstantCall1 => {
+		    match self.constants.get(operand) {
+			Some(constant) => format!("{:5} {}", operand, constant),
+			None => format!("{:5} <invalid constant>", operand),
+		    }
+		}
+		Code::LoadModuleVar | Code::StoreModuleVar => match names {
+		    Some((_, variables)) if operand < variables.len() => {
+			format!("{:5} {}", operand, variables.name(operand))
+		    }
+		    _ => format!("{:5}", operand),
+		},
+		Code::Jump | Code::JumpIf | Code::And | Code::Or => {
+		    format!("{:5} -> {:04}", operand, next + operand)
+		}
+		Code::Loop => format!("{:5} -> {:04}", operand, next.saturating_sub(operand)),
+		Code::LoadLocalCall1 => {
+		    let symbol = self.read_u16(offset + 2) as usize;
+		    match names {
+			Some((methods, _)) if symbol < methods.len() => {
+			    format!("{:5} {}", self.code[offset + 1], methods.name(symbol))
+			}
+			_ => format!("{:5} {}", self.code[offset + 1], symbol),
+		    }
+		}
+		code if code == Code::MethodInstance
+		    || code == Code::MethodStatic
+		    || code == Code::Call1JumpIf
+		    || code.arity().is_some() =>
+		{
+		    match names {
+			Some((methods, _)) if operand < methods.len() => {
+			    format!("{:5} {}", operand, methods.name(operand))
+			}
+			_ => format!("{:5}", operand),
+		    }
+		}
+		code if code.operand_bytes() > 0 => format!("{:5}", operand),
+		_ => String::new(),
+	    };
+	    if operands.is_empty() {
+		out.push_str(&code.name());
+	    } else {
+		let _ = write!(out, "{:<18}{}", code.name(), operands);
+	    }
+	    out.push('\n');
+	    offset = next;
+
+	    // Each upvalue a closure captures follows as a pair of bytes.
+	    if code == Code::Closure {
+		if let Some(Constant::Fn(proto)) = self.constants.get(operand) {
+		    for _ in 0..proto.num_upvalues {
+			let kind = if self.code[offset] != 0 { "local" } else { "upvalue" };
+			let _ = writeln!(out, "{:04}     |    {} {}", offset, kind, self.code[offset + 1]);
+			offset += 2;
+		    }
+		}
+	    }
+	    if code == Code::End {
+		break;
+	    }
+	}
+    }
+}
+
+/// A compiled function: the body of a module, method or block argument.
+#[derive(Debug, Clone, Default)]
+pub struct FnProto {
+    /// Debug name used in stack traces, such as `new(_)` or `(script)`.
+    pub name: String,
+    pub arity: usize,
+    pub num_upvalues: usize,
+    pub chunk: Chunk,
+}
+
+impl FnProto {
+    /// The disassembly of the function's chunk, followed by those of the
+    /// functions it defines, such as methods and block arguments.
+    pub fn disassemble(&self) -> String {
+	let mut out = String::new();
+	self.write_disassembly(&mut out, None);
+	out
+    }
+
+    /// Like `disassemble`, naming methods and module variables as
+    /// `Chunk::disassemble_with` does.
+    pub fn disassemble_with(&self, methods: &SymbolTable, variables: &ModuleScope) -> String {
+	let mut out = String::new();
+	self.write_disassembly(&mut out, Some((methods, variables)));
+	out
+    }
+
+    fn write_disassembly(&self, out: &mut String, names: Option<(&SymbolTable, &ModuleScope)>) {
+	let _ = writeln!(out, "== {} ==", self.name);
+	self.chunk.write_disassembly(out, names);
+	for constant in &self.chunk.constants {
+	    if let Constant::Fn(proto) = constant {
+		out.push('\n');
+		proto.write_disassembly(out, names);
+	    }
+	}
+    }
+}
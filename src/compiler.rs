@@ -0,0 +1,2271 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+
+use hashbrown::HashMap;
+
+use crate::ast::*;
+use crate::chunk::{Code, Constant, FnProto, LocalName};
+use crate::core::to_u32;
+use crate::lexer::{Span, Token};
+use crate::parser::{self, ParseError, ParseOptions, MAX_PARAMETERS};
+
+/// The maximum number of local variables that can be in scope at once.
+pub const MAX_LOCALS: usize = 256;
+
+/// The maximum number of variables a function can close over.
+pub const MAX_UPVALUES: usize = 256;
+
+/// The maximum number of fields a class can have, including inherited ones.
+pub const MAX_FIELDS: usize = 255;
+
+/// The maximum number of distinct constants one function may use.
+pub const MAX_CONSTANTS: usize = 1 << 16;
+
+/// The maximum number of module-level variables in one module.
+pub const MAX_MODULE_VARS: usize = 1 << 16;
+
+/// The maximum distance a single jump instruction can cover.
+pub const MAX_JUMP: usize = 1 << 16;
+
+/// The maximum length in bytes of a method's name.
+pub const MAX_METHOD_NAME: usize = 64;
+
+/// The maximum length in bytes of a variable's name.
+pub const MAX_VARIABLE_NAME: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Span,
+    /// Text of the offending token, "\n" for a newline, empty if there is
+    /// none to show.
+    pub token: String,
+    /// Whether the error was found at the end of the input.
+    pub at_end: bool,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "[line {}] Error", self.span.line)?;
+	parser::write_location(f, &self.token, self.at_end)?;
+	write!(f, ": {}", self.message)
+    }
+}
+
+impl error::Error for CompileError {}
+
+impl From<ParseError> for CompileError {
+    fn from(error: ParseError) -> CompileError {
+	CompileError {
+	    message: error.message,
+	    span: error.span,
+	    token: error.token,
+	    at_end: error.at_end,
+	}
+    }
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
+
+/// How hard the compiler works on the code it emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// 0 compiles everything as it is written. 1 and up fold expressions
+    /// made only of literals into their values, and leave out branches of
+    /// `if`, `while`, `?:`, `&&` and `||` that can never run, along with
+    /// the jumps around them. Code left out still has to compile.
+    pub opt_level: u8,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+	CompileOptions { opt_level: 1 }
+    }
+}
+
+/// An ordered set of names, each assigned the index it was added at.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+	SymbolTable::default()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+	self.indices.get(name).copied()
+    }
+
+    /// Adds `name`, which must not already be present.
+    pub fn add(&mut self, name: &str) -> usize {
+	let index = self.names.len();
+	self.names.push(name.to_string());
+	self.indices.insert(name.to_string(), index);
+	index
+    }
+
+    /// Returns the index of `name`, adding it if needed.
+    pub fn ensure(&mut self, name: &str) -> usize {
+	match self.find(name) {
+	    Some(index) => index,
+	    None => self.add(name),
+	}
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+	&self.names[index]
+    }
+
+    pub fn len(&self) -> usize {
+	self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.names.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+	self.names.iter().map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableError {
+    AlreadyDefined,
+    /// A lowercase variable was used earlier in the module than its
+    /// definition, first on `line`.
+    UsedBeforeDefinition { line: u32 },
+    TooMany,
+}
+
+/// The top-level variable names of a module. It outlives a single compile
+/// so later code in the same module (such as REPL lines) sees earlier
+/// definitions.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleScope {
+    names: SymbolTable,
+    // For variables referenced before any definition, where they were
+    // first used. Module-level names may be used before they are defined,
+    // as long as a definition appears somewhere in the module.
+    first_use: Vec<Option<Span>>,
+}
+
+impl ModuleScope {
+    pub fn new() -> ModuleScope {
+	ModuleScope::default()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+	self.names.find(name)
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+	self.names.name(index)
+    }
+
+    pub fn len(&self) -> usize {
+	self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.names.is_empty()
+    }
+
+    pub fn is_defined(&self, index: usize) -> bool {
+	self.first_use[index].is_none()
+    }
+
+    /// Defines `name`, fulfilling an earlier implicit declaration if there
+    /// was one.
+    pub fn define(&mut self, name: &str) -> Result<usize, VariableError> {
+	match self.names.find(name) {
+	    Some(index) => match self.first_use[index].take() {
+		None => Err(VariableError::AlreadyDefined),
+		Some(span) if parser::is_local_name(name) => {
+		    Err(VariableError::UsedBeforeDefinition { line: span.line })
+		}
+		Some(_) => Ok(index),
+	    },
+	    None => self.add(name, None),
+	}
+    }
+
+    /// Where the variable at `index` was first used, if it is used but not
+    /// defined yet.
+    pub fn first_use(&self, index: usize) -> Option<Span> {
+	self.first_use[index]
+    }
+
+    /// Declares `name` because it is used at `span` before any definition.
+    pub fn declare_implicit(&mut self, name: &str, span: Span) -> Result<usize, VariableError> {
+	match self.names.find(name) {
+	    Some(index) => Ok(index),
+	    None => self.add(name, Some(span)),
+	}
+    }
+
+    /// Names used but never defined, with where they were first used.
+    pub fn undefined(&self) -> impl Iterator<Item = (&str, Span)> {
+	self.names
+	    .iter()
+	    .zip(&self.first_use)
+	    .filter_map(|(name, span)| span.map(|span| (name, span)))
+    }
+
+    fn add(&mut self, name: &str, first_use: Option<Span>) -> Result<usize, VariableError> {
+	if self.names.len() == MAX_MODULE_VARS {
+	    return Err(VariableError::TooMany);
+	}
+	self.first_use.push(first_use);
+	Ok(self.names.add(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureKind {
+    /// A name followed by a (possibly empty) parenthesized parameter list.
+    Method,
+    /// Just a name. Also used for prefix operators.
+    Getter,
+    /// A name followed by "=".
+    Setter,
+    /// A square bracketed parameter list.
+    Subscript,
+    /// A square bracketed parameter list followed by "=".
+    SubscriptSetter,
+    /// A constructor initializer function. Has a distinct signature to
+    /// prevent it from being invoked directly outside of the constructor
+    /// on the metaclass.
+    Initializer,
+}
+
+/// A method signature: the name and shape of a method, which together
+/// identify it in a class's method table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub kind: SignatureKind,
+    /// The number of parameters, including the value of a setter.
+    pub arity: usize,
+}
+
+impl Signature {
+    pub fn new(name: &str, kind: SignatureKind, arity: usize) -> Signature {
+	Signature {
+	    name: name.to_string(),
+	    kind,
+	    arity,
+	}
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	fn params(f: &mut fmt::Formatter, arity: usize, open: char, close: char) -> fmt::Result {
+	    let underscores = vec!["_"; arity];
+	    write!(f, "{}{}{}", open, underscores.join(","), close)
+	}
+	match self.kind {
+	    SignatureKind::Method => {
+		f.write_str(&self.name)?;
+		params(f, self.arity, '(', ')')
+	    }
+	    SignatureKind::Getter => f.write_str(&self.name),
+	    SignatureKind::Setter => write!(f, "{}=(_)", self.name),
+	    SignatureKind::Subscript => params(f, self.arity, '[', ']'),
+	    SignatureKind::SubscriptSetter => {
+		params(f, self.arity - 1, '[', ']')?;
+		f.write_str("=(_)")
+	    }
+	    SignatureKind::Initializer => {
+		write!(f, "init {}", self.name)?;
+		params(f, self.arity, '(', ')')
+	    }
+	}
+    }
+}
+
+/// Why `Signature::parse` rejected a signature, and where in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureError {
+    pub message: String,
+    /// The column of the character at fault, counting from 1, or one past
+    /// the end if the signature stops short.
+    pub column: usize,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{} at column {}.", self.message, self.column)
+    }
+}
+
+impl error::Error for SignatureError {}
+
+// The operators a class can define, longest first so that each is matched
+// whole. Of these, "-" may also be a getter, and "!" and "~" only are.
+const OPERATORS: &[&str] = &[
+    "...", "..", "<<", ">>", "<=", ">=", "==", "!=", "+", "-", "*", "/", "%", "<", ">", "&", "|",
+    "^", "!", "~",
+];
+
+impl Signature {
+    /// Parses a signature written the way the VM stores it, such as
+    /// `"update(_,_)"`, `"name"`, `"name=(_)"`, `"[_,_]"`, `"[_]=(_)"`,
+    /// `"-(_)"` or `"init new(_)"`, checking that it is one a class could
+    /// define. The result writes back as the same string.
+    pub fn parse(signature: &str) -> Result<Signature, SignatureError> {
+	let mut parser = SignatureParser {
+	    chars: signature.chars().collect(),
+	    at: 0,
+	};
+	let parsed = parser.signature()?;
+	match parser.peek() {
+	    None => Ok(parsed),
+	    Some(ch) => Err(parser.error(format!("Unexpected '{}' after the signature", ch))),
+	}
+    }
+}
+
+struct SignatureParser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl SignatureParser {
+    fn peek(&self) -> Option<char> {
+	self.chars.get(self.at).copied()
+    }
+
+    fn error(&self, message: impl Into<String>) -> SignatureError {
+	SignatureError {
+	    message: message.into(),
+	    column: self.at + 1,
+	}
+    }
+
+    fn match_str(&mut self, text: &str) -> bool {
+	let len = text.chars().count();
+	let matches = self.chars.len() >= self.at + len
+	    && self.chars[self.at..self.at + len].iter().copied().eq(text.chars());
+	if matches {
+	    self.at += len;
+	}
+	matches
+    }
+
+    fn consume(&mut self, ch: char, message: &str) -> Result<(), SignatureError> {
+	if self.peek() == Some(ch) {
+	    self.at += 1;
+	    Ok(())
+	} else {
+	    Err(self.error(message))
+	}
+    }
+
+    fn signature(&mut self) -> Result<Signature, SignatureError> {
+	if self.match_str("init ") {
+	    let name = self.name()?;
+	    if self.peek() != Some('(') {
+		return Err(self.error("Expect '(' after an initializer's name"));
+	    }
+	    let arity = self.parameters(')')?;
+	    return Ok(Signature::new(&name, SignatureKind::Initializer, arity));
+	}
+	if self.peek() == Some('[') {
+	    let start = self.at;
+	    let arity = self.parameters(']')?;
+	    if arity == 0 {
+		self.at = start + 1;
+		return Err(self.error("Expect '_' for a subscript's parameter"));
+	    }
+	    if self.setter_value()? {
+		return Ok(Signature::new("[]", SignatureKind::SubscriptSetter, arity + 1));
+	    }
+	    return Ok(Signature::new("[]", SignatureKind::Subscript, arity));
+	}
+	if let Some(operator) = OPERATORS.iter().find(|operator| self.match_str(operator)) {
+	    return self.operator(operator);
+	}
+	let name = self.name()?;
+	if name == "is" {
+	    return self.operator("is");
+	}
+	if self.setter_value()? {
+	    return Ok(Signature::new(&name, SignatureKind::Setter, 1));
+	}
+	if self.peek() == Some('(') {
+	    let arity = self.parameters(')')?;
+	    return Ok(Signature::new(&name, SignatureKind::Method, arity));
+	}
+	Ok(Signature::new(&name, SignatureKind::Getter, 0))
+    }
+
+    // A name that could be written as a method's: an identifier, but not
+    // a reserved word.
+    fn name(&mut self) -> Result<String, SignatureError> {
+	let start = self.at;
+	if !self.peek().is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_') {
+	    return Err(self.error("Expect a method name"));
+	}
+	while self.peek().is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+	    self.at += 1;
+	}
+	let name: String = self.chars[start..self.at].iter().collect();
+	if name != "is" && Token::keyword(&name).is_some() {
+	    self.at = start;
+	    return Err(self.error(format!("'{}' is a reserved word", name)));
+	}
+	Ok(name)
+    }
+
+    // The rest of an operator's signature, after its name.
+    fn operator(&mut self, name: &str) -> Result<Signature, SignatureError> {
+	if self.peek() != Some('(') {
+	    if matches!(name, "-" | "!" | "~") {
+		return Ok(Signature::new(name, SignatureKind::Getter, 0));
+	    }
+	    return Err(self.error("Expect '(' after operator name"));
+	}
+	if matches!(name, "!" | "~") {
+	    return Err(self.error(format!("Operator '{}' takes no parameters", name)));
+	}
+	let start = self.at;
+	if self.parameters(')')? != 1 {
+	    self.at = start;
+	    return Err(self.error(format!("Operator '{}' takes one parameter", name)));
+	}
+	Ok(Signature::new(name, SignatureKind::Method, 1))
+    }
+
+    // Whether a setter's "=(_)" follows.
+    fn setter_value(&mut self) -> Result<bool, SignatureError> {
+	if !self.match_str("=") {
+	    return Ok(false);
+	}
+	self.consume('(', "Expect '(' after '='")?;
+	self.consume('_', "Expect '_' for the value a setter takes")?;
+	self.consume(')', "Expect ')' after a setter's parameter")?;
+	Ok(true)
+    }
+
+    // A list of "_" separated by commas between brackets, the opening one
+    // next, closed by `close`, returning how many there are.
+    fn parameters(&mut self, close: char) -> Result<usize, SignatureError> {
+	self.at += 1;
+	if self.peek() == Some(close) {
+	    self.at += 1;
+	    return Ok(0);
+	}
+	let mut arity = 0;
+	loop {
+	    if arity == MAX_PARAMETERS {
+		return Err(self.error(format!(
+		    "Methods cannot have more than {} parameters",
+		    MAX_PARAMETERS
+		)));
+	    }
+	    self.consume('_', "Expect '_' for a parameter")?;
+	    arity += 1;
+	    if self.peek() != Some(',') {
+		break;
+	    }
+	    self.at += 1;
+	}
+	self.consume(close, &format!("Expect ',' or '{}' after a parameter", close))?;
+	Ok(arity)
+    }
+}
+
+/// Parses and compiles `source` as the body of a module. Fails with every
+/// error in it, up to `parser::MAX_ERRORS`, as wren_c reports them: in the
+/// order they are found in the source, then each variable that is used but
+/// never defined.
+pub fn compile(
+    source: &str,
+    module: &mut ModuleScope,
+    methods: &mut SymbolTable,
+    options: &ParseOptions,
+    compile_options: &CompileOptions,
+) -> Result<FnProto, Vec<CompileError>> {
+    let (ast, errors) = parser::parse_recovering(source, options);
+    let mut compiler = Compiler::with_options(module, methods, compile_options);
+    // What parsed is compiled too, for the errors in it.
+    compiler.errors = errors.into_iter().map(CompileError::from).collect();
+    compiler.compile_module(&ast)
+}
+
+/// Parses and compiles `source`, which must be a single expression, as the
+/// body of a module that returns its value.
+pub fn compile_expression(
+    source: &str,
+    module: &mut ModuleScope,
+    methods: &mut SymbolTable,
+    options: &ParseOptions,
+    compile_options: &CompileOptions,
+) -> CompileResult<FnProto> {
+    let expr = parser::parse_expression_with(source, options)?;
+    Compiler::with_options(module, methods, compile_options).compile_expression(&expr)
+}
+
+struct Local {
+    name: String,
+    depth: i32,
+    // Whether a closure captures the local, so it must be closed over
+    // rather than popped when it goes out of scope.
+    is_captured: bool,
+    // Index of the local's entry in the chunk's named locals, unless it is
+    // hidden.
+    debug_name: Option<usize>,
+}
+
+// A variable captured from an enclosing function.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Upvalue {
+    // Whether it is a local of the immediately enclosing function, rather
+    // than one of its upvalues.
+    is_local: bool,
+    index: usize,
+}
+
+// Where a non-module variable lives.
+#[derive(Clone, Copy)]
+enum Resolved {
+    Local(usize),
+    Upvalue(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FnKind {
+    Module,
+    /// A block argument.
+    Block,
+    Method,
+    /// The instance side of a constructor, which always returns `this`.
+    Initializer,
+}
+
+// The class whose methods are being compiled.
+struct ClassInfo {
+    name: String,
+    is_foreign: bool,
+    // Index of the function the class is defined in, whose locals hold the
+    // class's static fields.
+    fn_index: usize,
+    // Instance fields used so far, in slot order.
+    fields: SymbolTable,
+    // Symbols of the methods defined so far, to catch duplicates.
+    methods: Vec<usize>,
+    static_methods: Vec<usize>,
+    in_static: bool,
+    // The method being compiled, which an unnamed `super` call invokes.
+    signature: Option<Signature>,
+}
+
+struct Loop {
+    // Where the loop's condition starts, which `continue` jumps back to.
+    start: usize,
+    // Depth of the scope enclosing the loop. Locals deeper than this are
+    // discarded when breaking out or continuing.
+    scope_depth: i32,
+    exit_jumps: Vec<usize>,
+}
+
+// The state of one function being compiled. Nested block arguments push
+// further states.
+struct FnState {
+    kind: FnKind,
+    proto: FnProto,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    // -1 for module level, where variables are module variables rather
+    // than locals.
+    scope_depth: i32,
+    loops: Vec<Loop>,
+    // Where each number and string constant is in the chunk, so that each
+    // is only added once without searching the constants.
+    constant_indices: HashMap<ConstantKey, u16>,
+}
+
+// What makes a constant the same as another, as `Constant`'s `PartialEq`
+// compares them. Functions are never the same.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Num(u64),
+    String(String),
+}
+
+impl ConstantKey {
+    fn of(constant: &Constant) -> Option<ConstantKey> {
+	match constant {
+	    Constant::Num(n) => Some(ConstantKey::Num(n.to_bits())),
+	    Constant::String(s) => Some(ConstantKey::String(s.clone())),
+	    Constant::Fn(_) => None,
+	}
+    }
+}
+
+impl FnState {
+    fn new(name: String, kind: FnKind) -> FnState {
+	// Slot zero holds the receiver in methods, where it is `this`, and
+	// the function being called otherwise, which can't be referenced.
+	let mut proto = FnProto {
+	    name,
+	    ..FnProto::default()
+	};
+	let reserved = match kind {
+	    FnKind::Method | FnKind::Initializer => {
+		proto.chunk.locals.push(LocalName {
+		    name: "this".to_string(),
+		    slot: 0,
+		    start: 0,
+		    end: 0,
+		});
+		Local {
+		    name: "this".to_string(),
+		    depth: -1,
+		    is_captured: false,
+		    debug_name: Some(0),
+		}
+	    }
+	    FnKind::Module | FnKind::Block => Local {
+		name: String::new(),
+		depth: -1,
+		is_captured: false,
+		debug_name: None,
+	    },
+	};
+	FnState {
+	    kind,
+	    proto,
+	    locals: vec![reserved],
+	    upvalues: Vec::new(),
+	    scope_depth: if kind == FnKind::Module { -1 } else { 0 },
+	    loops: Vec::new(),
+	    constant_indices: HashMap::new(),
+	}
+    }
+
+    // Ends the scope of the named locals from `first` on at the current
+    // end of the code.
+    fn end_locals(&mut self, first: usize) {
+	let end = self.proto.chunk.code.len();
+	for local in &self.locals[first..] {
+	    if let Some(index) = local.debug_name {
+		self.proto.chunk.locals[index].end = end;
+	    }
+	}
+    }
+
+    fn finish(mut self) -> FnProto {
+	self.end_locals(0);
+	self.proto
+    }
+}
+
+/// Lowers a parsed module to bytecode.
+pub struct Compiler<'a> {
+    module: &'a mut ModuleScope,
+    methods: &'a mut SymbolTable,
+    fns: Vec<FnState>,
+    classes: Vec<ClassInfo>,
+    line: u32,
+    options: CompileOptions,
+    // The errors found so far, including the parser's. Compiling goes on
+    // past each to find the rest.
+    errors: Vec<CompileError>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(module: &'a mut ModuleScope, methods: &'a mut SymbolTable) -> Compiler<'a> {
+	Compiler::with_options(module, methods, &CompileOptions::default())
+    }
+
+    pub fn with_options(
+	module: &'a mut ModuleScope,
+	methods: &'a mut SymbolTable,
+	options: &CompileOptions,
+    ) -> Compiler<'a> {
+	Compiler {
+	    module,
+	    methods,
+	    fns: Vec::new(),
+	    classes: Vec::new(),
+	    line: 1,
+	    options: *options,
+	    errors: Vec::new(),
+	}
+    }
+
+    /// Compiles a module, going on past each statement with an error to
+    /// find the rest, as wren_c does. Fails with the errors in the order
+    /// they are found in the source, then each variable that is used but
+    /// never defined, up to `parser::MAX_ERRORS`.
+    pub fn compile_module(mut self, ast: &Module) -> Result<FnProto, Vec<CompileError>> {
+	self.fns.push(FnState::new("(script)".to_string(), FnKind::Module));
+	self.statements(&ast.statements);
+	self.emit_op(Code::EndModule);
+	self.emit_op(Code::Return);
+	self.finish_module()
+    }
+
+    /// Compiles an expression as a module body that returns its value
+    /// rather than ending the module, as wren_c does for the REPL.
+    pub fn compile_expression(mut self, expr: &Expr) -> CompileResult<FnProto> {
+	self.fns.push(FnState::new("(script)".to_string(), FnKind::Module));
+	self.expression(expr)?;
+	self.emit_op(Code::Return);
+	self.finish_module().map_err(|mut errors| errors.remove(0))
+    }
+
+    fn finish_module(mut self) -> Result<FnProto, Vec<CompileError>> {
+	// Errors from the parser are merged in where they were found.
+	self.errors.sort_by_key(|error| error.span.start);
+	// A local's initializer using its own name has already been reported.
+	let undefined: Vec<CompileError> = self
+	    .module
+	    .undefined()
+	    .map(|(name, span)| self.undefined_variable(name, span))
+	    .filter(|error| !self.errors.contains(error))
+	    .collect();
+	self.errors.extend(undefined);
+	if !self.errors.is_empty() {
+	    self.errors.truncate(parser::MAX_ERRORS);
+	    return Err(self.errors);
+	}
+	Ok(self.fns.pop().expect("module function").finish())
+    }
+
+    fn undefined_variable(&self, name: &str, span: Span) -> CompileError {
+	self.error_at_token(span, name, "Variable is used but not defined.")
+    }
+
+    // Emitting code.
+
+    fn current(&mut self) -> &mut FnState {
+	self.fns.last_mut().expect("a function is being compiled")
+    }
+
+    fn code_len(&mut self) -> usize {
+	self.current().proto.chunk.code.len()
+    }
+
+    fn emit(&mut self, byte: u8) {
+	let line = self.line;
+	self.current().proto.chunk.write(byte, line);
+    }
+
+    fn emit_op(&mut self, code: Code) {
+	self.emit(code as u8);
+    }
+
+    fn emit_short(&mut self, arg: u16) {
+	for byte in arg.to_be_bytes().iter() {
+	    self.emit(*byte);
+	}
+    }
+
+    fn emit_byte_arg(&mut self, code: Code, arg: u8) {
+	self.emit_op(code);
+	self.emit(arg);
+    }
+
+    fn emit_short_arg(&mut self, code: Code, arg: u16) {
+	self.emit_op(code);
+	self.emit_short(arg);
+    }
+
+    // Emits a jump with a placeholder offset, returning where to patch it.
+    fn emit_jump(&mut self, code: Code) -> usize {
+	self.emit_short_arg(code, 0xffff);
+	self.code_len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, span: Span) -> CompileResult<()> {
+	let jump = self.code_len() - offset - 2;
+	if jump >= MAX_JUMP {
+	    return Err(self.error(span, "Too much code to jump over."));
+	}
+	let bytes = (jump as u16).to_be_bytes();
+	let code = &mut self.current().proto.chunk.code;
+	code[offset] = bytes[0];
+	code[offset + 1] = bytes[1];
+	Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize, span: Span) -> CompileResult<()> {
+	// The offset includes the two operand bytes of the loop itself.
+	let offset = self.code_len() - start + 3;
+	if offset >= MAX_JUMP {
+	    return Err(self.error(span, "Loop body too large."));
+	}
+	self.emit_short_arg(Code::Loop, offset as u16);
+	Ok(())
+    }
+
+    // Compiles code that can never run, for any errors in it and the module
+    // variables it uses, then throws the code away.
+    fn dead_code(
+	&mut self,
+	compile: impl FnOnce(&mut Compiler<'a>) -> CompileResult<()>,
+    ) -> CompileResult<()> {
+	let chunk = &self.current().proto.chunk;
+	let (code_len, num_locals) = (chunk.code.len(), chunk.locals.len());
+	let num_constants = chunk.constants.len();
+	compile(self)?;
+	let state = self.current();
+	state.proto.chunk.code.truncate(code_len);
+	state.proto.chunk.lines.truncate(code_len);
+	state.proto.chunk.locals.truncate(num_locals);
+	// Constants are shared, so any added since were only used here.
+	for constant in state.proto.chunk.constants.drain(num_constants..) {
+	    if let Some(key) = ConstantKey::of(&constant) {
+		state.constant_indices.remove(&key);
+	    }
+	}
+	// Forget the breaks that were thrown away.
+	for enclosing in &mut state.loops {
+	    enclosing.exit_jumps.retain(|&jump| jump < code_len);
+	}
+	Ok(())
+    }
+
+    // Whether `condition` is always true or always false, if the compiler
+    // is looking for branches that can't run.
+    fn constant_condition(&self, condition: &Expr) -> Option<bool> {
+	match self.options.opt_level {
+	    0 => None,
+	    _ => fold(condition).map(|value| !is_falsy(&value)),
+	}
+    }
+
+    fn add_constant(&mut self, constant: Constant, span: Span) -> CompileResult<u16> {
+	let key = ConstantKey::of(&constant);
+	let state = self.current();
+	if let Some(&index) = key.as_ref().and_then(|key| state.constant_indices.get(key)) {
+	    return Ok(index);
+	}
+	if state.proto.chunk.constants.len() == MAX_CONSTANTS {
+	    return Err(self.error(
+		span,
+		format!("A function may only contain {} unique constants.", MAX_CONSTANTS),
+	    ));
+	}
+	let state = self.current();
+	let index = state.proto.chunk.constants.len() as u16;
+	state.proto.chunk.constants.push(constant);
+	if let Some(key) = key {
+	    state.constant_indices.insert(key, index);
+	}
+	Ok(index)
+    }
+
+    fn emit_constant(&mut self, constant: Constant, span: Span) -> CompileResult<()> {
+	let index = self.add_constant(constant, span)?;
+	self.emit_short_arg(Code::Constant, index);
+	Ok(())
+    }
+
+    fn call_signature(&mut self, signature: &Signature) {
+	self.invoke(Code::Call0, signature);
+    }
+
+    // Emits `CALL_n` or `SUPER_n`, as chosen by `instruction`, for
+    // `signature`.
+    fn invoke(&mut self, instruction: Code, signature: &Signature) {
+	let symbol = self.methods.ensure(&signature.to_string());
+	let code = match instruction {
+	    Code::Super0 => Code::super_call(signature.arity),
+	    _ => Code::call(signature.arity),
+	};
+	self.emit_short_arg(code, symbol as u16);
+    }
+
+    fn call_method(&mut self, name: &str, kind: SignatureKind, arity: usize) {
+	self.call_signature(&Signature::new(name, kind, arity));
+    }
+
+    fn error(&self, span: Span, message: impl Into<String>) -> CompileError {
+	self.error_at_token(span, "", message)
+    }
+
+    fn error_at(&self, ident: &Ident, message: impl Into<String>) -> CompileError {
+	self.error_at_token(ident.span, &ident.name, message)
+    }
+
+    fn error_at_token(&self, span: Span, token: &str, message: impl Into<String>) -> CompileError {
+	CompileError {
+	    message: message.into(),
+	    span,
+	    token: token.to_string(),
+	    at_end: false,
+	}
+    }
+
+    // Variables and scopes.
+
+    fn push_scope(&mut self) {
+	self.current().scope_depth += 1;
+    }
+
+    fn pop_scope(&mut self) {
+	let depth = self.current().scope_depth;
+	let popped = self.discard_locals(depth);
+	let state = self.current();
+	let remaining = state.locals.len() - popped;
+	state.end_locals(remaining);
+	state.locals.truncate(remaining);
+	state.scope_depth -= 1;
+    }
+
+    // Emits code to pop the locals declared at `depth` or deeper, without
+    // forgetting them. Returns how many there were.
+    fn discard_locals(&mut self, depth: i32) -> usize {
+	let discarded: Vec<bool> = self
+	    .current()
+	    .locals
+	    .iter()
+	    .rev()
+	    .take_while(|local| local.depth >= depth)
+	    .map(|local| local.is_captured)
+	    .collect();
+	for &is_captured in &discarded {
+	    // A captured local moves into its upvalue as it leaves the stack.
+	    self.emit_op(if is_captured {
+		Code::CloseUpvalue
+	    } else {
+		Code::Pop
+	    });
+	}
+	discarded.len()
+    }
+
+    // Declares a variable in the current scope, returning its module
+    // variable index or local slot.
+    fn declare_variable(&mut self, name: &Ident) -> CompileResult<usize> {
+	self.declare_variable_at(name, name)
+    }
+
+    // As `declare_variable`, reporting errors at the token `at`.
+    fn declare_variable_at(&mut self, name: &Ident, at: &Ident) -> CompileResult<usize> {
+	// As in wren_c, the variable is declared anyway.
+	if name.name.len() > MAX_VARIABLE_NAME {
+	    let message =
+		format!("Variable name cannot be longer than {} characters.", MAX_VARIABLE_NAME);
+	    let error = self.error_at(at, message);
+	    self.errors.push(error);
+	}
+	self.add_variable(name, at)
+    }
+
+    // Declares a variable without checking the length of its name, which
+    // wren_c doesn't for a `for` loop's.
+    fn add_variable(&mut self, name: &Ident, at: &Ident) -> CompileResult<usize> {
+	if self.current().scope_depth == -1 {
+	    return match self.module.define(&name.name) {
+		Ok(index) => Ok(index),
+		Err(VariableError::AlreadyDefined) => {
+		    Err(self.error_at(at, "Module variable is already defined."))
+		}
+		Err(VariableError::UsedBeforeDefinition { line }) => {
+		    Err(self.used_before_definition(name, at, line))
+		}
+		Err(VariableError::TooMany) => {
+		    Err(self.error_at(at, "Too many module variables defined."))
+		}
+	    };
+	}
+
+	let state = self.current();
+	let depth = state.scope_depth;
+	let duplicate = state
+	    .locals
+	    .iter()
+	    .rev()
+	    .take_while(|local| local.depth >= depth)
+	    .any(|local| local.name == name.name);
+	if duplicate {
+	    return Err(self.error_at(at, "Variable is already declared in this scope."));
+	}
+	if state.locals.len() == MAX_LOCALS {
+	    return Err(self.error_at(
+		at,
+		format!("Cannot declare more than {} variables in one scope.", MAX_LOCALS),
+	    ));
+	}
+	let slot = state.locals.len();
+	// Hidden variables have a space in their name.
+	let debug_name = if name.name.contains(' ') {
+	    None
+	} else {
+	    let chunk = &mut state.proto.chunk;
+	    chunk.locals.push(LocalName {
+		name: name.name.clone(),
+		slot,
+		start: chunk.code.len(),
+		end: chunk.code.len(),
+	    });
+	    Some(chunk.locals.len() - 1)
+	};
+	state.locals.push(Local {
+	    name: name.name.clone(),
+	    depth,
+	    is_captured: false,
+	    debug_name,
+	});
+	Ok(slot)
+    }
+
+    fn used_before_definition(&self, name: &Ident, at: &Ident, line: u32) -> CompileError {
+	self.error_at(
+	    at,
+	    format!(
+		"Variable '{}' referenced before this definition (first use at line {}).",
+		name.name, line
+	    ),
+	)
+    }
+
+    // A local can't be read in its own initializer, where its name falls
+    // through to a module variable. A lowercase one can never be defined
+    // after that use, so rather than wait for the end of the module to
+    // report it as undefined, reports it the same way now, at the use the
+    // initializer implicitly declared it with.
+    fn check_initializer(&self, name: &Ident, module_len: usize) -> CompileResult<()> {
+	if !parser::is_local_name(&name.name) {
+	    return Ok(());
+	}
+	let first_use = self
+	    .module
+	    .find(&name.name)
+	    .filter(|&index| index >= module_len)
+	    .and_then(|index| self.module.first_use(index));
+	match first_use {
+	    Some(span) => Err(self.undefined_variable(&name.name, span)),
+	    None => Ok(()),
+	}
+    }
+
+    // Stores the value on top of the stack in a just-declared variable.
+    // Locals already live in their stack slot.
+    fn define_variable(&mut self, index: usize) {
+	if self.current().scope_depth >= 0 {
+	    return;
+	}
+	self.emit_short_arg(Code::StoreModuleVar, index as u16);
+	self.emit_op(Code::Pop);
+    }
+
+    fn resolve_local(&self, fn_index: usize, name: &str) -> Option<usize> {
+	self.fns[fn_index]
+	    .locals
+	    .iter()
+	    .rposition(|local| !local.name.is_empty() && local.name == name)
+    }
+
+    fn load_local(&mut self, slot: usize) {
+	if slot <= 8 {
+	    self.emit_op(Code::from_u8(Code::LoadLocal0 as u8 + slot as u8).expect("LOAD_LOCAL_n"));
+	} else {
+	    self.emit_byte_arg(Code::LoadLocal, slot as u8);
+	}
+    }
+
+    fn module_variable(&mut self, name: &str, span: Span) -> CompileResult<u16> {
+	match self.module.declare_implicit(name, span) {
+	    Ok(index) => Ok(index as u16),
+	    Err(_) => Err(self.error(span, "Too many module variables defined.")),
+	}
+    }
+
+    // Finds `name` among the upvalues of the function at `fn_index`,
+    // capturing it from the enclosing functions if it is one of their
+    // locals.
+    fn resolve_upvalue(
+	&mut self,
+	fn_index: usize,
+	name: &str,
+	span: Span,
+    ) -> CompileResult<Option<usize>> {
+	// The module function has nothing to close over.
+	if fn_index == 0 {
+	    return Ok(None);
+	}
+	let enclosing = fn_index - 1;
+	if let Some(slot) = self.resolve_local(enclosing, name) {
+	    self.fns[enclosing].locals[slot].is_captured = true;
+	    return self.add_upvalue(fn_index, true, slot, span).map(Some);
+	}
+	match self.resolve_upvalue(enclosing, name, span)? {
+	    Some(index) => self.add_upvalue(fn_index, false, index, span).map(Some),
+	    None => Ok(None),
+	}
+    }
+
+    fn add_upvalue(
+	&mut self,
+	fn_index: usize,
+	is_local: bool,
+	index: usize,
+	span: Span,
+    ) -> CompileResult<usize> {
+	let upvalue = Upvalue { is_local, index };
+	let upvalues = &mut self.fns[fn_index].upvalues;
+	if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+	    return Ok(existing);
+	}
+	if upvalues.len() == MAX_UPVALUES {
+	    return Err(self.error(
+		span,
+		format!("A function may only close over {} variables.", MAX_UPVALUES),
+	    ));
+	}
+	upvalues.push(upvalue);
+	Ok(upvalues.len() - 1)
+    }
+
+    // Looks `name` up as a local or upvalue of the current function.
+    fn resolve_nonmodule(&mut self, name: &str, span: Span) -> CompileResult<Option<Resolved>> {
+	let fn_index = self.fns.len() - 1;
+	if let Some(slot) = self.resolve_local(fn_index, name) {
+	    return Ok(Some(Resolved::Local(slot)));
+	}
+	Ok(self
+	    .resolve_upvalue(fn_index, name, span)?
+	    .map(Resolved::Upvalue))
+    }
+
+    fn load_name(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	match self.resolve_nonmodule(name, span)? {
+	    Some(Resolved::Local(slot)) => {
+		self.load_local(slot);
+		return Ok(());
+	    }
+	    Some(Resolved::Upvalue(index)) => {
+		self.emit_byte_arg(Code::LoadUpvalue, index as u8);
+		return Ok(());
+	    }
+	    None => {}
+	}
+	// Inside a class, a lowercase name that isn't a variable is a getter
+	// on `this`.
+	if self.implicit_this(name) {
+	    self.load_this(span)?;
+	    self.call_method(name, SignatureKind::Getter, 0);
+	    return Ok(());
+	}
+	let index = self.module_variable(name, span)?;
+	self.emit_short_arg(Code::LoadModuleVar, index);
+	Ok(())
+    }
+
+    fn store_name(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	match self.resolve_nonmodule(name, span)? {
+	    Some(Resolved::Local(slot)) => {
+		self.emit_byte_arg(Code::StoreLocal, slot as u8);
+		return Ok(());
+	    }
+	    Some(Resolved::Upvalue(index)) => {
+		self.emit_byte_arg(Code::StoreUpvalue, index as u8);
+		return Ok(());
+	    }
+	    None => {}
+	}
+	let index = self.module_variable(name, span)?;
+	self.emit_short_arg(Code::StoreModuleVar, index);
+	Ok(())
+    }
+
+    fn implicit_this(&self, name: &str) -> bool {
+	!self.classes.is_empty() && parser::is_local_name(name)
+    }
+
+    fn load_this(&mut self, span: Span) -> CompileResult<()> {
+	match self.resolve_nonmodule("this", span)? {
+	    Some(Resolved::Local(slot)) => self.load_local(slot),
+	    Some(Resolved::Upvalue(index)) => self.emit_byte_arg(Code::LoadUpvalue, index as u8),
+	    None => {
+		return Err(self.error_at_token(span, "this", "Cannot use 'this' outside of a method."))
+	    }
+	}
+	Ok(())
+    }
+
+    // Loads a variable declared by `declare_variable`, which is local if
+    // it was declared inside a function or block.
+    fn load_variable(&mut self, index: usize, is_local: bool) {
+	if is_local {
+	    self.load_local(index);
+	} else {
+	    self.emit_short_arg(Code::LoadModuleVar, index as u16);
+	}
+    }
+
+    // Finds or allocates the slot of an instance field of the enclosing
+    // class.
+    fn field(&mut self, name: &str, span: Span) -> CompileResult<u8> {
+	let class = match self.classes.last_mut() {
+	    Some(class) => class,
+	    None => {
+		let message = "Cannot reference a field outside of a class definition.";
+		return Err(self.error_at_token(span, name, message));
+	    }
+	};
+	if class.is_foreign {
+	    return Err(self.error_at_token(span, name, "Cannot define fields in a foreign class."));
+	}
+	if class.in_static {
+	    let message = "Cannot use an instance field in a static method.";
+	    return Err(self.error_at_token(span, name, message));
+	}
+	let field = class.fields.ensure(name);
+	if field >= MAX_FIELDS {
+	    let message = format!("A class can only have {} fields.", MAX_FIELDS);
+	    return Err(self.error_at_token(span, name, message));
+	}
+	Ok(field as u8)
+    }
+
+    // Whether code is compiled directly in a method body, where `this` is
+    // in slot zero, rather than in a block argument within one.
+    fn in_method_body(&self) -> bool {
+	matches!(
+	    self.fns.last().map(|state| state.kind),
+	    Some(FnKind::Method) | Some(FnKind::Initializer)
+	)
+    }
+
+    fn load_field(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	let field = self.field(name, span)?;
+	if self.in_method_body() {
+	    self.emit_byte_arg(Code::LoadFieldThis, field);
+	} else {
+	    self.load_this(span)?;
+	    self.emit_byte_arg(Code::LoadField, field);
+	}
+	Ok(())
+    }
+
+    // Stores the value on top of the stack in a field, leaving it there.
+    fn store_field(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	let field = self.field(name, span)?;
+	if self.in_method_body() {
+	    self.emit_byte_arg(Code::StoreFieldThis, field);
+	} else {
+	    self.load_this(span)?;
+	    self.emit_byte_arg(Code::StoreField, field);
+	}
+	Ok(())
+    }
+
+    fn load_core_variable(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	let index = self.module_variable(name, span)?;
+	self.emit_short_arg(Code::LoadModuleVar, index);
+	Ok(())
+    }
+
+    // Statements.
+
+    // Compiles each of `statements`, recording the error in one and going on
+    // to the next.
+    fn statements(&mut self, statements: &[Stmt]) {
+	for stmt in statements {
+	    self.recovering(|compiler| compiler.statement(stmt));
+	}
+    }
+
+    // Runs `compile`, and if it fails, records the error and puts back the
+    // functions, classes and scopes being compiled as they were, so that
+    // compiling can go on. Nothing is emitted once there is an error, so the
+    // code it left behind doesn't matter.
+    fn recovering(&mut self, compile: impl FnOnce(&mut Compiler<'a>) -> CompileResult<()>) {
+	let (num_fns, num_classes) = (self.fns.len(), self.classes.len());
+	let state = self.current();
+	let (num_locals, scope_depth, num_loops) =
+	    (state.locals.len(), state.scope_depth, state.loops.len());
+	let error = match compile(self) {
+	    Ok(()) => return,
+	    Err(error) => error,
+	};
+	self.errors.push(error);
+	self.fns.truncate(num_fns);
+	self.classes.truncate(num_classes);
+	let state = self.current();
+	state.locals.truncate(num_locals);
+	state.scope_depth = scope_depth;
+	state.loops.truncate(num_loops);
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> CompileResult<()> {
+	self.line = stmt.span.line;
+	match &stmt.kind {
+	    StmtKind::Expr(expr) => {
+		self.expression(expr)?;
+		self.emit_op(Code::Pop);
+	    }
+	    StmtKind::Var {
+		name,
+		initializer,
+		end,
+	    } => {
+		// The variable isn't in scope in its own initializer.
+		let module_len = self.module.len();
+		match initializer {
+		    // The variable is still declared if its initializer has an
+		    // error, as later uses of it are fine.
+		    Some(expr) => self.recovering(|compiler| compiler.expression(expr)),
+		    None => self.emit_op(Code::Null),
+		}
+		if self.current().scope_depth >= 0 {
+		    self.check_initializer(name, module_len)?;
+		}
+		let index = self.declare_variable_at(name, end)?;
+		self.define_variable(index);
+	    }
+	    StmtKind::Class(class) => self.class_definition(class)?,
+	    StmtKind::Import { module, variables } => {
+		self.import(module, variables, stmt.span)?
+	    }
+	    StmtKind::Block(statements) => {
+		self.push_scope();
+		self.statements(statements);
+		self.pop_scope();
+	    }
+	    StmtKind::If {
+		condition,
+		then_branch,
+		else_branch,
+	    } => match self.constant_condition(condition) {
+		Some(true) => {
+		    self.statement(then_branch)?;
+		    if let Some(else_branch) = else_branch {
+			self.dead_code(|compiler| compiler.statement(else_branch))?;
+		    }
+		}
+		Some(false) => {
+		    self.dead_code(|compiler| compiler.statement(then_branch))?;
+		    if let Some(else_branch) = else_branch {
+			self.statement(else_branch)?;
+		    }
+		}
+		None => {
+		    self.expression(condition)?;
+		    let if_jump = self.emit_jump(Code::JumpIf);
+		    self.statement(then_branch)?;
+		    match else_branch {
+			Some(else_branch) => {
+			    let else_jump = self.emit_jump(Code::Jump);
+			    self.patch_jump(if_jump, stmt.span)?;
+			    self.statement(else_branch)?;
+			    self.patch_jump(else_jump, stmt.span)?;
+			}
+			None => self.patch_jump(if_jump, stmt.span)?,
+		    }
+		}
+	    },
+	    StmtKind::While { condition, body } => {
+		let always = self.constant_condition(condition);
+		if always == Some(false) {
+		    return self.dead_code(|compiler| {
+			compiler.start_loop();
+			compiler.statement(body)?;
+			compiler.current().loops.pop();
+			Ok(())
+		    });
+		}
+		let start = self.start_loop();
+		// A loop that always runs only leaves by a break.
+		let exit = match always {
+		    Some(true) => None,
+		    _ => {
+			self.expression(condition)?;
+			Some(self.emit_jump(Code::JumpIf))
+		    }
+		};
+		self.statement(body)?;
+		self.emit_loop(start, stmt.span)?;
+		self.end_loop(exit, stmt.span)?;
+	    }
+	    StmtKind::For {
+		variable,
+		sequence,
+		body,
+	    } => self.for_statement(variable, sequence, body, stmt.span)?,
+	    StmtKind::Break => {
+		let scope_depth = match self.current().loops.last() {
+		    Some(innermost) => innermost.scope_depth,
+		    None => {
+			let message = "Cannot use 'break' outside of a loop.";
+			return Err(self.error_at_token(stmt.span, "break", message));
+		    }
+		};
+		// Pop the locals of the scopes being exited. They stay
+		// declared for the code after the break in this scope.
+		self.discard_locals(scope_depth + 1);
+		let jump = self.emit_jump(Code::Jump);
+		self.current().loops.last_mut().expect("in a loop").exit_jumps.push(jump);
+	    }
+	    StmtKind::Continue => {
+		let (start, scope_depth) = match self.current().loops.last() {
+		    Some(innermost) => (innermost.start, innermost.scope_depth),
+		    None => {
+			let message = "Cannot use 'continue' outside of a loop.";
+			return Err(self.error_at_token(stmt.span, "continue", message));
+		    }
+		};
+		// As for a break, but jumping back to the loop's condition.
+		self.discard_locals(scope_depth + 1);
+		self.emit_loop(start, stmt.span)?;
+	    }
+	    StmtKind::Return(value) => {
+		let is_initializer = self.current().kind == FnKind::Initializer;
+		match value {
+		    Some(_) if is_initializer => {
+			let message = "A constructor cannot return a value.";
+			return Err(self.error_at_token(stmt.span, "return", message));
+		    }
+		    Some(expr) => self.expression(expr)?,
+		    // Initializers return the new instance.
+		    None if is_initializer => self.emit_op(Code::LoadLocal0),
+		    None => self.emit_op(Code::Null),
+		}
+		self.emit_op(Code::Return);
+	    }
+	}
+	Ok(())
+    }
+
+    // Starts a loop whose condition comes next, returning where it starts.
+    fn start_loop(&mut self) -> usize {
+	let start = self.code_len();
+	let scope_depth = self.current().scope_depth;
+	self.current().loops.push(Loop {
+	    start,
+	    scope_depth,
+	    exit_jumps: Vec::new(),
+	});
+	start
+    }
+
+    // Patches the loop's exit condition, if it has one, and breaks to jump
+    // here.
+    fn end_loop(&mut self, exit: Option<usize>, span: Span) -> CompileResult<()> {
+	if let Some(exit) = exit {
+	    self.patch_jump(exit, span)?;
+	}
+	let innermost = self.current().loops.pop().expect("in a loop");
+	for jump in innermost.exit_jumps {
+	    self.patch_jump(jump, span)?;
+	}
+	Ok(())
+    }
+
+    // A for loop is sugar for the iterator protocol:
+    //
+    //     var seq_ = sequence
+    //     var iter_ = null
+    //     while (iter_ = seq_.iterate(iter_)) {
+    //       var variable = seq_.iteratorValue(iter_)
+    //       body
+    //     }
+    fn for_statement(
+	&mut self,
+	variable: &Ident,
+	sequence: &Expr,
+	body: &Stmt,
+	span: Span,
+    ) -> CompileResult<()> {
+	// The hidden variables have names that can't clash with real ones.
+	self.push_scope();
+	self.expression(sequence)?;
+	let seq_slot = self.declare_variable(&Ident {
+	    name: "seq ".to_string(),
+	    span,
+	})?;
+	self.emit_op(Code::Null);
+	let iter_slot = self.declare_variable(&Ident {
+	    name: "iter ".to_string(),
+	    span,
+	})?;
+
+	let start = self.start_loop();
+	self.load_local(seq_slot);
+	self.load_local(iter_slot);
+	self.call_method("iterate", SignatureKind::Method, 1);
+	self.emit_byte_arg(Code::StoreLocal, iter_slot as u8);
+	let exit = self.emit_jump(Code::JumpIf);
+
+	self.load_local(seq_slot);
+	self.load_local(iter_slot);
+	self.call_method("iteratorValue", SignatureKind::Method, 1);
+
+	// Each iteration gets a fresh loop variable in its own scope.
+	self.push_scope();
+	self.add_variable(variable, variable)?;
+	self.statement(body)?;
+	self.pop_scope();
+
+	self.emit_loop(start, span)?;
+	self.end_loop(Some(exit), span)?;
+	self.pop_scope();
+	Ok(())
+    }
+
+    // Expressions.
+
+    fn expression(&mut self, expr: &Expr) -> CompileResult<()> {
+	self.line = expr.span.line;
+	let span = expr.span;
+	if self.options.opt_level > 0 && !is_literal(&expr.kind) {
+	    if let Some(kind) = fold(expr) {
+		return self.expression(&Expr { kind, span });
+	    }
+	}
+	match &expr.kind {
+	    ExprKind::Null => self.emit_op(Code::Null),
+	    ExprKind::Bool(true) => self.emit_op(Code::True),
+	    ExprKind::Bool(false) => self.emit_op(Code::False),
+	    ExprKind::Num(n) => self.emit_constant(Constant::Num(*n), span)?,
+	    ExprKind::String(s) => self.emit_constant(Constant::String(s.clone()), span)?,
+	    ExprKind::Interpolation(parts) => {
+		// "a %(b) c" compiles to ["a ", b, " c"].join().
+		self.load_core_variable("List", span)?;
+		self.call_method("new", SignatureKind::Method, 0);
+		for part in parts {
+		    self.expression(part)?;
+		    self.call_method("addCore_", SignatureKind::Method, 1);
+		}
+		self.call_method("join", SignatureKind::Method, 0);
+	    }
+	    ExprKind::List(elements) => {
+		self.load_core_variable("List", span)?;
+		self.call_method("new", SignatureKind::Method, 0);
+		for element in elements {
+		    self.expression(element)?;
+		    self.call_method("addCore_", SignatureKind::Method, 1);
+		}
+	    }
+	    ExprKind::Map(entries) => {
+		self.load_core_variable("Map", span)?;
+		self.call_method("new", SignatureKind::Method, 0);
+		for (key, value) in entries {
+		    self.expression(key)?;
+		    self.expression(value)?;
+		    self.call_method("addCore_", SignatureKind::Method, 2);
+		}
+	    }
+	    ExprKind::Name(name) => self.load_name(name, span)?,
+	    // Using `this`, `super` or a field where it can't be used is an
+	    // error that the rest of the expression is compiled past, as in
+	    // wren_c.
+	    ExprKind::Field(name) => self.recovering(|compiler| compiler.load_field(name, span)),
+	    ExprKind::StaticField(name) => self.recovering(|compiler| {
+		compiler.static_field(name, span)?;
+		compiler.load_name(name, span)
+	    }),
+	    ExprKind::This => self.recovering(|compiler| compiler.load_this(span)),
+	    ExprKind::Super { name, args, block } => self.recovering(|compiler| {
+		compiler.super_call(name.as_ref(), args.as_deref(), block.as_deref(), span)
+	    }),
+	    ExprKind::Call {
+		receiver,
+		name,
+		args,
+		block,
+	    } => {
+		match receiver {
+		    Some(receiver) => self.expression(receiver)?,
+		    None => self.load_this(name.span)?,
+		}
+		self.finish_call(Code::Call0, name, args.as_deref(), block.as_deref())?;
+	    }
+	    ExprKind::Subscript { receiver, args } => {
+		self.expression(receiver)?;
+		for arg in args {
+		    self.expression(arg)?;
+		}
+		self.call_method("", SignatureKind::Subscript, args.len());
+	    }
+	    ExprKind::Unary { op, operand } => {
+		self.expression(operand)?;
+		self.call_method(op.method_name(), SignatureKind::Getter, 0);
+	    }
+	    ExprKind::Binary { op, left, right } => {
+		self.expression(left)?;
+		self.expression(right)?;
+		self.call_method(op.method_name(), SignatureKind::Method, 1);
+	    }
+	    ExprKind::And(left, right) => match self.constant_condition(left) {
+		Some(true) => self.expression(right)?,
+		Some(false) => {
+		    self.expression(left)?;
+		    self.dead_code(|compiler| compiler.expression(right))?;
+		}
+		None => {
+		    self.expression(left)?;
+		    let jump = self.emit_jump(Code::And);
+		    self.expression(right)?;
+		    self.patch_jump(jump, span)?;
+		}
+	    },
+	    ExprKind::Or(left, right) => match self.constant_condition(left) {
+		Some(true) => {
+		    self.expression(left)?;
+		    self.dead_code(|compiler| compiler.expression(right))?;
+		}
+		Some(false) => self.expression(right)?,
+		None => {
+		    self.expression(left)?;
+		    let jump = self.emit_jump(Code::Or);
+		    self.expression(right)?;
+		    self.patch_jump(jump, span)?;
+		}
+	    },
+	    ExprKind::Conditional {
+		condition,
+		then_branch,
+		else_branch,
+	    } => match self.constant_condition(condition) {
+		Some(true) => {
+		    self.expression(then_branch)?;
+		    self.dead_code(|compiler| compiler.expression(else_branch))?;
+		}
+		Some(false) => {
+		    self.dead_code(|compiler| compiler.expression(then_branch))?;
+		    self.expression(else_branch)?;
+		}
+		None => {
+		    self.expression(condition)?;
+		    let if_jump = self.emit_jump(Code::JumpIf);
+		    self.expression(then_branch)?;
+		    let else_jump = self.emit_jump(Code::Jump);
+		    self.patch_jump(if_jump, span)?;
+		    self.expression(else_branch)?;
+		    self.patch_jump(else_jump, span)?;
+		}
+	    },
+	    ExprKind::Assign { target, value } => self.assignment(target, value)?,
+	}
+	Ok(())
+    }
+
+    // Reports `name`, of a method being defined or called, if it is longer
+    // than wren_c allows. Compiling goes on with it as it does there.
+    fn check_method_name(&mut self, name: &Ident) {
+	if name.name.len() > MAX_METHOD_NAME {
+	    let message = format!("Method names cannot be longer than {} characters.", MAX_METHOD_NAME);
+	    let error = self.error_at(name, message);
+	    self.errors.push(error);
+	}
+    }
+
+    // Compiles the arguments and block argument of a call whose receiver
+    // is already on the stack, then the call itself.
+    fn finish_call(
+	&mut self,
+	instruction: Code,
+	name: &Ident,
+	args: Option<&[Expr]>,
+	block: Option<&BlockArg>,
+    ) -> CompileResult<()> {
+	self.check_method_name(name);
+	let mut signature = match args {
+	    Some(args) => {
+		for arg in args {
+		    self.expression(arg)?;
+		}
+		Signature::new(&name.name, SignatureKind::Method, args.len())
+	    }
+	    None => Signature::new(&name.name, SignatureKind::Getter, 0),
+	};
+	if let Some(block) = block {
+	    signature.kind = SignatureKind::Method;
+	    signature.arity += 1;
+	    self.block_argument(block, &signature)?;
+	}
+	self.line = name.span.line;
+	self.invoke(instruction, &signature);
+	Ok(())
+    }
+
+    // `super.name(args)`, or `super(args)` to call the superclass's
+    // version of the method being compiled.
+    fn super_call(
+	&mut self,
+	name: Option<&Ident>,
+	args: Option<&[Expr]>,
+	block: Option<&BlockArg>,
+	span: Span,
+    ) -> CompileResult<()> {
+	let enclosing = match self.classes.last() {
+	    Some(class) => class.signature.clone().expect("in a method"),
+	    None => {
+		return Err(self.error_at_token(span, "super", "Cannot use 'super' outside of a method."))
+	    }
+	};
+	self.load_this(span)?;
+	if let Some(name) = name {
+	    return self.finish_call(Code::Super0, name, args, block);
+	}
+
+	let mut called = Signature::new(&enclosing.name, SignatureKind::Getter, 0);
+	if let Some(args) = args {
+	    called.kind = SignatureKind::Method;
+	    called.arity = args.len();
+	    for arg in args {
+		self.expression(arg)?;
+	    }
+	}
+	if let Some(block) = block {
+	    called.kind = SignatureKind::Method;
+	    called.arity += 1;
+	    self.block_argument(block, &called)?;
+	}
+	if enclosing.kind == SignatureKind::Initializer {
+	    if called.kind != SignatureKind::Method {
+		let message = "A superclass constructor must have an argument list.";
+		return Err(self.error_at_token(span, "super", message));
+	    }
+	    called.kind = SignatureKind::Initializer;
+	}
+	self.invoke(Code::Super0, &called);
+	Ok(())
+    }
+
+    // Static fields are locals of the function the class is defined in,
+    // which its methods close over. The first use of one declares it there,
+    // initialized to null.
+    fn static_field(&mut self, name: &str, span: Span) -> CompileResult<()> {
+	let fn_index = match self.classes.last() {
+	    Some(class) => class.fn_index,
+	    None => {
+		let message = "Cannot use a static field outside of a class definition.";
+		return Err(self.error_at_token(span, name, message));
+	    }
+	};
+	if self.resolve_local(fn_index, name).is_some() {
+	    return Ok(());
+	}
+	// Compile into the enclosing function for a moment. Its code is
+	// between the class's methods, so the local's slot is free.
+	let inner = self.fns.split_off(fn_index + 1);
+	let result = self.declare_variable(&Ident {
+	    name: name.to_string(),
+	    span,
+	});
+	if result.is_ok() {
+	    self.emit_op(Code::Null);
+	}
+	self.fns.extend(inner);
+	result.map(|_| ())
+    }
+
+    fn block_argument(&mut self, block: &BlockArg, signature: &Signature) -> CompileResult<()> {
+	let name = format!("{} block argument", signature);
+	let mut state = FnState::new(name, FnKind::Block);
+	state.proto.arity = block.params.len();
+	self.fns.push(state);
+	for param in &block.params {
+	    self.declare_variable(param)?;
+	}
+	self.body(&block.body)?;
+	self.end_fn(block.span)
+    }
+
+    // Finishes the function being compiled and emits code to create a
+    // closure of it in the enclosing function.
+    fn end_fn(&mut self, span: Span) -> CompileResult<()> {
+	let state = self.fns.pop().expect("function being compiled");
+	let upvalues = state.upvalues.clone();
+	let mut proto = state.finish();
+	proto.num_upvalues = upvalues.len();
+	let index = self.add_constant(Constant::Fn(Box::new(proto)), span)?;
+	self.emit_short_arg(Code::Closure, index);
+	for upvalue in upvalues {
+	    self.emit(upvalue.is_local as u8);
+	    self.emit(upvalue.index as u8);
+	}
+	Ok(())
+    }
+
+    fn body(&mut self, body: &Body) -> CompileResult<()> {
+	let is_initializer = self.current().kind == FnKind::Initializer;
+	match body {
+	    Body::Expr(expr) => {
+		self.expression(expr)?;
+		// An initializer discards the value and returns `this`.
+		if is_initializer {
+		    self.emit_op(Code::Pop);
+		}
+	    }
+	    Body::Block(statements) => {
+		self.statements(statements);
+		// Implicitly return null from statement bodies.
+		if !is_initializer {
+		    self.emit_op(Code::Null);
+		}
+	    }
+	}
+	if is_initializer {
+	    self.emit_op(Code::LoadLocal0);
+	}
+	self.emit_op(Code::Return);
+	Ok(())
+    }
+
+    // Imports.
+
+    fn import(
+	&mut self,
+	module: &str,
+	variables: &[ImportVariable],
+	span: Span,
+    ) -> CompileResult<()> {
+	let constant = self.add_constant(Constant::String(module.to_string()), span)?;
+	// Load the module, then discard the value its body returns.
+	self.emit_short_arg(Code::ImportModule, constant);
+	self.emit_op(Code::Pop);
+	for variable in variables {
+	    let source = &variable.name;
+	    let constant =
+		self.add_constant(Constant::String(source.name.clone()), source.span)?;
+	    let index = self.declare_variable(variable.alias.as_ref().unwrap_or(source))?;
+	    self.emit_short_arg(Code::ImportVariable, constant);
+	    self.define_variable(index);
+	}
+	Ok(())
+    }
+
+    // Classes.
+
+    fn class_definition(&mut self, class: &ClassDecl) -> CompileResult<()> {
+	// Declare the variable first so methods can refer to the class.
+	let is_local = self.current().scope_depth >= 0;
+	let variable = self.declare_variable(&class.name)?;
+
+	self.emit_constant(Constant::String(class.name.name.clone()), class.name.span)?;
+	match &class.superclass {
+	    Some(superclass) => self.expression(superclass)?,
+	    None => self.load_core_variable("Object", class.name.span)?,
+	}
+	// The field count isn't known until the methods have been compiled.
+	// Foreign classes have no fields.
+	let num_fields_offset = if class.is_foreign {
+	    self.emit_op(Code::ForeignClass);
+	    None
+	} else {
+	    self.emit_byte_arg(Code::Class, 255);
+	    Some(self.code_len() - 1)
+	};
+	self.define_variable(variable);
+
+	self.push_scope();
+	self.classes.push(ClassInfo {
+	    name: class.name.name.clone(),
+	    is_foreign: class.is_foreign,
+	    fn_index: self.fns.len() - 1,
+	    fields: SymbolTable::new(),
+	    methods: Vec::new(),
+	    static_methods: Vec::new(),
+	    in_static: false,
+	    signature: None,
+	});
+	for method in &class.methods {
+	    self.recovering(|compiler| compiler.method(method, variable, is_local));
+	}
+	let info = self.classes.pop().expect("class being compiled");
+	if let Some(offset) = num_fields_offset {
+	    self.current().proto.chunk.code[offset] = info.fields.len() as u8;
+	}
+	self.class_attributes(class, variable, is_local)?;
+	self.pop_scope();
+	Ok(())
+    }
+
+    // Stores the class's runtime attributes and those of its methods on it,
+    // as a `ClassAttributes`, if there are any.
+    fn class_attributes(
+	&mut self,
+	class: &ClassDecl,
+	variable: usize,
+	is_local: bool,
+    ) -> CompileResult<()> {
+	let own = attribute_map(&class.attributes);
+	let methods: Vec<(String, Expr)> = class
+	    .methods
+	    .iter()
+	    .filter_map(|method| {
+		let map = attribute_map(&method.attributes)?;
+		let key = format!(
+		    "{}{}{}",
+		    if method.is_foreign { "foreign " } else { "" },
+		    if method.is_static { "static " } else { "" },
+		    method_signature(method)
+		);
+		Some((key, map))
+	    })
+	    .collect();
+	if own.is_none() && methods.is_empty() {
+	    return Ok(());
+	}
+
+	let span = class.name.span;
+	self.load_variable(variable, is_local);
+	self.load_core_variable("ClassAttributes", span)?;
+	match own {
+	    Some(map) => self.expression(&map)?,
+	    None => self.emit_op(Code::Null),
+	}
+	if methods.is_empty() {
+	    self.emit_op(Code::Null);
+	} else {
+	    let entries = methods
+		.into_iter()
+		.map(|(key, map)| {
+		    let key = Expr {
+			kind: ExprKind::String(key),
+			span: map.span,
+		    };
+		    (key, map)
+		})
+		.collect();
+	    self.expression(&Expr {
+		kind: ExprKind::Map(entries),
+		span,
+	    })?;
+	}
+	self.call_method("new", SignatureKind::Method, 2);
+	self.emit_op(Code::EndClass);
+	Ok(())
+    }
+
+    fn method(&mut self, method: &Method, class_variable: usize, is_local: bool) -> CompileResult<()> {
+	self.check_method_name(&method.name);
+	let arity = method.params.len();
+	let signature = method_signature(method);
+	let symbol = self.methods.ensure(&signature.to_string());
+
+	let class = self.classes.last_mut().expect("in a class");
+	let defined = if method.is_static {
+	    &mut class.static_methods
+	} else {
+	    &mut class.methods
+	};
+	if defined.contains(&symbol) {
+	    let message = format!(
+		"Class {} already defines a {}method '{}'.",
+		class.name,
+		if method.is_static { "static " } else { "" },
+		signature
+	    );
+	    // wren_c reports it at the end of the signature.
+	    let token = match method.kind {
+		MethodKind::Getter | MethodKind::Unary => method.name.name.as_str(),
+		MethodKind::Subscript => "]",
+		_ => ")",
+	    };
+	    return Err(self.error_at_token(method.signature_end, token, message));
+	}
+	defined.push(symbol);
+	class.in_static = method.is_static;
+	class.signature = Some(signature.clone());
+
+	match &method.body {
+	    Some(body) => {
+		let kind = if method.kind == MethodKind::Constructor {
+		    FnKind::Initializer
+		} else {
+		    FnKind::Method
+		};
+		let mut state = FnState::new(signature.to_string(), kind);
+		state.proto.arity = arity;
+		self.fns.push(state);
+		for param in &method.params {
+		    self.declare_variable(param)?;
+		}
+		self.body(body)?;
+		self.end_fn(method.span)?;
+	    }
+	    // A foreign method is bound by the VM when it's defined, using its
+	    // signature. Failing to, it reports the line the signature ends on,
+	    // as wren_c does.
+	    None => {
+		self.line = method.signature_end.line;
+		self.emit_constant(Constant::String(signature.to_string()), method.name.span)?
+	    }
+	}
+	self.define_method(class_variable, is_local, method.is_static, symbol);
+
+	if method.kind == MethodKind::Constructor {
+	    // The constructor itself is a static method that creates the
+	    // instance and then runs the initializer on it.
+	    let constructor = Signature::new(&method.name.name, SignatureKind::Method, arity);
+	    let mut state = FnState::new(constructor.to_string(), FnKind::Block);
+	    state.proto.arity = arity;
+	    self.fns.push(state);
+	    let is_foreign = self.classes.last().expect("in a class").is_foreign;
+	    self.emit_op(if is_foreign {
+		Code::ForeignConstruct
+	    } else {
+		Code::Construct
+	    });
+	    self.emit_short_arg(Code::call(arity), symbol as u16);
+	    self.emit_op(Code::Return);
+	    self.end_fn(method.span)?;
+	    let constructor_symbol = self.methods.ensure(&constructor.to_string());
+	    self.define_method(class_variable, is_local, true, constructor_symbol);
+	}
+	Ok(())
+    }
+
+    // Binds the method closure on top of the stack to the class.
+    fn define_method(&mut self, class_variable: usize, is_local: bool, is_static: bool, symbol: usize) {
+	// Load the class each time, since it isn't kept on top of the stack.
+	self.load_variable(class_variable, is_local);
+	let code = if is_static {
+	    Code::MethodStatic
+	} else {
+	    Code::MethodInstance
+	};
+	self.emit_short_arg(code, symbol as u16);
+    }
+
+    fn assignment(&mut self, target: &Expr, value: &Expr) -> CompileResult<()> {
+	match &target.kind {
+	    ExprKind::Name(name) => {
+		let is_variable = self.resolve_nonmodule(name, target.span)?.is_some();
+		if !is_variable && self.implicit_this(name) {
+		    // A setter on the implicit `this`.
+		    self.load_this(target.span)?;
+		    self.expression(value)?;
+		    self.line = target.span.line;
+		    self.call_method(name, SignatureKind::Setter, 1);
+		    return Ok(());
+		}
+		self.expression(value)?;
+		self.line = target.span.line;
+		self.store_name(name, target.span)
+	    }
+	    ExprKind::Field(name) => {
+		self.expression(value)?;
+		self.store_field(name, target.span)
+	    }
+	    ExprKind::StaticField(name) => {
+		self.static_field(name, target.span)?;
+		self.expression(value)?;
+		self.store_name(name, target.span)
+	    }
+	    ExprKind::Super {
+		name: Some(name),
+		args: None,
+		block: None,
+	    } => {
+		if self.classes.is_empty() {
+		    let message = "Cannot use 'super' outside of a method.";
+		    return Err(self.error_at_token(target.span, "super", message));
+		}
+		self.check_method_name(name);
+		self.load_this(target.span)?;
+		self.expression(value)?;
+		self.line = name.span.line;
+		self.invoke(Code::Super0, &Signature::new(&name.name, SignatureKind::Setter, 1));
+		Ok(())
+	    }
+	    ExprKind::Call {
+		receiver: Some(receiver),
+		name,
+		..
+	    } => {
+		self.check_method_name(name);
+		self.expression(receiver)?;
+		self.expression(value)?;
+		self.line = name.span.line;
+		self.call_method(&name.name, SignatureKind::Setter, 1);
+		Ok(())
+	    }
+	    ExprKind::Subscript { receiver, args } => {
+		self.expression(receiver)?;
+		for arg in args {
+		    self.expression(arg)?;
+		}
+		self.expression(value)?;
+		self.call_method("", SignatureKind::SubscriptSetter, args.len() + 1);
+		Ok(())
+	    }
+	    // Everything else is rejected by the parser or unsupported.
+	    _ => self.expression(target),
+	}
+    }
+}
+
+/// The signature a method definition binds.
+pub(crate) fn method_signature(method: &Method) -> Signature {
+    let arity = method.params.len();
+    match method.kind {
+	MethodKind::Getter | MethodKind::Unary => {
+	    Signature::new(&method.name.name, SignatureKind::Getter, 0)
+	}
+	MethodKind::Setter => Signature::new(&method.name.name, SignatureKind::Setter, 1),
+	MethodKind::Method | MethodKind::Binary => {
+	    Signature::new(&method.name.name, SignatureKind::Method, arity)
+	}
+	MethodKind::Subscript => Signature::new("", SignatureKind::Subscript, arity),
+	MethodKind::SubscriptSetter => Signature::new("", SignatureKind::SubscriptSetter, arity),
+	MethodKind::Constructor => Signature::new(&method.name.name, SignatureKind::Initializer, arity),
+    }
+}
+
+/// The signature a call with these arguments uses.
+pub(crate) fn call_signature(
+    name: &Ident,
+    args: &Option<Vec<Expr>>,
+    block: &Option<Box<BlockArg>>,
+) -> Signature {
+    let mut signature = match args {
+	Some(args) => Signature::new(&name.name, SignatureKind::Method, args.len()),
+	None => Signature::new(&name.name, SignatureKind::Getter, 0),
+    };
+    if block.is_some() {
+	signature.kind = SignatureKind::Method;
+	signature.arity += 1;
+    }
+    signature
+}
+
+/// The name in a signature string, without its parameters.
+pub(crate) fn signature_name(signature: &str) -> &str {
+    signature.split(['(', '=']).next().unwrap_or(signature)
+}
+
+// An attribute group's name, or `None` for ungrouped attributes, and the
+// values of each of its keys.
+type AttributeGroup<'a> = (Option<&'a str>, Vec<(&'a Ident, Vec<&'a Expr>)>);
+
+/// Groups the runtime attributes in `attributes` the way
+/// `ClassAttributes` exposes them: a map from each group, or null for
+/// ungrouped ones, to a map from each key to the list of its values.
+/// Returns `None` if there are no runtime attributes.
+fn attribute_map(attributes: &[Attribute]) -> Option<Expr> {
+    let mut groups: Vec<AttributeGroup> = Vec::new();
+    for attribute in attributes.iter().filter(|attribute| attribute.is_runtime) {
+	let group = attribute.group.as_ref().map(|group| group.name.as_str());
+	let keys = match groups.iter().position(|(name, _)| *name == group) {
+	    Some(index) => &mut groups[index].1,
+	    None => {
+		groups.push((group, Vec::new()));
+		&mut groups.last_mut().expect("just pushed").1
+	    }
+	};
+	match keys.iter().position(|(key, _)| key.name == attribute.key.name) {
+	    Some(index) => keys[index].1.push(&attribute.value),
+	    None => keys.push((&attribute.key, vec![&attribute.value])),
+	}
+    }
+    if groups.is_empty() {
+	return None;
+    }
+
+    let literal = |kind: ExprKind, span: Span| Expr { kind, span };
+    let span = attributes[0].key.span;
+    let entries = groups
+	.into_iter()
+	.map(|(group, keys)| {
+	    let group = match group {
+		Some(name) => literal(ExprKind::String(name.to_string()), span),
+		None => literal(ExprKind::Null, span),
+	    };
+	    let keys = keys
+		.into_iter()
+		.map(|(key, values)| {
+		    let values = values.into_iter().cloned().collect();
+		    (
+			literal(ExprKind::String(key.name.clone()), key.span),
+			literal(ExprKind::List(values), key.span),
+		    )
+		})
+		.collect();
+	    (group, literal(ExprKind::Map(keys), span))
+	})
+	.collect();
+    Some(literal(ExprKind::Map(entries), span))
+}
+
+fn is_literal(kind: &ExprKind) -> bool {
+    matches!(
+	kind,
+	ExprKind::Null | ExprKind::Bool(_) | ExprKind::Num(_) | ExprKind::String(_)
+    )
+}
+
+fn is_falsy(literal: &ExprKind) -> bool {
+    matches!(literal, ExprKind::Null | ExprKind::Bool(false))
+}
+
+// The literal `expr` always evaluates to, if it is made only of literals
+// and operators whose result the compiler can work out the same way the
+// core library's methods would. Those methods can't be replaced by
+// scripts. An operand that `&&`, `||` or `?:` skips has to fold too, so
+// that one with a name in it is still compiled for its errors.
+fn fold(expr: &Expr) -> Option<ExprKind> {
+    use ExprKind::{Bool, Num};
+    let folded = match &expr.kind {
+	kind if is_literal(kind) => kind.clone(),
+	ExprKind::Unary { op, operand } => match (op, fold(operand)?) {
+	    (UnaryOp::Neg, Num(n)) => Num(-n),
+	    (UnaryOp::BitNot, Num(n)) => Num(!to_u32(n) as f64),
+	    (UnaryOp::Not, Bool(b)) => Bool(!b),
+	    (UnaryOp::Not, ExprKind::Null) => Bool(true),
+	    _ => return None,
+	},
+	ExprKind::Binary { op, left, right } => match (op, fold(left)?, fold(right)?) {
+	    // Literals are equal when they are the same value.
+	    (BinaryOp::Eq, left, right) => Bool(left == right),
+	    (BinaryOp::NotEq, left, right) => Bool(left != right),
+	    (op, Num(a), Num(b)) => match op {
+		BinaryOp::Add => Num(a + b),
+		BinaryOp::Sub => Num(a - b),
+		BinaryOp::Mul => Num(a * b),
+		BinaryOp::Div => Num(a / b),
+		BinaryOp::Mod => Num(a % b),
+		BinaryOp::Lt => Bool(a < b),
+		BinaryOp::Gt => Bool(a > b),
+		BinaryOp::LtEq => Bool(a <= b),
+		BinaryOp::GtEq => Bool(a >= b),
+		BinaryOp::BitAnd => Num((to_u32(a) & to_u32(b)) as f64),
+		BinaryOp::BitOr => Num((to_u32(a) | to_u32(b)) as f64),
+		BinaryOp::BitXor => Num((to_u32(a) ^ to_u32(b)) as f64),
+		BinaryOp::Shl => Num(to_u32(a).wrapping_shl(to_u32(b)) as f64),
+		BinaryOp::Shr => Num(to_u32(a).wrapping_shr(to_u32(b)) as f64),
+		_ => return None,
+	    },
+	    (BinaryOp::Add, ExprKind::String(a), ExprKind::String(b)) => {
+		ExprKind::String(a + &b)
+	    }
+	    _ => return None,
+	},
+	ExprKind::And(left, right) => match (fold(left)?, fold(right)?) {
+	    (left, _) if is_falsy(&left) => left,
+	    (_, right) => right,
+	},
+	ExprKind::Or(left, right) => match (fold(left)?, fold(right)?) {
+	    (left, right) if is_falsy(&left) => right,
+	    (left, _) => left,
+	},
+	ExprKind::Conditional {
+	    condition,
+	    then_branch,
+	    else_branch,
+	} => match (fold(condition)?, fold(then_branch)?, fold(else_branch)?) {
+	    (condition, _, else_branch) if is_falsy(&condition) => else_branch,
+	    (_, then_branch, _) => then_branch,
+	},
+	_ => return None,
+    };
+    Some(folded)
+}
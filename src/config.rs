@@ -0,0 +1,196 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use crate::compiler::CompileOptions;
+use crate::error::WrenError;
+use crate::heap::{GcEvent, POOL_SIZE};
+use crate::lexer::MAX_INTERPOLATION_NESTING;
+use crate::loader::ModuleLoader;
+use crate::parser::{ParseOptions, MAX_NESTING};
+use crate::vm::WrenVM;
+
+/// Receives the text a script prints with `System.print` and friends.
+pub type WriteFn = fn(&mut WrenVM, &str);
+
+/// Receives each compile error and uncaught runtime error.
+pub type ErrorFn = fn(&mut WrenVM, &WrenError);
+
+/// Returns the seconds `System.clock` reports, counted from any fixed point.
+pub type ClockFn = fn() -> f64;
+
+/// Called every `INTERRUPT_INTERVAL` instructions while scripts run, and
+/// returns whether to stop them, such as when they have run too long.
+pub type InterruptFn = fn(&mut WrenVM) -> bool;
+
+/// How many instructions scripts run between calls to the `interrupt_fn`.
+pub const INTERRUPT_INTERVAL: u32 = 1024;
+
+/// Told about each garbage collection once it finishes.
+pub type GcFn = fn(&mut WrenVM, &GcEvent);
+
+/// Settings for a `WrenVM`, fixed when it is created.
+pub struct WrenConfiguration {
+    /// Bytes to allocate before the first collection.
+    pub initial_heap_size: usize,
+    /// After a collection, the next one is never scheduled below this many
+    /// bytes, so small heaps aren't collected constantly.
+    pub min_heap_size: usize,
+    /// How far the heap may grow past the live bytes of the previous
+    /// collection before the next one, as a percentage. 50 waits until
+    /// the heap is half again as large.
+    pub heap_growth_percent: usize,
+    /// How many bytes the heap may hold, roughly, before allocating more
+    /// is a runtime error in the fiber that does it, once collecting
+    /// garbage has failed to free enough. Without one, the heap grows
+    /// until the host runs out of memory. Fibers' stacks don't count
+    /// towards it, as `max_stack_size` limits each of them. Nor does the
+    /// core library, which the VM builds before the limit applies.
+    pub max_heap_size: Option<usize>,
+    /// With this, a collection is done a slice at a time rather than all
+    /// at once: each instruction that allocates traces at most this many
+    /// objects, keeping pauses short at the cost of garbage living longer.
+    /// `WrenVM::gc_step` does the same between calls into the VM.
+    pub gc_step_size: Option<usize>,
+    /// With this, the heap has a nursery of about this many bytes for new
+    /// objects. When it fills, a minor collection frees the ones that are
+    /// already garbage without tracing the rest of the heap, and tenures
+    /// the others, which are only collected when the whole heap is.
+    pub nursery_size: Option<usize>,
+    /// How many buffers of freed lists, instances, closures and fibers the
+    /// heap keeps, of each kind, to back new objects instead of allocating.
+    /// 0 turns this off. Only small buffers are kept, and they don't count
+    /// towards the size of the heap. Other objects, such as ranges and
+    /// upvalues, have no buffers of their own to keep.
+    pub pool_size: usize,
+    /// Whether the VM fuses common pairs of instructions as it loads each
+    /// function, and does arithmetic on numbers without calling methods.
+    /// Only has an effect with the `superinstructions` feature.
+    pub superinstructions: bool,
+    /// Whether the VM runs scripts through a table of a function for each
+    /// instruction, instead of the `match` it otherwise uses. Only has an
+    /// effect with the `threaded-dispatch` feature.
+    pub threaded_dispatch: bool,
+    /// How many calls deep a fiber may go before the VM gives up with a
+    /// stack overflow. There is no limit by default, as in wren_c, and only
+    /// `max_stack_size` stops runaway recursion.
+    pub max_call_depth: usize,
+    /// How many values a fiber's stack may hold before the VM gives up with
+    /// a stack overflow, rather than growing the stack until memory runs
+    /// out. It is checked as each call starts, so a fiber can go past it by
+    /// the slots of the call. The default, 1M values or 16 MB, lets a
+    /// small recursive method go about half a million calls deep.
+    pub max_stack_size: usize,
+    /// How deeply statements and expressions may nest before code fails to
+    /// compile, rather than overflowing the host's stack in the compiler.
+    /// This and the other limits on scripts here don't apply to the core
+    /// library, which the VM builds before they do.
+    pub max_nesting: usize,
+    /// How deeply string interpolations may nest inside one another.
+    pub max_interpolation_nesting: usize,
+    /// How hard the compiler optimizes, as `CompileOptions::opt_level`.
+    pub opt_level: u8,
+    /// Whether scripts run the same way on every machine, as lockstep
+    /// multiplayer and replays need. `System.clock` reads the `clock_fn`,
+    /// or without one a clock that only moves as `Timer.sleep` wakes
+    /// fibers, which it does in order without waiting. `Random.new()`
+    /// always starts the same sequence, `Num`'s trigonometric, logarithmic
+    /// and power methods are computed in software rather than by the
+    /// platform. Maps iterate in an order set by their keys and the order
+    /// they were added either way.
+    pub deterministic: bool,
+    /// Supplies the source of imported modules. Without one, every import
+    /// of a module that isn't already loaded fails.
+    pub module_loader: Option<Box<dyn ModuleLoader>>,
+    /// Where `System` output goes. Without one, it is written to stdout, or
+    /// dropped without the `std` feature.
+    pub write_fn: Option<WriteFn>,
+    /// Where errors are reported. Without one, they are written to stderr,
+    /// or dropped without the `std` feature.
+    pub error_fn: Option<ErrorFn>,
+    /// What `System.clock` reads, which also seeds `Random`. Without one,
+    /// the system clock is used, which targets such as
+    /// `wasm32-unknown-unknown` don't have, and without the `std` feature
+    /// the clock is always 0.
+    pub clock_fn: Option<ClockFn>,
+    /// Lets the host stop scripts that run too long with
+    /// `WrenError::Timeout`, by a time limit or any other measure. Without
+    /// one, only `WrenVM::set_fuel` limits them.
+    pub interrupt_fn: Option<InterruptFn>,
+    /// Lets the host log or graph what the garbage collector does. It is
+    /// called in the middle of whatever allocated, so it shouldn't run
+    /// scripts of its own.
+    pub gc_fn: Option<GcFn>,
+}
+
+impl Default for WrenConfiguration {
+    fn default() -> WrenConfiguration {
+	WrenConfiguration {
+	    initial_heap_size: 10 * 1024 * 1024,
+	    min_heap_size: 1024 * 1024,
+	    heap_growth_percent: 50,
+	    max_heap_size: None,
+	    gc_step_size: None,
+	    nursery_size: None,
+	    pool_size: POOL_SIZE,
+	    superinstructions: true,
+	    threaded_dispatch: true,
+	    max_call_depth: usize::MAX,
+	    max_stack_size: 1 << 20,
+	    max_nesting: MAX_NESTING,
+	    max_interpolation_nesting: MAX_INTERPOLATION_NESTING,
+	    opt_level: CompileOptions::default().opt_level,
+	    deterministic: false,
+	    module_loader: None,
+	    write_fn: None,
+	    error_fn: None,
+	    clock_fn: None,
+	    interrupt_fn: None,
+	    gc_fn: None,
+	}
+    }
+}
+
+impl WrenConfiguration {
+    /// The limits the compiler puts on source code.
+    pub fn parse_options(&self) -> ParseOptions {
+	ParseOptions {
+	    max_nesting: self.max_nesting,
+	    max_interpolation_nesting: self.max_interpolation_nesting,
+	}
+    }
+
+    /// How the compiler optimizes code.
+    pub fn compile_options(&self) -> CompileOptions {
+	CompileOptions {
+	    opt_level: self.opt_level,
+	}
+    }
+}
+
+impl fmt::Debug for WrenConfiguration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	f.debug_struct("WrenConfiguration")
+	    .field("initial_heap_size", &self.initial_heap_size)
+	    .field("min_heap_size", &self.min_heap_size)
+	    .field("heap_growth_percent", &self.heap_growth_percent)
+	    .field("max_heap_size", &self.max_heap_size)
+	    .field("gc_step_size", &self.gc_step_size)
+	    .field("nursery_size", &self.nursery_size)
+	    .field("pool_size", &self.pool_size)
+	    .field("superinstructions", &self.superinstructions)
+	    .field("threaded_dispatch", &self.threaded_dispatch)
+	    .field("max_call_depth", &self.max_call_depth)
+	    .field("max_stack_size", &self.max_stack_size)
+	    .field("max_nesting", &self.max_nesting)
+	    .field("max_interpolation_nesting", &self.max_interpolation_nesting)
+	    .field("opt_level", &self.opt_level)
+	    .field("deterministic", &self.deterministic)
+	    .field("module_loader", &self.module_loader.is_some())
+	    .field("write_fn", &self.write_fn.is_some())
+	    .field("error_fn", &self.error_fn.is_some())
+	    .field("clock_fn", &self.clock_fn.is_some())
+	    .field("interrupt_fn", &self.interrupt_fn.is_some())
+	    .field("gc_fn", &self.gc_fn.is_some())
+	    .finish()
+    }
+}
@@ -0,0 +1,1713 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::compiler::Signature;
+#[allow(unused_imports)]
+use crate::float::Float;
+use crate::parser::MAX_PARAMETERS;
+use crate::value::*;
+use crate::vm::{Method, Primitive, PrimitiveError, PrimitiveResult, WrenVM};
+
+const CORE_SOURCE: &str = include_str!("core.wren");
+
+// A primitive method of a core class, bound to its metaclass if it is
+// static.
+struct CorePrimitive {
+    signature: &'static str,
+    is_static: bool,
+    function: Primitive,
+}
+
+const fn method(signature: &'static str, function: Primitive) -> CorePrimitive {
+    CorePrimitive {
+	signature,
+	is_static: false,
+	function,
+    }
+}
+
+const fn static_method(signature: &'static str, function: Primitive) -> CorePrimitive {
+    CorePrimitive {
+	signature,
+	is_static: true,
+	function,
+    }
+}
+
+// The primitives of each core class, as wren_c binds them with PRIMITIVE.
+// A primitive is given one argument for each `_` in its signature, after
+// the receiver.
+
+const OBJECT_PRIMITIVES: &[CorePrimitive] = &[
+    method("!", object_not),
+    method("==(_)", object_eqeq),
+    method("!=(_)", object_bangeq),
+    method("is(_)", object_is),
+    method("toString", object_to_string),
+    method("type", object_type),
+];
+
+const CLASS_PRIMITIVES: &[CorePrimitive] = &[
+    method("attributes", class_attributes),
+    method("name", class_name),
+    method("supertype", class_supertype),
+    method("toString", class_name),
+];
+
+const OBJECT_METACLASS_PRIMITIVES: &[CorePrimitive] = &[
+    method("same(_,_)", object_same),
+];
+
+const BOOL_PRIMITIVES: &[CorePrimitive] = &[
+    method("!", bool_not),
+    method("toString", to_string),
+];
+
+const NULL_PRIMITIVES: &[CorePrimitive] = &[
+    method("!", null_not),
+    method("toString", to_string),
+];
+
+const NUM_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("fromString(_)", num_from_string),
+    static_method("infinity", num_infinity),
+    static_method("nan", num_nan),
+    static_method("pi", num_pi),
+    static_method("tau", num_tau),
+    static_method("largest", num_largest),
+    static_method("smallest", num_smallest),
+    static_method("maxSafeInteger", num_max_safe_integer),
+    static_method("minSafeInteger", num_min_safe_integer),
+    method("-(_)", num_minus),
+    method("+(_)", num_plus),
+    method("*(_)", num_multiply),
+    method("/(_)", num_divide),
+    method("<(_)", num_lt),
+    method(">(_)", num_gt),
+    method("<=(_)", num_lte),
+    method(">=(_)", num_gte),
+    method("&(_)", num_bitwise_and),
+    method("|(_)", num_bitwise_or),
+    method("^(_)", num_bitwise_xor),
+    method("<<(_)", num_bitwise_left_shift),
+    method(">>(_)", num_bitwise_right_shift),
+    method("~", num_bitwise_not),
+    method("..(_)", num_dot_dot),
+    method("...(_)", num_dot_dot_dot),
+    method("abs", num_abs),
+    method("acos", num_acos),
+    method("asin", num_asin),
+    method("atan", num_atan),
+    method("cbrt", num_cbrt),
+    method("ceil", num_ceil),
+    method("cos", num_cos),
+    method("floor", num_floor),
+    method("-", num_negate),
+    method("round", num_round),
+    method("min(_)", num_min),
+    method("max(_)", num_max),
+    method("clamp(_,_)", num_clamp),
+    method("sin", num_sin),
+    method("sqrt", num_sqrt),
+    method("tan", num_tan),
+    method("log", num_log),
+    method("log2", num_log2),
+    method("exp", num_exp),
+    method("%(_)", num_mod),
+    method("==(_)", num_eqeq),
+    method("!=(_)", num_bangeq),
+    method("atan(_)", num_atan2),
+    method("pow(_)", num_pow),
+    method("fraction", num_fraction),
+    method("isInfinity", num_is_infinity),
+    method("isInteger", num_is_integer),
+    method("isNan", num_is_nan),
+    method("sign", num_sign),
+    method("toString", to_string),
+    method("truncate", num_truncate),
+];
+
+const STRING_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("fromCodePoint(_)", string_from_code_point),
+    static_method("fromByte(_)", string_from_byte),
+    method("+(_)", string_plus),
+    method("[_]", string_subscript),
+    method("byteAt_(_)", string_byte_at),
+    method("byteCount_", string_byte_count),
+    method("codePointAt_(_)", string_code_point_at),
+    method("contains(_)", string_contains),
+    method("endsWith(_)", string_ends_with),
+    method("indexOf(_)", string_index_of),
+    method("indexOf(_,_)", string_index_of),
+    method("iterate(_)", string_iterate),
+    method("iterateByte_(_)", string_iterate_byte),
+    method("iteratorValue(_)", string_iterator_value),
+    method("startsWith(_)", string_starts_with),
+    method("toString", string_to_string),
+    method("count", string_count),
+    method("isEmpty", string_is_empty),
+    method("split(_)", string_split),
+    method("replace(_,_)", string_replace),
+    method("trim()", string_trim),
+    method("trim(_)", string_trim),
+    method("trimEnd()", string_trim_end),
+    method("trimEnd(_)", string_trim_end),
+    method("trimStart()", string_trim_start),
+    method("trimStart(_)", string_trim_start),
+    method("*(_)", string_multiply),
+];
+
+const LIST_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("filled(_,_)", list_filled),
+    static_method("new()", list_new),
+    method("[_]", list_subscript),
+    method("[_]=(_)", list_subscript_setter),
+    method("add(_)", list_add),
+    method("addCore_(_)", list_add_core),
+    method("clear()", list_clear),
+    method("count", list_count),
+    method("insert(_,_)", list_insert),
+    method("iterate(_)", list_iterate),
+    method("iteratorValue(_)", list_iterator_value),
+    method("removeAt(_)", list_remove_at),
+    method("remove(_)", list_remove_value),
+    method("indexOf(_)", list_index_of),
+    method("swap(_,_)", list_swap),
+];
+
+const MAP_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("new()", map_new),
+    method("addCore_(_,_)", map_add_core),
+    method("clear()", map_clear),
+    method("containsKey(_)", map_contains_key),
+    method("count", map_count),
+    method("[_]", map_subscript),
+    method("[_]=(_)", map_subscript_setter),
+    method("remove(_)", map_remove_primitive),
+    method("iterate(_)", map_iterate),
+    method("keyIteratorValue_(_)", map_key_iterator_value),
+    method("valueIteratorValue_(_)", map_value_iterator_value),
+];
+
+const RANGE_PRIMITIVES: &[CorePrimitive] = &[
+    method("from", range_from),
+    method("to", range_to),
+    method("min", range_min),
+    method("max", range_max),
+    method("isInclusive", range_is_inclusive),
+    method("iterate(_)", range_iterate),
+    method("iteratorValue(_)", range_iterator_value),
+    method("toString", to_string),
+];
+
+const FN_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("new(_)", fn_new),
+    method("arity", fn_arity),
+    method("toString", to_string),
+];
+
+const FIBER_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("new(_)", fiber_new),
+    static_method("abort(_)", fiber_abort),
+    static_method("current", fiber_current),
+    static_method("suspend()", fiber_suspend),
+    static_method("yield()", fiber_yield),
+    static_method("yield(_)", fiber_yield),
+    method("call()", fiber_call),
+    method("call(_)", fiber_call),
+    method("error", fiber_error),
+    method("isDone", fiber_is_done),
+    method("transfer()", fiber_transfer),
+    method("transfer(_)", fiber_transfer),
+    method("transferError(_)", fiber_transfer_error),
+    method("try()", fiber_try),
+    method("try(_)", fiber_try),
+];
+
+const SYSTEM_PRIMITIVES: &[CorePrimitive] = &[
+    static_method("clock", system_clock),
+    static_method("gc()", system_gc),
+    static_method("writeString_(_)", system_write_string),
+];
+// The classes declared in core.wren whose primitives are bound once it has
+// run.
+const CORE_PRIMITIVES: &[(&str, &[CorePrimitive])] = &[
+    ("Bool", BOOL_PRIMITIVES),
+    ("Null", NULL_PRIMITIVES),
+    ("Num", NUM_PRIMITIVES),
+    ("String", STRING_PRIMITIVES),
+    ("List", LIST_PRIMITIVES),
+    ("Map", MAP_PRIMITIVES),
+    ("Range", RANGE_PRIMITIVES),
+    ("Fn", FN_PRIMITIVES),
+    ("Fiber", FIBER_PRIMITIVES),
+    ("System", SYSTEM_PRIMITIVES),
+];
+
+/// Every primitive with its signature, in the same order in every VM of
+/// this build, so a snapshot can refer to one by its position.
+pub(crate) fn primitives() -> impl Iterator<Item = (&'static str, Primitive)> {
+    let classes: &[&[CorePrimitive]] =
+	&[OBJECT_PRIMITIVES, CLASS_PRIMITIVES, OBJECT_METACLASS_PRIMITIVES];
+    let rest = CORE_PRIMITIVES.iter().map(|&(_, primitives)| primitives);
+    let tables = classes.iter().copied().chain(rest);
+    tables.flatten().map(|primitive| (primitive.signature, primitive.function))
+}
+
+/// Creates the core classes and binds their primitives.
+pub(crate) fn initialize(vm: &mut WrenVM) {
+    // Object has no superclass, so it is built by hand.
+    let object = vm.new_single_class("Object", 0);
+    vm.core.object = object;
+    define(vm, "Object", object);
+    bind_primitives(vm, object, OBJECT_PRIMITIVES);
+
+    // Now Class can be defined as a subclass of Object.
+    let class = vm.new_single_class("Class", 0);
+    vm.core.class = class;
+    define(vm, "Class", class);
+    vm.bind_superclass(class, object);
+    bind_primitives(vm, class, CLASS_PRIMITIVES);
+
+    // Finally Object's metaclass, a subclass of Class, closes the loop.
+    let object_metaclass = vm.new_single_class("Object metaclass", 0);
+    vm.heap.class_mut(object).class = Some(object_metaclass);
+    vm.heap.class_mut(object_metaclass).class = Some(class);
+    vm.heap.class_mut(class).class = Some(class);
+    vm.bind_superclass(object_metaclass, class);
+    bind_primitives(vm, object_metaclass, OBJECT_METACLASS_PRIMITIVES);
+
+    // The rest of the core classes are declared in Wren, along with the
+    // methods that are easier to write in it, and the primitives are bound
+    // to them afterwards.
+    let result = vm.interpret_in_module(vm.core_module, CORE_SOURCE);
+    assert!(result.is_ok(), "core.wren should run");
+    for &(name, primitives) in CORE_PRIMITIVES {
+	let class = find_class(vm, name);
+	bind_primitives(vm, class, primitives);
+    }
+
+    vm.core.bool = find_class(vm, "Bool");
+    vm.core.null = find_class(vm, "Null");
+    vm.core.num = find_class(vm, "Num");
+    vm.core.string = find_class(vm, "String");
+    vm.core.list = find_class(vm, "List");
+    vm.core.map = find_class(vm, "Map");
+    vm.core.range = find_class(vm, "Range");
+    vm.core.function = find_class(vm, "Fn");
+    vm.core.fiber = find_class(vm, "Fiber");
+
+    let function = vm.core.function;
+    for arity in 0..=MAX_PARAMETERS {
+	let params = vec!["_"; arity].join(",");
+	let symbol = vm.methods.ensure(&format!("call({})", params));
+	vm.bind_method(function, symbol, Method::FunctionCall);
+    }
+}
+
+fn define(vm: &mut WrenVM, name: &str, class: ObjRef) {
+    vm.define_variable(vm.core_module, name, Value::Obj(class))
+	.expect("core class names are unique");
+}
+
+fn find_class(vm: &WrenVM, name: &str) -> ObjRef {
+    let core = vm.heap.module(vm.core_module);
+    let index = core.scope.find(name).expect("core class");
+    core.variables[index].as_obj().expect("core class")
+}
+
+fn bind_primitives(vm: &mut WrenVM, class: ObjRef, primitives: &[CorePrimitive]) {
+    for (index, primitive) in primitives.iter().enumerate() {
+	let signature = primitive.signature;
+	// Calls only reach primitives bound to signatures written the way the
+	// compiler writes them.
+	debug_assert!(
+	    Signature::parse(signature).is_ok_and(|parsed| parsed.to_string() == signature),
+	    "malformed signature '{}'",
+	    signature
+	);
+	let is_bound = |other: &CorePrimitive| {
+	    other.signature == signature && other.is_static == primitive.is_static
+	};
+	debug_assert!(!primitives[..index].iter().any(is_bound), "'{}' is bound twice", signature);
+	let target = if primitive.is_static {
+	    vm.heap.class(class).class.expect("class has a metaclass")
+	} else {
+	    class
+	};
+	let symbol = vm.methods.ensure(signature);
+	vm.bind_method(target, symbol, Method::Primitive(primitive.function));
+    }
+}
+
+/// Converts a value to the string `toString` returns for it.
+pub(crate) fn value_to_string(vm: &WrenVM, value: Value) -> String {
+    let obj = match value {
+	Value::Null => return "null".to_string(),
+	Value::Bool(b) => return b.to_string(),
+	Value::Num(n) => return num_to_string(n),
+	Value::Obj(obj) => obj,
+    };
+    match vm.heap.get(obj) {
+	Obj::String(string) => string.value.clone(),
+	Obj::List(list) => {
+	    let elements: Vec<String> = list
+		.elements
+		.iter()
+		.map(|&element| value_to_string(vm, element))
+		.collect();
+	    format!("[{}]", elements.join(", "))
+	}
+	Obj::Map(map) => {
+	    let entries: Vec<String> = map
+		.iter()
+		.map(|(key, value)| {
+		    format!("{}: {}", value_to_string(vm, key), value_to_string(vm, value))
+		})
+		.collect();
+	    format!("{{{}}}", entries.join(", "))
+	}
+	Obj::Range(range) => format!(
+	    "{}{}{}",
+	    num_to_string(range.from),
+	    if range.is_inclusive { ".." } else { "..." },
+	    num_to_string(range.to)
+	),
+	Obj::Fn(_) | Obj::Closure(_) => "<fn>".to_string(),
+	Obj::Class(class) => class.name.clone(),
+	Obj::Instance(instance) => format!("instance of {}", vm.heap.class(instance.class).name),
+	Obj::Foreign(foreign) => format!("instance of {}", vm.heap.class(foreign.class).name),
+	Obj::Fiber(_) => "instance of Fiber".to_string(),
+	Obj::Upvalue(_) => "upvalue".to_string(),
+	Obj::Module(module) => module.name.clone(),
+    }
+}
+
+/// Formats a number the way wren_c does, with C's `%.14g`.
+pub fn num_to_string(n: f64) -> String {
+    if n.is_nan() {
+	return "nan".to_string();
+    }
+    if n.is_infinite() {
+	return if n > 0.0 { "infinity" } else { "-infinity" }.to_string();
+    }
+    if n == 0.0 {
+	return if n.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    // Round to 14 significant digits first, since that can change the
+    // exponent.
+    let scientific = format!("{:.13e}", n);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').expect("exponent"));
+    let exponent: i32 = exponent[1..].parse().expect("numeric exponent");
+    if !(-4..14).contains(&exponent) {
+	let sign = if exponent < 0 { '-' } else { '+' };
+	format!(
+	    "{}e{}{:02}",
+	    trim_fraction(mantissa),
+	    sign,
+	    exponent.abs()
+	)
+    } else {
+	let fixed = format!("{:.*}", (13 - exponent) as usize, n);
+	trim_fraction(&fixed).to_string()
+    }
+}
+
+// Drops trailing zeros after the decimal point, and the point itself.
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') {
+	number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+	number
+    }
+}
+
+fn validate_num(vm: &mut WrenVM, value: Value, arg_name: &str) -> Result<f64, PrimitiveError> {
+    match value.as_num() {
+	Some(n) => Ok(n),
+	None => Err(vm.error(format!("{} must be a number.", arg_name))),
+    }
+}
+
+fn validate_int(vm: &mut WrenVM, value: Value, arg_name: &str) -> Result<f64, PrimitiveError> {
+    let n = validate_num(vm, value, arg_name)?;
+    validate_int_value(vm, n, arg_name)
+}
+
+fn validate_int_value(vm: &mut WrenVM, n: f64, arg_name: &str) -> Result<f64, PrimitiveError> {
+    if n.trunc() != n {
+	return Err(vm.error(format!("{} must be an integer.", arg_name)));
+    }
+    Ok(n)
+}
+
+// Fails with the error `maybe_collect` raises when the heap is full if
+// `size` more bytes, or a size too large to count, wouldn't fit in it, so
+// that a script can't have the host allocate more than it allows at once.
+// Sizes no allocation can have fail even with no limit on the heap.
+// Returns the size otherwise.
+fn reserve(vm: &mut WrenVM, size: Option<usize>) -> Result<usize, PrimitiveError> {
+    match size {
+	Some(size) if size <= isize::MAX as usize && vm.heap.has_room(size) => Ok(size),
+	_ => Err(vm.error("Out of memory.")),
+    }
+}
+
+/// Validates `value` as an index into a sequence of `count` elements,
+/// allowing negative indices from the end.
+fn validate_index(
+    vm: &mut WrenVM,
+    value: Value,
+    count: usize,
+    arg_name: &str,
+) -> Result<usize, PrimitiveError> {
+    let index = validate_num(vm, value, arg_name)?;
+    validate_index_value(vm, index, count, arg_name)
+}
+
+fn validate_index_value(
+    vm: &mut WrenVM,
+    index: f64,
+    count: usize,
+    arg_name: &str,
+) -> Result<usize, PrimitiveError> {
+    let mut index = validate_int_value(vm, index, arg_name)?;
+    if index < 0.0 {
+	index += count as f64;
+    }
+    if index >= 0.0 && index < count as f64 {
+	Ok(index as usize)
+    } else {
+	Err(vm.error(format!("{} out of bounds.", arg_name)))
+    }
+}
+
+fn validate_string(vm: &mut WrenVM, value: Value, arg_name: &str) -> Result<String, PrimitiveError> {
+    match vm.heap.as_str(value) {
+	Some(string) => Ok(string.to_string()),
+	None => Err(vm.error(format!("{} must be a string.", arg_name))),
+    }
+}
+
+/// Validates `range` as a slice of a sequence of `count` elements,
+/// returning the first index, the number of elements and the direction to
+/// step in.
+fn calculate_range(
+    vm: &mut WrenVM,
+    range: ObjRange,
+    count: usize,
+) -> Result<(usize, usize, isize), PrimitiveError> {
+    // An empty range is allowed at the end of a sequence, so `list[0..-1]`
+    // and `list[0...list.count]` copy even an empty list.
+    let end = if range.is_inclusive { -1.0 } else { count as f64 };
+    if range.from == count as f64 && range.to == end {
+	return Ok((0, 0, 0));
+    }
+    let from = validate_index_value(vm, range.from, count, "Range start")?;
+    let mut to = validate_int_value(vm, range.to, "Range end")?;
+    if to < 0.0 {
+	to += count as f64;
+    }
+    if !range.is_inclusive {
+	// An exclusive range with the same start and end is empty.
+	if to == from as f64 {
+	    return Ok((from, 0, 0));
+	}
+	// Make the end inclusive, whichever way the range runs.
+	to += if to >= from as f64 { -1.0 } else { 1.0 };
+    }
+    if to < 0.0 || to >= count as f64 {
+	return Err(vm.error("Range end out of bounds."));
+    }
+    let to = to as usize;
+    let step = if from < to { 1 } else { -1 };
+    Ok((from, from.max(to) - from.min(to) + 1, step))
+}
+
+fn validate_subscript_range(vm: &mut WrenVM, value: Value) -> Result<ObjRange, PrimitiveError> {
+    if let Some(obj) = value.as_obj() {
+	if let Obj::Range(range) = vm.heap.get(obj) {
+	    return Ok(*range);
+	}
+    }
+    Err(vm.error("Subscript must be a number or a range."))
+}
+
+/// Map keys must have value semantics so their hash can't change.
+pub(crate) fn is_valid_key(vm: &WrenVM, value: Value) -> bool {
+    match value {
+	Value::Null | Value::Bool(_) | Value::Num(_) => true,
+	Value::Obj(obj) => matches!(
+	    vm.heap.get(obj),
+	    Obj::String(_) | Obj::Range(_) | Obj::Class(_)
+	),
+    }
+}
+
+fn validate_key(vm: &mut WrenVM, value: Value) -> Result<(), PrimitiveError> {
+    if is_valid_key(vm, value) {
+	Ok(())
+    } else {
+	Err(vm.error("Key must be a value type."))
+    }
+}
+
+fn receiver(value: Value) -> ObjRef {
+    value.as_obj().expect("object receiver")
+}
+
+fn object_not(_vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(false))
+}
+
+fn object_eqeq(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(vm.values_equal(args[0], args[1])))
+}
+
+fn object_bangeq(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(!vm.values_equal(args[0], args[1])))
+}
+
+fn object_is(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let target = match args[1].as_obj() {
+	Some(obj) if matches!(vm.heap.get(obj), Obj::Class(_)) => obj,
+	_ => return Err(vm.error("Right operand must be a class.")),
+    };
+    let mut class = Some(vm.class_of(args[0]));
+    while let Some(current) = class {
+	if current == target {
+	    return Ok(Value::Bool(true));
+	}
+	class = vm.heap.class(current).superclass;
+    }
+    Ok(Value::Bool(false))
+}
+
+fn object_same(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(args[1].same(args[2])))
+}
+
+fn object_to_string(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let class = vm.class_of(args[0]);
+    let string = format!("instance of {}", vm.heap.class(class).name);
+    Ok(vm.new_string(string))
+}
+
+// `toString` for the built-in types, which know how to print themselves.
+fn to_string(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let string = value_to_string(vm, args[0]);
+    Ok(vm.new_string(string))
+}
+
+fn object_type(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Obj(vm.class_of(args[0])))
+}
+
+fn class_name(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let name = vm.heap.class(receiver(args[0])).name.clone();
+    Ok(vm.new_string(name))
+}
+
+fn class_attributes(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(vm.heap.class(receiver(args[0])).attributes)
+}
+
+fn class_supertype(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(match vm.heap.class(receiver(args[0])).superclass {
+	Some(superclass) => Value::Obj(superclass),
+	None => Value::Null,
+    })
+}
+
+fn bool_not(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(args[0].is_falsy()))
+}
+
+fn null_not(_vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(true))
+}
+
+fn num_from_string(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let string = validate_string(vm, args[1], "Argument")?;
+    // Like C's strtod, allow surrounding whitespace and hex literals.
+    let text = string.trim_matches(|c: char| c.is_ascii_whitespace());
+    let (negative, digits) = match text.strip_prefix('-') {
+	Some(rest) => (true, rest),
+	None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let number = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+	Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => hex
+	    .bytes()
+	    .fold(0.0, |n, b| n * 16.0 + (b as char).to_digit(16).expect("hex digit") as f64),
+	Some(_) => return Ok(Value::Null),
+	None if digits.starts_with(['+', '-']) => return Ok(Value::Null),
+	None => match digits.parse::<f64>() {
+	    Ok(number) => number,
+	    Err(_) => return Ok(Value::Null),
+	},
+    };
+    if number.is_infinite() && !digits.to_ascii_lowercase().starts_with("inf") {
+	return Err(vm.error("Number literal is too large."));
+    }
+    Ok(Value::Num(if negative { -number } else { number }))
+}
+
+macro_rules! num_constant {
+    ($($name:ident => $value:expr;)*) => {
+	$(
+	    fn $name(_vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+		Ok(Value::Num($value))
+	    }
+	)*
+    };
+}
+
+num_constant! {
+    num_infinity => f64::INFINITY;
+    num_nan => f64::NAN;
+    num_pi => core::f64::consts::PI;
+    num_tau => core::f64::consts::TAU;
+    num_largest => f64::MAX;
+    num_smallest => f64::MIN_POSITIVE;
+    num_max_safe_integer => 9007199254740991.0;
+    num_min_safe_integer => -9007199254740991.0;
+}
+
+macro_rules! num_fn {
+    ($($name:ident => |$n:ident| $result:expr;)*) => {
+	$(
+	    fn $name(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+		let $n = args[0].as_num().expect("num receiver");
+		Ok(Value::from($result))
+	    }
+	)*
+    };
+}
+
+num_fn! {
+    num_negate => |n| -n;
+    num_abs => |n| n.abs();
+    num_ceil => |n| n.ceil();
+    num_floor => |n| n.floor();
+    num_round => |n| n.round();
+    num_sqrt => |n| n.sqrt();
+    num_truncate => |n| n.trunc();
+    // C's modf gives infinities a fractional part of zero.
+    num_fraction => |n| if n.is_infinite() { 0.0_f64.copysign(n) } else { n.fract() };
+    num_is_infinity => |n| n.is_infinite();
+    num_is_integer => |n| n.is_finite() && n.trunc() == n;
+    num_is_nan => |n| n.is_nan();
+    num_sign => |n| if n > 0.0 {
+	1.0
+    } else if n < 0.0 {
+	-1.0
+    } else {
+	0.0
+    };
+    num_bitwise_not => |n| !to_u32(n) as f64;
+}
+
+// Methods whose results the platform's math library may round differently
+// from another's. A deterministic VM computes them with `libm` instead.
+macro_rules! num_math_fn {
+    ($($name:ident => $method:ident, $libm:ident;)*) => {
+	$(
+	    fn $name(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+		let n = args[0].as_num().expect("num receiver");
+		Ok(Value::Num(if vm.config.deterministic { libm::$libm(n) } else { n.$method() }))
+	    }
+	)*
+    };
+}
+
+num_math_fn! {
+    num_acos => acos, acos;
+    num_asin => asin, asin;
+    num_atan => atan, atan;
+    num_cbrt => cbrt, cbrt;
+    num_cos => cos, cos;
+    num_sin => sin, sin;
+    num_tan => tan, tan;
+    num_log => ln, log;
+    num_log2 => log2, log2;
+    num_exp => exp, exp;
+}
+
+// Converts a number to an unsigned 32-bit integer for the bitwise operators
+// the way wren_c's `(uint32_t)` cast does on x86-64: truncated to a 64-bit
+// integer and wrapped, with numbers outside its range, infinities and NaN
+// becoming 0 rather than saturating.
+pub(crate) fn to_u32(n: f64) -> u32 {
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if (-LIMIT..LIMIT).contains(&n) { n as i64 as u32 } else { 0 }
+}
+
+macro_rules! num_infix {
+    ($($name:ident => |$a:ident, $b:ident| $result:expr;)*) => {
+	$(
+	    fn $name(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+		let $a = args[0].as_num().expect("num receiver");
+		let $b = validate_num(vm, args[1], "Right operand")?;
+		Ok(Value::from($result))
+	    }
+	)*
+    };
+}
+
+num_infix! {
+    num_plus => |a, b| a + b;
+    num_minus => |a, b| a - b;
+    num_multiply => |a, b| a * b;
+    num_divide => |a, b| a / b;
+    num_mod => |a, b| a % b;
+    num_lt => |a, b| a < b;
+    num_gt => |a, b| a > b;
+    num_lte => |a, b| a <= b;
+    num_gte => |a, b| a >= b;
+    num_bitwise_and => |a, b| (to_u32(a) & to_u32(b)) as f64;
+    num_bitwise_or => |a, b| (to_u32(a) | to_u32(b)) as f64;
+    num_bitwise_xor => |a, b| (to_u32(a) ^ to_u32(b)) as f64;
+    num_bitwise_left_shift => |a, b| to_u32(a).wrapping_shl(to_u32(b)) as f64;
+    num_bitwise_right_shift => |a, b| to_u32(a).wrapping_shr(to_u32(b)) as f64;
+}
+
+fn num_atan2(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let n = args[0].as_num().expect("num receiver");
+    let x = validate_num(vm, args[1], "Right operand")?;
+    Ok(Value::Num(if vm.config.deterministic { libm::atan2(n, x) } else { n.atan2(x) }))
+}
+
+fn num_pow(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let n = args[0].as_num().expect("num receiver");
+    let power = validate_num(vm, args[1], "Power value")?;
+    Ok(Value::Num(if vm.config.deterministic { libm::pow(n, power) } else { n.powf(power) }))
+}
+
+fn num_min(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let n = args[0].as_num().expect("num receiver");
+    let other = validate_num(vm, args[1], "Other value")?;
+    Ok(Value::Num(if n <= other { n } else { other }))
+}
+
+fn num_max(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let n = args[0].as_num().expect("num receiver");
+    let other = validate_num(vm, args[1], "Other value")?;
+    Ok(Value::Num(if n > other { n } else { other }))
+}
+
+fn num_clamp(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let n = args[0].as_num().expect("num receiver");
+    let min = validate_num(vm, args[1], "Min value")?;
+    let max = validate_num(vm, args[2], "Max value")?;
+    Ok(Value::Num(if n < min {
+	min
+    } else if n > max {
+	max
+    } else {
+	n
+    }))
+}
+
+fn num_eqeq(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(args[1].as_num() == args[0].as_num()))
+}
+
+fn num_bangeq(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(args[1].as_num() != args[0].as_num()))
+}
+
+fn num_dot_dot(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let from = args[0].as_num().expect("num receiver");
+    let to = validate_num(vm, args[1], "Right hand side of range")?;
+    Ok(vm.new_range(from, to, true))
+}
+
+fn num_dot_dot_dot(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let from = args[0].as_num().expect("num receiver");
+    let to = validate_num(vm, args[1], "Right hand side of range")?;
+    Ok(vm.new_range(from, to, false))
+}
+
+fn string_plus(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let right = validate_string(vm, args[1], "Right operand")?;
+    let left = vm.heap.as_str(args[0]).expect("string receiver");
+    let result = format!("{}{}", left, right);
+    Ok(vm.new_string(result))
+}
+
+fn string_to_string(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(args[0])
+}
+
+// Strings hold UTF-8 text, so a lone byte that isn't a whole character,
+// such as the middle of a multi-byte sequence, becomes U+FFFD.
+fn string_from_byte_value(byte: u8) -> String {
+    String::from_utf8_lossy(&[byte]).into_owned()
+}
+
+fn string_from_code_point(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let code_point = validate_int(vm, args[1], "Code point")?;
+    if code_point < 0.0 {
+	return Err(vm.error("Code point cannot be negative."));
+    }
+    if code_point > 0x10ffff as f64 {
+	return Err(vm.error("Code point cannot be greater than 0x10ffff."));
+    }
+    let c = char::from_u32(code_point as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+    Ok(vm.new_string(c.to_string()))
+}
+
+fn string_from_byte(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let byte = validate_int(vm, args[1], "Byte")?;
+    if byte < 0.0 {
+	return Err(vm.error("Byte cannot be negative."));
+    }
+    if byte > 0xff as f64 {
+	return Err(vm.error("Byte cannot be greater than 0xff."));
+    }
+    Ok(vm.new_string(string_from_byte_value(byte as u8)))
+}
+
+fn string_of(vm: &WrenVM, value: Value) -> String {
+    str_of(vm, value).to_string()
+}
+
+// The receiver of a string method, borrowed rather than copied for the
+// methods that only look at a character or two of it, which scripts call
+// for each one.
+fn str_of(vm: &WrenVM, value: Value) -> &str {
+    vm.heap.as_str(value).expect("string receiver")
+}
+
+// The code point starting at byte `index` of `string`, as a string.
+fn code_point_string_at(string: &str, index: usize) -> String {
+    match string.get(index..).and_then(|rest| rest.chars().next()) {
+	Some(c) => c.to_string(),
+	None => string_from_byte_value(string.as_bytes()[index]),
+    }
+}
+
+fn string_subscript(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    if args[1].is_num() {
+	let index = validate_index(vm, args[1], str_of(vm, args[0]).len(), "Subscript")?;
+	let result = code_point_string_at(str_of(vm, args[0]), index);
+	return Ok(vm.new_string(result));
+    }
+    let string = string_of(vm, args[0]);
+    let range = validate_subscript_range(vm, args[1])?;
+    let (start, count, step) = calculate_range(vm, range, string.len())?;
+    // Take each code point that starts within the range of bytes.
+    let result: String = (0..count)
+	.map(|i| (start as isize + i as isize * step) as usize)
+	.filter_map(|index| string.get(index..).and_then(|rest| rest.chars().next()))
+	.collect();
+    Ok(vm.new_string(result))
+}
+
+fn string_byte_at(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let index = validate_index(vm, args[1], str_of(vm, args[0]).len(), "Index")?;
+    Ok(Value::Num(str_of(vm, args[0]).as_bytes()[index] as f64))
+}
+
+fn string_byte_count(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(str_of(vm, args[0]).len() as f64))
+}
+
+fn string_code_point_at(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let index = validate_index(vm, args[1], str_of(vm, args[0]).len(), "Index")?;
+    let string = str_of(vm, args[0]);
+    // There's no code point starting in the middle of a sequence.
+    Ok(Value::Num(
+	match string.get(index..).and_then(|rest| rest.chars().next()) {
+	    Some(c) => c as u32 as f64,
+	    None => -1.0,
+	},
+    ))
+}
+
+fn string_contains(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let search = validate_string(vm, args[1], "Argument")?;
+    Ok(Value::Bool(string_of(vm, args[0]).contains(&search)))
+}
+
+fn string_ends_with(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let search = validate_string(vm, args[1], "Argument")?;
+    Ok(Value::Bool(string_of(vm, args[0]).ends_with(&search)))
+}
+
+fn string_starts_with(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let search = validate_string(vm, args[1], "Argument")?;
+    Ok(Value::Bool(string_of(vm, args[0]).starts_with(&search)))
+}
+
+// Handles both `indexOf(_)` and `indexOf(_,_)`, which searches from the
+// byte index given as the second argument.
+fn string_index_of(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let string = string_of(vm, args[0]);
+    let search = validate_string(vm, args[1], "Argument")?;
+    let start = match args.get(2) {
+	Some(&start) => validate_index(vm, start, string.len(), "Start")?,
+	None => 0,
+    };
+    if search.is_empty() {
+	return Ok(Value::Num(start as f64));
+    }
+    // A match can only begin on a character boundary.
+    let start = (start..=string.len())
+	.find(|&index| string.is_char_boundary(index))
+	.expect("the end is a boundary");
+    Ok(Value::Num(match string[start..].find(&search) {
+	Some(index) => (start + index) as f64,
+	None => -1.0,
+    }))
+}
+
+// Iterates over the byte indices where each code point starts.
+fn string_iterate(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    if args[1].is_null() {
+	return Ok(if str_of(vm, args[0]).is_empty() {
+	    Value::Bool(false)
+	} else {
+	    Value::Num(0.0)
+	});
+    }
+    let index = validate_int(vm, args[1], "Iterator")?;
+    if index < 0.0 {
+	return Ok(Value::Bool(false));
+    }
+    let string = str_of(vm, args[0]);
+    let next = (index as usize + 1..string.len()).find(|&index| string.is_char_boundary(index));
+    Ok(match next {
+	Some(next) => Value::Num(next as f64),
+	None => Value::Bool(false),
+    })
+}
+
+fn string_iterate_byte(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = str_of(vm, args[0]).len();
+    if args[1].is_null() {
+	return Ok(if count == 0 {
+	    Value::Bool(false)
+	} else {
+	    Value::Num(0.0)
+	});
+    }
+    let index = validate_int(vm, args[1], "Iterator")?;
+    if index < 0.0 || index >= count as f64 - 1.0 {
+	return Ok(Value::Bool(false));
+    }
+    Ok(Value::Num(index + 1.0))
+}
+
+fn string_iterator_value(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let index = validate_index(vm, args[1], str_of(vm, args[0]).len(), "Iterator")?;
+    let result = code_point_string_at(str_of(vm, args[0]), index);
+    Ok(vm.new_string(result))
+}
+
+fn string_count(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(string_of(vm, args[0]).chars().count() as f64))
+}
+
+fn string_is_empty(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(string_of(vm, args[0]).is_empty()))
+}
+
+fn string_split(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let delimiter = match vm.heap.as_str(args[1]) {
+	Some(delimiter) if !delimiter.is_empty() => delimiter.to_string(),
+	_ => return Err(vm.error("Delimiter must be a non-empty string.")),
+    };
+    let string = str_of(vm, args[0]);
+    let count = string.matches(&delimiter).count() + 1;
+    let part_size = core::mem::size_of::<Obj>() + core::mem::size_of::<Value>();
+    reserve(vm, count.checked_mul(part_size).and_then(|size| size.checked_add(string.len())))?;
+    let parts: Vec<String> = string_of(vm, args[0])
+	.split(&delimiter)
+	.map(str::to_string)
+	.collect();
+    let elements = parts.into_iter().map(|part| vm.new_string(part)).collect();
+    Ok(vm.new_list(elements))
+}
+
+fn string_replace(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let from = match vm.heap.as_str(args[1]) {
+	Some(from) if !from.is_empty() => from.to_string(),
+	_ => return Err(vm.error("From must be a non-empty string.")),
+    };
+    let to = match vm.heap.as_str(args[2]) {
+	Some(to) => to.to_string(),
+	None => return Err(vm.error("To must be a string.")),
+    };
+    if to.len() > from.len() {
+	let string = string_of(vm, args[0]);
+	let count = string.matches(&from).count();
+	let growth = count.checked_mul(to.len() - from.len());
+	reserve(vm, growth.and_then(|growth| growth.checked_add(string.len())))?;
+    }
+    let result = string_of(vm, args[0]).replace(&from, &to);
+    Ok(vm.new_string(result))
+}
+
+// The characters `trim` and friends remove: their argument's, or
+// whitespace when called without one.
+fn trim_chars(vm: &mut WrenVM, args: &[Value]) -> Result<Vec<char>, PrimitiveError> {
+    match args.get(1) {
+	Some(&chars) => match vm.heap.as_str(chars) {
+	    Some(chars) => Ok(chars.chars().collect()),
+	    None => Err(vm.error("Characters must be a string.")),
+	},
+	None => Ok(vec!['\t', '\r', '\n', ' ']),
+    }
+}
+
+fn string_trim(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let chars = trim_chars(vm, args)?;
+    let result = string_of(vm, args[0]).trim_matches(&chars[..]).to_string();
+    Ok(vm.new_string(result))
+}
+
+fn string_trim_end(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let chars = trim_chars(vm, args)?;
+    let result = string_of(vm, args[0]).trim_end_matches(&chars[..]).to_string();
+    Ok(vm.new_string(result))
+}
+
+fn string_trim_start(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let chars = trim_chars(vm, args)?;
+    let result = string_of(vm, args[0]).trim_start_matches(&chars[..]).to_string();
+    Ok(vm.new_string(result))
+}
+
+fn string_multiply(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = match args[1].as_num() {
+	Some(count) if count >= 0.0 && count.trunc() == count => count as usize,
+	_ => return Err(vm.error("Count must be a non-negative integer.")),
+    };
+    let length = str_of(vm, args[0]).len();
+    let size = reserve(vm, length.checked_mul(count))?;
+    // Even with room in the heap, the host may not have the memory, which
+    // is the same error rather than aborting it.
+    let mut result = String::new();
+    if result.try_reserve_exact(size).is_err() {
+	return Err(vm.error("Out of memory."));
+    }
+    // Copy what's there so far until it's full, as `str::repeat` does.
+    if size > 0 {
+	result.push_str(str_of(vm, args[0]));
+    }
+    while result.len() < size {
+	let copied = result.len().min(size - result.len());
+	result.extend_from_within(..copied);
+    }
+    Ok(vm.new_string(result))
+}
+
+fn list_filled(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let size = validate_int(vm, args[1], "Size")?;
+    if size < 0.0 {
+	return Err(vm.error("Size cannot be negative."));
+    }
+    reserve(vm, (size as usize).checked_mul(core::mem::size_of::<Value>()))?;
+    // As in `string_multiply`, memory the host doesn't have is an error.
+    let mut elements = Vec::new();
+    if elements.try_reserve_exact(size as usize).is_err() {
+	return Err(vm.error("Out of memory."));
+    }
+    elements.resize(size as usize, args[2]);
+    Ok(vm.new_list(elements))
+}
+
+fn list_new(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    // List literals start out as `List.new()`, so this is where most lists
+    // get a buffer from a freed one.
+    let elements = vm.heap.take_values(0);
+    Ok(vm.new_list(elements))
+}
+
+fn list_elements(vm: &WrenVM, list: Value) -> &Vec<Value> {
+    &vm.heap.list(receiver(list)).elements
+}
+
+fn list_elements_mut(vm: &mut WrenVM, list: Value) -> &mut Vec<Value> {
+    &mut vm.heap.list_mut(receiver(list)).elements
+}
+
+/// Inserts `element` at `index` of `list`, counting any growth of its
+/// storage towards the size of the heap.
+pub(crate) fn list_insert_at(vm: &mut WrenVM, list: ObjRef, index: usize, element: Value) {
+    let elements = &mut vm.heap.list_mut(list).elements;
+    let old_capacity = elements.capacity();
+    elements.insert(index, element);
+    let new_capacity = elements.capacity();
+    let value_size = core::mem::size_of::<Value>();
+    vm.heap.resized(old_capacity * value_size, new_capacity * value_size);
+}
+
+fn list_clear(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    list_elements_mut(vm, args[0]).clear();
+    Ok(Value::Null)
+}
+
+fn list_insert(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    // The index may be one past the end, to append.
+    let count = list_elements(vm, args[0]).len();
+    let index = validate_index(vm, args[1], count + 1, "Index")?;
+    list_insert_at(vm, receiver(args[0]), index, args[2]);
+    Ok(args[2])
+}
+
+fn list_remove_at(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = list_elements(vm, args[0]).len();
+    let index = validate_index(vm, args[1], count, "Index")?;
+    Ok(list_elements_mut(vm, args[0]).remove(index))
+}
+
+fn list_find(vm: &WrenVM, list: Value, value: Value) -> Option<usize> {
+    list_elements(vm, list)
+	.iter()
+	.position(|&element| vm.values_equal(element, value))
+}
+
+fn list_remove_value(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    match list_find(vm, args[0], args[1]) {
+	Some(index) => Ok(list_elements_mut(vm, args[0]).remove(index)),
+	None => Ok(Value::Null),
+    }
+}
+
+fn list_index_of(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(match list_find(vm, args[0], args[1]) {
+	Some(index) => index as f64,
+	None => -1.0,
+    }))
+}
+
+fn list_swap(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = list_elements(vm, args[0]).len();
+    let a = validate_index(vm, args[1], count, "Index 0")?;
+    let b = validate_index(vm, args[2], count, "Index 1")?;
+    list_elements_mut(vm, args[0]).swap(a, b);
+    Ok(Value::Null)
+}
+
+fn list_add(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = list_elements(vm, args[0]).len();
+    list_insert_at(vm, receiver(args[0]), count, args[1]);
+    Ok(args[1])
+}
+
+// Used by list literals, where returning the list keeps it on the stack
+// for the next element.
+fn list_add_core(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = list_elements(vm, args[0]).len();
+    list_insert_at(vm, receiver(args[0]), count, args[1]);
+    Ok(args[0])
+}
+
+fn list_count(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(vm.heap.list(receiver(args[0])).elements.len() as f64))
+}
+
+fn list_iterate(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = vm.heap.list(receiver(args[0])).elements.len();
+    if args[1].is_null() {
+	return Ok(if count == 0 {
+	    Value::Bool(false)
+	} else {
+	    Value::Num(0.0)
+	});
+    }
+    let index = validate_int(vm, args[1], "Iterator")?;
+    if index < 0.0 || index >= count as f64 - 1.0 {
+	return Ok(Value::Bool(false));
+    }
+    Ok(Value::Num(index + 1.0))
+}
+
+fn list_iterator_value(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = vm.heap.list(receiver(args[0])).elements.len();
+    let index = validate_index(vm, args[1], count, "Iterator")?;
+    Ok(vm.heap.list(receiver(args[0])).elements[index])
+}
+
+fn list_subscript(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = list_elements(vm, args[0]).len();
+    if args[1].is_num() {
+	let index = validate_index(vm, args[1], count, "Subscript")?;
+	return Ok(list_elements(vm, args[0])[index]);
+    }
+    let range = validate_subscript_range(vm, args[1])?;
+    let (start, count, step) = calculate_range(vm, range, count)?;
+    let elements = list_elements(vm, args[0]);
+    let slice = (0..count)
+	.map(|i| elements[(start as isize + i as isize * step) as usize])
+	.collect();
+    Ok(vm.new_list(slice))
+}
+
+fn list_subscript_setter(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let count = vm.heap.list(receiver(args[0])).elements.len();
+    let index = validate_index(vm, args[1], count, "Subscript")?;
+    vm.heap.list_mut(receiver(args[0])).elements[index] = args[2];
+    Ok(args[2])
+}
+
+fn map_new(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Obj(vm.heap.alloc(Obj::Map(ObjMap::default()))))
+}
+
+// The table is kept at most 75% full, and starts with room for 16 entries.
+const MAP_LOAD_PERCENT: usize = 75;
+const MAP_MIN_CAPACITY: usize = 16;
+
+/// Thomas Wang's 64-bit integer hash, as used by wren_c.
+fn hash_bits(bits: u64) -> u32 {
+    let mut hash = (!bits).wrapping_add(bits << 18);
+    hash ^= hash >> 31;
+    hash = hash.wrapping_mul(21);
+    hash ^= hash >> 11;
+    hash = hash.wrapping_add(hash << 6);
+    hash ^= hash >> 22;
+    (hash & 0x3fffffff) as u32
+}
+
+fn hash_number(n: f64) -> u32 {
+    hash_bits(n.to_bits())
+}
+
+/// Whether two map keys are the same key. As in wren_c, numbers are only
+/// when their bits are, so 0 and -0 are different keys while NaN can be
+/// found again, even though `0 == -0` and `NaN != NaN`.
+fn keys_equal(vm: &WrenVM, a: Value, b: Value) -> bool {
+    match (a.as_num(), b.as_num()) {
+	(Some(a), Some(b)) => a.to_bits() == b.to_bits(),
+	_ => vm.values_equal(a, b),
+    }
+}
+
+/// Hashes a value that `is_valid_key` accepts.
+fn hash_value(vm: &WrenVM, value: Value) -> u32 {
+    match value {
+	Value::Bool(false) => 0,
+	Value::Null => 1,
+	Value::Bool(true) => 2,
+	Value::Num(n) => hash_number(n),
+	Value::Obj(obj) => match vm.heap.get(obj) {
+	    Obj::String(string) => string.hash,
+	    Obj::Range(range) => hash_number(range.from) ^ hash_number(range.to),
+	    Obj::Class(class) => hash_string(&class.name),
+	    _ => unreachable!("map key must be a value type"),
+	},
+    }
+}
+
+/// Finds the slot for `key`: the entry holding it if there is one,
+/// otherwise the slot it should be inserted into, preferring the first
+/// tombstone passed along the way. `None` if the table has no room at all.
+fn map_find_slot(vm: &WrenVM, map: ObjRef, key: Value) -> Option<(usize, bool)> {
+    let entries = &vm.heap.map(map).entries;
+    if entries.is_empty() {
+	return None;
+    }
+    let capacity = entries.len();
+    let mut index = hash_value(vm, key) as usize % capacity;
+    let mut tombstone = None;
+    for _ in 0..capacity {
+	match entries[index] {
+	    MapEntry::Empty => return Some((tombstone.unwrap_or(index), false)),
+	    MapEntry::Tombstone => {
+		tombstone.get_or_insert(index);
+	    }
+	    MapEntry::Full { key: existing, .. } => {
+		if keys_equal(vm, existing, key) {
+		    return Some((index, true));
+		}
+	    }
+	}
+	index = (index + 1) % capacity;
+    }
+    tombstone.map(|index| (index, false))
+}
+
+/// The index of the entry holding `key` in `map`, if any.
+pub(crate) fn map_find(vm: &WrenVM, map: ObjRef, key: Value) -> Option<usize> {
+    match map_find_slot(vm, map, key) {
+	Some((index, true)) => Some(index),
+	_ => None,
+    }
+}
+
+/// The value stored under `key` in `map`, if any.
+pub(crate) fn map_get(vm: &WrenVM, map: ObjRef, key: Value) -> Option<Value> {
+    let index = map_find(vm, map, key)?;
+    match vm.heap.map(map).entries[index] {
+	MapEntry::Full { value, .. } => Some(value),
+	_ => None,
+    }
+}
+
+/// Rebuilds `map`'s table with room for `capacity` entries.
+fn map_resize(vm: &mut WrenVM, map: ObjRef, capacity: usize) {
+    let old = core::mem::replace(
+	&mut vm.heap.map_mut(map).entries,
+	vec![MapEntry::Empty; capacity],
+    );
+    let entry_size = core::mem::size_of::<MapEntry>();
+    vm.heap.resized(old.len() * entry_size, capacity * entry_size);
+    for entry in old {
+	if let MapEntry::Full { key, value } = entry {
+	    let (index, _) = map_find_slot(vm, map, key).expect("resized map has room");
+	    vm.heap.map_mut(map).entries[index] = MapEntry::Full { key, value };
+	}
+    }
+}
+
+pub(crate) fn map_set(vm: &mut WrenVM, map: ObjRef, key: Value, value: Value) {
+    let ObjMap { entries, count } = vm.heap.map(map);
+    let capacity = entries.len();
+    if count + 1 > capacity * MAP_LOAD_PERCENT / 100 {
+	map_resize(vm, map, (capacity * 2).max(MAP_MIN_CAPACITY));
+    }
+    let (index, found) = map_find_slot(vm, map, key).expect("map has room");
+    let map = vm.heap.map_mut(map);
+    map.entries[index] = MapEntry::Full { key, value };
+    if !found {
+	map.count += 1;
+    }
+}
+
+/// Removes `key` from `map`, returning its value if it was present.
+pub(crate) fn map_remove(vm: &mut WrenVM, map: ObjRef, key: Value) -> Option<Value> {
+    let index = map_find(vm, map, key)?;
+    let object = vm.heap.map_mut(map);
+    let value = match core::mem::replace(&mut object.entries[index], MapEntry::Tombstone) {
+	MapEntry::Full { value, .. } => value,
+	_ => unreachable!("found entry is full"),
+    };
+    object.count -= 1;
+
+    let (count, capacity) = (object.count, object.entries.len());
+    if count == 0 {
+	map_resize(vm, map, 0);
+    } else if capacity > MAP_MIN_CAPACITY && count < capacity / 2 * MAP_LOAD_PERCENT / 100 {
+	map_resize(vm, map, (capacity / 2).max(MAP_MIN_CAPACITY));
+    }
+    Some(value)
+}
+
+fn map_add_core(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    validate_key(vm, args[1])?;
+    map_set(vm, receiver(args[0]), args[1], args[2]);
+    Ok(args[0])
+}
+
+fn map_clear(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let map = receiver(args[0]);
+    let object = vm.heap.map_mut(map);
+    let old = core::mem::take(&mut object.entries);
+    object.count = 0;
+    vm.heap.resized(old.len() * core::mem::size_of::<MapEntry>(), 0);
+    Ok(Value::Null)
+}
+
+fn map_contains_key(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    validate_key(vm, args[1])?;
+    Ok(Value::Bool(map_find(vm, receiver(args[0]), args[1]).is_some()))
+}
+
+fn map_count(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(vm.heap.map(receiver(args[0])).count as f64))
+}
+
+fn map_subscript(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    validate_key(vm, args[1])?;
+    Ok(map_get(vm, receiver(args[0]), args[1]).unwrap_or(Value::Null))
+}
+
+fn map_subscript_setter(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    validate_key(vm, args[1])?;
+    map_set(vm, receiver(args[0]), args[1], args[2]);
+    Ok(args[2])
+}
+
+fn map_remove_primitive(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    validate_key(vm, args[1])?;
+    Ok(map_remove(vm, receiver(args[0]), args[1]).unwrap_or(Value::Null))
+}
+
+/// Iterators over a map are indexes into its table, skipping empty slots.
+fn map_iterate(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let map = receiver(args[0]);
+    let capacity = vm.heap.map(map).entries.len();
+    let start = if args[1].is_null() {
+	0
+    } else {
+	let index = validate_int(vm, args[1], "Iterator")?;
+	// Iterators past the end are done, as are negative ones.
+	if index < 0.0 || index >= capacity as f64 {
+	    return Ok(Value::Bool(false));
+	}
+	index as usize + 1
+    };
+    let entries = &vm.heap.map(map).entries;
+    Ok((start..capacity)
+	.find(|&index| matches!(entries[index], MapEntry::Full { .. }))
+	.map_or(Value::Bool(false), |index| Value::Num(index as f64)))
+}
+
+fn map_entry_at(vm: &mut WrenVM, args: &[Value]) -> Result<(Value, Value), PrimitiveError> {
+    let capacity = vm.heap.map(receiver(args[0])).entries.len();
+    let index = validate_index(vm, args[1], capacity, "Iterator")?;
+    match vm.heap.map(receiver(args[0])).entries[index] {
+	MapEntry::Full { key, value } => Ok((key, value)),
+	_ => Err(vm.error("Invalid map iterator.")),
+    }
+}
+
+fn map_key_iterator_value(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(map_entry_at(vm, args)?.0)
+}
+
+fn map_value_iterator_value(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(map_entry_at(vm, args)?.1)
+}
+
+fn range_of(vm: &WrenVM, value: Value) -> ObjRange {
+    *vm.heap.range(value.as_obj().expect("range receiver"))
+}
+
+fn range_from(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(range_of(vm, args[0]).from))
+}
+
+fn range_to(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(range_of(vm, args[0]).to))
+}
+
+fn range_min(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let range = range_of(vm, args[0]);
+    Ok(Value::Num(range.from.min(range.to)))
+}
+
+fn range_max(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let range = range_of(vm, args[0]);
+    Ok(Value::Num(range.from.max(range.to)))
+}
+
+fn range_is_inclusive(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Bool(range_of(vm, args[0]).is_inclusive))
+}
+
+fn range_iterate(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let range = range_of(vm, args[0]);
+
+    // Special case: empty range.
+    if range.from == range.to && !range.is_inclusive {
+	return Ok(Value::Bool(false));
+    }
+
+    // Start the iteration.
+    if args[1].is_null() {
+	return Ok(Value::Num(range.from));
+    }
+
+    // Iterate towards `to` from `from`.
+    let mut iterator = validate_num(vm, args[1], "Iterator")?;
+    if range.from < range.to {
+	iterator += 1.0;
+	if iterator > range.to {
+	    return Ok(Value::Bool(false));
+	}
+    } else {
+	iterator -= 1.0;
+	if iterator < range.to {
+	    return Ok(Value::Bool(false));
+	}
+    }
+    if !range.is_inclusive && iterator == range.to {
+	return Ok(Value::Bool(false));
+    }
+    Ok(Value::Num(iterator))
+}
+
+fn range_iterator_value(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    // Assume the iterator is a number so that is the value of the range.
+    Ok(args[1])
+}
+
+fn fn_new(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    match args[1].as_obj() {
+	Some(obj) if matches!(vm.heap.get(obj), Obj::Closure(_)) => Ok(args[1]),
+	_ => Err(vm.error("Argument must be a function.")),
+    }
+}
+
+fn fn_arity(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let closure = vm.heap.closure(receiver(args[0]));
+    Ok(Value::Num(vm.heap.function(closure.function).body.arity as f64))
+}
+
+fn fiber_new(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let closure = match args[1].as_obj() {
+	Some(obj) if matches!(vm.heap.get(obj), Obj::Closure(_)) => obj,
+	_ => return Err(vm.error("Argument must be a function.")),
+    };
+    let function = vm.heap.closure(closure).function;
+    if vm.heap.function(function).body.arity > 1 {
+	return Err(vm.error("Function cannot take more than one parameter."));
+    }
+    let mut stack = vm.heap.take_values(1);
+    stack.push(Value::Obj(closure));
+    let frames = vm.heap.take_frames(1);
+    let fiber = ObjFiber::with_buffers(closure, stack, frames);
+    Ok(Value::Obj(vm.heap.alloc(Obj::Fiber(fiber))))
+}
+
+fn fiber_abort(_vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    // Aborting with null is a no-op, which returns the receiver as wren_c
+    // does.
+    if args[1].is_null() {
+	Ok(args[0])
+    } else {
+	Err(PrimitiveError::Error(args[1]))
+    }
+}
+
+fn fiber_current(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Obj(vm.fiber.expect("a running fiber")))
+}
+
+fn fiber_suspend(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    // Switching to no fiber stops the interpreter, leaving the current
+    // fiber to be resumed later.
+    vm.switch_fiber(None);
+    Err(PrimitiveError::FiberSwitch)
+}
+
+fn fiber_yield(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let current = vm.fiber.expect("a running fiber");
+    let fiber = vm.heap.fiber_mut(current);
+    let caller = fiber.caller.take();
+    fiber.state = FiberState::Other;
+
+    if caller.is_some() {
+	// When the yielding fiber resumes, the result of the yield call goes
+	// in the Fiber class's slot, so discard the value's slot.
+	if args.len() > 1 {
+	    vm.stack.pop();
+	}
+	vm.switch_fiber(caller);
+	// Make the caller's call return the yielded value.
+	*vm.stack.last_mut().expect("the call's slot") = args.get(1).copied().unwrap_or(Value::Null);
+    } else {
+	// Yielding from the root fiber stops the interpreter.
+	vm.switch_fiber(None);
+    }
+    Err(PrimitiveError::FiberSwitch)
+}
+
+// Switches to `fiber`, passing it the value in `args[1]`, if any. A `call`
+// records the current fiber as the one to return to.
+fn run_fiber(vm: &mut WrenVM, fiber: ObjRef, args: &[Value], is_call: bool, verb: &str) -> PrimitiveResult {
+    let target = vm.heap.fiber(fiber);
+    if target.has_error() {
+	return Err(vm.error(format!("Cannot {} an aborted fiber.", verb)));
+    }
+    if target.awaiting_host {
+	return Err(vm.error(format!("Cannot {} a fiber waiting on the host.", verb)));
+    }
+    if is_call {
+	// A called fiber can't be called again until it returns or yields,
+	// but it can be transferred to.
+	if target.caller.is_some() {
+	    return Err(vm.error("Fiber has already been called."));
+	}
+	if target.state == FiberState::Root {
+	    return Err(vm.error("Cannot call root fiber."));
+	}
+    }
+    if vm.fiber_frames(fiber).is_empty() {
+	return Err(vm.error(format!("Cannot {} a finished fiber.", verb)));
+    }
+    if is_call {
+	vm.heap.fiber_mut(fiber).caller = vm.fiber;
+    }
+
+    // The result of this call goes in the receiver's slot when the current
+    // fiber resumes, so the value's slot isn't needed.
+    let value = args.get(1).copied();
+    if value.is_some() {
+	vm.stack.pop();
+    }
+    vm.switch_fiber(Some(fiber));
+
+    let frames = &vm.frames;
+    let is_starting = frames.len() == 1 && frames[0].ip == 0;
+    if is_starting {
+	// Bind the value to the fiber function's parameter, if it has one.
+	let function = vm.heap.closure(frames[0].closure).function;
+	if vm.heap.function(function).body.arity == 1 {
+	    vm.stack.push(value.unwrap_or(Value::Null));
+	}
+    } else {
+	// Make the yield or transfer that suspended the fiber return the
+	// value. That includes a fiber transferring to itself, though wren_c
+	// returns the fiber instead until it first calls a method written in
+	// Wren, reading an instruction pointer it hasn't stored yet.
+	*vm.stack.last_mut().expect("the suspended call's slot") = value.unwrap_or(Value::Null);
+    }
+    Err(PrimitiveError::FiberSwitch)
+}
+
+fn fiber_call(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    run_fiber(vm, receiver(args[0]), args, true, "call")
+}
+
+fn fiber_error(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    Ok(vm.heap.fiber(receiver(args[0])).error)
+}
+
+fn fiber_is_done(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let fiber = receiver(args[0]);
+    let is_done = vm.fiber_frames(fiber).is_empty() || vm.heap.fiber(fiber).has_error();
+    Ok(Value::Bool(is_done))
+}
+
+fn fiber_transfer(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    run_fiber(vm, receiver(args[0]), args, false, "transfer to")
+}
+
+fn fiber_transfer_error(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    match run_fiber(vm, receiver(args[0]), &args[..1], false, "transfer to") {
+	// Raise the error in the fiber just switched to.
+	Err(PrimitiveError::FiberSwitch) => Err(PrimitiveError::Error(args[1])),
+	result => result,
+    }
+}
+
+fn fiber_try(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let result = run_fiber(vm, receiver(args[0]), args, true, "try");
+    // Only mark the fiber as tried if we switched to it.
+    if let Err(PrimitiveError::FiberSwitch) = result {
+	let fiber = vm.fiber.expect("the tried fiber");
+	vm.heap.fiber_mut(fiber).state = FiberState::Try;
+    }
+    result
+}
+
+fn system_clock(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    Ok(Value::Num(vm.clock()))
+}
+
+fn system_gc(vm: &mut WrenVM, _args: &[Value]) -> PrimitiveResult {
+    vm.collect_garbage();
+    Ok(Value::Null)
+}
+
+fn system_write_string(vm: &mut WrenVM, args: &[Value]) -> PrimitiveResult {
+    let text = string_of(vm, args[1]);
+    vm.write(&text);
+    Ok(args[1])
+}
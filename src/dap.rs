@@ -0,0 +1,422 @@
+// A server for the Debug Adapter Protocol, through which editors such as VS
+// Code debug programs. It is built on the VM's debug hooks.
+//
+// Messages are JSON, each preceded by a `Content-Length` header. A thread
+// reads the client's requests into a channel. While the program is paused
+// the server waits on it, and while the program runs it checks it on every
+// call and return, so requests such as `pause` are handled promptly.
+//
+// Wren has no threads of its own, so the client is shown a single thread
+// running whichever fiber is current.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::debug::{DebugAction, DebugHook, PauseReason};
+use crate::error::WrenError;
+use crate::protocol::{read_message, write_message};
+use crate::vm::WrenVM;
+
+const THREAD_ID: u64 = 1;
+
+/// Serves one client for the run of a program.
+///
+/// Source files are found the way `FileModuleLoader` finds them: the
+/// module `lib/util` is `lib/util.wren` below the root or one of the search
+/// paths. Modules from elsewhere, such as the main script, are given with
+/// `add_source`.
+pub struct DapServer {
+    session: Arc<Mutex<Session>>,
+}
+
+impl DapServer {
+    pub fn new(
+	input: impl Read + Send + 'static,
+	output: impl Write + Send + 'static,
+	root: impl Into<PathBuf>,
+    ) -> DapServer {
+	let (sender, messages) = mpsc::channel();
+	thread::spawn(move || {
+	    let mut input = BufReader::new(input);
+	    while let Ok(Some(message)) = read_message(&mut input) {
+		if sender.send(message).is_err() {
+		    break;
+		}
+	    }
+	});
+	DapServer {
+	    session: Arc::new(Mutex::new(Session {
+		messages,
+		output: Box::new(output),
+		seq: 0,
+		connected: true,
+		directories: vec![root.into()],
+		sources: HashMap::new(),
+		breakpoints: HashMap::new(),
+		pause_reason: None,
+	    })),
+	}
+    }
+
+    /// Adds a directory to look for source files in, after the root and any
+    /// search paths added before it.
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+	self.session.lock().unwrap().directories.push(path.into());
+    }
+
+    /// Shows the source of `module` as the file at `path`.
+    pub fn add_source(&mut self, module: &str, path: impl Into<PathBuf>) {
+	self.session.lock().unwrap().sources.insert(module.to_string(), path.into());
+    }
+
+    /// Waits for the client to finish configuring, then debugs `program`
+    /// as it runs in `vm`, and returns its result.
+    ///
+    /// If the client disconnects, the program carries on without it.
+    pub fn run(
+	self,
+	vm: &mut WrenVM,
+	program: impl FnOnce(&mut WrenVM) -> Result<(), WrenError>,
+    ) -> Result<(), WrenError> {
+	// Requests before `configurationDone` set up the session and its
+	// breakpoints.
+	loop {
+	    let mut session = self.session.lock().unwrap();
+	    match session.next_message(true) {
+		Some(message) => {
+		    if let Handled::Configured = session.handle(vm, &message) {
+			break;
+		    }
+		}
+		None => break,
+	    }
+	}
+
+	let previous = vm.set_debug_hook(Some(Box::new(Hook(Arc::clone(&self.session)))));
+	let result = program(vm);
+	vm.set_debug_hook(previous);
+
+	let mut session = self.session.lock().unwrap();
+	if let Err(error) = &result {
+	    let output = format!("{}\n", error);
+	    session.event("output", json!({ "category": "stderr", "output": output }));
+	}
+	session.event("terminated", json!({}));
+	// Answer what's left, such as the client's `disconnect`.
+	while let Some(message) = session.next_message(true) {
+	    session.handle(vm, &message);
+	}
+	session.detach(vm);
+	result
+    }
+}
+
+// The hook installed while the program runs, sharing the session with the
+// server that installed it.
+struct Hook(Arc<Mutex<Session>>);
+
+impl DebugHook for Hook {
+    fn on_line(&mut self, vm: &mut WrenVM, reason: PauseReason) -> DebugAction {
+	let mut session = self.0.lock().unwrap();
+	let reason = match (session.pause_reason.take(), reason) {
+	    (_, PauseReason::Breakpoint) => "breakpoint",
+	    (Some(requested), PauseReason::Step) => requested,
+	    (None, PauseReason::Step) => "step",
+	};
+	session.event(
+	    "stopped",
+	    json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+	);
+	while let Some(message) = session.next_message(true) {
+	    if let Handled::Resume(action) = session.handle(vm, &message) {
+		return action;
+	    }
+	}
+	DebugAction::Continue
+    }
+
+    fn on_call(&mut self, vm: &mut WrenVM) {
+	self.0.lock().unwrap().poll(vm);
+    }
+
+    fn on_return(&mut self, vm: &mut WrenVM) {
+	self.0.lock().unwrap().poll(vm);
+    }
+}
+
+// What handling a request asks the program to do.
+enum Handled {
+    Nothing,
+    /// Start running, now that the client is configured.
+    Configured,
+    /// Carry on from a pause.
+    Resume(DebugAction),
+}
+
+struct Session {
+    messages: Receiver<Value>,
+    output: Box<dyn Write + Send>,
+    /// The sequence number of the last message sent.
+    seq: u64,
+    connected: bool,
+    /// The root and search paths.
+    directories: Vec<PathBuf>,
+    sources: HashMap<String, PathBuf>,
+    /// The client's breakpoints, by module, so setting those of a source
+    /// can replace the ones set before.
+    breakpoints: HashMap<String, Vec<u32>>,
+    /// Why the next step pauses, when the client asked for the pause.
+    pause_reason: Option<&'static str>,
+}
+
+impl Session {
+    // The next request from the client, waiting for it if `wait` is set.
+    // Returns `None` if there is none, or the client has gone.
+    fn next_message(&mut self, wait: bool) -> Option<Value> {
+	if !self.connected {
+	    return None;
+	}
+	let received = if wait {
+	    self.messages.recv().map_err(|_| TryRecvError::Disconnected)
+	} else {
+	    self.messages.try_recv()
+	};
+	match received {
+	    Ok(message) => Some(message),
+	    Err(TryRecvError::Empty) => None,
+	    Err(TryRecvError::Disconnected) => {
+		self.connected = false;
+		None
+	    }
+	}
+    }
+
+    // Handles the requests that arrived while the program was running.
+    fn poll(&mut self, vm: &mut WrenVM) {
+	while let Some(message) = self.next_message(false) {
+	    // Nothing is paused, so there is nothing to resume.
+	    self.handle(vm, &message);
+	}
+	if !self.connected {
+	    self.detach(vm);
+	}
+    }
+
+    // Leaves the program to run on its own.
+    fn detach(&mut self, vm: &mut WrenVM) {
+	self.connected = false;
+	for (module, lines) in self.breakpoints.drain() {
+	    for line in lines {
+		vm.remove_breakpoint(&module, line);
+	    }
+	}
+    }
+
+    fn handle(&mut self, vm: &mut WrenVM, message: &Value) -> Handled {
+	if message["type"] != "request" {
+	    return Handled::Nothing;
+	}
+	let command = message["command"].as_str().unwrap_or("");
+	let arguments = &message["arguments"];
+	let mut handled = Handled::Nothing;
+	let body = match command {
+	    "initialize" => {
+		self.respond(message, Ok(json!({ "supportsConfigurationDoneRequest": true })));
+		self.event("initialized", json!({}));
+		return Handled::Nothing;
+	    }
+	    "launch" | "attach" => {
+		if arguments["stopOnEntry"] == true {
+		    vm.pause();
+		    self.pause_reason = Some("entry");
+		}
+		Ok(json!({}))
+	    }
+	    "setBreakpoints" => Ok(self.set_breakpoints(vm, arguments)),
+	    "setExceptionBreakpoints" => Ok(json!({})),
+	    "configurationDone" => {
+		handled = Handled::Configured;
+		Ok(json!({}))
+	    }
+	    "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+	    "stackTrace" => Ok(self.stack_trace(vm)),
+	    "scopes" => {
+		// Each frame has a single scope, numbered one more than the
+		// frame since zero means none.
+		let frame = arguments["frameId"].as_u64().unwrap_or(0);
+		Ok(json!({ "scopes": [{
+		    "name": "Locals",
+		    "variablesReference": frame + 1,
+		    "expensive": false,
+		}] }))
+	    }
+	    "variables" => {
+		let frame = arguments["variablesReference"].as_u64().unwrap_or(0);
+		let variables: Vec<Value> = match frame.checked_sub(1) {
+		    Some(frame) => vm
+			.debug_locals(frame as usize)
+			.into_iter()
+			.map(|local| {
+			    json!({
+				"name": local.name,
+				"value": local.value,
+				"variablesReference": 0,
+			    })
+			})
+			.collect(),
+		    None => Vec::new(),
+		};
+		Ok(json!({ "variables": variables }))
+	    }
+	    "continue" => {
+		handled = Handled::Resume(DebugAction::Continue);
+		Ok(json!({ "allThreadsContinued": true }))
+	    }
+	    "next" => {
+		handled = Handled::Resume(DebugAction::StepOver);
+		Ok(json!({}))
+	    }
+	    "stepIn" => {
+		handled = Handled::Resume(DebugAction::StepIn);
+		Ok(json!({}))
+	    }
+	    "stepOut" => {
+		handled = Handled::Resume(DebugAction::StepOut);
+		Ok(json!({}))
+	    }
+	    "pause" => {
+		vm.pause();
+		self.pause_reason = Some("pause");
+		Ok(json!({}))
+	    }
+	    "disconnect" | "terminate" => {
+		self.respond(message, Ok(json!({})));
+		self.detach(vm);
+		return Handled::Resume(DebugAction::Continue);
+	    }
+	    _ => Err(format!("Unsupported request '{}'.", command)),
+	};
+	self.respond(message, body);
+	handled
+    }
+
+    fn set_breakpoints(&mut self, vm: &mut WrenVM, arguments: &Value) -> Value {
+	let path = arguments["source"]["path"].as_str().unwrap_or("");
+	let module = self.module_for_path(Path::new(path));
+	let lines: Vec<u32> = arguments["breakpoints"]
+	    .as_array()
+	    .map(|breakpoints| {
+		breakpoints
+		    .iter()
+		    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+		    .map(|line| line as u32)
+		    .collect()
+	    })
+	    .unwrap_or_default();
+	if let Some(module) = &module {
+	    for line in self.breakpoints.remove(module).unwrap_or_default() {
+		vm.remove_breakpoint(module, line);
+	    }
+	    for &line in &lines {
+		vm.set_breakpoint(module, line);
+	    }
+	    self.breakpoints.insert(module.clone(), lines.clone());
+	}
+	let breakpoints: Vec<Value> = lines
+	    .iter()
+	    .map(|line| json!({ "verified": module.is_some(), "line": line }))
+	    .collect();
+	json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self, vm: &WrenVM) -> Value {
+	let frames: Vec<Value> = vm
+	    .debug_frames()
+	    .into_iter()
+	    .enumerate()
+	    .map(|(id, frame)| {
+		let path = self.path_for_module(&frame.module);
+		json!({
+		    "id": id,
+		    "name": frame.function,
+		    "line": frame.line,
+		    "column": 1,
+		    "source": { "name": frame.module, "path": path.to_string_lossy() },
+		})
+	    })
+	    .collect();
+	json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn path_for_module(&self, module: &str) -> PathBuf {
+	if let Some(path) = self.sources.get(module) {
+	    return fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+	}
+	// The first file that exists, or the one below the root.
+	let file = format!("{}.wren", module);
+	let paths: Vec<PathBuf> =
+	    self.directories.iter().map(|directory| directory.join(&file)).collect();
+	paths
+	    .iter()
+	    .find_map(|path| fs::canonicalize(path).ok())
+	    .unwrap_or_else(|| paths[0].clone())
+    }
+
+    fn module_for_path(&self, path: &Path) -> Option<String> {
+	let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+	let path = canonical(path);
+	let source = self.sources.iter().find(|(_, source)| canonical(source) == path);
+	if let Some((module, _)) = source {
+	    return Some(module.clone());
+	}
+	if path.extension()? != "wren" {
+	    return None;
+	}
+	let relative = self
+	    .directories
+	    .iter()
+	    .find_map(|directory| path.strip_prefix(canonical(directory)).ok())?
+	    .with_extension("");
+	let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+	Some(parts?.join("/"))
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) {
+	let mut response = json!({
+	    "type": "response",
+	    "request_seq": request["seq"],
+	    "command": request["command"],
+	    "success": body.is_ok(),
+	});
+	match body {
+	    Ok(body) => response["body"] = body,
+	    Err(message) => response["message"] = Value::String(message),
+	}
+	self.send(response);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+	self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) {
+	if !self.connected {
+	    return;
+	}
+	self.seq += 1;
+	message["seq"] = json!(self.seq);
+	if write_message(&mut self.output, &message).is_err() {
+	    self.connected = false;
+	}
+    }
+}
@@ -0,0 +1,245 @@
+// Hooks for building a debugger on the VM.
+//
+// A host installs a `DebugHook`, which the VM calls as functions are called
+// and return, and before the first instruction of a line runs when that
+// line has a breakpoint or the hook asked to step. Execution is paused for
+// as long as the hook runs, so it can inspect the running fiber with
+// `debug_frames` and `debug_locals`, change breakpoints, and then return
+// how to carry on.
+//
+// Functions of the core library are skipped, as they are in stack traces.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use ::core::mem;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::chunk::Code;
+use crate::core;
+use crate::error::StackFrame;
+use crate::value::{CallFrame, FnBody, ObjRef};
+use crate::vm::{MaybeSend, WrenVM};
+
+/// How to carry on once a `DebugHook` has paused at a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next line run, in any function.
+    StepIn,
+    /// Pause at the next line of this function or one that called it.
+    StepOver,
+    /// Pause at the next line of a function that called this one.
+    StepOut,
+}
+
+/// Why a `DebugHook` was called for a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+}
+
+/// Receives events from the VM while it runs Wren code.
+///
+/// The VM is paused while a method runs. It can be inspected and its
+/// breakpoints changed, but the hook must not run code in it.
+pub trait DebugHook: MaybeSend {
+    /// Called before a line runs, when it has a breakpoint or is where a
+    /// step ends. Returns how to carry on.
+    fn on_line(&mut self, vm: &mut WrenVM, reason: PauseReason) -> DebugAction;
+
+    /// Called when a function is called, before any of it runs.
+    fn on_call(&mut self, _vm: &mut WrenVM) {}
+
+    /// Called when a function is about to return.
+    fn on_return(&mut self, _vm: &mut WrenVM) {}
+}
+
+/// A local variable of a paused function, with its value as `toString`
+/// would show it, except that strings are quoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugVariable {
+    pub name: String,
+    pub value: String,
+}
+
+pub(crate) struct DebugState {
+    hook: Option<Box<dyn DebugHook>>,
+    /// Lines with a breakpoint, by module name.
+    breakpoints: HashMap<String, HashSet<u32>>,
+    step: DebugAction,
+    /// How many frames the fiber had when the step began.
+    step_depth: usize,
+    /// Whether the running frame was just pushed and hasn't run yet.
+    pub(crate) entering: bool,
+    /// Whether the hook is running, during which the running frame's
+    /// instruction pointer is at the instruction about to run rather than
+    /// past the last one.
+    paused: bool,
+}
+
+impl Default for DebugState {
+    fn default() -> DebugState {
+	DebugState {
+	    hook: None,
+	    breakpoints: HashMap::new(),
+	    step: DebugAction::Continue,
+	    step_depth: 0,
+	    entering: false,
+	    paused: false,
+	}
+    }
+}
+
+impl DebugState {
+    pub(crate) fn is_enabled(&self) -> bool {
+	self.hook.is_some()
+    }
+}
+
+impl WrenVM {
+    /// Installs `hook`, or removes the current one if it is `None`.
+    /// Returns the hook it replaces.
+    pub fn set_debug_hook(
+	&mut self,
+	hook: Option<Box<dyn DebugHook>>,
+    ) -> Option<Box<dyn DebugHook>> {
+	mem::replace(&mut self.debug.hook, hook)
+    }
+
+    /// Pauses at `line` of the module named `module` whenever it runs.
+    pub fn set_breakpoint(&mut self, module: &str, line: u32) {
+	self.debug.breakpoints.entry(module.to_string()).or_default().insert(line);
+    }
+
+    /// Removes a breakpoint, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, module: &str, line: u32) -> bool {
+	match self.debug.breakpoints.get_mut(module) {
+	    Some(lines) => lines.remove(&line),
+	    None => false,
+	}
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+	self.debug.breakpoints.clear();
+    }
+
+    /// Pauses at the next line run, as if stepping into it.
+    pub fn pause(&mut self) {
+	self.debug.step = DebugAction::StepIn;
+    }
+
+    /// The calls running in the current fiber, innermost first.
+    pub fn debug_frames(&self) -> Vec<StackFrame> {
+	self.debug_call_frames()
+	    .into_iter()
+	    .map(|(frame, offset)| {
+		let function = self.heap.function(self.heap.closure(frame.closure).function);
+		StackFrame {
+		    module: self.heap.module(function.module).name.clone(),
+		    line: function.body.lines[offset],
+		    function: function.body.name.clone(),
+		}
+	    })
+	    .collect()
+    }
+
+    /// The local variables in scope in a frame from `debug_frames`, by
+    /// its index there, in the order they were declared.
+    pub fn debug_locals(&self, frame: usize) -> Vec<DebugVariable> {
+	let (frame, offset) = match self.debug_call_frames().get(frame) {
+	    Some(&found) => found,
+	    None => return Vec::new(),
+	};
+	let body = &self.heap.function(self.heap.closure(frame.closure).function).body;
+	body.locals
+	    .iter()
+	    .filter(|local| local.start <= offset && offset < local.end)
+	    .filter_map(|local| {
+		let value = *self.stack.get(frame.base + local.slot)?;
+		let value = match self.heap.as_str(value) {
+		    Some(string) => format!("{:?}", string),
+		    None => core::value_to_string(self, value),
+		};
+		Some(DebugVariable {
+		    name: local.name.clone(),
+		    value,
+		})
+	    })
+	    .collect()
+    }
+
+    // The current fiber's frames outside the core library, innermost
+    // first, with the offset of the instruction each is running.
+    pub(crate) fn debug_call_frames(&self) -> Vec<(CallFrame, usize)> {
+	let top = self.frames.len().saturating_sub(1);
+	self.frames
+	    .iter()
+	    .enumerate()
+	    .rev()
+	    .filter(|(_, frame)| {
+		let function = self.heap.closure(frame.closure).function;
+		self.heap.function(function).module != self.core_module
+	    })
+	    .map(|(index, frame)| {
+		let offset = if self.debug.paused && index == top {
+		    frame.ip
+		} else {
+		    frame.ip.saturating_sub(1)
+		};
+		(*frame, offset)
+	    })
+	    .collect()
+    }
+
+    // Reports the instruction at `ip` of the running frame to the hook
+    // before it runs. The frame's instruction pointer must be stored.
+    pub(crate) fn debug_instruction(&mut self, body: &FnBody, module: ObjRef, ip: usize) {
+	if mem::take(&mut self.debug.entering) {
+	    self.debug_event(|hook, vm| hook.on_call(vm));
+	}
+	let line = body.lines[ip];
+	if ip == 0 || body.lines[ip - 1] != line {
+	    let depth = self.frames.len();
+	    let has_breakpoint = self
+		.debug
+		.breakpoints
+		.get(&self.heap.module(module).name)
+		.is_some_and(|lines| lines.contains(&line));
+	    let reason = match self.debug.step {
+		_ if has_breakpoint => Some(PauseReason::Breakpoint),
+		DebugAction::StepIn => Some(PauseReason::Step),
+		DebugAction::StepOver if depth <= self.debug.step_depth => Some(PauseReason::Step),
+		DebugAction::StepOut if depth < self.debug.step_depth => Some(PauseReason::Step),
+		_ => None,
+	    };
+	    if let Some(reason) = reason {
+		if let Some(action) = self.debug_event(|hook, vm| hook.on_line(vm, reason)) {
+		    self.debug.step = action;
+		    self.debug.step_depth = depth;
+		}
+	    }
+	}
+	if body.code[ip] == Code::Return as u8 {
+	    self.debug_event(|hook, vm| hook.on_return(vm));
+	}
+    }
+
+    fn debug_event<T>(
+	&mut self,
+	event: impl FnOnce(&mut dyn DebugHook, &mut WrenVM) -> T,
+    ) -> Option<T> {
+	let mut hook = self.debug.hook.take()?;
+	self.debug.paused = true;
+	let result = event(hook.as_mut(), self);
+	self.debug.paused = false;
+	// Keep a hook installed while this one ran.
+	if self.debug.hook.is_none() {
+	    self.debug.hook = Some(hook);
+	}
+	Some(result)
+    }
+}
@@ -0,0 +1,103 @@
+// Checking source without running it, for tools that only need to know
+// whether a script is valid, such as editors and CI pipelines.
+//
+// Errors and warnings from every stage come out as `Diagnostic`s, so they
+// can be collected and shown the same way whichever stage found them.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::ast::Module;
+use crate::compiler::CompileError;
+use crate::lexer::Span;
+use crate::lint::Lint;
+use crate::parser::{self, ParseError, ParseOptions};
+use crate::vm::WrenVM;
+
+/// How serious a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The source can't run.
+    Error,
+    /// The source runs, but is probably a mistake.
+    Warning,
+}
+
+/// An error or warning about source code, with where it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Text of the offending token, "\n" for a newline, empty if there is
+    /// none to show.
+    pub token: String,
+    /// Whether the error was found at the end of the input.
+    pub at_end: bool,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	let severity = match self.severity {
+	    Severity::Error => "Error",
+	    Severity::Warning => "Warning",
+	};
+	write!(f, "[line {}] {}", self.span.line, severity)?;
+	parser::write_location(f, &self.token, self.at_end)?;
+	write!(f, ": {}", self.message)
+    }
+}
+
+impl From<CompileError> for Diagnostic {
+    fn from(error: CompileError) -> Diagnostic {
+	Diagnostic {
+	    severity: Severity::Error,
+	    message: error.message,
+	    span: error.span,
+	    token: error.token,
+	    at_end: error.at_end,
+	}
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Diagnostic {
+	CompileError::from(error).into()
+    }
+}
+
+impl From<Lint> for Diagnostic {
+    fn from(lint: Lint) -> Diagnostic {
+	Diagnostic {
+	    severity: Severity::Warning,
+	    message: lint.message,
+	    span: lint.span,
+	    token: String::new(),
+	    at_end: false,
+	}
+    }
+}
+
+/// Parses a complete source file into its syntax tree, or finds its
+/// syntax errors, going on past each to the next statement.
+pub fn parse(source: &str) -> Result<Module, Vec<Diagnostic>> {
+    let (module, errors) = parser::parse_recovering(source, &ParseOptions::default());
+    if errors.is_empty() {
+	Ok(module)
+    } else {
+	Err(errors.into_iter().map(Diagnostic::from).collect())
+    }
+}
+
+/// Compiles `source` as a module of its own without running it, finding
+/// everything `parse` does along with variables that are used but never
+/// defined and the other errors of resolving names, all in one pass.
+pub fn check(source: &str) -> Result<(), Vec<Diagnostic>> {
+    let mut vm = WrenVM::new();
+    let module = vm.get_module("main");
+    match vm.compile_in_module(module, source, false) {
+	Ok(_) => Ok(()),
+	Err(errors) => Err(errors.into_iter().map(Diagnostic::from).collect()),
+    }
+}
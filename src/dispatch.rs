@@ -0,0 +1,683 @@
+// An interpreter loop that dispatches through a table of a function for
+// each instruction, rather than one `match`, with the `threaded-dispatch`
+// feature. It runs exactly what `run` does, so the two can be compared.
+//
+// The frame's registers, which `run` keeps in locals, live in a struct the
+// handlers take by reference. Stable Rust can't promise tail calls, so a
+// handler returns to the loop, which calls the next one through the table,
+// instead of calling it itself. Whether that beats the jump table a `match`
+// compiles to depends on the compiler and the machine.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::chunk::{Code, CODES};
+use crate::config::INTERRUPT_INTERVAL;
+use crate::error::WrenError;
+use crate::parser::MAX_PARAMETERS;
+use crate::value::*;
+use crate::vm::{Method, PrimitiveError, WrenVM};
+
+// The running frame, as the handlers see it.
+struct Registers {
+    closure: ObjRef,
+    body: Arc<FnBody>,
+    module: ObjRef,
+    field_base: usize,
+    ip: usize,
+    base: usize,
+}
+
+impl Registers {
+    // Loads the frame on top of `vm`'s stack of frames.
+    fn load(vm: &WrenVM) -> Registers {
+	let frame = *vm.frames.last().expect("a frame to run");
+	let closure = vm.heap.closure(frame.closure);
+	let function = vm.heap.function(closure.function);
+	Registers {
+	    closure: frame.closure,
+	    body: Arc::clone(&function.body),
+	    module: function.module,
+	    field_base: closure.field_base,
+	    ip: frame.ip,
+	    base: frame.base,
+	}
+    }
+
+    fn store(&self, vm: &mut WrenVM) {
+	vm.frames.last_mut().expect("a running frame").ip = self.ip;
+    }
+
+    fn read_byte(&mut self) -> u8 {
+	self.ip += 1;
+	self.body.code[self.ip - 1]
+    }
+
+    fn read_short(&mut self) -> usize {
+	self.ip += 2;
+	u16::from_be_bytes([self.body.code[self.ip - 2], self.body.code[self.ip - 1]]) as usize
+    }
+}
+
+// What the loop does once a handler returns.
+enum Flow {
+    Next,
+    // Raise the error in the current fiber, whose frame has been stored.
+    Throw(Value),
+    Stop(Result<Value, WrenError>),
+}
+
+type Handler = fn(&mut WrenVM, &mut Registers, Code) -> Flow;
+
+// Each instruction's handler, by opcode.
+static HANDLERS: [Handler; CODES.len()] = {
+    let mut handlers = [not_emitted as Handler; CODES.len()];
+    handlers[Code::Constant as usize] = load_constant;
+    handlers[Code::Null as usize] = load_null;
+    handlers[Code::False as usize] = load_false;
+    handlers[Code::True as usize] = load_true;
+    let mut code = Code::LoadLocal0 as usize;
+    while code <= Code::LoadLocal8 as usize {
+	handlers[code] = load_local_n;
+	code += 1;
+    }
+    handlers[Code::LoadLocal as usize] = load_local;
+    handlers[Code::StoreLocal as usize] = store_local;
+    handlers[Code::LoadUpvalue as usize] = load_upvalue;
+    handlers[Code::StoreUpvalue as usize] = store_upvalue;
+    handlers[Code::LoadModuleVar as usize] = load_module_var;
+    handlers[Code::StoreModuleVar as usize] = store_module_var;
+    handlers[Code::LoadFieldThis as usize] = load_field_this;
+    handlers[Code::StoreFieldThis as usize] = store_field_this;
+    handlers[Code::LoadField as usize] = load_field;
+    handlers[Code::StoreField as usize] = store_field;
+    handlers[Code::Pop as usize] = pop;
+    let mut code = Code::Call0 as usize;
+    while code <= Code::Super16 as usize {
+	handlers[code] = call;
+	code += 1;
+    }
+    handlers[Code::Jump as usize] = jump;
+    handlers[Code::Loop as usize] = loop_;
+    handlers[Code::JumpIf as usize] = jump_if;
+    handlers[Code::And as usize] = and;
+    handlers[Code::Or as usize] = or;
+    handlers[Code::CloseUpvalue as usize] = close_upvalue;
+    handlers[Code::Return as usize] = return_;
+    handlers[Code::Closure as usize] = closure;
+    handlers[Code::Construct as usize] = construct;
+    handlers[Code::ForeignConstruct as usize] = foreign_construct;
+    handlers[Code::Class as usize] = class;
+    handlers[Code::EndClass as usize] = end_class;
+    handlers[Code::ForeignClass as usize] = foreign_class;
+    handlers[Code::MethodInstance as usize] = method;
+    handlers[Code::MethodStatic as usize] = method;
+    handlers[Code::EndModule as usize] = end_module;
+    handlers[Code::ImportModule as usize] = import_module;
+    handlers[Code::ImportVariable as usize] = import_variable;
+    #[cfg(feature = "superinstructions")]
+    {
+	handlers[Code::ConstantCall1 as usize] = constant_call1;
+	handlers[Code::LoadLocalCall1 as usize] = load_local_call1;
+	handlers[Code::Call1JumpIf as usize] = call1_jump_if;
+    }
+    handlers
+};
+
+impl WrenVM {
+    // Runs the frames on the stack until the bottom one returns, like
+    // `run`.
+    pub(crate) fn run_threaded(&mut self) -> Result<Value, WrenError> {
+	let mut registers = Registers::load(self);
+	self.debug.entering = registers.ip == 0;
+	loop {
+	    if let Some(fuel) = &mut self.fuel {
+		if *fuel == 0 {
+		    registers.store(self);
+		    return Err(self.abort_all(WrenError::Timeout));
+		}
+		*fuel -= 1;
+	    }
+	    if let Some(interrupt) = self.config.interrupt_fn {
+		self.interrupt_countdown -= 1;
+		if self.interrupt_countdown == 0 {
+		    self.interrupt_countdown = INTERRUPT_INTERVAL;
+		    registers.store(self);
+		    if interrupt(self) {
+			return Err(self.abort_all(WrenError::Timeout));
+		    }
+		}
+	    }
+	    if self.debug.is_enabled() {
+		if registers.module != self.core_module {
+		    registers.store(self);
+		    self.debug_instruction(&registers.body, registers.module, registers.ip);
+		}
+		self.debug.entering = false;
+	    }
+	    let code = Code::from_u8(registers.read_byte()).expect("valid opcode");
+	    match HANDLERS[code as usize](self, &mut registers, code) {
+		Flow::Next => {}
+		Flow::Throw(error) => match self.runtime_error(error) {
+		    // Carry on in whichever fiber caught it.
+		    Ok(()) => registers = Registers::load(self),
+		    Err(error) => return Err(error),
+		},
+		Flow::Stop(result) => return result,
+	    }
+	}
+    }
+}
+
+fn peek(vm: &WrenVM) -> Value {
+    *vm.stack.last().expect("stack underflow")
+}
+
+fn pop_value(vm: &mut WrenVM) -> Value {
+    vm.stack.pop().expect("stack underflow")
+}
+
+// Raises `error` in the current fiber.
+fn raise(vm: &mut WrenVM, registers: &Registers, error: Value) -> Flow {
+    registers.store(vm);
+    Flow::Throw(error)
+}
+
+fn maybe_collect(vm: &mut WrenVM, registers: &Registers) -> Flow {
+    match vm.maybe_collect() {
+	Ok(()) => Flow::Next,
+	Err(error) => raise(vm, registers, error),
+    }
+}
+
+// Calls `closure`, whose receiver or first argument is at `base`.
+fn push_frame(vm: &mut WrenVM, registers: &mut Registers, closure: ObjRef, base: usize) -> Flow {
+    if vm.frames.len() >= vm.config.max_call_depth || vm.stack.len() >= vm.config.max_stack_size
+    {
+	return Flow::Stop(Err(vm.abort_all(WrenError::StackOverflow)));
+    }
+    registers.store(vm);
+    vm.frames.push(CallFrame { closure, ip: 0, base });
+    vm.debug.entering = true;
+    *registers = Registers::load(vm);
+    Flow::Next
+}
+
+fn not_emitted(_: &mut WrenVM, _: &mut Registers, code: Code) -> Flow {
+    unreachable!("{:?} is not emitted by the compiler", code)
+}
+
+fn load_constant(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    vm.stack.push(registers.body.constants[index]);
+    Flow::Next
+}
+
+fn load_null(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    vm.stack.push(Value::Null);
+    Flow::Next
+}
+
+fn load_false(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    vm.stack.push(Value::Bool(false));
+    Flow::Next
+}
+
+fn load_true(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    vm.stack.push(Value::Bool(true));
+    Flow::Next
+}
+
+fn load_local_n(vm: &mut WrenVM, registers: &mut Registers, code: Code) -> Flow {
+    let slot = code as usize - Code::LoadLocal0 as usize;
+    vm.stack.push(vm.stack[registers.base + slot]);
+    Flow::Next
+}
+
+fn load_local(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let slot = registers.read_byte() as usize;
+    vm.stack.push(vm.stack[registers.base + slot]);
+    Flow::Next
+}
+
+fn store_local(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let slot = registers.read_byte() as usize;
+    vm.stack[registers.base + slot] = peek(vm);
+    Flow::Next
+}
+
+fn load_upvalue(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_byte() as usize;
+    let upvalue = vm.heap.closure(registers.closure).upvalues[index];
+    vm.stack.push(vm.upvalue_value(upvalue));
+    Flow::Next
+}
+
+fn store_upvalue(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_byte() as usize;
+    let upvalue = vm.heap.closure(registers.closure).upvalues[index];
+    vm.set_upvalue(upvalue, peek(vm));
+    Flow::Next
+}
+
+fn load_module_var(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let value = vm.heap.module(registers.module).variables[index];
+    vm.stack.push(value);
+    Flow::Next
+}
+
+fn store_module_var(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let value = peek(vm);
+    vm.heap.module_mut(registers.module).variables[index] = value;
+    Flow::Next
+}
+
+fn load_field_this(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let field = registers.field_base + registers.read_byte() as usize;
+    let receiver = vm.stack[registers.base].as_obj().expect("instance receiver");
+    let value = vm.heap.instance(receiver).fields[field];
+    vm.stack.push(value);
+    Flow::Next
+}
+
+fn store_field_this(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let field = registers.field_base + registers.read_byte() as usize;
+    let receiver = vm.stack[registers.base].as_obj().expect("instance receiver");
+    let value = peek(vm);
+    vm.heap.instance_mut(receiver).fields[field] = value;
+    Flow::Next
+}
+
+fn load_field(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let field = registers.field_base + registers.read_byte() as usize;
+    let instance = pop_value(vm).as_obj().expect("instance");
+    let value = vm.heap.instance(instance).fields[field];
+    vm.stack.push(value);
+    Flow::Next
+}
+
+fn store_field(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let field = registers.field_base + registers.read_byte() as usize;
+    let instance = pop_value(vm).as_obj().expect("instance");
+    let value = peek(vm);
+    vm.heap.instance_mut(instance).fields[field] = value;
+    Flow::Next
+}
+
+fn pop(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    pop_value(vm);
+    Flow::Next
+}
+
+fn call(vm: &mut WrenVM, registers: &mut Registers, code: Code) -> Flow {
+    let symbol = registers.read_short();
+    let num_args = code.arity().expect("call arity") + 1;
+    let args_start = vm.stack.len() - num_args;
+    let receiver = vm.stack[args_start];
+    let class = if (code as u8) < Code::Super0 as u8 {
+	vm.class_of(receiver)
+    } else {
+	// Look the method up on the superclass of the class the running
+	// method is bound to.
+	let bound = vm.heap.closure(registers.closure).class.expect("super in a method");
+	vm.heap.class(bound).superclass.expect("a superclass")
+    };
+    match vm.heap.class(class).method(symbol) {
+	Some(Method::Primitive(primitive)) => {
+	    let mut args = [Value::Null; MAX_PARAMETERS + 1];
+	    args[..num_args].copy_from_slice(&vm.stack[args_start..]);
+	    registers.store(vm);
+	    match primitive(vm, &args[..num_args]) {
+		Ok(result) => {
+		    vm.stack.truncate(args_start);
+		    vm.stack.push(result);
+		    maybe_collect(vm, registers)
+		}
+		// The frame was already stored, and the current fiber may
+		// have changed since.
+		Err(PrimitiveError::Error(error)) => Flow::Throw(error),
+		Err(PrimitiveError::FiberSwitch) => {
+		    if vm.fiber.is_none() {
+			return Flow::Stop(Ok(Value::Null));
+		    }
+		    *registers = Registers::load(vm);
+		    // A fiber that hasn't run yet starts a call.
+		    vm.debug.entering = registers.ip == 0;
+		    Flow::Next
+		}
+	    }
+	}
+	Some(Method::FunctionCall) => {
+	    let closure = receiver.as_obj().expect("function receiver");
+	    let function = vm.heap.closure(closure).function;
+	    let arity = vm.heap.function(function).body.arity;
+	    if num_args - 1 < arity {
+		let error = vm.new_string("Function expects more arguments.");
+		return raise(vm, registers, error);
+	    }
+	    // Drop any extra arguments so they don't occupy the slots of the
+	    // function's locals.
+	    vm.stack.truncate(args_start + 1 + arity);
+	    push_frame(vm, registers, closure, args_start)
+	}
+	Some(foreign @ (Method::Foreign(_) | Method::ForeignClosure(_))) => {
+	    registers.store(vm);
+	    vm.call_foreign(foreign, symbol, args_start);
+	    let fiber = vm.fiber.expect("a running fiber");
+	    if vm.heap.fiber(fiber).has_error() {
+		return Flow::Throw(vm.heap.fiber(fiber).error);
+	    }
+	    // A method that suspended the fiber stops the interpreter until
+	    // the host resumes it.
+	    if vm.heap.fiber(fiber).awaiting_host {
+		vm.switch_fiber(None);
+		return Flow::Stop(Ok(Value::Null));
+	    }
+	    maybe_collect(vm, registers)
+	}
+	Some(Method::Block(closure)) => push_frame(vm, registers, closure, args_start),
+	None => {
+	    let error = vm.method_not_found(class, symbol);
+	    raise(vm, registers, error)
+	}
+    }
+}
+
+fn jump(_: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let offset = registers.read_short();
+    registers.ip += offset;
+    Flow::Next
+}
+
+fn loop_(_: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let offset = registers.read_short();
+    registers.ip -= offset;
+    Flow::Next
+}
+
+fn jump_if(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let offset = registers.read_short();
+    if pop_value(vm).is_falsy() {
+	registers.ip += offset;
+    }
+    Flow::Next
+}
+
+fn and(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let offset = registers.read_short();
+    if peek(vm).is_falsy() {
+	// Short-circuit, leaving the left operand as the result.
+	registers.ip += offset;
+    } else {
+	pop_value(vm);
+    }
+    Flow::Next
+}
+
+fn or(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let offset = registers.read_short();
+    if peek(vm).is_falsy() {
+	pop_value(vm);
+    } else {
+	registers.ip += offset;
+    }
+    Flow::Next
+}
+
+fn close_upvalue(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    vm.close_upvalues(vm.stack.len() - 1);
+    pop_value(vm);
+    Flow::Next
+}
+
+fn return_(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let result = pop_value(vm);
+    // Close any upvalues still referring to the frame's locals.
+    vm.close_upvalues(registers.base);
+    vm.frames.pop();
+    vm.stack.truncate(registers.base);
+    if vm.frames.is_empty() {
+	// The fiber is complete. Return to the fiber that ran it, or stop if
+	// there isn't one.
+	let fiber = vm.fiber.expect("a running fiber");
+	match vm.heap.fiber_mut(fiber).caller.take() {
+	    Some(caller) => {
+		vm.switch_fiber(Some(caller));
+		*vm.stack.last_mut().expect("the call's slot") = result;
+	    }
+	    None => {
+		vm.stack.push(result);
+		return Flow::Stop(Ok(result));
+	    }
+	}
+    } else {
+	// The result replaces the receiver and arguments.
+	vm.stack.push(result);
+    }
+    *registers = Registers::load(vm);
+    Flow::Next
+}
+
+fn closure(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let function = registers.body.constants[index].as_obj().expect("function constant");
+    let num_upvalues = vm.heap.function(function).body.num_upvalues;
+    let mut upvalues = match num_upvalues {
+	0 => Vec::new(),
+	_ => vm.heap.take_refs(num_upvalues),
+    };
+    for _ in 0..num_upvalues {
+	let is_local = registers.read_byte() != 0;
+	let index = registers.read_byte() as usize;
+	upvalues.push(if is_local {
+	    // Capture a local of the enclosing function.
+	    vm.capture_upvalue(registers.base + index)
+	} else {
+	    // Share one of the enclosing function's upvalues.
+	    vm.heap.closure(registers.closure).upvalues[index]
+	});
+    }
+    // Functions nested in a method belong to the same class.
+    let enclosing = vm.heap.closure(registers.closure);
+    let new_closure = ObjClosure {
+	upvalues,
+	class: enclosing.class,
+	field_base: enclosing.field_base,
+	..ObjClosure::new(function)
+    };
+    let new_closure = vm.heap.alloc(Obj::Closure(new_closure));
+    vm.stack.push(Value::Obj(new_closure));
+    maybe_collect(vm, registers)
+}
+
+fn construct(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let class = vm.stack[registers.base].as_obj().expect("class receiver");
+    let num_fields = vm.heap.class(class).num_fields;
+    let mut fields = match num_fields {
+	0 => Vec::new(),
+	_ => vm.heap.take_values(num_fields),
+    };
+    fields.resize(num_fields, Value::Null);
+    let instance = vm.heap.alloc(Obj::Instance(ObjInstance { class, fields }));
+    vm.stack[registers.base] = Value::Obj(instance);
+    maybe_collect(vm, registers)
+}
+
+fn foreign_construct(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    registers.store(vm);
+    vm.create_foreign(registers.base);
+    let fiber = vm.fiber.expect("a running fiber");
+    if vm.heap.fiber(fiber).has_error() {
+	return Flow::Throw(vm.heap.fiber(fiber).error);
+    }
+    maybe_collect(vm, registers)
+}
+
+fn class(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let num_fields = registers.read_byte() as usize;
+    let superclass = pop_value(vm);
+    let name = peek(vm);
+    let class = match vm.create_class(name, superclass, num_fields, false) {
+	Ok(class) => class,
+	Err(error) => return raise(vm, registers, error),
+    };
+    *vm.stack.last_mut().expect("the class name's slot") = Value::Obj(class);
+    maybe_collect(vm, registers)
+}
+
+fn end_class(vm: &mut WrenVM, _: &mut Registers, _: Code) -> Flow {
+    let attributes = pop_value(vm);
+    let class = pop_value(vm).as_obj().expect("class");
+    vm.heap.class_mut(class).attributes = attributes;
+    Flow::Next
+}
+
+fn foreign_class(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let superclass = pop_value(vm);
+    let name = peek(vm);
+    let class = match vm.create_class(name, superclass, 0, true) {
+	Ok(class) => class,
+	Err(error) => return raise(vm, registers, error),
+    };
+    if let Err(error) = vm.bind_foreign_class_methods(registers.module, class) {
+	return raise(vm, registers, error);
+    }
+    *vm.stack.last_mut().expect("the class name's slot") = Value::Obj(class);
+    maybe_collect(vm, registers)
+}
+
+fn method(vm: &mut WrenVM, registers: &mut Registers, code: Code) -> Flow {
+    let symbol = registers.read_short();
+    let owner = pop_value(vm).as_obj().expect("class");
+    let method = pop_value(vm);
+    let is_static = code == Code::MethodStatic;
+    let class = if is_static {
+	vm.heap.class(owner).class.expect("class has a metaclass")
+    } else {
+	owner
+    };
+    if vm.heap.as_str(method).is_some() {
+	// A foreign method, given by its signature.
+	match vm.find_foreign_method(registers.module, owner, is_static, method) {
+	    Ok(foreign) => vm.bind_method(class, symbol, foreign),
+	    Err(error) => return raise(vm, registers, error),
+	}
+	return Flow::Next;
+    }
+    let method = method.as_obj().expect("method closure");
+    let field_base = vm
+	.heap
+	.class(class)
+	.superclass
+	.map_or(0, |superclass| vm.heap.class(superclass).num_fields);
+    let closure = vm.heap.closure_mut(method);
+    closure.class = Some(class);
+    closure.field_base = field_base;
+    vm.bind_method(class, symbol, Method::Block(method));
+    Flow::Next
+}
+
+fn end_module(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    vm.last_module = Some(registers.module);
+    vm.stack.push(Value::Null);
+    Flow::Next
+}
+
+fn import_module(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let name = registers.body.constants[index];
+    match vm.import_module(registers.module, name) {
+	Ok(Value::Obj(module)) if matches!(vm.heap.get(module), Obj::Module(_)) => {
+	    // Already loaded, so there's nothing to run.
+	    vm.last_module = Some(module);
+	    vm.stack.push(Value::Null);
+	    Flow::Next
+	}
+	Ok(closure) => {
+	    // Run the module's body. Its result lands in the closure's slot,
+	    // which the import then discards.
+	    vm.stack.push(closure);
+	    let closure = closure.as_obj().expect("module closure");
+	    match push_frame(vm, registers, closure, vm.stack.len() - 1) {
+		Flow::Next => maybe_collect(vm, registers),
+		flow => flow,
+	    }
+	}
+	Err(error) => raise(vm, registers, error),
+    }
+}
+
+fn import_variable(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let name = registers.body.constants[index];
+    let module = vm.last_module.expect("a module was imported");
+    match vm.get_module_variable(module, name) {
+	Ok(value) => {
+	    vm.stack.push(value);
+	    Flow::Next
+	}
+	Err(error) => raise(vm, registers, error),
+    }
+}
+
+// A superinstruction either finishes in its handler, or does the first of
+// the instructions it stands for and carries on with the second.
+
+#[cfg(feature = "superinstructions")]
+fn constant_call1(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let index = registers.read_short();
+    let constant = registers.body.constants[index];
+    // The call that follows.
+    let ip = registers.ip;
+    let symbol = u16::from_be_bytes([registers.body.code[ip + 1], registers.body.code[ip + 2]]);
+    match (peek(vm).as_num(), constant.as_num(), vm.num_op(symbol as usize)) {
+	(Some(a), Some(b), Some(op)) => {
+	    *vm.stack.last_mut().expect("stack underflow") = op.apply(a, b);
+	    registers.ip += 3;
+	}
+	_ => vm.stack.push(constant),
+    }
+    Flow::Next
+}
+
+#[cfg(feature = "superinstructions")]
+fn load_local_call1(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let value = vm.stack[registers.base + registers.read_byte() as usize];
+    let ip = registers.ip;
+    let symbol = u16::from_be_bytes([registers.body.code[ip], registers.body.code[ip + 1]]);
+    match (peek(vm).as_num(), value.as_num(), vm.num_op(symbol as usize)) {
+	(Some(a), Some(b), Some(op)) => {
+	    *vm.stack.last_mut().expect("stack underflow") = op.apply(a, b);
+	    registers.ip += 2;
+	    Flow::Next
+	}
+	_ => {
+	    vm.stack.push(value);
+	    call(vm, registers, Code::Call1)
+	}
+    }
+}
+
+#[cfg(feature = "superinstructions")]
+fn call1_jump_if(vm: &mut WrenVM, registers: &mut Registers, _: Code) -> Flow {
+    let ip = registers.ip;
+    let symbol = u16::from_be_bytes([registers.body.code[ip], registers.body.code[ip + 1]]);
+    let length = vm.stack.len();
+    let operands = (vm.stack[length - 2].as_num(), vm.stack[length - 1].as_num());
+    match (operands, vm.num_op(symbol as usize)) {
+	((Some(a), Some(b)), Some(op)) => {
+	    vm.stack.truncate(length - 2);
+	    // The jump that follows.
+	    let code = &registers.body.code;
+	    let offset = u16::from_be_bytes([code[ip + 3], code[ip + 4]]);
+	    registers.ip += 5;
+	    if op.apply(a, b).is_falsy() {
+		registers.ip += offset as usize;
+	    }
+	    Flow::Next
+	}
+	_ => call(vm, registers, Code::Call1),
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+
+use crate::compiler::CompileError;
+use crate::lint::Lint;
+use crate::parser;
+
+/// Why interpreting code, calling a method or using the slot API failed.
+/// Compile errors, uncaught runtime errors and stack overflows are also
+/// reported to the configured `error_fn`, as are the warnings of
+/// `WrenVM::lint`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WrenError {
+    /// Source code in `module` failed to compile. This is the first error
+    /// found, and each of them is reported to the `error_fn`.
+    Compile { module: String, error: CompileError },
+    /// A runtime error aborted a fiber and nothing caught it.
+    Runtime {
+	message: String,
+	/// The calls that were running, innermost first, including those in
+	/// the fibers that called the aborted one.
+	stack_trace: Vec<StackFrame>,
+	/// A likely fix the VM suggests, such as a method whose signature is
+	/// close to that of one that's missing. Scripts that catch the error
+	/// never see it, and it isn't part of how the error displays.
+	hint: Option<String>,
+    },
+    /// A fiber's stack grew past the configured `max_stack_size`, or it
+    /// called more methods deep than `max_call_depth`. Unlike other runtime
+    /// errors, it can't be caught with `Fiber.try`, and aborts every fiber
+    /// that was running.
+    StackOverflow,
+    /// A script used up the fuel given to `WrenVM::set_fuel`, or the
+    /// configured `interrupt_fn` stopped it. Like a stack overflow, it
+    /// can't be caught with `Fiber.try`, and aborts every fiber that was
+    /// running.
+    Timeout,
+    /// A slot, list index or variable given to the embedding API didn't
+    /// hold what was asked of it, such as a number in a slot holding a
+    /// string.
+    Api { message: String },
+    /// Bytes given to `load_compiled` weren't a compiled module this VM can
+    /// load.
+    Bytecode { message: String },
+    /// A snapshot couldn't be taken, or bytes given to `restore` weren't a
+    /// snapshot this VM can restore.
+    Snapshot { message: String },
+    /// Code in `module` that `WrenVM::lint` found is probably a mistake.
+    /// Only reported to the `error_fn`, never returned as an error.
+    Warning { module: String, warning: Lint },
+}
+
+/// A call that was running when a runtime error happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub module: String,
+    pub line: u32,
+    /// The name of the function, such as `update(_)` or `(script)`.
+    pub function: String,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "[{} line {}] in {}", self.module, self.line, self.function)
+    }
+}
+
+impl fmt::Display for WrenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    WrenError::Compile { module, error } => {
+		write!(f, "[{} line {}] Error", module, error.span.line)?;
+		parser::write_location(f, &error.token, error.at_end)?;
+		write!(f, ": {}", error.message)
+	    }
+	    WrenError::Runtime {
+		message,
+		stack_trace,
+		..
+	    } => {
+		f.write_str(message)?;
+		for frame in stack_trace {
+		    write!(f, "\n{}", frame)?;
+		}
+		Ok(())
+	    }
+	    WrenError::Warning { module, warning } => write!(
+		f,
+		"[{} line {}] Warning: {}",
+		module, warning.span.line, warning.message
+	    ),
+	    WrenError::StackOverflow => f.write_str("Stack overflow."),
+	    WrenError::Timeout => f.write_str("Script timed out."),
+	    WrenError::Api { message }
+	    | WrenError::Bytecode { message }
+	    | WrenError::Snapshot { message } => f.write_str(message),
+	}
+    }
+}
+
+impl error::Error for WrenError {}
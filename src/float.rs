@@ -0,0 +1,7 @@
+// The float functions beyond arithmetic, such as `floor` and `powf`, which
+// core doesn't have. Without std they come from num_traits' `Float`, on
+// libm. Where std is linked, even without the `std` feature as in tests,
+// its own are used ahead of the trait's, which then goes unused, so
+// modules import it with `#[allow(unused_imports)]`.
+
+pub(crate) use num_traits::Float;
@@ -0,0 +1,767 @@
+// A formatter for Wren source, which prints its syntax tree back out.
+//
+// The tree doesn't keep comments, so they are taken from the lexer and
+// written between the statements and methods around them: before the
+// next one, or after the one ending on their line. Comments between the
+// elements of a list, map, argument or parameter list are kept with the
+// element before or after them the same way. Parentheses are put back
+// where the tree needs them and kept where the source had them, and
+// literals are copied from the source, so numbers and strings keep the way
+// they were written. Lists, maps and argument lists that were split across
+// lines get a line per element, and method chains keep their line breaks.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::{iter, slice};
+
+use crate::ast::*;
+use crate::lexer::{Lexer, Span, Token};
+use crate::parser::{self, ParseResult, Precedence};
+
+/// How `format_with` lays out code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Spaces per level of indentation.
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+	FormatOptions { indent_width: 2 }
+    }
+}
+
+/// Formats `source` with the default options.
+pub fn format(source: &str) -> ParseResult<String> {
+    format_with(source, &FormatOptions::default())
+}
+
+/// Formats `source`, which must parse.
+pub fn format_with(source: &str, options: &FormatOptions) -> ParseResult<String> {
+    let module = parser::parse(source)?;
+    let mut lexer = Lexer::new(source);
+    while lexer.next_token().token != Token::Eof {}
+
+    let mut formatter = Formatter {
+	source,
+	indent_width: options.indent_width,
+	comments: lexer.comments().to_vec(),
+	out: String::new(),
+	indent: 0,
+	at_line_start: true,
+	last_end: 0,
+	block_start: true,
+    };
+    if source.starts_with("#!/") {
+	let end = source.find('\n').unwrap_or(source.len());
+	formatter.write(source[..end].trim_end());
+	formatter.newline();
+	formatter.last_end = end;
+	formatter.block_start = false;
+    }
+    formatter.statements(&module.statements, source.len());
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    indent_width: usize,
+    /// The comments not yet written, in order.
+    comments: Vec<Span>,
+    out: String,
+    indent: usize,
+    /// Whether nothing is written on the current line yet, so the next
+    /// text is indented.
+    at_line_start: bool,
+    /// Where the last statement, method or comment written ended in the
+    /// source.
+    last_end: usize,
+    /// Whether nothing is written in the current block yet, so the next
+    /// item doesn't get a blank line before it.
+    block_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn write(&mut self, text: &str) {
+	if self.at_line_start && !text.is_empty() {
+	    self.out.extend(iter::repeat_n(' ', self.indent * self.indent_width));
+	    self.at_line_start = false;
+	}
+	self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+	self.out.push('\n');
+	self.at_line_start = true;
+    }
+
+    // Starts a statement or method beginning at `start` in the source, after
+    // the comments before it. A blank line before it is kept.
+    fn begin_item(&mut self, start: usize) {
+	self.comments_before(start);
+	self.blank_line_before(start);
+    }
+
+    // Ends a statement or method ending at `end` in the source, with the
+    // comments after it on the same line.
+    fn end_item(&mut self, end: usize) {
+	// Comments inside the item are left for later.
+	self.last_end = self.trailing_comments(end, self.source.len());
+	self.newline();
+    }
+
+    // Writes the comments between `from` and `to` that are on the same line
+    // as `from`, the end of what was written last, and returns where the
+    // last of them ends.
+    fn trailing_comments(&mut self, from: usize, to: usize) -> usize {
+	let mut end = from;
+	while let Some(index) =
+	    self.comments.iter().position(|comment| end <= comment.start && comment.start < to)
+	{
+	    let comment = self.comments[index];
+	    if self.source[end..comment.start].contains('\n') {
+		break;
+	    }
+	    self.comments.remove(index);
+	    self.write(" ");
+	    self.write(self.comment(comment));
+	    end = comment.end;
+	}
+	end
+    }
+
+    // Removes the comments that start between `from` and `to`, for the
+    // caller to write.
+    fn take_comments(&mut self, from: usize, to: usize) -> Vec<Span> {
+	let (taken, rest) = self
+	    .comments
+	    .iter()
+	    .partition(|comment| from <= comment.start && comment.start < to);
+	self.comments = rest;
+	taken
+    }
+
+    fn comment(&self, comment: Span) -> &'a str {
+	self.source[comment.start..comment.end].trim_end()
+    }
+
+    // Writes the comments before `offset` on lines of their own.
+    fn comments_before(&mut self, offset: usize) {
+	while self.comments.first().is_some_and(|comment| comment.start < offset) {
+	    let comment = self.comments.remove(0);
+	    self.blank_line_before(comment.start);
+	    self.write(self.comment(comment));
+	    self.newline();
+	    self.last_end = self.last_end.max(comment.end);
+	}
+    }
+
+    fn blank_line_before(&mut self, start: usize) {
+	if !self.block_start && self.last_end < start {
+	    // Only the lines between two line breaks are whole.
+	    let lines: Vec<&str> = self.source[self.last_end..start].split('\n').collect();
+	    let between = &lines[1..lines.len().max(2) - 1];
+	    if between.iter().any(|line| line.trim().is_empty()) {
+		self.newline();
+	    }
+	}
+	self.block_start = false;
+    }
+
+    fn has_comment_before(&self, offset: usize) -> bool {
+	self.comments.first().is_some_and(|comment| comment.start < offset)
+    }
+
+    // Statements.
+
+    // Writes `statements` a line each, then the comments before `close`,
+    // the end of the block they're in.
+    fn statements(&mut self, statements: &[Stmt], close: usize) {
+	for stmt in statements {
+	    let start = match &stmt.kind {
+		StmtKind::Class(class) => self.attributes_start(&class.attributes),
+		_ => None,
+	    };
+	    self.begin_item(start.unwrap_or(stmt.span.start));
+	    self.statement(stmt);
+	    self.end_item(stmt.span.end);
+	}
+	self.comments_before(close);
+    }
+
+    // Writes the rest of a block after its "{", from `from` in the source,
+    // where `close` is the offset of its "}".
+    fn finish_block(&mut self, statements: &[Stmt], from: usize, close: usize) {
+	if statements.is_empty() && !self.has_comment_before(close) {
+	    self.write("}");
+	    return;
+	}
+	self.trailing_comments(from, close);
+	self.indent += 1;
+	self.newline();
+	self.block_start = true;
+	self.statements(statements, close);
+	self.indent -= 1;
+	self.write("}");
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+	match &stmt.kind {
+	    StmtKind::Expr(expr) => self.expr(expr, Precedence::Lowest),
+	    StmtKind::Var { name, initializer, .. } => {
+		self.write("var ");
+		self.write(&name.name);
+		if let Some(initializer) = initializer {
+		    self.write(" = ");
+		    self.expr(initializer, Precedence::Lowest);
+		}
+	    }
+	    StmtKind::Class(class) => self.class(class, stmt.span.end - 1),
+	    StmtKind::Import { variables, .. } => {
+		self.write("import ");
+		self.write(self.import_module(stmt.span));
+		if !variables.is_empty() {
+		    let variables: Vec<String> = variables
+			.iter()
+			.map(|variable| match &variable.alias {
+			    Some(alias) => format!("{} as {}", variable.name.name, alias.name),
+			    None => variable.name.name.clone(),
+			})
+			.collect();
+		    self.write(" for ");
+		    self.write(&variables.join(", "));
+		}
+	    }
+	    StmtKind::Block(statements) => {
+		self.write("{");
+		self.finish_block(statements, stmt.span.start + 1, stmt.span.end - 1);
+	    }
+	    StmtKind::If {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.write("if (");
+		self.expr(condition, Precedence::Lowest);
+		self.write(") ");
+		self.statement(then_branch);
+		if let Some(else_branch) = else_branch {
+		    self.write(" else ");
+		    self.statement(else_branch);
+		}
+	    }
+	    StmtKind::While { condition, body } => {
+		self.write("while (");
+		self.expr(condition, Precedence::Lowest);
+		self.write(") ");
+		self.statement(body);
+	    }
+	    StmtKind::For {
+		variable,
+		sequence,
+		body,
+	    } => {
+		self.write("for (");
+		self.write(&variable.name);
+		self.write(" in ");
+		self.expr(sequence, Precedence::Lowest);
+		self.write(") ");
+		self.statement(body);
+	    }
+	    StmtKind::Break => self.write("break"),
+	    StmtKind::Continue => self.write("continue"),
+	    StmtKind::Return(value) => {
+		self.write("return");
+		if let Some(value) = value {
+		    self.write(" ");
+		    self.expr(value, Precedence::Lowest);
+		}
+	    }
+	}
+    }
+
+    // The module name of the import at `span`, as written.
+    fn import_module(&self, span: Span) -> &'a str {
+	let source = self.source;
+	let module = Lexer::new(&source[span.start..span.end])
+	    .find(|lexeme| matches!(lexeme.token, Token::String(_)))
+	    .expect("an import has a module name");
+	&source[span.start + module.span.start..span.start + module.span.end]
+    }
+
+    // Classes.
+
+    fn class(&mut self, class: &ClassDecl, close: usize) {
+	self.attributes(&class.attributes);
+	if class.is_foreign {
+	    self.write("foreign ");
+	}
+	self.write("class ");
+	self.write(&class.name.name);
+	let mut header_end = class.name.span.end;
+	if let Some(superclass) = &class.superclass {
+	    self.write(" is ");
+	    self.expr(superclass, Precedence::Call);
+	    header_end = superclass.span.end;
+	}
+	self.write(" {");
+	if class.methods.is_empty() && !self.has_comment_before(close) {
+	    self.write("}");
+	    return;
+	}
+	let open = self.find_token(header_end, close, Token::LeftBrace);
+	self.trailing_comments(open.map_or(close, |open| open + 1), close);
+	self.indent += 1;
+	self.newline();
+	self.block_start = true;
+	for method in &class.methods {
+	    let start = self.attributes_start(&method.attributes);
+	    self.begin_item(start.unwrap_or(method.span.start));
+	    self.method(method);
+	    self.end_item(method.span.end);
+	}
+	self.comments_before(close);
+	self.indent -= 1;
+	self.write("}");
+    }
+
+    // Where the "#" of the first attribute is.
+    fn attributes_start(&self, attributes: &[Attribute]) -> Option<usize> {
+	let first = attributes.first()?;
+	let name = first.group.as_ref().unwrap_or(&first.key);
+	self.source[..name.span.start].rfind('#')
+    }
+
+    // Writes the attributes a line each, with the entries of a group
+    // together.
+    fn attributes(&mut self, attributes: &[Attribute]) {
+	let mut rest = attributes;
+	while let Some(attribute) = rest.first() {
+	    self.write(if attribute.is_runtime { "#!" } else { "#" });
+	    match &attribute.group {
+		Some(group) => {
+		    let in_group = |entry: &&Attribute| {
+			entry.group.as_ref().map(|name| name.span) == Some(group.span)
+		    };
+		    let count = rest.iter().take_while(in_group).count();
+		    let entries: Vec<String> =
+			rest[..count].iter().map(|entry| self.attribute_entry(entry)).collect();
+		    self.write(&format!("{}({})", group.name, entries.join(", ")));
+		    rest = &rest[count..];
+		}
+		None => {
+		    self.write(&self.attribute_entry(attribute));
+		    rest = &rest[1..];
+		}
+	    }
+	    self.newline();
+	}
+    }
+
+    fn attribute_entry(&self, attribute: &Attribute) -> String {
+	let value = attribute.value.span;
+	if value == attribute.key.span {
+	    attribute.key.name.clone()
+	} else {
+	    format!("{} = {}", attribute.key.name, &self.source[value.start..value.end])
+	}
+    }
+
+    fn method(&mut self, method: &Method) {
+	self.attributes(&method.attributes);
+	if method.is_foreign {
+	    self.write("foreign ");
+	}
+	if method.is_static {
+	    self.write("static ");
+	}
+	let name = &method.name;
+	let within = name.span.start..method.span.end;
+	let header_end = match method.kind {
+	    MethodKind::Constructor => {
+		self.write("construct ");
+		self.write(&name.name);
+		self.params(Token::LeftParen, &method.params, within)
+	    }
+	    MethodKind::Method | MethodKind::Binary => {
+		self.write(&name.name);
+		self.params(Token::LeftParen, &method.params, within)
+	    }
+	    MethodKind::Getter | MethodKind::Unary => {
+		self.write(&name.name);
+		name.span.end
+	    }
+	    MethodKind::Setter => {
+		self.write(&name.name);
+		self.write("=");
+		self.params(Token::LeftParen, &method.params, within)
+	    }
+	    MethodKind::Subscript => self.params(Token::LeftBracket, &method.params, within),
+	    MethodKind::SubscriptSetter => {
+		let (value, params) =
+		    method.params.split_last().expect("a subscript setter has a value");
+		let end = self.params(Token::LeftBracket, params, within.clone());
+		self.write("=");
+		self.params(Token::LeftParen, slice::from_ref(value), end..within.end)
+	    }
+	};
+	if let Some(body) = &method.body {
+	    self.write(" ");
+	    let close = method.span.end - 1;
+	    let open = self.find_token(header_end, close, Token::LeftBrace).unwrap_or(close);
+	    self.body(body, &[], open, close);
+	}
+    }
+
+    // Writes a parameter list, the first one in `within`, and returns where
+    // it ends in the source.
+    fn params(&mut self, open: Token, params: &[Ident], within: Range<usize>) -> usize {
+	let close = match open {
+	    Token::LeftParen => Token::RightParen,
+	    Token::LeftBracket => Token::RightBracket,
+	    _ => Token::Pipe,
+	};
+	let spans: Vec<Span> = params.iter().map(|param| param.span).collect();
+	self.sequence(open, params, &spans, close, within, |formatter, param| {
+	    formatter.write(&param.name);
+	})
+    }
+
+    // Writes a method body or block argument, starting with its "{" at
+    // `open` and then `params`, up to its "}" at `close`.
+    fn body(&mut self, body: &Body, params: &[Ident], open: usize, close: usize) {
+	self.write("{");
+	let mut from = open + 1;
+	if !params.is_empty() {
+	    from = self.params(Token::Pipe, params, from..close);
+	}
+	match body {
+	    Body::Expr(expr) => {
+		self.write(" ");
+		self.expr(expr, Precedence::Lowest);
+		self.write(" }");
+	    }
+	    Body::Block(statements) => self.finish_block(statements, from, close),
+	}
+    }
+
+    // Expressions.
+
+    fn expr(&mut self, expr: &Expr, min: Precedence) {
+	let parens = self.is_parenthesized(expr) || precedence(expr) < min;
+	if parens {
+	    self.write("(");
+	}
+	self.expr_kind(expr);
+	if parens {
+	    self.write(")");
+	}
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) {
+	match &expr.kind {
+	    ExprKind::Null => self.write("null"),
+	    ExprKind::Bool(value) => self.write(if *value { "true" } else { "false" }),
+	    ExprKind::Num(_) | ExprKind::String(_) | ExprKind::Interpolation(_) => {
+		self.write(self.literal(expr.span));
+	    }
+	    ExprKind::List(elements) => {
+		let spans: Vec<Span> = elements.iter().map(|element| element.span).collect();
+		let (open, close) = (Token::LeftBracket, Token::RightBracket);
+		self.sequence(open, elements, &spans, close, expr.span.start..expr.span.end, |formatter, element| {
+		    formatter.expr(element, Precedence::Lowest);
+		});
+	    }
+	    ExprKind::Map(entries) => {
+		let spans: Vec<Span> =
+		    entries.iter().map(|(key, value)| key.span.to(value.span)).collect();
+		let (open, close) = (Token::LeftBrace, Token::RightBrace);
+		self.sequence(open, entries, &spans, close, expr.span.start..expr.span.end, |formatter, (key, value)| {
+		    formatter.expr(key, Precedence::Unary);
+		    formatter.write(": ");
+		    formatter.expr(value, Precedence::Lowest);
+		});
+	    }
+	    ExprKind::Name(name) | ExprKind::Field(name) | ExprKind::StaticField(name) => {
+		self.write(name);
+	    }
+	    ExprKind::This => self.write("this"),
+	    ExprKind::Call {
+		receiver,
+		name,
+		args,
+		block,
+	    } => {
+		let mut continued = false;
+		if let Some(receiver) = receiver {
+		    self.expr(receiver, Precedence::Call);
+		    continued = self.starts_line(name.span.start);
+		    if continued {
+			self.indent += 1;
+			self.newline();
+		    }
+		    self.write(".");
+		}
+		self.write(&name.name);
+		self.call_arguments(name.span.end..expr.span.end, args, block);
+		if continued {
+		    self.indent -= 1;
+		}
+	    }
+	    ExprKind::Super { name, args, block } => {
+		self.write("super");
+		let mut end = expr.span.start + "super".len();
+		if let Some(name) = name {
+		    self.write(".");
+		    self.write(&name.name);
+		    end = name.span.end;
+		}
+		self.call_arguments(end..expr.span.end, args, block);
+	    }
+	    ExprKind::Subscript { receiver, args } => {
+		self.expr(receiver, Precedence::Call);
+		let spans: Vec<Span> = args.iter().map(|arg| arg.span).collect();
+		let within = receiver.span.end..expr.span.end;
+		let (open, close) = (Token::LeftBracket, Token::RightBracket);
+		self.sequence(open, args, &spans, close, within, |formatter, arg| {
+		    formatter.expr(arg, Precedence::Lowest);
+		});
+	    }
+	    ExprKind::Unary { op, operand } => {
+		self.write(op.method_name());
+		// Prefix operators nest without parentheses.
+		let min = match operand.kind {
+		    ExprKind::Unary { .. } => Precedence::Unary,
+		    _ => Precedence::Call,
+		};
+		self.expr(operand, min);
+	    }
+	    ExprKind::Binary { op, left, right } => {
+		let precedence = binary_precedence(*op);
+		self.expr(left, precedence);
+		// Ranges are written without spaces, as in "0...count".
+		if precedence == Precedence::Range {
+		    self.write(op.method_name());
+		} else {
+		    self.write(" ");
+		    self.write(op.method_name());
+		    self.write(" ");
+		}
+		self.expr(right, precedence.next());
+	    }
+	    ExprKind::And(left, right) => {
+		self.expr(left, Precedence::LogicalAnd.next());
+		self.write(" && ");
+		self.expr(right, Precedence::LogicalAnd);
+	    }
+	    ExprKind::Or(left, right) => {
+		self.expr(left, Precedence::LogicalOr.next());
+		self.write(" || ");
+		self.expr(right, Precedence::LogicalOr);
+	    }
+	    ExprKind::Conditional {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.expr(condition, Precedence::LogicalOr);
+		self.write(" ? ");
+		self.expr(then_branch, Precedence::LogicalOr);
+		self.write(" : ");
+		self.expr(else_branch, Precedence::Assignment);
+	    }
+	    ExprKind::Assign { target, value } => {
+		self.expr(target, Precedence::Lowest);
+		self.write(" = ");
+		self.expr(value, Precedence::Lowest);
+	    }
+	}
+    }
+
+    // Writes the arguments of a call, which are `within` the source
+    // between the end of its name and the end of the call.
+    fn call_arguments(
+	&mut self,
+	within: Range<usize>,
+	args: &Option<Vec<Expr>>,
+	block: &Option<Box<BlockArg>>,
+    ) {
+	if let Some(args) = args {
+	    let spans: Vec<Span> = args.iter().map(|arg| arg.span).collect();
+	    let (open, close) = (Token::LeftParen, Token::RightParen);
+	    self.sequence(open, args, &spans, close, within, |formatter, arg| {
+		formatter.expr(arg, Precedence::Lowest);
+	    });
+	}
+	if let Some(block) = block {
+	    self.write(" ");
+	    self.body(&block.body, &block.params, block.span.start, block.span.end - 1);
+	}
+    }
+
+    // Writes `items`, which are at `spans` in the source, between the first
+    // `open` in `within` and the `close` after them, and returns where that
+    // ends. If they were split across lines they get a line each, with the
+    // comments after each on its line and the others on lines of their own.
+    fn sequence<T>(
+	&mut self,
+	open: Token,
+	items: &[T],
+	spans: &[Span],
+	close: Token,
+	within: Range<usize>,
+	mut item: impl FnMut(&mut Formatter<'a>, &T),
+    ) -> usize {
+	let start = self.find_token(within.start, within.end, open.clone()).unwrap_or(within.start);
+	let last = spans.last().map_or(start + 1, |span| span.end);
+	let end = self.find_token(last, within.end, close.clone()).unwrap_or(within.end);
+	let multiline = self.is_split(start, spans, end)
+	    && (!items.is_empty() || self.comments.iter().any(|comment| comment.start < end));
+	self.write(&open.to_string());
+	let mut from = start + 1;
+	if multiline {
+	    self.indent += 1;
+	    self.trailing_comments(from, spans.first().map_or(end, |span| span.start));
+	    for (index, (element, span)) in items.iter().zip(spans).enumerate() {
+		for comment in self.take_comments(from, span.start) {
+		    self.newline();
+		    self.write(self.comment(comment));
+		}
+		self.newline();
+		item(self, element);
+		if index + 1 < items.len() {
+		    self.write(",");
+		}
+		let next = spans.get(index + 1).map_or(end, |next| next.start);
+		self.trailing_comments(span.end, next);
+		from = span.end;
+	    }
+	    for comment in self.take_comments(from, end) {
+		self.newline();
+		self.write(self.comment(comment));
+	    }
+	    self.indent -= 1;
+	    self.newline();
+	} else {
+	    for (index, (element, span)) in items.iter().zip(spans).enumerate() {
+		if index > 0 {
+		    self.write(", ");
+		}
+		for comment in self.take_comments(from, span.start) {
+		    self.write(self.comment(comment));
+		    self.write(" ");
+		}
+		item(self, element);
+		// The comments before the comma stay before it.
+		let next = spans.get(index + 1).map_or(end, |next| next.start);
+		from = self.find_token(span.end, next, Token::Comma).unwrap_or(span.end);
+		for comment in self.take_comments(span.end, from) {
+		    self.write(" ");
+		    self.write(self.comment(comment));
+		}
+	    }
+	    for comment in self.take_comments(from, end) {
+		self.write(" ");
+		self.write(self.comment(comment));
+	    }
+	}
+	self.write(&close.to_string());
+	end + 1
+    }
+
+    // Where the first `token` between `from` and `to` is in the source.
+    fn find_token(&self, from: usize, to: usize, token: Token) -> Option<usize> {
+	Lexer::new(&self.source[from..to])
+	    .find(|lexeme| lexeme.token == token)
+	    .map(|lexeme| from + lexeme.span.start)
+    }
+
+    // Whether the source has a line break between the items at `spans`, or
+    // between them and the delimiters at `start` and `end`.
+    fn is_split(&self, start: usize, spans: &[Span], end: usize) -> bool {
+	let mut from = start;
+	for span in spans {
+	    if self.source[from..span.start].contains('\n') {
+		return true;
+	    }
+	    from = span.end;
+	}
+	self.source[from..end].contains('\n')
+    }
+
+    // Whether the "." before the name at `offset` begins its line, as
+    // when a method chain continues on the next one.
+    fn starts_line(&self, offset: usize) -> bool {
+	let before = self.source[..offset].trim_end();
+	match before.strip_suffix('.') {
+	    Some(before) => before.trim_end_matches([' ', '\t', '\r']).ends_with('\n'),
+	    None => false,
+	}
+    }
+
+    // A literal as written, without any parentheses around it.
+    fn literal(&self, span: Span) -> &'a str {
+	let mut text = &self.source[span.start..span.end];
+	while let Some(inner) = text.strip_prefix('(').and_then(|text| text.strip_suffix(')')) {
+	    text = inner.trim();
+	}
+	text
+    }
+
+    // Whether the source has `expr` in parentheses. Its span then includes
+    // them, and starts before its first operand's.
+    fn is_parenthesized(&self, expr: &Expr) -> bool {
+	if !self.source[expr.span.start..].starts_with('(') {
+	    return false;
+	}
+	let first = match &expr.kind {
+	    ExprKind::Call {
+		receiver: Some(first),
+		..
+	    }
+	    | ExprKind::Subscript { receiver: first, .. }
+	    | ExprKind::Binary { left: first, .. }
+	    | ExprKind::And(first, _)
+	    | ExprKind::Or(first, _)
+	    | ExprKind::Conditional {
+		condition: first, ..
+	    }
+	    | ExprKind::Assign { target: first, .. } => first,
+	    _ => return true,
+	};
+	first.span.start != expr.span.start
+    }
+}
+
+// How tightly `expr` binds, like the operator that makes it.
+fn precedence(expr: &Expr) -> Precedence {
+    match &expr.kind {
+	ExprKind::Call {
+	    receiver: Some(_), ..
+	}
+	| ExprKind::Subscript { .. } => Precedence::Call,
+	ExprKind::Unary { .. } => Precedence::Unary,
+	ExprKind::Binary { op, .. } => binary_precedence(*op),
+	ExprKind::And(..) => Precedence::LogicalAnd,
+	ExprKind::Or(..) => Precedence::LogicalOr,
+	ExprKind::Conditional { .. } | ExprKind::Assign { .. } => Precedence::Assignment,
+	_ => Precedence::Primary,
+    }
+}
+
+fn binary_precedence(op: BinaryOp) -> Precedence {
+    match op {
+	BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => Precedence::Factor,
+	BinaryOp::Add | BinaryOp::Sub => Precedence::Term,
+	BinaryOp::RangeInclusive | BinaryOp::RangeExclusive => Precedence::Range,
+	BinaryOp::Shl | BinaryOp::Shr => Precedence::BitwiseShift,
+	BinaryOp::BitAnd => Precedence::BitwiseAnd,
+	BinaryOp::BitXor => Precedence::BitwiseXor,
+	BinaryOp::BitOr => Precedence::BitwiseOr,
+	BinaryOp::Lt | BinaryOp::Gt | BinaryOp::LtEq | BinaryOp::GtEq => Precedence::Comparison,
+	BinaryOp::Is => Precedence::Is,
+	BinaryOp::Eq | BinaryOp::NotEq => Precedence::Equality,
+    }
+}
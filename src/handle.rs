@@ -0,0 +1,191 @@
+use alloc::sync::Arc;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::api::api_error;
+use crate::bind::IntoSlot;
+use crate::chunk::Code;
+use crate::compiler::Signature;
+use crate::error::WrenError;
+use crate::value::{FiberState, FnBody, Obj, ObjClosure, ObjFiber, ObjFn, Value};
+use crate::vm::WrenVM;
+
+/// Keeps a Wren value alive while the host holds on to it, such as an
+/// object to call methods on or a handle from `make_call_handle`.
+///
+/// A handle is only valid in the VM that made it, and must be given back
+/// with `release_handle` once it's no longer needed, or the value will
+/// never be collected.
+#[derive(Debug)]
+pub struct WrenHandle {
+    index: usize,
+}
+
+impl WrenVM {
+    /// Makes a handle for calling the method with `signature`, such as
+    /// `"update(_)"`, on any receiver with `call`.
+    ///
+    /// Fails with an API error if `signature` isn't one a method could
+    /// have, saying what `Signature::parse` found wrong with it.
+    pub fn make_call_handle(&mut self, signature: &str) -> Result<WrenHandle, WrenError> {
+	let arity = Signature::parse(signature)
+	    .map_err(|error| api_error(format!("Invalid signature '{}': {}", signature, error)))?
+	    .arity;
+	let symbol = self.methods.ensure(signature) as u16;
+	// A stub function that calls the method on the receiver and arguments
+	// in its slots, and returns the result.
+	let [high, low] = symbol.to_be_bytes();
+	let code = vec![
+	    Code::call(arity) as u8,
+	    high,
+	    low,
+	    Code::Return as u8,
+	    Code::End as u8,
+	];
+	let body = FnBody {
+	    name: signature.to_string(),
+	    arity,
+	    num_upvalues: 0,
+	    lines: vec![0; code.len()],
+	    code,
+	    constants: Vec::new(),
+	    locals: Vec::new(),
+	};
+	let function = self.heap.alloc(Obj::Fn(ObjFn {
+	    body: Arc::new(body),
+	    module: self.core_module,
+	}));
+	let closure = self.heap.alloc(Obj::Closure(ObjClosure::new(function)));
+	Ok(self.make_handle(Value::Obj(closure)))
+    }
+
+    /// Calls the method of a handle from `make_call_handle`, with the
+    /// receiver in slot 0 and the arguments in the slots after it. If the
+    /// call succeeds, its result is left in slot 0.
+    ///
+    /// It can't be used from within a foreign method.
+    pub fn call(&mut self, method: &WrenHandle) -> Result<(), WrenError> {
+	if self.fiber.is_some() {
+	    return Err(api_error("Can't call a handle from within a foreign method."));
+	}
+	let closure = match self.handles[method.index].and_then(Value::as_obj) {
+	    Some(obj) if matches!(self.heap.get(obj), Obj::Closure(_)) => obj,
+	    _ => return Err(api_error("The handle isn't a live call handle.")),
+	};
+	let function = self.heap.closure(closure).function;
+	let arity = self.heap.function(function).body.arity;
+	if self.slot_count() <= arity {
+	    return Err(api_error(format!(
+		"The call needs {} slots for its receiver and arguments.",
+		arity + 1
+	    )));
+	}
+
+	// The host's slots become the fiber's stack, less any extra ones.
+	let base = self.api_stack.take().expect("slots");
+	let mut slots = mem::take(&mut self.stack);
+	slots.drain(..base);
+	slots.truncate(arity + 1);
+	let frames = self.heap.take_frames(1);
+	let mut fiber = ObjFiber::with_buffers(closure, slots, frames);
+	fiber.state = FiberState::Root;
+	let fiber = self.heap.alloc(Obj::Fiber(fiber));
+	self.switch_fiber(Some(fiber));
+	let result = self.run()?;
+	self.switch_fiber(None);
+	self.stack.push(result);
+	self.api_stack = Some(0);
+	Ok(())
+    }
+
+    /// Suspends the fiber running the current foreign method once the
+    /// method returns, and returns a handle to the fiber for
+    /// `resume_fiber`. Until then, `interpret` or `call` returns as though
+    /// the fiber had finished, so the host can wait for an operation, such
+    /// as an async read, to complete without blocking the VM.
+    ///
+    /// Only a foreign method can suspend its fiber. If it also aborts the
+    /// fiber, the error wins.
+    pub fn suspend_fiber(&mut self) -> Result<WrenHandle, WrenError> {
+	let fiber = match (self.fiber, self.api_stack) {
+	    (Some(fiber), Some(_)) => fiber,
+	    _ => return Err(api_error("Only a foreign method can suspend its fiber.")),
+	};
+	self.heap.fiber_mut(fiber).awaiting_host = true;
+	Ok(self.make_handle(Value::Obj(fiber)))
+    }
+
+    /// Resumes a fiber suspended by `suspend_fiber`, making the foreign call
+    /// that suspended it return `result`. An `Err` result aborts the fiber
+    /// with its message instead. The fiber runs until it finishes or
+    /// suspends again, and runtime errors are reported as by `interpret`.
+    ///
+    /// The handle stays the host's to release. Like `call`, it can't be
+    /// used from within a foreign method.
+    pub fn resume_fiber<T: IntoSlot>(
+	&mut self,
+	fiber: &WrenHandle,
+	result: T,
+    ) -> Result<(), WrenError> {
+	if self.fiber.is_some() {
+	    return Err(api_error("Can't resume a fiber from within a foreign method."));
+	}
+	let fiber = self.handles[fiber.index]
+	    .and_then(|value| value.as_obj())
+	    .filter(|&obj| matches!(self.heap.get(obj), Obj::Fiber(fiber) if fiber.awaiting_host));
+	let fiber = match fiber {
+	    Some(fiber) => fiber,
+	    None => return Err(api_error("The handle isn't a fiber waiting to be resumed.")),
+	};
+	self.heap.fiber_mut(fiber).awaiting_host = false;
+
+	// Slots the host was using are released, and the suspended call's
+	// slot, on top of the fiber's stack, is slot 0 while the result is
+	// stored in it.
+	self.switch_fiber(Some(fiber));
+	let base = self.stack.len() - 1;
+	self.api_stack = Some(base);
+	let stored = result.into_slot(self, 0);
+	self.api_stack = None;
+	self.stack.truncate(base + 1);
+	if let Err(error) = stored {
+	    let error = self.new_string(error.to_string());
+	    self.runtime_error(error)?;
+	}
+	self.run()?;
+	self.switch_fiber(None);
+	Ok(())
+    }
+
+    /// Makes a handle for the value in `slot`.
+    pub fn get_slot_handle(&mut self, slot: usize) -> WrenHandle {
+	let value = self.slot(slot);
+	self.make_handle(value)
+    }
+
+    /// Stores the value of `handle` in `slot`.
+    pub fn set_slot_handle(&mut self, slot: usize, handle: &WrenHandle) {
+	let value = self.handles[handle.index].expect("a live handle");
+	self.set_slot(slot, value);
+    }
+
+    /// Gives back a handle, allowing its value to be collected.
+    pub fn release_handle(&mut self, handle: WrenHandle) {
+	self.handles[handle.index] = None;
+    }
+
+    fn make_handle(&mut self, value: Value) -> WrenHandle {
+	let index = match self.handles.iter().position(Option::is_none) {
+	    Some(index) => {
+		self.handles[index] = Some(value);
+		index
+	    }
+	    None => {
+		self.handles.push(Some(value));
+		self.handles.len() - 1
+	    }
+	};
+	WrenHandle { index }
+    }
+}
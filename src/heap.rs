@@ -0,0 +1,684 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+
+use hashbrown::HashTable;
+
+use crate::config::WrenConfiguration;
+use crate::value::*;
+use crate::vm::Method;
+
+/// Owns every heap object. Objects are addressed by `ObjRef` index and
+/// reclaimed by a mark-and-sweep collector once nothing references them.
+///
+/// Marking can be done a slice at a time, with the program running in
+/// between. Objects allocated meanwhile are marked, and an object changed
+/// after it was traced is traced again, so nothing reachable is missed.
+///
+/// With a nursery, objects that survive a collection are tenured: they
+/// stay marked in between collections, so a minor collection neither
+/// traces nor frees them, and only looks again at those that change.
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Option<Obj>>,
+    colors: Vec<Color>,
+    // Whether a collection has marked objects and not yet swept.
+    is_collecting: bool,
+    // Indexes of freed slots, reused before the heap grows.
+    free: Vec<u32>,
+    // Marked objects whose references haven't been traced yet.
+    gray: Vec<ObjRef>,
+    // Approximate size of the heap. Each object is counted when it is
+    // allocated, and maps report their tables being resized. Growth of
+    // other objects afterwards is only picked up by the next collection.
+    bytes_allocated: usize,
+    next_gc: usize,
+    min_heap_size: usize,
+    heap_growth_percent: usize,
+    max_heap_size: Option<usize>,
+    // Objects allocated since the last collection, when there is a nursery.
+    nursery: Vec<ObjRef>,
+    nursery_bytes: usize,
+    nursery_size: Option<usize>,
+    stats: GcStats,
+    counts: ObjectCounts,
+    // How large the heap was when the collection in progress started.
+    bytes_before: usize,
+    pool: Pool,
+    // Strings made by `intern`, found by their contents. Being here doesn't
+    // keep a string alive.
+    interned: HashTable<ObjRef>,
+}
+
+/// How many buffers of each kind the heap keeps from freed objects, by
+/// default, to reuse for new ones.
+pub const POOL_SIZE: usize = 4096;
+
+// Only buffers this small are pooled, so the pool stays small too.
+const MAX_POOLED_CAPACITY: usize = 8;
+
+// The element and field buffers of freed lists and instances, upvalue
+// buffers of freed closures, and stacks and call frames of freed fibers,
+// kept to back new objects without going to the allocator.
+//
+// Other objects, such as ranges and upvalues, have no buffer. They are
+// stored in the heap's table of objects itself, whose free slots are
+// reused, so making one doesn't reach the allocator anyway.
+#[derive(Debug)]
+struct Pool {
+    values: Vec<Vec<Value>>,
+    refs: Vec<Vec<ObjRef>>,
+    frames: Vec<Vec<CallFrame>>,
+    size: usize,
+}
+
+impl Pool {
+    // Drops `obj`, keeping its buffers if there is room for them, or
+    // running its finalizer if it is foreign.
+    fn free(&mut self, obj: Obj) {
+	match obj {
+	    Obj::List(list) => keep(&mut self.values, list.elements, self.size),
+	    Obj::Instance(instance) => keep(&mut self.values, instance.fields, self.size),
+	    Obj::Closure(closure) => keep(&mut self.refs, closure.upvalues, self.size),
+	    Obj::Fiber(fiber) => {
+		keep(&mut self.values, fiber.stack, self.size);
+		keep(&mut self.frames, fiber.frames, self.size);
+	    }
+	    obj => finalize(obj),
+	}
+    }
+}
+
+fn keep<T>(buffers: &mut Vec<Vec<T>>, mut buffer: Vec<T>, size: usize) {
+    if buffers.len() < size && (1..=MAX_POOLED_CAPACITY).contains(&buffer.capacity()) {
+	buffer.clear();
+	buffers.push(buffer);
+    }
+}
+
+fn take<T>(buffers: &mut Vec<Vec<T>>, capacity: usize) -> Vec<T> {
+    match buffers.pop() {
+	Some(mut buffer) => {
+	    buffer.reserve(capacity);
+	    buffer
+	}
+	None => Vec::with_capacity(capacity),
+    }
+}
+
+/// Counts of the work the garbage collector has done, for comparing how
+/// it fares with different configurations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Collections of the whole heap, whether done at once or in steps.
+    pub collections: usize,
+    /// Collections of just the nursery.
+    pub minor_collections: usize,
+    /// Objects whose references were traced, over all collections.
+    pub objects_traced: usize,
+    pub objects_freed: usize,
+    /// Objects that survived a minor collection and left the nursery.
+    pub objects_tenured: usize,
+}
+
+/// What one garbage collection did, as given to the configured `gc_fn`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcEvent {
+    /// Whether only the nursery was collected.
+    pub minor: bool,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    pub objects_freed: usize,
+    /// The objects left on the heap.
+    pub objects: ObjectCounts,
+    /// Seconds spent collecting, by the clock `System.clock` reads. For a
+    /// collection done in steps, this is the time taken by all of them.
+    pub pause: f64,
+}
+
+/// How many objects of each type are on the heap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjectCounts {
+    pub strings: usize,
+    pub lists: usize,
+    pub maps: usize,
+    pub ranges: usize,
+    pub fns: usize,
+    pub closures: usize,
+    pub upvalues: usize,
+    pub classes: usize,
+    pub instances: usize,
+    pub foreign: usize,
+    pub fibers: usize,
+    pub modules: usize,
+}
+
+impl ObjectCounts {
+    pub fn total(&self) -> usize {
+	self.strings
+	    + self.lists
+	    + self.maps
+	    + self.ranges
+	    + self.fns
+	    + self.closures
+	    + self.upvalues
+	    + self.classes
+	    + self.instances
+	    + self.foreign
+	    + self.fibers
+	    + self.modules
+    }
+
+    // The count `obj` is included in.
+    fn of(&mut self, obj: &Obj) -> &mut usize {
+	match obj {
+	    Obj::String(_) => &mut self.strings,
+	    Obj::List(_) => &mut self.lists,
+	    Obj::Map(_) => &mut self.maps,
+	    Obj::Range(_) => &mut self.ranges,
+	    Obj::Fn(_) => &mut self.fns,
+	    Obj::Closure(_) => &mut self.closures,
+	    Obj::Upvalue(_) => &mut self.upvalues,
+	    Obj::Class(_) => &mut self.classes,
+	    Obj::Instance(_) => &mut self.instances,
+	    Obj::Foreign(_) => &mut self.foreign,
+	    Obj::Fiber(_) => &mut self.fibers,
+	    Obj::Module(_) => &mut self.modules,
+	}
+    }
+}
+
+// Where an object is in a collection: white until it is found to be
+// reachable, gray until its references have been traced, then black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    White,
+    Gray,
+    Black,
+}
+
+macro_rules! accessors {
+    ($($get:ident, $get_mut:ident, $variant:ident, $ty:ty;)*) => {
+	$(
+	    pub fn $get(&self, obj: ObjRef) -> &$ty {
+		match self.get(obj) {
+		    Obj::$variant(inner) => inner,
+		    other => panic!(concat!("expected ", stringify!($variant), ", got {:?}"), other),
+		}
+	    }
+
+	    pub fn $get_mut(&mut self, obj: ObjRef) -> &mut $ty {
+		match self.get_mut(obj) {
+		    Obj::$variant(inner) => inner,
+		    other => panic!(concat!("expected ", stringify!($variant), ", got {:?}"), other),
+		}
+	    }
+	)*
+    };
+}
+
+impl Heap {
+    pub fn new(config: &WrenConfiguration) -> Heap {
+	Heap {
+	    objects: Vec::new(),
+	    colors: Vec::new(),
+	    is_collecting: false,
+	    free: Vec::new(),
+	    gray: Vec::new(),
+	    bytes_allocated: 0,
+	    next_gc: config.initial_heap_size,
+	    min_heap_size: config.min_heap_size,
+	    heap_growth_percent: config.heap_growth_percent,
+	    max_heap_size: config.max_heap_size,
+	    nursery: Vec::new(),
+	    nursery_bytes: 0,
+	    nursery_size: config.nursery_size,
+	    stats: GcStats::default(),
+	    counts: ObjectCounts::default(),
+	    bytes_before: 0,
+	    pool: Pool {
+		values: Vec::new(),
+		refs: Vec::new(),
+		frames: Vec::new(),
+		size: config.pool_size,
+	    },
+	    interned: HashTable::new(),
+	}
+    }
+
+    pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+	let size = object_size(&obj);
+	self.bytes_allocated += size;
+	*self.counts.of(&obj) += 1;
+	let obj = match self.free.pop() {
+	    Some(index) => {
+		self.objects[index as usize] = Some(obj);
+		ObjRef(index)
+	    }
+	    None => {
+		self.objects.push(Some(obj));
+		self.colors.push(Color::White);
+		ObjRef((self.objects.len() - 1) as u32)
+	    }
+	};
+	// What a new object references was reachable when it was made, but
+	// may not have been marked yet.
+	if self.is_collecting {
+	    self.mark(obj);
+	}
+	if self.nursery_size.is_some() {
+	    self.nursery.push(obj);
+	    self.nursery_bytes += size;
+	}
+	obj
+    }
+
+    /// Puts `obj` in place of the object at `at`, such as a placeholder
+    /// allocated before what `obj` refers to existed.
+    pub fn replace(&mut self, at: ObjRef, obj: Obj) {
+	let size = object_size(&obj);
+	*self.counts.of(&obj) += 1;
+	let old = mem::replace(self.get_mut(at), obj);
+	*self.counts.of(&old) -= 1;
+	self.resized(object_size(&old), size);
+	self.pool.free(old);
+    }
+
+    /// Frees `objs` without waiting for a collection, for objects made for
+    /// something that failed before anything else could refer to them.
+    pub fn free(&mut self, objs: &[ObjRef]) {
+	for &obj in objs {
+	    if let Some(old) = self.objects[obj.index()].take() {
+		let size = object_size(&old);
+		self.bytes_allocated = self.bytes_allocated.saturating_sub(size);
+		if self.nursery_size.is_some() {
+		    self.nursery_bytes = self.nursery_bytes.saturating_sub(size);
+		}
+		*self.counts.of(&old) -= 1;
+		self.pool.free(old);
+		self.colors[obj.index()] = Color::White;
+		self.free.push(obj.0);
+	    }
+	}
+	let objects = &self.objects;
+	self.nursery.retain(|obj| objects[obj.index()].is_some());
+	self.gray.retain(|obj| objects[obj.index()].is_some());
+	self.forget_freed_strings();
+    }
+
+    /// An empty buffer with room for at least `capacity` list elements or
+    /// instance fields, from a freed object if one was kept.
+    pub fn take_values(&mut self, capacity: usize) -> Vec<Value> {
+	take(&mut self.pool.values, capacity)
+    }
+
+    /// An empty buffer for at least `capacity` upvalues of a closure, from
+    /// a freed closure if one was kept.
+    pub fn take_refs(&mut self, capacity: usize) -> Vec<ObjRef> {
+	take(&mut self.pool.refs, capacity)
+    }
+
+    /// An empty buffer for at least `capacity` call frames of a fiber, from
+    /// a freed fiber if one was kept.
+    pub fn take_frames(&mut self, capacity: usize) -> Vec<CallFrame> {
+	take(&mut self.pool.frames, capacity)
+    }
+
+    /// A string holding `value`, shared with everything else that interns
+    /// the same contents, so that equal strings are the same object.
+    pub fn intern(&mut self, value: String) -> ObjRef {
+	let hash = hash_string(&value);
+	let objects = &self.objects;
+	let existing = self.interned.find(u64::from(hash), |&obj| match &objects[obj.index()] {
+	    Some(Obj::String(string)) => string.value == value,
+	    _ => false,
+	});
+	if let Some(&obj) = existing {
+	    return obj;
+	}
+	let obj = self.alloc(Obj::String(ObjString { value, hash }));
+	let objects = &self.objects;
+	self.interned.insert_unique(u64::from(hash), obj, |&obj| match &objects[obj.index()] {
+	    Some(Obj::String(string)) => u64::from(string.hash),
+	    _ => 0,
+	});
+	obj
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Obj {
+	self.objects[obj.index()].as_ref().expect("live object")
+    }
+
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Obj {
+	// A traced object that changes could be given a reference the
+	// collection hasn't seen, so it is traced again. Tenured objects
+	// are marked in between collections, so those that change wait to
+	// be traced by the next one.
+	let color = &mut self.colors[obj.index()];
+	if *color == Color::Black {
+	    *color = Color::Gray;
+	    self.gray.push(obj);
+	}
+	self.objects[obj.index()].as_mut().expect("live object")
+    }
+
+    /// Every live object.
+    pub fn objects(&self) -> impl Iterator<Item = ObjRef> + '_ {
+	let live = self.objects.iter().enumerate().filter(|(_, obj)| obj.is_some());
+	live.map(|(index, _)| ObjRef(index as u32))
+    }
+
+    /// The number of live objects.
+    pub fn len(&self) -> usize {
+	self.objects.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.len() == 0
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+	self.bytes_allocated
+    }
+
+    /// Accounts for an object that grew or shrank from `old_size` bytes to
+    /// `new_size` after it was allocated.
+    pub fn resized(&mut self, old_size: usize, new_size: usize) {
+	self.bytes_allocated = (self.bytes_allocated + new_size).saturating_sub(old_size);
+    }
+
+    /// Whether enough has been allocated since the last collection to
+    /// warrant another, or to go past the configured `max_heap_size`.
+    pub fn should_collect(&self) -> bool {
+	self.bytes_allocated > self.next_gc || self.is_full()
+    }
+
+    /// Whether enough has been allocated since the last collection to fill
+    /// the configured nursery.
+    pub fn is_nursery_full(&self) -> bool {
+	self.nursery_size.is_some_and(|size| self.nursery_bytes > size)
+    }
+
+    pub fn stats(&self) -> GcStats {
+	self.stats
+    }
+
+    pub fn counts(&self) -> ObjectCounts {
+	self.counts
+    }
+
+    /// Whether the heap is larger than the configured `max_heap_size`.
+    pub fn is_full(&self) -> bool {
+	self.max_heap_size.is_some_and(|max| self.bytes_allocated > max)
+    }
+
+    /// Sets the `max_heap_size`, which the VM lifts while it bootstraps
+    /// the core library.
+    pub fn set_max_heap_size(&mut self, max_heap_size: Option<usize>) {
+	self.max_heap_size = max_heap_size;
+    }
+
+    /// Whether `size` more bytes would still fit under the configured
+    /// `max_heap_size`, for checking objects whose size a script chooses
+    /// before allocating them.
+    pub fn has_room(&self, size: usize) -> bool {
+	self.max_heap_size.is_none_or(|max| self.bytes_allocated.saturating_add(size) <= max)
+    }
+
+    accessors! {
+	string, string_mut, String, ObjString;
+	list, list_mut, List, ObjList;
+	map, map_mut, Map, ObjMap;
+	range, range_mut, Range, ObjRange;
+	function, function_mut, Fn, ObjFn;
+	closure, closure_mut, Closure, ObjClosure;
+	upvalue, upvalue_mut, Upvalue, ObjUpvalue;
+	class, class_mut, Class, ObjClass;
+	instance, instance_mut, Instance, ObjInstance;
+	foreign, foreign_mut, Foreign, ObjForeign;
+	fiber, fiber_mut, Fiber, ObjFiber;
+	module, module_mut, Module, ObjModule;
+    }
+
+    /// The string contents of `value`, if it is a string.
+    pub fn as_str(&self, value: Value) -> Option<&str> {
+	match self.get(value.as_obj()?) {
+	    Obj::String(string) => Some(&string.value),
+	    _ => None,
+	}
+    }
+
+    /// Marks `obj` as reachable, starting a collection if none is in
+    /// progress. Its references are traced by `trace` or `collect`.
+    pub fn mark(&mut self, obj: ObjRef) {
+	if !self.is_collecting {
+	    self.is_collecting = true;
+	    self.bytes_before = self.bytes_allocated;
+	}
+	let color = &mut self.colors[obj.index()];
+	if *color == Color::White {
+	    *color = Color::Gray;
+	    self.gray.push(obj);
+	}
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+	if let Some(obj) = value.as_obj() {
+	    self.mark(obj);
+	}
+    }
+
+    /// Whether objects have been marked by a collection that hasn't
+    /// finished.
+    pub fn is_collecting(&self) -> bool {
+	self.is_collecting
+    }
+
+    /// Forgets what a collection in progress has marked, and which objects
+    /// are tenured, so the next collection starts from nothing and frees
+    /// everything unreachable by then.
+    pub fn reset_marks(&mut self) {
+	if self.is_collecting || self.nursery_size.is_some() {
+	    self.colors.fill(Color::White);
+	    self.gray.clear();
+	    self.is_collecting = false;
+	}
+    }
+
+    /// Traces the references of up to `budget` marked objects, and returns
+    /// whether everything reachable from the marked objects has been.
+    pub fn trace(&mut self, budget: usize) -> bool {
+	for _ in 0..budget {
+	    match self.gray.pop() {
+		Some(obj) => self.blacken(obj),
+		None => break,
+	    }
+	}
+	self.gray.is_empty()
+    }
+
+    /// Traces everything reachable from the objects marked so far and
+    /// frees the rest.
+    pub fn collect(&mut self) -> GcEvent {
+	while let Some(obj) = self.gray.pop() {
+	    self.blacken(obj);
+	}
+
+	// Everything that survives is tenured, if there is a nursery.
+	let survivor = match self.nursery_size {
+	    Some(_) => Color::Black,
+	    None => Color::White,
+	};
+	let mut freed = 0;
+	let mut live_bytes = 0;
+	for (index, slot) in self.objects.iter_mut().enumerate() {
+	    let color = &mut self.colors[index];
+	    if *color != Color::White {
+		*color = survivor;
+		live_bytes += object_size(slot.as_ref().expect("marked objects are live"));
+	    } else if let Some(obj) = slot.take() {
+		*self.counts.of(&obj) -= 1;
+		self.pool.free(obj);
+		self.free.push(index as u32);
+		freed += 1;
+	    }
+	}
+
+	self.forget_freed_strings();
+	self.nursery.clear();
+	self.nursery_bytes = 0;
+	self.is_collecting = false;
+	self.stats.collections += 1;
+	self.stats.objects_freed += freed;
+	self.bytes_allocated = live_bytes;
+	self.next_gc = (live_bytes + live_bytes * self.heap_growth_percent / 100)
+	    .max(self.min_heap_size);
+	self.event(false, freed)
+    }
+
+    /// Traces everything reachable from the objects marked so far, without
+    /// tracing tenured objects that haven't changed, and frees what is left
+    /// in the nursery. The survivors are tenured.
+    pub fn collect_nursery(&mut self) -> GcEvent {
+	while let Some(obj) = self.gray.pop() {
+	    self.blacken(obj);
+	}
+
+	let mut freed = 0;
+	for obj in mem::take(&mut self.nursery) {
+	    let index = obj.index();
+	    if self.colors[index] != Color::White {
+		self.colors[index] = Color::Black;
+		self.stats.objects_tenured += 1;
+	    } else if let Some(obj) = self.objects[index].take() {
+		self.bytes_allocated = self.bytes_allocated.saturating_sub(object_size(&obj));
+		*self.counts.of(&obj) -= 1;
+		self.pool.free(obj);
+		self.free.push(index as u32);
+		freed += 1;
+	    }
+	}
+
+	self.forget_freed_strings();
+	self.nursery_bytes = 0;
+	self.is_collecting = false;
+	self.stats.minor_collections += 1;
+	self.stats.objects_freed += freed;
+	self.event(true, freed)
+    }
+
+    // Removes the interned strings that have just been freed.
+    fn forget_freed_strings(&mut self) {
+	let objects = &self.objects;
+	self.interned.retain(|obj| objects[obj.index()].is_some());
+    }
+
+    // Describes the collection that just finished, apart from how long it
+    // took.
+    fn event(&self, minor: bool, objects_freed: usize) -> GcEvent {
+	GcEvent {
+	    minor,
+	    bytes_before: self.bytes_before,
+	    bytes_after: self.bytes_allocated,
+	    objects_freed,
+	    objects: self.counts,
+	    pause: 0.0,
+	}
+    }
+
+    // Marks everything `obj` references.
+    fn blacken(&mut self, obj: ObjRef) {
+	self.colors[obj.index()] = Color::Black;
+	self.stats.objects_traced += 1;
+	let mut children = Vec::new();
+	self.references(obj, &mut children);
+	for child in children {
+	    self.mark_value(child);
+	}
+    }
+
+    /// Adds what `obj` references to `children`, with the objects among
+    /// them in a method table as closures.
+    pub fn references(&self, obj: ObjRef, children: &mut Vec<Value>) {
+	match self.get(obj) {
+	    Obj::String(_) | Obj::Range(_) => {}
+	    Obj::List(list) => children.extend(list.elements.iter().copied()),
+	    Obj::Map(map) => {
+		for (key, value) in map.iter() {
+		    children.push(key);
+		    children.push(value);
+		}
+	    }
+	    Obj::Fn(function) => {
+		children.extend(function.body.constants.iter().copied());
+		children.push(Value::Obj(function.module));
+	    }
+	    Obj::Closure(closure) => {
+		children.push(Value::Obj(closure.function));
+		children.extend(closure.upvalues.iter().map(|&upvalue| Value::Obj(upvalue)));
+		children.extend(closure.class.map(Value::Obj));
+	    }
+	    Obj::Class(class) => {
+		children.extend(class.class.map(Value::Obj));
+		children.extend(class.superclass.map(Value::Obj));
+		children.push(class.attributes);
+		for method in class.methods.iter().flatten() {
+		    if let Method::Block(closure) = method {
+			children.push(Value::Obj(*closure));
+		    }
+		}
+	    }
+	    // An open upvalue's value is on its fiber's stack.
+	    Obj::Upvalue(ObjUpvalue::Open { fiber, .. }) => children.push(Value::Obj(*fiber)),
+	    Obj::Upvalue(ObjUpvalue::Closed(value)) => children.push(*value),
+	    Obj::Instance(instance) => {
+		children.push(Value::Obj(instance.class));
+		children.extend(instance.fields.iter().copied());
+	    }
+	    Obj::Foreign(foreign) => children.push(Value::Obj(foreign.class)),
+	    Obj::Fiber(fiber) => {
+		children.extend(fiber.stack.iter().copied());
+		children.extend(fiber.frames.iter().map(|frame| Value::Obj(frame.closure)));
+		children.extend(fiber.open_upvalues.iter().map(|&upvalue| Value::Obj(upvalue)));
+		children.extend(fiber.caller.map(Value::Obj));
+		children.push(fiber.error);
+	    }
+	    Obj::Module(module) => children.extend(module.variables.iter().copied()),
+	}
+    }
+}
+
+// Runs the finalizer of a foreign object being freed.
+fn finalize(obj: Obj) {
+    if let Obj::Foreign(mut foreign) = obj {
+	if let Some(finalize) = foreign.finalize {
+	    finalize(&mut *foreign.data);
+	}
+    }
+}
+
+// Roughly how many bytes `obj` occupies, including what it owns.
+fn object_size(obj: &Obj) -> usize {
+    let owned = match obj {
+	Obj::String(string) => string.value.capacity(),
+	Obj::List(list) => list.elements.capacity() * mem::size_of::<Value>(),
+	Obj::Map(map) => map.entries.capacity() * mem::size_of::<MapEntry>(),
+	Obj::Range(_) | Obj::Upvalue(_) => 0,
+	Obj::Fn(function) => {
+	    function.body.code.len()
+		+ function.body.constants.len() * mem::size_of::<Value>()
+		+ function.body.lines.len() * mem::size_of::<u32>()
+	}
+	Obj::Closure(closure) => closure.upvalues.capacity() * mem::size_of::<ObjRef>(),
+	Obj::Class(class) => class.methods.capacity() * mem::size_of::<Option<Method>>(),
+	Obj::Instance(instance) => instance.fields.capacity() * mem::size_of::<Value>(),
+	Obj::Foreign(foreign) => mem::size_of_val(&*foreign.data),
+	Obj::Fiber(fiber) => {
+	    fiber.stack.capacity() * mem::size_of::<Value>()
+		+ fiber.frames.capacity() * mem::size_of::<CallFrame>()
+		+ fiber.open_upvalues.capacity() * mem::size_of::<ObjRef>()
+	}
+	Obj::Module(module) => module.variables.capacity() * mem::size_of::<Value>(),
+    };
+    mem::size_of::<Obj>() + owned
+}
@@ -0,0 +1,224 @@
+// Classifies source for syntax highlighting.
+//
+// It works from the tokens alone, so it copes with code that doesn't
+// parse, as code being edited often doesn't. A little context is kept to
+// tell method definitions from calls: which braces are class bodies, and
+// whether a name starts a member of one.
+
+use alloc::vec::Vec;
+
+use crate::lexer::{Lexeme, Lexer, Span, Token};
+
+/// What a span of source is, for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// A line or block comment, or a shebang line.
+    Comment,
+    Keyword,
+    Number,
+    /// The text of a string literal, including its quotes.
+    String,
+    /// The `%(` and `)` around an expression interpolated into a string.
+    Interpolation,
+    /// `_name` or `__name`.
+    Field,
+    /// The `#` or `#!` of an attribute and its keys.
+    Attribute,
+    /// The name of a method where it is defined.
+    Method,
+    /// The name of a method where it is called.
+    MethodCall,
+    /// Any other name: a variable, class or module.
+    Variable,
+    Operator,
+}
+
+/// Classifies the tokens and comments of `source`, in the order they
+/// appear. Punctuation, whitespace and malformed tokens are left out.
+pub fn tokenize_for_highlighting(source: &str) -> Vec<(Span, TokenClass)> {
+    let mut lexer = Lexer::new(source);
+    let lexemes: Vec<Lexeme> = lexer.by_ref().collect();
+    let mut highlighter = Highlighter {
+	source,
+	tokens: Vec::new(),
+	braces: Vec::new(),
+	in_class_header: false,
+	at_member_start: false,
+	in_attribute: false,
+	in_attribute_group: false,
+    };
+    if source.starts_with("#!/") {
+	let end = source.find('\n').unwrap_or(source.len());
+	highlighter.tokens.push((Span::new(0, end, 1, 1), TokenClass::Comment));
+    }
+    for (index, lexeme) in lexemes.iter().enumerate() {
+	let previous = index.checked_sub(1).map(|index| &lexemes[index].token);
+	let next = lexemes.get(index + 1).map(|lexeme| &lexeme.token);
+	highlighter.lexeme(lexeme, previous, next);
+    }
+    let mut tokens = highlighter.tokens;
+    tokens.extend(lexer.comments().iter().map(|&span| (span, TokenClass::Comment)));
+    tokens.sort_by_key(|(span, _)| span.start);
+    tokens
+}
+
+struct Highlighter<'a> {
+    source: &'a str,
+    tokens: Vec<(Span, TokenClass)>,
+    /// For each `{` not yet closed, whether it opens a class body.
+    braces: Vec<bool>,
+    /// Whether the tokens are between `class` and its body.
+    in_class_header: bool,
+    /// Whether a name here would begin a method definition.
+    at_member_start: bool,
+    /// Whether the token follows an attribute's `#` or `#!`.
+    in_attribute: bool,
+    /// Whether the tokens are in the parentheses of `#group(...)`.
+    in_attribute_group: bool,
+}
+
+impl<'a> Highlighter<'a> {
+    fn lexeme(&mut self, lexeme: &Lexeme, previous: Option<&Token>, next: Option<&Token>) {
+	let span = lexeme.span;
+	let at_member_start = self.at_member_start;
+	let in_class_body = self.braces.last() == Some(&true);
+	let in_attribute = self.in_attribute;
+	self.at_member_start = false;
+	self.in_attribute = false;
+	match &lexeme.token {
+	    Token::Class => {
+		self.in_class_header = true;
+		self.add(span, TokenClass::Keyword);
+	    }
+	    Token::LeftBrace => {
+		self.braces.push(self.in_class_header);
+		self.at_member_start = self.in_class_header;
+		self.in_class_header = false;
+	    }
+	    Token::RightBrace => {
+		self.braces.pop();
+	    }
+	    Token::Line => self.at_member_start = in_class_body,
+	    Token::Static | Token::Construct | Token::Foreign => {
+		self.at_member_start = at_member_start;
+		self.add(span, TokenClass::Keyword);
+	    }
+	    Token::RightParen => self.in_attribute_group = false,
+	    Token::Hash => {
+		self.in_attribute = true;
+		self.add(span, TokenClass::Attribute);
+	    }
+	    Token::Bang if in_attribute => {
+		self.in_attribute = true;
+		self.add(span, TokenClass::Attribute);
+	    }
+	    Token::Name(_) => {
+		if in_attribute {
+		    self.in_attribute_group = next == Some(&Token::LeftParen);
+		}
+		let class = match previous {
+		    _ if in_attribute => TokenClass::Attribute,
+		    Some(Token::LeftParen) | Some(Token::Comma) if self.in_attribute_group => {
+			TokenClass::Attribute
+		    }
+		    Some(Token::Dot) => TokenClass::MethodCall,
+		    _ if at_member_start => TokenClass::Method,
+		    _ if self.in_class_header => TokenClass::Variable,
+		    _ => match next {
+			Some(Token::LeftParen) | Some(Token::LeftBrace) => TokenClass::MethodCall,
+			_ => TokenClass::Variable,
+		    },
+		};
+		self.add(span, class);
+	    }
+	    Token::Field(_) | Token::StaticField(_) => self.add(span, TokenClass::Field),
+	    Token::Number(_) => self.add(span, TokenClass::Number),
+	    Token::String(_) => self.add(span, TokenClass::String),
+	    // The delimiters of an interpolation are lexed with the string
+	    // around them, so split them off.
+	    Token::InterpolationStart(_) => {
+		self.add_part(span, span.start, span.end - 2, TokenClass::String);
+		self.add_part(span, span.end - 2, span.end, TokenClass::Interpolation);
+	    }
+	    Token::InterpolationPart(_) => {
+		self.add_part(span, span.start, span.start + 1, TokenClass::Interpolation);
+		self.add_part(span, span.start + 1, span.end - 2, TokenClass::String);
+		self.add_part(span, span.end - 2, span.end, TokenClass::Interpolation);
+	    }
+	    Token::InterpolationEnd(_) => {
+		self.add_part(span, span.start, span.start + 1, TokenClass::Interpolation);
+		self.add_part(span, span.start + 1, span.end, TokenClass::String);
+	    }
+	    Token::As
+	    | Token::Break
+	    | Token::Continue
+	    | Token::Else
+	    | Token::False
+	    | Token::For
+	    | Token::If
+	    | Token::Import
+	    | Token::In
+	    | Token::Is
+	    | Token::Null
+	    | Token::Return
+	    | Token::Super
+	    | Token::This
+	    | Token::True
+	    | Token::Var
+	    | Token::While => self.add(span, TokenClass::Keyword),
+	    Token::DotDot
+	    | Token::DotDotDot
+	    | Token::Star
+	    | Token::Slash
+	    | Token::Percent
+	    | Token::Plus
+	    | Token::Minus
+	    | Token::LtLt
+	    | Token::GtGt
+	    | Token::Pipe
+	    | Token::PipePipe
+	    | Token::Caret
+	    | Token::Amp
+	    | Token::AmpAmp
+	    | Token::Bang
+	    | Token::Tilde
+	    | Token::Question
+	    | Token::Eq
+	    | Token::Lt
+	    | Token::Gt
+	    | Token::LtEq
+	    | Token::GtEq
+	    | Token::EqEq
+	    | Token::BangEq => self.add(span, TokenClass::Operator),
+	    Token::LeftParen
+	    | Token::LeftBracket
+	    | Token::RightBracket
+	    | Token::Colon
+	    | Token::Dot
+	    | Token::Comma
+	    | Token::Error(_)
+	    | Token::Eof => {}
+	}
+    }
+
+    fn add(&mut self, span: Span, class: TokenClass) {
+	self.tokens.push((span, class));
+    }
+
+    // Adds the part of `span` from `start` to `end`, working out the line
+    // and column it starts at.
+    fn add_part(&mut self, span: Span, start: usize, end: usize, class: TokenClass) {
+	if start >= end {
+	    return;
+	}
+	let before = &self.source[span.start..start];
+	let (line, column) = match before.rfind('\n') {
+	    Some(newline) => {
+		let lines = before.matches('\n').count() as u32;
+		(span.line + lines, (before.len() - newline) as u32)
+	    }
+	    None => (span.line, span.column + before.len() as u32),
+	};
+	self.add(Span::new(start, end, line, column), class);
+    }
+}
@@ -0,0 +1,474 @@
+// A JSON parser that reads a document a byte at a time and reports it as a
+// series of events, so a document never has to be in memory all at once.
+// The `json` module builds Wren values from its events, and a host can use
+// it to feed a large document to a script piece by piece.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::error;
+use core::fmt;
+use core::iter::Map;
+
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, Read};
+
+// How deeply arrays and objects can nest, so deep input is an error rather
+// than a stack overflow where it is turned into values, and so is a List
+// that contains itself when converting back.
+pub(crate) const MAX_NESTING: usize = 512;
+
+/// What is wrong with a JSON document, and where, with lines and columns
+/// counted from 1. An error reading the document is reported where the
+/// parser had got to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "{} at line {}, column {}.", self.message, self.line, self.column)
+    }
+}
+
+impl error::Error for JsonError {}
+
+/// How `WrenVM::get_slot_json_with` and `Json.stringify` write JSON. The
+/// default writes everything on one line, with the members of objects in
+/// the order of their Maps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// What each level of nesting is indented by, with each element and
+    /// member on a line of its own, unless it is empty.
+    pub indent: String,
+    /// Whether the members of objects are written in order of their keys.
+    pub sort_keys: bool,
+    /// Whether characters outside ASCII are written as `\u` escapes, those
+    /// outside the Basic Multilingual Plane as surrogate pairs.
+    pub ascii_only: bool,
+}
+
+/// A step through a JSON document, in the order they appear in it. The
+/// members of an object are each a `Key` followed by the events of its
+/// value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonEvent {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    Key(String),
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Clone, Copy)]
+enum Container {
+    Object,
+    Array,
+}
+
+// What the parser expects to find next.
+#[derive(Clone, Copy)]
+enum Expect {
+    Value,
+    // The first element of an array, or the `]` of an empty one.
+    FirstElement,
+    // The first key of an object, or the `}` of an empty one.
+    FirstKey,
+    // A `,` or the end of the innermost array or object, or the end of the
+    // document after its value.
+    Separator,
+}
+
+/// Parses a JSON document as described by RFC 8259, from bytes that are
+/// read only as they are needed. It is an iterator over the events of the
+/// document, which ends after the last one or after the first error, such
+/// as text after the document's value.
+pub struct JsonParser<I> {
+    bytes: I,
+    peeked: Option<u8>,
+    // Why reading the bytes failed, which ends the document.
+    failure: Option<String>,
+    line: usize,
+    column: usize,
+    containers: Vec<Container>,
+    expect: Expect,
+    is_finished: bool,
+}
+
+impl<B: Iterator<Item = u8>> JsonParser<Map<B, fn(u8) -> Result<u8, Infallible>>> {
+    /// A parser of the document in `bytes`, such as those of a `&str`.
+    pub fn from_bytes(bytes: impl IntoIterator<IntoIter = B>) -> Self {
+	JsonParser::new(bytes.into_iter().map(Ok as fn(u8) -> Result<u8, Infallible>))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> JsonParser<io::Bytes<BufReader<R>>> {
+    /// A parser of the document `reader` reads, which is buffered.
+    pub fn from_reader(reader: R) -> Self {
+	JsonParser::new(BufReader::new(reader).bytes())
+    }
+}
+
+impl<I, E> JsonParser<I>
+where
+    I: Iterator<Item = Result<u8, E>>,
+    E: fmt::Display,
+{
+    /// A parser of the document in `bytes`, which ends with the first
+    /// error reading them.
+    pub fn new(bytes: I) -> Self {
+	JsonParser {
+	    bytes,
+	    peeked: None,
+	    failure: None,
+	    line: 1,
+	    column: 1,
+	    containers: Vec::new(),
+	    expect: Expect::Value,
+	    is_finished: false,
+	}
+    }
+
+    /// How many arrays and objects the parser is inside.
+    pub fn depth(&self) -> usize {
+	self.containers.len()
+    }
+
+    // The next event, or `None` at the end of the document.
+    fn step(&mut self) -> Result<Option<JsonEvent>, JsonError> {
+	self.skip_whitespace();
+	let container = self.containers.last().copied();
+	// The byte that ends the innermost array or object.
+	let closer = match container {
+	    Some(Container::Object) => b'}',
+	    _ => b']',
+	};
+	let event = match (self.expect, container) {
+	    (Expect::Separator, None) if self.peek().is_some() => {
+		return Err(self.error("Unexpected text after the value"));
+	    }
+	    (Expect::Separator, None) => return self.failure().map(|()| None),
+	    (Expect::FirstElement | Expect::FirstKey | Expect::Separator, _)
+		if self.eat(closer) =>
+	    {
+		self.close()
+	    }
+	    (Expect::Value | Expect::FirstElement, _) => self.value()?,
+	    (Expect::FirstKey, _) => self.key()?,
+	    (Expect::Separator, Some(container)) => {
+		self.expect(b',')?;
+		self.skip_whitespace();
+		match container {
+		    Container::Array => self.value()?,
+		    Container::Object => self.key()?,
+		}
+	    }
+	};
+	Ok(Some(event))
+    }
+
+    fn value(&mut self) -> Result<JsonEvent, JsonError> {
+	let event = match self.peek() {
+	    Some(b'{') => return self.open(Container::Object),
+	    Some(b'[') => return self.open(Container::Array),
+	    Some(b'"') => JsonEvent::String(self.string()?),
+	    Some(b'-' | b'0'..=b'9') => JsonEvent::Number(self.number()?),
+	    Some(b't') => self.keyword("true", JsonEvent::Bool(true))?,
+	    Some(b'f') => self.keyword("false", JsonEvent::Bool(false))?,
+	    Some(b'n') => self.keyword("null", JsonEvent::Null)?,
+	    _ => return Err(self.unexpected()),
+	};
+	self.expect = Expect::Separator;
+	Ok(event)
+    }
+
+    fn key(&mut self) -> Result<JsonEvent, JsonError> {
+	if self.peek() != Some(b'"') {
+	    return Err(self.unexpected());
+	}
+	let key = self.string()?;
+	self.skip_whitespace();
+	self.expect(b':')?;
+	self.expect = Expect::Value;
+	Ok(JsonEvent::Key(key))
+    }
+
+    // Steps past the `{` or `[` that starts an object or array.
+    fn open(&mut self, container: Container) -> Result<JsonEvent, JsonError> {
+	if self.containers.len() == MAX_NESTING {
+	    return Err(self.error("Arrays and objects are nested too deeply"));
+	}
+	self.advance();
+	self.containers.push(container);
+	Ok(match container {
+	    Container::Object => {
+		self.expect = Expect::FirstKey;
+		JsonEvent::StartObject
+	    }
+	    Container::Array => {
+		self.expect = Expect::FirstElement;
+		JsonEvent::StartArray
+	    }
+	})
+    }
+
+    // Ends the innermost array or object, whose `]` or `}` has been read.
+    fn close(&mut self) -> JsonEvent {
+	self.expect = Expect::Separator;
+	match self.containers.pop() {
+	    Some(Container::Object) => JsonEvent::EndObject,
+	    _ => JsonEvent::EndArray,
+	}
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+	let (line, column) = (self.line, self.column);
+	self.advance();
+	let mut bytes = Vec::new();
+	loop {
+	    match self.peek() {
+		Some(b'"') => break,
+		Some(b'\\') => {
+		    self.advance();
+		    let mut encoded = [0; 4];
+		    let escaped = self.escape()?;
+		    bytes.extend_from_slice(escaped.encode_utf8(&mut encoded).as_bytes());
+		}
+		Some(0x00..=0x1f) => {
+		    return Err(self.error("Control characters in strings must be escaped"));
+		}
+		Some(byte) => {
+		    self.advance();
+		    bytes.push(byte);
+		}
+		None => {
+		    self.failure()?;
+		    return Err(JsonError {
+			message: "Unterminated string".to_string(),
+			line,
+			column,
+		    });
+		}
+	    }
+	}
+	self.advance();
+	String::from_utf8(bytes).map_err(|_| JsonError {
+	    message: "Invalid UTF-8 in a string".to_string(),
+	    line,
+	    column,
+	})
+    }
+
+    // The character an escape sequence stands for, after its backslash.
+    fn escape(&mut self) -> Result<char, JsonError> {
+	let escaped = match self.peek() {
+	    Some(b'"') => '"',
+	    Some(b'\\') => '\\',
+	    Some(b'/') => '/',
+	    Some(b'b') => '\u{8}',
+	    Some(b'f') => '\u{c}',
+	    Some(b'n') => '\n',
+	    Some(b'r') => '\r',
+	    Some(b't') => '\t',
+	    Some(b'u') => {
+		self.advance();
+		let unit = self.hex_unit()?;
+		// A character outside the Basic Multilingual Plane is written as
+		// a surrogate pair.
+		let code_point = match unit {
+		    0xd800..=0xdbff if self.eat(b'\\') => {
+			if !self.eat(b'u') {
+			    return Err(self.error("Unpaired surrogate in a \\u escape"));
+			}
+			match self.hex_unit()? {
+			    low @ 0xdc00..=0xdfff => {
+				0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+			    }
+			    _ => return Err(self.error("Expected a low surrogate")),
+			}
+		    }
+		    _ => unit,
+		};
+		return char::from_u32(code_point)
+		    .ok_or_else(|| self.error("Unpaired surrogate in a \\u escape"));
+	    }
+	    _ => return Err(self.error("Invalid escape sequence")),
+	};
+	self.advance();
+	Ok(escaped)
+    }
+
+    // The four hex digits of a `\u` escape.
+    fn hex_unit(&mut self) -> Result<u32, JsonError> {
+	let mut unit = 0;
+	for _ in 0..4 {
+	    match self.peek().and_then(|byte| (byte as char).to_digit(16)) {
+		Some(digit) => {
+		    self.advance();
+		    unit = unit * 16 + digit;
+		}
+		None => return Err(self.error("Expected four hex digits")),
+	    }
+	}
+	Ok(unit)
+    }
+
+    fn number(&mut self) -> Result<f64, JsonError> {
+	let mut text = String::new();
+	if self.eat(b'-') {
+	    text.push('-');
+	}
+	// A number has no leading zeros.
+	if self.eat(b'0') {
+	    text.push('0');
+	} else if self.digits(&mut text) == 0 {
+	    return Err(self.unexpected());
+	}
+	if self.eat(b'.') {
+	    text.push('.');
+	    if self.digits(&mut text) == 0 {
+		return Err(self.unexpected());
+	    }
+	}
+	if self.eat(b'e') || self.eat(b'E') {
+	    text.push('e');
+	    if self.eat(b'-') {
+		text.push('-');
+	    } else {
+		self.eat(b'+');
+	    }
+	    if self.digits(&mut text) == 0 {
+		return Err(self.unexpected());
+	    }
+	}
+	text.parse().map_err(|_| self.error("Invalid number"))
+    }
+
+    // Reads digits onto the end of `text`, and returns how many.
+    fn digits(&mut self, text: &mut String) -> usize {
+	let start = text.len();
+	while let Some(digit @ b'0'..=b'9') = self.peek() {
+	    self.advance();
+	    text.push(digit as char);
+	}
+	text.len() - start
+    }
+
+    fn keyword(&mut self, keyword: &str, event: JsonEvent) -> Result<JsonEvent, JsonError> {
+	for byte in keyword.bytes() {
+	    if !self.eat(byte) {
+		return Err(self.unexpected());
+	    }
+	}
+	Ok(event)
+    }
+
+    fn skip_whitespace(&mut self) {
+	while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+	    self.advance();
+	}
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+	if self.peeked.is_none() && self.failure.is_none() {
+	    match self.bytes.next() {
+		Some(Ok(byte)) => self.peeked = Some(byte),
+		Some(Err(error)) => self.failure = Some(error.to_string()),
+		None => {}
+	    }
+	}
+	self.peeked
+    }
+
+    // Steps past the byte `peek` returned, keeping count of the line and
+    // column of the next one.
+    fn advance(&mut self) {
+	match self.peeked.take() {
+	    Some(b'\n') => {
+		self.line += 1;
+		self.column = 1;
+	    }
+	    // The continuation bytes of a UTF-8 character don't start a
+	    // column of their own.
+	    Some(byte) if byte & 0xc0 != 0x80 => self.column += 1,
+	    _ => {}
+	}
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+	let is_next = self.peek() == Some(byte);
+	if is_next {
+	    self.advance();
+	}
+	is_next
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+	match self.eat(byte) {
+	    true => Ok(()),
+	    false => Err(self.unexpected()),
+	}
+    }
+
+    fn unexpected(&mut self) -> JsonError {
+	let lead = match self.peek() {
+	    Some(byte) => byte,
+	    None => return self.error("Unexpected end of JSON"),
+	};
+	// The rest of the character is read to show it, which is fine as
+	// parsing stops here.
+	let mut encoded = vec![lead];
+	while encoded.len() < 4 {
+	    self.peeked = None;
+	    match self.peek() {
+		Some(byte) if byte & 0xc0 == 0x80 => encoded.push(byte),
+		_ => break,
+	    }
+	}
+	let c = String::from_utf8_lossy(&encoded).chars().next().unwrap_or('\u{fffd}');
+	self.error(&format!("Unexpected character '{}'", c.escape_debug()))
+    }
+
+    // Whether reading the bytes failed, as an error.
+    fn failure(&self) -> Result<(), JsonError> {
+	match &self.failure {
+	    Some(failure) => Err(self.error(failure)),
+	    None => Ok(()),
+	}
+    }
+
+    // An error at the current position, which is about reading the bytes if
+    // that is what failed.
+    fn error(&self, message: &str) -> JsonError {
+	JsonError {
+	    message: self.failure.as_deref().unwrap_or(message).to_string(),
+	    line: self.line,
+	    column: self.column,
+	}
+    }
+}
+
+impl<I, E> Iterator for JsonParser<I>
+where
+    I: Iterator<Item = Result<u8, E>>,
+    E: fmt::Display,
+{
+    type Item = Result<JsonEvent, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+	if self.is_finished {
+	    return None;
+	}
+	let step = self.step();
+	self.is_finished = !matches!(step, Ok(Some(_)));
+	step.transpose()
+    }
+}
@@ -0,0 +1,781 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::Peekable;
+use core::str::CharIndices;
+
+/// Location of a token in the source: byte offsets plus the 1-based line
+/// and column where it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: u32, column: u32) -> Span {
+	Span { start, end, line, column }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+	Span {
+	    start: self.start.min(other.start),
+	    end: self.end.max(other.end),
+	    line: self.line,
+	    column: self.column,
+	}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Colon,
+    Dot,
+    DotDot,
+    DotDotDot,
+    Comma,
+    Star,
+    Slash,
+    Percent,
+    Hash,
+    Plus,
+    Minus,
+    LtLt,
+    GtGt,
+    Pipe,
+    PipePipe,
+    Caret,
+    Amp,
+    AmpAmp,
+    Bang,
+    Tilde,
+    Question,
+    Eq,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    EqEq,
+    BangEq,
+
+    As,
+    Break,
+    Continue,
+    Class,
+    Construct,
+    Else,
+    False,
+    For,
+    Foreign,
+    If,
+    Import,
+    In,
+    Is,
+    Null,
+    Return,
+    Static,
+    Super,
+    This,
+    True,
+    Var,
+    While,
+
+    /// `_name`, an instance field.
+    Field(String),
+    /// `__name`, a static field.
+    StaticField(String),
+    Name(String),
+    Number(f64),
+    String(String),
+    /// The part of a string before its first `%(`.
+    InterpolationStart(String),
+    /// Text between a `)` closing one interpolation and the next `%(`.
+    InterpolationPart(String),
+    /// Text after the last interpolation, up to the closing quote.
+    InterpolationEnd(String),
+
+    /// A newline. Wren statements are newline-terminated.
+    Line,
+    /// Malformed input; the lexer keeps going after reporting it.
+    Error(String),
+    Eof,
+}
+
+impl Token {
+    pub fn keyword(name: &str) -> Option<Token> {
+	let token = match name {
+	    "as" => Token::As,
+	    "break" => Token::Break,
+	    "continue" => Token::Continue,
+	    "class" => Token::Class,
+	    "construct" => Token::Construct,
+	    "else" => Token::Else,
+	    "false" => Token::False,
+	    "for" => Token::For,
+	    "foreign" => Token::Foreign,
+	    "if" => Token::If,
+	    "import" => Token::Import,
+	    "in" => Token::In,
+	    "is" => Token::Is,
+	    "null" => Token::Null,
+	    "return" => Token::Return,
+	    "static" => Token::Static,
+	    "super" => Token::Super,
+	    "this" => Token::This,
+	    "true" => Token::True,
+	    "var" => Token::Var,
+	    "while" => Token::While,
+	    _ => return None,
+	};
+	Some(token)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	let text = match self {
+	    Token::LeftParen => "(",
+	    Token::RightParen => ")",
+	    Token::LeftBracket => "[",
+	    Token::RightBracket => "]",
+	    Token::LeftBrace => "{",
+	    Token::RightBrace => "}",
+	    Token::Colon => ":",
+	    Token::Dot => ".",
+	    Token::DotDot => "..",
+	    Token::DotDotDot => "...",
+	    Token::Comma => ",",
+	    Token::Star => "*",
+	    Token::Slash => "/",
+	    Token::Percent => "%",
+	    Token::Hash => "#",
+	    Token::Plus => "+",
+	    Token::Minus => "-",
+	    Token::LtLt => "<<",
+	    Token::GtGt => ">>",
+	    Token::Pipe => "|",
+	    Token::PipePipe => "||",
+	    Token::Caret => "^",
+	    Token::Amp => "&",
+	    Token::AmpAmp => "&&",
+	    Token::Bang => "!",
+	    Token::Tilde => "~",
+	    Token::Question => "?",
+	    Token::Eq => "=",
+	    Token::Lt => "<",
+	    Token::Gt => ">",
+	    Token::LtEq => "<=",
+	    Token::GtEq => ">=",
+	    Token::EqEq => "==",
+	    Token::BangEq => "!=",
+	    Token::As => "as",
+	    Token::Break => "break",
+	    Token::Continue => "continue",
+	    Token::Class => "class",
+	    Token::Construct => "construct",
+	    Token::Else => "else",
+	    Token::False => "false",
+	    Token::For => "for",
+	    Token::Foreign => "foreign",
+	    Token::If => "if",
+	    Token::Import => "import",
+	    Token::In => "in",
+	    Token::Is => "is",
+	    Token::Null => "null",
+	    Token::Return => "return",
+	    Token::Static => "static",
+	    Token::Super => "super",
+	    Token::This => "this",
+	    Token::True => "true",
+	    Token::Var => "var",
+	    Token::While => "while",
+	    Token::Field(name) | Token::StaticField(name) | Token::Name(name) => name,
+	    Token::Number(n) => return write!(f, "{}", n),
+	    Token::String(s) => return write!(f, "\"{}\"", s),
+	    Token::InterpolationStart(s) => return write!(f, "\"{}%(", s),
+	    Token::InterpolationPart(s) => return write!(f, "){}%(", s),
+	    Token::InterpolationEnd(s) => return write!(f, "){}\"", s),
+	    Token::Line => "newline",
+	    Token::Error(message) => message,
+	    Token::Eof => "end of file",
+	};
+	f.write_str(text)
+    }
+}
+
+/// How deeply string interpolations may nest inside one another by
+/// default.
+pub const MAX_INTERPOLATION_NESTING: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexeme {
+    pub token: Token,
+    pub span: Span,
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    start: usize,
+    start_line: u32,
+    start_column: u32,
+    line: u32,
+    line_start: usize,
+    done: bool,
+    // Unmatched '(' count for each interpolation we're inside, innermost
+    // last. When one drops to zero, the ')' resumes the enclosing string.
+    parens: Vec<usize>,
+    // Every comment skipped so far, in order.
+    comments: Vec<Span>,
+    // Errors in a string or comment cut off by the end of the source, which
+    // wren_c reports at the line it stops on and then lexes on past.
+    errors: Vec<Lexeme>,
+    // The rest of the bytes of an invalid character, last first. wren_c
+    // lexes bytes rather than characters, so each is an error of its own.
+    invalid_bytes: Vec<u8>,
+    // How deeply interpolations may nest, which the parser can change.
+    pub(crate) max_interpolation_nesting: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
+	let mut lexer = Lexer {
+	    source,
+	    chars: source.char_indices().peekable(),
+	    start: 0,
+	    start_line: 1,
+	    start_column: 1,
+	    line: 1,
+	    line_start: 0,
+	    done: false,
+	    parens: Vec::new(),
+	    comments: Vec::new(),
+	    errors: Vec::new(),
+	    invalid_bytes: Vec::new(),
+	    max_interpolation_nesting: MAX_INTERPOLATION_NESTING,
+	};
+	lexer.skip_shebang();
+	lexer
+    }
+
+    /// Lexes the whole of `source`, ending with `Token::Eof`.
+    pub fn tokenize(source: &str) -> Vec<Lexeme> {
+	Lexer::new(source).collect()
+    }
+
+    /// The spans of the comments skipped so far, which the tokens leave
+    /// out.
+    pub fn comments(&self) -> &[Span] {
+	&self.comments
+    }
+
+    /// The errors found so far in tokens that were still produced, as
+    /// `Token::Error`s: a string or block comment cut off by the end of
+    /// the source, which ends there.
+    pub fn errors(&self) -> &[Lexeme] {
+	&self.errors
+    }
+
+    pub(crate) fn take_errors(&mut self) -> Vec<Lexeme> {
+	core::mem::take(&mut self.errors)
+    }
+
+    pub fn next_token(&mut self) -> Lexeme {
+	if let Some(byte) = self.invalid_bytes.pop() {
+	    self.begin_token();
+	    return self.make(invalid_byte(byte));
+	}
+	loop {
+	    self.begin_token();
+	    let ch = match self.advance() {
+		Some(ch) => ch,
+		None => return self.make(Token::Eof),
+	    };
+	    let token = match ch {
+		'(' => {
+		    if let Some(count) = self.parens.last_mut() {
+			*count += 1;
+		    }
+		    Token::LeftParen
+		}
+		')' => {
+		    if let Some(count) = self.parens.last_mut() {
+			*count -= 1;
+			if *count == 0 {
+			    self.parens.pop();
+			    self.read_string(true)
+			} else {
+			    Token::RightParen
+			}
+		    } else {
+			Token::RightParen
+		    }
+		}
+		'[' => Token::LeftBracket,
+		']' => Token::RightBracket,
+		'{' => Token::LeftBrace,
+		'}' => Token::RightBrace,
+		':' => Token::Colon,
+		',' => Token::Comma,
+		'*' => Token::Star,
+		'%' => Token::Percent,
+		'#' => Token::Hash,
+		'^' => Token::Caret,
+		'+' => Token::Plus,
+		'-' => Token::Minus,
+		'~' => Token::Tilde,
+		'?' => Token::Question,
+		'|' => self.two_char('|', Token::PipePipe, Token::Pipe),
+		'&' => self.two_char('&', Token::AmpAmp, Token::Amp),
+		'=' => self.two_char('=', Token::EqEq, Token::Eq),
+		'!' => self.two_char('=', Token::BangEq, Token::Bang),
+		'.' => {
+		    if self.match_char('.') {
+			self.two_char('.', Token::DotDotDot, Token::DotDot)
+		    } else {
+			Token::Dot
+		    }
+		}
+		'/' => {
+		    if self.match_char('/') {
+			self.skip_line_comment();
+			self.add_comment();
+			continue;
+		    }
+		    if self.match_char('*') {
+			self.skip_block_comment();
+			self.add_comment();
+			continue;
+		    }
+		    Token::Slash
+		}
+		'<' => {
+		    if self.match_char('<') {
+			Token::LtLt
+		    } else {
+			self.two_char('=', Token::LtEq, Token::Lt)
+		    }
+		}
+		'>' => {
+		    if self.match_char('>') {
+			Token::GtGt
+		    } else {
+			self.two_char('=', Token::GtEq, Token::Gt)
+		    }
+		}
+		'\n' => {
+		    let lexeme = self.make(Token::Line);
+		    self.new_line();
+		    return lexeme;
+		}
+		' ' | '\r' | '\t' => {
+		    self.skip_whitespace();
+		    continue;
+		}
+		'"' if self.peek() == Some('"') && self.peek_next() == Some('"') => {
+		    self.read_raw_string()
+		}
+		'"' => self.read_string(false),
+		'_' => {
+		    let is_static = self.match_char('_');
+		    let name = self.read_name();
+		    if is_static {
+			Token::StaticField(name)
+		    } else {
+			Token::Field(name)
+		    }
+		}
+		ch if is_name_start(ch) => {
+		    let name = self.read_name();
+		    Token::keyword(&name).unwrap_or(Token::Name(name))
+		}
+		'0' if self.peek() == Some('x') => self.read_hex_number(),
+		ch if ch.is_ascii_digit() => self.read_number(),
+		ch if ch.is_ascii_graphic() => {
+		    Token::Error(format!("Invalid character '{}'.", ch))
+		}
+		ch => {
+		    let mut buffer = [0; 4];
+		    let bytes = ch.encode_utf8(&mut buffer).as_bytes();
+		    self.invalid_bytes.extend(bytes[1..].iter().rev());
+		    invalid_byte(bytes[0])
+		}
+	    };
+	    return self.make(token);
+	}
+    }
+
+    fn begin_token(&mut self) {
+	self.start = self.offset();
+	self.start_line = self.line;
+	self.start_column = (self.start - self.line_start) as u32 + 1;
+    }
+
+    fn add_comment(&mut self) {
+	let span = Span::new(self.start, self.offset(), self.start_line, self.start_column);
+	self.comments.push(span);
+    }
+
+    // Records an error at the current line, for a token that goes on to be
+    // produced.
+    fn error(&mut self, message: &str) {
+	let span = Span::new(self.start, self.offset(), self.line, self.start_column);
+	self.errors.push(Lexeme {
+	    token: Token::Error(message.to_string()),
+	    span,
+	});
+    }
+
+    fn make(&mut self, token: Token) -> Lexeme {
+	Lexeme {
+	    token,
+	    span: Span::new(self.start, self.offset(), self.start_line, self.start_column),
+	}
+    }
+
+    fn offset(&mut self) -> usize {
+	match self.chars.peek() {
+	    Some(&(i, _)) => i,
+	    None => self.source.len(),
+	}
+    }
+
+    fn advance(&mut self) -> Option<char> {
+	self.chars.next().map(|(_, ch)| ch)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+	self.chars.peek().map(|&(_, ch)| ch)
+    }
+
+    fn peek_next(&self) -> Option<char> {
+	let mut chars = self.chars.clone();
+	chars.next();
+	chars.next().map(|(_, ch)| ch)
+    }
+
+    fn match_char(&mut self, ch: char) -> bool {
+	if self.peek() == Some(ch) {
+	    self.advance();
+	    return true;
+	}
+	false
+    }
+
+    fn two_char(&mut self, ch: char, two: Token, one: Token) -> Token {
+	if self.match_char(ch) {
+	    two
+	} else {
+	    one
+	}
+    }
+
+    fn new_line(&mut self) {
+	self.line += 1;
+	self.line_start = self.offset();
+    }
+
+    fn skip_shebang(&mut self) {
+	if self.source.starts_with("#!/") {
+	    while let Some(ch) = self.peek() {
+		if ch == '\n' {
+		    break;
+		}
+		self.advance();
+	    }
+	}
+    }
+
+    fn skip_whitespace(&mut self) {
+	while let Some(ch) = self.peek() {
+	    if ch != ' ' && ch != '\r' && ch != '\t' {
+		break;
+	    }
+	    self.advance();
+	}
+    }
+
+    fn skip_line_comment(&mut self) {
+	while let Some(ch) = self.peek() {
+	    if ch == '\n' {
+		break;
+	    }
+	    self.advance();
+	}
+    }
+
+    // Block comments nest, so count the depth.
+    fn skip_block_comment(&mut self) {
+	let mut nesting = 1;
+	while nesting > 0 {
+	    match self.advance() {
+		None => return self.error("Unterminated block comment."),
+		Some('/') if self.peek() == Some('*') => {
+		    self.advance();
+		    nesting += 1;
+		}
+		Some('*') if self.peek() == Some('/') => {
+		    self.advance();
+		    nesting -= 1;
+		}
+		Some('\n') => self.new_line(),
+		Some(_) => {}
+	    }
+	}
+    }
+
+    fn read_name(&mut self) -> String {
+	while let Some(ch) = self.peek() {
+	    if !is_name_start(ch) && !ch.is_ascii_digit() {
+		break;
+	    }
+	    self.advance();
+	}
+	let end = self.offset();
+	self.source[self.start..end].to_string()
+    }
+
+    fn read_number(&mut self) -> Token {
+	self.skip_digits();
+	// A fraction needs a digit after the dot, otherwise `1.foo` is a call.
+	if self.peek() == Some('.') && self.peek_next().is_some_and(|ch| ch.is_ascii_digit()) {
+	    self.advance();
+	    self.skip_digits();
+	}
+	// An exponent may have a sign, but must have a digit.
+	if self.match_char('e') || self.match_char('E') {
+	    if !self.match_char('+') {
+		self.match_char('-');
+	    }
+	    if !self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+		return Token::Error("Unterminated scientific notation.".to_string());
+	    }
+	    self.skip_digits();
+	}
+	let end = self.offset();
+	match self.source[self.start..end].parse::<f64>() {
+	    Ok(n) if n.is_infinite() => number_too_large(),
+	    Ok(n) => Token::Number(n),
+	    Err(_) => Token::Error("Invalid number literal.".to_string()),
+	}
+    }
+
+    // Reads the digits of a hexadecimal literal, after its `0`. As in
+    // wren_c, `0x` alone is zero, and the value must fit in 64 signed bits.
+    fn read_hex_number(&mut self) -> Token {
+	self.advance();
+	let digits = self.offset();
+	while self.peek().is_some_and(|ch| ch.is_ascii_hexdigit()) {
+	    self.advance();
+	}
+	let digits = &self.source[digits..self.offset()];
+	if digits.is_empty() {
+	    return Token::Number(0.0);
+	}
+	match i64::from_str_radix(digits, 16) {
+	    Ok(n) => Token::Number(n as f64),
+	    Err(_) => number_too_large(),
+	}
+    }
+
+    fn skip_digits(&mut self) {
+	while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+	    self.advance();
+	}
+    }
+
+    // Reads string contents up to the closing quote or the next `%(`.
+    // `resumed` is set when continuing after an interpolated expression.
+    fn read_string(&mut self, resumed: bool) -> Token {
+	let mut string = String::new();
+	loop {
+	    let ch = match self.advance() {
+		None => {
+		    self.error("Unterminated string.");
+		    break;
+		}
+		Some(ch) => ch,
+	    };
+	    match ch {
+		'"' => break,
+		'\n' => {
+		    self.new_line();
+		    string.push(ch);
+		}
+		'\\' => {
+		    if let Err(message) = self.read_escape(&mut string) {
+			self.skip_string();
+			return Token::Error(message);
+		    }
+		}
+		'%' => {
+		    if !self.match_char('(') {
+			self.skip_string();
+			return Token::Error("Expect '(' after '%'.".to_string());
+		    }
+		    if self.parens.len() >= self.max_interpolation_nesting {
+			self.skip_string();
+			return Token::Error(format!(
+			    "Interpolation may only nest {} levels deep.",
+			    self.max_interpolation_nesting
+			));
+		    }
+		    self.parens.push(1);
+		    return if resumed {
+			Token::InterpolationPart(string)
+		    } else {
+			Token::InterpolationStart(string)
+		    };
+		}
+		ch => string.push(ch),
+	    }
+	}
+	if resumed {
+	    Token::InterpolationEnd(string)
+	} else {
+	    Token::String(string)
+	}
+    }
+
+    // Reads a raw string, after its opening quote, up to the closing `"""`,
+    // with no escapes or interpolation. As in wren_c, carriage returns are
+    // dropped, and so are a first and a last line holding nothing but
+    // spaces and tabs, along with the newline that separates them from the
+    // rest.
+    fn read_raw_string(&mut self) -> Token {
+	self.advance();
+	self.advance();
+	let mut string = String::new();
+	loop {
+	    match self.advance() {
+		None => return Token::Error("Unterminated raw string.".to_string()),
+		Some('"') if self.peek() == Some('"') && self.peek_next() == Some('"') => {
+		    self.advance();
+		    self.advance();
+		    break;
+		}
+		Some('\r') => {}
+		Some('\n') => {
+		    self.new_line();
+		    string.push('\n');
+		}
+		Some(ch) => string.push(ch),
+	    }
+	}
+	let is_blank = |line: &str| line.chars().all(|ch| ch == ' ' || ch == '\t');
+	let start = match string.find('\n') {
+	    Some(newline) if is_blank(&string[..newline]) => newline + 1,
+	    _ => 0,
+	};
+	let end = match string.rfind('\n') {
+	    Some(newline) if is_blank(&string[newline + 1..]) => newline,
+	    _ => string.len(),
+	};
+	// With a single blank line, both trims remove its newline.
+	Token::String(string.get(start..end).unwrap_or("").to_string())
+    }
+
+    // Consumes the rest of a malformed string so lexing resumes after it.
+    fn skip_string(&mut self) {
+	while let Some(ch) = self.advance() {
+	    match ch {
+		'"' => break,
+		'\\' => {
+		    self.advance();
+		}
+		'\n' => self.new_line(),
+		_ => {}
+	    }
+	}
+    }
+
+    fn read_escape(&mut self, string: &mut String) -> Result<(), String> {
+	let ch = match self.advance() {
+	    None => return Err("Unterminated string.".to_string()),
+	    Some(ch) => ch,
+	};
+	match ch {
+	    '"' => string.push('"'),
+	    '\\' => string.push('\\'),
+	    '%' => string.push('%'),
+	    '0' => string.push('\0'),
+	    'a' => string.push('\x07'),
+	    'b' => string.push('\x08'),
+	    'e' => string.push('\x1b'),
+	    'f' => string.push('\x0c'),
+	    'n' => string.push('\n'),
+	    'r' => string.push('\r'),
+	    't' => string.push('\t'),
+	    'v' => string.push('\x0b'),
+	    'u' => {
+		let code = self.read_hex_escape(4, "Unicode")?;
+		string.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+	    }
+	    'U' => {
+		let code = self.read_hex_escape(8, "Unicode")?;
+		string.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+	    }
+	    'x' => {
+		// A raw byte. Strings are UTF-8 here, so bytes above 0x7f
+		// are mapped to the code point with the same value.
+		let byte = self.read_hex_escape(2, "byte")?;
+		string.push(char::from_u32(byte).unwrap_or('\u{fffd}'));
+	    }
+	    // As in wren_c, the rest of the string is read as usual.
+	    ch => self.error(&format!("Invalid escape character '{}'.", ch)),
+	}
+	Ok(())
+    }
+
+    fn read_hex_escape(&mut self, digits: usize, description: &str) -> Result<u32, String> {
+	let mut value = 0;
+	for _ in 0..digits {
+	    match self.peek().and_then(|ch| ch.to_digit(16)) {
+		Some(digit) => {
+		    self.advance();
+		    value = value * 16 + digit;
+		}
+		None => return Err(format!("Incomplete {} escape sequence.", description)),
+	    }
+	}
+	Ok(value)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Lexeme;
+
+    fn next(&mut self) -> Option<Lexeme> {
+	if self.done {
+	    return None;
+	}
+	let lexeme = self.next_token();
+	if lexeme.token == Token::Eof {
+	    self.done = true;
+	}
+	Some(lexeme)
+    }
+}
+
+// wren_c's error for a literal out of range, naming the size of the C
+// `long` it parses hexadecimal literals into.
+fn number_too_large() -> Token {
+    Token::Error("Number literal was too large (8).".to_string())
+}
+
+fn invalid_byte(byte: u8) -> Token {
+    Token::Error(format!("Invalid byte 0x{:x}.", byte))
+}
+
+fn is_name_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || ch == '_'
+}
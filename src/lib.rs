@@ -0,0 +1,80 @@
+// Only `alloc` is needed without the `std` feature, so the VM can run where
+// there is no operating system.
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod api;
+pub mod ast;
+pub mod bind;
+pub mod bytecode;
+pub mod chunk;
+pub mod compiler;
+pub mod config;
+mod core;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod debug;
+pub mod diagnostic;
+#[cfg(feature = "threaded-dispatch")]
+mod dispatch;
+pub mod error;
+mod float;
+pub mod formatter;
+pub mod handle;
+pub mod highlight;
+pub mod heap;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod loader;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+mod optional;
+pub mod parser;
+#[cfg(feature = "superinstructions")]
+mod peephole;
+#[cfg(feature = "std")]
+pub mod profiler;
+#[cfg(any(feature = "dap", feature = "lsp"))]
+mod protocol;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod snapshot;
+pub mod value;
+pub mod visit;
+pub mod vm;
+
+pub use crate::api::WrenType;
+pub use crate::bind::{ForeignClass, ForeignFn, FromSlot, IntoSlot};
+pub use crate::compiler::{CompileOptions, Signature, SignatureError, SignatureKind};
+pub use crate::config::{ClockFn, ErrorFn, GcFn, InterruptFn, WrenConfiguration, WriteFn};
+#[cfg(feature = "dap")]
+pub use crate::dap::DapServer;
+pub use crate::debug::{DebugAction, DebugHook, DebugVariable, PauseReason};
+pub use crate::diagnostic::{check, parse, Diagnostic, Severity};
+pub use crate::error::{StackFrame, WrenError};
+pub use crate::formatter::{format, FormatOptions};
+pub use crate::handle::WrenHandle;
+pub use crate::heap::{GcEvent, GcStats, ObjectCounts};
+pub use crate::highlight::{tokenize_for_highlighting, TokenClass};
+#[cfg(feature = "json")]
+pub use crate::json::{JsonError, JsonEvent, JsonOptions, JsonParser};
+pub use crate::lint::{Lint, LintKind};
+#[cfg(feature = "std")]
+pub use crate::loader::FileModuleLoader;
+pub use crate::loader::ModuleLoader;
+#[cfg(feature = "lsp")]
+pub use crate::lsp::LspServer;
+#[cfg(feature = "std")]
+pub use crate::profiler::{FunctionProfile, ProfileReport, Profiler};
+pub use crate::visit::{Fold, Visitor};
+pub use crate::vm::{
+    FinalizerFn, ForeignClassMethods, ForeignMethodFn, HostData, MaybeSend, MaybeSendSync, WrenVM,
+};
+#[cfg(feature = "macros")]
+pub use wren_rs_macros::{wren_class, wren_method};
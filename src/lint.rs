@@ -0,0 +1,571 @@
+// A lint pass over the syntax tree, finding code that compiles but is
+// probably a mistake.
+//
+// It works from the source alone, without running it, so it only checks
+// calls whose receiver's class is known: literals, classes named directly,
+// whether core classes or ones the module declares, and the objects their
+// constructors make.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use hashbrown::HashMap;
+
+use crate::ast::*;
+use crate::compiler::{self, call_signature, signature_name, Signature, SignatureKind};
+use crate::error::WrenError;
+use crate::lexer::Span;
+use crate::parser::{self, is_local_name};
+use crate::value::ObjRef;
+use crate::vm::{closest_signature, WrenVM};
+
+/// The kinds of mistake `WrenVM::lint` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    /// A local variable that's declared but never read.
+    UnusedVariable,
+    /// Code after a `return`, `break` or `continue` that always runs.
+    UnreachableCode,
+    /// A variable with the same name as one in an enclosing scope, which
+    /// it hides.
+    ShadowedVariable,
+    /// A call of a method the receiver's class doesn't have under any
+    /// number of arguments.
+    UnknownMethod,
+    /// A call of a method the receiver's class has, but with a different
+    /// number of arguments.
+    WrongArity,
+}
+
+/// A likely mistake found by `WrenVM::lint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "[line {}] Warning: {}", self.span.line, self.message)
+    }
+}
+
+impl WrenVM {
+    /// Checks `source`, as the module named `module`, for likely mistakes,
+    /// without compiling or running it. Each is reported to the configured
+    /// `error_fn` as a `WrenError::Warning`, and they are returned in the
+    /// order they appear in the source. Source that doesn't parse is a
+    /// compile error.
+    pub fn lint(&mut self, module: &str, source: &str) -> Result<Vec<Lint>, WrenError> {
+	let ast = match parser::parse(source) {
+	    Ok(ast) => ast,
+	    Err(error) => {
+		let error = WrenError::Compile {
+		    module: module.to_string(),
+		    error: error.into(),
+		};
+		self.report(&error);
+		return Err(error);
+	    }
+	};
+	let mut lints = {
+	    let mut linter = Linter::new(self, &ast);
+	    linter.statements(&ast.statements);
+	    linter.lints
+	};
+	lints.sort_by_key(|lint| lint.span.start);
+	for lint in &lints {
+	    self.report(&WrenError::Warning {
+		module: module.to_string(),
+		warning: lint.clone(),
+	    });
+	}
+	Ok(lints)
+    }
+}
+
+struct Linter<'a> {
+    vm: &'a WrenVM,
+    lints: Vec<Lint>,
+    /// The top-level variables the module declares, by name, with where.
+    module_variables: HashMap<&'a str, Span>,
+    /// The classes the module declares at the top level, by name.
+    classes: HashMap<&'a str, &'a ClassDecl>,
+    /// The scopes of the local variables in scope, innermost last.
+    scopes: Vec<Vec<Local<'a>>>,
+    /// How many class definitions the code being checked is in.
+    class_depth: usize,
+}
+
+struct Local<'a> {
+    name: &'a str,
+    span: Span,
+    is_read: bool,
+    /// Whether to warn if it's never read. Parameters needn't be.
+    must_be_read: bool,
+}
+
+// The class a call's receiver is known to be an instance of.
+enum Receiver<'a> {
+    Core(ObjRef),
+    // The metaclass of a class the module declares.
+    Declared(&'a ClassDecl),
+    // A class the module declares.
+    Instance(&'a ClassDecl),
+}
+
+impl<'a> Linter<'a> {
+    fn new(vm: &'a WrenVM, module: &'a Module) -> Linter<'a> {
+	let mut module_variables = HashMap::new();
+	let mut classes = HashMap::new();
+	for stmt in &module.statements {
+	    match &stmt.kind {
+		StmtKind::Var { name, .. } => {
+		    module_variables.insert(name.name.as_str(), name.span);
+		}
+		StmtKind::Class(class) => {
+		    module_variables.insert(class.name.name.as_str(), class.name.span);
+		    classes.insert(class.name.name.as_str(), &**class);
+		}
+		StmtKind::Import { variables, .. } => {
+		    for variable in variables {
+			let name = variable.alias.as_ref().unwrap_or(&variable.name);
+			module_variables.insert(name.name.as_str(), name.span);
+		    }
+		}
+		_ => {}
+	    }
+	}
+	Linter {
+	    vm,
+	    lints: Vec::new(),
+	    module_variables,
+	    classes,
+	    scopes: Vec::new(),
+	    class_depth: 0,
+	}
+    }
+
+    fn warn(&mut self, kind: LintKind, span: Span, message: String) {
+	self.lints.push(Lint { kind, message, span });
+    }
+
+    // Scopes.
+
+    fn push_scope(&mut self) {
+	self.scopes.push(Vec::new());
+    }
+
+    fn pop_scope(&mut self) {
+	let scope = self.scopes.pop().expect("a scope to pop");
+	for local in scope.into_iter().filter(|local| local.must_be_read && !local.is_read) {
+	    let message = format!("Local variable '{}' is never read.", local.name);
+	    self.warn(LintKind::UnusedVariable, local.span, message);
+	}
+    }
+
+    // Declares a local variable. At the top level of the module, variables
+    // are module variables instead, which other modules may import.
+    fn declare(&mut self, name: &'a Ident, must_be_read: bool) {
+	let Some((scope, outer)) = self.scopes.split_last() else {
+	    return;
+	};
+	// Declaring a name twice in one scope is a compile error.
+	if !scope.iter().any(|local| local.name == name.name) {
+	    let outer = outer.iter().rev().flatten().find(|local| local.name == name.name);
+	    // Methods can only see module variables with capitalized names.
+	    let module_variable = self
+		.module_variables
+		.get(name.name.as_str())
+		.filter(|_| self.class_depth == 0 || !is_local_name(&name.name));
+	    if let Some(span) = outer.map(|local| local.span).or(module_variable.copied()) {
+		let message = format!(
+		    "Variable '{}' shadows the one declared on line {}.",
+		    name.name, span.line
+		);
+		self.warn(LintKind::ShadowedVariable, name.span, message);
+	    }
+	}
+	self.scopes.last_mut().expect("checked above").push(Local {
+	    name: &name.name,
+	    span: name.span,
+	    is_read: false,
+	    must_be_read,
+	});
+    }
+
+    fn find_local(&mut self, name: &str) -> Option<&mut Local<'a>> {
+	self.scopes.iter_mut().rev().flatten().find(|local| local.name == name)
+    }
+
+    // Statements.
+
+    fn statements(&mut self, statements: &'a [Stmt]) {
+	for stmt in statements {
+	    self.statement(stmt);
+	}
+	if let Some(index) = statements.iter().position(always_exits) {
+	    if let Some(next) = statements.get(index + 1) {
+		self.warn(LintKind::UnreachableCode, next.span, "Unreachable code.".to_string());
+	    }
+	}
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) {
+	match &stmt.kind {
+	    StmtKind::Expr(expr) => self.expr(expr),
+	    StmtKind::Var { name, initializer, .. } => {
+		// A variable isn't in scope in its own initializer.
+		if let Some(initializer) = initializer {
+		    self.expr(initializer);
+		}
+		self.declare(name, true);
+	    }
+	    StmtKind::Class(class) => {
+		if let Some(superclass) = &class.superclass {
+		    self.expr(superclass);
+		}
+		self.class_depth += 1;
+		for method in &class.methods {
+		    self.push_scope();
+		    for param in &method.params {
+			self.declare(param, false);
+		    }
+		    if let Some(body) = &method.body {
+			self.body(body);
+		    }
+		    self.pop_scope();
+		}
+		self.class_depth -= 1;
+	    }
+	    StmtKind::Import { variables, .. } => {
+		for variable in variables {
+		    self.declare(variable.alias.as_ref().unwrap_or(&variable.name), true);
+		}
+	    }
+	    StmtKind::Block(statements) => {
+		self.push_scope();
+		self.statements(statements);
+		self.pop_scope();
+	    }
+	    StmtKind::If {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.expr(condition);
+		self.statement(then_branch);
+		if let Some(else_branch) = else_branch {
+		    self.statement(else_branch);
+		}
+	    }
+	    StmtKind::While { condition, body } => {
+		self.expr(condition);
+		self.statement(body);
+	    }
+	    StmtKind::For {
+		variable,
+		sequence,
+		body,
+	    } => {
+		self.expr(sequence);
+		self.push_scope();
+		// Loops often only count.
+		self.declare(variable, false);
+		self.statement(body);
+		self.pop_scope();
+	    }
+	    StmtKind::Break | StmtKind::Continue | StmtKind::Return(None) => {}
+	    StmtKind::Return(Some(value)) => self.expr(value),
+	}
+    }
+
+    // The body of a method or block argument, in the scope of its
+    // parameters.
+    fn body(&mut self, body: &'a Body) {
+	match body {
+	    Body::Expr(expr) => self.expr(expr),
+	    Body::Block(statements) => self.statements(statements),
+	}
+    }
+
+    // Expressions.
+
+    fn expr(&mut self, expr: &'a Expr) {
+	match &expr.kind {
+	    ExprKind::Null
+	    | ExprKind::Bool(_)
+	    | ExprKind::Num(_)
+	    | ExprKind::String(_)
+	    | ExprKind::Field(_)
+	    | ExprKind::StaticField(_)
+	    | ExprKind::This => {}
+	    ExprKind::Interpolation(parts) | ExprKind::List(parts) => {
+		for part in parts {
+		    self.expr(part);
+		}
+	    }
+	    ExprKind::Map(entries) => {
+		for (key, value) in entries {
+		    self.expr(key);
+		    self.expr(value);
+		}
+	    }
+	    ExprKind::Name(name) => {
+		if let Some(local) = self.find_local(name) {
+		    local.is_read = true;
+		}
+	    }
+	    ExprKind::Call {
+		receiver,
+		name,
+		args,
+		block,
+	    } => {
+		if let Some(receiver) = receiver {
+		    self.expr(receiver);
+		}
+		self.arguments(args, block);
+		if let Some(receiver) = receiver {
+		    let signature = call_signature(name, args, block);
+		    self.check_call(receiver, &signature, name.span);
+		}
+	    }
+	    ExprKind::Super { args, block, .. } => self.arguments(args, block),
+	    ExprKind::Subscript { receiver, args } => {
+		self.expr(receiver);
+		for arg in args {
+		    self.expr(arg);
+		}
+	    }
+	    ExprKind::Unary { op, operand } => {
+		self.expr(operand);
+		let signature = Signature::new(op.method_name(), SignatureKind::Getter, 0);
+		self.check_call(operand, &signature, expr.span);
+	    }
+	    ExprKind::Binary { op, left, right } => {
+		self.expr(left);
+		self.expr(right);
+		let signature = Signature::new(op.method_name(), SignatureKind::Method, 1);
+		self.check_call(left, &signature, expr.span);
+	    }
+	    ExprKind::And(left, right) | ExprKind::Or(left, right) => {
+		self.expr(left);
+		self.expr(right);
+	    }
+	    ExprKind::Conditional {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.expr(condition);
+		self.expr(then_branch);
+		self.expr(else_branch);
+	    }
+	    ExprKind::Assign { target, value } => {
+		// Assigning a variable doesn't read it, and assigning a
+		// getter calls its setter.
+		match &target.kind {
+		    ExprKind::Name(_) => {}
+		    ExprKind::Call {
+			receiver: Some(receiver),
+			name,
+			..
+		    } => {
+			self.expr(receiver);
+			let signature = Signature::new(&name.name, SignatureKind::Setter, 1);
+			self.check_call(receiver, &signature, name.span);
+		    }
+		    _ => self.expr(target),
+		}
+		self.expr(value);
+	    }
+	}
+    }
+
+    fn arguments(&mut self, args: &'a Option<Vec<Expr>>, block: &'a Option<Box<BlockArg>>) {
+	for arg in args.iter().flatten() {
+	    self.expr(arg);
+	}
+	if let Some(block) = block {
+	    self.push_scope();
+	    for param in &block.params {
+		self.declare(param, false);
+	    }
+	    self.body(&block.body);
+	    self.pop_scope();
+	}
+    }
+
+    // Methods.
+
+    // Warns if `receiver` is known not to have a method with `signature`.
+    fn check_call(&mut self, receiver: &Expr, signature: &Signature, span: Span) {
+	let (class, signatures) = match self.receiver_class(receiver) {
+	    Some(Receiver::Core(class)) => {
+		(self.vm.heap.class(class).name.clone(), self.vm.class_signatures(class))
+	    }
+	    Some(Receiver::Declared(class)) => {
+		// A class has its static methods and constructors, and the
+		// methods of every class.
+		let mut signatures = self.vm.class_signatures(self.vm.core.class);
+		for method in class.methods.iter().filter(|method| method.is_static) {
+		    signatures.push(compiler::method_signature(method).to_string());
+		}
+		for method in &class.methods {
+		    if method.kind == MethodKind::Constructor {
+			let arity = method.params.len();
+			let constructor =
+			    Signature::new(&method.name.name, SignatureKind::Method, arity);
+			signatures.push(constructor.to_string());
+		    }
+		}
+		(format!("{} metaclass", class.name.name), signatures)
+	    }
+	    Some(Receiver::Instance(class)) => match self.instance_signatures(class) {
+		Some(signatures) => (class.name.name.clone(), signatures),
+		None => return,
+	    },
+	    None => return,
+	};
+	let signature = signature.to_string();
+	if signatures.contains(&signature) {
+	    return;
+	}
+	let name = signature_name(&signature);
+	let similar: Vec<String> = signatures
+	    .iter()
+	    .filter(|other| signature_name(other) == name)
+	    .map(|other| format!("'{}'", other))
+	    .collect();
+	match similar.split_last() {
+	    None => {
+		let mut message = format!("{} does not implement '{}'.", class, signature);
+		let candidates = signatures.iter().map(String::as_str);
+		if let Some(similar) = closest_signature(&signature, candidates) {
+		    message.push_str(&format!(" Did you mean '{}'?", similar));
+		}
+		self.warn(LintKind::UnknownMethod, span, message);
+	    }
+	    Some((last, [])) => {
+		let message =
+		    format!("{} does not implement '{}', only {}.", class, signature, last);
+		self.warn(LintKind::WrongArity, span, message);
+	    }
+	    Some((last, rest)) => {
+		let message = format!(
+		    "{} does not implement '{}', only {} and {}.",
+		    class,
+		    signature,
+		    rest.join(", "),
+		    last
+		);
+		self.warn(LintKind::WrongArity, span, message);
+	    }
+	}
+    }
+
+    // The signatures of the methods instances of `class` have, including
+    // inherited ones, unless it inherits from a class that isn't known.
+    fn instance_signatures(&self, class: &'a ClassDecl) -> Option<Vec<String>> {
+	let mut signatures = Vec::new();
+	let mut class = class;
+	// Classes inheriting from each other in a cycle fail when they run.
+	for _ in 0..=self.classes.len() {
+	    for method in &class.methods {
+		if !method.is_static && method.kind != MethodKind::Constructor {
+		    signatures.push(compiler::method_signature(method).to_string());
+		}
+	    }
+	    let superclass = match class.superclass.as_ref().map(|superclass| &superclass.kind) {
+		None => self.vm.core.object,
+		Some(ExprKind::Name(name)) => match self.classes.get(name.as_str()) {
+		    Some(&superclass) => {
+			class = superclass;
+			continue;
+		    }
+		    None if self.module_variables.contains_key(name.as_str()) => return None,
+		    None => self.vm.core_class(name)?,
+		},
+		Some(_) => return None,
+	    };
+	    signatures.extend(self.vm.class_signatures(superclass));
+	    return Some(signatures);
+	}
+	None
+    }
+
+    // The class of `receiver`, if it's known without running the code: a
+    // literal's, the metaclass of a class named directly, or the class of
+    // an object one of its constructors makes.
+    fn receiver_class(&self, receiver: &Expr) -> Option<Receiver<'a>> {
+	let core = &self.vm.core;
+	let class = match &receiver.kind {
+	    ExprKind::Null => core.null,
+	    ExprKind::Bool(_) => core.bool,
+	    ExprKind::Num(_) => core.num,
+	    ExprKind::String(_) | ExprKind::Interpolation(_) => core.string,
+	    ExprKind::List(_) => core.list,
+	    ExprKind::Map(_) => core.map,
+	    ExprKind::Binary {
+		op: BinaryOp::RangeInclusive | BinaryOp::RangeExclusive,
+		..
+	    } => core.range,
+	    ExprKind::Name(name) => {
+		let is_local = self.scopes.iter().flatten().any(|local| local.name == name);
+		// In a method, other lowercase names are getters on `this`.
+		if is_local || (self.class_depth > 0 && is_local_name(name)) {
+		    return None;
+		}
+		if self.module_variables.contains_key(name.as_str()) {
+		    return self.classes.get(name.as_str()).map(|&class| Receiver::Declared(class));
+		}
+		let class = self.vm.core_class(name)?;
+		self.vm.heap.class(class).class?
+	    }
+	    ExprKind::Call {
+		receiver: Some(class),
+		name,
+		args,
+		block,
+	    } => {
+		let signature = call_signature(name, args, block);
+		match (self.receiver_class(class)?, &class.kind) {
+		    (Receiver::Declared(class), _) => {
+			let is_constructor = class.methods.iter().any(|method| {
+			    method.kind == MethodKind::Constructor
+				&& method.name.name == name.name
+				&& signature.kind == SignatureKind::Method
+				&& method.params.len() == signature.arity
+			});
+			return is_constructor.then_some(Receiver::Instance(class));
+		    }
+		    // Core classes are made with `new`.
+		    (Receiver::Core(_), ExprKind::Name(class)) if name.name == "new" => {
+			self.vm.core_class(class)?
+		    }
+		    _ => return None,
+		}
+	    }
+	    _ => return None,
+	};
+	Some(Receiver::Core(class))
+    }
+}
+
+// Whether running `stmt` never goes on to the statement after it.
+fn always_exits(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+	StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue => true,
+	StmtKind::Block(statements) => statements.iter().any(always_exits),
+	StmtKind::If {
+	    then_branch,
+	    else_branch: Some(else_branch),
+	    ..
+	} => always_exits(then_branch) && always_exits(else_branch),
+	_ => false,
+    }
+}
@@ -0,0 +1,87 @@
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::iter;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::{Component, Path, PathBuf};
+
+use crate::vm::MaybeSend;
+
+/// Finds the source of the modules a program imports.
+pub trait ModuleLoader: MaybeSend {
+    /// Turns the string in an `import` written in the module `importer`
+    /// into the name the module is registered under, so two imports of the
+    /// same module by different paths share it. Returns `None` if `name`
+    /// can't be resolved. By default names are used unchanged.
+    fn resolve_module(&mut self, importer: &str, name: &str) -> Option<String> {
+	let _ = importer;
+	Some(name.to_string())
+    }
+
+    /// Returns the source of the module called `name`, or `None` if there
+    /// is no such module.
+    fn load_module(&mut self, name: &str) -> Option<String>;
+}
+
+/// Loads modules from `.wren` files below a root directory.
+///
+/// Imports starting with `./` or `../` are relative to the importing
+/// module, and any others to the root, so `import "./util"` in the module
+/// `lib/a` loads `lib/util.wren`. A module that isn't below the root is
+/// looked for below each of the search paths in turn.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct FileModuleLoader {
+    root: PathBuf,
+    search_paths: Vec<PathBuf>,
+}
+
+#[cfg(feature = "std")]
+impl FileModuleLoader {
+    pub fn new(root: impl Into<PathBuf>) -> FileModuleLoader {
+	FileModuleLoader {
+	    root: root.into(),
+	    search_paths: Vec::new(),
+	}
+    }
+
+    /// Adds a directory to look for modules in after the root and any
+    /// search paths added before it.
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+	self.search_paths.push(path.into());
+    }
+}
+
+#[cfg(feature = "std")]
+impl ModuleLoader for FileModuleLoader {
+    fn resolve_module(&mut self, importer: &str, name: &str) -> Option<String> {
+	if !name.starts_with("./") && !name.starts_with("../") {
+	    return Some(name.to_string());
+	}
+	let directory = Path::new(importer).parent().unwrap_or_else(|| Path::new(""));
+	// Normalize the joined path, refusing to climb above the root.
+	let path = directory.join(name);
+	let mut components = Vec::new();
+	for component in path.components() {
+	    match component {
+		Component::Normal(part) => components.push(part.to_str()?),
+		Component::ParentDir => {
+		    components.pop()?;
+		}
+		Component::CurDir => {}
+		Component::RootDir | Component::Prefix(_) => return None,
+	    }
+	}
+	Some(components.join("/"))
+    }
+
+    fn load_module(&mut self, name: &str) -> Option<String> {
+	let file = format!("{}.wren", name);
+	iter::once(&self.root)
+	    .chain(&self.search_paths)
+	    .find_map(|directory| fs::read_to_string(directory.join(&file)).ok())
+    }
+}
@@ -0,0 +1,885 @@
+// A server for the Language Server Protocol, through which editors check
+// code, jump to definitions and complete names as it is written.
+//
+// Messages are framed as they are for the Debug Adapter Protocol, and the
+// client's requests are answered in order as they arrive. Documents are
+// kept as full text. Diagnostics come from compiling and linting a
+// document when it is opened or saved, and highlighting from its tokens;
+// everything else works from its syntax tree.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+
+use serde_json::{json, Value};
+
+use crate::ast::*;
+use crate::compiler::{self, call_signature, signature_name, Signature, SignatureKind};
+use crate::config::WrenConfiguration;
+use crate::diagnostic::{self, Diagnostic, Severity};
+use crate::highlight::{tokenize_for_highlighting, TokenClass};
+use crate::lexer::Span;
+use crate::parser::{self, is_local_name};
+use crate::protocol::{read_message, write_message};
+use crate::vm::WrenVM;
+
+// JSON-RPC error codes.
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enumerations.
+const SEVERITY_ERROR: u64 = 1;
+const SEVERITY_WARNING: u64 = 2;
+const SYMBOL_CLASS: u64 = 5;
+const SYMBOL_METHOD: u64 = 6;
+const SYMBOL_PROPERTY: u64 = 7;
+const SYMBOL_CONSTRUCTOR: u64 = 9;
+const SYMBOL_VARIABLE: u64 = 13;
+const COMPLETION_METHOD: u64 = 2;
+const COMPLETION_VARIABLE: u64 = 6;
+const COMPLETION_CLASS: u64 = 7;
+
+// The semantic token types and modifiers the server uses, which tokens
+// refer to by index.
+const TOKEN_TYPES: [&str; 9] = [
+    "comment",
+    "keyword",
+    "number",
+    "string",
+    "operator",
+    "property",
+    "decorator",
+    "method",
+    "variable",
+];
+const TOKEN_MODIFIERS: [&str; 1] = ["declaration"];
+
+/// Serves one client until it exits.
+///
+/// Each document is checked on its own: imports aren't followed, so names
+/// it imports are known only by where they are imported.
+pub struct LspServer {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// Looks up the core classes for completion.
+    vm: WrenVM,
+    documents: HashMap<String, Document>,
+    is_shut_down: bool,
+}
+
+struct Document {
+    text: String,
+    /// The syntax tree of the last version of the text that parsed.
+    ast: Option<Module>,
+    /// Whether `ast` is of the current text.
+    is_current: bool,
+}
+
+impl Document {
+    fn new(text: String) -> Document {
+	let mut document = Document {
+	    text: String::new(),
+	    ast: None,
+	    is_current: false,
+	};
+	document.update(text);
+	document
+    }
+
+    fn update(&mut self, text: String) {
+	match parser::parse(&text) {
+	    Ok(ast) => {
+		self.ast = Some(ast);
+		self.is_current = true;
+	    }
+	    Err(_) => self.is_current = false,
+	}
+	self.text = text;
+    }
+
+    // The syntax tree, if it matches the text, so its spans can be used.
+    fn current_ast(&self) -> Option<&Module> {
+	self.ast.as_ref().filter(|_| self.is_current)
+    }
+}
+
+impl LspServer {
+    pub fn new(input: impl Read + 'static, output: impl Write + 'static) -> LspServer {
+	LspServer {
+	    input: Box::new(BufReader::new(input)),
+	    output: Box::new(output),
+	    vm: WrenVM::with_configuration(quiet_configuration()),
+	    documents: HashMap::new(),
+	    is_shut_down: false,
+	}
+    }
+
+    /// Answers the client until it sends `exit` or the input ends. Returns
+    /// whether it asked the server to shut down first, as it should.
+    pub fn run(mut self) -> bool {
+	while let Ok(Some(message)) = read_message(&mut self.input) {
+	    if message["method"] == "exit" {
+		return self.is_shut_down;
+	    }
+	    self.handle(&message);
+	}
+	false
+    }
+
+    fn handle(&mut self, message: &Value) {
+	let method = message["method"].as_str().unwrap_or("");
+	let params = &message["params"];
+	let Some(id) = message.get("id") else {
+	    self.notification(method, params);
+	    return;
+	};
+	let result = if self.is_shut_down {
+	    Err((INVALID_REQUEST, "The server has shut down.".to_string()))
+	} else {
+	    self.request(method, params)
+	};
+	let mut response = json!({ "jsonrpc": "2.0", "id": id });
+	match result {
+	    Ok(result) => response["result"] = result,
+	    Err((code, message)) => {
+		response["error"] = json!({ "code": code, "message": message });
+	    }
+	}
+	self.send(&response);
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+	match method {
+	    "initialize" => Ok(json!({
+		"capabilities": {
+		    "textDocumentSync": {
+			"openClose": true,
+			"change": 1,
+			"save": { "includeText": true },
+		    },
+		    "definitionProvider": true,
+		    "documentSymbolProvider": true,
+		    "completionProvider": { "triggerCharacters": ["."] },
+		    "semanticTokensProvider": {
+			"legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": TOKEN_MODIFIERS },
+			"full": true,
+		    },
+		},
+		"serverInfo": { "name": "wren", "version": env!("CARGO_PKG_VERSION") },
+	    })),
+	    "shutdown" => {
+		self.is_shut_down = true;
+		Ok(Value::Null)
+	    }
+	    "textDocument/definition" => {
+		let (uri, document, offset) = self.position(params)?;
+		let Some(ast) = document.current_ast() else {
+		    return Ok(json!([]));
+		};
+		let locations: Vec<Value> = definitions(ast, offset)
+		    .into_iter()
+		    .map(|span| json!({ "uri": uri, "range": range(&document.text, span) }))
+		    .collect();
+		Ok(Value::Array(locations))
+	    }
+	    "textDocument/documentSymbol" => {
+		let document = self.document(params)?;
+		let symbols = match document.current_ast() {
+		    Some(ast) => document_symbols(&document.text, ast),
+		    None => Vec::new(),
+		};
+		Ok(Value::Array(symbols))
+	    }
+	    "textDocument/semanticTokens/full" => {
+		let document = self.document(params)?;
+		Ok(json!({ "data": semantic_tokens(&document.text) }))
+	    }
+	    "textDocument/completion" => {
+		let (_, document, offset) = self.position(params)?;
+		Ok(Value::Array(completions(&self.vm, document, offset)))
+	    }
+	    _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'.", method))),
+	}
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+	let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+	match method {
+	    "textDocument/didOpen" => {
+		let text = params["textDocument"]["text"].as_str().unwrap_or("");
+		self.documents.insert(uri.clone(), Document::new(text.to_string()));
+		self.publish_diagnostics(&uri);
+	    }
+	    "textDocument/didChange" => {
+		// The whole text is sent, so only the last change matters.
+		let changes = params["contentChanges"].as_array();
+		let text = changes.and_then(|changes| changes.last()).map(|change| &change["text"]);
+		if let (Some(document), Some(text)) =
+		    (self.documents.get_mut(&uri), text.and_then(Value::as_str))
+		{
+		    document.update(text.to_string());
+		}
+	    }
+	    "textDocument/didSave" => {
+		if let (Some(document), Some(text)) =
+		    (self.documents.get_mut(&uri), params["text"].as_str())
+		{
+		    document.update(text.to_string());
+		}
+		self.publish_diagnostics(&uri);
+	    }
+	    "textDocument/didClose" => {
+		self.documents.remove(&uri);
+		let params = json!({ "uri": uri, "diagnostics": [] });
+		self.notify("textDocument/publishDiagnostics", params);
+	    }
+	    _ => {}
+	}
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) {
+	let Some(document) = self.documents.get(uri) else {
+	    return;
+	};
+	let params = json!({ "uri": uri, "diagnostics": diagnostics(&document.text) });
+	self.notify("textDocument/publishDiagnostics", params);
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, (i64, String)> {
+	let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+	self.documents
+	    .get(uri)
+	    .ok_or_else(|| (INVALID_PARAMS, format!("Unknown document '{}'.", uri)))
+    }
+
+    // The document and offset in it of a request's `position`.
+    fn position<'a>(
+	&'a self,
+	params: &'a Value,
+    ) -> Result<(&'a Value, &'a Document, usize), (i64, String)> {
+	let document = self.document(params)?;
+	let position = &params["position"];
+	let line = position["line"].as_u64().unwrap_or(0) as usize;
+	let character = position["character"].as_u64().unwrap_or(0) as usize;
+	let offset = offset(&document.text, line, character);
+	Ok((&params["textDocument"]["uri"], document, offset))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+	self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn send(&mut self, message: &Value) {
+	// A client that has gone away will also close the input, which ends
+	// the server.
+	let _ = write_message(&mut self.output, message);
+    }
+}
+
+// A configuration whose VM reports nothing, since errors are sent to the
+// client instead.
+fn quiet_configuration() -> WrenConfiguration {
+    WrenConfiguration {
+	error_fn: Some(|_, _| {}),
+	..WrenConfiguration::default()
+    }
+}
+
+// Positions.
+
+// The offset of a zero-based line and UTF-16 column, clamped to the text.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let start = match line {
+	0 => 0,
+	_ => match text.match_indices('\n').nth(line - 1) {
+	    Some((index, _)) => index + 1,
+	    None => return text.len(),
+	},
+    };
+    let mut units = 0;
+    for (index, c) in text[start..].char_indices() {
+	if units >= character || c == '\n' {
+	    return start + index;
+	}
+	units += c.len_utf16();
+    }
+    text.len()
+}
+
+// The zero-based line and UTF-16 column of `offset`.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+// Diagnostics.
+
+// Compiles and lints `text` in a VM of its own, so its module variables
+// don't clash with an earlier version's.
+fn diagnostics(text: &str) -> Vec<Value> {
+    let mut found = diagnostic::check(text).err().unwrap_or_default();
+    let mut vm = WrenVM::with_configuration(quiet_configuration());
+    found.extend(vm.lint("main", text).unwrap_or_default().into_iter().map(Diagnostic::from));
+    found.iter().map(|found| diagnostic(text, found)).collect()
+}
+
+fn diagnostic(text: &str, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+	Severity::Error => SEVERITY_ERROR,
+	Severity::Warning => SEVERITY_WARNING,
+    };
+    json!({
+	"range": range(text, diagnostic.span),
+	"severity": severity,
+	"source": "wren",
+	"message": diagnostic.message,
+    })
+}
+
+// Highlighting.
+
+// The tokens of `text` in the protocol's encoding: five numbers each, for
+// the line and start relative to the token before, the length, the type
+// and the modifiers. Tokens spanning lines are split, one per line.
+fn semantic_tokens(text: &str) -> Vec<u64> {
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for (span, class) in tokenize_for_highlighting(text) {
+	let (token_type, modifiers) = match class {
+	    TokenClass::Comment => (0, 0),
+	    TokenClass::Keyword => (1, 0),
+	    TokenClass::Number => (2, 0),
+	    TokenClass::String => (3, 0),
+	    TokenClass::Interpolation | TokenClass::Operator => (4, 0),
+	    TokenClass::Field => (5, 0),
+	    TokenClass::Attribute => (6, 0),
+	    TokenClass::Method => (7, 1),
+	    TokenClass::MethodCall => (7, 0),
+	    TokenClass::Variable => (8, 0),
+	};
+	let first_line = span.line as u64 - 1;
+	let mut line_start = span.start + 1 - span.column as usize;
+	let mut start = span.start;
+	let pieces = text[span.start..span.end].split('\n');
+	for (line, piece) in (first_line..).zip(pieces) {
+	    let piece = piece.trim_end_matches('\r');
+	    if !piece.is_empty() {
+		let character = utf16_len(&text[line_start..start]);
+		let delta_start = if line == previous_line {
+		    character - previous_start
+		} else {
+		    character
+		};
+		data.extend([line - previous_line, delta_start, utf16_len(piece)]);
+		data.extend([token_type, modifiers]);
+		previous_line = line;
+		previous_start = character;
+	    }
+	    start += text[start..].find('\n').map_or(piece.len(), |newline| newline + 1);
+	    line_start = start;
+	}
+    }
+    data
+}
+
+fn utf16_len(text: &str) -> u64 {
+    text.chars().map(|c| c.len_utf16() as u64).sum()
+}
+
+// Symbols.
+
+fn document_symbols(text: &str, ast: &Module) -> Vec<Value> {
+    let symbol = |name: String, kind, span: Span, selection: Span| {
+	json!({
+	    "name": name,
+	    "kind": kind,
+	    "range": range(text, span),
+	    "selectionRange": range(text, selection),
+	})
+    };
+    let mut symbols = Vec::new();
+    for stmt in &ast.statements {
+	match &stmt.kind {
+	    StmtKind::Var { name, .. } => {
+		symbols.push(symbol(name.name.clone(), SYMBOL_VARIABLE, stmt.span, name.span));
+	    }
+	    StmtKind::Class(class) => {
+		let methods: Vec<Value> = class
+		    .methods
+		    .iter()
+		    .map(|method| {
+			let kind = match method.kind {
+			    MethodKind::Constructor => SYMBOL_CONSTRUCTOR,
+			    MethodKind::Getter | MethodKind::Setter => SYMBOL_PROPERTY,
+			    _ => SYMBOL_METHOD,
+			};
+			let mut name = compiler::method_signature(method).to_string();
+			if method.is_static {
+			    name = format!("static {}", name);
+			}
+			symbol(name, kind, method.span, method.name.span)
+		    })
+		    .collect();
+		let name = &class.name;
+		let mut class = symbol(name.name.clone(), SYMBOL_CLASS, stmt.span, name.span);
+		class["children"] = Value::Array(methods);
+		symbols.push(class);
+	    }
+	    _ => {}
+	}
+    }
+    symbols
+}
+
+// Definitions.
+
+// Where the name at `offset` is defined in `ast`.
+fn definitions(ast: &Module, offset: usize) -> Vec<Span> {
+    let mut resolver = Resolver::new(ast, offset);
+    resolver.statements(&ast.statements);
+    resolver.found.unwrap_or_default()
+}
+
+// Finds what the name at an offset refers to, tracking the local
+// variables in scope on the way to it as the compiler does.
+struct Resolver<'a> {
+    offset: usize,
+    /// The top-level variables the module declares, by name, with where.
+    module_variables: HashMap<&'a str, Span>,
+    /// The classes the module declares at the top level.
+    classes: Vec<&'a ClassDecl>,
+    /// The scopes of the local variables in scope, innermost last.
+    scopes: Vec<Vec<&'a Ident>>,
+    /// The class whose method the code is in.
+    class: Option<&'a ClassDecl>,
+    /// The definitions of the name at the offset, once it's reached.
+    found: Option<Vec<Span>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(module: &'a Module, offset: usize) -> Resolver<'a> {
+	let mut module_variables = HashMap::new();
+	let mut classes = Vec::new();
+	for stmt in &module.statements {
+	    match &stmt.kind {
+		StmtKind::Var { name, .. } => {
+		    module_variables.insert(name.name.as_str(), name.span);
+		}
+		StmtKind::Class(class) => {
+		    module_variables.insert(class.name.name.as_str(), class.name.span);
+		    classes.push(&**class);
+		}
+		StmtKind::Import { variables, .. } => {
+		    for variable in variables {
+			let name = variable.alias.as_ref().unwrap_or(&variable.name);
+			module_variables.insert(name.name.as_str(), name.span);
+		    }
+		}
+		_ => {}
+	    }
+	}
+	Resolver {
+	    offset,
+	    module_variables,
+	    classes,
+	    scopes: Vec::new(),
+	    class: None,
+	    found: None,
+	}
+    }
+
+    fn contains(&self, span: Span) -> bool {
+	span.start <= self.offset && self.offset <= span.end
+    }
+
+    // Declares a local variable. At the top level of the module, variables
+    // are module variables instead.
+    fn declare(&mut self, name: &'a Ident) {
+	if let Some(scope) = self.scopes.last_mut() {
+	    scope.push(name);
+	}
+    }
+
+    fn statements(&mut self, statements: &'a [Stmt]) {
+	for stmt in statements {
+	    if self.found.is_some() {
+		return;
+	    }
+	    self.statement(stmt);
+	}
+    }
+
+    // Looks for the offset in `stmt`, or only declares what it declares
+    // if the offset isn't in it.
+    fn statement(&mut self, stmt: &'a Stmt) {
+	if !self.contains(stmt.span) {
+	    match &stmt.kind {
+		StmtKind::Var { name, .. } => self.declare(name),
+		StmtKind::Import { variables, .. } => {
+		    for variable in variables {
+			self.declare(variable.alias.as_ref().unwrap_or(&variable.name));
+		    }
+		}
+		_ => {}
+	    }
+	    return;
+	}
+	match &stmt.kind {
+	    StmtKind::Expr(expr) => self.expr(expr),
+	    StmtKind::Var { name, initializer, .. } => {
+		if let Some(initializer) = initializer {
+		    self.expr(initializer);
+		}
+		self.declare(name);
+	    }
+	    StmtKind::Class(class) => {
+		if let Some(superclass) = &class.superclass {
+		    self.expr(superclass);
+		}
+		let enclosing = self.class.replace(class);
+		for method in &class.methods {
+		    if !self.contains(method.span) {
+			continue;
+		    }
+		    self.scopes.push(method.params.iter().collect());
+		    if let Some(body) = &method.body {
+			self.body(body);
+		    }
+		    self.scopes.pop();
+		}
+		self.class = enclosing;
+	    }
+	    StmtKind::Import { .. } | StmtKind::Break | StmtKind::Continue => {}
+	    StmtKind::Block(statements) => {
+		self.scopes.push(Vec::new());
+		self.statements(statements);
+		self.scopes.pop();
+	    }
+	    StmtKind::If {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.expr(condition);
+		self.statement(then_branch);
+		if let Some(else_branch) = else_branch {
+		    self.statement(else_branch);
+		}
+	    }
+	    StmtKind::While { condition, body } => {
+		self.expr(condition);
+		self.statement(body);
+	    }
+	    StmtKind::For {
+		variable,
+		sequence,
+		body,
+	    } => {
+		self.expr(sequence);
+		self.scopes.push(vec![variable]);
+		self.statement(body);
+		self.scopes.pop();
+	    }
+	    StmtKind::Return(value) => {
+		if let Some(value) = value {
+		    self.expr(value);
+		}
+	    }
+	}
+    }
+
+    fn body(&mut self, body: &'a Body) {
+	match body {
+	    Body::Expr(expr) => self.expr(expr),
+	    Body::Block(statements) => self.statements(statements),
+	}
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+	if self.found.is_some() || !self.contains(expr.span) {
+	    return;
+	}
+	match &expr.kind {
+	    ExprKind::Null
+	    | ExprKind::Bool(_)
+	    | ExprKind::Num(_)
+	    | ExprKind::String(_)
+	    | ExprKind::Field(_)
+	    | ExprKind::StaticField(_)
+	    | ExprKind::This => {}
+	    ExprKind::Interpolation(parts) | ExprKind::List(parts) => {
+		for part in parts {
+		    self.expr(part);
+		}
+	    }
+	    ExprKind::Map(entries) => {
+		for (key, value) in entries {
+		    self.expr(key);
+		    self.expr(value);
+		}
+	    }
+	    ExprKind::Name(name) => self.found = Some(self.resolve_name(name)),
+	    ExprKind::Call {
+		receiver,
+		name,
+		args,
+		block,
+	    } => {
+		if self.contains(name.span) {
+		    let signature = call_signature(name, args, block);
+		    self.found = Some(self.resolve_call(receiver.as_deref(), &signature));
+		    return;
+		}
+		if let Some(receiver) = receiver {
+		    self.expr(receiver);
+		}
+		self.arguments(args, block);
+	    }
+	    ExprKind::Super { args, block, .. } => self.arguments(args, block),
+	    ExprKind::Subscript { receiver, args } => {
+		self.expr(receiver);
+		for arg in args {
+		    self.expr(arg);
+		}
+	    }
+	    ExprKind::Unary { operand, .. } => self.expr(operand),
+	    ExprKind::Binary { left, right, .. }
+	    | ExprKind::And(left, right)
+	    | ExprKind::Or(left, right) => {
+		self.expr(left);
+		self.expr(right);
+	    }
+	    ExprKind::Conditional {
+		condition,
+		then_branch,
+		else_branch,
+	    } => {
+		self.expr(condition);
+		self.expr(then_branch);
+		self.expr(else_branch);
+	    }
+	    ExprKind::Assign { target, value } => {
+		// Assigning a getter calls its setter.
+		let setter = match &target.kind {
+		    ExprKind::Call { receiver, name, .. } if self.contains(name.span) => {
+			Some((receiver.as_deref(), name.name.as_str()))
+		    }
+		    ExprKind::Name(name)
+			if self.contains(target.span) && self.is_implicit_getter(name) =>
+		    {
+			Some((None, name.as_str()))
+		    }
+		    _ => None,
+		};
+		if let Some((receiver, name)) = setter {
+		    let signature = Signature::new(name, SignatureKind::Setter, 1);
+		    self.found = Some(self.resolve_call(receiver, &signature));
+		    return;
+		}
+		self.expr(target);
+		self.expr(value);
+	    }
+	}
+    }
+
+    fn arguments(&mut self, args: &'a Option<Vec<Expr>>, block: &'a Option<Box<BlockArg>>) {
+	for arg in args.iter().flatten() {
+	    self.expr(arg);
+	}
+	if let Some(block) = block {
+	    if self.contains(block.span) {
+		self.scopes.push(block.params.iter().collect());
+		self.body(&block.body);
+		self.scopes.pop();
+	    }
+	}
+    }
+
+    // A bare name is a local variable, a getter on `this` if it's
+    // lowercase and in a method, or a module variable.
+    fn resolve_name(&self, name: &str) -> Vec<Span> {
+	if let Some(local) = self.scopes.iter().rev().flatten().find(|local| local.name == name) {
+	    return vec![local.span];
+	}
+	if self.is_implicit_getter(name) {
+	    let getter = Signature::new(name, SignatureKind::Getter, 0);
+	    return self.resolve_call(None, &getter);
+	}
+	self.module_variables.get(name).copied().into_iter().collect()
+    }
+
+    // The methods a call with `signature` may reach. When the receiver's
+    // class isn't known, that's every method with the signature.
+    fn resolve_call(&self, receiver: Option<&Expr>, signature: &Signature) -> Vec<Span> {
+	let signature = signature.to_string();
+	let found = match receiver.map(|receiver| &receiver.kind) {
+	    None | Some(ExprKind::This) => match self.class {
+		Some(class) => methods_named(&[class], &signature, |_| true),
+		None => Vec::new(),
+	    },
+	    Some(ExprKind::Name(name)) if !self.is_local(name) => {
+		match self.classes.iter().find(|class| class.name.name == *name) {
+		    Some(class) => methods_named(&[class], &signature, |method| {
+			method.is_static || method.kind == MethodKind::Constructor
+		    }),
+		    None => Vec::new(),
+		}
+	    }
+	    _ => methods_named(&self.classes, &signature, |method| {
+		!method.is_static && method.kind != MethodKind::Constructor
+	    }),
+	};
+	if !found.is_empty() {
+	    return found;
+	}
+	methods_named(&self.classes, &signature, |_| true)
+    }
+
+    // Whether `name` is a call on `this` in a method rather than a variable.
+    fn is_implicit_getter(&self, name: &str) -> bool {
+	self.class.is_some() && is_local_name(name) && !self.is_local(name)
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+	self.scopes.iter().flatten().any(|local| local.name == name)
+    }
+}
+
+// The names of the methods in `classes` that `filter` accepts and a call
+// with `signature` reaches.
+fn methods_named(
+    classes: &[&ClassDecl],
+    signature: &str,
+    filter: impl Fn(&Method) -> bool,
+) -> Vec<Span> {
+    classes
+	.iter()
+	.flat_map(|class| &class.methods)
+	.filter(|method| filter(method) && called_signature(method).to_string() == signature)
+	.map(|method| method.name.span)
+	.collect()
+}
+
+// The signature of the calls that reach `method`. A constructor is called
+// on its class like any other method.
+fn called_signature(method: &Method) -> Signature {
+    match method.kind {
+	MethodKind::Constructor => {
+	    Signature::new(&method.name.name, SignatureKind::Method, method.params.len())
+	}
+	_ => compiler::method_signature(method),
+    }
+}
+
+// Completion.
+
+// What may be written at `offset`: a method after a `.`, and otherwise a
+// module or core variable. It works from the text before the offset, since
+// code being written rarely parses, and from the last syntax tree that did.
+fn completions(vm: &WrenVM, document: &Document, offset: usize) -> Vec<Value> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+    let before = &document.text[..offset.min(document.text.len())];
+    let before = before.trim_end_matches(is_name_char);
+    let classes: Vec<&ClassDecl> = document
+	.ast
+	.iter()
+	.flat_map(|ast| &ast.statements)
+	.filter_map(|stmt| match &stmt.kind {
+	    StmtKind::Class(class) => Some(&**class),
+	    _ => None,
+	})
+	.collect();
+
+    if let Some(before) = before.strip_suffix('.') {
+	let receiver = &before[before.trim_end_matches(is_name_char).len()..];
+	return member_signatures(vm, &classes, receiver)
+	    .into_iter()
+	    .filter(|signature| {
+		let name = signature_name(signature);
+		name.starts_with(char::is_alphabetic) && !name.ends_with('_')
+	    })
+	    .map(|signature| {
+		let name = signature_name(&signature).to_string();
+		json!({ "label": signature, "kind": COMPLETION_METHOD, "insertText": name })
+	    })
+	    .collect();
+    }
+
+    let mut variables = BTreeSet::new();
+    for stmt in document.ast.iter().flat_map(|ast| &ast.statements) {
+	match &stmt.kind {
+	    StmtKind::Var { name, .. } => {
+		variables.insert((name.name.clone(), COMPLETION_VARIABLE));
+	    }
+	    StmtKind::Class(class) => {
+		variables.insert((class.name.name.clone(), COMPLETION_CLASS));
+	    }
+	    StmtKind::Import { variables: imported, .. } => {
+		for variable in imported {
+		    let name = variable.alias.as_ref().unwrap_or(&variable.name);
+		    variables.insert((name.name.clone(), COMPLETION_VARIABLE));
+		}
+	    }
+	    _ => {}
+	}
+    }
+    let core = vm.heap.module(vm.core_module);
+    for index in 0..core.scope.len() {
+	let name = core.scope.name(index);
+	let kind = match vm.core_class(name) {
+	    Some(_) => COMPLETION_CLASS,
+	    None => COMPLETION_VARIABLE,
+	};
+	variables.insert((name.to_string(), kind));
+    }
+    variables
+	.into_iter()
+	.map(|(name, kind)| json!({ "label": name, "kind": kind }))
+	.collect()
+}
+
+// The signatures of the methods `receiver`, the name before a `.`, may
+// have: a class's static methods and constructors if it names one, and
+// otherwise the instance methods of every class.
+fn member_signatures(vm: &WrenVM, classes: &[&ClassDecl], receiver: &str) -> BTreeSet<String> {
+    if let Some(class) = classes.iter().find(|class| class.name.name == receiver) {
+	let mut signatures: BTreeSet<String> =
+	    vm.class_signatures(vm.core.class).into_iter().collect();
+	for method in &class.methods {
+	    if method.is_static || method.kind == MethodKind::Constructor {
+		signatures.insert(called_signature(method).to_string());
+	    }
+	}
+	return signatures;
+    }
+    if let Some(metaclass) = vm.core_class(receiver).and_then(|class| vm.heap.class(class).class)
+    {
+	return vm.class_signatures(metaclass).into_iter().collect();
+    }
+
+    let mut signatures = BTreeSet::new();
+    for class in classes {
+	for method in &class.methods {
+	    if !method.is_static && method.kind != MethodKind::Constructor {
+		signatures.insert(called_signature(method).to_string());
+	    }
+	}
+    }
+    let core = vm.heap.module(vm.core_module);
+    for index in 0..core.scope.len() {
+	if let Some(class) = vm.core_class(core.scope.name(index)) {
+	    signatures.extend(vm.class_signatures(class));
+	}
+    }
+    signatures
+}
@@ -0,0 +1,320 @@
+// The `json` module, which converts between JSON text and Wren values as
+// described by RFC 8259: objects are Maps with String keys, arrays are
+// Lists, and numbers are Nums.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::core::map_set;
+use crate::error::WrenError;
+#[allow(unused_imports)]
+use crate::float::Float;
+use crate::handle::WrenHandle;
+use crate::json::{JsonError, JsonEvent, JsonOptions, JsonParser, MAX_NESTING};
+use crate::value::{Obj, ObjMap, ObjRef, Value};
+use crate::vm::{ForeignMethodFn, WrenVM};
+
+pub(crate) const SOURCE: &str = include_str!("json.wren");
+
+/// The foreign static methods of the `Json` class, by signature.
+pub(crate) const METHODS: &[(&str, ForeignMethodFn)] = &[
+    ("parse_(_)", json_parse),
+    ("stringify_(_,_,_,_)", json_stringify),
+];
+
+fn json_parse(vm: &mut WrenVM) {
+    // `Json.parse` has checked it is a string.
+    let source = vm.get_slot_string(1).unwrap_or("").to_string();
+    match vm.set_slot_json(0, JsonParser::from_bytes(source.into_bytes())) {
+	Ok(()) => {}
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn json_stringify(vm: &mut WrenVM) {
+    // `Json.stringify` has checked its options.
+    let options = JsonOptions {
+	indent: vm.get_slot_string(2).unwrap_or("").to_string(),
+	sort_keys: vm.get_slot_bool(3).unwrap_or(false),
+	ascii_only: vm.get_slot_bool(4).unwrap_or(false),
+    };
+    match vm.get_slot_json_with(1, &options) {
+	Ok(json) => vm.set_slot_string(0, json),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+// An array or object whose events are being read.
+enum Partial {
+    List(Vec<Value>),
+    // The key of the member whose value comes next.
+    Map(ObjRef, Value),
+}
+
+impl WrenVM {
+    /// Converts the value in `slot` to JSON with the default options, as
+    /// `Json.stringify` does.
+    pub fn get_slot_json(&self, slot: usize) -> Result<String, WrenError> {
+	self.get_slot_json_with(slot, &JsonOptions::default())
+    }
+
+    /// Converts the value in `slot` to JSON, which fails for values other
+    /// than Maps with String keys, Lists, Strings, finite Nums, Bools and
+    /// null, and for a List or Map that contains itself.
+    pub fn get_slot_json_with(
+	&self,
+	slot: usize,
+	options: &JsonOptions,
+    ) -> Result<String, WrenError> {
+	let mut stringifier = Stringifier {
+	    vm: self,
+	    options,
+	    output: String::new(),
+	    depth: 0,
+	};
+	match stringifier.value(self.slot(slot)) {
+	    Ok(()) => Ok(stringifier.output),
+	    Err(message) => Err(WrenError::Api { message }),
+	}
+    }
+
+    /// Parses a JSON document into `slot`, as `Json.parse` does, reading
+    /// the document only as it goes.
+    pub fn set_slot_json<I, E>(
+	&mut self,
+	slot: usize,
+	parser: JsonParser<I>,
+    ) -> Result<(), JsonError>
+    where
+	I: Iterator<Item = Result<u8, E>>,
+	E: fmt::Display,
+    {
+	let mut containers = Vec::new();
+	let mut document = Value::Null;
+	for event in parser {
+	    let value = match event? {
+		JsonEvent::StartObject => {
+		    let map = self.heap.alloc(Obj::Map(ObjMap::default()));
+		    containers.push(Partial::Map(map, Value::Null));
+		    continue;
+		}
+		JsonEvent::StartArray => {
+		    containers.push(Partial::List(Vec::new()));
+		    continue;
+		}
+		JsonEvent::Key(key) => {
+		    let key = self.new_string(key);
+		    if let Some(Partial::Map(_, pending)) = containers.last_mut() {
+			*pending = key;
+		    }
+		    continue;
+		}
+		JsonEvent::EndObject | JsonEvent::EndArray => match containers.pop() {
+		    Some(Partial::Map(map, _)) => Value::Obj(map),
+		    Some(Partial::List(elements)) => self.new_list(elements),
+		    None => Value::Null,
+		},
+		JsonEvent::String(string) => self.new_string(string),
+		JsonEvent::Number(number) => Value::Num(number),
+		JsonEvent::Bool(value) => Value::Bool(value),
+		JsonEvent::Null => Value::Null,
+	    };
+	    match containers.last_mut() {
+		Some(Partial::List(elements)) => elements.push(value),
+		Some(&mut Partial::Map(map, key)) => map_set(self, map, key, value),
+		// The parser ends the document after its value, or reports
+		// the text that follows it.
+		None => document = value,
+	    }
+	}
+	self.set_slot(slot, document);
+	Ok(())
+    }
+
+    /// Feeds a JSON document to a script an event at a time, without
+    /// building the whole of it, by calling `handler` with the name of each
+    /// event and its value: `startObject`, `endObject`, `startArray` and
+    /// `endArray` with null, `key` with the member's key, and `value` with
+    /// a String, Num, Bool or null. `handler` is a handle to anything with
+    /// a `call(_,_)` method, usually a Fn.
+    ///
+    /// It stops at the first runtime error in `handler`, reported as by
+    /// `call`, or at the first error in the document, which is returned as
+    /// an API error as the events before it have already been handled.
+    pub fn stream_json<I, E>(
+	&mut self,
+	handler: &WrenHandle,
+	parser: JsonParser<I>,
+    ) -> Result<(), WrenError>
+    where
+	I: Iterator<Item = Result<u8, E>>,
+	E: fmt::Display,
+    {
+	let call = self.make_call_handle("call(_,_)")?;
+	let mut result = Ok(());
+	for event in parser {
+	    let event = match event {
+		Ok(event) => event,
+		Err(error) => {
+		    result = Err(WrenError::Api {
+			message: error.to_string(),
+		    });
+		    break;
+		}
+	    };
+	    let (name, value) = match event {
+		JsonEvent::StartObject => ("startObject", Value::Null),
+		JsonEvent::EndObject => ("endObject", Value::Null),
+		JsonEvent::StartArray => ("startArray", Value::Null),
+		JsonEvent::EndArray => ("endArray", Value::Null),
+		JsonEvent::Key(key) => ("key", self.new_string(key)),
+		JsonEvent::String(string) => ("value", self.new_string(string)),
+		JsonEvent::Number(number) => ("value", Value::Num(number)),
+		JsonEvent::Bool(value) => ("value", Value::Bool(value)),
+		JsonEvent::Null => ("value", Value::Null),
+	    };
+	    self.ensure_slots(3);
+	    self.set_slot_handle(0, handler);
+	    self.set_slot_string(1, name);
+	    self.set_slot(2, value);
+	    result = self.call(&call);
+	    if result.is_err() {
+		break;
+	    }
+	}
+	self.release_handle(call);
+	result
+    }
+}
+
+struct Stringifier<'a> {
+    vm: &'a WrenVM,
+    options: &'a JsonOptions,
+    output: String,
+    depth: usize,
+}
+
+impl Stringifier<'_> {
+    fn value(&mut self, value: Value) -> Result<(), String> {
+	let vm = self.vm;
+	match value {
+	    Value::Null => self.output.push_str("null"),
+	    Value::Bool(value) => self.output.push_str(if value { "true" } else { "false" }),
+	    Value::Num(value) if !value.is_finite() => {
+		return Err("NaN and infinity cannot be converted to JSON.".to_string());
+	    }
+	    // Integers are written without a fraction, as JavaScript does.
+	    Value::Num(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+		let _ = write!(self.output, "{}", value as i64);
+	    }
+	    Value::Num(value) => {
+		let _ = write!(self.output, "{}", value);
+	    }
+	    Value::Obj(obj) => match vm.heap.get(obj) {
+		Obj::String(string) => self.string(&string.value),
+		Obj::List(list) => {
+		    self.open('[')?;
+		    for (index, &element) in list.elements.iter().enumerate() {
+			self.separate(index);
+			self.value(element)?;
+		    }
+		    self.close(']', list.elements.is_empty());
+		}
+		Obj::Map(map) => {
+		    let mut members = Vec::with_capacity(map.count);
+		    for (key, value) in map.iter() {
+			match vm.heap.as_str(key) {
+			    Some(key) => members.push((key, value)),
+			    None => return Err("Map keys must be strings.".to_string()),
+			}
+		    }
+		    if self.options.sort_keys {
+			members.sort_by_key(|&(key, _)| key);
+		    }
+		    self.open('{')?;
+		    for (index, &(key, value)) in members.iter().enumerate() {
+			self.separate(index);
+			self.string(key);
+			self.output.push(':');
+			if !self.options.indent.is_empty() {
+			    self.output.push(' ');
+			}
+			self.value(value)?;
+		    }
+		    self.close('}', members.is_empty());
+		}
+		_ => {
+		    let class = &vm.heap.class(vm.class_of(value)).name;
+		    return Err(format!("{} cannot be converted to JSON.", class));
+		}
+	    },
+	}
+	Ok(())
+    }
+
+    fn string(&mut self, string: &str) {
+	self.output.push('"');
+	for c in string.chars() {
+	    match c {
+		'"' => self.output.push_str("\\\""),
+		'\\' => self.output.push_str("\\\\"),
+		'\u{8}' => self.output.push_str("\\b"),
+		'\u{c}' => self.output.push_str("\\f"),
+		'\n' => self.output.push_str("\\n"),
+		'\r' => self.output.push_str("\\r"),
+		'\t' => self.output.push_str("\\t"),
+		'\u{0}'..='\u{1f}' => {
+		    let _ = write!(self.output, "\\u{:04x}", c as u32);
+		}
+		_ if self.options.ascii_only && !c.is_ascii() => {
+		    for unit in c.encode_utf16(&mut [0; 2]) {
+			let _ = write!(self.output, "\\u{:04x}", unit);
+		    }
+		}
+		_ => self.output.push(c),
+	    }
+	}
+	self.output.push('"');
+    }
+
+    fn open(&mut self, bracket: char) -> Result<(), String> {
+	if self.depth == MAX_NESTING {
+	    return Err("Lists and Maps are nested too deeply, or contain themselves.".to_string());
+	}
+	self.depth += 1;
+	self.output.push(bracket);
+	Ok(())
+    }
+
+    // Starts the element at `index` of the List or Map being written.
+    fn separate(&mut self, index: usize) {
+	if index > 0 {
+	    self.output.push(',');
+	}
+	self.newline();
+    }
+
+    fn close(&mut self, bracket: char, is_empty: bool) {
+	self.depth -= 1;
+	if !is_empty {
+	    self.newline();
+	}
+	self.output.push(bracket);
+    }
+
+    fn newline(&mut self) {
+	if !self.options.indent.is_empty() {
+	    self.output.push('\n');
+	    for _ in 0..self.depth {
+		self.output.push_str(&self.options.indent);
+	    }
+	}
+    }
+}
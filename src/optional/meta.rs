@@ -0,0 +1,51 @@
+// The `meta` module, which lets a script compile and run code at runtime.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::value::Value;
+use crate::vm::{ForeignMethodFn, WrenVM};
+
+pub(crate) const SOURCE: &str = include_str!("meta.wren");
+
+/// The foreign static methods of the `Meta` class, by signature.
+pub(crate) const METHODS: &[(&str, ForeignMethodFn)] = &[
+    ("compile_(_)", meta_compile),
+    ("getModuleVariables_(_)", meta_get_module_variables),
+];
+
+// Compiles the source in slot 1 into a function that runs it in the module
+// of the method that called `Meta`, or returns the compile error's message.
+fn meta_compile(vm: &mut WrenVM) {
+    // The top frame is the `Meta` method that called this one.
+    let frames = &vm.frames;
+    let caller = frames[frames.len().saturating_sub(2)].closure;
+    let module = vm.heap.function(vm.heap.closure(caller).function).module;
+    let source = match vm.get_slot_string(1) {
+	Ok(source) => source.to_string(),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    return vm.abort_fiber(0);
+	}
+    };
+    match vm.compile_in_module(module, &source, false) {
+	Ok(closure) => vm.set_slot(0, Value::Obj(closure)),
+	Err(errors) => vm.set_slot_string(0, errors[0].to_string()),
+    }
+}
+
+fn meta_get_module_variables(vm: &mut WrenVM) {
+    let module = match vm.get_slot_string(1).map(|name| vm.modules.get(name)) {
+	Ok(Some(&module)) => module,
+	Ok(None) => return vm.set_slot_null(0),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    return vm.abort_fiber(0);
+	}
+    };
+    let scope = &vm.heap.module(module).scope;
+    let names: Vec<String> = (0..scope.len()).map(|index| scope.name(index).to_string()).collect();
+    let names = names.into_iter().map(|name| vm.new_string(name)).collect();
+    let list = vm.new_list(names);
+    vm.set_slot(0, list);
+}
@@ -0,0 +1,65 @@
+// Modules that ship with the VM but can be left out of the build with cargo
+// features. A script imports them like any other module, and they are used
+// when the host's module loader doesn't supply a module of the same name.
+
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "meta")]
+mod meta;
+#[cfg(feature = "random")]
+pub(crate) mod random;
+#[cfg(feature = "scheduler")]
+mod scheduler;
+#[cfg(feature = "timer")]
+mod timer;
+
+use crate::vm::WrenVM;
+
+/// Registers the host functions of the enabled optional modules.
+#[cfg_attr(
+    not(any(
+	feature = "json",
+	feature = "meta",
+	feature = "random",
+	feature = "timer"
+    )),
+    allow(unused_variables)
+)]
+pub(crate) fn initialize(vm: &mut WrenVM) {
+    #[cfg(feature = "json")]
+    for &(signature, method) in json::METHODS {
+	vm.bind_foreign_method("json", "Json", true, signature, method);
+    }
+    #[cfg(feature = "meta")]
+    for &(signature, method) in meta::METHODS {
+	vm.bind_foreign_method("meta", "Meta", true, signature, method);
+    }
+    #[cfg(feature = "random")]
+    {
+	vm.bind_foreign_class("random", "Random", random::CLASS);
+	for &(signature, method) in random::METHODS {
+	    vm.bind_foreign_method("random", "Random", false, signature, method);
+	}
+    }
+    #[cfg(feature = "timer")]
+    for &(signature, method) in timer::METHODS {
+	vm.bind_foreign_method("timer", "Timer", true, signature, method);
+    }
+}
+
+/// The source of the optional module `name`, if it is enabled.
+pub(crate) fn source(name: &str) -> Option<&'static str> {
+    match name {
+	#[cfg(feature = "json")]
+	"json" => Some(json::SOURCE),
+	#[cfg(feature = "meta")]
+	"meta" => Some(meta::SOURCE),
+	#[cfg(feature = "random")]
+	"random" => Some(random::SOURCE),
+	#[cfg(feature = "scheduler")]
+	"scheduler" => Some(scheduler::SOURCE),
+	#[cfg(feature = "timer")]
+	"timer" => Some(timer::SOURCE),
+	_ => None,
+    }
+}
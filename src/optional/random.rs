@@ -0,0 +1,145 @@
+// The `random` module: a pseudo-random number generator using the WELL512a
+// algorithm, as in wren_c.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::vm::{ForeignClassMethods, ForeignMethodFn, HostData, WrenVM};
+
+pub(crate) const SOURCE: &str = include_str!("random.wren");
+
+/// The foreign methods of the `Random` class, by signature.
+pub(crate) const METHODS: &[(&str, ForeignMethodFn)] = &[
+    ("seed_()", random_seed0),
+    ("seed_(_)", random_seed1),
+    ("seed_(_,_,_,_,_,_,_,_,_,_,_,_,_,_,_,_)", random_seed16),
+    ("float()", random_float),
+    ("int()", random_int0),
+];
+
+pub(crate) const CLASS: ForeignClassMethods = ForeignClassMethods {
+    allocate: random_allocate,
+    finalize: None,
+};
+
+#[derive(Debug, Default)]
+struct Well512 {
+    state: [u32; 16],
+    index: usize,
+}
+
+impl Well512 {
+    // Fills the state from a single 64-bit seed with SplitMix64, so nearby
+    // seeds still give unrelated sequences.
+    fn seed(&mut self, seed: u64) {
+	let mut seed = seed;
+	for word in &mut self.state {
+	    seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+	    let mut z = seed;
+	    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+	    *word = (z ^ (z >> 31)) as u32;
+	}
+	self.index = 0;
+    }
+
+    fn advance(&mut self) -> u32 {
+	let state = &mut self.state;
+	let mut a = state[self.index];
+	let mut c = state[(self.index + 13) & 15];
+	let b = a ^ c ^ (a << 16) ^ (c << 15);
+	c = state[(self.index + 9) & 15];
+	c ^= c >> 11;
+	a = b ^ c;
+	state[self.index] = a;
+	let d = a ^ ((a << 5) & 0xda442d24);
+
+	self.index = (self.index + 15) & 15;
+	a = state[self.index];
+	state[self.index] = a ^ b ^ d ^ (a << 2) ^ (b << 18) ^ (c << 28);
+	state[self.index]
+    }
+}
+
+/// The state of the `Random` whose data is `data`, its words and the index
+/// of the next, for a snapshot to save.
+pub(crate) fn state(data: &HostData) -> Option<([u32; 16], usize)> {
+    data.downcast_ref::<Well512>().map(|well| (well.state, well.index))
+}
+
+/// The data of a `Random` restored from a snapshot, if `index` is one of
+/// `state`'s.
+pub(crate) fn restore(state: [u32; 16], index: usize) -> Option<Box<HostData>> {
+    match index < state.len() {
+	true => Some(Box::new(Well512 { state, index })),
+	false => None,
+    }
+}
+
+fn well(vm: &mut WrenVM) -> &mut Well512 {
+    vm.get_slot_foreign_mut::<Well512>(0).expect("a Random")
+}
+
+fn random_allocate(vm: &mut WrenVM) {
+    vm.set_slot_new_foreign(0, 0, Well512::default())
+	.expect("the Random class");
+}
+
+fn random_seed0(vm: &mut WrenVM) {
+    // As with `System.clock`, the host's clock is used if it has one. A
+    // deterministic VM always starts from the same seed.
+    let now = match vm.config.clock_fn {
+	_ if vm.config.deterministic => 0,
+	Some(clock_fn) => clock_fn().to_bits(),
+	#[cfg(feature = "std")]
+	None => SystemTime::now()
+	    .duration_since(UNIX_EPOCH)
+	    .map_or(0, |time| time.as_nanos() as u64),
+	#[cfg(not(feature = "std"))]
+	None => 0,
+    };
+    well(vm).seed(now);
+}
+
+fn random_seed1(vm: &mut WrenVM) {
+    match vm.get_slot_double(1) {
+	Ok(seed) => well(vm).seed(seed.to_bits()),
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    vm.abort_fiber(0);
+	}
+    }
+}
+
+fn random_seed16(vm: &mut WrenVM) {
+    let mut state = [0; 16];
+    for (i, word) in state.iter_mut().enumerate() {
+	match vm.get_slot_double(i + 1) {
+	    Ok(n) => *word = n as i64 as u32,
+	    Err(error) => {
+		vm.set_slot_string(0, error.to_string());
+		return vm.abort_fiber(0);
+	    }
+	}
+    }
+    let well = well(vm);
+    well.state = state;
+    well.index = 0;
+}
+
+fn random_float(vm: &mut WrenVM) {
+    let well = well(vm);
+    // A double has 53 bits of precision, so take 32 random bits shifted up
+    // by 21 and add another 21 bits, then scale from [0, 2^53) to [0, 1).
+    let mut result = well.advance() as f64 * (1 << 21) as f64;
+    result += (well.advance() & ((1 << 21) - 1)) as f64;
+    result /= 9007199254740992.0;
+    vm.set_slot_double(0, result);
+}
+
+fn random_int0(vm: &mut WrenVM) {
+    let result = well(vm).advance();
+    vm.set_slot_double(0, result as f64);
+}
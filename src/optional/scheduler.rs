@@ -0,0 +1,40 @@
+// The `scheduler` module, as in wren-cli: fibers that `Scheduler.add` queues
+// run once the script's main fiber finishes or waits on the host, which is
+// how modules built on `suspend_fiber`, like `timer`, share the
+// VM between several fibers.
+
+use crate::error::WrenError;
+use crate::vm::WrenVM;
+
+pub(crate) const SOURCE: &str = include_str!("scheduler.wren");
+
+impl WrenVM {
+    /// Runs the fibers scripts have queued with `Scheduler.add`, in order,
+    /// each until it finishes or waits on the host. It returns when the
+    /// queue is empty, or with the first runtime error, which is reported
+    /// as by `interpret`.
+    ///
+    /// A host with an event loop calls it after `interpret` and after each
+    /// `resume_fiber`, as either may queue more fibers.
+    pub fn run_scheduled(&mut self) -> Result<(), WrenError> {
+	// Nothing was scheduled if no script imported the module.
+	if !self.has_variable("scheduler", "Scheduler") {
+	    return Ok(());
+	}
+	let run_next = self.make_call_handle("runNextScheduled_()")?;
+	let result = loop {
+	    self.ensure_slots(1);
+	    let ran = self
+		.get_variable("scheduler", "Scheduler", 0)
+		.and_then(|()| self.call(&run_next));
+	    match ran {
+		// A fiber that waits on the host leaves null rather than true.
+		Ok(()) if self.slot(0).as_bool() == Some(false) => break Ok(()),
+		Ok(()) => {}
+		Err(error) => break Err(error),
+	    }
+	};
+	self.release_handle(run_next);
+	result
+    }
+}
@@ -0,0 +1,79 @@
+// The `timer` module, as in wren-cli: `Timer.sleep` suspends its fiber and
+// the VM's event loop resumes it once the time is up, running the fibers
+// `Scheduler.add` queued in between.
+
+use alloc::string::ToString;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::WrenError;
+use crate::vm::{ForeignMethodFn, WrenVM};
+
+pub(crate) const SOURCE: &str = include_str!("timer.wren");
+
+/// The foreign static methods of the `Timer` class, by signature.
+pub(crate) const METHODS: &[(&str, ForeignMethodFn)] = &[("startTimer_(_)", timer_start)];
+
+impl WrenVM {
+    /// Runs the scheduled fibers, as `run_scheduled` does, and then resumes
+    /// each fiber sleeping in `Timer.sleep` once its time is up, until
+    /// every fiber has finished or one fails with a runtime error. It
+    /// blocks the thread while it waits.
+    ///
+    /// A host that waits on events of its own calls `wake_timers` in its
+    /// loop instead.
+    pub fn run_event_loop(&mut self) -> Result<(), WrenError> {
+	self.run_scheduled()?;
+	while let Some(deadline) = self.wake_timers()? {
+	    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+	}
+	Ok(())
+    }
+
+    /// Resumes the fibers sleeping in `Timer.sleep` whose time is up, each
+    /// followed by the fibers it scheduled, and returns when the next one
+    /// wakes up, or `None` if no fiber is sleeping.
+    ///
+    /// A deterministic VM doesn't wait for the time to pass: it wakes every
+    /// sleeping fiber in order, moving its clock on to each one's deadline.
+    pub fn wake_timers(&mut self) -> Result<Option<Instant>, WrenError> {
+	// The timer that is up first, or the one started first of those that
+	// are up at the same time.
+	while let Some(index) = (0..self.timers.len()).min_by_key(|&index| self.timers[index].0) {
+	    let deadline = self.timers[index].0;
+	    if let (true, Some(start)) = (self.config.deterministic, self.start_time) {
+		self.virtual_time = (deadline - start).as_secs_f64();
+	    } else if deadline > Instant::now() {
+		return Ok(Some(deadline));
+	    }
+	    let (_, fiber) = self.timers.remove(index);
+	    let result = self.resume_fiber(&fiber, ());
+	    self.release_handle(fiber);
+	    result?;
+	    self.run_scheduled()?;
+	}
+	Ok(None)
+    }
+}
+
+fn timer_start(vm: &mut WrenVM) {
+    // `Timer.sleep` has checked the number.
+    let milliseconds = vm.get_slot_double(1).unwrap_or(0.0);
+    let fiber = match vm.suspend_fiber() {
+	Ok(fiber) => fiber,
+	Err(error) => {
+	    vm.set_slot_string(0, error.to_string());
+	    return vm.abort_fiber(0);
+	}
+    };
+    let duration = Duration::from_secs_f64(milliseconds / 1000.0);
+    // A deterministic VM's deadlines are on its own clock, counted from
+    // when the first fiber slept.
+    let deadline = if vm.config.deterministic {
+	let start = *vm.start_time.get_or_insert_with(Instant::now);
+	start + Duration::from_secs_f64(vm.virtual_time) + duration
+    } else {
+	Instant::now() + duration
+    };
+    vm.timers.push((deadline, fiber));
+}
@@ -0,0 +1,1364 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+
+use crate::ast::*;
+use crate::compiler::MAX_VARIABLE_NAME;
+use crate::lexer::{Lexeme, Lexer, Span, Token, MAX_INTERPOLATION_NESTING};
+
+/// The maximum number of parameters a method or block argument may take.
+pub const MAX_PARAMETERS: usize = 16;
+
+/// How deeply statements and expressions may nest by default, well short
+/// of overflowing the stack of the recursive parser and compiler. A chain
+/// of operators or calls nests as deeply as it is long.
+pub const MAX_NESTING: usize = 128;
+
+/// The most errors `parse_recovering` reports before giving up on the
+/// rest of the source.
+pub const MAX_ERRORS: usize = 64;
+
+/// Limits on source code, beyond which it is a parse error rather than
+/// exhausting the host's stack, for hosts that run untrusted scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// How deeply statements and expressions may nest, counting each
+    /// nested block, statement and operand.
+    pub max_nesting: usize,
+    /// How deeply string interpolations may nest inside one another.
+    pub max_interpolation_nesting: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+	ParseOptions {
+	    max_nesting: MAX_NESTING,
+	    max_interpolation_nesting: MAX_INTERPOLATION_NESTING,
+	}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    /// Text of the offending token, "\n" for a newline, empty if there is
+    /// none to show.
+    pub token: String,
+    /// Whether the error was found at the end of the input.
+    pub at_end: bool,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "[line {}] Error", self.span.line)?;
+	write_location(f, &self.token, self.at_end)?;
+	write!(f, ": {}", self.message)
+    }
+}
+
+/// Writes where an error was found the way wren_c does, as " at 'token'",
+/// " at newline", " at end of file", or nothing for an error in a token
+/// itself.
+pub(crate) fn write_location(f: &mut fmt::Formatter, token: &str, at_end: bool) -> fmt::Result {
+    if at_end {
+	f.write_str(" at end of file")
+    } else if token == "\n" {
+	f.write_str(" at newline")
+    } else if token.len() > MAX_VARIABLE_NAME {
+	// wren_c shows no more of a long token than the longest name.
+	let mut end = MAX_VARIABLE_NAME;
+	while !token.is_char_boundary(end) {
+	    end -= 1;
+	}
+	write!(f, " at '{}...'", &token[..end])
+    } else if !token.is_empty() {
+	write!(f, " at '{}'", token)
+    } else {
+	Ok(())
+    }
+}
+
+impl error::Error for ParseError {}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+// The argument list and block argument following a method name.
+type CallArguments = (Option<Vec<Expr>>, Option<Box<BlockArg>>);
+
+/// Parses a complete source file.
+pub fn parse(source: &str) -> ParseResult<Module> {
+    parse_with(source, &ParseOptions::default())
+}
+
+/// Parses a complete source file within the limits of `options`.
+pub fn parse_with(source: &str, options: &ParseOptions) -> ParseResult<Module> {
+    Parser::with_options(source, options).parse_module()
+}
+
+/// Parses a complete source file, going on past syntax errors to find the
+/// rest of them, as wren_c does. Returns the statements that parsed, and
+/// the errors, of which there are at most `MAX_ERRORS`.
+pub fn parse_recovering(source: &str, options: &ParseOptions) -> (Module, Vec<ParseError>) {
+    Parser::with_options(source, options).parse_module_recovering()
+}
+
+/// Parses `source` as a single expression, such as a line typed into the
+/// REPL whose value should be printed.
+pub fn parse_expression(source: &str) -> ParseResult<Expr> {
+    parse_expression_with(source, &ParseOptions::default())
+}
+
+/// Parses `source` as a single expression within the limits of `options`.
+pub fn parse_expression_with(source: &str, options: &ParseOptions) -> ParseResult<Expr> {
+    let mut parser = Parser::with_options(source, options);
+    parser.ignore_newlines()?;
+    let expr = parser.expression()?;
+    parser.ignore_newlines()?;
+    parser.consume(&Token::Eof, "Expect end of expression.")?;
+    Ok(expr)
+}
+
+// Binding power of infix operators, weakest first, as in the reference
+// grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    None,
+    Lowest,
+    Assignment,
+    Conditional,
+    LogicalOr,
+    LogicalAnd,
+    Equality,
+    Is,
+    Comparison,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    BitwiseShift,
+    Range,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    pub(crate) fn next(self) -> Precedence {
+	match self {
+	    Precedence::None => Precedence::Lowest,
+	    Precedence::Lowest => Precedence::Assignment,
+	    Precedence::Assignment => Precedence::Conditional,
+	    Precedence::Conditional => Precedence::LogicalOr,
+	    Precedence::LogicalOr => Precedence::LogicalAnd,
+	    Precedence::LogicalAnd => Precedence::Equality,
+	    Precedence::Equality => Precedence::Is,
+	    Precedence::Is => Precedence::Comparison,
+	    Precedence::Comparison => Precedence::BitwiseOr,
+	    Precedence::BitwiseOr => Precedence::BitwiseXor,
+	    Precedence::BitwiseXor => Precedence::BitwiseAnd,
+	    Precedence::BitwiseAnd => Precedence::BitwiseShift,
+	    Precedence::BitwiseShift => Precedence::Range,
+	    Precedence::Range => Precedence::Term,
+	    Precedence::Term => Precedence::Factor,
+	    Precedence::Factor => Precedence::Unary,
+	    Precedence::Unary => Precedence::Call,
+	    Precedence::Call | Precedence::Primary => Precedence::Primary,
+	}
+    }
+}
+
+// Whether an expression can start with `token`, as `Parser::prefix` parses.
+fn starts_expression(token: &Token) -> bool {
+    matches!(
+	token,
+	Token::LeftParen
+	    | Token::LeftBracket
+	    | Token::LeftBrace
+	    | Token::Minus
+	    | Token::Bang
+	    | Token::Tilde
+	    | Token::Null
+	    | Token::True
+	    | Token::False
+	    | Token::This
+	    | Token::Number(_)
+	    | Token::String(_)
+	    | Token::InterpolationStart(_)
+	    | Token::InterpolationPart(_)
+	    | Token::InterpolationEnd(_)
+	    | Token::Field(_)
+	    | Token::StaticField(_)
+	    | Token::Name(_)
+	    | Token::Super
+    )
+}
+
+// Whether a method's signature can start with `token`, as
+// `Parser::method_signature` parses.
+fn starts_method(token: &Token) -> bool {
+    matches!(
+	token,
+	Token::Construct | Token::Name(_) | Token::LeftBracket | Token::Bang | Token::Tilde
+    ) || binary_op(token).is_some()
+}
+
+fn infix_precedence(token: &Token) -> Precedence {
+    match token {
+	Token::LeftBracket | Token::Dot => Precedence::Call,
+	Token::DotDot | Token::DotDotDot => Precedence::Range,
+	Token::Star | Token::Slash | Token::Percent => Precedence::Factor,
+	Token::Plus | Token::Minus => Precedence::Term,
+	Token::LtLt | Token::GtGt => Precedence::BitwiseShift,
+	Token::Pipe => Precedence::BitwiseOr,
+	Token::Caret => Precedence::BitwiseXor,
+	Token::Amp => Precedence::BitwiseAnd,
+	Token::PipePipe => Precedence::LogicalOr,
+	Token::AmpAmp => Precedence::LogicalAnd,
+	Token::Question => Precedence::Assignment,
+	Token::EqEq | Token::BangEq => Precedence::Equality,
+	Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => Precedence::Comparison,
+	Token::Is => Precedence::Is,
+	_ => Precedence::None,
+    }
+}
+
+fn binary_op(token: &Token) -> Option<BinaryOp> {
+    let op = match token {
+	Token::Plus => BinaryOp::Add,
+	Token::Minus => BinaryOp::Sub,
+	Token::Star => BinaryOp::Mul,
+	Token::Slash => BinaryOp::Div,
+	Token::Percent => BinaryOp::Mod,
+	Token::Lt => BinaryOp::Lt,
+	Token::Gt => BinaryOp::Gt,
+	Token::LtEq => BinaryOp::LtEq,
+	Token::GtEq => BinaryOp::GtEq,
+	Token::EqEq => BinaryOp::Eq,
+	Token::BangEq => BinaryOp::NotEq,
+	Token::Amp => BinaryOp::BitAnd,
+	Token::Pipe => BinaryOp::BitOr,
+	Token::Caret => BinaryOp::BitXor,
+	Token::LtLt => BinaryOp::Shl,
+	Token::GtGt => BinaryOp::Shr,
+	Token::DotDot => BinaryOp::RangeInclusive,
+	Token::DotDotDot => BinaryOp::RangeExclusive,
+	Token::Is => BinaryOp::Is,
+	_ => return None,
+    };
+    Some(op)
+}
+
+/// Whether `name` could be a local variable or a method on `this`, as
+/// opposed to a capitalized module-level variable.
+pub fn is_local_name(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_lowercase())
+}
+
+fn error_at(lexeme: &Lexeme, message: String) -> ParseError {
+    ParseError {
+	message,
+	span: lexeme.span,
+	token: token_text(&lexeme.token),
+	at_end: lexeme.token == Token::Eof,
+    }
+}
+
+// An error the lexer recorded and went on past.
+fn lexer_error(error: &Lexeme) -> ParseError {
+    error_at(error, error.token.to_string())
+}
+
+// The text an error at `token` shows.
+fn token_text(token: &Token) -> String {
+    match token {
+	Token::Eof | Token::Error(_) => String::new(),
+	Token::Line => "\n".to_string(),
+	token => token.to_string(),
+    }
+}
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    // The token after the current one, once it has been looked at.
+    next: Option<Lexeme>,
+    current: Lexeme,
+    previous: Lexeme,
+    // How many class bodies enclose the current token. A bare lowercase
+    // name followed by arguments is only a call on `this` inside one.
+    class_depth: usize,
+    // How many statements and expressions enclose the current token.
+    depth: usize,
+    max_nesting: usize,
+    // How many braces the tokens before the current one leave open.
+    braces: usize,
+    // The errors found so far, and how many there may be before parsing
+    // gives up. With one, the first error ends parsing.
+    errors: Vec<ParseError>,
+    max_errors: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Parser<'a> {
+	Parser::with_options(source, &ParseOptions::default())
+    }
+
+    pub fn with_options(source: &'a str, options: &ParseOptions) -> Parser<'a> {
+	let mut lexer = Lexer::new(source);
+	lexer.max_interpolation_nesting = options.max_interpolation_nesting;
+	let current = lexer.next().expect("lexer always yields Eof");
+	let mut parser = Parser {
+	    lexer,
+	    next: None,
+	    previous: current.clone(),
+	    current,
+	    class_depth: 0,
+	    depth: 0,
+	    max_nesting: options.max_nesting,
+	    braces: 0,
+	    errors: Vec::new(),
+	    max_errors: 1,
+	};
+	// Errors the lexer went on past are found as the token with them
+	// becomes the current one.
+	let errors = parser.lexer.take_errors();
+	parser.errors.extend(errors.iter().map(lexer_error));
+	parser
+    }
+
+    pub fn parse_module(&mut self) -> ParseResult<Module> {
+	let module = self.module(1);
+	match self.errors.drain(..).next() {
+	    Some(error) => Err(error),
+	    None => Ok(module),
+	}
+    }
+
+    /// Parses the rest of the source, going on past each syntax error as
+    /// `parse_recovering` does.
+    pub fn parse_module_recovering(&mut self) -> (Module, Vec<ParseError>) {
+	let module = self.module(MAX_ERRORS);
+	(module, core::mem::take(&mut self.errors))
+    }
+
+    // Parses top-level statements until the end of the source or until
+    // `max_errors` errors. Most errors are passed over where they are found,
+    // as wren_c does, and the rest end the statement they are found in.
+    fn module(&mut self, max_errors: usize) -> Module {
+	self.max_errors = max_errors;
+	let mut statements = Vec::new();
+	loop {
+	    let mut start = self.current.span.start;
+	    let result = self.ignore_newlines().and_then(|_| {
+		start = self.current.span.start;
+		self.next_definition(&mut statements)
+	    });
+	    match result {
+		Ok(true) => break,
+		Ok(false) => {}
+		Err(error) => {
+		    self.errors.push(error);
+		    // Skip the token with the error if nothing was parsed, so
+		    // as not to find it again.
+		    let skip = self.current.span.start == start;
+		    if self.errors.len() >= max_errors || !self.synchronize(skip) {
+			break;
+		    }
+		}
+	    }
+	}
+	Module { statements }
+    }
+
+    // Parses a top-level statement into `statements`, returning whether it
+    // was the last.
+    fn next_definition(&mut self, statements: &mut Vec<Stmt>) -> ParseResult<bool> {
+	if self.match_token(&Token::Eof)? {
+	    return Ok(true);
+	}
+	statements.push(self.definition()?);
+	// Without a newline, this must be the end of the file.
+	if !self.match_line()? {
+	    self.consume(&Token::Eof, "Expect end of file.")?;
+	    return Ok(true);
+	}
+	Ok(false)
+    }
+
+    // Skips to the end of the top-level statement with an error, at a
+    // newline or the start of a line outside any braces, collecting the
+    // lexer's errors on the way. Returns false if that makes `max_errors`
+    // errors.
+    fn synchronize(&mut self, skip: bool) -> bool {
+	self.depth = 0;
+	self.class_depth = 0;
+	let mut skip = skip;
+	while !self.check(&Token::Eof) {
+	    let starts_line = self.current.span.line > self.previous.span.line;
+	    if !skip && (self.check(&Token::Line) || starts_line) && self.braces == 0 {
+		break;
+	    }
+	    skip = false;
+	    if let Err(error) = self.advance() {
+		self.errors.push(error);
+		if self.errors.len() >= self.max_errors {
+		    return false;
+		}
+	    }
+	}
+	true
+    }
+
+    // Token plumbing.
+
+    fn advance(&mut self) -> ParseResult<()> {
+	let next = self.next.take().or_else(|| self.lexer.next());
+	let next = next.unwrap_or_else(|| self.current.clone());
+	match self.current.token {
+	    Token::LeftBrace => self.braces += 1,
+	    Token::RightBrace => self.braces = self.braces.saturating_sub(1),
+	    _ => {}
+	}
+	self.previous = core::mem::replace(&mut self.current, next);
+	for error in self.lexer.take_errors() {
+	    self.recover(lexer_error(&error))?;
+	}
+	if let Token::Error(message) = &self.current.token {
+	    let message = message.clone();
+	    return Err(self.error_at_current(message));
+	}
+	Ok(())
+    }
+
+    fn check(&self, token: &Token) -> bool {
+	&self.current.token == token
+    }
+
+    fn match_token(&mut self, token: &Token) -> ParseResult<bool> {
+	if !self.check(token) {
+	    return Ok(false);
+	}
+	self.advance()?;
+	Ok(true)
+    }
+
+    // Like wren_c, a token other than the one expected is skipped once its
+    // error is recorded, and so is the one expected if it comes next.
+    fn consume(&mut self, token: &Token, message: &str) -> ParseResult<()> {
+	if self.match_token(token)? {
+	    return Ok(());
+	}
+	self.recover(self.error_at_current(message))?;
+	self.advance()?;
+	self.match_token(token)?;
+	Ok(())
+    }
+
+    fn consume_name(&mut self, message: &str) -> ParseResult<Ident> {
+	if !matches!(self.current.token, Token::Name(_)) {
+	    self.recover(self.error_at_current(message))?;
+	    self.advance()?;
+	    // Go on with the skipped token as the name, unless one follows it.
+	    if !matches!(self.current.token, Token::Name(_)) {
+		return Ok(Ident {
+		    name: self.previous.token.to_string(),
+		    span: self.previous.span,
+		});
+	    }
+	}
+	let ident = Ident {
+	    name: self.current.token.to_string(),
+	    span: self.current.span,
+	};
+	self.advance()?;
+	Ok(ident)
+    }
+
+    // Matches one or more newlines.
+    fn match_line(&mut self) -> ParseResult<bool> {
+	if !self.match_token(&Token::Line)? {
+	    return Ok(false);
+	}
+	while self.match_token(&Token::Line)? {}
+	Ok(true)
+    }
+
+    fn ignore_newlines(&mut self) -> ParseResult<()> {
+	while self.match_token(&Token::Line)? {}
+	Ok(())
+    }
+
+    fn consume_line(&mut self, message: &str) -> ParseResult<()> {
+	self.consume(&Token::Line, message)?;
+	self.ignore_newlines()
+    }
+
+    fn peek_next(&mut self) -> Option<&Token> {
+	if self.next.is_none() {
+	    self.next = self.lexer.next();
+	}
+	self.next.as_ref().map(|lexeme| &lexeme.token)
+    }
+
+    fn error_at_current(&self, message: impl Into<String>) -> ParseError {
+	error_at(&self.current, message.into())
+    }
+
+    fn error_at_previous(&self, message: impl Into<String>) -> ParseError {
+	error_at(&self.previous, message.into())
+    }
+
+    // Records `error` so that parsing can go on past it, unless that would
+    // make `max_errors` errors, when it fails with it instead.
+    fn recover(&mut self, error: ParseError) -> ParseResult<()> {
+	if self.errors.len() + 1 >= self.max_errors {
+	    return Err(error);
+	}
+	self.errors.push(error);
+	Ok(())
+    }
+
+    // Enters a statement or expression, which the caller leaves by taking
+    // one from `depth`.
+    fn nest(&mut self) -> ParseResult<()> {
+	if self.depth >= self.max_nesting {
+	    return Err(self.error_at_current("Code is nested too deeply."));
+	}
+	self.depth += 1;
+	Ok(())
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+	start.to(self.previous.span)
+    }
+
+    // Statements.
+
+    fn definition(&mut self) -> ParseResult<Stmt> {
+	let attributes = self.attributes()?;
+	let start = self.current.span;
+	if self.match_token(&Token::Class)? {
+	    return self.class_definition(start, false, attributes);
+	}
+	if self.match_token(&Token::Foreign)? {
+	    self.consume(&Token::Class, "Expect 'class' after 'foreign'.")?;
+	    return self.class_definition(start, true, attributes);
+	}
+	if !attributes.is_empty() {
+	    return Err(self.error_at_current("Attributes can only specified before a class or a method"));
+	}
+	if self.match_token(&Token::Import)? {
+	    return self.import(start);
+	}
+	if self.match_token(&Token::Var)? {
+	    return self.variable_definition(start);
+	}
+	self.statement()
+    }
+
+    fn variable_definition(&mut self, start: Span) -> ParseResult<Stmt> {
+	let name = self.consume_name("Expect variable name.")?;
+	let initializer = if self.match_token(&Token::Eq)? {
+	    self.ignore_newlines()?;
+	    Some(self.expression()?)
+	} else {
+	    None
+	};
+	let end = Ident {
+	    name: token_text(&self.previous.token),
+	    span: self.previous.span,
+	};
+	Ok(Stmt {
+	    kind: StmtKind::Var { name, initializer, end },
+	    span: self.span_from(start),
+	})
+    }
+
+    fn import(&mut self, start: Span) -> ParseResult<Stmt> {
+	self.ignore_newlines()?;
+	let module = match &self.current.token {
+	    Token::String(name) => name.clone(),
+	    _ => return Err(self.error_at_current("Expect a string after 'import'.")),
+	};
+	self.advance()?;
+
+	let mut variables = Vec::new();
+	if self.match_token(&Token::For)? {
+	    self.ignore_newlines()?;
+	    loop {
+		self.ignore_newlines()?;
+		let name = self.consume_name("Expect variable name.")?;
+		let alias = if self.match_token(&Token::As)? {
+		    Some(self.consume_name("Expect variable name.")?)
+		} else {
+		    None
+		};
+		variables.push(ImportVariable { name, alias });
+		if !self.match_token(&Token::Comma)? {
+		    break;
+		}
+	    }
+	}
+	Ok(Stmt {
+	    kind: StmtKind::Import { module, variables },
+	    span: self.span_from(start),
+	})
+    }
+
+    fn class_definition(
+	&mut self,
+	start: Span,
+	is_foreign: bool,
+	attributes: Vec<Attribute>,
+    ) -> ParseResult<Stmt> {
+	// wren_c declares the class as it does any other variable.
+	let name = self.consume_name("Expect variable name.")?;
+	let superclass = if self.match_token(&Token::Is)? {
+	    Some(self.parse_precedence(Precedence::Call)?)
+	} else {
+	    None
+	};
+
+	self.consume(&Token::LeftBrace, "Expect '{' after class declaration.")?;
+	self.match_line()?;
+
+	self.class_depth += 1;
+	let methods = self.class_body();
+	self.class_depth -= 1;
+	let methods = methods?;
+
+	let span = self.span_from(start);
+	let class = ClassDecl {
+	    name,
+	    superclass,
+	    is_foreign,
+	    methods,
+	    attributes,
+	    span,
+	};
+	Ok(Stmt {
+	    kind: StmtKind::Class(Box::new(class)),
+	    span,
+	})
+    }
+
+    fn class_body(&mut self) -> ParseResult<Vec<Method>> {
+	let mut methods = Vec::new();
+	while !self.match_token(&Token::RightBrace)? {
+	    match self.method()? {
+		Some(method) => methods.push(method),
+		None => break,
+	    }
+	    // Don't require a newline after the last definition.
+	    if self.match_token(&Token::RightBrace)? {
+		break;
+	    }
+	    self.consume_line("Expect newline after definition in class.")?;
+	}
+	Ok(methods)
+    }
+
+    // Parses a method, or like wren_c, skips a token that can't start one
+    // and ends the class body.
+    fn method(&mut self) -> ParseResult<Option<Method>> {
+	let attributes = self.attributes()?;
+	let start = self.current.span;
+	let is_foreign = self.match_token(&Token::Foreign)?;
+	let is_static = self.match_token(&Token::Static)?;
+	if !starts_method(&self.current.token) {
+	    self.recover(self.error_at_current("Expect method definition."))?;
+	    self.advance()?;
+	    return Ok(None);
+	}
+
+	let (kind, name, params) = self.method_signature()?;
+	let signature_end = self.previous.span;
+	if kind == MethodKind::Constructor && is_static {
+	    return Err(self.error_at_previous("A constructor cannot be static."));
+	}
+
+	let body = if is_foreign {
+	    None
+	} else {
+	    self.consume(&Token::LeftBrace, "Expect '{' to begin method body.")?;
+	    Some(self.finish_body()?)
+	};
+	Ok(Some(Method {
+	    kind,
+	    name,
+	    params,
+	    signature_end,
+	    is_static,
+	    is_foreign,
+	    body,
+	    attributes,
+	    span: self.span_from(start),
+	}))
+    }
+
+    // Any attributes before a class or method, each on its own line.
+    fn attributes(&mut self) -> ParseResult<Vec<Attribute>> {
+	let mut attributes = Vec::new();
+	while self.match_token(&Token::Hash)? {
+	    let is_runtime = self.match_token(&Token::Bang)?;
+	    let name = self.consume_name("Expect an attribute definition after #.")?;
+	    if self.match_token(&Token::LeftParen)? {
+		self.ignore_newlines()?;
+		if self.check(&Token::RightParen) {
+		    return Err(self.error_at_current(
+			"Expected attributes in group, group cannot be empty.",
+		    ));
+		}
+		loop {
+		    let key = self.consume_name("Expect name for attribute key.")?;
+		    let value = self.attribute_value(&key)?;
+		    let span = key.span.to(value.span);
+		    attributes.push(Attribute {
+			group: Some(name.clone()),
+			key,
+			value,
+			is_runtime,
+			span,
+		    });
+		    self.ignore_newlines()?;
+		    if !self.match_token(&Token::Comma)? {
+			break;
+		    }
+		    self.ignore_newlines()?;
+		}
+		self.consume(&Token::RightParen, "Expected ')' after grouped attributes.")?;
+	    } else if self.check(&Token::Eq) || self.check(&Token::Line) {
+		let value = self.attribute_value(&name)?;
+		let span = name.span.to(value.span);
+		attributes.push(Attribute {
+		    group: None,
+		    key: name,
+		    value,
+		    is_runtime,
+		    span,
+		});
+	    } else {
+		return Err(self.error_at_current(
+		    "Expect an equal, newline or grouping after an attribute key.",
+		));
+	    }
+	    self.consume_line("Expect newline after attribute.")?;
+	}
+	Ok(attributes)
+    }
+
+    // The "= value" after an attribute's key, if there is one.
+    fn attribute_value(&mut self, key: &Ident) -> ParseResult<Expr> {
+	if !self.match_token(&Token::Eq)? {
+	    return Ok(Expr {
+		kind: ExprKind::Null,
+		span: key.span,
+	    });
+	}
+	let kind = match self.current.token.clone() {
+	    Token::Null => ExprKind::Null,
+	    Token::True => ExprKind::Bool(true),
+	    Token::False => ExprKind::Bool(false),
+	    Token::Number(n) => ExprKind::Num(n),
+	    Token::String(s) | Token::Name(s) => ExprKind::String(s),
+	    _ => {
+		return Err(self.error_at_current(
+		    "Expect a Bool, Num, String or Identifier literal for an attribute value.",
+		))
+	    }
+	};
+	let span = self.current.span;
+	self.advance()?;
+	Ok(Expr { kind, span })
+    }
+
+    fn method_signature(&mut self) -> ParseResult<(MethodKind, Ident, Vec<Ident>)> {
+	let token = self.current.token.clone();
+	let span = self.current.span;
+	match token {
+	    Token::Construct => {
+		self.advance()?;
+		let name = self.consume_name("Expect constructor name after 'construct'.")?;
+		if !self.check(&Token::LeftParen) {
+		    return Err(self.error_at_current("A parameter list is required for a constructor."));
+		}
+		let params = self.parameter_list()?;
+		Ok((MethodKind::Constructor, name, params))
+	    }
+	    Token::Name(name) => {
+		self.advance()?;
+		let name = Ident { name, span };
+		if self.match_token(&Token::Eq)? {
+		    self.consume(&Token::LeftParen, "Expect '(' after '='.")?;
+		    let param = self.consume_name("Expect variable name.")?;
+		    self.consume(&Token::RightParen, "Expect ')' after parameter name.")?;
+		    return Ok((MethodKind::Setter, name, vec![param]));
+		}
+		if self.check(&Token::LeftParen) {
+		    let params = self.parameter_list()?;
+		    return Ok((MethodKind::Method, name, params));
+		}
+		Ok((MethodKind::Getter, name, Vec::new()))
+	    }
+	    Token::LeftBracket => {
+		self.advance()?;
+		// Unlike a parameter list, a subscript needs at least one.
+		let params = self.parameters()?;
+		self.consume(&Token::RightBracket, "Expect ']' after parameters.")?;
+		let name = Ident {
+		    name: "[]".to_string(),
+		    span: self.span_from(span),
+		};
+		if self.match_token(&Token::Eq)? {
+		    self.consume(&Token::LeftParen, "Expect '(' after '='.")?;
+		    let mut params = params;
+		    params.push(self.consume_name("Expect variable name.")?);
+		    self.consume(&Token::RightParen, "Expect ')' after parameter name.")?;
+		    return Ok((MethodKind::SubscriptSetter, name, params));
+		}
+		Ok((MethodKind::Subscript, name, params))
+	    }
+	    Token::Bang | Token::Tilde => {
+		self.advance()?;
+		let name = Ident {
+		    name: token.to_string(),
+		    span,
+		};
+		Ok((MethodKind::Unary, name, Vec::new()))
+	    }
+	    _ if binary_op(&token).is_some() => {
+		self.advance()?;
+		let name = Ident {
+		    name: token.to_string(),
+		    span,
+		};
+		// "-" is both a prefix and an infix operator.
+		if token == Token::Minus && !self.check(&Token::LeftParen) {
+		    return Ok((MethodKind::Unary, name, Vec::new()));
+		}
+		self.consume(&Token::LeftParen, "Expect '(' after operator name.")?;
+		let param = self.consume_name("Expect variable name.")?;
+		self.consume(&Token::RightParen, "Expect ')' after parameter name.")?;
+		Ok((MethodKind::Binary, name, vec![param]))
+	    }
+	    _ => Err(self.error_at_current("Expect method definition.")),
+	}
+    }
+
+    // A parenthesized parameter list, "(a, b)".
+    fn parameter_list(&mut self) -> ParseResult<Vec<Ident>> {
+	self.consume(&Token::LeftParen, "Expect '(' before parameters.")?;
+	let params = self.parameters_until(&Token::RightParen)?;
+	self.consume(&Token::RightParen, "Expect ')' after parameters.")?;
+	Ok(params)
+    }
+
+    fn parameters_until(&mut self, close: &Token) -> ParseResult<Vec<Ident>> {
+	if self.check(close) {
+	    return Ok(Vec::new());
+	}
+	self.parameters()
+    }
+
+    // One or more parameters, separated by commas.
+    fn parameters(&mut self) -> ParseResult<Vec<Ident>> {
+	let mut params = Vec::new();
+	loop {
+	    self.ignore_newlines()?;
+	    let param = self.consume_name("Expect variable name.")?;
+	    if params.len() == MAX_PARAMETERS {
+		return Err(self.error_at_previous(format!(
+		    "Methods cannot have more than {} parameters.",
+		    MAX_PARAMETERS
+		)));
+	    }
+	    params.push(param);
+	    if !self.match_token(&Token::Comma)? {
+		break;
+	    }
+	}
+	self.ignore_newlines()?;
+	Ok(params)
+    }
+
+    // Parses the rest of a body after its "{".
+    fn finish_body(&mut self) -> ParseResult<Body> {
+	if self.match_token(&Token::RightBrace)? {
+	    return Ok(Body::Block(Vec::new()));
+	}
+	// A "{" with no newline after it is a single-expression body.
+	if !self.match_line()? {
+	    let expr = self.expression()?;
+	    self.consume(&Token::RightBrace, "Expect '}' at end of block.")?;
+	    return Ok(Body::Expr(expr));
+	}
+	if self.match_token(&Token::RightBrace)? {
+	    return Ok(Body::Block(Vec::new()));
+	}
+	// As in wren_c, a body cut off by the end of the file is missing a
+	// statement before it is missing its "}".
+	let mut statements = Vec::new();
+	loop {
+	    statements.push(self.definition()?);
+	    self.consume_line("Expect newline after statement.")?;
+	    if self.check(&Token::RightBrace) || self.check(&Token::Eof) {
+		break;
+	    }
+	}
+	self.consume(&Token::RightBrace, "Expect '}' at end of block.")?;
+	Ok(Body::Block(statements))
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+	self.nest()?;
+	let stmt = self.nested_statement();
+	self.depth -= 1;
+	stmt
+    }
+
+    fn nested_statement(&mut self) -> ParseResult<Stmt> {
+	let start = self.current.span;
+	let kind = if self.match_token(&Token::Break)? {
+	    StmtKind::Break
+	} else if self.match_token(&Token::Continue)? {
+	    StmtKind::Continue
+	} else if self.match_token(&Token::For)? {
+	    self.consume(&Token::LeftParen, "Expect '(' after 'for'.")?;
+	    let variable = self.consume_name("Expect for loop variable name.")?;
+	    self.consume(&Token::In, "Expect 'in' after loop variable.")?;
+	    self.ignore_newlines()?;
+	    let sequence = self.expression()?;
+	    self.consume(&Token::RightParen, "Expect ')' after loop expression.")?;
+	    let body = Box::new(self.statement()?);
+	    StmtKind::For {
+		variable,
+		sequence,
+		body,
+	    }
+	} else if self.match_token(&Token::If)? {
+	    self.consume(&Token::LeftParen, "Expect '(' after 'if'.")?;
+	    self.ignore_newlines()?;
+	    let condition = self.expression()?;
+	    self.consume(&Token::RightParen, "Expect ')' after if condition.")?;
+	    let then_branch = Box::new(self.statement()?);
+	    let else_branch = if self.match_token(&Token::Else)? {
+		Some(Box::new(self.statement()?))
+	    } else {
+		None
+	    };
+	    StmtKind::If {
+		condition,
+		then_branch,
+		else_branch,
+	    }
+	} else if self.match_token(&Token::Return)? {
+	    if self.check(&Token::Line) {
+		StmtKind::Return(None)
+	    } else {
+		StmtKind::Return(Some(self.expression()?))
+	    }
+	} else if self.match_token(&Token::While)? {
+	    self.consume(&Token::LeftParen, "Expect '(' after 'while'.")?;
+	    self.ignore_newlines()?;
+	    let condition = self.expression()?;
+	    self.consume(&Token::RightParen, "Expect ')' after while condition.")?;
+	    let body = Box::new(self.statement()?);
+	    StmtKind::While { condition, body }
+	} else if self.match_token(&Token::LeftBrace)? {
+	    match self.finish_body()? {
+		Body::Expr(expr) => {
+		    let span = expr.span;
+		    StmtKind::Block(vec![Stmt {
+			kind: StmtKind::Expr(expr),
+			span,
+		    }])
+		}
+		Body::Block(statements) => StmtKind::Block(statements),
+	    }
+	} else {
+	    StmtKind::Expr(self.expression()?)
+	};
+	Ok(Stmt {
+	    kind,
+	    span: self.span_from(start),
+	})
+    }
+
+    // Expressions.
+
+    pub fn expression(&mut self) -> ParseResult<Expr> {
+	self.parse_precedence(Precedence::Lowest)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> ParseResult<Expr> {
+	self.nest()?;
+	let expr = self.nested_precedence(precedence);
+	self.depth -= 1;
+	expr
+    }
+
+    fn nested_precedence(&mut self, precedence: Precedence) -> ParseResult<Expr> {
+	// Like wren_c, skip a token that can't start an expression, and go on
+	// as if it were one.
+	if !starts_expression(&self.current.token) {
+	    self.recover(self.error_at_current("Expected expression."))?;
+	    self.advance()?;
+	    return Ok(Expr {
+		kind: ExprKind::Null,
+		span: self.previous.span,
+	    });
+	}
+	self.advance()?;
+	let can_assign = precedence <= Precedence::Conditional;
+	let expr = self.prefix(can_assign)?;
+	let depth = self.depth;
+	let expr = self.infixes(expr, precedence, can_assign);
+	self.depth = depth;
+	expr
+    }
+
+    // Applies the operators, calls and subscripts that follow `expr`. Each
+    // makes what came before it an operand, as parentheses would, so they
+    // count towards `max_nesting` even though they are parsed in a loop.
+    fn infixes(&mut self, mut expr: Expr, precedence: Precedence, can_assign: bool) -> ParseResult<Expr> {
+	while precedence <= infix_precedence(&self.current.token) {
+	    self.nest()?;
+	    self.advance()?;
+	    expr = self.infix(expr, can_assign)?;
+	}
+	Ok(expr)
+    }
+
+    fn prefix(&mut self, can_assign: bool) -> ParseResult<Expr> {
+	let start = self.previous.span;
+	let token = self.previous.token.clone();
+	let kind = match token {
+	    Token::LeftParen => {
+		let expr = self.expression()?;
+		self.consume(&Token::RightParen, "Expect ')' after expression.")?;
+		return Ok(Expr {
+		    kind: expr.kind,
+		    span: self.span_from(start),
+		});
+	    }
+	    Token::LeftBracket => self.list()?,
+	    Token::LeftBrace => self.map()?,
+	    Token::Minus | Token::Bang | Token::Tilde => {
+		let op = match token {
+		    Token::Minus => UnaryOp::Neg,
+		    Token::Bang => UnaryOp::Not,
+		    _ => UnaryOp::BitNot,
+		};
+		self.ignore_newlines()?;
+		let operand = self.parse_precedence(Precedence::Unary.next())?;
+		ExprKind::Unary {
+		    op,
+		    operand: Box::new(operand),
+		}
+	    }
+	    Token::Null => ExprKind::Null,
+	    Token::True => ExprKind::Bool(true),
+	    Token::False => ExprKind::Bool(false),
+	    Token::This => ExprKind::This,
+	    Token::Number(n) => ExprKind::Num(n),
+	    // As in wren_c, the pieces of an interpolated string are strings
+	    // wherever they turn up.
+	    Token::String(s) | Token::InterpolationEnd(s) => ExprKind::String(s),
+	    Token::InterpolationStart(s) | Token::InterpolationPart(s) => self.interpolation(s)?,
+	    Token::Field(name) => ExprKind::Field(name),
+	    Token::StaticField(name) => ExprKind::StaticField(name),
+	    Token::Name(name) => {
+		let is_call = self.check(&Token::LeftParen) || self.check(&Token::LeftBrace);
+		if is_call && self.class_depth > 0 && is_local_name(&name) {
+		    let name = Ident { name, span: start };
+		    let (args, block) = self.call_arguments()?;
+		    self.allow_line_before_dot()?;
+		    ExprKind::Call {
+			receiver: None,
+			name,
+			args,
+			block,
+		    }
+		} else {
+		    ExprKind::Name(name)
+		}
+	    }
+	    Token::Super => self.super_call()?,
+	    _ => {
+		self.recover(self.error_at_previous("Expected expression."))?;
+		ExprKind::Null
+	    }
+	};
+	let expr = Expr {
+	    kind,
+	    span: self.span_from(start),
+	};
+	self.assignment(expr, can_assign)
+    }
+
+    fn infix(&mut self, left: Expr, can_assign: bool) -> ParseResult<Expr> {
+	let start = left.span;
+	let token = self.previous.token.clone();
+	let kind = match token {
+	    Token::Dot => {
+		self.ignore_newlines()?;
+		let name = self.consume_name("Expect method name after '.'.")?;
+		let (args, block) = self.call_arguments()?;
+		self.allow_line_before_dot()?;
+		ExprKind::Call {
+		    receiver: Some(Box::new(left)),
+		    name,
+		    args,
+		    block,
+		}
+	    }
+	    Token::LeftBracket => {
+		let args = self.arguments_until(&Token::RightBracket)?;
+		self.consume(&Token::RightBracket, "Expect ']' after arguments.")?;
+		self.allow_line_before_dot()?;
+		ExprKind::Subscript {
+		    receiver: Box::new(left),
+		    args,
+		}
+	    }
+	    Token::AmpAmp => {
+		self.ignore_newlines()?;
+		let right = self.parse_precedence(Precedence::LogicalAnd)?;
+		ExprKind::And(Box::new(left), Box::new(right))
+	    }
+	    Token::PipePipe => {
+		self.ignore_newlines()?;
+		let right = self.parse_precedence(Precedence::LogicalOr)?;
+		ExprKind::Or(Box::new(left), Box::new(right))
+	    }
+	    Token::Question => {
+		self.ignore_newlines()?;
+		let then_branch = self.parse_precedence(Precedence::Conditional)?;
+		self.ignore_newlines()?;
+		self.consume(
+		    &Token::Colon,
+		    "Expect ':' after then branch of conditional operator.",
+		)?;
+		self.ignore_newlines()?;
+		let else_branch = self.parse_precedence(Precedence::Assignment)?;
+		ExprKind::Conditional {
+		    condition: Box::new(left),
+		    then_branch: Box::new(then_branch),
+		    else_branch: Box::new(else_branch),
+		}
+	    }
+	    token => {
+		let op = binary_op(&token).expect("token has an infix precedence");
+		self.ignore_newlines()?;
+		let right = self.parse_precedence(infix_precedence(&token).next())?;
+		ExprKind::Binary {
+		    op,
+		    left: Box::new(left),
+		    right: Box::new(right),
+		}
+	    }
+	};
+	let expr = Expr {
+	    kind,
+	    span: self.span_from(start),
+	};
+	self.assignment(expr, can_assign)
+    }
+
+    // Wraps `target` in an assignment if it's followed by "=".
+    fn assignment(&mut self, target: Expr, can_assign: bool) -> ParseResult<Expr> {
+	if !self.check(&Token::Eq) {
+	    return Ok(target);
+	}
+	let assignable = match &target.kind {
+	    ExprKind::Name(_) | ExprKind::Field(_) | ExprKind::StaticField(_) => true,
+	    ExprKind::Subscript { .. } => true,
+	    ExprKind::Call { args, block, .. } | ExprKind::Super { args, block, .. } => {
+		args.is_none() && block.is_none()
+	    }
+	    _ => false,
+	};
+	// As in wren_c, the "=" is left for the caller, which expects
+	// something else there.
+	if !assignable || !can_assign {
+	    return Ok(target);
+	}
+	self.advance()?;
+	self.ignore_newlines()?;
+	let value = self.expression()?;
+	let span = target.span.to(value.span);
+	Ok(Expr {
+	    kind: ExprKind::Assign {
+		target: Box::new(target),
+		value: Box::new(value),
+	    },
+	    span,
+	})
+    }
+
+    fn super_call(&mut self) -> ParseResult<ExprKind> {
+	let name = if self.match_token(&Token::Dot)? {
+	    Some(self.consume_name("Expect method name after 'super.'.")?)
+	} else {
+	    None
+	};
+	let (args, block) = self.call_arguments()?;
+	self.allow_line_before_dot()?;
+	Ok(ExprKind::Super { name, args, block })
+    }
+
+    // The optional "(args)" and block argument after a method name.
+    fn call_arguments(&mut self) -> ParseResult<CallArguments> {
+	let args = if self.match_token(&Token::LeftParen)? {
+	    let args = self.arguments_until(&Token::RightParen)?;
+	    self.consume(&Token::RightParen, "Expect ')' after arguments.")?;
+	    Some(args)
+	} else {
+	    None
+	};
+	let block = if self.check(&Token::LeftBrace) {
+	    let start = self.current.span;
+	    self.advance()?;
+	    let params = if self.match_token(&Token::Pipe)? {
+		let params = self.parameters_until(&Token::Pipe)?;
+		self.consume(&Token::Pipe, "Expect '|' after function parameters.")?;
+		params
+	    } else {
+		Vec::new()
+	    };
+	    let body = self.finish_body()?;
+	    Some(Box::new(BlockArg {
+		params,
+		body,
+		span: self.span_from(start),
+	    }))
+	} else {
+	    None
+	};
+	let count = args.as_ref().map_or(0, Vec::len) + block.is_some() as usize;
+	if count > MAX_PARAMETERS {
+	    return Err(self.error_at_previous(format!(
+		"Methods cannot have more than {} parameters.",
+		MAX_PARAMETERS
+	    )));
+	}
+	Ok((args, block))
+    }
+
+    fn arguments_until(&mut self, close: &Token) -> ParseResult<Vec<Expr>> {
+	let mut args = Vec::new();
+	self.ignore_newlines()?;
+	if self.check(close) {
+	    return Ok(args);
+	}
+	loop {
+	    self.ignore_newlines()?;
+	    args.push(self.expression()?);
+	    if !self.match_token(&Token::Comma)? {
+		break;
+	    }
+	}
+	self.ignore_newlines()?;
+	Ok(args)
+    }
+
+    // Lets a method chain continue on the next line with a leading ".".
+    fn allow_line_before_dot(&mut self) -> ParseResult<()> {
+	if self.check(&Token::Line) && self.peek_next() == Some(&Token::Dot) {
+	    self.advance()?;
+	}
+	Ok(())
+    }
+
+    fn list(&mut self) -> ParseResult<ExprKind> {
+	let mut elements = Vec::new();
+	loop {
+	    self.ignore_newlines()?;
+	    // Allow a trailing comma.
+	    if self.check(&Token::RightBracket) {
+		break;
+	    }
+	    elements.push(self.expression()?);
+	    if !self.match_token(&Token::Comma)? {
+		break;
+	    }
+	}
+	self.ignore_newlines()?;
+	self.consume(&Token::RightBracket, "Expect ']' after list elements.")?;
+	Ok(ExprKind::List(elements))
+    }
+
+    fn map(&mut self) -> ParseResult<ExprKind> {
+	let mut entries = Vec::new();
+	loop {
+	    self.ignore_newlines()?;
+	    if self.check(&Token::RightBrace) {
+		break;
+	    }
+	    let key = self.parse_precedence(Precedence::Unary)?;
+	    self.consume(&Token::Colon, "Expect ':' after map key.")?;
+	    self.ignore_newlines()?;
+	    let value = self.expression()?;
+	    entries.push((key, value));
+	    if !self.match_token(&Token::Comma)? {
+		break;
+	    }
+	}
+	self.ignore_newlines()?;
+	self.consume(&Token::RightBrace, "Expect '}' after map entries.")?;
+	Ok(ExprKind::Map(entries))
+    }
+
+    fn interpolation(&mut self, first: String) -> ParseResult<ExprKind> {
+	let mut parts = vec![Expr {
+	    kind: ExprKind::String(first),
+	    span: self.previous.span,
+	}];
+	loop {
+	    self.ignore_newlines()?;
+	    parts.push(self.expression()?);
+	    self.ignore_newlines()?;
+	    let span = self.current.span;
+	    match self.current.token.clone() {
+		Token::InterpolationPart(s) => {
+		    self.advance()?;
+		    parts.push(Expr {
+			kind: ExprKind::String(s),
+			span,
+		    });
+		}
+		_ => break,
+	    }
+	}
+	// The trailing text, which wren_c takes from any string token.
+	let span = self.current.span;
+	let last = match self.trailing_string()? {
+	    Some(s) => s,
+	    None => {
+		self.recover(self.error_at_current("Expect end of string interpolation."))?;
+		// As `consume` does, skip the token in case the string follows.
+		self.advance()?;
+		self.trailing_string()?.unwrap_or_default()
+	    }
+	};
+	parts.push(Expr {
+	    kind: ExprKind::String(last),
+	    span,
+	});
+	Ok(ExprKind::Interpolation(parts))
+    }
+
+    // Takes the string or end of an interpolation next, if there is one.
+    fn trailing_string(&mut self) -> ParseResult<Option<String>> {
+	match self.current.token.clone() {
+	    Token::String(s) | Token::InterpolationEnd(s) => {
+		self.advance()?;
+		Ok(Some(s))
+	    }
+	    _ => Ok(None),
+	}
+    }
+}
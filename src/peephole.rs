@@ -0,0 +1,175 @@
+// A peephole pass the VM runs over each function as it loads it, fusing
+// common pairs of instructions into superinstructions so the dispatch loop
+// goes round fewer times. When their operands are numbers, they also do
+// arithmetic and comparisons themselves instead of calling `Num`'s methods,
+// which scripts can't replace.
+//
+// A superinstruction takes up the same bytes as the pair it replaces, so
+// jumps, lines and the scopes of locals are unchanged. Most leave their
+// second instruction in place, to run as usual when the operands aren't
+// numbers. Pairs are only fused within a line, so a debugger stepping by
+// line can't tell. One that replaces its second instruction is only used
+// when nothing jumps to that instruction.
+
+use alloc::vec::Vec;
+
+use crate::chunk::{Chunk, Code, Constant};
+use crate::compiler::SymbolTable;
+use crate::value::Value;
+
+/// An operator superinstructions apply themselves when both operands are
+/// numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NumOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+const NUM_OPS: [(NumOp, &str); 11] = [
+    (NumOp::Add, "+(_)"),
+    (NumOp::Subtract, "-(_)"),
+    (NumOp::Multiply, "*(_)"),
+    (NumOp::Divide, "/(_)"),
+    (NumOp::Modulo, "%(_)"),
+    (NumOp::Less, "<(_)"),
+    (NumOp::Greater, ">(_)"),
+    (NumOp::LessEqual, "<=(_)"),
+    (NumOp::GreaterEqual, ">=(_)"),
+    (NumOp::Equal, "==(_)"),
+    (NumOp::NotEqual, "!=(_)"),
+];
+
+impl NumOp {
+    /// What `Num`'s method for the operator returns for `a` and `b`.
+    pub(crate) fn apply(self, a: f64, b: f64) -> Value {
+	match self {
+	    NumOp::Add => Value::Num(a + b),
+	    NumOp::Subtract => Value::Num(a - b),
+	    NumOp::Multiply => Value::Num(a * b),
+	    NumOp::Divide => Value::Num(a / b),
+	    NumOp::Modulo => Value::Num(a % b),
+	    NumOp::Less => Value::Bool(a < b),
+	    NumOp::Greater => Value::Bool(a > b),
+	    NumOp::LessEqual => Value::Bool(a <= b),
+	    NumOp::GreaterEqual => Value::Bool(a >= b),
+	    NumOp::Equal => Value::Bool(a == b),
+	    NumOp::NotEqual => Value::Bool(a != b),
+	}
+    }
+}
+
+/// The operator of each method symbol, for those that have one, adding
+/// the operators' signatures to `methods`.
+pub(crate) fn num_ops(methods: &mut SymbolTable) -> Vec<Option<NumOp>> {
+    let mut ops = Vec::new();
+    for (op, signature) in NUM_OPS {
+	let symbol = methods.ensure(signature);
+	if ops.len() <= symbol {
+	    ops.resize(symbol + 1, None);
+	}
+	ops[symbol] = Some(op);
+    }
+    ops
+}
+
+/// Replaces pairs of instructions in `chunk` with superinstructions.
+pub(crate) fn fuse(chunk: &mut Chunk, num_ops: &[Option<NumOp>]) {
+    let starts = instruction_starts(chunk);
+    let mut is_target = vec![false; chunk.code.len() + 1];
+    for &offset in &starts {
+	if let Some(target) = jump_target(chunk, offset) {
+	    if let Some(is_target) = is_target.get_mut(target) {
+		*is_target = true;
+	    }
+	}
+    }
+
+    // Whether the instruction at `offset` calls one of the operators.
+    let calls_num_op = |chunk: &Chunk, offset: usize| {
+	chunk.code[offset] == Code::Call1 as u8
+	    && num_ops
+		.get(chunk.read_u16(offset + 1) as usize)
+		.is_some_and(|op| op.is_some())
+    };
+
+    let mut pairs = starts.windows(2);
+    while let Some(&[first, second]) = pairs.next() {
+	if chunk.lines[first] != chunk.lines[second] {
+	    continue;
+	}
+	let code = Code::from_u8(chunk.code[first]).expect("valid opcode");
+	match code {
+	    Code::Constant if calls_num_op(chunk, second) => {
+		chunk.code[first] = Code::ConstantCall1 as u8;
+	    }
+	    Code::LoadLocal0
+	    | Code::LoadLocal1
+	    | Code::LoadLocal2
+	    | Code::LoadLocal3
+	    | Code::LoadLocal4
+	    | Code::LoadLocal5
+	    | Code::LoadLocal6
+	    | Code::LoadLocal7
+	    | Code::LoadLocal8
+		if !is_target[second] && calls_num_op(chunk, second) =>
+	    {
+		// The slot takes the place of the call's opcode.
+		chunk.code[first] = Code::LoadLocalCall1 as u8;
+		chunk.code[second] = code as u8 - Code::LoadLocal0 as u8;
+		// The call is part of this instruction now.
+		pairs.next();
+	    }
+	    Code::Call1
+		if chunk.code[second] == Code::JumpIf as u8 && calls_num_op(chunk, first) =>
+	    {
+		chunk.code[first] = Code::Call1JumpIf as u8;
+	    }
+	    _ => {}
+	}
+    }
+}
+
+// The offset of each instruction in `chunk`.
+fn instruction_starts(chunk: &Chunk) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+	starts.push(offset);
+	let code = Code::from_u8(chunk.code[offset]).expect("valid opcode");
+	let mut next = offset + 1 + code.operand_bytes();
+	// Each upvalue a closure captures follows as a pair of bytes.
+	if code == Code::Closure {
+	    let constant = chunk.read_u16(offset + 1) as usize;
+	    if let Some(Constant::Fn(proto)) = chunk.constants.get(constant) {
+		next += 2 * proto.num_upvalues;
+	    }
+	}
+	if code == Code::End {
+	    break;
+	}
+	offset = next;
+    }
+    starts
+}
+
+// Where the instruction at `offset` jumps to, if it is a jump.
+fn jump_target(chunk: &Chunk, offset: usize) -> Option<usize> {
+    let code = Code::from_u8(chunk.code[offset])?;
+    let next = offset + 1 + code.operand_bytes();
+    match code {
+	Code::Jump | Code::JumpIf | Code::And | Code::Or => {
+	    Some(next + chunk.read_u16(offset + 1) as usize)
+	}
+	Code::Loop => next.checked_sub(chunk.read_u16(offset + 1) as usize),
+	_ => None,
+    }
+}
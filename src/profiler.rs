@@ -0,0 +1,242 @@
+// A profiler for Wren functions, built on the VM's debug hooks.
+//
+// Every call and return is an event. The time between two events is spent
+// in the calls that were running after the first, so it's counted as
+// exclusive time of the innermost one, inclusive time of each of them, and
+// time of the whole stack for flame graphs. Primitives and foreign methods
+// count towards the function that called them, as do functions of the core
+// library.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::debug::{DebugAction, DebugHook, PauseReason};
+use crate::value::{FnBody, ObjRef};
+use crate::vm::{Method, WrenVM};
+
+/// Records how often each function is called and how long it runs, while
+/// installed as the VM's debug hook.
+///
+/// Clones share their recording, so one can be installed while another
+/// reports on it.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    recording: Arc<Mutex<Recording>>,
+}
+
+/// The time spent in one function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub module: String,
+    /// The function's name, after its class's for methods.
+    pub function: String,
+    /// The line of the function's first instruction.
+    pub line: u32,
+    pub calls: u64,
+    /// Time spent in the function and the functions it called.
+    pub inclusive: Duration,
+    /// Time spent in the function itself.
+    pub exclusive: Duration,
+}
+
+impl FunctionProfile {
+    /// How the function is named in reports, such as `Foo.bar(_)
+    /// (main:3)`.
+    pub fn label(&self) -> String {
+	format!("{} ({}:{})", self.function, self.module, self.line)
+    }
+}
+
+/// What a `Profiler` recorded.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    /// Each function called, by exclusive time, longest first.
+    pub functions: Vec<FunctionProfile>,
+    /// The time spent with each stack of calls running, outermost first,
+    /// by index in `functions`.
+    pub stacks: Vec<(Vec<usize>, Duration)>,
+}
+
+impl ProfileReport {
+    /// The stacks in the collapsed format flame graph tools read: each
+    /// stack's labels joined by `;`, then its time in microseconds.
+    pub fn collapsed_stacks(&self) -> String {
+	let mut out = String::new();
+	for (stack, time) in &self.stacks {
+	    let labels: Vec<String> =
+		stack.iter().map(|&index| self.functions[index].label()).collect();
+	    out.push_str(&format!("{} {}\n", labels.join(";"), time.as_micros()));
+	}
+	out
+    }
+}
+
+/// A table of the functions, longest exclusive time first.
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	fn millis(time: Duration) -> String {
+	    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+	}
+	writeln!(f, "{:>10}  {:>12}  {:>12}  Function", "Calls", "Inclusive", "Exclusive")?;
+	for function in &self.functions {
+	    writeln!(
+		f,
+		"{:>10}  {:>12}  {:>12}  {}",
+		function.calls,
+		millis(function.inclusive),
+		millis(function.exclusive),
+		function.label()
+	    )?;
+	}
+	Ok(())
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+	Profiler::default()
+    }
+
+    /// What has been recorded so far.
+    pub fn report(&self) -> ProfileReport {
+	let recording = self.recording.lock().unwrap();
+	let functions = &recording.functions;
+	let mut order: Vec<usize> = (0..functions.len()).collect();
+	order.sort_by(|&a, &b| functions[b].exclusive.cmp(&functions[a].exclusive));
+	let mut positions = vec![0; order.len()];
+	for (position, &index) in order.iter().enumerate() {
+	    positions[index] = position;
+	}
+	let mut stacks: Vec<(Vec<usize>, Duration)> = recording
+	    .stacks
+	    .iter()
+	    .map(|(stack, &time)| (stack.iter().map(|&index| positions[index]).collect(), time))
+	    .collect();
+	stacks.sort();
+	ProfileReport {
+	    functions: order.iter().map(|&index| functions[index].clone()).collect(),
+	    stacks,
+	}
+    }
+
+    /// Forgets what has been recorded.
+    pub fn reset(&self) {
+	*self.recording.lock().unwrap() = Recording::default();
+    }
+}
+
+impl DebugHook for Profiler {
+    fn on_line(&mut self, _vm: &mut WrenVM, _reason: PauseReason) -> DebugAction {
+	DebugAction::Continue
+    }
+
+    fn on_call(&mut self, vm: &mut WrenVM) {
+	let mut recording = self.recording.lock().unwrap();
+	recording.record(vm, 0);
+	if let Some(&called) = recording.stack.last() {
+	    recording.functions[called].calls += 1;
+	}
+    }
+
+    fn on_return(&mut self, vm: &mut WrenVM) {
+	// The returning call is over once its last instruction runs.
+	self.recording.lock().unwrap().record(vm, 1);
+    }
+}
+
+#[derive(Default)]
+struct Recording {
+    functions: Vec<FunctionProfile>,
+    /// Each function's index in `functions`, by the address of its body.
+    /// The bodies are kept so that their addresses aren't reused.
+    indexes: HashMap<usize, usize>,
+    bodies: Vec<Arc<FnBody>>,
+    /// The calls running since the last event, outermost first.
+    stack: Vec<usize>,
+    /// When the last event happened.
+    since: Option<Instant>,
+    stacks: HashMap<Vec<usize>, Duration>,
+    /// The event at which each function's inclusive time was last counted,
+    /// so recursive calls count once.
+    counted: Vec<u64>,
+    events: u64,
+}
+
+impl Recording {
+    // Counts the time since the last event towards the calls running
+    // since, and takes the calls running now, leaving out the innermost
+    // `finished` ones.
+    fn record(&mut self, vm: &WrenVM, finished: usize) {
+	let now = Instant::now();
+	if let Some(since) = self.since {
+	    let time = now - since;
+	    self.events += 1;
+	    if let Some(&innermost) = self.stack.last() {
+		self.functions[innermost].exclusive += time;
+		match self.stacks.get_mut(&self.stack) {
+		    Some(total) => *total += time,
+		    None => {
+			self.stacks.insert(self.stack.clone(), time);
+		    }
+		}
+	    }
+	    for &index in &self.stack {
+		if self.counted[index] != self.events {
+		    self.counted[index] = self.events;
+		    self.functions[index].inclusive += time;
+		}
+	    }
+	}
+
+	let frames = vm.debug_call_frames();
+	self.stack.clear();
+	for (frame, _) in frames.iter().rev().take(frames.len().saturating_sub(finished)) {
+	    let index = self.function_index(vm, frame.closure);
+	    self.stack.push(index);
+	}
+	// Leave out the time spent recording.
+	self.since = Some(Instant::now());
+    }
+
+    fn function_index(&mut self, vm: &WrenVM, closure: ObjRef) -> usize {
+	let closure_obj = vm.heap.closure(closure);
+	let function = vm.heap.function(closure_obj.function);
+	if let Some(&index) = self.indexes.get(&(Arc::as_ptr(&function.body) as usize)) {
+	    return index;
+	}
+	// Methods are named after their class, but not the functions nested
+	// in them, which are bound to it too.
+	let is_method = |method: &Option<Method>| match method {
+	    Some(Method::Block(bound)) => *bound == closure,
+	    _ => false,
+	};
+	let class = closure_obj
+	    .class
+	    .map(|class| vm.heap.class(class))
+	    .filter(|class| class.methods.iter().any(is_method));
+	let name = match class {
+	    Some(class) => {
+		let class = class.name.strip_suffix(" metaclass").unwrap_or(&class.name);
+		format!("{}.{}", class, function.body.name)
+	    }
+	    None => function.body.name.clone(),
+	};
+	let index = self.functions.len();
+	self.functions.push(FunctionProfile {
+	    module: vm.heap.module(function.module).name.clone(),
+	    function: name,
+	    line: function.body.lines.first().copied().unwrap_or(0),
+	    calls: 0,
+	    inclusive: Duration::default(),
+	    exclusive: Duration::default(),
+	});
+	self.counted.push(0);
+	self.indexes.insert(Arc::as_ptr(&function.body) as usize, index);
+	self.bodies.push(Arc::clone(&function.body));
+	index
+    }
+}
@@ -0,0 +1,39 @@
+// The framing shared by the Debug Adapter and Language Server protocols:
+// each JSON message is preceded by a `Content-Length` header and a blank
+// line.
+
+use alloc::string::{String, ToString};
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+// Reads a message, or `None` at the end of the input.
+pub(crate) fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+	let mut header = String::new();
+	if input.read_line(&mut header)? == 0 {
+	    return Ok(None);
+	}
+	let header = header.trim_end();
+	if header.is_empty() {
+	    break;
+	}
+	if let Some((name, value)) = header.split_once(':') {
+	    if name.eq_ignore_ascii_case("Content-Length") {
+		length = value.trim().parse::<usize>().ok();
+	    }
+	}
+    }
+    let length = length
+	.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+pub(crate) fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()
+}
@@ -0,0 +1,639 @@
+// Conversion between Rust data and Wren values with serde, so the host
+// can put a struct in a slot, and read one back, in one call.
+//
+// Values map the way they do to JSON: numbers of every width are Nums,
+// sequences and tuples are Lists, and structs and maps are Maps, with the
+// field names as string keys. Enums are externally tagged: a unit
+// variant is its name, and any other is a Map from its name to its
+// content.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use std::fmt::Display;
+
+use serde::de::{
+
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::ser::{self, Serialize};
+
+use crate::api::api_error;
+use crate::core;
+use crate::error::WrenError;
+use crate::value::{Obj, ObjMap, ObjRef, Value};
+use crate::vm::WrenVM;
+
+impl ser::Error for WrenError {
+    fn custom<T: Display>(message: T) -> WrenError {
+	api_error(message.to_string())
+    }
+}
+
+impl de::Error for WrenError {
+    fn custom<T: Display>(message: T) -> WrenError {
+	api_error(message.to_string())
+    }
+}
+
+impl WrenVM {
+    /// Converts `value` to Wren values and stores the result in `slot`.
+    /// Integers too large for a double lose precision, and map keys must
+    /// convert to value types, such as strings and numbers.
+    pub fn set_slot_serialized<T: Serialize + ?Sized>(
+	&mut self,
+	slot: usize,
+	value: &T,
+    ) -> Result<(), WrenError> {
+	let value = value.serialize(Serializer { vm: self })?;
+	self.set_slot(slot, value);
+	Ok(())
+    }
+
+    /// Reads the value in `slot`, and the lists and maps it contains, as a
+    /// `T`.
+    pub fn get_slot_deserialized<T: DeserializeOwned>(&self, slot: usize) -> Result<T, WrenError> {
+	T::deserialize(Deserializer {
+	    vm: self,
+	    value: self.slot(slot),
+	})
+    }
+}
+
+// Serializing.
+
+struct Serializer<'a> {
+    vm: &'a mut WrenVM,
+}
+
+impl<'a> Serializer<'a> {
+    fn new_map(&mut self) -> ObjRef {
+	self.vm.heap.alloc(Obj::Map(ObjMap::default()))
+    }
+
+    // `{variant: content}`, for an enum variant with content.
+    fn tagged(&mut self, variant: &str, content: Value) -> Value {
+	let map = self.new_map();
+	let key = self.vm.new_string(variant);
+	core::map_set(self.vm, map, key, content);
+	Value::Obj(map)
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, WrenError> {
+	Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, WrenError> {
+	Ok(Value::Num(v as f64))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, WrenError> {
+	Ok(Value::Num(v as f64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, WrenError> {
+	Ok(Value::Num(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, WrenError> {
+	Ok(Value::Num(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, WrenError> {
+	Ok(self.vm.new_string(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, WrenError> {
+	Ok(self.vm.new_string(v))
+    }
+
+    // Bytes are a list of numbers, as a sequence of `u8` would be.
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, WrenError> {
+	let elements = v.iter().map(|&byte| Value::Num(byte.into())).collect();
+	Ok(self.vm.new_list(elements))
+    }
+
+    fn serialize_none(self) -> Result<Value, WrenError> {
+	Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, WrenError> {
+	value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, WrenError> {
+	Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, WrenError> {
+	Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+	self,
+	_name: &'static str,
+	_index: u32,
+	variant: &'static str,
+    ) -> Result<Value, WrenError> {
+	Ok(self.vm.new_string(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+	self,
+	_name: &'static str,
+	value: &T,
+    ) -> Result<Value, WrenError> {
+	value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+	mut self,
+	_name: &'static str,
+	_index: u32,
+	variant: &'static str,
+	value: &T,
+    ) -> Result<Value, WrenError> {
+	let content = value.serialize(Serializer { vm: self.vm })?;
+	Ok(self.tagged(variant, content))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'a>, WrenError> {
+	Ok(SeqSerializer {
+	    vm: self.vm,
+	    elements: Vec::with_capacity(len.unwrap_or(0)),
+	    variant: None,
+	})
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>, WrenError> {
+	self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+	self,
+	_name: &'static str,
+	len: usize,
+    ) -> Result<SeqSerializer<'a>, WrenError> {
+	self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+	self,
+	_name: &'static str,
+	_index: u32,
+	variant: &'static str,
+	len: usize,
+    ) -> Result<SeqSerializer<'a>, WrenError> {
+	Ok(SeqSerializer {
+	    vm: self.vm,
+	    elements: Vec::with_capacity(len),
+	    variant: Some(variant),
+	})
+    }
+
+    fn serialize_map(mut self, _len: Option<usize>) -> Result<MapSerializer<'a>, WrenError> {
+	let map = self.new_map();
+	Ok(MapSerializer {
+	    vm: self.vm,
+	    map,
+	    key: None,
+	    variant: None,
+	})
+    }
+
+    fn serialize_struct(
+	self,
+	_name: &'static str,
+	len: usize,
+    ) -> Result<MapSerializer<'a>, WrenError> {
+	self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+	self,
+	_name: &'static str,
+	_index: u32,
+	variant: &'static str,
+	len: usize,
+    ) -> Result<MapSerializer<'a>, WrenError> {
+	let mut serializer = self.serialize_map(Some(len))?;
+	serializer.variant = Some(variant);
+	Ok(serializer)
+    }
+}
+
+struct SeqSerializer<'a> {
+    vm: &'a mut WrenVM,
+    elements: Vec<Value>,
+    /// The variant to tag the list with, for a tuple variant.
+    variant: Option<&'static str>,
+}
+
+impl<'a> SeqSerializer<'a> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	let value = value.serialize(Serializer { vm: self.vm })?;
+	self.elements.push(value);
+	Ok(())
+    }
+
+    fn finish(self) -> Result<Value, WrenError> {
+	let list = self.vm.new_list(self.elements);
+	let mut serializer = Serializer { vm: self.vm };
+	Ok(match self.variant {
+	    Some(variant) => serializer.tagged(variant, list),
+	    None => list,
+	})
+    }
+}
+
+impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	self.push(value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	self.push(value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	self.push(value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	self.push(value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+struct MapSerializer<'a> {
+    vm: &'a mut WrenVM,
+    map: ObjRef,
+    /// The key of the value to be serialized next.
+    key: Option<Value>,
+    /// The variant to tag the map with, for a struct variant.
+    variant: Option<&'static str>,
+}
+
+impl<'a> MapSerializer<'a> {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Value, value: &T) -> Result<(), WrenError> {
+	let value = value.serialize(Serializer { vm: self.vm })?;
+	core::map_set(self.vm, self.map, key, value);
+	Ok(())
+    }
+
+    fn finish(self) -> Result<Value, WrenError> {
+	let mut serializer = Serializer { vm: self.vm };
+	Ok(match self.variant {
+	    Some(variant) => serializer.tagged(variant, Value::Obj(self.map)),
+	    None => Value::Obj(self.map),
+	})
+    }
+}
+
+impl<'a> ser::SerializeMap for MapSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), WrenError> {
+	let key = key.serialize(Serializer { vm: self.vm })?;
+	if !core::is_valid_key(self.vm, key) {
+	    return Err(api_error("Map keys must be value types."));
+	}
+	self.key = Some(key);
+	Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WrenError> {
+	let key = self.key.take().expect("serialize_key before serialize_value");
+	self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for MapSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+	&mut self,
+	key: &'static str,
+	value: &T,
+    ) -> Result<(), WrenError> {
+	let key = self.vm.new_string(key);
+	self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for MapSerializer<'a> {
+    type Ok = Value;
+    type Error = WrenError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+	&mut self,
+	key: &'static str,
+	value: &T,
+    ) -> Result<(), WrenError> {
+	let key = self.vm.new_string(key);
+	self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, WrenError> {
+	self.finish()
+    }
+}
+
+// Deserializing.
+
+#[derive(Clone, Copy)]
+struct Deserializer<'a> {
+    vm: &'a WrenVM,
+    value: Value,
+}
+
+impl<'a> Deserializer<'a> {
+    fn with(self, value: Value) -> Deserializer<'a> {
+	Deserializer { vm: self.vm, value }
+    }
+
+    fn invalid_type(self, expected: &dyn de::Expected) -> WrenError {
+	let unexpected = match self.value {
+	    Value::Null => de::Unexpected::Unit,
+	    Value::Bool(value) => de::Unexpected::Bool(value),
+	    Value::Num(value) => de::Unexpected::Float(value),
+	    Value::Obj(obj) => match self.vm.heap.get(obj) {
+		Obj::String(string) => de::Unexpected::Str(&string.value),
+		Obj::List(_) => de::Unexpected::Seq,
+		Obj::Map(_) => de::Unexpected::Map,
+		_ => de::Unexpected::Other("an object"),
+	    },
+	};
+	de::Error::invalid_type(unexpected, expected)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = WrenError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, WrenError> {
+	match self.value {
+	    Value::Null => visitor.visit_unit(),
+	    Value::Bool(value) => visitor.visit_bool(value),
+	    // Whole numbers are integers, so they can be read as any integer
+	    // type they fit in.
+	    Value::Num(value)
+		if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 =>
+	    {
+		visitor.visit_i64(value as i64)
+	    }
+	    Value::Num(value) => visitor.visit_f64(value),
+	    Value::Obj(obj) => match self.vm.heap.get(obj) {
+		Obj::String(string) => visitor.visit_str(&string.value),
+		Obj::List(list) => visitor.visit_seq(Elements {
+		    deserializer: self,
+		    elements: list.elements.iter(),
+		}),
+		Obj::Map(map) => visitor.visit_map(Entries {
+		    deserializer: self,
+		    entries: Box::new(map.iter()),
+		    value: None,
+		}),
+		_ => Err(self.invalid_type(&visitor)),
+	    },
+	}
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, WrenError> {
+	if self.value.is_null() {
+	    visitor.visit_none()
+	} else {
+	    visitor.visit_some(self)
+	}
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+	self,
+	_name: &'static str,
+	visitor: V,
+    ) -> Result<V::Value, WrenError> {
+	visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+	self,
+	_name: &'static str,
+	_variants: &'static [&'static str],
+	visitor: V,
+    ) -> Result<V::Value, WrenError> {
+	let Some(obj) = self.value.as_obj() else {
+	    return Err(self.invalid_type(&"a string or map"));
+	};
+	match self.vm.heap.get(obj) {
+	    Obj::String(_) => visitor.visit_enum(Variant {
+		deserializer: self,
+		content: None,
+	    }),
+	    Obj::Map(map) => {
+		let mut entries = map.iter();
+		match (entries.next(), entries.next()) {
+		    (Some((variant, content)), None) => visitor.visit_enum(Variant {
+			deserializer: self.with(variant),
+			content: Some(self.with(content)),
+		    }),
+		    _ => Err(de::Error::invalid_length(map.count, &"a map with one entry")),
+		}
+	    }
+	    _ => Err(self.invalid_type(&"a string or map")),
+	}
+    }
+
+    serde::forward_to_deserialize_any! {
+	bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+	bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+	identifier ignored_any
+    }
+}
+
+struct Elements<'a> {
+    deserializer: Deserializer<'a>,
+    elements: std::slice::Iter<'a, Value>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Elements<'a> {
+    type Error = WrenError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+	&mut self,
+	seed: T,
+    ) -> Result<Option<T::Value>, WrenError> {
+	match self.elements.next() {
+	    Some(&element) => seed.deserialize(self.deserializer.with(element)).map(Some),
+	    None => Ok(None),
+	}
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+	Some(self.elements.len())
+    }
+}
+
+struct Entries<'a> {
+    deserializer: Deserializer<'a>,
+    entries: Box<dyn Iterator<Item = (Value, Value)> + 'a>,
+    /// The value of the entry whose key was read last.
+    value: Option<Value>,
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = WrenError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+	&mut self,
+	seed: K,
+    ) -> Result<Option<K::Value>, WrenError> {
+	match self.entries.next() {
+	    Some((key, value)) => {
+		self.value = Some(value);
+		seed.deserialize(self.deserializer.with(key)).map(Some)
+	    }
+	    None => Ok(None),
+	}
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, WrenError> {
+	let value = self.value.take().expect("next_key_seed before next_value_seed");
+	seed.deserialize(self.deserializer.with(value))
+    }
+}
+
+// An enum variant: its name, and its content unless it's a unit variant.
+struct Variant<'a> {
+    deserializer: Deserializer<'a>,
+    content: Option<Deserializer<'a>>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = WrenError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), WrenError> {
+	let variant = seed.deserialize(self.deserializer)?;
+	Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = WrenError;
+
+    fn unit_variant(self) -> Result<(), WrenError> {
+	match self.content {
+	    None => Ok(()),
+	    Some(content) => de::Deserialize::deserialize(content),
+	}
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, WrenError> {
+	match self.content {
+	    Some(content) => seed.deserialize(content),
+	    None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant")),
+	}
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+	self,
+	_len: usize,
+	visitor: V,
+    ) -> Result<V::Value, WrenError> {
+	match self.content {
+	    Some(content) => de::Deserializer::deserialize_seq(content, visitor),
+	    None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"tuple variant")),
+	}
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+	self,
+	_fields: &'static [&'static str],
+	visitor: V,
+    ) -> Result<V::Value, WrenError> {
+	match self.content {
+	    Some(content) => de::Deserializer::deserialize_map(content, visitor),
+	    None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"struct variant")),
+	}
+    }
+}
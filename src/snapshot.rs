@@ -0,0 +1,1085 @@
+// Snapshots of a VM's state, which a host can save, such as with a saved
+// game, and restore later into a VM of the same build.
+//
+// A snapshot holds every module and everything reachable from them:
+// classes, functions and their code, fibers, and the values in variables.
+// Objects are numbered in the order they are found and refer to each
+// other by number. Restoring allocates them afresh and swaps them in for
+// the VM's modules, so what the host holds handles to is left as it was.
+//
+// Functions of the host can't be saved. Foreign methods are saved by the
+// module, class and signature they were bound to, and foreign classes by
+// their module and name, to be looked up in the restoring VM. Primitives
+// are saved by their position among the core library's. Foreign objects
+// hold data of the host's, so a VM with any can't be saved, except for
+// those of the optional modules' classes, such as `Random`, whose data is
+// known and saved as it is.
+//
+// The layout, with integers as LEB128 varints as in compiled modules:
+//
+//     magic "\0wrs", version (u16, little-endian), build, method names,
+//     object kinds, objects, modules, core module, core classes, checksum
+//
+// where a value is tagged as null, false, true, a number (its bits as a
+// little-endian u64) or an object, and an optional object is 0 for none or
+// its number plus one. The checksum is the CRC-32 of everything before it.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use ::core::convert::TryFrom;
+use ::core::{iter, mem};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::bytecode::{self, ConstantKind, Reader, Unverified, Writer};
+use crate::compiler::{ModuleScope, Signature};
+use crate::core;
+use crate::error::WrenError;
+use crate::lexer::Span;
+use crate::value::*;
+#[cfg(feature = "random")]
+use crate::optional::random;
+use crate::vm::{
+    CoreClasses, FinalizerFn, ForeignClassMethods, HostData, Method, Primitive, WrenVM,
+};
+
+/// The first bytes of every snapshot.
+pub const MAGIC: &[u8; 4] = b"\0wrs";
+
+/// The version of the format. Snapshots of any other version are rejected.
+pub const VERSION: u16 = 2;
+
+const KIND_STRING: u8 = 0;
+const KIND_LIST: u8 = 1;
+const KIND_MAP: u8 = 2;
+const KIND_RANGE: u8 = 3;
+const KIND_FN: u8 = 4;
+const KIND_CLOSURE: u8 = 5;
+const KIND_UPVALUE: u8 = 6;
+const KIND_CLASS: u8 = 7;
+const KIND_INSTANCE: u8 = 8;
+const KIND_FIBER: u8 = 9;
+const KIND_MODULE: u8 = 10;
+const KIND_FOREIGN: u8 = 11;
+
+const VALUE_NULL: u8 = 0;
+const VALUE_FALSE: u8 = 1;
+const VALUE_TRUE: u8 = 2;
+const VALUE_NUM: u8 = 3;
+const VALUE_OBJ: u8 = 4;
+
+const METHOD_NONE: u8 = 0;
+const METHOD_PRIMITIVE: u8 = 1;
+const METHOD_FUNCTION_CALL: u8 = 2;
+const METHOD_BLOCK: u8 = 3;
+const METHOD_FOREIGN: u8 = 4;
+
+// The foreign classes of the optional modules, whose objects' data is saved.
+#[cfg(feature = "random")]
+const FOREIGN_RANDOM: u8 = 0;
+
+const ENTRY_EMPTY: u8 = 0;
+const ENTRY_TOMBSTONE: u8 = 1;
+const ENTRY_FULL: u8 = 2;
+
+// The key a foreign method is bound under: module, class, whether it is
+// static, and signature.
+type ForeignKey = (String, String, bool, String);
+
+impl WrenVM {
+    /// Saves every module, and everything reachable from them such as
+    /// fibers and the values of variables, as bytes that `restore` can
+    /// load into a VM of the same build.
+    ///
+    /// Values the host holds handles to are only saved if a module can
+    /// reach them. Fails while the VM is running code, if a foreign object
+    /// is reachable other than a `Random`, with an error naming its class,
+    /// or if a fiber is sleeping in `Timer.sleep`.
+    pub fn snapshot(&self) -> Result<Vec<u8>, WrenError> {
+	if self.fiber.is_some() {
+	    return Err(snapshot_error("Cannot take a snapshot while the VM is running."));
+	}
+	#[cfg(feature = "timer")]
+	if !self.timers.is_empty() {
+	    return Err(snapshot_error("Cannot take a snapshot while a fiber is sleeping."));
+	}
+	Saver::new(self).save().map_err(|message| WrenError::Snapshot { message })
+    }
+
+    /// Replaces every module with those of a snapshot from `snapshot`,
+    /// along with what they reach.
+    ///
+    /// The VM should be set up as the one the snapshot was taken in was,
+    /// with the same foreign methods and classes bound. Handles taken
+    /// before keep referring to the values they did, and fibers sleeping
+    /// in `Timer.sleep` are forgotten.
+    ///
+    /// A snapshot that was changed since it was taken is rejected by its
+    /// checksum. The kinds of its objects, the references between them and
+    /// the slots its code and fibers use are checked too, with nothing left
+    /// behind if that fails. Code edited with care to keep its checksum
+    /// right and pass a value of the wrong type can still crash the VM.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), WrenError> {
+	if self.fiber.is_some() {
+	    return Err(snapshot_error("Cannot restore a snapshot while the VM is running."));
+	}
+	let restored = Loader::new(self, bytes)
+	    .load()
+	    .map_err(|message| WrenError::Snapshot { message })?;
+	for name in &restored.methods[self.methods.len()..] {
+	    self.methods.add(name);
+	}
+	self.modules = restored.modules;
+	self.core_module = restored.core_module;
+	self.core = restored.core;
+	self.last_module = None;
+	#[cfg(feature = "timer")]
+	for (_, fiber) in mem::take(&mut self.timers) {
+	    self.release_handle(fiber);
+	}
+	Ok(())
+    }
+}
+
+/// Whether `bytes` start like a snapshot.
+pub fn is_snapshot(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+fn snapshot_error(message: &str) -> WrenError {
+    WrenError::Snapshot {
+	message: message.to_string(),
+    }
+}
+
+// What has to match between the VM that took a snapshot and the one that
+// restores it: the crate's version, and whether code has superinstructions.
+fn build() -> String {
+    let mut build = env!("CARGO_PKG_VERSION").to_string();
+    if cfg!(feature = "superinstructions") {
+	build.push_str("+superinstructions");
+    }
+    build
+}
+
+// The core classes, in the order they are saved.
+fn core_classes(core: &CoreClasses) -> [ObjRef; 11] {
+    [
+	core.object,
+	core.class,
+	core.bool,
+	core.null,
+	core.num,
+	core.string,
+	core.list,
+	core.map,
+	core.range,
+	core.function,
+	core.fiber,
+    ]
+}
+
+struct Saver<'a> {
+    vm: &'a WrenVM,
+    out: Writer,
+    // Each object's number, and the objects in order.
+    numbers: HashMap<ObjRef, usize>,
+    objects: Vec<ObjRef>,
+    // The position of each primitive, by address.
+    primitives: HashMap<usize, usize>,
+    // A key each foreign method is bound under, by the address of its
+    // function or the index of its closure, and likewise for the foreign
+    // classes by the address of their allocator.
+    foreign_methods: HashMap<(bool, usize), &'a ForeignKey>,
+    foreign_classes: HashMap<usize, &'a (String, String)>,
+}
+
+impl<'a> Saver<'a> {
+    fn new(vm: &'a WrenVM) -> Saver<'a> {
+	let primitives = core::primitives().enumerate();
+	let mut saver = Saver {
+	    vm,
+	    out: Writer::default(),
+	    numbers: HashMap::new(),
+	    objects: Vec::new(),
+	    primitives: primitives.map(|(index, (_, primitive))| (primitive as usize, index)).collect(),
+	    foreign_methods: HashMap::new(),
+	    foreign_classes: HashMap::new(),
+	};
+	// A function bound under several keys is saved under the least, so
+	// snapshots of the same state are the same.
+	for (key, method) in &vm.foreign_methods {
+	    let id = match *method {
+		Method::Foreign(function) => (false, function as usize),
+		Method::ForeignClosure(index) => (true, index),
+		_ => continue,
+	    };
+	    let least = saver.foreign_methods.entry(id).or_insert(key);
+	    if key < *least {
+		*least = key;
+	    }
+	}
+	for (key, methods) in &vm.foreign_classes {
+	    let least = saver.foreign_classes.entry(methods.allocate as usize).or_insert(key);
+	    if key < *least {
+		*least = key;
+	    }
+	}
+	saver
+    }
+
+    fn save(mut self) -> Result<Vec<u8>, String> {
+	let mut modules: Vec<(&String, ObjRef)> =
+	    self.vm.modules.iter().map(|(name, &module)| (name, module)).collect();
+	modules.sort();
+	let roots = modules.iter().map(|&(_, module)| module);
+	let core = core_classes(&self.vm.core);
+	for root in roots.chain([self.vm.core_module]).chain(core) {
+	    self.number(root);
+	}
+	// Number everything reachable, breadth first.
+	let mut children = Vec::new();
+	let mut next = 0;
+	while next < self.objects.len() {
+	    let obj = self.objects[next];
+	    next += 1;
+	    match self.vm.heap.get(obj) {
+		Obj::Foreign(foreign) if foreign_data(&*foreign.data).is_none() => {
+		    let class = &self.vm.heap.class(foreign.class).name;
+		    return Err(format!(
+			"Cannot take a snapshot of a foreign object of class {}.",
+			class
+		    ));
+		}
+		_ => {}
+	    }
+	    self.vm.heap.references(obj, &mut children);
+	    for child in children.drain(..) {
+		if let Some(child) = child.as_obj() {
+		    self.number(child);
+		}
+	    }
+	}
+
+	self.out.bytes.extend_from_slice(MAGIC);
+	self.out.bytes.extend_from_slice(&VERSION.to_le_bytes());
+	self.out.string(&build());
+	let methods: Vec<String> = self.vm.methods.iter().map(str::to_string).collect();
+	self.out.strings(&methods);
+	self.out.varint(self.objects.len() as u64);
+	for index in 0..self.objects.len() {
+	    let kind = kind(self.vm.heap.get(self.objects[index]));
+	    self.out.bytes.push(kind);
+	}
+	for index in 0..self.objects.len() {
+	    self.object(self.objects[index])?;
+	}
+	self.out.varint(modules.len() as u64);
+	for (name, module) in modules {
+	    self.out.string(name);
+	    self.reference(module);
+	}
+	self.reference(self.vm.core_module);
+	for class in core {
+	    self.reference(class);
+	}
+	self.out.seal();
+	Ok(self.out.bytes)
+    }
+
+    fn number(&mut self, obj: ObjRef) {
+	if !self.numbers.contains_key(&obj) {
+	    self.numbers.insert(obj, self.objects.len());
+	    self.objects.push(obj);
+	}
+    }
+
+    fn reference(&mut self, obj: ObjRef) {
+	self.out.varint(self.numbers[&obj] as u64);
+    }
+
+    fn optional(&mut self, obj: Option<ObjRef>) {
+	match obj {
+	    Some(obj) => self.out.varint(self.numbers[&obj] as u64 + 1),
+	    None => self.out.varint(0),
+	}
+    }
+
+    fn value(&mut self, value: Value) {
+	match value {
+	    Value::Null => self.out.bytes.push(VALUE_NULL),
+	    Value::Bool(false) => self.out.bytes.push(VALUE_FALSE),
+	    Value::Bool(true) => self.out.bytes.push(VALUE_TRUE),
+	    Value::Num(n) => {
+		self.out.bytes.push(VALUE_NUM);
+		self.out.f64(n);
+	    }
+	    Value::Obj(obj) => {
+		self.out.bytes.push(VALUE_OBJ);
+		self.reference(obj);
+	    }
+	}
+    }
+
+    fn values(&mut self, values: &[Value]) {
+	self.out.varint(values.len() as u64);
+	for &value in values {
+	    self.value(value);
+	}
+    }
+
+    fn references(&mut self, objs: &[ObjRef]) {
+	self.out.varint(objs.len() as u64);
+	for &obj in objs {
+	    self.reference(obj);
+	}
+    }
+
+    fn object(&mut self, obj: ObjRef) -> Result<(), String> {
+	match self.vm.heap.get(obj) {
+	    Obj::String(string) => self.out.string(&string.value),
+	    Obj::List(list) => self.values(&list.elements),
+	    Obj::Map(map) => {
+		// The table is saved as it is, since keys hash the same in
+		// any VM.
+		self.out.varint(map.entries.len() as u64);
+		for &entry in &map.entries {
+		    match entry {
+			MapEntry::Empty => self.out.bytes.push(ENTRY_EMPTY),
+			MapEntry::Tombstone => self.out.bytes.push(ENTRY_TOMBSTONE),
+			MapEntry::Full { key, value } => {
+			    self.out.bytes.push(ENTRY_FULL);
+			    self.value(key);
+			    self.value(value);
+			}
+		    }
+		}
+	    }
+	    Obj::Range(range) => {
+		self.out.f64(range.from);
+		self.out.f64(range.to);
+		self.out.bytes.push(range.is_inclusive as u8);
+	    }
+	    Obj::Fn(function) => {
+		self.reference(function.module);
+		let body = &function.body;
+		self.out.string(&body.name);
+		self.out.varint(body.arity as u64);
+		self.out.varint(body.num_upvalues as u64);
+		self.out.varint(body.code.len() as u64);
+		self.out.bytes.extend_from_slice(&body.code);
+		self.values(&body.constants);
+		self.out.lines(&body.lines);
+		self.out.locals(&body.locals);
+	    }
+	    Obj::Closure(closure) => {
+		self.reference(closure.function);
+		self.references(&closure.upvalues);
+		self.optional(closure.class);
+		self.out.varint(closure.field_base as u64);
+	    }
+	    Obj::Upvalue(ObjUpvalue::Open { fiber, slot }) => {
+		self.out.bytes.push(0);
+		self.reference(*fiber);
+		self.out.varint(*slot as u64);
+	    }
+	    Obj::Upvalue(ObjUpvalue::Closed(value)) => {
+		self.out.bytes.push(1);
+		self.value(*value);
+	    }
+	    Obj::Class(class) => {
+		self.out.string(&class.name);
+		self.optional(class.class);
+		self.optional(class.superclass);
+		self.out.varint(class.num_fields as u64);
+		self.out.varint(class.methods.len() as u64);
+		for &method in &class.methods {
+		    self.method(method)?;
+		}
+		match class.foreign {
+		    Some(methods) => {
+			let (module, name) = self.foreign_classes[&(methods.allocate as usize)];
+			self.out.bytes.push(1);
+			self.out.string(module);
+			self.out.string(name);
+		    }
+		    None => self.out.bytes.push(0),
+		}
+		self.value(class.attributes);
+	    }
+	    Obj::Instance(instance) => {
+		self.reference(instance.class);
+		self.values(&instance.fields);
+	    }
+	    Obj::Foreign(foreign) => {
+		let (class, words) =
+		    foreign_data(&*foreign.data).expect("the host's are rejected when numbered");
+		self.reference(foreign.class);
+		self.out.bytes.push(class);
+		for word in words {
+		    self.out.varint(u64::from(word));
+		}
+	    }
+	    Obj::Fiber(fiber) => {
+		self.values(&fiber.stack);
+		self.out.varint(fiber.frames.len() as u64);
+		for frame in &fiber.frames {
+		    self.reference(frame.closure);
+		    self.out.varint(frame.ip as u64);
+		    self.out.varint(frame.base as u64);
+		}
+		self.references(&fiber.open_upvalues);
+		self.optional(fiber.caller);
+		self.value(fiber.error);
+		let state = match fiber.state {
+		    FiberState::Root => 0,
+		    FiberState::Try => 1,
+		    FiberState::Other => 2,
+		};
+		self.out.bytes.push(state);
+		self.out.bytes.push(fiber.awaiting_host as u8);
+	    }
+	    Obj::Module(module) => {
+		self.out.string(&module.name);
+		self.values(&module.variables);
+		let scope = &module.scope;
+		self.out.varint(scope.len() as u64);
+		for index in 0..scope.len() {
+		    self.out.string(scope.name(index));
+		    match scope.first_use(index) {
+			Some(span) => {
+			    self.out.bytes.push(1);
+			    self.out.varint(span.start as u64);
+			    self.out.varint(span.end as u64);
+			    self.out.varint(span.line as u64);
+			    self.out.varint(span.column as u64);
+			}
+			None => self.out.bytes.push(0),
+		    }
+		}
+	    }
+	}
+	Ok(())
+    }
+
+    fn method(&mut self, method: Option<Method>) -> Result<(), String> {
+	let (is_closure, id) = match method {
+	    None => {
+		self.out.bytes.push(METHOD_NONE);
+		return Ok(());
+	    }
+	    Some(Method::Primitive(primitive)) => {
+		self.out.bytes.push(METHOD_PRIMITIVE);
+		self.out.varint(self.primitives[&(primitive as usize)] as u64);
+		return Ok(());
+	    }
+	    Some(Method::FunctionCall) => {
+		self.out.bytes.push(METHOD_FUNCTION_CALL);
+		return Ok(());
+	    }
+	    Some(Method::Block(closure)) => {
+		self.out.bytes.push(METHOD_BLOCK);
+		self.reference(closure);
+		return Ok(());
+	    }
+	    Some(Method::Foreign(function)) => (false, function as usize),
+	    Some(Method::ForeignClosure(index)) => (true, index),
+	};
+	let (module, class, is_static, signature) = self
+	    .foreign_methods
+	    .get(&(is_closure, id))
+	    .ok_or_else(|| "Cannot take a snapshot of an unbound foreign method.".to_string())?;
+	self.out.bytes.push(METHOD_FOREIGN);
+	self.out.string(module);
+	self.out.string(class);
+	self.out.bytes.push(*is_static as u8);
+	self.out.string(signature);
+	Ok(())
+    }
+}
+
+fn kind(obj: &Obj) -> u8 {
+    match obj {
+	Obj::String(_) => KIND_STRING,
+	Obj::List(_) => KIND_LIST,
+	Obj::Map(_) => KIND_MAP,
+	Obj::Range(_) => KIND_RANGE,
+	Obj::Fn(_) => KIND_FN,
+	Obj::Closure(_) => KIND_CLOSURE,
+	Obj::Upvalue(_) => KIND_UPVALUE,
+	Obj::Class(_) => KIND_CLASS,
+	Obj::Instance(_) => KIND_INSTANCE,
+	Obj::Foreign(_) => KIND_FOREIGN,
+	Obj::Fiber(_) => KIND_FIBER,
+	Obj::Module(_) => KIND_MODULE,
+    }
+}
+
+// Which of the optional modules' foreign classes an object with `data` is
+// of, and the words of its data, or None for the host's foreign objects.
+#[cfg_attr(not(feature = "random"), allow(unused_variables))]
+fn foreign_data(data: &HostData) -> Option<(u8, Vec<u32>)> {
+    #[cfg(feature = "random")]
+    if let Some((state, index)) = random::state(data) {
+	let mut words = state.to_vec();
+	words.push(index as u32);
+	return Some((FOREIGN_RANDOM, words));
+    }
+    None
+}
+
+// What a snapshot replaces in the VM that restores it.
+struct Restored {
+    methods: Vec<String>,
+    modules: HashMap<String, ObjRef>,
+    core_module: ObjRef,
+    core: CoreClasses,
+}
+
+struct Loader<'a, 'b> {
+    vm: &'a mut WrenVM,
+    reader: Reader<'b>,
+    // The kind of each object, and where it has been allocated.
+    kinds: Vec<u8>,
+    objects: Vec<ObjRef>,
+    // The signature of each method symbol.
+    methods: Vec<String>,
+    // Each primitive, and the signatures each is bound to by address.
+    primitives: Vec<Primitive>,
+    signatures: HashSet<(usize, &'static str)>,
+}
+
+impl<'a, 'b> Loader<'a, 'b> {
+    fn new(vm: &'a mut WrenVM, bytes: &'b [u8]) -> Loader<'a, 'b> {
+	Loader {
+	    vm,
+	    reader: Reader::new(bytes, "snapshot"),
+	    kinds: Vec::new(),
+	    objects: Vec::new(),
+	    methods: Vec::new(),
+	    primitives: core::primitives().map(|(_, primitive)| primitive).collect(),
+	    signatures: core::primitives()
+		.map(|(signature, primitive)| (primitive as usize, signature))
+		.collect(),
+	}
+    }
+
+    // Reads the snapshot, freeing the objects allocated for it if it turns
+    // out to be invalid.
+    fn load(mut self) -> Result<Restored, String> {
+	let restored = self.read();
+	if restored.is_err() {
+	    self.vm.heap.free(&self.objects);
+	}
+	restored
+    }
+
+    fn read(&mut self) -> Result<Restored, String> {
+	if !matches!(self.reader.take(MAGIC.len()), Ok(magic) if magic == MAGIC) {
+	    return Err("Not a Wren snapshot.".to_string());
+	}
+	let version = u16::from_le_bytes([self.reader.byte()?, self.reader.byte()?]);
+	if version != VERSION {
+	    return Err(format!(
+		"Snapshot has version {}, but only version {} is supported.",
+		version, VERSION
+	    ));
+	}
+	self.reader.unseal()?;
+	let snapshot_build = self.reader.string()?;
+	if snapshot_build != build() {
+	    return Err(format!(
+		"Snapshot was taken by build {}, not this build {}.",
+		snapshot_build,
+		build()
+	    ));
+	}
+	// Code refers to methods by symbol, so the snapshot's must start with
+	// this VM's.
+	self.methods = self.reader.strings()?;
+	let (ours, methods) = (&self.vm.methods, &self.methods);
+	if methods.len() < ours.len() || !ours.iter().zip(methods).all(|(a, b)| a == b) {
+	    return Err("Snapshot doesn't have the methods of this VM.".to_string());
+	}
+
+	let count = self.reader.length()?;
+	for _ in 0..count {
+	    match self.reader.byte()? {
+		kind @ KIND_STRING..=KIND_FOREIGN => self.kinds.push(kind),
+		_ => return Err(self.reader.invalid("object kind")),
+	    }
+	}
+	// Objects refer to each other in any order, so they are all allocated
+	// before any is read.
+	let placeholder = ObjRange {
+	    from: 0.0,
+	    to: 0.0,
+	    is_inclusive: false,
+	};
+	for _ in 0..count {
+	    let obj = self.vm.heap.alloc(Obj::Range(placeholder));
+	    self.objects.push(obj);
+	}
+	for index in 0..count {
+	    let obj = self.object(self.kinds[index])?;
+	    self.vm.heap.replace(self.objects[index], obj);
+	}
+	self.check_objects()?;
+
+	let mut modules = HashMap::new();
+	for _ in 0..self.reader.length()? {
+	    let name = self.reader.string()?;
+	    let module = self.reference(KIND_MODULE)?;
+	    modules.insert(name, module);
+	}
+	let core_module = self.reference(KIND_MODULE)?;
+	let mut core = [ObjRef::default(); 11];
+	for class in &mut core {
+	    *class = self.reference(KIND_CLASS)?;
+	}
+	let [object, class, bool, null, num, string, list, map, range, function, fiber] = core;
+	if !self.reader.is_at_end() {
+	    return Err("Unexpected bytes after the snapshot.".to_string());
+	}
+	Ok(Restored {
+	    methods: mem::take(&mut self.methods),
+	    modules,
+	    core_module,
+	    core: CoreClasses {
+		object,
+		class,
+		bool,
+		null,
+		num,
+		string,
+		list,
+		map,
+		range,
+		function,
+		fiber,
+	    },
+	})
+    }
+
+    // Checks what the VM indexes without checking, across objects that were
+    // each read whole: that functions' code stays within what it refers
+    // to, that closures have their function's upvalues and their class the
+    // fields it uses, that methods take the arguments of their signature,
+    // that instances have their class's fields, and that fibers' frames and
+    // open upvalues are within their stacks.
+    fn check_objects(&self) -> Result<(), String> {
+	let heap = &self.vm.heap;
+	let within_stack = |fiber: ObjRef, slot: usize| slot < heap.fiber(fiber).stack.len();
+	let mut verified = HashMap::new();
+	for &obj in &self.objects {
+	    if let Obj::Fn(function) = heap.get(obj) {
+		let body = &function.body;
+		let constants = body.constants.iter().map(|&constant| match heap.as_str(constant) {
+		    Some(_) => ConstantKind::String,
+		    None => match constant.as_obj().map(|obj| heap.get(obj)) {
+			Some(Obj::Fn(nested)) => ConstantKind::Fn(nested.body.num_upvalues),
+			_ => ConstantKind::Other,
+		    },
+		});
+		let function = bytecode::verify(&Unverified {
+		    name: &body.name,
+		    arity: body.arity,
+		    num_upvalues: body.num_upvalues,
+		    code: &body.code,
+		    constants: constants.collect(),
+		    variables: heap.module(function.module).variables.len(),
+		    methods: self.methods.len(),
+		})?;
+		verified.insert(obj, function);
+	    }
+	}
+	for &obj in &self.objects {
+	    let (valid, what) = match heap.get(obj) {
+		Obj::Closure(closure) => {
+		    let function = heap.function(closure.function);
+		    let num_fields = closure.field_base + verified[&closure.function].num_fields;
+		    let has_fields = match closure.class {
+			Some(class) => num_fields <= heap.class(class).num_fields,
+			None => num_fields == 0,
+		    };
+		    (closure.upvalues.len() == function.body.num_upvalues && has_fields, "closure")
+		}
+		Obj::Class(class) => {
+		    let superclass_fields =
+			class.superclass.map_or(0, |superclass| heap.class(superclass).num_fields);
+		    let methods = class.methods.iter().enumerate().all(|(symbol, method)| match method {
+			Some(Method::Block(closure)) => {
+			    let closure = heap.closure(*closure);
+			    let arity = heap.function(closure.function).body.arity;
+			    let signature = Signature::parse(&self.methods[symbol]);
+			    signature.is_ok_and(|signature| signature.arity == arity)
+				&& closure.class.is_some_and(|bound| self.is_ancestor(bound, obj))
+			}
+			_ => true,
+		    });
+		    (class.num_fields >= superclass_fields && methods, "class")
+		}
+		Obj::Instance(instance) => {
+		    (instance.fields.len() == heap.class(instance.class).num_fields, "instance")
+		}
+		Obj::Upvalue(ObjUpvalue::Open { fiber, slot }) => (within_stack(*fiber, *slot), "upvalue"),
+		// Only `Random`s are read, whose class must be the one bound to
+		// their methods.
+		#[cfg(feature = "random")]
+		Obj::Foreign(foreign) => {
+		    let methods = heap.class(foreign.class).foreign;
+		    let allocate = random::CLASS.allocate as usize;
+		    (methods.is_some_and(|methods| methods.allocate as usize == allocate), "Random")
+		}
+		Obj::Fiber(fiber) => {
+		    // The slots each frame has when it resumes, which must be at
+		    // least what its code expects there: those up to the next
+		    // frame's result, or up to the top.
+		    let frames = fiber.frames.iter().enumerate().all(|(index, frame)| {
+			let function = heap.closure(frame.closure).function;
+			let mut available = match fiber.frames.get(index + 1) {
+			    Some(next) => next.base + 1,
+			    None => fiber.stack.len(),
+			};
+			// A fiber that hasn't started is passed its parameter.
+			if fiber.frames.len() == 1 && frame.ip == 0 {
+			    let arity = heap.function(function).body.arity;
+			    available += arity;
+			    if arity > 1 {
+				return false;
+			    }
+			}
+			let height = verified[&function].heights.get(frame.ip).copied().flatten();
+			within_stack(obj, frame.base)
+			    && height.is_some_and(|height| frame.base + height <= available)
+		    });
+		    let open = fiber.open_upvalues.iter().all(|&upvalue| {
+			matches!(heap.upvalue(upvalue), ObjUpvalue::Open { fiber, .. } if *fiber == obj)
+		    });
+		    (frames && open, "fiber")
+		}
+		_ => (true, ""),
+	    };
+	    if !valid {
+		return Err(self.reader.invalid(what));
+	    }
+	}
+	Ok(())
+    }
+
+    // Whether `ancestor` is `class` or one of its superclasses.
+    fn is_ancestor(&self, ancestor: ObjRef, class: ObjRef) -> bool {
+	let heap = &self.vm.heap;
+	// A cycle of superclasses is cut short by the number of classes.
+	let classes = iter::successors(Some(class), |&class| heap.class(class).superclass);
+	classes.take(self.objects.len()).any(|class| class == ancestor)
+    }
+
+    // An object of one of `kinds`, or of any kind if `kinds` is empty.
+    fn reference_of(&mut self, kinds: &[u8]) -> Result<ObjRef, String> {
+	let number = self.reader.number()?;
+	match self.kinds.get(number) {
+	    Some(kind) if kinds.is_empty() || kinds.contains(kind) => Ok(self.objects[number]),
+	    _ => Err(self.reader.invalid("object reference")),
+	}
+    }
+
+    fn reference(&mut self, kind: u8) -> Result<ObjRef, String> {
+	self.reference_of(&[kind])
+    }
+
+    fn optional(&mut self, kind: u8) -> Result<Option<ObjRef>, String> {
+	match self.reader.number()? {
+	    0 => Ok(None),
+	    number => match self.kinds.get(number - 1) {
+		Some(&found) if found == kind => Ok(Some(self.objects[number - 1])),
+		_ => Err(self.reader.invalid("object reference")),
+	    },
+	}
+    }
+
+    fn references(&mut self, kind: u8) -> Result<Vec<ObjRef>, String> {
+	let count = self.reader.length()?;
+	(0..count).map(|_| self.reference(kind)).collect()
+    }
+
+    // A value whose object, if it is one, is of one of `kinds`, or of any
+    // kind if `kinds` is empty.
+    fn value_of(&mut self, kinds: &[u8]) -> Result<Value, String> {
+	Ok(match self.reader.byte()? {
+	    VALUE_NULL => Value::Null,
+	    VALUE_FALSE => Value::Bool(false),
+	    VALUE_TRUE => Value::Bool(true),
+	    VALUE_NUM => Value::Num(self.reader.f64()?),
+	    VALUE_OBJ => Value::Obj(self.reference_of(kinds)?),
+	    _ => return Err(self.reader.invalid("value")),
+	})
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+	self.value_of(&[])
+    }
+
+    fn values(&mut self) -> Result<Vec<Value>, String> {
+	let count = self.reader.length()?;
+	(0..count).map(|_| self.value()).collect()
+    }
+
+    fn flag(&mut self) -> Result<bool, String> {
+	match self.reader.byte()? {
+	    0 => Ok(false),
+	    1 => Ok(true),
+	    _ => Err(self.reader.invalid("flag")),
+	}
+    }
+
+    fn object(&mut self, kind: u8) -> Result<Obj, String> {
+	Ok(match kind {
+	    KIND_STRING => Obj::String(ObjString::new(self.reader.string()?)),
+	    KIND_LIST => Obj::List(ObjList {
+		elements: self.values()?,
+	    }),
+	    KIND_MAP => {
+		let mut map = ObjMap::default();
+		for _ in 0..self.reader.length()? {
+		    let entry = match self.reader.byte()? {
+			ENTRY_EMPTY => MapEntry::Empty,
+			ENTRY_TOMBSTONE => MapEntry::Tombstone,
+			ENTRY_FULL => {
+			    map.count += 1;
+			    MapEntry::Full {
+				key: self.value_of(&[KIND_STRING, KIND_RANGE, KIND_CLASS])?,
+				value: self.value()?,
+			    }
+			}
+			_ => return Err(self.reader.invalid("map entry")),
+		    };
+		    map.entries.push(entry);
+		}
+		Obj::Map(map)
+	    }
+	    KIND_RANGE => Obj::Range(ObjRange {
+		from: self.reader.f64()?,
+		to: self.reader.f64()?,
+		is_inclusive: self.flag()?,
+	    }),
+	    KIND_FN => {
+		let module = self.reference(KIND_MODULE)?;
+		let name = self.reader.string()?;
+		let arity = self.reader.number()?;
+		let num_upvalues = self.reader.number()?;
+		let code_length = self.reader.length()?;
+		let code = self.reader.take(code_length)?.to_vec();
+		let constants = self.values()?;
+		let lines = self.reader.lines(&name, code.len())?;
+		let locals = self.reader.locals()?;
+		if lines.len() != code.len() {
+		    return Err(format!("Function '{}' doesn't have a line for each byte.", name));
+		}
+		Obj::Fn(ObjFn {
+		    body: Arc::new(FnBody {
+			name,
+			arity,
+			num_upvalues,
+			code,
+			constants,
+			lines,
+			locals,
+		    }),
+		    module,
+		})
+	    }
+	    KIND_CLOSURE => Obj::Closure(ObjClosure {
+		function: self.reference(KIND_FN)?,
+		upvalues: self.references(KIND_UPVALUE)?,
+		class: self.optional(KIND_CLASS)?,
+		field_base: self.reader.number()?,
+	    }),
+	    KIND_UPVALUE => Obj::Upvalue(match self.flag()? {
+		false => ObjUpvalue::Open {
+		    fiber: self.reference(KIND_FIBER)?,
+		    slot: self.reader.number()?,
+		},
+		true => ObjUpvalue::Closed(self.value()?),
+	    }),
+	    KIND_CLASS => {
+		let name = self.reader.string()?;
+		let class = self.optional(KIND_CLASS)?;
+		let superclass = self.optional(KIND_CLASS)?;
+		let num_fields = self.reader.number()?;
+		let mut methods = Vec::new();
+		for symbol in 0..self.reader.length()? {
+		    methods.push(self.method(symbol)?);
+		}
+		let foreign = match self.flag()? {
+		    true => Some(self.foreign_class()?),
+		    false => None,
+		};
+		Obj::Class(ObjClass {
+		    name,
+		    class,
+		    superclass,
+		    num_fields,
+		    methods,
+		    foreign,
+		    attributes: self.value()?,
+		})
+	    }
+	    KIND_INSTANCE => Obj::Instance(ObjInstance {
+		class: self.reference(KIND_CLASS)?,
+		fields: self.values()?,
+	    }),
+	    KIND_FIBER => {
+		let stack = self.values()?;
+		let mut frames = Vec::new();
+		for _ in 0..self.reader.length()? {
+		    frames.push(CallFrame {
+			closure: self.reference(KIND_CLOSURE)?,
+			ip: self.reader.number()?,
+			base: self.reader.number()?,
+		    });
+		}
+		Obj::Fiber(ObjFiber {
+		    stack,
+		    frames,
+		    open_upvalues: self.references(KIND_UPVALUE)?,
+		    caller: self.optional(KIND_FIBER)?,
+		    error: self.value()?,
+		    state: match self.reader.byte()? {
+			0 => FiberState::Root,
+			1 => FiberState::Try,
+			2 => FiberState::Other,
+			_ => return Err(self.reader.invalid("fiber state")),
+		    },
+		    awaiting_host: self.flag()?,
+		})
+	    }
+	    KIND_MODULE => {
+		let name = self.reader.string()?;
+		let variables = self.values()?;
+		let mut scope = ModuleScope::new();
+		for index in 0..self.reader.length()? {
+		    let variable = self.reader.string()?;
+		    let defined = match self.flag()? {
+			true => {
+			    let span = Span::new(
+				self.reader.number()?,
+				self.reader.number()?,
+				self.number_u32()?,
+				self.number_u32()?,
+			    );
+			    scope.declare_implicit(&variable, span)
+			}
+			false => scope.define(&variable),
+		    };
+		    if defined != Ok(index) {
+			return Err(self.reader.invalid("module variable"));
+		    }
+		}
+		if scope.len() != variables.len() {
+		    return Err(self.reader.invalid("module"));
+		}
+		Obj::Module(ObjModule {
+		    name,
+		    variables,
+		    scope,
+		})
+	    }
+	    KIND_FOREIGN => {
+		let class = self.reference(KIND_CLASS)?;
+		let (data, finalize) = self.foreign_data()?;
+		Obj::Foreign(ObjForeign {
+		    class,
+		    data,
+		    finalize,
+		})
+	    }
+	    _ => unreachable!("kinds are checked when read"),
+	})
+    }
+
+    // The data of a foreign object of one of the optional modules' classes,
+    // and the finalizer it goes with.
+    fn foreign_data(&mut self) -> Result<(Box<HostData>, Option<FinalizerFn>), String> {
+	match self.reader.byte()? {
+	    #[cfg(feature = "random")]
+	    FOREIGN_RANDOM => {
+		let mut state = [0; 16];
+		for word in &mut state {
+		    *word = self.number_u32()?;
+		}
+		let index = self.reader.number()?;
+		let data = random::restore(state, index);
+		Ok((data.ok_or_else(|| self.reader.invalid("Random"))?, random::CLASS.finalize))
+	    }
+	    _ => Err(self.reader.invalid("foreign object")),
+	}
+    }
+
+    fn number_u32(&mut self) -> Result<u32, String> {
+	let number = self.reader.varint()?;
+	u32::try_from(number).map_err(|_| self.reader.invalid("integer"))
+    }
+
+    // The method bound to `symbol`, which primitives and foreign methods
+    // must have been bound to by their signature, as they expect its
+    // arguments.
+    fn method(&mut self, symbol: usize) -> Result<Option<Method>, String> {
+	let signature = self.methods.get(symbol).cloned();
+	Ok(Some(match self.reader.byte()? {
+	    METHOD_NONE => return Ok(None),
+	    METHOD_PRIMITIVE => {
+		let index = self.reader.number()?;
+		let primitive = self.primitives.get(index).copied();
+		match (primitive, signature) {
+		    (Some(primitive), Some(signature))
+			if self.signatures.contains(&(primitive as usize, signature.as_str())) =>
+		    {
+			Method::Primitive(primitive)
+		    }
+		    _ => return Err(self.reader.invalid("primitive")),
+		}
+	    }
+	    METHOD_FUNCTION_CALL => Method::FunctionCall,
+	    METHOD_BLOCK => Method::Block(self.reference(KIND_CLOSURE)?),
+	    METHOD_FOREIGN => {
+		let key = (
+		    self.reader.string()?,
+		    self.reader.string()?,
+		    self.flag()?,
+		    self.reader.string()?,
+		);
+		if Some(&key.3) != signature.as_ref() {
+		    return Err(self.reader.invalid("foreign method"));
+		}
+		match self.vm.foreign_methods.get(&key) {
+		    Some(&method) => method,
+		    None => {
+			let (module, class, is_static, signature) = key;
+			return Err(format!(
+			    "Could not find foreign method '{}' for class {}{} in module '{}'.",
+			    signature,
+			    class,
+			    if is_static { " metaclass" } else { "" },
+			    module
+			));
+		    }
+		}
+	    }
+	    _ => return Err(self.reader.invalid("method")),
+	}))
+    }
+
+    fn foreign_class(&mut self) -> Result<ForeignClassMethods, String> {
+	let key = (self.reader.string()?, self.reader.string()?);
+	match self.vm.foreign_classes.get(&key) {
+	    Some(&methods) => Ok(methods),
+	    None => {
+		let (module, class) = key;
+		Err(format!("Could not find foreign class '{}' in module '{}'.", class, module))
+	    }
+	}
+    }
+}
@@ -0,0 +1,359 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::chunk::LocalName;
+use crate::compiler::ModuleScope;
+use crate::vm::{FinalizerFn, ForeignClassMethods, HostData, Method};
+
+/// A reference to an object on the VM's heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ObjRef(pub(crate) u32);
+
+impl ObjRef {
+    pub fn index(self) -> usize {
+	self.0 as usize
+    }
+}
+
+/// A Wren value. Null, booleans and numbers are stored inline, everything
+/// else lives on the heap.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    /// Only `false` and `null` are falsy.
+    pub fn is_falsy(self) -> bool {
+	matches!(self, Value::Null | Value::Bool(false))
+    }
+
+    pub fn is_null(self) -> bool {
+	matches!(self, Value::Null)
+    }
+
+    pub fn is_bool(self) -> bool {
+	matches!(self, Value::Bool(_))
+    }
+
+    pub fn is_num(self) -> bool {
+	matches!(self, Value::Num(_))
+    }
+
+    /// Whether the value is a reference to a heap object, rather than
+    /// stored inline.
+    pub fn is_obj(self) -> bool {
+	matches!(self, Value::Obj(_))
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+	match self {
+	    Value::Bool(b) => Some(b),
+	    _ => None,
+	}
+    }
+
+    pub fn as_num(self) -> Option<f64> {
+	match self {
+	    Value::Num(n) => Some(n),
+	    _ => None,
+	}
+    }
+
+    pub fn as_obj(self) -> Option<ObjRef> {
+	match self {
+	    Value::Obj(obj) => Some(obj),
+	    _ => None,
+	}
+    }
+
+    /// Identity: the same number, or the same heap object.
+    pub fn same(self, other: Value) -> bool {
+	match (self, other) {
+	    (Value::Null, Value::Null) => true,
+	    (Value::Bool(a), Value::Bool(b)) => a == b,
+	    (Value::Num(a), Value::Num(b)) => a == b,
+	    (Value::Obj(a), Value::Obj(b)) => a == b,
+	    _ => false,
+	}
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+	Value::Bool(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+	Value::Num(value)
+    }
+}
+
+impl From<ObjRef> for Value {
+    fn from(obj: ObjRef) -> Value {
+	Value::Obj(obj)
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjString {
+    pub value: String,
+    pub hash: u32,
+}
+
+impl ObjString {
+    pub fn new(value: String) -> ObjString {
+	let hash = hash_string(&value);
+	ObjString { value, hash }
+    }
+}
+
+/// FNV-1a, the hash wren_c uses for strings.
+pub fn hash_string(value: &str) -> u32 {
+    let mut hash: u32 = 2166136261;
+    for byte in value.bytes() {
+	hash ^= byte as u32;
+	hash = hash.wrapping_mul(16777619);
+    }
+    hash
+}
+
+#[derive(Debug, Default)]
+pub struct ObjList {
+    pub elements: Vec<Value>,
+}
+
+/// A slot in a map's hash table.
+#[derive(Debug, Clone, Copy)]
+pub enum MapEntry {
+    Empty,
+    /// A removed entry, which lookups have to probe past.
+    Tombstone,
+    Full { key: Value, value: Value },
+}
+
+/// A hash table with open addressing and linear probing.
+#[derive(Debug, Default)]
+pub struct ObjMap {
+    pub entries: Vec<MapEntry>,
+    /// The number of full entries.
+    pub count: usize,
+}
+
+impl ObjMap {
+    /// The full entries' keys and values, in table order.
+    pub fn iter(&self) -> impl Iterator<Item = (Value, Value)> + '_ {
+	self.entries.iter().filter_map(|entry| match *entry {
+	    MapEntry::Full { key, value } => Some((key, value)),
+	    _ => None,
+	})
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ObjRange {
+    pub from: f64,
+    pub to: f64,
+    pub is_inclusive: bool,
+}
+
+/// The executable part of a compiled function, shared by every closure
+/// created from it.
+#[derive(Debug, Default)]
+pub struct FnBody {
+    pub name: String,
+    pub arity: usize,
+    pub num_upvalues: usize,
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub lines: Vec<u32>,
+    pub locals: Vec<LocalName>,
+}
+
+#[derive(Debug)]
+pub struct ObjFn {
+    pub body: Arc<FnBody>,
+    pub module: ObjRef,
+}
+
+#[derive(Debug)]
+pub struct ObjClosure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+    /// For methods and the functions nested in them, the class the method
+    /// is bound to. `super` calls look up its superclass.
+    pub class: Option<ObjRef>,
+    /// Where the fields of `class` start in an instance, after those of
+    /// its superclasses.
+    pub field_base: usize,
+}
+
+impl ObjClosure {
+    pub fn new(function: ObjRef) -> ObjClosure {
+	ObjClosure {
+	    function,
+	    upvalues: Vec::new(),
+	    class: None,
+	    field_base: 0,
+	}
+    }
+}
+
+pub struct ObjClass {
+    pub name: String,
+    /// The class of this class: its metaclass. Only unset while the core
+    /// classes are being wired together.
+    pub class: Option<ObjRef>,
+    pub superclass: Option<ObjRef>,
+    pub num_fields: usize,
+    /// Methods indexed by method symbol, including inherited ones.
+    pub methods: Vec<Option<Method>>,
+    /// How to create and destroy instances of a foreign class.
+    pub foreign: Option<ForeignClassMethods>,
+    /// The `ClassAttributes` holding the class's runtime attributes, or
+    /// null if it has none.
+    pub attributes: Value,
+}
+
+impl ObjClass {
+    pub fn method(&self, symbol: usize) -> Option<Method> {
+	self.methods.get(symbol).copied().flatten()
+    }
+}
+
+impl fmt::Debug for ObjClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "ObjClass({})", self.name)
+    }
+}
+
+/// A variable captured by a closure. It refers to the variable's stack
+/// slot while the variable is in scope, and holds the value itself once
+/// the variable has gone out of scope.
+#[derive(Debug, Clone, Copy)]
+pub enum ObjUpvalue {
+    Open { fiber: ObjRef, slot: usize },
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct ObjInstance {
+    pub class: ObjRef,
+    pub fields: Vec<Value>,
+}
+
+/// An instance of a foreign class, holding data owned by the host.
+pub struct ObjForeign {
+    pub class: ObjRef,
+    pub data: Box<HostData>,
+    pub finalize: Option<FinalizerFn>,
+}
+
+impl fmt::Debug for ObjForeign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	f.debug_struct("ObjForeign").field("class", &self.class).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CallFrame {
+    pub closure: ObjRef,
+    /// Index of the next instruction to execute. Only up to date for
+    /// frames other than the one currently running.
+    pub ip: usize,
+    /// Stack index of slot zero: the receiver or function being called.
+    pub base: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FiberState {
+    /// The fiber running a module's top-level code, which can't be called.
+    Root,
+    /// Run by `try`, so a runtime error returns to the caller instead of
+    /// aborting it too.
+    Try,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct ObjFiber {
+    /// While the fiber is running, its stack and frames are moved into the
+    /// VM and these are left empty.
+    pub stack: Vec<Value>,
+    pub frames: Vec<CallFrame>,
+    /// Upvalues still pointing into the stack, ordered by slot.
+    pub open_upvalues: Vec<ObjRef>,
+    /// The fiber that ran this one with `call` or `try`, and to which it
+    /// returns when it finishes or yields.
+    pub caller: Option<ObjRef>,
+    /// The runtime error that aborted the fiber, or null.
+    pub error: Value,
+    pub state: FiberState,
+    /// Whether a foreign method suspended the fiber with `suspend_fiber`,
+    /// and it is waiting for the host to call `resume_fiber`.
+    pub awaiting_host: bool,
+}
+
+impl ObjFiber {
+    pub fn new(closure: ObjRef) -> ObjFiber {
+	ObjFiber::with_buffers(closure, vec![Value::Obj(closure)], Vec::with_capacity(1))
+    }
+
+    /// A fiber that runs `closure` from the start, with `stack` holding its
+    /// receiver and arguments, and `frames` an empty buffer for its calls.
+    pub fn with_buffers(
+	closure: ObjRef,
+	stack: Vec<Value>,
+	mut frames: Vec<CallFrame>,
+    ) -> ObjFiber {
+	frames.push(CallFrame {
+	    closure,
+	    ip: 0,
+	    base: 0,
+	});
+	ObjFiber {
+	    stack,
+	    frames,
+	    open_upvalues: Vec::new(),
+	    caller: None,
+	    error: Value::Null,
+	    state: FiberState::Other,
+	    awaiting_host: false,
+	}
+    }
+
+    pub fn has_error(&self) -> bool {
+	!self.error.is_null()
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjModule {
+    pub name: String,
+    pub variables: Vec<Value>,
+    pub scope: ModuleScope,
+}
+
+#[derive(Debug)]
+pub enum Obj {
+    String(ObjString),
+    List(ObjList),
+    Map(ObjMap),
+    Range(ObjRange),
+    Fn(ObjFn),
+    Closure(ObjClosure),
+    Upvalue(ObjUpvalue),
+    Class(ObjClass),
+    Instance(ObjInstance),
+    Foreign(ObjForeign),
+    Fiber(ObjFiber),
+    Module(ObjModule),
+}
@@ -0,0 +1,508 @@
+// Traversals of the syntax tree `parser::parse` returns, for tools outside
+// the crate that work from source: `Visitor` looks at each node in turn,
+// and `Fold` rebuilds the tree, replacing the nodes it chooses to.
+//
+// Each method of the traits visits one kind of node, and by default goes
+// on to that node's children through the function of the same name, so an
+// implementation only overrides the nodes it cares about. Children are
+// visited in the order they appear in the source.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::ast::*;
+
+/// Visits the nodes of a syntax tree, borrowing them for `'a`.
+pub trait Visitor<'a> {
+    fn visit_module(&mut self, module: &'a Module) {
+	walk_module(self, module);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+	walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+	walk_expr(self, expr);
+    }
+
+    fn visit_class(&mut self, class: &'a ClassDecl) {
+	walk_class(self, class);
+    }
+
+    fn visit_method(&mut self, method: &'a Method) {
+	walk_method(self, method);
+    }
+
+    fn visit_body(&mut self, body: &'a Body) {
+	walk_body(self, body);
+    }
+
+    fn visit_block_arg(&mut self, block: &'a BlockArg) {
+	walk_block_arg(self, block);
+    }
+
+    fn visit_attribute(&mut self, attribute: &'a Attribute) {
+	walk_attribute(self, attribute);
+    }
+
+    /// Visits a name written in a declaration, call or attribute. Names
+    /// used as variables are `ExprKind::Name` expressions instead.
+    fn visit_ident(&mut self, _ident: &'a Ident) {}
+}
+
+pub fn walk_module<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, module: &'a Module) {
+    for stmt in &module.statements {
+	visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, stmt: &'a Stmt) {
+    match &stmt.kind {
+	StmtKind::Expr(expr) => visitor.visit_expr(expr),
+	StmtKind::Var { name, initializer, .. } => {
+	    visitor.visit_ident(name);
+	    if let Some(initializer) = initializer {
+		visitor.visit_expr(initializer);
+	    }
+	}
+	StmtKind::Class(class) => visitor.visit_class(class),
+	StmtKind::Import { variables, .. } => {
+	    for variable in variables {
+		visitor.visit_ident(&variable.name);
+		if let Some(alias) = &variable.alias {
+		    visitor.visit_ident(alias);
+		}
+	    }
+	}
+	StmtKind::Block(statements) => {
+	    for stmt in statements {
+		visitor.visit_stmt(stmt);
+	    }
+	}
+	StmtKind::If {
+	    condition,
+	    then_branch,
+	    else_branch,
+	} => {
+	    visitor.visit_expr(condition);
+	    visitor.visit_stmt(then_branch);
+	    if let Some(else_branch) = else_branch {
+		visitor.visit_stmt(else_branch);
+	    }
+	}
+	StmtKind::While { condition, body } => {
+	    visitor.visit_expr(condition);
+	    visitor.visit_stmt(body);
+	}
+	StmtKind::For {
+	    variable,
+	    sequence,
+	    body,
+	} => {
+	    visitor.visit_ident(variable);
+	    visitor.visit_expr(sequence);
+	    visitor.visit_stmt(body);
+	}
+	StmtKind::Break | StmtKind::Continue => {}
+	StmtKind::Return(value) => {
+	    if let Some(value) = value {
+		visitor.visit_expr(value);
+	    }
+	}
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+	ExprKind::Null
+	| ExprKind::Bool(_)
+	| ExprKind::Num(_)
+	| ExprKind::String(_)
+	| ExprKind::Name(_)
+	| ExprKind::Field(_)
+	| ExprKind::StaticField(_)
+	| ExprKind::This => {}
+	ExprKind::Interpolation(parts) | ExprKind::List(parts) => {
+	    for part in parts {
+		visitor.visit_expr(part);
+	    }
+	}
+	ExprKind::Map(entries) => {
+	    for (key, value) in entries {
+		visitor.visit_expr(key);
+		visitor.visit_expr(value);
+	    }
+	}
+	ExprKind::Call {
+	    receiver,
+	    name,
+	    args,
+	    block,
+	} => {
+	    if let Some(receiver) = receiver {
+		visitor.visit_expr(receiver);
+	    }
+	    visitor.visit_ident(name);
+	    walk_arguments(visitor, args, block);
+	}
+	ExprKind::Super { name, args, block } => {
+	    if let Some(name) = name {
+		visitor.visit_ident(name);
+	    }
+	    walk_arguments(visitor, args, block);
+	}
+	ExprKind::Subscript { receiver, args } => {
+	    visitor.visit_expr(receiver);
+	    for arg in args {
+		visitor.visit_expr(arg);
+	    }
+	}
+	ExprKind::Unary { operand, .. } => visitor.visit_expr(operand),
+	ExprKind::Binary { left, right, .. }
+	| ExprKind::And(left, right)
+	| ExprKind::Or(left, right) => {
+	    visitor.visit_expr(left);
+	    visitor.visit_expr(right);
+	}
+	ExprKind::Conditional {
+	    condition,
+	    then_branch,
+	    else_branch,
+	} => {
+	    visitor.visit_expr(condition);
+	    visitor.visit_expr(then_branch);
+	    visitor.visit_expr(else_branch);
+	}
+	ExprKind::Assign { target, value } => {
+	    visitor.visit_expr(target);
+	    visitor.visit_expr(value);
+	}
+    }
+}
+
+fn walk_arguments<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    args: &'a Option<Vec<Expr>>,
+    block: &'a Option<Box<BlockArg>>,
+) {
+    for arg in args.iter().flatten() {
+	visitor.visit_expr(arg);
+    }
+    if let Some(block) = block {
+	visitor.visit_block_arg(block);
+    }
+}
+
+pub fn walk_class<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, class: &'a ClassDecl) {
+    for attribute in &class.attributes {
+	visitor.visit_attribute(attribute);
+    }
+    visitor.visit_ident(&class.name);
+    if let Some(superclass) = &class.superclass {
+	visitor.visit_expr(superclass);
+    }
+    for method in &class.methods {
+	visitor.visit_method(method);
+    }
+}
+
+pub fn walk_method<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, method: &'a Method) {
+    for attribute in &method.attributes {
+	visitor.visit_attribute(attribute);
+    }
+    visitor.visit_ident(&method.name);
+    for param in &method.params {
+	visitor.visit_ident(param);
+    }
+    if let Some(body) = &method.body {
+	visitor.visit_body(body);
+    }
+}
+
+pub fn walk_body<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, body: &'a Body) {
+    match body {
+	Body::Expr(expr) => visitor.visit_expr(expr),
+	Body::Block(statements) => {
+	    for stmt in statements {
+		visitor.visit_stmt(stmt);
+	    }
+	}
+    }
+}
+
+pub fn walk_block_arg<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, block: &'a BlockArg) {
+    for param in &block.params {
+	visitor.visit_ident(param);
+    }
+    visitor.visit_body(&block.body);
+}
+
+pub fn walk_attribute<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, attribute: &'a Attribute) {
+    if let Some(group) = &attribute.group {
+	visitor.visit_ident(group);
+    }
+    visitor.visit_ident(&attribute.key);
+    visitor.visit_expr(&attribute.value);
+}
+
+/// Rebuilds a syntax tree, node by node.
+pub trait Fold {
+    fn fold_module(&mut self, module: Module) -> Module {
+	fold_module(self, module)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+	fold_stmt(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+	fold_expr(self, expr)
+    }
+
+    fn fold_class(&mut self, class: ClassDecl) -> ClassDecl {
+	fold_class(self, class)
+    }
+
+    fn fold_method(&mut self, method: Method) -> Method {
+	fold_method(self, method)
+    }
+
+    fn fold_body(&mut self, body: Body) -> Body {
+	fold_body(self, body)
+    }
+
+    fn fold_block_arg(&mut self, block: BlockArg) -> BlockArg {
+	fold_block_arg(self, block)
+    }
+
+    fn fold_attribute(&mut self, attribute: Attribute) -> Attribute {
+	fold_attribute(self, attribute)
+    }
+
+    /// Folds a name written in a declaration, call or attribute.
+    fn fold_ident(&mut self, ident: Ident) -> Ident {
+	ident
+    }
+}
+
+pub fn fold_module<F: Fold + ?Sized>(folder: &mut F, module: Module) -> Module {
+    Module {
+	statements: fold_statements(folder, module.statements),
+    }
+}
+
+fn fold_statements<F: Fold + ?Sized>(folder: &mut F, statements: Vec<Stmt>) -> Vec<Stmt> {
+    statements.into_iter().map(|stmt| folder.fold_stmt(stmt)).collect()
+}
+
+// Folds a boxed node in place, keeping its allocation.
+fn fold_boxed_stmt<F: Fold + ?Sized>(folder: &mut F, mut stmt: Box<Stmt>) -> Box<Stmt> {
+    *stmt = folder.fold_stmt(*stmt);
+    stmt
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, stmt: Stmt) -> Stmt {
+    let kind = match stmt.kind {
+	StmtKind::Expr(expr) => StmtKind::Expr(folder.fold_expr(expr)),
+	StmtKind::Var {
+	    name,
+	    initializer,
+	    end,
+	} => StmtKind::Var {
+	    name: folder.fold_ident(name),
+	    initializer: initializer.map(|initializer| folder.fold_expr(initializer)),
+	    end,
+	},
+	StmtKind::Class(class) => StmtKind::Class(Box::new(folder.fold_class(*class))),
+	StmtKind::Import { module, variables } => StmtKind::Import {
+	    module,
+	    variables: variables
+		.into_iter()
+		.map(|variable| ImportVariable {
+		    name: folder.fold_ident(variable.name),
+		    alias: variable.alias.map(|alias| folder.fold_ident(alias)),
+		})
+		.collect(),
+	},
+	StmtKind::Block(statements) => StmtKind::Block(fold_statements(folder, statements)),
+	StmtKind::If {
+	    condition,
+	    then_branch,
+	    else_branch,
+	} => StmtKind::If {
+	    condition: folder.fold_expr(condition),
+	    then_branch: fold_boxed_stmt(folder, then_branch),
+	    else_branch: else_branch.map(|else_branch| fold_boxed_stmt(folder, else_branch)),
+	},
+	StmtKind::While { condition, body } => StmtKind::While {
+	    condition: folder.fold_expr(condition),
+	    body: fold_boxed_stmt(folder, body),
+	},
+	StmtKind::For {
+	    variable,
+	    sequence,
+	    body,
+	} => StmtKind::For {
+	    variable: folder.fold_ident(variable),
+	    sequence: folder.fold_expr(sequence),
+	    body: fold_boxed_stmt(folder, body),
+	},
+	StmtKind::Break => StmtKind::Break,
+	StmtKind::Continue => StmtKind::Continue,
+	StmtKind::Return(value) => StmtKind::Return(value.map(|value| folder.fold_expr(value))),
+    };
+    Stmt {
+	kind,
+	span: stmt.span,
+    }
+}
+
+fn fold_exprs<F: Fold + ?Sized>(folder: &mut F, exprs: Vec<Expr>) -> Vec<Expr> {
+    exprs.into_iter().map(|expr| folder.fold_expr(expr)).collect()
+}
+
+fn fold_boxed_expr<F: Fold + ?Sized>(folder: &mut F, mut expr: Box<Expr>) -> Box<Expr> {
+    *expr = folder.fold_expr(*expr);
+    expr
+}
+
+fn fold_block<F: Fold + ?Sized>(
+    folder: &mut F,
+    block: Option<Box<BlockArg>>,
+) -> Option<Box<BlockArg>> {
+    block.map(|block| Box::new(folder.fold_block_arg(*block)))
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let kind = match expr.kind {
+	kind @ (ExprKind::Null
+	| ExprKind::Bool(_)
+	| ExprKind::Num(_)
+	| ExprKind::String(_)
+	| ExprKind::Name(_)
+	| ExprKind::Field(_)
+	| ExprKind::StaticField(_)
+	| ExprKind::This) => kind,
+	ExprKind::Interpolation(parts) => ExprKind::Interpolation(fold_exprs(folder, parts)),
+	ExprKind::List(elements) => ExprKind::List(fold_exprs(folder, elements)),
+	ExprKind::Map(entries) => ExprKind::Map(
+	    entries
+		.into_iter()
+		.map(|(key, value)| (folder.fold_expr(key), folder.fold_expr(value)))
+		.collect(),
+	),
+	ExprKind::Call {
+	    receiver,
+	    name,
+	    args,
+	    block,
+	} => ExprKind::Call {
+	    receiver: receiver.map(|receiver| fold_boxed_expr(folder, receiver)),
+	    name: folder.fold_ident(name),
+	    args: args.map(|args| fold_exprs(folder, args)),
+	    block: fold_block(folder, block),
+	},
+	ExprKind::Super { name, args, block } => ExprKind::Super {
+	    name: name.map(|name| folder.fold_ident(name)),
+	    args: args.map(|args| fold_exprs(folder, args)),
+	    block: fold_block(folder, block),
+	},
+	ExprKind::Subscript { receiver, args } => ExprKind::Subscript {
+	    receiver: fold_boxed_expr(folder, receiver),
+	    args: fold_exprs(folder, args),
+	},
+	ExprKind::Unary { op, operand } => ExprKind::Unary {
+	    op,
+	    operand: fold_boxed_expr(folder, operand),
+	},
+	ExprKind::Binary { op, left, right } => ExprKind::Binary {
+	    op,
+	    left: fold_boxed_expr(folder, left),
+	    right: fold_boxed_expr(folder, right),
+	},
+	ExprKind::And(left, right) => {
+	    ExprKind::And(fold_boxed_expr(folder, left), fold_boxed_expr(folder, right))
+	}
+	ExprKind::Or(left, right) => {
+	    ExprKind::Or(fold_boxed_expr(folder, left), fold_boxed_expr(folder, right))
+	}
+	ExprKind::Conditional {
+	    condition,
+	    then_branch,
+	    else_branch,
+	} => ExprKind::Conditional {
+	    condition: fold_boxed_expr(folder, condition),
+	    then_branch: fold_boxed_expr(folder, then_branch),
+	    else_branch: fold_boxed_expr(folder, else_branch),
+	},
+	ExprKind::Assign { target, value } => ExprKind::Assign {
+	    target: fold_boxed_expr(folder, target),
+	    value: fold_boxed_expr(folder, value),
+	},
+    };
+    Expr {
+	kind,
+	span: expr.span,
+    }
+}
+
+pub fn fold_class<F: Fold + ?Sized>(folder: &mut F, class: ClassDecl) -> ClassDecl {
+    let attributes = fold_attributes(folder, class.attributes);
+    ClassDecl {
+	name: folder.fold_ident(class.name),
+	superclass: class.superclass.map(|superclass| folder.fold_expr(superclass)),
+	is_foreign: class.is_foreign,
+	methods: class.methods.into_iter().map(|method| folder.fold_method(method)).collect(),
+	attributes,
+	span: class.span,
+    }
+}
+
+fn fold_attributes<F: Fold + ?Sized>(folder: &mut F, attributes: Vec<Attribute>) -> Vec<Attribute> {
+    attributes.into_iter().map(|attribute| folder.fold_attribute(attribute)).collect()
+}
+
+fn fold_idents<F: Fold + ?Sized>(folder: &mut F, idents: Vec<Ident>) -> Vec<Ident> {
+    idents.into_iter().map(|ident| folder.fold_ident(ident)).collect()
+}
+
+pub fn fold_method<F: Fold + ?Sized>(folder: &mut F, method: Method) -> Method {
+    let attributes = fold_attributes(folder, method.attributes);
+    Method {
+	kind: method.kind,
+	name: folder.fold_ident(method.name),
+	params: fold_idents(folder, method.params),
+	signature_end: method.signature_end,
+	is_static: method.is_static,
+	is_foreign: method.is_foreign,
+	body: method.body.map(|body| folder.fold_body(body)),
+	attributes,
+	span: method.span,
+    }
+}
+
+pub fn fold_body<F: Fold + ?Sized>(folder: &mut F, body: Body) -> Body {
+    match body {
+	Body::Expr(expr) => Body::Expr(folder.fold_expr(expr)),
+	Body::Block(statements) => Body::Block(fold_statements(folder, statements)),
+    }
+}
+
+pub fn fold_block_arg<F: Fold + ?Sized>(folder: &mut F, block: BlockArg) -> BlockArg {
+    BlockArg {
+	params: fold_idents(folder, block.params),
+	body: folder.fold_body(block.body),
+	span: block.span,
+    }
+}
+
+pub fn fold_attribute<F: Fold + ?Sized>(folder: &mut F, attribute: Attribute) -> Attribute {
+    Attribute {
+	group: attribute.group.map(|group| folder.fold_ident(group)),
+	key: folder.fold_ident(attribute.key),
+	value: folder.fold_expr(attribute.value),
+	is_runtime: attribute.is_runtime,
+	span: attribute.span,
+    }
+}
@@ -0,0 +1,2082 @@
+use alloc::boxed::Box;
+#[cfg(not(feature = "send"))]
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use ::core::any::Any;
+use ::core::mem;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use hashbrown::HashMap;
+
+use crate::bytecode;
+use crate::chunk::{Code, Constant, FnProto};
+use crate::compiler::{
+    self, CompileError, ModuleScope, Signature, SymbolTable, VariableError, MAX_FIELDS,
+};
+use crate::config::{WrenConfiguration, INTERRUPT_INTERVAL};
+use crate::core;
+use crate::debug::DebugState;
+use crate::error::{StackFrame, WrenError};
+#[cfg(feature = "timer")]
+use crate::handle::WrenHandle;
+use crate::heap::{GcEvent, GcStats, Heap, ObjectCounts};
+use crate::optional;
+use crate::parser::MAX_PARAMETERS;
+#[cfg(feature = "superinstructions")]
+use crate::peephole::{self, NumOp};
+use crate::value::*;
+
+// A span around a garbage collection, named `name`, whose results
+// `finish_gc` records.
+#[cfg(feature = "tracing")]
+macro_rules! gc_span {
+    ($name:literal $(, $field:ident)*) => {
+	tracing::debug_span!(
+	    $name,
+	    $($field,)*
+	    bytes_before = tracing::field::Empty,
+	    bytes_after = tracing::field::Empty,
+	    objects_freed = tracing::field::Empty,
+	)
+    };
+}
+
+/// A method implemented in Rust. It receives the receiver followed by the
+/// arguments and returns the result of the call.
+pub type Primitive = fn(&mut WrenVM, &[Value]) -> PrimitiveResult;
+
+pub type PrimitiveResult = Result<Value, PrimitiveError>;
+
+/// A method implemented by the host for a `foreign` method declaration. It
+/// reads the receiver and arguments from slots 0 and up, and leaves its
+/// result in slot 0.
+pub type ForeignMethodFn = fn(&mut WrenVM);
+
+/// A foreign method made from a closure by `bind`.
+#[cfg(not(feature = "send"))]
+pub(crate) type ForeignClosure = Rc<dyn Fn(&mut WrenVM)>;
+#[cfg(feature = "send")]
+pub(crate) type ForeignClosure = Arc<dyn Fn(&mut WrenVM) + Send + Sync>;
+
+/// Data the host hands the VM to own: a foreign object's value or the VM's
+/// user data. With the `send` feature it must be `Send`, like the VM.
+#[cfg(not(feature = "send"))]
+pub type HostData = dyn Any;
+#[cfg(feature = "send")]
+pub type HostData = dyn Any + Send;
+
+/// What the VM needs of everything the host gives it to keep, whether
+/// closures, foreign data, debug hooks or module loaders. With the `send`
+/// feature this is `Send`, so a `WrenVM` can be moved to another thread;
+/// otherwise every type has it. A VM is never `Sync`.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "send"))]
+impl<T: ?Sized> MaybeSend for T {}
+#[cfg(feature = "send")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "send")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Like `MaybeSend`, for closures `bind` shares between the VM's
+/// references to them: `Send + Sync` with the `send` feature.
+#[cfg(not(feature = "send"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "send"))]
+impl<T: ?Sized> MaybeSendSync for T {}
+#[cfg(feature = "send")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "send")]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+
+/// Why a primitive returned without a value.
+#[derive(Debug, Clone, Copy)]
+pub enum PrimitiveError {
+    /// A runtime error, usually a message string, that aborts the fiber.
+    Error(Value),
+    /// The primitive switched to another fiber, or stopped running fibers
+    /// altogether. Whatever the resumed fiber expects has already been
+    /// stored on its stack.
+    FiberSwitch,
+}
+
+/// Called with a foreign object's data when it is garbage collected, before
+/// the data is dropped.
+pub type FinalizerFn = fn(&mut dyn Any);
+
+/// The host functions behind a foreign class.
+#[derive(Debug, Clone, Copy)]
+pub struct ForeignClassMethods {
+    /// Creates an instance, given the class in slot 0 and the constructor's
+    /// arguments after it, by calling `set_slot_new_foreign` on slot 0.
+    pub allocate: ForeignMethodFn,
+    pub finalize: Option<FinalizerFn>,
+}
+
+/// An entry in a class's method table.
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    Primitive(Primitive),
+    /// `Fn.call(...)`, which invokes the receiver itself.
+    FunctionCall,
+    /// A method written in Wren, as a closure.
+    Block(ObjRef),
+    /// A `foreign` method implemented by the host.
+    Foreign(ForeignMethodFn),
+    /// A `foreign` method implemented by a closure given to `bind`, by its
+    /// index in the VM's `foreign_closures`.
+    ForeignClosure(usize),
+}
+
+// Whether `a` and `b` are the same foreign method.
+fn same_foreign(a: Method, b: Method) -> bool {
+    match (a, b) {
+	(Method::Foreign(a), Method::Foreign(b)) => a as usize == b as usize,
+	(Method::ForeignClosure(a), Method::ForeignClosure(b)) => a == b,
+	_ => false,
+    }
+}
+
+/// The built-in classes the VM needs to find the class of a value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoreClasses {
+    pub object: ObjRef,
+    pub class: ObjRef,
+    pub bool: ObjRef,
+    pub null: ObjRef,
+    pub num: ObjRef,
+    pub string: ObjRef,
+    pub list: ObjRef,
+    pub map: ObjRef,
+    pub range: ObjRef,
+    pub function: ObjRef,
+    pub fiber: ObjRef,
+}
+
+pub struct WrenVM {
+    pub(crate) config: WrenConfiguration,
+    pub(crate) heap: Heap,
+    /// Every method signature ever used, shared by all classes.
+    pub(crate) methods: SymbolTable,
+    pub(crate) modules: HashMap<String, ObjRef>,
+    /// The most recently imported module, which `IMPORT_VARIABLE` reads
+    /// from.
+    pub(crate) last_module: Option<ObjRef>,
+    pub(crate) core_module: ObjRef,
+    pub(crate) core: CoreClasses,
+    /// The fiber being run, whose stack and frames are moved into `stack`
+    /// and `frames` while it runs.
+    pub(crate) fiber: Option<ObjRef>,
+    pub(crate) stack: Vec<Value>,
+    pub(crate) frames: Vec<CallFrame>,
+    /// Host functions for foreign methods, keyed by module, class, whether
+    /// the method is static, and signature.
+    pub(crate) foreign_methods: HashMap<(String, String, bool, String), Method>,
+    /// The closures given to `bind`, which `Method::ForeignClosure` refers
+    /// to by index.
+    pub(crate) foreign_closures: Vec<ForeignClosure>,
+    /// Host functions for foreign classes, keyed by module and class.
+    pub(crate) foreign_classes: HashMap<(String, String), ForeignClassMethods>,
+    /// Where slot 0 is on `stack` while a foreign method runs.
+    pub(crate) api_stack: Option<usize>,
+    /// Values held by the host through a `WrenHandle`, by handle index.
+    pub(crate) handles: Vec<Option<Value>>,
+    /// Whatever the host keeps on the VM with `set_user_data`.
+    pub(crate) user_data: Option<Box<HostData>>,
+    /// When the VM was created, which `System.clock` counts from if there
+    /// is no `clock_fn`.
+    #[cfg(feature = "std")]
+    pub(crate) start_time: Option<Instant>,
+    /// The seconds a deterministic VM's clock reads without a `clock_fn`,
+    /// which move on to each sleeping fiber's deadline as it wakes.
+    pub(crate) virtual_time: f64,
+    /// The fibers sleeping in `Timer.sleep`, with when each wakes up.
+    #[cfg(feature = "timer")]
+    pub(crate) timers: Vec<(Instant, WrenHandle)>,
+    pub(crate) debug: DebugState,
+    /// How many more instructions scripts may run, if limited.
+    pub(crate) fuel: Option<u64>,
+    /// The error just raised for a missing method, and the fix suggested
+    /// for it, until the error is caught or reported.
+    pub(crate) hint: Option<(Value, String)>,
+    /// Instructions left until the `interrupt_fn` is next called.
+    pub(crate) interrupt_countdown: u32,
+    /// Seconds spent so far on the collection `gc_step` is doing.
+    pub(crate) gc_pause: f64,
+    pub(crate) last_gc: Option<GcEvent>,
+    /// The operator superinstructions apply to numbers themselves, by
+    /// method symbol.
+    #[cfg(feature = "superinstructions")]
+    pub(crate) num_ops: Vec<Option<NumOp>>,
+}
+
+// Fails to build if anything the VM keeps, with the `send` feature, isn't
+// `Send`.
+#[cfg(feature = "send")]
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<WrenVM>();
+};
+
+impl Default for WrenVM {
+    fn default() -> WrenVM {
+	WrenVM::new()
+    }
+}
+
+impl WrenVM {
+    pub fn new() -> WrenVM {
+	WrenVM::with_configuration(WrenConfiguration::default())
+    }
+
+    pub fn with_configuration(config: WrenConfiguration) -> WrenVM {
+	let mut heap = Heap::new(&config);
+	// Reading the system clock panics on targets without one, so it is
+	// left alone when the host has its own, or the VM doesn't use it.
+	#[cfg(feature = "std")]
+	let start_time = match config.clock_fn {
+	    None if !config.deterministic => Some(Instant::now()),
+	    _ => None,
+	};
+	let core_module = heap.alloc(Obj::Module(ObjModule {
+	    name: "core".to_string(),
+	    variables: Vec::new(),
+	    scope: ModuleScope::new(),
+	}));
+	#[allow(unused_mut)]
+	let mut methods = SymbolTable::new();
+	#[cfg(feature = "superinstructions")]
+	let num_ops = peephole::num_ops(&mut methods);
+	let mut vm = WrenVM {
+	    config,
+	    heap,
+	    methods,
+	    modules: HashMap::new(),
+	    last_module: None,
+	    core_module,
+	    core: CoreClasses::default(),
+	    fiber: None,
+	    stack: Vec::new(),
+	    frames: Vec::new(),
+	    foreign_methods: HashMap::new(),
+	    foreign_closures: Vec::new(),
+	    foreign_classes: HashMap::new(),
+	    api_stack: None,
+	    handles: Vec::new(),
+	    user_data: None,
+	    #[cfg(feature = "std")]
+	    start_time,
+	    virtual_time: 0.0,
+	    #[cfg(feature = "timer")]
+	    timers: Vec::new(),
+	    debug: DebugState::default(),
+	    fuel: None,
+	    hint: None,
+	    interrupt_countdown: INTERRUPT_INTERVAL,
+	    gc_pause: 0.0,
+	    last_gc: None,
+	    #[cfg(feature = "superinstructions")]
+	    num_ops,
+	};
+	// The core library is the VM's own code, so it is compiled and run
+	// without the limits the host puts on scripts, which could be too
+	// small for it.
+	let limits = WrenConfiguration::default();
+	let config = &mut vm.config;
+	let max_nesting = mem::replace(&mut config.max_nesting, limits.max_nesting);
+	let max_interpolation_nesting =
+	    mem::replace(&mut config.max_interpolation_nesting, limits.max_interpolation_nesting);
+	let max_call_depth = mem::replace(&mut config.max_call_depth, limits.max_call_depth);
+	let max_stack_size = mem::replace(&mut config.max_stack_size, limits.max_stack_size);
+	vm.heap.set_max_heap_size(None);
+	core::initialize(&mut vm);
+	optional::initialize(&mut vm);
+	vm.heap.set_max_heap_size(vm.config.max_heap_size);
+	vm.config.max_nesting = max_nesting;
+	vm.config.max_interpolation_nesting = max_interpolation_nesting;
+	vm.config.max_call_depth = max_call_depth;
+	vm.config.max_stack_size = max_stack_size;
+	vm
+    }
+
+    /// Compiles and runs `source` in the module named `module`, creating
+    /// the module if it doesn't exist yet.
+    pub fn interpret(&mut self, module: &str, source: &str) -> Result<(), WrenError> {
+	let module = self.get_module(module);
+	self.interpret_in_module(module, source)
+    }
+
+    pub(crate) fn interpret_in_module(&mut self, module: ObjRef, source: &str) -> Result<(), WrenError> {
+	let closure = self.compile_and_report(module, source, false)?;
+	self.run_module(closure)?;
+	Ok(())
+    }
+
+    /// Compiles and runs `source`, which must be a single expression, in the
+    /// module named `module`, creating the module if it doesn't exist yet.
+    /// If it succeeds, the expression's value is left in slot 0, as by
+    /// `call`, so a REPL or a configuration file can get at it.
+    pub fn interpret_expression(&mut self, module: &str, source: &str) -> Result<(), WrenError> {
+	let module = self.get_module(module);
+	let closure = self.compile_and_report(module, source, true)?;
+	let result = self.run_module(closure)?;
+	self.stack.push(result);
+	self.api_stack = Some(0);
+	Ok(())
+    }
+
+    // Compiles `source` into a closure, reporting a compile error as well as
+    // returning it.
+    fn compile_and_report(
+	&mut self,
+	module: ObjRef,
+	source: &str,
+	is_expression: bool,
+    ) -> Result<ObjRef, WrenError> {
+	self.compile_in_module(module, source, is_expression).map_err(|errors| {
+	    let module = self.heap.module(module).name.clone();
+	    self.report_compile_errors(&module, errors)
+	})
+    }
+
+    // Reports each of the `errors` found compiling `module`, as wren_c does,
+    // and returns the first.
+    fn report_compile_errors(&mut self, module: &str, errors: Vec<CompileError>) -> WrenError {
+	let errors: Vec<WrenError> = errors
+	    .into_iter()
+	    .map(|error| WrenError::Compile {
+		module: module.to_string(),
+		error,
+	    })
+	    .collect();
+	for error in &errors {
+	    self.report(error);
+	}
+	errors.into_iter().next().expect("a failed compile has an error")
+    }
+
+    /// Compiles `source` in the module named `module` without running it,
+    /// into bytes `load_compiled` can run later, in this VM or another.
+    /// Variables it declares are added to the module, as they would be by
+    /// `interpret`.
+    pub fn compile_to_bytes(&mut self, module: &str, source: &str) -> Result<Vec<u8>, WrenError> {
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("compile", module).entered();
+	let module = self.get_module(module);
+	let options = self.config.parse_options();
+	let compile_options = self.config.compile_options();
+	let ObjModule {
+	    name,
+	    variables,
+	    scope,
+	} = self.heap.module_mut(module);
+	let methods = &mut self.methods;
+	let result = compiler::compile(source, scope, methods, &options, &compile_options);
+	variables.resize(scope.len(), Value::Null);
+	match result {
+	    Ok(proto) => {
+		let methods = &self.methods;
+		Ok(bytecode::serialize(
+		    proto,
+		    name,
+		    |symbol| methods.name(symbol),
+		    |slot| scope.name(slot),
+		))
+	    }
+	    Err(errors) => {
+		let module = name.clone();
+		Err(self.report_compile_errors(&module, errors))
+	    }
+	}
+    }
+
+    /// Runs a module compiled by `compile_to_bytes`, in the module it was
+    /// compiled for, as `interpret` would run its source.
+    ///
+    /// The bytes are rejected if they were corrupted, or if their code could
+    /// index outside its stack, constants or upvalues. Code crafted to pass
+    /// an instruction a value of the wrong type isn't caught, so the bytes
+    /// should still come from a trusted build rather than from users.
+    pub fn load_compiled(&mut self, bytes: &[u8]) -> Result<(), WrenError> {
+	let compiled = bytecode::deserialize(bytes).map_err(|message| WrenError::Bytecode { message })?;
+	let module = self.get_module(&compiled.module);
+	let mut function = compiled.function;
+
+	// Look up the methods and variables the code uses by name.
+	let methods: Vec<usize> = compiled.methods.iter().map(|name| self.methods.ensure(name)).collect();
+	let ObjModule {
+	    variables, scope, ..
+	} = self.heap.module_mut(module);
+	let mut slots = Vec::with_capacity(compiled.variables.len());
+	for name in &compiled.variables {
+	    let slot = match scope.find(name) {
+		Some(slot) if scope.is_defined(slot) => slot,
+		_ => scope.define(name).map_err(|_| WrenError::Bytecode {
+		    message: format!("Could not define variable '{}'.", name),
+		})?,
+	    };
+	    slots.push(slot);
+	}
+	variables.resize(scope.len(), Value::Null);
+	bytecode::remap(
+	    &mut function,
+	    &mut |symbol| Ok(methods[symbol]),
+	    &mut |slot| Ok(slots[slot]),
+	)
+	.map_err(|message| WrenError::Bytecode { message })?;
+
+	let function = self.load_fn(function, module);
+	let closure = self.heap.alloc(Obj::Closure(ObjClosure::new(function)));
+	self.run_module(closure)?;
+	Ok(())
+    }
+
+    // Runs the body of a module in a new fiber, and returns what it returns.
+    fn run_module(&mut self, closure: ObjRef) -> Result<Value, WrenError> {
+	// Slots the host was using outside a foreign method are released.
+	if self.fiber.is_none() {
+	    self.api_stack = None;
+	}
+	let mut fiber = ObjFiber::new(closure);
+	fiber.state = FiberState::Root;
+	let fiber = self.heap.alloc(Obj::Fiber(fiber));
+	self.switch_fiber(Some(fiber));
+	let result = self.run()?;
+	self.switch_fiber(None);
+	Ok(result)
+    }
+
+    /// Makes `to` the running fiber, parking the current one's stack and
+    /// frames back in its object.
+    pub(crate) fn switch_fiber(&mut self, to: Option<ObjRef>) {
+	if let Some(current) = self.fiber {
+	    let fiber = self.heap.fiber_mut(current);
+	    fiber.stack = mem::take(&mut self.stack);
+	    fiber.frames = mem::take(&mut self.frames);
+	}
+	self.fiber = to;
+	if let Some(next) = to {
+	    let fiber = self.heap.fiber_mut(next);
+	    self.stack = mem::take(&mut fiber.stack);
+	    self.frames = mem::take(&mut fiber.frames);
+	}
+    }
+
+    // Returns the upvalue for the current fiber's stack `slot`, reusing an
+    // open one if the slot has already been captured.
+    pub(crate) fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+	let fiber = self.fiber.expect("a running fiber");
+	let open = &self.heap.fiber(fiber).open_upvalues;
+	let position = open.partition_point(|&upvalue| match self.heap.upvalue(upvalue) {
+	    ObjUpvalue::Open { slot: open_slot, .. } => *open_slot < slot,
+	    ObjUpvalue::Closed(_) => unreachable!("closed upvalues are not kept open"),
+	});
+	if let Some(&existing) = open.get(position) {
+	    if let ObjUpvalue::Open { slot: open_slot, .. } = self.heap.upvalue(existing) {
+		if *open_slot == slot {
+		    return existing;
+		}
+	    }
+	}
+	let upvalue = self.heap.alloc(Obj::Upvalue(ObjUpvalue::Open { fiber, slot }));
+	self.heap.fiber_mut(fiber).open_upvalues.insert(position, upvalue);
+	upvalue
+    }
+
+    // Closes the current fiber's upvalues for stack slots at or above
+    // `from`, moving the values off the stack.
+    pub(crate) fn close_upvalues(&mut self, from: usize) {
+	let fiber = self.fiber.expect("a running fiber");
+	while let Some(&upvalue) = self.heap.fiber(fiber).open_upvalues.last() {
+	    let slot = match *self.heap.upvalue(upvalue) {
+		ObjUpvalue::Open { slot, .. } if slot >= from => slot,
+		_ => break,
+	    };
+	    *self.heap.upvalue_mut(upvalue) = ObjUpvalue::Closed(self.stack[slot]);
+	    self.heap.fiber_mut(fiber).open_upvalues.pop();
+	}
+    }
+
+    pub(crate) fn upvalue_value(&self, upvalue: ObjRef) -> Value {
+	match *self.heap.upvalue(upvalue) {
+	    ObjUpvalue::Open { fiber, slot } if Some(fiber) == self.fiber => self.stack[slot],
+	    ObjUpvalue::Open { fiber, slot } => self.heap.fiber(fiber).stack[slot],
+	    ObjUpvalue::Closed(value) => value,
+	}
+    }
+
+    pub(crate) fn set_upvalue(&mut self, upvalue: ObjRef, value: Value) {
+	match *self.heap.upvalue(upvalue) {
+	    ObjUpvalue::Open { fiber, slot } if Some(fiber) == self.fiber => self.stack[slot] = value,
+	    ObjUpvalue::Open { fiber, slot } => self.heap.fiber_mut(fiber).stack[slot] = value,
+	    ObjUpvalue::Closed(_) => *self.heap.upvalue_mut(upvalue) = ObjUpvalue::Closed(value),
+	}
+    }
+
+    /// The call frames of `fiber`, wherever they currently live.
+    pub(crate) fn fiber_frames(&self, fiber: ObjRef) -> &[CallFrame] {
+	if self.fiber == Some(fiber) {
+	    &self.frames
+	} else {
+	    &self.heap.fiber(fiber).frames
+	}
+    }
+
+    // Aborts the current fiber with `error`, along with every fiber that
+    // called it, until one run with `try` is found. That fiber's caller
+    // resumes with the error as the result of `try`. If none is found, the
+    // error is reported and returned.
+    pub(crate) fn runtime_error(&mut self, error: Value) -> Result<(), WrenError> {
+	let hint = self.hint.take().filter(|(value, _)| value.same(error)).map(|(_, hint)| hint);
+	let mut current = self.fiber.expect("a running fiber");
+	let mut aborted = Vec::new();
+	loop {
+	    aborted.push(current);
+	    let fiber = self.heap.fiber_mut(current);
+	    fiber.error = error;
+	    if fiber.state == FiberState::Try {
+		let caller = fiber.caller.expect("a tried fiber has a caller");
+		self.switch_fiber(Some(caller));
+		*self.stack.last_mut().expect("the try call's slot") = error;
+		return Ok(());
+	    }
+	    match fiber.caller.take() {
+		Some(caller) => current = caller,
+		None => break,
+	    }
+	}
+	let error = self.report_error(error, &aborted, hint);
+	self.switch_fiber(None);
+	Err(error)
+    }
+
+    // Aborts the current fiber and every fiber that called it with `error`,
+    // even those run with `try`, since the error is the VM's and not the
+    // script's.
+    pub(crate) fn abort_all(&mut self, error: WrenError) -> WrenError {
+	let message = self.new_string(error.to_string());
+	let mut current = self.fiber;
+	while let Some(fiber) = current {
+	    let fiber = self.heap.fiber_mut(fiber);
+	    fiber.error = message;
+	    current = fiber.caller.take();
+	}
+	self.switch_fiber(None);
+	self.report(&error);
+	error
+    }
+
+    /// Limits scripts to running `fuel` more instructions, after which they
+    /// stop with `WrenError::Timeout` until more is given, or removes the
+    /// limit with `None`. Fuel carries over between calls to `interpret`
+    /// and `call`, so a host can budget a whole frame or request.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+	self.fuel = fuel;
+    }
+
+    /// How many more instructions scripts may run, if `set_fuel` limits
+    /// them.
+    pub fn fuel(&self) -> Option<u64> {
+	self.fuel
+    }
+
+    /// Roughly how many bytes the objects on the heap take up, counting
+    /// those that are garbage until they are collected.
+    pub fn bytes_allocated(&self) -> usize {
+	self.heap.bytes_allocated()
+    }
+
+    /// Frees every object that is no longer reachable from a module, the
+    /// stack or a running function. Returns the number of objects freed.
+    ///
+    /// The compiler produces plain data rather than heap objects, so an
+    /// in-progress compile holds nothing that needs rooting.
+    pub fn collect_garbage(&mut self) -> usize {
+	#[cfg(feature = "tracing")]
+	let _span = gc_span!("collect_garbage").entered();
+	let start = self.clock();
+	self.heap.reset_marks();
+	self.mark_roots();
+	let event = self.heap.collect();
+	self.finish_gc(event, start)
+    }
+
+    /// Does up to `budget` objects' worth of a collection, starting one if
+    /// none is in progress, and finishes it once everything reachable has
+    /// been traced. Returns whether it finished.
+    ///
+    /// A host that can't afford the pause of `collect_garbage`, such as a
+    /// game running a frame at a time, can call this between frames.
+    pub fn gc_step(&mut self, budget: usize) -> bool {
+	#[cfg(feature = "tracing")]
+	let _span = gc_span!("gc_step", budget).entered();
+	let start = self.clock();
+	if !self.heap.is_collecting() {
+	    self.heap.reset_marks();
+	    self.mark_roots();
+	}
+	if !self.heap.trace(budget) {
+	    self.gc_pause += self.clock() - start;
+	    return false;
+	}
+	// The roots may have changed since they were marked.
+	self.mark_roots();
+	let event = self.heap.collect();
+	self.finish_gc(event, start);
+	true
+    }
+
+    /// How much work the garbage collector has done so far.
+    pub fn gc_stats(&self) -> GcStats {
+	self.heap.stats()
+    }
+
+    /// What the last garbage collection did, if there has been one.
+    pub fn last_gc(&self) -> Option<&GcEvent> {
+	self.last_gc.as_ref()
+    }
+
+    /// How many objects of each type are on the heap, counting those that
+    /// are garbage until they are collected.
+    pub fn object_counts(&self) -> ObjectCounts {
+	self.heap.counts()
+    }
+
+    // Frees the unreachable objects in the nursery.
+    fn collect_nursery(&mut self) -> usize {
+	#[cfg(feature = "tracing")]
+	let _span = gc_span!("collect_nursery").entered();
+	let start = self.clock();
+	self.mark_roots();
+	let event = self.heap.collect_nursery();
+	self.finish_gc(event, start)
+    }
+
+    // Fills in how long the collection took, given when its last pause
+    // began, and reports it to the `gc_fn`. Returns the number of objects
+    // freed.
+    fn finish_gc(&mut self, mut event: GcEvent, start: f64) -> usize {
+	event.pause = self.gc_pause + (self.clock() - start);
+	self.gc_pause = 0.0;
+	#[cfg(feature = "tracing")]
+	tracing::Span::current()
+	    .record("bytes_before", event.bytes_before)
+	    .record("bytes_after", event.bytes_after)
+	    .record("objects_freed", event.objects_freed);
+	self.last_gc = Some(event);
+	if let Some(gc_fn) = self.config.gc_fn {
+	    gc_fn(self, &event);
+	}
+	event.objects_freed
+    }
+
+    // Marks everything the VM itself holds on to.
+    fn mark_roots(&mut self) {
+	self.heap.mark(self.core_module);
+	if let Some(fiber) = self.fiber {
+	    self.heap.mark(fiber);
+	}
+	for &module in self.modules.values() {
+	    self.heap.mark(module);
+	}
+	for &value in &self.stack {
+	    self.heap.mark_value(value);
+	}
+	for frame in &self.frames {
+	    self.heap.mark(frame.closure);
+	}
+	for &value in self.handles.iter().flatten() {
+	    self.heap.mark_value(value);
+	}
+    }
+
+    /// Looks up a top-level variable in a loaded module.
+    pub fn find_variable(&self, module: &str, name: &str) -> Option<Value> {
+	let module = self.heap.module(*self.modules.get(module)?);
+	let index = module.scope.find(name)?;
+	if !module.scope.is_defined(index) {
+	    return None;
+	}
+	module.variables.get(index).copied()
+    }
+
+    /// The names of the top-level variables defined in a loaded module,
+    /// including the core classes every module imports.
+    pub fn variable_names(&self, module: &str) -> Vec<&str> {
+	let module = match self.modules.get(module) {
+	    Some(&module) => self.heap.module(module),
+	    None => return Vec::new(),
+	};
+	(0..module.scope.len())
+	    .filter(|&index| module.scope.is_defined(index))
+	    .map(|index| module.scope.name(index))
+	    .collect()
+    }
+
+    /// Every method signature used so far, such as `count` or `add(_)`.
+    pub fn method_signatures(&self) -> impl Iterator<Item = &str> {
+	self.methods.iter()
+    }
+
+    pub(crate) fn get_module(&mut self, name: &str) -> ObjRef {
+	if let Some(&module) = self.modules.get(name) {
+	    return module;
+	}
+	let module = self.heap.alloc(Obj::Module(ObjModule {
+	    name: name.to_string(),
+	    variables: Vec::new(),
+	    scope: ModuleScope::new(),
+	}));
+	// Every module implicitly imports the core module.
+	let core = self.heap.module(self.core_module);
+	let variables: Vec<(String, Value)> = (0..core.scope.len())
+	    .map(|index| (core.scope.name(index).to_string(), core.variables[index]))
+	    .collect();
+	for (name, value) in variables {
+	    self.define_variable(module, &name, value)
+		.expect("core variables are unique");
+	}
+	self.modules.insert(name.to_string(), module);
+	module
+    }
+
+    /// Loads the module `name` imported from `importer`, returning the
+    /// closure for its body if it has yet to run, or the module itself if
+    /// it was already loaded.
+    ///
+    /// A module is registered before its body runs, so an import cycle
+    /// finds the partially initialized module rather than loading it again.
+    pub(crate) fn import_module(&mut self, importer: ObjRef, name: Value) -> Result<Value, Value> {
+	let name = self.heap.as_str(name).expect("module name").to_string();
+	let importer = self.heap.module(importer).name.clone();
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("load_module", module = %name, %importer).entered();
+	let resolved = match &mut self.config.module_loader {
+	    Some(loader) => loader.resolve_module(&importer, &name),
+	    None => Some(name.clone()),
+	};
+	let name = match resolved {
+	    Some(resolved) => resolved,
+	    None => {
+		let message = format!(
+		    "Could not resolve module '{}' imported from '{}'.",
+		    name, importer
+		);
+		return Err(self.new_string(message));
+	    }
+	};
+	if let Some(&module) = self.modules.get(&name) {
+	    return Ok(Value::Obj(module));
+	}
+	// The host's modules take precedence over the optional ones.
+	let source = self
+	    .config
+	    .module_loader
+	    .as_mut()
+	    .and_then(|loader| loader.load_module(&name))
+	    .or_else(|| optional::source(&name).map(str::to_string));
+	let source = match source {
+	    Some(source) => source,
+	    None => return Err(self.new_string(format!("Could not load module '{}'.", name))),
+	};
+	let module = self.get_module(&name);
+	match self.compile_in_module(module, &source, false) {
+	    Ok(closure) => Ok(Value::Obj(closure)),
+	    Err(errors) => {
+		self.report_compile_errors(&name, errors);
+		Err(self.new_string(format!("Could not compile module '{}'.", name)))
+	    }
+	}
+    }
+
+    pub(crate) fn get_module_variable(
+	&mut self,
+	module: ObjRef,
+	name: Value,
+    ) -> Result<Value, Value> {
+	let name = self.heap.as_str(name).expect("variable name");
+	let module = self.heap.module(module);
+	match module.scope.find(name) {
+	    Some(index) => Ok(module.variables[index]),
+	    None => {
+		let message = format!(
+		    "Could not find a variable named '{}' in module '{}'.",
+		    name, module.name
+		);
+		Err(self.new_string(message))
+	    }
+	}
+    }
+
+    pub(crate) fn compile_in_module(
+	&mut self,
+	module: ObjRef,
+	source: &str,
+	is_expression: bool,
+    ) -> Result<ObjRef, Vec<CompileError>> {
+	#[cfg(feature = "tracing")]
+	let _span =
+	    tracing::debug_span!("compile", module = %self.heap.module(module).name).entered();
+	let options = self.config.parse_options();
+	let compile_options = self.config.compile_options();
+	let ObjModule {
+	    variables, scope, ..
+	} = self.heap.module_mut(module);
+	let methods = &mut self.methods;
+	let result = if is_expression {
+	    compiler::compile_expression(source, scope, methods, &options, &compile_options)
+		.map_err(|error| vec![error])
+	} else {
+	    compiler::compile(source, scope, methods, &options, &compile_options)
+	};
+	// Variables declared by the compile get a slot even if it failed, so
+	// the scope and values stay in step.
+	variables.resize(scope.len(), Value::Null);
+	let function = self.load_fn(result?, module);
+	Ok(self.heap.alloc(Obj::Closure(ObjClosure::new(function))))
+    }
+
+    /// Compiles `source` in the module named `module` without running it,
+    /// and returns its bytecode in readable form. Variables it declares are
+    /// added to the module, as they would be by `interpret`.
+    pub fn disassemble(&mut self, module: &str, source: &str) -> Result<String, WrenError> {
+	let module = self.get_module(module);
+	let options = self.config.parse_options();
+	let compile_options = self.config.compile_options();
+	let ObjModule {
+	    name,
+	    variables,
+	    scope,
+	} = self.heap.module_mut(module);
+	let methods = &mut self.methods;
+	let result = compiler::compile(source, scope, methods, &options, &compile_options);
+	variables.resize(scope.len(), Value::Null);
+	match result {
+	    Ok(proto) => Ok(proto.disassemble_with(&self.methods, scope)),
+	    Err(errors) => {
+		let module = name.clone();
+		Err(self.report_compile_errors(&module, errors))
+	    }
+	}
+    }
+
+    /// Turns a compiled prototype into a function object, allocating its
+    /// constants on the heap.
+    #[allow(unused_mut)]
+    fn load_fn(&mut self, mut proto: FnProto, module: ObjRef) -> ObjRef {
+	#[cfg(feature = "superinstructions")]
+	if self.config.superinstructions {
+	    peephole::fuse(&mut proto.chunk, &self.num_ops);
+	}
+	let constants = proto
+	    .chunk
+	    .constants
+	    .into_iter()
+	    .map(|constant| match constant {
+		Constant::Num(n) => Value::Num(n),
+		// Every literal with the same contents is the same string.
+		Constant::String(s) => Value::Obj(self.heap.intern(s)),
+		Constant::Fn(proto) => Value::Obj(self.load_fn(*proto, module)),
+	    })
+	    .collect();
+	let body = FnBody {
+	    name: proto.name,
+	    arity: proto.arity,
+	    num_upvalues: proto.num_upvalues,
+	    code: proto.chunk.code,
+	    constants,
+	    lines: proto.chunk.lines,
+	    locals: proto.chunk.locals,
+	};
+	self.heap.alloc(Obj::Fn(ObjFn {
+	    body: Arc::new(body),
+	    module,
+	}))
+    }
+
+    /// Defines a top-level variable in `module`.
+    pub(crate) fn define_variable(
+	&mut self,
+	module: ObjRef,
+	name: &str,
+	value: Value,
+    ) -> Result<usize, VariableError> {
+	let module = self.heap.module_mut(module);
+	let index = module.scope.define(name)?;
+	module.variables.resize(module.scope.len(), Value::Null);
+	module.variables[index] = value;
+	Ok(index)
+    }
+
+    pub fn new_string(&mut self, value: impl Into<String>) -> Value {
+	Value::Obj(self.heap.alloc(Obj::String(ObjString::new(value.into()))))
+    }
+
+    pub fn new_list(&mut self, elements: Vec<Value>) -> Value {
+	Value::Obj(self.heap.alloc(Obj::List(ObjList { elements })))
+    }
+
+    pub fn new_range(&mut self, from: f64, to: f64, is_inclusive: bool) -> Value {
+	Value::Obj(self.heap.alloc(Obj::Range(ObjRange {
+	    from,
+	    to,
+	    is_inclusive,
+	})))
+    }
+
+    /// Creates a class with no superclass and no metaclass.
+    pub(crate) fn new_single_class(&mut self, name: &str, num_fields: usize) -> ObjRef {
+	self.heap.alloc(Obj::Class(ObjClass {
+	    name: name.to_string(),
+	    class: None,
+	    superclass: None,
+	    num_fields,
+	    methods: Vec::new(),
+	    foreign: None,
+	    attributes: Value::Null,
+	}))
+    }
+
+    /// Creates a class and its metaclass, inheriting from `superclass`.
+    pub(crate) fn new_class(
+	&mut self,
+	name: &str,
+	superclass: ObjRef,
+	num_fields: usize,
+    ) -> ObjRef {
+	let metaclass = self.new_single_class(&format!("{} metaclass", name), 0);
+	self.heap.class_mut(metaclass).class = Some(self.core.class);
+	// Metaclasses always inherit Class and do not parallel the
+	// non-metaclass hierarchy.
+	self.bind_superclass(metaclass, self.core.class);
+
+	let class = self.new_single_class(name, num_fields);
+	self.heap.class_mut(class).class = Some(metaclass);
+	self.bind_superclass(class, superclass);
+	class
+    }
+
+    /// Makes `superclass` the superclass of `class`, inheriting its fields
+    /// and copying down its methods.
+    pub(crate) fn bind_superclass(&mut self, class: ObjRef, superclass: ObjRef) {
+	let (num_fields, methods) = {
+	    let superclass = self.heap.class(superclass);
+	    (superclass.num_fields, superclass.methods.clone())
+	};
+	let class = self.heap.class_mut(class);
+	class.superclass = Some(superclass);
+	class.num_fields += num_fields;
+	for (symbol, method) in methods.into_iter().enumerate() {
+	    if let Some(method) = method {
+		bind(class, symbol, method);
+	    }
+	}
+    }
+
+    /// Registers `method` as the implementation of the foreign method with
+    /// `signature`, such as `"add(_,_)"`, in `class` of `module`.
+    ///
+    /// It can be registered at any time. A class defined afterwards picks
+    /// it up, and in a class already defined it replaces the method the
+    /// class has, along with the subclasses that inherit that method.
+    pub fn bind_foreign_method(
+	&mut self,
+	module: &str,
+	class: &str,
+	is_static: bool,
+	signature: &str,
+	method: ForeignMethodFn,
+    ) {
+	let key = (module.to_string(), class.to_string(), is_static, signature.to_string());
+	self.rebind_foreign_method(&key, Method::Foreign(method));
+	self.foreign_methods.insert(key, Method::Foreign(method));
+    }
+
+    /// Registers the functions that create and destroy instances of the
+    /// foreign class `class` in `module`.
+    ///
+    /// Like foreign methods, they can be registered at any time. If the
+    /// class is already defined, new instances are created with them, while
+    /// existing ones keep the finalizer they were created with.
+    pub fn bind_foreign_class(&mut self, module: &str, class: &str, methods: ForeignClassMethods) {
+	if let Some(class) = self.defined_class(module, class) {
+	    if let Some(foreign) = &mut self.heap.class_mut(class).foreign {
+		*foreign = methods;
+	    }
+	}
+	self.foreign_classes.insert((module.to_string(), class.to_string()), methods);
+    }
+
+    // Puts `method` in place of the foreign method bound under `key`, if
+    // its class is already defined, and in the subclasses that inherit it.
+    pub(crate) fn rebind_foreign_method(
+	&mut self,
+	key: &(String, String, bool, String),
+	method: Method,
+    ) {
+	let (module, class, is_static, signature) = key;
+	let class = self.defined_class(module, class);
+	let (Some(class), Some(symbol)) = (class, self.methods.find(signature)) else {
+	    return;
+	};
+	let class = match is_static {
+	    true => self.heap.class(class).class.expect("class has a metaclass"),
+	    false => class,
+	};
+	let old = match self.heap.class(class).method(symbol) {
+	    Some(old @ (Method::Foreign(_) | Method::ForeignClosure(_))) => old,
+	    _ => return,
+	};
+	// A method the class inherits is its superclass's to replace.
+	let superclass = self.heap.class(class).superclass;
+	let inherited = superclass.and_then(|class| self.heap.class(class).method(symbol));
+	if inherited.is_some_and(|inherited| same_foreign(inherited, old)) {
+	    return;
+	}
+	let heirs: Vec<ObjRef> = self
+	    .heap
+	    .objects()
+	    .filter(|&obj| matches!(self.heap.get(obj), Obj::Class(_)))
+	    .filter(|&heir| self.inherits(heir, class))
+	    .filter(|&heir| {
+		let method = self.heap.class(heir).method(symbol);
+		method.is_some_and(|method| same_foreign(method, old))
+	    })
+	    .collect();
+	for heir in heirs {
+	    self.bind_method(heir, symbol, method);
+	}
+    }
+
+    // The class named `name` in the loaded module `module`, if it has been
+    // defined.
+    fn defined_class(&self, module: &str, name: &str) -> Option<ObjRef> {
+	let class = self.find_variable(module, name)?.as_obj()?;
+	match self.heap.get(class) {
+	    Obj::Class(class_obj) if class_obj.name == name => Some(class),
+	    _ => None,
+	}
+    }
+
+    // Whether `class` is `ancestor` or a subclass of it.
+    fn inherits(&self, class: ObjRef, ancestor: ObjRef) -> bool {
+	let mut class = Some(class);
+	while let Some(current) = class {
+	    if current == ancestor {
+		return true;
+	    }
+	    class = self.heap.class(current).superclass;
+	}
+	false
+    }
+
+    // Finds the host function for a foreign method being defined in `class`.
+    pub(crate) fn find_foreign_method(
+	&mut self,
+	module: ObjRef,
+	class: ObjRef,
+	is_static: bool,
+	signature: Value,
+    ) -> Result<Method, Value> {
+	let module = self.heap.module(module).name.clone();
+	let class = self.heap.class(class).name.clone();
+	let signature = self.heap.as_str(signature).expect("signature").to_string();
+	let key = (module, class, is_static, signature);
+	match self.foreign_methods.get(&key) {
+	    Some(&method) => Ok(method),
+	    None => {
+		// A static method is looked for in the metaclass, as wren_c
+		// names it.
+		let (module, class, is_static, signature) = key;
+		let message = format!(
+		    "Could not find foreign method '{}' for class {}{} in module '{}'.",
+		    signature,
+		    class,
+		    if is_static { " metaclass" } else { "" },
+		    module
+		);
+		Err(self.new_string(message))
+	    }
+	}
+    }
+
+    // Runs the foreign method bound to `symbol` whose receiver is at
+    // `args_start`, leaving its result in place of the receiver and
+    // arguments.
+    pub(crate) fn call_foreign(&mut self, method: Method, symbol: usize, args_start: usize) {
+	#[cfg(feature = "tracing")]
+	let _span =
+	    tracing::trace_span!("foreign_call", method = self.methods.name(symbol)).entered();
+	#[cfg(not(feature = "tracing"))]
+	let _ = symbol;
+	let previous = self.api_stack.replace(args_start);
+	match method {
+	    Method::Foreign(method) => method(self),
+	    Method::ForeignClosure(index) => {
+		let closure = self.foreign_closures[index].clone();
+		closure(self);
+	    }
+	    _ => unreachable!("not a foreign method"),
+	}
+	self.api_stack = previous;
+	self.stack.truncate(args_start + 1);
+    }
+
+    // Attaches the host functions registered for the foreign class `class`
+    // defined in `module`.
+    pub(crate) fn bind_foreign_class_methods(
+	&mut self,
+	module: ObjRef,
+	class: ObjRef,
+    ) -> Result<(), Value> {
+	let key = (
+	    self.heap.module(module).name.clone(),
+	    self.heap.class(class).name.clone(),
+	);
+	match self.foreign_classes.get(&key) {
+	    Some(&methods) => {
+		self.heap.class_mut(class).foreign = Some(methods);
+		Ok(())
+	    }
+	    None => {
+		let (module, class) = key;
+		let message = format!(
+		    "Could not find foreign class '{}' in module '{}'.",
+		    class, module
+		);
+		Err(self.new_string(message))
+	    }
+	}
+    }
+
+    // Runs the allocator of the foreign class in the slot at `base`, which
+    // replaces the class with the new instance. The constructor's arguments
+    // stay in place for the initializer.
+    pub(crate) fn create_foreign(&mut self, base: usize) {
+	let class = self.stack[base].as_obj().expect("class receiver");
+	let methods = self.heap.class(class).foreign.expect("a foreign class");
+	#[cfg(feature = "tracing")]
+	let _span = {
+	    let class = &self.heap.class(class).name;
+	    tracing::trace_span!("foreign_allocate", %class).entered()
+	};
+	let previous = self.api_stack.replace(base);
+	(methods.allocate)(self);
+	self.api_stack = previous;
+	// Only a foreign method's fiber can wait on the host, as the instance
+	// is needed right away.
+	let fiber = self.fiber.expect("a running fiber");
+	if self.heap.fiber(fiber).awaiting_host {
+	    let error = self.new_string("A foreign class's allocator cannot suspend its fiber.");
+	    let fiber = self.heap.fiber_mut(fiber);
+	    fiber.awaiting_host = false;
+	    fiber.error = error;
+	}
+    }
+
+    pub(crate) fn bind_method(&mut self, class: ObjRef, symbol: usize, method: Method) {
+	bind(self.heap.class_mut(class), symbol, method);
+    }
+
+    /// The class named `name` in the core module, such as `List`.
+    pub(crate) fn core_class(&self, name: &str) -> Option<ObjRef> {
+	let core = self.heap.module(self.core_module);
+	match core.variables.get(core.scope.find(name)?) {
+	    Some(&Value::Obj(class)) => match self.heap.get(class) {
+		Obj::Class(_) => Some(class),
+		_ => None,
+	    },
+	    _ => None,
+	}
+    }
+
+    /// The signatures of the methods `class` has, including inherited ones.
+    pub(crate) fn class_signatures(&self, class: ObjRef) -> Vec<String> {
+	self.heap
+	    .class(class)
+	    .methods
+	    .iter()
+	    .enumerate()
+	    .filter(|(_, method)| method.is_some())
+	    .map(|(symbol, _)| self.methods.name(symbol).to_string())
+	    .collect()
+    }
+
+    // The error for calling the method `symbol` on an instance of `class`,
+    // which doesn't have it. One of its methods whose signature is close,
+    // in case it's a typo, is kept as the hint of the error if nothing
+    // catches it.
+    pub(crate) fn method_not_found(&mut self, class: ObjRef, symbol: usize) -> Value {
+	let signature = self.methods.name(symbol);
+	let message = format!(
+	    "{} does not implement '{}'.",
+	    self.heap.class(class).name,
+	    signature
+	);
+	let signatures = self.class_signatures(class);
+	let hint = closest_signature(signature, signatures.iter().map(String::as_str))
+	    .map(|similar| format!("Did you mean '{}'?", similar));
+	let error = self.new_string(message);
+	self.hint = hint.map(|hint| (error, hint));
+	error
+    }
+
+    pub fn class_of(&self, value: Value) -> ObjRef {
+	match value {
+	    Value::Null => self.core.null,
+	    Value::Bool(_) => self.core.bool,
+	    Value::Num(_) => self.core.num,
+	    Value::Obj(obj) => match self.heap.get(obj) {
+		Obj::String(_) => self.core.string,
+		Obj::List(_) => self.core.list,
+		Obj::Map(_) => self.core.map,
+		Obj::Range(_) => self.core.range,
+		Obj::Fn(_) | Obj::Closure(_) => self.core.function,
+		Obj::Class(class) => class.class.expect("class has a metaclass"),
+		Obj::Instance(instance) => instance.class,
+		Obj::Foreign(foreign) => foreign.class,
+		Obj::Fiber(_) => self.core.fiber,
+		Obj::Upvalue(_) | Obj::Module(_) => panic!("{:?} is not a first-class value", obj),
+	    },
+	}
+    }
+
+    /// Whether `a` and `b` are equal: the same object, or strings or ranges
+    /// with the same contents.
+    pub fn values_equal(&self, a: Value, b: Value) -> bool {
+	if a.same(b) {
+	    return true;
+	}
+	let (a, b) = match (a.as_obj(), b.as_obj()) {
+	    (Some(a), Some(b)) => (a, b),
+	    _ => return false,
+	};
+	match (self.heap.get(a), self.heap.get(b)) {
+	    (Obj::String(a), Obj::String(b)) => a.hash == b.hash && a.value == b.value,
+	    (Obj::Range(a), Obj::Range(b)) => {
+		a.from == b.from && a.to == b.to && a.is_inclusive == b.is_inclusive
+	    }
+	    _ => false,
+	}
+    }
+
+    /// Makes a runtime error carrying `message`, for primitives to return.
+    pub(crate) fn error(&mut self, message: impl Into<String>) -> PrimitiveError {
+	PrimitiveError::Error(self.new_string(message))
+    }
+
+    // Creates a class from the operands of `CLASS` or `FOREIGN_CLASS`,
+    // checking the superclass can be inherited from.
+    pub(crate) fn create_class(
+	&mut self,
+	name: Value,
+	superclass: Value,
+	num_fields: usize,
+	is_foreign: bool,
+    ) -> Result<ObjRef, Value> {
+	let name = self.heap.as_str(name).expect("class name").to_string();
+	let superclass = match superclass.as_obj() {
+	    Some(obj) if matches!(self.heap.get(obj), Obj::Class(_)) => obj,
+	    _ => {
+		let message = format!("Class '{}' cannot inherit from a non-class object.", name);
+		return Err(self.new_string(message));
+	    }
+	};
+	if self.heap.class(superclass).foreign.is_some() {
+	    let message = format!(
+		"Class '{}' cannot inherit from foreign class '{}'.",
+		name,
+		self.heap.class(superclass).name
+	    );
+	    return Err(self.new_string(message));
+	}
+	// Primitive methods on the built-in classes assume their receiver is
+	// the matching kind of object, so they can't be subclassed.
+	let core = self.core;
+	let sealed = [
+	    core.class,
+	    core.fiber,
+	    core.function,
+	    core.list,
+	    core.map,
+	    core.range,
+	    core.string,
+	    core.bool,
+	    core.null,
+	    core.num,
+	];
+	if sealed.contains(&superclass) {
+	    let message = format!(
+		"Class '{}' cannot inherit from built-in class '{}'.",
+		name,
+		self.heap.class(superclass).name
+	    );
+	    return Err(self.new_string(message));
+	}
+	if num_fields + self.heap.class(superclass).num_fields > MAX_FIELDS {
+	    let message = format!(
+		"Class '{}' may not have more than {} fields, including inherited ones.",
+		name, MAX_FIELDS
+	    );
+	    return Err(self.new_string(message));
+	}
+	if is_foreign && self.heap.class(superclass).num_fields > 0 {
+	    let message = format!(
+		"Foreign class '{}' may not inherit from a class with fields.",
+		name
+	    );
+	    return Err(self.new_string(message));
+	}
+	Ok(self.new_class(&name, superclass, num_fields))
+    }
+
+    /// The seconds `System.clock` reports, from the configured `clock_fn` or
+    /// since the VM was created. Without either it is always 0.
+    pub(crate) fn clock(&self) -> f64 {
+	match self.config.clock_fn {
+	    Some(clock_fn) => clock_fn(),
+	    None if self.config.deterministic => self.virtual_time,
+	    #[cfg(feature = "std")]
+	    None => self.start_time.map_or(0.0, |start_time| start_time.elapsed().as_secs_f64()),
+	    #[cfg(not(feature = "std"))]
+	    None => 0.0,
+	}
+    }
+
+    /// Writes `text` to the configured `write_fn`, or stdout. Without
+    /// `std`, text is dropped if there is no `write_fn`.
+    pub(crate) fn write(&mut self, text: &str) {
+	match self.config.write_fn {
+	    Some(write_fn) => write_fn(self, text),
+	    #[cfg(feature = "std")]
+	    None => {
+		std::print!("{}", text);
+		let _ = io::stdout().flush();
+	    }
+	    #[cfg(not(feature = "std"))]
+	    None => {}
+	}
+    }
+
+    // Passes `error` to the configured `error_fn`, or writes it to stderr.
+    pub(crate) fn report(&mut self, error: &WrenError) {
+	match self.config.error_fn {
+	    Some(error_fn) => error_fn(self, error),
+	    #[cfg(feature = "std")]
+	    None => {
+		std::eprintln!("{}", error);
+		if let WrenError::Runtime { hint: Some(hint), .. } = error {
+		    std::eprintln!("Note: {}", hint);
+		}
+	    }
+	    #[cfg(not(feature = "std"))]
+	    None => {}
+	}
+    }
+
+    // Reports an uncaught runtime error, with the calls running in each of
+    // the `fibers` it aborted.
+    fn report_error(&mut self, error: Value, fibers: &[ObjRef], hint: Option<String>) -> WrenError {
+	let message = self.heap.as_str(error).unwrap_or("[error object]").to_string();
+	let mut stack_trace = Vec::new();
+	for &fiber in fibers {
+	    for frame in self.fiber_frames(fiber).iter().rev() {
+		let function = self.heap.function(self.heap.closure(frame.closure).function);
+		// Skip the core library and the stubs behind call handles.
+		if function.module == self.core_module {
+		    continue;
+		}
+		stack_trace.push(StackFrame {
+		    module: self.heap.module(function.module).name.clone(),
+		    line: function.body.lines[frame.ip.saturating_sub(1)],
+		    function: function.body.name.clone(),
+		});
+	    }
+	}
+	let error = WrenError::Runtime {
+	    message,
+	    stack_trace,
+	    hint,
+	};
+	self.report(&error);
+	error
+    }
+
+    // The operator superinstructions apply themselves for the method
+    // `symbol`, if there is one.
+    #[cfg(feature = "superinstructions")]
+    pub(crate) fn num_op(&self, symbol: usize) -> Option<NumOp> {
+	self.num_ops.get(symbol).copied().flatten()
+    }
+
+    // Collects garbage if the heap needs it, failing with the error to raise
+    // if it is still full after a full collection. Collections only happen
+    // between instructions, when every live object is reachable from the
+    // stack, frames or modules.
+    pub(crate) fn maybe_collect(&mut self) -> Result<(), Value> {
+	if self.heap.is_full() {
+	    self.collect_garbage();
+	    if self.heap.is_full() {
+		return Err(self.new_string("Out of memory."));
+	    }
+	} else if self.heap.should_collect() || self.heap.is_collecting() {
+	    match self.config.gc_step_size {
+		Some(budget) => {
+		    self.gc_step(budget);
+		}
+		None if self.heap.should_collect() => {
+		    self.collect_garbage();
+		}
+		None => {}
+	    }
+	} else if self.heap.is_nursery_full() {
+	    self.collect_nursery();
+	}
+	Ok(())
+    }
+
+    // Runs the frames on the stack until the bottom one returns.
+    pub(crate) fn run(&mut self) -> Result<Value, WrenError> {
+	#[cfg(feature = "threaded-dispatch")]
+	if self.config.threaded_dispatch {
+	    return self.run_threaded();
+	}
+
+	let mut closure;
+	let mut body;
+	let mut module;
+	let mut field_base;
+	let mut ip;
+	let mut base;
+
+	macro_rules! load_frame {
+	    () => {{
+		let frame = *self.frames.last().expect("a frame to run");
+		closure = frame.closure;
+		let closure_obj = self.heap.closure(closure);
+		field_base = closure_obj.field_base;
+		let function = self.heap.function(closure_obj.function);
+		body = Arc::clone(&function.body);
+		module = function.module;
+		ip = frame.ip;
+		base = frame.base;
+	    }};
+	}
+
+	macro_rules! store_frame {
+	    () => {
+		self.frames.last_mut().expect("a running frame").ip = ip;
+	    };
+	}
+
+	macro_rules! read_byte {
+	    () => {{
+		ip += 1;
+		body.code[ip - 1]
+	    }};
+	}
+
+	macro_rules! read_short {
+	    () => {{
+		ip += 2;
+		u16::from_be_bytes([body.code[ip - 2], body.code[ip - 1]]) as usize
+	    }};
+	}
+
+	// Raises `error` in the current fiber, continuing in whichever fiber
+	// catches it.
+	macro_rules! throw {
+	    ($error:expr) => {{
+		match self.runtime_error($error) {
+		    Ok(()) => {
+			load_frame!();
+			continue;
+		    }
+		    Err(error) => return Err(error),
+		}
+	    }};
+	}
+
+	macro_rules! runtime_error {
+	    ($error:expr) => {{
+		store_frame!();
+		throw!($error);
+	    }};
+	}
+
+	// Calls `closure`, whose receiver or first argument is at `base`.
+	macro_rules! push_frame {
+	    ($closure:expr, $base:expr) => {{
+		if self.frames.len() >= self.config.max_call_depth
+		    || self.stack.len() >= self.config.max_stack_size
+		{
+		    return Err(self.abort_all(WrenError::StackOverflow));
+		}
+		store_frame!();
+		self.frames.push(CallFrame {
+		    closure: $closure,
+		    ip: 0,
+		    base: $base,
+		});
+		self.debug.entering = true;
+		load_frame!();
+	    }};
+	}
+
+	macro_rules! maybe_collect {
+	    () => {
+		if let Err(error) = self.maybe_collect() {
+		    runtime_error!(error);
+		}
+	    };
+	}
+
+	macro_rules! peek {
+	    () => {
+		*self.stack.last().expect("stack underflow")
+	    };
+	}
+
+	macro_rules! pop {
+	    () => {
+		self.stack.pop().expect("stack underflow")
+	    };
+	}
+
+	load_frame!();
+	self.debug.entering = ip == 0;
+	loop {
+	    if let Some(fuel) = &mut self.fuel {
+		if *fuel == 0 {
+		    store_frame!();
+		    return Err(self.abort_all(WrenError::Timeout));
+		}
+		*fuel -= 1;
+	    }
+	    if let Some(interrupt) = self.config.interrupt_fn {
+		self.interrupt_countdown -= 1;
+		if self.interrupt_countdown == 0 {
+		    self.interrupt_countdown = INTERRUPT_INTERVAL;
+		    store_frame!();
+		    if interrupt(self) {
+			return Err(self.abort_all(WrenError::Timeout));
+		    }
+		}
+	    }
+	    if self.debug.is_enabled() {
+		if module != self.core_module {
+		    store_frame!();
+		    self.debug_instruction(&body, module, ip);
+		}
+		self.debug.entering = false;
+	    }
+	    #[allow(unused_mut)]
+	    let mut code = Code::from_u8(read_byte!()).expect("valid opcode");
+
+	    // A superinstruction either finishes here, or does the first of the
+	    // instructions it stands for and carries on with the second.
+	    #[cfg(feature = "superinstructions")]
+	    match code {
+		Code::ConstantCall1 => {
+		    let constant = body.constants[read_short!()];
+		    // The call that follows.
+		    let symbol = u16::from_be_bytes([body.code[ip + 1], body.code[ip + 2]]);
+		    match (peek!().as_num(), constant.as_num(), self.num_op(symbol as usize)) {
+			(Some(a), Some(b), Some(op)) => {
+			    *self.stack.last_mut().expect("stack underflow") = op.apply(a, b);
+			    ip += 3;
+			}
+			_ => self.stack.push(constant),
+		    }
+		    continue;
+		}
+		Code::LoadLocalCall1 => {
+		    let value = self.stack[base + read_byte!() as usize];
+		    let symbol = u16::from_be_bytes([body.code[ip], body.code[ip + 1]]);
+		    match (peek!().as_num(), value.as_num(), self.num_op(symbol as usize)) {
+			(Some(a), Some(b), Some(op)) => {
+			    *self.stack.last_mut().expect("stack underflow") = op.apply(a, b);
+			    ip += 2;
+			    continue;
+			}
+			_ => {
+			    self.stack.push(value);
+			    code = Code::Call1;
+			}
+		    }
+		}
+		Code::Call1JumpIf => {
+		    let symbol = u16::from_be_bytes([body.code[ip], body.code[ip + 1]]);
+		    let length = self.stack.len();
+		    let operands = (self.stack[length - 2].as_num(), self.stack[length - 1].as_num());
+		    match (operands, self.num_op(symbol as usize)) {
+			((Some(a), Some(b)), Some(op)) => {
+			    self.stack.truncate(length - 2);
+			    // The jump that follows.
+			    let offset = u16::from_be_bytes([body.code[ip + 3], body.code[ip + 4]]);
+			    ip += 5;
+			    if op.apply(a, b).is_falsy() {
+				ip += offset as usize;
+			    }
+			    continue;
+			}
+			_ => code = Code::Call1,
+		    }
+		}
+		_ => {}
+	    }
+
+	    match code {
+		Code::Constant => {
+		    let index = read_short!();
+		    self.stack.push(body.constants[index]);
+		}
+		Code::Null => self.stack.push(Value::Null),
+		Code::False => self.stack.push(Value::Bool(false)),
+		Code::True => self.stack.push(Value::Bool(true)),
+		Code::LoadLocal0
+		| Code::LoadLocal1
+		| Code::LoadLocal2
+		| Code::LoadLocal3
+		| Code::LoadLocal4
+		| Code::LoadLocal5
+		| Code::LoadLocal6
+		| Code::LoadLocal7
+		| Code::LoadLocal8 => {
+		    let slot = code as usize - Code::LoadLocal0 as usize;
+		    self.stack.push(self.stack[base + slot]);
+		}
+		Code::LoadLocal => {
+		    let slot = read_byte!() as usize;
+		    self.stack.push(self.stack[base + slot]);
+		}
+		Code::StoreLocal => {
+		    let slot = read_byte!() as usize;
+		    self.stack[base + slot] = peek!();
+		}
+		Code::LoadUpvalue => {
+		    let index = read_byte!() as usize;
+		    let upvalue = self.heap.closure(closure).upvalues[index];
+		    self.stack.push(self.upvalue_value(upvalue));
+		}
+		Code::StoreUpvalue => {
+		    let index = read_byte!() as usize;
+		    let upvalue = self.heap.closure(closure).upvalues[index];
+		    self.set_upvalue(upvalue, peek!());
+		}
+		Code::LoadModuleVar => {
+		    let index = read_short!();
+		    let value = self.heap.module(module).variables[index];
+		    self.stack.push(value);
+		}
+		Code::StoreModuleVar => {
+		    let index = read_short!();
+		    let value = peek!();
+		    self.heap.module_mut(module).variables[index] = value;
+		}
+		Code::Pop => {
+		    pop!();
+		}
+		Code::Jump => {
+		    let offset = read_short!();
+		    ip += offset;
+		}
+		Code::Loop => {
+		    let offset = read_short!();
+		    ip -= offset;
+		}
+		Code::JumpIf => {
+		    let offset = read_short!();
+		    if pop!().is_falsy() {
+			ip += offset;
+		    }
+		}
+		Code::And => {
+		    let offset = read_short!();
+		    if peek!().is_falsy() {
+			// Short-circuit, leaving the left operand as the result.
+			ip += offset;
+		    } else {
+			pop!();
+		    }
+		}
+		Code::Or => {
+		    let offset = read_short!();
+		    if peek!().is_falsy() {
+			pop!();
+		    } else {
+			ip += offset;
+		    }
+		}
+		Code::Closure => {
+		    let index = read_short!();
+		    let function = body.constants[index].as_obj().expect("function constant");
+		    let num_upvalues = self.heap.function(function).body.num_upvalues;
+		    let mut upvalues = match num_upvalues {
+			0 => Vec::new(),
+			_ => self.heap.take_refs(num_upvalues),
+		    };
+		    for _ in 0..num_upvalues {
+			let is_local = read_byte!() != 0;
+			let index = read_byte!() as usize;
+			upvalues.push(if is_local {
+			    // Capture a local of the enclosing function.
+			    self.capture_upvalue(base + index)
+			} else {
+			    // Share one of the enclosing function's upvalues.
+			    self.heap.closure(closure).upvalues[index]
+			});
+		    }
+		    // Functions nested in a method belong to the same class.
+		    let enclosing = self.heap.closure(closure);
+		    let new_closure = ObjClosure {
+			upvalues,
+			class: enclosing.class,
+			field_base: enclosing.field_base,
+			..ObjClosure::new(function)
+		    };
+		    let new_closure = self.heap.alloc(Obj::Closure(new_closure));
+		    self.stack.push(Value::Obj(new_closure));
+		    maybe_collect!();
+		}
+		Code::LoadFieldThis => {
+		    let field = field_base + read_byte!() as usize;
+		    let receiver = self.stack[base].as_obj().expect("instance receiver");
+		    let value = self.heap.instance(receiver).fields[field];
+		    self.stack.push(value);
+		}
+		Code::StoreFieldThis => {
+		    let field = field_base + read_byte!() as usize;
+		    let receiver = self.stack[base].as_obj().expect("instance receiver");
+		    let value = peek!();
+		    self.heap.instance_mut(receiver).fields[field] = value;
+		}
+		Code::LoadField => {
+		    let field = field_base + read_byte!() as usize;
+		    let instance = pop!().as_obj().expect("instance");
+		    let value = self.heap.instance(instance).fields[field];
+		    self.stack.push(value);
+		}
+		Code::StoreField => {
+		    let field = field_base + read_byte!() as usize;
+		    let instance = pop!().as_obj().expect("instance");
+		    let value = peek!();
+		    self.heap.instance_mut(instance).fields[field] = value;
+		}
+		Code::Construct => {
+		    let class = self.stack[base].as_obj().expect("class receiver");
+		    let num_fields = self.heap.class(class).num_fields;
+		    let mut fields = match num_fields {
+			0 => Vec::new(),
+			_ => self.heap.take_values(num_fields),
+		    };
+		    fields.resize(num_fields, Value::Null);
+		    let instance = self.heap.alloc(Obj::Instance(ObjInstance { class, fields }));
+		    self.stack[base] = Value::Obj(instance);
+		    maybe_collect!();
+		}
+		Code::ForeignConstruct => {
+		    store_frame!();
+		    self.create_foreign(base);
+		    let fiber = self.fiber.expect("a running fiber");
+		    if self.heap.fiber(fiber).has_error() {
+			throw!(self.heap.fiber(fiber).error);
+		    }
+		    maybe_collect!();
+		}
+		Code::ForeignClass => {
+		    let superclass = pop!();
+		    let name = peek!();
+		    let class = match self.create_class(name, superclass, 0, true) {
+			Ok(class) => class,
+			Err(error) => runtime_error!(error),
+		    };
+		    if let Err(error) = self.bind_foreign_class_methods(module, class) {
+			runtime_error!(error);
+		    }
+		    *self.stack.last_mut().expect("the class name's slot") = Value::Obj(class);
+		    maybe_collect!();
+		}
+		Code::Class => {
+		    let num_fields = read_byte!() as usize;
+		    let superclass = pop!();
+		    let name = peek!();
+		    let class = match self.create_class(name, superclass, num_fields, false) {
+			Ok(class) => class,
+			Err(error) => runtime_error!(error),
+		    };
+		    *self.stack.last_mut().expect("the class name's slot") = Value::Obj(class);
+		    maybe_collect!();
+		}
+		Code::EndClass => {
+		    let attributes = pop!();
+		    let class = pop!().as_obj().expect("class");
+		    self.heap.class_mut(class).attributes = attributes;
+		}
+		Code::MethodInstance | Code::MethodStatic => {
+		    let symbol = read_short!();
+		    let owner = pop!().as_obj().expect("class");
+		    let method = pop!();
+		    let is_static = code == Code::MethodStatic;
+		    let class = if is_static {
+			self.heap.class(owner).class.expect("class has a metaclass")
+		    } else {
+			owner
+		    };
+		    if self.heap.as_str(method).is_some() {
+			// A foreign method, given by its signature.
+			match self.find_foreign_method(module, owner, is_static, method) {
+			    Ok(foreign) => self.bind_method(class, symbol, foreign),
+			    Err(error) => runtime_error!(error),
+			}
+			continue;
+		    }
+		    let method = method.as_obj().expect("method closure");
+		    let field_base = self
+			.heap
+			.class(class)
+			.superclass
+			.map_or(0, |superclass| self.heap.class(superclass).num_fields);
+		    let closure = self.heap.closure_mut(method);
+		    closure.class = Some(class);
+		    closure.field_base = field_base;
+		    self.bind_method(class, symbol, Method::Block(method));
+		}
+		Code::ImportModule => {
+		    let name = body.constants[read_short!()];
+		    match self.import_module(module, name) {
+			Ok(Value::Obj(module)) if matches!(self.heap.get(module), Obj::Module(_)) => {
+			    // Already loaded, so there's nothing to run.
+			    self.last_module = Some(module);
+			    self.stack.push(Value::Null);
+			}
+			Ok(closure) => {
+			    // Run the module's body. Its result lands in the
+			    // closure's slot, which the import then discards.
+			    self.stack.push(closure);
+			    let closure = closure.as_obj().expect("module closure");
+			    push_frame!(closure, self.stack.len() - 1);
+			    maybe_collect!();
+			}
+			Err(error) => runtime_error!(error),
+		    }
+		}
+		Code::ImportVariable => {
+		    let name = body.constants[read_short!()];
+		    let module = self.last_module.expect("a module was imported");
+		    match self.get_module_variable(module, name) {
+			Ok(value) => self.stack.push(value),
+			Err(error) => runtime_error!(error),
+		    }
+		}
+		Code::EndModule => {
+		    self.last_module = Some(module);
+		    self.stack.push(Value::Null);
+		}
+		Code::CloseUpvalue => {
+		    self.close_upvalues(self.stack.len() - 1);
+		    pop!();
+		}
+		Code::Return => {
+		    let result = pop!();
+		    // Close any upvalues still referring to the frame's locals.
+		    self.close_upvalues(base);
+		    self.frames.pop();
+		    self.stack.truncate(base);
+		    if self.frames.is_empty() {
+			// The fiber is complete. Return to the fiber that ran
+			// it, or stop if there isn't one.
+			let fiber = self.fiber.expect("a running fiber");
+			match self.heap.fiber_mut(fiber).caller.take() {
+			    Some(caller) => {
+				self.switch_fiber(Some(caller));
+				*self.stack.last_mut().expect("the call's slot") = result;
+			    }
+			    None => {
+				self.stack.push(result);
+				return Ok(result);
+			    }
+			}
+		    } else {
+			// The result replaces the receiver and arguments.
+			self.stack.push(result);
+		    }
+		    load_frame!();
+		}
+		Code::Call0
+		| Code::Call1
+		| Code::Call2
+		| Code::Call3
+		| Code::Call4
+		| Code::Call5
+		| Code::Call6
+		| Code::Call7
+		| Code::Call8
+		| Code::Call9
+		| Code::Call10
+		| Code::Call11
+		| Code::Call12
+		| Code::Call13
+		| Code::Call14
+		| Code::Call15
+		| Code::Call16
+		| Code::Super0
+		| Code::Super1
+		| Code::Super2
+		| Code::Super3
+		| Code::Super4
+		| Code::Super5
+		| Code::Super6
+		| Code::Super7
+		| Code::Super8
+		| Code::Super9
+		| Code::Super10
+		| Code::Super11
+		| Code::Super12
+		| Code::Super13
+		| Code::Super14
+		| Code::Super15
+		| Code::Super16 => {
+		    let symbol = read_short!();
+		    let num_args = code.arity().expect("call arity") + 1;
+		    let args_start = self.stack.len() - num_args;
+		    let receiver = self.stack[args_start];
+		    let class = if (code as u8) < Code::Super0 as u8 {
+			self.class_of(receiver)
+		    } else {
+			// Look the method up on the superclass of the class the
+			// running method is bound to.
+			let bound = self.heap.closure(closure).class.expect("super in a method");
+			self.heap.class(bound).superclass.expect("a superclass")
+		    };
+		    match self.heap.class(class).method(symbol) {
+			Some(Method::Primitive(primitive)) => {
+			    let mut args = [Value::Null; MAX_PARAMETERS + 1];
+			    args[..num_args].copy_from_slice(&self.stack[args_start..]);
+			    store_frame!();
+			    match primitive(self, &args[..num_args]) {
+				Ok(result) => {
+				    self.stack.truncate(args_start);
+				    self.stack.push(result);
+				    maybe_collect!();
+				}
+				// The frame was already stored, and the current
+				// fiber may have changed since.
+				Err(PrimitiveError::Error(error)) => throw!(error),
+				Err(PrimitiveError::FiberSwitch) => {
+				    if self.fiber.is_none() {
+					return Ok(Value::Null);
+				    }
+				    load_frame!();
+				    // A fiber that hasn't run yet starts a call.
+				    self.debug.entering = ip == 0;
+				}
+			    }
+			}
+			Some(Method::FunctionCall) => {
+			    let closure = receiver.as_obj().expect("function receiver");
+			    let function = self.heap.closure(closure).function;
+			    let arity = self.heap.function(function).body.arity;
+			    if num_args - 1 < arity {
+				let error = self.new_string("Function expects more arguments.");
+				runtime_error!(error);
+			    }
+			    // Drop any extra arguments so they don't occupy the
+			    // slots of the function's locals.
+			    self.stack.truncate(args_start + 1 + arity);
+			    push_frame!(closure, args_start);
+			}
+			Some(foreign @ (Method::Foreign(_) | Method::ForeignClosure(_))) => {
+			    store_frame!();
+			    self.call_foreign(foreign, symbol, args_start);
+			    let fiber = self.fiber.expect("a running fiber");
+			    if self.heap.fiber(fiber).has_error() {
+				throw!(self.heap.fiber(fiber).error);
+			    }
+			    // A method that suspended the fiber stops the
+			    // interpreter until the host resumes it.
+			    if self.heap.fiber(fiber).awaiting_host {
+				self.switch_fiber(None);
+				return Ok(Value::Null);
+			    }
+			    maybe_collect!();
+			}
+			Some(Method::Block(closure)) => push_frame!(closure, args_start),
+			None => {
+			    let error = self.method_not_found(class, symbol);
+			    runtime_error!(error);
+			}
+		    }
+		}
+		code => unreachable!("{:?} is not emitted by the compiler", code),
+	    }
+	}
+    }
+}
+
+fn bind(class: &mut ObjClass, symbol: usize, method: Method) {
+    if class.methods.len() <= symbol {
+	class.methods.resize(symbol + 1, None);
+    }
+    class.methods[symbol] = Some(method);
+}
+
+/// The signature among `candidates` nearest to `signature`, if one is near
+/// enough to be a likely typo of it: within one edit of its name for every
+/// three characters, counting swapping two adjacent characters as one edit.
+/// Only named methods of the same kind and arity are compared, leaving out
+/// operators and private methods, whose names end in "_".
+pub(crate) fn closest_signature<'a>(
+    signature: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let wanted = Signature::parse(signature).ok().filter(is_suggestible)?;
+    let max_distance = wanted.name.chars().count() / 3;
+    candidates
+	.into_iter()
+	.filter_map(|candidate| {
+	    let parsed = Signature::parse(candidate).ok().filter(is_suggestible)?;
+	    let comparable = parsed.kind == wanted.kind && parsed.arity == wanted.arity;
+	    if !comparable || parsed.name == wanted.name {
+		return None;
+	    }
+	    Some((edit_distance(&wanted.name, &parsed.name), candidate))
+	})
+	.filter(|&(distance, _)| distance <= max_distance)
+	.min_by_key(|&(distance, _)| distance)
+	.map(|(_, candidate)| candidate)
+}
+
+// Whether `signature` is a named, public method, which a typo could have
+// meant.
+fn is_suggestible(signature: &Signature) -> bool {
+    let named = signature.name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+    named && !signature.name.ends_with('_')
+}
+
+// The optimal string alignment distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // The rows of the table for the two previous characters of `a`, and
+    // the current one.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+	let mut row = vec![i; b.len() + 1];
+	for j in 1..=b.len() {
+	    let cost = usize::from(a[i - 1] != b[j - 1]);
+	    row[j] = (previous[j] + 1).min(row[j - 1] + 1).min(previous[j - 1] + cost);
+	    if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+		row[j] = row[j].min(before[j - 2] + 1);
+	    }
+	}
+	before = mem::replace(&mut previous, row);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,41 @@
+// Checks scripts with `wren_rs::check`, which should find every error in
+// one pass, as an editor or CI pipeline needs.
+
+use wren_rs::{check, Diagnostic, Severity};
+
+fn errors(source: &str) -> Vec<String> {
+    let diagnostics: Vec<Diagnostic> = check(source).expect_err("the script has errors");
+    assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Error));
+    diagnostics.iter().map(Diagnostic::to_string).collect()
+}
+
+#[test]
+fn reports_independent_semantic_errors() {
+    let source = "var a = 1\nvar a = 2\nSystem.print(this)\n";
+    assert_eq!(
+	errors(source),
+	[
+	    "[line 2] Error at '2': Module variable is already defined.",
+	    "[line 3] Error at 'this': Cannot use 'this' outside of a method.",
+	]
+    );
+}
+
+#[test]
+fn reports_semantic_errors_alongside_syntax_errors() {
+    let source = "System.print(missing)\nclass A {\n  foo {\n    break\n  }\n}\nvar b = (1 +)\n";
+    assert_eq!(
+	errors(source),
+	[
+	    "[line 4] Error at 'break': Cannot use 'break' outside of a loop.",
+	    "[line 7] Error at ')': Expected expression.",
+	    "[line 7] Error at newline: Expect ')' after expression.",
+	    "[line 1] Error at 'missing': Variable is used but not defined.",
+	]
+    );
+}
+
+#[test]
+fn accepts_a_valid_script() {
+    assert_eq!(check("var a = 1\nSystem.print(a)\n"), Ok(()));
+}
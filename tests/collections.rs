@@ -0,0 +1,439 @@
+// Drives the core List, Map and String methods from Rust, one call per
+// operation over random sequences of them, and checks that they do what
+// Vec, BTreeMap and str do given the same operations.
+
+use std::collections::{BTreeMap, HashMap};
+use std::slice;
+
+use proptest::prelude::*;
+use wren_rs::{FromSlot, IntoSlot, WrenConfiguration, WrenError, WrenHandle, WrenType, WrenVM};
+
+// The values the collections hold in the tests, which Wren compares as
+// Rust does: numbers and strings by value, and never one with the other.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Item {
+    Bool(bool),
+    Num(i32),
+    Str(String),
+}
+
+impl IntoSlot for Item {
+    fn into_slot(self, vm: &mut WrenVM, slot: usize) -> Result<(), WrenError> {
+	match self {
+	    Item::Bool(b) => vm.set_slot_bool(slot, b),
+	    Item::Num(n) => vm.set_slot_double(slot, n as f64),
+	    Item::Str(s) => vm.set_slot_string(slot, s),
+	}
+	Ok(())
+    }
+}
+
+impl FromSlot for Item {
+    fn from_slot(vm: &mut WrenVM, slot: usize) -> Result<Item, WrenError> {
+	Ok(match vm.get_slot_type(slot) {
+	    WrenType::Bool => Item::Bool(vm.get_slot_bool(slot)?),
+	    WrenType::Num => Item::Num(vm.get_slot_double(slot)? as i32),
+	    WrenType::String => Item::Str(vm.get_slot_string(slot)?.to_string()),
+	    other => panic!("expected an item, got a {:?}", other),
+	})
+    }
+}
+
+fn ignore_error(_: &mut WrenVM, _: &WrenError) {}
+
+// A VM whose methods are called straight from Rust, as a host would.
+struct Harness {
+    vm: WrenVM,
+    methods: HashMap<&'static str, WrenHandle>,
+}
+
+impl Harness {
+    fn new() -> Harness {
+	// Operations the model rejects are runtime errors the tests expect,
+	// and shouldn't be printed.
+	let config = WrenConfiguration {
+	    error_fn: Some(ignore_error),
+	    ..WrenConfiguration::default()
+	};
+	Harness {
+	    vm: WrenVM::with_configuration(config),
+	    methods: HashMap::new(),
+	}
+    }
+
+    // A handle to the value `value` stores in a slot.
+    fn handle(&mut self, value: impl IntoSlot) -> WrenHandle {
+	self.vm.ensure_slots(1);
+	value.into_slot(&mut self.vm, 0).expect("a value");
+	self.vm.get_slot_handle(0)
+    }
+
+    fn new_list(&mut self) -> WrenHandle {
+	self.vm.ensure_slots(1);
+	self.vm.set_slot_new_list(0);
+	self.vm.get_slot_handle(0)
+    }
+
+    fn new_map(&mut self) -> WrenHandle {
+	self.vm.ensure_slots(1);
+	self.vm.set_slot_new_map(0);
+	self.vm.get_slot_handle(0)
+    }
+
+    // Calls the method with `signature` on `receiver` with `args`, and
+    // reads what it returns as an `R`.
+    fn call<R: FromSlot>(
+	&mut self,
+	receiver: &WrenHandle,
+	signature: &'static str,
+	args: &[Item],
+    ) -> Result<R, WrenError> {
+	let vm = &mut self.vm;
+	let method = self
+	    .methods
+	    .entry(signature)
+	    .or_insert_with(|| vm.make_call_handle(signature).expect("a valid signature"));
+	vm.ensure_slots(args.len() + 1);
+	vm.set_slot_handle(0, receiver);
+	for (slot, arg) in args.iter().enumerate() {
+	    arg.clone().into_slot(vm, slot + 1)?;
+	}
+	vm.call(method)?;
+	R::from_slot(vm, 0)
+    }
+
+    // Calls a method that returns a sequence on `receiver`, and reads the
+    // sequence's elements.
+    fn call_to_list(&mut self, receiver: &WrenHandle, signature: &'static str) -> Vec<Item> {
+	let sequence: WrenHandle = self.call(receiver, signature, &[]).expect("a sequence");
+	let list = self.call(&sequence, "toList", &[]).expect("a list");
+	self.vm.release_handle(sequence);
+	list
+    }
+
+    fn list(&mut self, list: &WrenHandle) -> Vec<Item> {
+	self.vm.ensure_slots(1);
+	self.vm.set_slot_handle(0, list);
+	self.vm.get_slot_list_as(0).expect("a list")
+    }
+
+    fn map(&mut self, map: &WrenHandle) -> BTreeMap<Item, Item> {
+	self.vm.ensure_slots(1);
+	self.vm.set_slot_handle(0, map);
+	self.vm.get_slot_map_as(0).expect("a map")
+    }
+}
+
+// The position an index refers to in a sequence of `count` elements, with
+// negative indices counting back from the end, if it's in bounds.
+fn wrap(index: i32, count: usize) -> Option<usize> {
+    let index = if index < 0 { index + count as i32 } else { index };
+    (0..count as i32).contains(&index).then_some(index as usize)
+}
+
+fn num(n: usize) -> Option<Item> {
+    Some(Item::Num(n as i32))
+}
+
+fn item() -> impl Strategy<Value = Item> {
+    prop_oneof![(-3..3).prop_map(Item::Num), "[ab]{0,2}".prop_map(Item::Str)]
+}
+
+fn index() -> impl Strategy<Value = i32> {
+    -6..6
+}
+
+#[derive(Debug, Clone)]
+enum ListOp {
+    Add(Item),
+    Insert(i32, Item),
+    RemoveAt(i32),
+    Remove(Item),
+    Get(i32),
+    Set(i32, Item),
+    IndexOf(Item),
+    Contains(Item),
+    Swap(i32, i32),
+    Count,
+    Clear,
+}
+
+fn list_op() -> impl Strategy<Value = ListOp> {
+    prop_oneof![
+	4 => item().prop_map(ListOp::Add),
+	2 => (index(), item()).prop_map(|(i, x)| ListOp::Insert(i, x)),
+	2 => index().prop_map(ListOp::RemoveAt),
+	1 => item().prop_map(ListOp::Remove),
+	1 => index().prop_map(ListOp::Get),
+	1 => (index(), item()).prop_map(|(i, x)| ListOp::Set(i, x)),
+	1 => item().prop_map(ListOp::IndexOf),
+	1 => item().prop_map(ListOp::Contains),
+	1 => (index(), index()).prop_map(|(i, j)| ListOp::Swap(i, j)),
+	1 => Just(ListOp::Count),
+	1 => Just(ListOp::Clear),
+    ]
+}
+
+// Applies `op` to the model of a list, returning what the method returns,
+// or `None` if it is an error.
+fn apply_list(model: &mut Vec<Item>, op: &ListOp) -> Option<Option<Item>> {
+    Some(match op {
+	ListOp::Add(x) => {
+	    model.push(x.clone());
+	    Some(x.clone())
+	}
+	ListOp::Insert(i, x) => {
+	    // The index may be one past the end, which -1 is.
+	    let i = wrap(*i, model.len() + 1)?;
+	    model.insert(i, x.clone());
+	    Some(x.clone())
+	}
+	ListOp::RemoveAt(i) => Some(model.remove(wrap(*i, model.len())?)),
+	ListOp::Remove(x) => model
+	    .iter()
+	    .position(|y| y == x)
+	    .map(|i| model.remove(i)),
+	ListOp::Get(i) => Some(model[wrap(*i, model.len())?].clone()),
+	ListOp::Set(i, x) => {
+	    let i = wrap(*i, model.len())?;
+	    model[i] = x.clone();
+	    Some(x.clone())
+	}
+	ListOp::IndexOf(x) => Some(Item::Num(model.iter().position(|y| y == x).map_or(-1, |i| i as i32))),
+	ListOp::Contains(x) => Some(Item::Bool(model.contains(x))),
+	ListOp::Swap(i, j) => {
+	    let (i, j) = (wrap(*i, model.len())?, wrap(*j, model.len())?);
+	    model.swap(i, j);
+	    None
+	}
+	ListOp::Count => num(model.len()),
+	ListOp::Clear => {
+	    model.clear();
+	    None
+	}
+    })
+}
+
+fn call_list(harness: &mut Harness, list: &WrenHandle, op: &ListOp) -> Result<Option<Item>, WrenError> {
+    match op {
+	ListOp::Add(x) => harness.call(list, "add(_)", slice::from_ref(x)),
+	ListOp::Insert(i, x) => harness.call(list, "insert(_,_)", &[Item::Num(*i), x.clone()]),
+	ListOp::RemoveAt(i) => harness.call(list, "removeAt(_)", &[Item::Num(*i)]),
+	ListOp::Remove(x) => harness.call(list, "remove(_)", slice::from_ref(x)),
+	ListOp::Get(i) => harness.call(list, "[_]", &[Item::Num(*i)]),
+	ListOp::Set(i, x) => harness.call(list, "[_]=(_)", &[Item::Num(*i), x.clone()]),
+	ListOp::IndexOf(x) => harness.call(list, "indexOf(_)", slice::from_ref(x)),
+	ListOp::Contains(x) => harness.call(list, "contains(_)", slice::from_ref(x)),
+	ListOp::Swap(i, j) => harness.call(list, "swap(_,_)", &[Item::Num(*i), Item::Num(*j)]),
+	ListOp::Count => harness.call(list, "count", &[]),
+	ListOp::Clear => harness.call(list, "clear()", &[]),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MapOp {
+    Set(Item, Item),
+    Get(Item),
+    Remove(Item),
+    ContainsKey(Item),
+    Count,
+    Clear,
+}
+
+fn map_op() -> impl Strategy<Value = MapOp> {
+    prop_oneof![
+	4 => (item(), item()).prop_map(|(k, v)| MapOp::Set(k, v)),
+	2 => item().prop_map(MapOp::Get),
+	2 => item().prop_map(MapOp::Remove),
+	1 => item().prop_map(MapOp::ContainsKey),
+	1 => Just(MapOp::Count),
+	1 => Just(MapOp::Clear),
+    ]
+}
+
+// Applies `op` to the model of a map, returning what the method returns.
+// None of them fail with the keys the tests use.
+fn apply_map(model: &mut BTreeMap<Item, Item>, op: &MapOp) -> Option<Item> {
+    match op {
+	MapOp::Set(k, v) => {
+	    model.insert(k.clone(), v.clone());
+	    Some(v.clone())
+	}
+	MapOp::Get(k) => model.get(k).cloned(),
+	MapOp::Remove(k) => model.remove(k),
+	MapOp::ContainsKey(k) => Some(Item::Bool(model.contains_key(k))),
+	MapOp::Count => num(model.len()),
+	MapOp::Clear => {
+	    model.clear();
+	    None
+	}
+    }
+}
+
+fn call_map(harness: &mut Harness, map: &WrenHandle, op: &MapOp) -> Result<Option<Item>, WrenError> {
+    match op {
+	MapOp::Set(k, v) => harness.call(map, "[_]=(_)", &[k.clone(), v.clone()]),
+	MapOp::Get(k) => harness.call(map, "[_]", slice::from_ref(k)),
+	MapOp::Remove(k) => harness.call(map, "remove(_)", slice::from_ref(k)),
+	MapOp::ContainsKey(k) => harness.call(map, "containsKey(_)", slice::from_ref(k)),
+	MapOp::Count => harness.call(map, "count", &[]),
+	MapOp::Clear => harness.call(map, "clear()", &[]),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum StringOp {
+    Append(String),
+    Replace(String, String),
+    Repeat(usize),
+    Trim,
+    Count,
+    ByteCount,
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    IndexOf(String),
+    Split(String),
+    Bytes,
+    CodePoints,
+}
+
+// Few enough characters that searches find things, some of them more than
+// a byte long.
+const TEXT: &str = "[ab, é\t]{0,4}";
+const PATTERN: &str = "[ab, é]{1,2}";
+
+fn string_op() -> impl Strategy<Value = StringOp> {
+    prop_oneof![
+	3 => TEXT.prop_map(StringOp::Append),
+	1 => (PATTERN, TEXT).prop_map(|(from, to)| StringOp::Replace(from, to)),
+	1 => (0..3usize).prop_map(StringOp::Repeat),
+	1 => Just(StringOp::Trim),
+	1 => Just(StringOp::Count),
+	1 => Just(StringOp::ByteCount),
+	1 => TEXT.prop_map(StringOp::Contains),
+	1 => TEXT.prop_map(StringOp::StartsWith),
+	1 => TEXT.prop_map(StringOp::EndsWith),
+	1 => TEXT.prop_map(StringOp::IndexOf),
+	1 => PATTERN.prop_map(StringOp::Split),
+	1 => Just(StringOp::Bytes),
+	1 => Just(StringOp::CodePoints),
+    ]
+}
+
+// The characters `trim` removes.
+const WHITESPACE: [char; 4] = ['\t', '\r', '\n', ' '];
+
+// Some strings don't grow any further, so the tests stay quick.
+const MAX_LENGTH: usize = 64;
+
+fn strs(strings: impl IntoIterator<Item = impl Into<String>>) -> Vec<Item> {
+    strings.into_iter().map(|s| Item::Str(s.into())).collect()
+}
+
+fn nums(nums: impl IntoIterator<Item = u32>) -> Vec<Item> {
+    nums.into_iter().map(|n| Item::Num(n as i32)).collect()
+}
+
+proptest! {
+    #[test]
+    fn list_matches_vec(ops in proptest::collection::vec(list_op(), 0..64)) {
+	let mut harness = Harness::new();
+	let list = harness.new_list();
+	let mut model = Vec::new();
+	for op in &ops {
+	    let expected = apply_list(&mut model, op);
+	    prop_assert_eq!(call_list(&mut harness, &list, op).ok(), expected, "{:?}", op);
+	    prop_assert_eq!(harness.list(&list), model.clone());
+	}
+    }
+
+    #[test]
+    fn map_matches_btree_map(ops in proptest::collection::vec(map_op(), 0..64)) {
+	let mut harness = Harness::new();
+	let map = harness.new_map();
+	let mut model = BTreeMap::new();
+	for op in &ops {
+	    let expected = apply_map(&mut model, op);
+	    prop_assert_eq!(call_map(&mut harness, &map, op).ok(), Some(expected), "{:?}", op);
+	    prop_assert_eq!(harness.map(&map), model.clone());
+	}
+    }
+
+    #[test]
+    fn string_matches_str(start in TEXT, ops in proptest::collection::vec(string_op(), 0..32)) {
+	let mut harness = Harness::new();
+	let mut model = start;
+	let mut string = harness.handle(model.as_str());
+	for op in &ops {
+	    let result = match op {
+		StringOp::Append(s) if model.len() < MAX_LENGTH => {
+		    model.push_str(s);
+		    harness.call(&string, "+(_)", &[Item::Str(s.clone())])
+		}
+		StringOp::Replace(from, to) if model.len() < MAX_LENGTH => {
+		    model = model.replace(from.as_str(), to);
+		    harness.call(&string, "replace(_,_)", &[Item::Str(from.clone()), Item::Str(to.clone())])
+		}
+		StringOp::Repeat(count) if model.len() < MAX_LENGTH => {
+		    model = model.repeat(*count);
+		    harness.call(&string, "*(_)", &[Item::Num(*count as i32)])
+		}
+		StringOp::Trim => {
+		    model = model.trim_matches(&WHITESPACE[..]).to_string();
+		    harness.call(&string, "trim()", &[])
+		}
+		StringOp::Count => {
+		    let count: f64 = harness.call(&string, "count", &[]).expect("a count");
+		    prop_assert_eq!(count as usize, model.chars().count());
+		    continue;
+		}
+		StringOp::ByteCount => {
+		    let count: f64 = harness.call(&string, "byteCount_", &[]).expect("a count");
+		    prop_assert_eq!(count as usize, model.len());
+		    continue;
+		}
+		StringOp::Contains(s) => {
+		    let found: bool = harness.call(&string, "contains(_)", &[Item::Str(s.clone())]).expect("a bool");
+		    prop_assert_eq!(found, model.contains(s.as_str()));
+		    continue;
+		}
+		StringOp::StartsWith(s) => {
+		    let found: bool = harness.call(&string, "startsWith(_)", &[Item::Str(s.clone())]).expect("a bool");
+		    prop_assert_eq!(found, model.starts_with(s.as_str()));
+		    continue;
+		}
+		StringOp::EndsWith(s) => {
+		    let found: bool = harness.call(&string, "endsWith(_)", &[Item::Str(s.clone())]).expect("a bool");
+		    prop_assert_eq!(found, model.ends_with(s.as_str()));
+		    continue;
+		}
+		StringOp::IndexOf(s) => {
+		    let index: f64 = harness.call(&string, "indexOf(_)", &[Item::Str(s.clone())]).expect("an index");
+		    prop_assert_eq!(index, model.find(s.as_str()).map_or(-1.0, |i| i as f64));
+		    continue;
+		}
+		StringOp::Split(delimiter) => {
+		    let parts: Vec<Item> = harness.call(&string, "split(_)", &[Item::Str(delimiter.clone())]).expect("a list");
+		    prop_assert_eq!(parts, strs(model.split(delimiter.as_str())));
+		    continue;
+		}
+		StringOp::Bytes => {
+		    let bytes = harness.call_to_list(&string, "bytes");
+		    prop_assert_eq!(bytes, nums(model.bytes().map(u32::from)));
+		    continue;
+		}
+		StringOp::CodePoints => {
+		    let code_points = harness.call_to_list(&string, "codePoints");
+		    prop_assert_eq!(code_points, nums(model.chars().map(u32::from)));
+		    continue;
+		}
+		_ => continue,
+	    };
+	    let result: String = result.expect("a string");
+	    prop_assert_eq!(&result, &model, "{:?}", op);
+	    harness.vm.release_handle(string);
+	    string = harness.handle(result);
+	}
+    }
+}
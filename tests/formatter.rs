@@ -0,0 +1,65 @@
+// Formats scripts with comments inside expressions, which should stay next
+// to the elements they were written beside, and checks that formatting the
+// result again changes nothing.
+
+use wren_rs::format;
+
+fn formats(source: &str, expected: &str) {
+    let formatted = format(source).expect("the script parses");
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).expect("the formatted script parses"), expected);
+}
+
+#[test]
+fn keeps_inline_comments_in_parameter_lists() {
+    formats(
+	"class Point {\n  construct new(a /* inline */, b) {\n    _a = a\n  }\n  [x, /* y */ y] { x }\n}\n",
+	"class Point {\n  construct new(a /* inline */, b) {\n    _a = a\n  }\n  [x, /* y */ y] { x }\n}\n",
+    );
+    formats(
+	"list.each { |x /* item */| System.print(x) }\n",
+	"list.each {|x /* item */| System.print(x) }\n",
+    );
+}
+
+#[test]
+fn keeps_comments_with_list_elements() {
+    formats(
+	"var list = [\n  1, // one\n  2,\n]\n",
+	"var list = [\n  1, // one\n  2\n]\n",
+    );
+    formats(
+	"var list = [ // numbers\n  // the first\n  1,\n  2 // two\n  // no more\n]\n",
+	"var list = [ // numbers\n  // the first\n  1,\n  2 // two\n  // no more\n]\n",
+    );
+}
+
+#[test]
+fn keeps_comments_with_map_entries() {
+    formats(
+	"var map = {\n  // first\n  \"a\": 1, // a\n  \"b\": 2\n}\n",
+	"var map = {\n  // first\n  \"a\": 1, // a\n  \"b\": 2\n}\n",
+    );
+}
+
+#[test]
+fn keeps_comments_with_arguments() {
+    formats(
+	"System.print(1, // one\n  2 /* two */)\n",
+	"System.print(\n  1, // one\n  2 /* two */\n)\n",
+    );
+    formats("System.print(1 /* one */, 2)\n", "System.print(1 /* one */, 2)\n");
+}
+
+#[test]
+fn keeps_comments_after_an_opening_brace() {
+    formats(
+	"list.each { // comment\n  System.print(1)\n}\n",
+	"list.each { // comment\n  System.print(1)\n}\n",
+    );
+    formats(
+	"class A { // a class\n  foo { // a method\n    if (true) { // a block\n      return\n    }\n  }\n}\n",
+	"class A { // a class\n  foo { // a method\n    if (true) { // a block\n      return\n    }\n  }\n}\n",
+    );
+    formats("{ // empty\n}\n", "{ // empty\n}\n");
+}
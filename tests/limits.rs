@@ -0,0 +1,50 @@
+// Builds VMs with limits too small for the core library, which the VM
+// bootstraps without them, and checks that scripts are still held to them.
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+fn vm(config: WrenConfiguration) -> WrenVM {
+    WrenVM::with_configuration(WrenConfiguration {
+	error_fn: Some(|_, _| {}),
+	..config
+    })
+}
+
+#[test]
+fn a_small_heap_limits_scripts_but_not_the_core_library() {
+    for max_heap_size in [0, 16 * 1024, 32 * 1024] {
+	let mut vm = vm(WrenConfiguration {
+	    max_heap_size: Some(max_heap_size),
+	    ..WrenConfiguration::default()
+	});
+	let source = "var list = []\nfor (i in 0...100000) list.add(\"%(i)\")";
+	assert!(vm.interpret("main", source).is_err(), "with {} bytes", max_heap_size);
+    }
+}
+
+#[test]
+fn small_compiler_limits_hold_scripts_but_not_the_core_library() {
+    let mut vm = vm(WrenConfiguration {
+	max_nesting: 8,
+	max_interpolation_nesting: 0,
+	..WrenConfiguration::default()
+    });
+    match vm.interpret("main", "System.print(\"%(1)\")") {
+	Err(WrenError::Compile { error, .. }) => {
+	    assert!(error.message.contains("Interpolation may only nest"), "{}", error.message);
+	}
+	other => panic!("expected a compile error, got {:?}", other),
+    }
+    assert!(matches!(vm.interpret("main", "System.print([[[[[[[[[1]]]]]]]]])"), Err(WrenError::Compile { .. })));
+}
+
+#[test]
+fn a_small_stack_limits_scripts_but_not_the_core_library() {
+    let mut vm = vm(WrenConfiguration {
+	max_call_depth: 1,
+	max_stack_size: 1,
+	..WrenConfiguration::default()
+    });
+    let source = "var f = null\nf = Fn.new { |n| n == 0 ? 0 : f.call(n - 1) }\nf.call(10)";
+    assert!(matches!(vm.interpret("main", source), Err(WrenError::StackOverflow)));
+}
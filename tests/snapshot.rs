@@ -0,0 +1,53 @@
+// Edits a snapshot one bit at a time, at every byte, and checks that each
+// edited copy is rejected without changing the VM it was restored into.
+
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+const SOURCE: &str = r#"
+class Counter {
+  construct new() { _count = 0 }
+  count { _count }
+  add() { _count = _count + 1 }
+}
+
+var counter = Counter.new()
+var names = {"a": [1, 2.5, "three"], "b": null}
+var ticks = Fiber.new {
+  while (true) {
+    counter.add()
+    Fiber.yield(counter.count)
+  }
+}
+ticks.call()
+"#;
+
+fn vm() -> WrenVM {
+    WrenVM::with_configuration(WrenConfiguration {
+	opt_level: 0,
+	..WrenConfiguration::default()
+    })
+}
+
+#[test]
+fn rejects_a_snapshot_edited_anywhere() {
+    let mut original = vm();
+    original.interpret("main", SOURCE).expect("the script runs");
+    let saved = original.snapshot().expect("the script saves");
+
+    // A failed restore leaves the VM as it was, so one serves every copy.
+    let mut vm = vm();
+    vm.interpret("main", "var Kept = 1").expect("the script runs");
+    for index in 0..saved.len() {
+	let mut edited = saved.clone();
+	edited[index] ^= 0x10;
+	assert!(
+	    matches!(vm.restore(&edited), Err(WrenError::Snapshot { .. })),
+	    "byte {} of {}",
+	    index,
+	    saved.len()
+	);
+    }
+    vm.interpret("main", "if (Kept != 1) Fiber.abort(\"changed\")")
+	.expect("the VM is unchanged");
+    vm.restore(&saved).expect("the script restores");
+}
@@ -0,0 +1,150 @@
+// JavaScript bindings, for running scripts in a browser or Node.
+//
+// `wasm-pack build --target web wasm` builds the module and its glue into
+// `wasm/pkg`, which `wasm/playground` loads. A VM's `write_fn` and
+// `error_fn` are plain functions, so the callbacks of the VM that is
+// running are kept where they can find them: in a thread-local, as
+// wasm32-unknown-unknown has a single thread.
+
+use std::cell::RefCell;
+
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+use wren_rs::{WrenConfiguration, WrenError, WrenVM};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(text: &str);
+
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn log_error(text: &str);
+
+    #[wasm_bindgen(js_namespace = performance)]
+    fn now() -> f64;
+}
+
+/// How `interpret` went, like the reference implementation's
+/// `WrenInterpretResult`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterpretResult {
+    Success,
+    CompileError,
+    RuntimeError,
+}
+
+// Where the running VM's output goes.
+#[derive(Default)]
+struct Output {
+    write: Option<Function>,
+    error: Option<Function>,
+    /// Text for `console.log` that no newline has ended yet.
+    line: String,
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(Output::default());
+}
+
+/// A VM, called `WrenVM` in JavaScript. Printed text goes to `console.log`
+/// and errors to `console.error`, unless callbacks are given for them.
+#[wasm_bindgen(js_name = WrenVM)]
+pub struct Vm {
+    vm: WrenVM,
+    write: Option<Function>,
+    error: Option<Function>,
+}
+
+#[wasm_bindgen(js_class = WrenVM)]
+impl Vm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Vm {
+	let config = WrenConfiguration {
+	    write_fn: Some(write),
+	    error_fn: Some(report),
+	    clock_fn: Some(clock),
+	    ..WrenConfiguration::default()
+	};
+	Vm {
+	    vm: WrenVM::with_configuration(config),
+	    write: None,
+	    error: None,
+	}
+    }
+
+    /// Passes the text scripts print to `callback`, or to `console.log` if
+    /// it is null.
+    #[wasm_bindgen(js_name = setWriteFn)]
+    pub fn set_write_fn(&mut self, callback: Option<Function>) {
+	self.write = callback;
+    }
+
+    /// Passes the message of each compile error and uncaught runtime error
+    /// to `callback`, or to `console.error` if it is null.
+    #[wasm_bindgen(js_name = setErrorFn)]
+    pub fn set_error_fn(&mut self, callback: Option<Function>) {
+	self.error = callback;
+    }
+
+    /// Runs `source` as the module named `module`.
+    pub fn interpret(&mut self, module: &str, source: &str) -> InterpretResult {
+	OUTPUT.with(|output| {
+	    let mut output = output.borrow_mut();
+	    output.write = self.write.clone();
+	    output.error = self.error.clone();
+	});
+	let result = self.vm.interpret(module, source);
+	OUTPUT.with(|output| {
+	    let output = output.replace(Output::default());
+	    if !output.line.is_empty() {
+		log(&output.line);
+	    }
+	});
+	match result {
+	    Ok(()) => InterpretResult::Success,
+	    Err(WrenError::Compile { .. }) => InterpretResult::CompileError,
+	    Err(_) => InterpretResult::RuntimeError,
+	}
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Vm {
+	Vm::new()
+    }
+}
+
+fn write(_vm: &mut WrenVM, text: &str) {
+    OUTPUT.with(|output| {
+	let output = &mut *output.borrow_mut();
+	match &output.write {
+	    Some(callback) => {
+		let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(text));
+	    }
+	    // `console.log` ends what it logs with a newline, so it is given
+	    // whole lines.
+	    None => {
+		output.line.push_str(text);
+		while let Some(end) = output.line.find('\n') {
+		    log(&output.line[..end]);
+		    output.line.drain(..=end);
+		}
+	    }
+	}
+    });
+}
+
+fn report(_vm: &mut WrenVM, error: &WrenError) {
+    let message = error.to_string();
+    OUTPUT.with(|output| match &output.borrow().error {
+	Some(callback) => {
+	    let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(&message));
+	}
+	None => log_error(&message),
+    });
+}
+
+fn clock() -> f64 {
+    now() / 1000.0
+}